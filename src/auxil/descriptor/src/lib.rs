@@ -0,0 +1,508 @@
+//! Descriptor set allocator for gfx-hal.
+//!
+//! Descriptor pools have a fixed capacity chosen at creation, so the allocator
+//! creates new pools as needed, sized from the layouts of the sets allocated
+//! from them. Freed sets are kept aside and handed out again for the same layout.
+//! All the sets of an allocator can be released at once with `Allocator::reset`,
+//! for example by keeping an allocator per frame in flight.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use hal::{
+    device::{Device, OutOfMemory},
+    pso::{
+        AllocationError,
+        DescriptorPool,
+        DescriptorPoolCreateFlags,
+        DescriptorRangeDesc,
+        DescriptorSetLayoutBinding,
+        DescriptorType,
+    },
+    Backend,
+};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+/// Number of descriptors of each type, sorted by type.
+type Counts = Vec<(DescriptorType, usize)>;
+
+static NEXT_LAYOUT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Descriptor set layout along with the descriptor counts of its sets.
+///
+/// Each layout gets a unique id, used to find the freed sets compatible with it.
+#[derive(Debug)]
+pub struct Layout<B: Backend> {
+    raw: B::DescriptorSetLayout,
+    counts: Counts,
+    id: usize,
+}
+
+impl<B: Backend> Layout<B> {
+    /// Wrap a layout created from `bindings`.
+    pub fn new<I>(raw: B::DescriptorSetLayout, bindings: I) -> Self
+    where
+        I: IntoIterator,
+        I::Item: Borrow<DescriptorSetLayoutBinding>,
+    {
+        let mut counts = Counts::new();
+        for binding in bindings {
+            let binding = binding.borrow();
+            if binding.count == 0 {
+                continue;
+            }
+            match counts.binary_search_by_key(&binding.ty, |&(ty, _)| ty) {
+                Ok(index) => counts[index].1 += binding.count,
+                Err(index) => counts.insert(index, (binding.ty, binding.count)),
+            }
+        }
+        Layout {
+            raw,
+            counts,
+            id: NEXT_LAYOUT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    pub fn raw(&self) -> &B::DescriptorSetLayout {
+        &self.raw
+    }
+
+    /// Descriptors needed by a set of this layout.
+    pub fn ranges(&self) -> impl Iterator<Item = DescriptorRangeDesc> + '_ {
+        self.counts
+            .iter()
+            .map(|&(ty, count)| DescriptorRangeDesc { ty, count })
+    }
+
+    pub fn into_raw(self) -> B::DescriptorSetLayout {
+        self.raw
+    }
+}
+
+/// Descriptor set allocated by an `Allocator`.
+#[derive(Debug)]
+pub struct DescriptorSet<B: Backend> {
+    raw: B::DescriptorSet,
+    layout: usize,
+    pool: usize,
+}
+
+impl<B: Backend> DescriptorSet<B> {
+    pub fn raw(&self) -> &B::DescriptorSet {
+        &self.raw
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Number of sets each pool has room for.
+    pub sets_per_pool: usize,
+    /// Flags of the created pools. With `FREE_DESCRIPTOR_SET`, `Allocator::trim`
+    /// returns the freed sets to their pools.
+    pub flags: DescriptorPoolCreateFlags,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            sets_per_pool: 64,
+            flags: DescriptorPoolCreateFlags::empty(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pool<B: Backend> {
+    raw: B::DescriptorPool,
+    counts: Counts,
+    /// Sets allocated from the pool and not returned to it.
+    allocated: usize,
+    /// Sets among `allocated` which were freed and are waiting to be reused.
+    freed: usize,
+    /// The pool failed to allocate even though it had room for more sets.
+    fragmented: bool,
+}
+
+/// Growable descriptor set allocator.
+///
+/// Pools are shared by the layouts needing the same descriptor counts.
+#[derive(Debug)]
+pub struct Allocator<B: Backend> {
+    config: Config,
+    pools: Vec<Option<Pool<B>>>,
+    /// Pools by the descriptor counts they were created for.
+    buckets: HashMap<Counts, Vec<usize>>,
+    /// Freed sets by layout id.
+    freed: HashMap<usize, Vec<DescriptorSet<B>>>,
+}
+
+impl<B: Backend> Allocator<B> {
+    pub fn new(config: Config) -> Self {
+        assert!(config.sets_per_pool > 0);
+        Allocator {
+            config,
+            pools: Vec::new(),
+            buckets: HashMap::new(),
+            freed: HashMap::new(),
+        }
+    }
+
+    /// Number of pools currently created.
+    pub fn pool_count(&self) -> usize {
+        self.pools.iter().filter(|pool| pool.is_some()).count()
+    }
+
+    /// Allocate a set of `layout`, reusing a freed one if possible.
+    ///
+    /// A reused set keeps the descriptors written to it before.
+    pub unsafe fn allocate(
+        &mut self,
+        device: &B::Device,
+        layout: &Layout<B>,
+    ) -> Result<DescriptorSet<B>, AllocationError> {
+        if let Some(set) = self.freed.get_mut(&layout.id).and_then(Vec::pop) {
+            self.pools[set.pool].as_mut().unwrap().freed -= 1;
+            return Ok(set);
+        }
+
+        let sets_per_pool = self.config.sets_per_pool;
+        let candidates = self
+            .buckets
+            .get(&layout.counts)
+            .map_or(&[][..], Vec::as_slice);
+        for &index in candidates {
+            let pool = self.pools[index].as_mut().unwrap();
+            if pool.fragmented || pool.allocated == sets_per_pool {
+                continue;
+            }
+            match pool.raw.allocate_set(&layout.raw) {
+                Ok(raw) => {
+                    pool.allocated += 1;
+                    return Ok(DescriptorSet {
+                        raw,
+                        layout: layout.id,
+                        pool: index,
+                    });
+                }
+                Err(AllocationError::OutOfPoolMemory) | Err(AllocationError::FragmentedPool) => {
+                    pool.fragmented = true;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+
+        let index = self
+            .create_pool(device, &layout.counts)
+            .map_err(AllocationError::OutOfMemory)?;
+        let pool = self.pools[index].as_mut().unwrap();
+        let raw = pool.raw.allocate_set(&layout.raw)?;
+        pool.allocated += 1;
+        Ok(DescriptorSet {
+            raw,
+            layout: layout.id,
+            pool: index,
+        })
+    }
+
+    unsafe fn create_pool(
+        &mut self,
+        device: &B::Device,
+        counts: &Counts,
+    ) -> Result<usize, OutOfMemory> {
+        let max_sets = self.config.sets_per_pool;
+        let ranges = counts
+            .iter()
+            .map(|&(ty, count)| DescriptorRangeDesc {
+                ty,
+                count: count * max_sets,
+            })
+            .collect::<Vec<_>>();
+        let raw = device.create_descriptor_pool(max_sets, &ranges, self.config.flags)?;
+        let pool = Pool {
+            raw,
+            counts: counts.clone(),
+            allocated: 0,
+            freed: 0,
+            fragmented: false,
+        };
+        let index = match self.pools.iter().position(Option::is_none) {
+            Some(index) => {
+                self.pools[index] = Some(pool);
+                index
+            }
+            None => {
+                self.pools.push(Some(pool));
+                self.pools.len() - 1
+            }
+        };
+        self.buckets.entry(counts.clone()).or_default().push(index);
+        Ok(index)
+    }
+
+    /// Free a set, keeping it for the next allocation of the same layout.
+    pub fn free(&mut self, set: DescriptorSet<B>) {
+        self.pools[set.pool].as_mut().unwrap().freed += 1;
+        self.freed.entry(set.layout).or_default().push(set);
+    }
+
+    /// Release the memory of the freed sets. With `FREE_DESCRIPTOR_SET`, the sets
+    /// are returned to their pools, otherwise only the pools whose sets were all
+    /// freed can be released. Pools left without sets are destroyed.
+    pub unsafe fn trim(&mut self, device: &B::Device) {
+        if self
+            .config
+            .flags
+            .contains(DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET)
+        {
+            let mut sets_by_pool = HashMap::new();
+            for set in self.freed.drain().flat_map(|(_, sets)| sets) {
+                sets_by_pool
+                    .entry(set.pool)
+                    .or_insert_with(Vec::new)
+                    .push(set.raw);
+            }
+            for (index, sets) in sets_by_pool {
+                let pool = self.pools[index].as_mut().unwrap();
+                pool.allocated -= sets.len();
+                pool.freed = 0;
+                pool.fragmented = false;
+                pool.raw.free(sets);
+            }
+        }
+
+        for index in 0 .. self.pools.len() {
+            let unused = match self.pools[index] {
+                Some(ref pool) => pool.allocated == pool.freed,
+                None => false,
+            };
+            if !unused {
+                continue;
+            }
+            let pool = self.pools[index].take().unwrap();
+            if pool.freed != 0 {
+                for sets in self.freed.values_mut() {
+                    sets.retain(|set| set.pool != index);
+                }
+            }
+            let bucket = self.buckets.get_mut(&pool.counts).unwrap();
+            bucket.retain(|&other| other != index);
+            if bucket.is_empty() {
+                self.buckets.remove(&pool.counts);
+            }
+            device.destroy_descriptor_pool(pool.raw);
+        }
+        self.freed.retain(|_, sets| !sets.is_empty());
+    }
+
+    /// Reset all pools, keeping them for the next allocations.
+    ///
+    /// All the sets allocated so far become invalid, and must not be freed.
+    pub unsafe fn reset(&mut self) {
+        self.freed.clear();
+        for pool in self.pools.iter_mut().filter_map(Option::as_mut) {
+            pool.raw.reset();
+            pool.allocated = 0;
+            pool.freed = 0;
+            pool.fragmented = false;
+        }
+    }
+
+    /// Destroy all pools, implicitly freeing all sets.
+    pub unsafe fn dispose(self, device: &B::Device) {
+        for pool in self.pools.into_iter().flatten() {
+            device.destroy_descriptor_pool(pool.raw);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::pso::{
+        BufferDescriptorFormat,
+        BufferDescriptorType,
+        ImageDescriptorType,
+        ShaderStageFlags,
+    };
+
+    const UNIFORM: DescriptorType = DescriptorType::Buffer {
+        ty: BufferDescriptorType::Uniform,
+        format: BufferDescriptorFormat::Structured {
+            dynamic_offset: false,
+        },
+    };
+
+    const SAMPLED: DescriptorType = DescriptorType::Image {
+        ty: ImageDescriptorType::Sampled { with_sampler: true },
+    };
+
+    fn binding(binding: u32, ty: DescriptorType, count: usize) -> DescriptorSetLayoutBinding {
+        DescriptorSetLayoutBinding {
+            binding,
+            ty,
+            count,
+            stage_flags: ShaderStageFlags::FRAGMENT,
+            immutable_samplers: false,
+        }
+    }
+
+    fn material_layout() -> Layout<mock::Backend> {
+        Layout::new(
+            (),
+            &[
+                binding(0, SAMPLED, 2),
+                binding(1, UNIFORM, 1),
+                binding(2, SAMPLED, 1),
+            ],
+        )
+    }
+
+    fn config(flags: DescriptorPoolCreateFlags) -> Config {
+        Config {
+            sets_per_pool: 2,
+            flags,
+        }
+    }
+
+    #[test]
+    fn test_layout_ranges() {
+        let layout = material_layout();
+        let ranges = layout
+            .ranges()
+            .map(|range| (range.ty, range.count))
+            .collect::<Vec<_>>();
+        let mut expected = vec![(SAMPLED, 3), (UNIFORM, 1)];
+        expected.sort();
+        assert_eq!(ranges, expected);
+    }
+
+    #[test]
+    fn test_grow() {
+        let device = mock::Device;
+        let mut allocator = Allocator::new(config(DescriptorPoolCreateFlags::empty()));
+        let layout = material_layout();
+        let sets = (0 .. 3)
+            .map(|_| unsafe { allocator.allocate(&device, &layout) }.unwrap())
+            .collect::<Vec<_>>();
+
+        assert_eq!(sets.len(), 3);
+        assert_eq!(allocator.pool_count(), 2);
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "create_descriptor_pool",
+                "allocate_set",
+                "allocate_set",
+                "create_descriptor_pool",
+                "allocate_set",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reuse_by_layout() {
+        let device = mock::Device;
+        let mut allocator = Allocator::new(config(DescriptorPoolCreateFlags::empty()));
+        let layout = material_layout();
+        let other = material_layout();
+        unsafe {
+            let set = allocator.allocate(&device, &layout).unwrap();
+            allocator.free(set);
+            // Same counts, but a different layout: the freed set can't be used.
+            allocator.allocate(&device, &other).unwrap();
+            allocator.allocate(&device, &layout).unwrap();
+        }
+        assert_eq!(
+            mock::take_calls(),
+            ["create_descriptor_pool", "allocate_set", "allocate_set"]
+        );
+        assert_eq!(allocator.pool_count(), 1);
+    }
+
+    #[test]
+    fn test_trim() {
+        let device = mock::Device;
+        let mut allocator = Allocator::new(config(DescriptorPoolCreateFlags::empty()));
+        let layout = material_layout();
+        unsafe {
+            let sets = (0 .. 3)
+                .map(|_| allocator.allocate(&device, &layout).unwrap())
+                .collect::<Vec<_>>();
+            // Only the second pool has all its sets freed.
+            for set in sets.into_iter().skip(1) {
+                allocator.free(set);
+            }
+            mock::take_calls();
+            allocator.trim(&device);
+            assert_eq!(mock::take_calls(), ["destroy_descriptor_pool"]);
+            assert_eq!(allocator.pool_count(), 1);
+
+            // The set freed from the first pool is still reused.
+            allocator.allocate(&device, &layout).unwrap();
+            assert!(mock::take_calls().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_trim_free_descriptor_set() {
+        let device = mock::Device;
+        let mut allocator = Allocator::new(config(DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET));
+        let layout = material_layout();
+        unsafe {
+            let sets = (0 .. 3)
+                .map(|_| allocator.allocate(&device, &layout).unwrap())
+                .collect::<Vec<_>>();
+            for set in sets.into_iter().skip(1) {
+                allocator.free(set);
+            }
+            mock::take_calls();
+            allocator.trim(&device);
+            assert_eq!(
+                mock::take_calls(),
+                ["free_sets", "free_sets", "destroy_descriptor_pool"]
+            );
+            assert_eq!(allocator.pool_count(), 1);
+
+            // The first pool has room again.
+            allocator.allocate(&device, &layout).unwrap();
+            assert_eq!(mock::take_calls(), ["allocate_set"]);
+        }
+    }
+
+    #[test]
+    fn test_reset() {
+        let device = mock::Device;
+        let mut allocator = Allocator::new(config(DescriptorPoolCreateFlags::empty()));
+        let layout = material_layout();
+        unsafe {
+            for _ in 0 .. 4 {
+                allocator.allocate(&device, &layout).unwrap();
+            }
+            mock::take_calls();
+            allocator.reset();
+            assert_eq!(
+                mock::take_calls(),
+                ["reset_descriptor_pool", "reset_descriptor_pool"]
+            );
+            for _ in 0 .. 4 {
+                allocator.allocate(&device, &layout).unwrap();
+            }
+            assert_eq!(mock::take_calls(), ["allocate_set"; 4]);
+            allocator.dispose(&device);
+        }
+        assert_eq!(
+            mock::take_calls(),
+            ["destroy_descriptor_pool", "destroy_descriptor_pool"]
+        );
+    }
+}
@@ -0,0 +1,991 @@
+//! Frame graph scheduling the passes of a frame.
+//!
+//! Passes are declared with `GraphBuilder::add_pass` together with the images and
+//! buffers they read and write. Compiling the graph:
+//!
+//! - culls the passes whose results are never used,
+//! - orders the remaining passes by their dependencies,
+//! - computes the `pipeline_barrier` needed in front of each pass,
+//! - places transient resources with non-overlapping lifetimes in the same memory.
+//!
+//! The compiled `Graph` doesn't own any resources: the memory of the transient heaps
+//! is allocated by the user, and the resources are looked up by handle when recording.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use gfx_tracker::{BufferId, BufferUsage, ImageId, ImageUsage, Tracker, Transitions};
+use hal::{
+    command::CommandBuffer,
+    format::Aspects,
+    image,
+    memory::Requirements,
+    pso::PipelineStage,
+    Backend,
+};
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap},
+    ops::Range,
+};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ImageHandle(usize);
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct BufferHandle(usize);
+
+/// Identifier of a pass, ordered by declaration.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PassId(usize);
+
+/// Subresources of an image. Passes always access whole images.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageInfo {
+    pub aspects: Aspects,
+    pub levels: image::Level,
+    pub layers: image::Layer,
+}
+
+/// Memory shared by transient resources with compatible memory types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TransientHeap {
+    /// Memory types allowed for every resource of the heap.
+    pub type_mask: u64,
+    pub size: u64,
+    pub alignment: u64,
+}
+
+/// Location of a transient resource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    /// Index into `Graph::heaps`.
+    pub heap: usize,
+    pub offset: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Usage {
+    Buffer(BufferUsage),
+    Image(ImageUsage),
+}
+
+impl Usage {
+    fn stages(&self) -> PipelineStage {
+        match *self {
+            Usage::Buffer(ref usage) => usage.stages,
+            Usage::Image(ref usage) => usage.stages,
+        }
+    }
+
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (Usage::Buffer(a), Usage::Buffer(b)) => Usage::Buffer(BufferUsage {
+                access: a.access | b.access,
+                stages: a.stages | b.stages,
+            }),
+            (Usage::Image(a), Usage::Image(b)) => {
+                assert_eq!(a.layout, b.layout, "Image is used in two layouts by a pass");
+                Usage::Image(ImageUsage {
+                    access: a.access | b.access,
+                    layout: a.layout,
+                    stages: a.stages | b.stages,
+                })
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ResourceNode {
+    name: String,
+    image: Option<ImageInfo>,
+    initial_layout: image::Layout,
+    /// Memory requirements of a transient resource, `None` if imported.
+    transient: Option<Requirements>,
+    final_usage: Option<Usage>,
+}
+
+#[derive(Debug)]
+struct Access {
+    resource: usize,
+    usage: Usage,
+    read: bool,
+    write: bool,
+}
+
+#[derive(Debug)]
+struct PassNode {
+    name: String,
+    side_effects: bool,
+    accesses: Vec<Access>,
+}
+
+/// Declaration of the resources and passes of a frame.
+#[derive(Debug, Default)]
+pub struct GraphBuilder {
+    resources: Vec<ResourceNode>,
+    passes: Vec<PassNode>,
+}
+
+impl GraphBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add_resource(&mut self, resource: ResourceNode) -> usize {
+        self.resources.push(resource);
+        self.resources.len() - 1
+    }
+
+    /// Declare an image living only within the graph. Its memory may be shared
+    /// with other transient resources, so it starts in the `Undefined` layout.
+    pub fn create_image(
+        &mut self,
+        name: &str,
+        info: ImageInfo,
+        requirements: Requirements,
+    ) -> ImageHandle {
+        ImageHandle(self.add_resource(ResourceNode {
+            name: name.to_string(),
+            image: Some(info),
+            initial_layout: image::Layout::Undefined,
+            transient: Some(requirements),
+            final_usage: None,
+        }))
+    }
+
+    /// Declare an image living outside of the graph, currently in `layout`.
+    /// Its content is considered used after the graph, and transitioned
+    /// to `final_usage` if any.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        info: ImageInfo,
+        layout: image::Layout,
+        final_usage: Option<ImageUsage>,
+    ) -> ImageHandle {
+        ImageHandle(self.add_resource(ResourceNode {
+            name: name.to_string(),
+            image: Some(info),
+            initial_layout: layout,
+            transient: None,
+            final_usage: final_usage.map(Usage::Image),
+        }))
+    }
+
+    /// Declare a buffer living only within the graph.
+    pub fn create_buffer(&mut self, name: &str, requirements: Requirements) -> BufferHandle {
+        BufferHandle(self.add_resource(ResourceNode {
+            name: name.to_string(),
+            image: None,
+            initial_layout: image::Layout::Undefined,
+            transient: Some(requirements),
+            final_usage: None,
+        }))
+    }
+
+    /// Declare a buffer living outside of the graph.
+    pub fn import_buffer(&mut self, name: &str, final_usage: Option<BufferUsage>) -> BufferHandle {
+        BufferHandle(self.add_resource(ResourceNode {
+            name: name.to_string(),
+            image: None,
+            initial_layout: image::Layout::Undefined,
+            transient: None,
+            final_usage: final_usage.map(Usage::Buffer),
+        }))
+    }
+
+    pub fn image_name(&self, image: ImageHandle) -> &str {
+        &self.resources[image.0].name
+    }
+
+    pub fn buffer_name(&self, buffer: BufferHandle) -> &str {
+        &self.resources[buffer.0].name
+    }
+
+    /// Declare the next pass. A pass reading a resource depends on the last pass
+    /// declared before it writing that resource.
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_> {
+        self.passes.push(PassNode {
+            name: name.to_string(),
+            side_effects: false,
+            accesses: Vec::new(),
+        });
+        PassBuilder {
+            pass: self.passes.len() - 1,
+            graph: self,
+        }
+    }
+
+    /// Compile the graph. Passes are scheduled depth-first: after a pass, the passes
+    /// it made ready go first, which keeps producers close to their consumers and
+    /// shortens the lifetimes of the transient resources. Otherwise the declaration
+    /// order is kept.
+    pub fn compile(self) -> Graph {
+        let kept = self.cull();
+        let order = self.schedule(&kept);
+        let lifetimes = self.lifetimes(&order);
+        let (heaps, placements) = self.place(&lifetimes);
+
+        let mut tracker = Tracker::new();
+        let tracked = self
+            .resources
+            .iter()
+            .map(|resource| match resource.image {
+                Some(info) => Tracked::Image(tracker.add_image(
+                    info.aspects,
+                    info.levels,
+                    info.layers,
+                    resource.initial_layout,
+                )),
+                None => Tracked::Buffer(tracker.add_buffer()),
+            })
+            .collect::<Vec<_>>();
+
+        let mut steps = Vec::with_capacity(order.len());
+        for (step, &pass) in order.iter().enumerate() {
+            let mut transitions = Transitions::new(None);
+            for access in &self.passes[pass].accesses {
+                let resource = access.resource;
+                if let (Some(placement), Some(lifetime)) =
+                    (placements[resource], &lifetimes[resource])
+                {
+                    if lifetime.steps.start == step {
+                        self.wait_aliased(
+                            &mut transitions,
+                            resource,
+                            placement,
+                            &lifetimes,
+                            &placements,
+                        );
+                    }
+                }
+                self.use_resource(
+                    &mut tracker,
+                    &mut transitions,
+                    resource,
+                    tracked[resource],
+                    access.usage,
+                );
+            }
+            steps.push(transitions);
+        }
+
+        let mut final_transitions = Transitions::new(None);
+        for (resource, node) in self.resources.iter().enumerate() {
+            if let Some(usage) = node.final_usage {
+                self.use_resource(
+                    &mut tracker,
+                    &mut final_transitions,
+                    resource,
+                    tracked[resource],
+                    usage,
+                );
+            }
+        }
+
+        let mut buffers = HashMap::new();
+        let mut images = HashMap::new();
+        for (resource, &id) in tracked.iter().enumerate() {
+            match id {
+                Tracked::Buffer(id) => {
+                    buffers.insert(id, BufferHandle(resource));
+                }
+                Tracked::Image(id) => {
+                    images.insert(id, ImageHandle(resource));
+                }
+            }
+        }
+
+        Graph {
+            pass_names: self.passes.into_iter().map(|pass| pass.name).collect(),
+            kept,
+            order: order.into_iter().map(PassId).collect(),
+            steps,
+            final_transitions,
+            heaps,
+            placements,
+            buffers,
+            images,
+        }
+    }
+
+    /// Find the passes contributing to a pass with side effects
+    /// or to an imported resource.
+    fn cull(&self) -> Vec<bool> {
+        let mut producers = vec![Vec::new(); self.passes.len()];
+        let mut last_writer = vec![None; self.resources.len()];
+        for (index, pass) in self.passes.iter().enumerate() {
+            for access in pass.accesses.iter().filter(|access| access.read) {
+                producers[index].extend(last_writer[access.resource]);
+            }
+            for access in pass.accesses.iter().filter(|access| access.write) {
+                last_writer[access.resource] = Some(index);
+            }
+        }
+
+        let mut stack = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|&(_, pass)| {
+                pass.side_effects
+                    || pass.accesses.iter().any(|access| {
+                        access.write && self.resources[access.resource].transient.is_none()
+                    })
+            })
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        let mut kept = vec![false; self.passes.len()];
+        while let Some(index) = stack.pop() {
+            if !kept[index] {
+                kept[index] = true;
+                stack.extend(&producers[index]);
+            }
+        }
+        kept
+    }
+
+    fn schedule(&self, kept: &[bool]) -> Vec<usize> {
+        // Read after write, write after write and write after read dependencies
+        // between the kept passes.
+        let mut dependents = vec![Vec::new(); self.passes.len()];
+        let mut pending = vec![0; self.passes.len()];
+        let mut writer: Vec<Option<usize>> = vec![None; self.resources.len()];
+        let mut readers = vec![Vec::new(); self.resources.len()];
+        for index in (0 .. self.passes.len()).filter(|&index| kept[index]) {
+            let accesses = &self.passes[index].accesses;
+            let mut after = Vec::new();
+            for access in accesses {
+                after.extend(writer[access.resource]);
+                if access.write {
+                    after.extend(readers[access.resource].iter().cloned());
+                }
+            }
+            after.sort();
+            after.dedup();
+            for &other in &after {
+                dependents[other].push(index);
+            }
+            pending[index] = after.len();
+
+            for access in accesses {
+                if access.write {
+                    writer[access.resource] = Some(index);
+                    readers[access.resource].clear();
+                } else {
+                    readers[access.resource].push(index);
+                }
+            }
+        }
+
+        // Ready passes are keyed by the step making them ready, latest first,
+        // then by declaration.
+        let mut ready = (0 .. self.passes.len())
+            .filter(|&index| kept[index] && pending[index] == 0)
+            .map(|index| (0, Reverse(index)))
+            .collect::<BinaryHeap<_>>();
+        let mut order = Vec::new();
+        while let Some((_, Reverse(index))) = ready.pop() {
+            order.push(index);
+            for &other in &dependents[index] {
+                pending[other] -= 1;
+                if pending[other] == 0 {
+                    ready.push((order.len(), Reverse(other)));
+                }
+            }
+        }
+        order
+    }
+
+    fn lifetimes(&self, order: &[usize]) -> Vec<Option<Lifetime>> {
+        let mut lifetimes: Vec<Option<Lifetime>> = vec![None; self.resources.len()];
+        for (step, &pass) in order.iter().enumerate() {
+            for access in &self.passes[pass].accesses {
+                let stages = access.usage.stages();
+                match lifetimes[access.resource] {
+                    Some(ref mut lifetime) => {
+                        lifetime.steps.end = step;
+                        lifetime.last_stages = stages;
+                    }
+                    None => {
+                        lifetimes[access.resource] = Some(Lifetime {
+                            steps: step .. step,
+                            first_stages: stages,
+                            last_stages: stages,
+                        })
+                    }
+                }
+            }
+        }
+        lifetimes
+    }
+
+    /// Place the used transient resources, largest first, at the lowest offset
+    /// not overlapping the resources alive at the same time. Resources are only
+    /// shared between heaps with exactly the same memory types.
+    fn place(
+        &self,
+        lifetimes: &[Option<Lifetime>],
+    ) -> (Vec<TransientHeap>, Vec<Option<Placement>>) {
+        let mut transients = self
+            .resources
+            .iter()
+            .enumerate()
+            .filter_map(
+                |(index, resource)| match (resource.transient, &lifetimes[index]) {
+                    (Some(requirements), Some(_)) => Some((index, requirements)),
+                    _ => None,
+                },
+            )
+            .collect::<Vec<_>>();
+        transients.sort_by_key(|&(_, requirements)| Reverse(requirements.size));
+
+        let mut heaps: Vec<TransientHeap> = Vec::new();
+        let mut placements = vec![None; self.resources.len()];
+        let mut placed: Vec<(usize, Placement, u64)> = Vec::new();
+        for (index, requirements) in transients {
+            let heap = match heaps
+                .iter()
+                .position(|heap| heap.type_mask == requirements.type_mask)
+            {
+                Some(heap) => heap,
+                None => {
+                    heaps.push(TransientHeap {
+                        type_mask: requirements.type_mask,
+                        size: 0,
+                        alignment: 1,
+                    });
+                    heaps.len() - 1
+                }
+            };
+
+            let lifetime = lifetimes[index].as_ref().unwrap();
+            let conflicts = placed
+                .iter()
+                .filter(|&&(other, placement, _)| {
+                    placement.heap == heap && lifetimes[other].as_ref().unwrap().overlaps(lifetime)
+                })
+                .map(|&(_, placement, size)| placement.offset .. placement.offset + size)
+                .collect::<Vec<_>>();
+            let mut candidates = conflicts
+                .iter()
+                .map(|range| align_up(range.end, requirements.alignment))
+                .collect::<Vec<_>>();
+            candidates.push(0);
+            candidates.sort();
+            let offset = candidates
+                .into_iter()
+                .find(|&offset| {
+                    conflicts.iter().all(|range| {
+                        offset + requirements.size <= range.start || range.end <= offset
+                    })
+                })
+                .unwrap();
+
+            let heap_info = &mut heaps[heap];
+            heap_info.size = heap_info.size.max(offset + requirements.size);
+            heap_info.alignment = heap_info.alignment.max(requirements.alignment);
+            let placement = Placement { heap, offset };
+            placements[index] = Some(placement);
+            placed.push((index, placement, requirements.size));
+        }
+        (heaps, placements)
+    }
+
+    /// Wait for the previous users of the memory a transient resource is placed in.
+    fn wait_aliased(
+        &self,
+        transitions: &mut Transitions,
+        resource: usize,
+        placement: Placement,
+        lifetimes: &[Option<Lifetime>],
+        placements: &[Option<Placement>],
+    ) {
+        let memory = |index: usize, placement: Placement| {
+            placement.offset .. placement.offset + self.resources[index].transient.unwrap().size
+        };
+        let range = memory(resource, placement);
+        let lifetime = lifetimes[resource].as_ref().unwrap();
+        for (other, other_placement) in placements.iter().enumerate() {
+            let other_placement = match *other_placement {
+                Some(p) if other != resource && p.heap == placement.heap => p,
+                _ => continue,
+            };
+            let other_range = memory(other, other_placement);
+            let other_lifetime = lifetimes[other].as_ref().unwrap();
+            if other_range.start < range.end
+                && range.start < other_range.end
+                && other_lifetime.steps.end < lifetime.steps.start
+            {
+                transitions.wait(other_lifetime.last_stages .. lifetime.first_stages);
+            }
+        }
+    }
+
+    fn use_resource(
+        &self,
+        tracker: &mut Tracker,
+        transitions: &mut Transitions,
+        resource: usize,
+        tracked: Tracked,
+        usage: Usage,
+    ) {
+        match (tracked, usage) {
+            (Tracked::Buffer(id), Usage::Buffer(usage)) => {
+                tracker.use_buffer(transitions, id, usage)
+            }
+            (Tracked::Image(id), Usage::Image(usage)) => {
+                let info = self.resources[resource].image.unwrap();
+                let range = image::SubresourceRange {
+                    aspects: info.aspects,
+                    levels: 0 .. info.levels,
+                    layers: 0 .. info.layers,
+                };
+                tracker.use_image(transitions, id, &range, usage)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// Accesses of a pass under construction.
+#[derive(Debug)]
+pub struct PassBuilder<'a> {
+    graph: &'a mut GraphBuilder,
+    pass: usize,
+}
+
+impl<'a> PassBuilder<'a> {
+    pub fn id(&self) -> PassId {
+        PassId(self.pass)
+    }
+
+    fn access(self, resource: usize, usage: Usage, read: bool, write: bool) -> Self {
+        let accesses = &mut self.graph.passes[self.pass].accesses;
+        match accesses
+            .iter_mut()
+            .find(|access| access.resource == resource)
+        {
+            Some(access) => {
+                access.usage = access.usage.merge(usage);
+                access.read |= read;
+                access.write |= write;
+            }
+            None => accesses.push(Access {
+                resource,
+                usage,
+                read,
+                write,
+            }),
+        }
+        self
+    }
+
+    pub fn read_image(self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.access(image.0, Usage::Image(usage), true, false)
+    }
+
+    /// Write an image, discarding its previous content unless it's also read.
+    pub fn write_image(self, image: ImageHandle, usage: ImageUsage) -> Self {
+        self.access(image.0, Usage::Image(usage), false, true)
+    }
+
+    pub fn read_buffer(self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.access(buffer.0, Usage::Buffer(usage), true, false)
+    }
+
+    /// Write a buffer, discarding its previous content unless it's also read.
+    pub fn write_buffer(self, buffer: BufferHandle, usage: BufferUsage) -> Self {
+        self.access(buffer.0, Usage::Buffer(usage), false, true)
+    }
+
+    /// Keep the pass even if none of its results are used by the graph,
+    /// for example when it writes to resources unknown to the graph.
+    pub fn side_effects(self) -> Self {
+        self.graph.passes[self.pass].side_effects = true;
+        self
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Tracked {
+    Buffer(BufferId),
+    Image(ImageId),
+}
+
+#[derive(Clone, Debug)]
+struct Lifetime {
+    /// First and last steps using the resource, inclusive.
+    steps: Range<usize>,
+    first_stages: PipelineStage,
+    last_stages: PipelineStage,
+}
+
+impl Lifetime {
+    fn overlaps(&self, other: &Lifetime) -> bool {
+        self.steps.start <= other.steps.end && other.steps.start <= self.steps.end
+    }
+}
+
+fn align_up(offset: u64, alignment: u64) -> u64 {
+    (offset + alignment - 1) / alignment * alignment
+}
+
+/// Compiled graph, ready to be recorded every frame.
+#[derive(Debug)]
+pub struct Graph {
+    pass_names: Vec<String>,
+    kept: Vec<bool>,
+    order: Vec<PassId>,
+    /// Transitions in front of each pass of `order`.
+    steps: Vec<Transitions>,
+    final_transitions: Transitions,
+    heaps: Vec<TransientHeap>,
+    placements: Vec<Option<Placement>>,
+    buffers: HashMap<BufferId, BufferHandle>,
+    images: HashMap<ImageId, ImageHandle>,
+}
+
+impl Graph {
+    /// Passes to record, in order.
+    pub fn order(&self) -> &[PassId] {
+        &self.order
+    }
+
+    pub fn is_culled(&self, pass: PassId) -> bool {
+        !self.kept[pass.0]
+    }
+
+    pub fn pass_name(&self, pass: PassId) -> &str {
+        &self.pass_names[pass.0]
+    }
+
+    /// Memory to allocate for the transient resources.
+    pub fn heaps(&self) -> &[TransientHeap] {
+        &self.heaps
+    }
+
+    /// Placement of a transient image, `None` if it's imported or unused.
+    pub fn image_placement(&self, image: ImageHandle) -> Option<Placement> {
+        self.placements[image.0]
+    }
+
+    /// Placement of a transient buffer, `None` if it's imported or unused.
+    pub fn buffer_placement(&self, buffer: BufferHandle) -> Option<Placement> {
+        self.placements[buffer.0]
+    }
+
+    /// Record the graph, calling `record_pass` for the commands of each pass
+    /// after the barriers it needs.
+    pub unsafe fn record<'a, B, C, F, G, P>(
+        &self,
+        command_buffer: &mut C,
+        buffers: F,
+        images: G,
+        mut record_pass: P,
+    ) where
+        B: Backend,
+        C: CommandBuffer<B>,
+        F: Fn(BufferHandle) -> &'a B::Buffer,
+        G: Fn(ImageHandle) -> &'a B::Image,
+        P: FnMut(&mut C, PassId),
+    {
+        for (transitions, &pass) in self.steps.iter().zip(&self.order) {
+            self.record_transitions(command_buffer, transitions, &buffers, &images);
+            record_pass(command_buffer, pass);
+        }
+        self.record_transitions(command_buffer, &self.final_transitions, &buffers, &images);
+    }
+
+    unsafe fn record_transitions<'a, B, C, F, G>(
+        &self,
+        command_buffer: &mut C,
+        transitions: &Transitions,
+        buffers: &F,
+        images: &G,
+    ) where
+        B: Backend,
+        C: CommandBuffer<B>,
+        F: Fn(BufferHandle) -> &'a B::Buffer,
+        G: Fn(ImageHandle) -> &'a B::Image,
+    {
+        transitions.record::<B, _, _, _>(
+            command_buffer,
+            |id| buffers(self.buffers[&id]),
+            |id| images(self.images[&id]),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::{buffer, device::Device as _, image::Access as ImageAccess, image::Layout};
+    use std::iter;
+
+    #[derive(Debug, PartialEq)]
+    enum State {
+        Buffer(Range<buffer::State>),
+        Image(Range<image::State>),
+    }
+
+    fn barrier(transitions: &Transitions) -> (Range<PipelineStage>, Vec<State>) {
+        let buffers = transitions
+            .buffers
+            .iter()
+            .map(|transition| State::Buffer(transition.states.clone()));
+        let images = transitions
+            .images
+            .iter()
+            .map(|transition| State::Image(transition.states.clone()));
+        (transitions.stages(), buffers.chain(images).collect())
+    }
+
+    /// Barriers recorded in front of the passes and after the last one.
+    fn barriers(graph: &Graph) -> Vec<(Range<PipelineStage>, Vec<State>)> {
+        graph
+            .steps
+            .iter()
+            .chain(iter::once(&graph.final_transitions))
+            .filter(|transitions| !transitions.is_empty())
+            .map(barrier)
+            .collect()
+    }
+
+    /// Calls made when recording the graph, along with the names of the passes.
+    fn record(graph: &Graph) -> Vec<String> {
+        let device = mock::Device;
+        let buffer = unsafe { device.create_buffer(256, buffer::Usage::STORAGE) }.unwrap();
+        let image = unsafe {
+            device.create_image(
+                image::Kind::D2(16, 16, 1, 1),
+                1,
+                hal::format::Format::Rgba8Unorm,
+                image::Tiling::Optimal,
+                image::Usage::COLOR_ATTACHMENT | image::Usage::SAMPLED,
+                image::ViewCapabilities::empty(),
+            )
+        }
+        .unwrap();
+        mock::take_calls();
+
+        let mut calls = Vec::new();
+        unsafe {
+            graph.record(
+                &mut mock::CommandBuffer,
+                |_| &buffer,
+                |_| &image,
+                |_, pass| {
+                    calls.extend(mock::take_calls().into_iter().map(String::from));
+                    calls.push(graph.pass_name(pass).to_string());
+                },
+            );
+        }
+        calls.extend(mock::take_calls().into_iter().map(String::from));
+        calls
+    }
+
+    const COLOR: ImageInfo = ImageInfo {
+        aspects: Aspects::COLOR,
+        levels: 1,
+        layers: 1,
+    };
+
+    const REQUIREMENTS: Requirements = Requirements {
+        size: 256,
+        alignment: 64,
+        type_mask: 0x3,
+    };
+
+    fn color_write() -> ImageUsage {
+        ImageUsage {
+            access: ImageAccess::COLOR_ATTACHMENT_WRITE,
+            layout: Layout::ColorAttachmentOptimal,
+            stages: PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+        }
+    }
+
+    fn sampled() -> ImageUsage {
+        ImageUsage {
+            access: ImageAccess::SHADER_READ,
+            layout: Layout::ShaderReadOnlyOptimal,
+            stages: PipelineStage::FRAGMENT_SHADER,
+        }
+    }
+
+    #[test]
+    fn test_cull_and_barriers() {
+        let undefined = (ImageAccess::empty(), Layout::Undefined);
+        let attachment = (ImageAccess::COLOR_ATTACHMENT_WRITE, Layout::ColorAttachmentOptimal);
+        let shader = (ImageAccess::SHADER_READ, Layout::ShaderReadOnlyOptimal);
+        let mut builder = GraphBuilder::new();
+        let present = ImageUsage {
+            access: ImageAccess::empty(),
+            layout: Layout::Present,
+            stages: PipelineStage::BOTTOM_OF_PIPE,
+        };
+        let backbuffer =
+            builder.import_image("backbuffer", COLOR, Layout::Undefined, Some(present));
+        let debug = builder.create_image("debug", COLOR, REQUIREMENTS);
+        let lighting = builder.create_image("lighting", COLOR, REQUIREMENTS);
+
+        let debug_pass = builder
+            .add_pass("debug")
+            .write_image(debug, color_write())
+            .id();
+        let light_pass = builder
+            .add_pass("light")
+            .write_image(lighting, color_write())
+            .id();
+        let compose_pass = builder
+            .add_pass("compose")
+            .read_image(lighting, sampled())
+            .write_image(backbuffer, color_write())
+            .id();
+        let graph = builder.compile();
+
+        assert!(graph.is_culled(debug_pass));
+        assert_eq!(graph.order(), &[light_pass, compose_pass]);
+        assert_eq!(graph.image_placement(debug), None);
+        assert_eq!(graph.image_placement(backbuffer), None);
+
+        assert_eq!(
+            record(&graph),
+            [
+                "pipeline_barrier",
+                "light",
+                "pipeline_barrier",
+                "compose",
+                "pipeline_barrier",
+            ]
+        );
+        assert_eq!(
+            barriers(&graph),
+            vec![
+                (
+                    PipelineStage::TOP_OF_PIPE .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    vec![State::Image(undefined .. attachment)],
+                ),
+                (
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT
+                        .. PipelineStage::FRAGMENT_SHADER | PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                    vec![
+                        State::Image(attachment .. shader),
+                        State::Image(undefined .. attachment),
+                    ],
+                ),
+                (
+                    PipelineStage::COLOR_ATTACHMENT_OUTPUT .. PipelineStage::BOTTOM_OF_PIPE,
+                    vec![State::Image(attachment .. (ImageAccess::empty(), Layout::Present))],
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_side_effects() {
+        let mut builder = GraphBuilder::new();
+        let scratch = builder.create_buffer("scratch", REQUIREMENTS);
+        let usage = BufferUsage {
+            access: buffer::Access::SHADER_WRITE,
+            stages: PipelineStage::COMPUTE_SHADER,
+        };
+        let unused = builder.add_pass("unused").write_buffer(scratch, usage).id();
+        let kept = builder
+            .add_pass("kept")
+            .write_buffer(scratch, usage)
+            .side_effects()
+            .id();
+        let graph = builder.compile();
+
+        assert!(graph.is_culled(unused));
+        assert_eq!(graph.order(), &[kept]);
+        assert_eq!(
+            graph.buffer_placement(scratch),
+            Some(Placement { heap: 0, offset: 0 })
+        );
+    }
+
+    #[test]
+    fn test_order_and_aliasing() {
+        let undefined = (ImageAccess::empty(), Layout::Undefined);
+        let attachment = (ImageAccess::COLOR_ATTACHMENT_WRITE, Layout::ColorAttachmentOptimal);
+        let mut builder = GraphBuilder::new();
+        let first_out = builder.import_image("first", COLOR, Layout::General, None);
+        let second_out = builder.import_image("second", COLOR, Layout::General, None);
+        let first = builder.create_image("first temp", COLOR, REQUIREMENTS);
+        let second = builder.create_image("second temp", COLOR, REQUIREMENTS);
+        let shared = builder.create_image("shared", COLOR, REQUIREMENTS);
+
+        let a = builder
+            .add_pass("a")
+            .write_image(first, color_write())
+            .write_image(shared, color_write())
+            .id();
+        let b = builder
+            .add_pass("b")
+            .write_image(second, color_write())
+            .id();
+        let c = builder
+            .add_pass("c")
+            .read_image(first, sampled())
+            .write_image(first_out, color_write())
+            .id();
+        let d = builder
+            .add_pass("d")
+            .read_image(second, sampled())
+            .read_image(shared, sampled())
+            .write_image(second_out, color_write())
+            .id();
+        let graph = builder.compile();
+
+        // `c` only depends on `a`, so it's moved before `b`,
+        // ending the lifetime of `first` before `second` starts.
+        assert_eq!(graph.order(), &[a, c, b, d]);
+        assert_eq!(
+            graph.heaps(),
+            &[TransientHeap {
+                type_mask: 0x3,
+                size: 512,
+                alignment: 64,
+            }]
+        );
+        assert_eq!(
+            graph.image_placement(first),
+            Some(Placement { heap: 0, offset: 0 })
+        );
+        assert_eq!(
+            graph.image_placement(second),
+            Some(Placement { heap: 0, offset: 0 })
+        );
+        assert_eq!(
+            graph.image_placement(shared),
+            Some(Placement {
+                heap: 0,
+                offset: 256
+            })
+        );
+
+        // `b` waits for `c` to be done sampling the memory it reuses.
+        let calls = record(&graph);
+        let position = calls.iter().position(|call| call == "b").unwrap();
+        assert_eq!(calls[position - 1], "pipeline_barrier");
+        let step = graph.order().iter().position(|&pass| pass == b).unwrap();
+        assert_eq!(
+            barrier(&graph.steps[step]),
+            (
+                PipelineStage::FRAGMENT_SHADER .. PipelineStage::COLOR_ATTACHMENT_OUTPUT,
+                vec![State::Image(undefined .. attachment)],
+            )
+        );
+    }
+}
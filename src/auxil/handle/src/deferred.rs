@@ -0,0 +1,150 @@
+use std::collections::VecDeque;
+
+/// Queue of resources waiting for the submissions which may use them.
+///
+/// Submissions are numbered in order. A resource pushed after `n` submissions
+/// is released once the first `n` submissions have completed, which are checked
+/// in order on their fences.
+#[derive(Debug)]
+pub(crate) struct Deferred<F, R> {
+    /// Number of submissions made.
+    submitted: u64,
+    /// Number of submissions found complete.
+    completed: u64,
+    /// Fences of the submissions in flight, oldest first.
+    in_flight: VecDeque<F>,
+    /// Fences of completed submissions, to be reused.
+    free: Vec<F>,
+    /// Resources with the number of submissions they wait for.
+    retired: VecDeque<(u64, R)>,
+}
+
+impl<F, R> Deferred<F, R> {
+    pub fn new() -> Self {
+        Deferred {
+            submitted: 0,
+            completed: 0,
+            in_flight: VecDeque::new(),
+            free: Vec::new(),
+            retired: VecDeque::new(),
+        }
+    }
+
+    /// Queue `resource` until the submissions made so far have completed.
+    pub fn retire(&mut self, resource: R) {
+        self.retired.push_back((self.submitted, resource));
+    }
+
+    /// Take a fence of a completed submission, which needs a reset before reuse.
+    pub fn take_fence(&mut self) -> Option<F> {
+        self.free.pop()
+    }
+
+    /// Record a submission signalling `fence`.
+    pub fn submit(&mut self, fence: F) {
+        self.in_flight.push_back(fence);
+        self.submitted += 1;
+    }
+
+    /// Check whether no submission is in flight.
+    pub fn is_idle(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+
+    /// Fences of the submissions in flight, oldest first.
+    pub fn in_flight(&self) -> impl Iterator<Item = &F> {
+        self.in_flight.iter()
+    }
+
+    /// Check the submissions in flight in order with `is_complete`, stopping at the
+    /// first one not complete, and return the resources which aren't used anymore.
+    pub fn poll<E>(
+        &mut self,
+        mut is_complete: impl FnMut(&F) -> Result<bool, E>,
+    ) -> Result<Vec<R>, E> {
+        while let Some(fence) = self.in_flight.front() {
+            if !is_complete(fence)? {
+                break;
+            }
+            let fence = self.in_flight.pop_front().unwrap();
+            self.free.push(fence);
+            self.completed += 1;
+        }
+
+        let mut released = Vec::new();
+        while let Some(&(submitted, _)) = self.retired.front() {
+            if submitted > self.completed {
+                break;
+            }
+            released.push(self.retired.pop_front().unwrap().1);
+        }
+        Ok(released)
+    }
+
+    /// Return all the fences and retired resources, regardless of the submissions.
+    pub fn drain(&mut self) -> (Vec<F>, Vec<R>) {
+        let fences = self
+            .in_flight
+            .drain(..)
+            .chain(self.free.drain(..))
+            .collect();
+        let resources = self
+            .retired
+            .drain(..)
+            .map(|(_, resource)| resource)
+            .collect();
+        self.completed = self.submitted;
+        (fences, resources)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Queue = Deferred<u32, &'static str>;
+
+    fn poll(queue: &mut Queue, signalled: u32) -> Vec<&'static str> {
+        queue.poll(|&fence| Ok::<_, ()>(fence < signalled)).unwrap()
+    }
+
+    #[test]
+    fn test_release_unused() {
+        let mut queue = Queue::new();
+        queue.retire("a");
+        assert_eq!(poll(&mut queue, 0), vec!["a"]);
+    }
+
+    #[test]
+    fn test_release_in_order() {
+        let mut queue = Queue::new();
+        queue.submit(0);
+        queue.retire("a");
+        queue.submit(1);
+        queue.retire("b");
+        queue.retire("c");
+        assert_eq!(poll(&mut queue, 0), Vec::<&str>::new());
+        assert_eq!(poll(&mut queue, 1), vec!["a"]);
+        assert_eq!(queue.in_flight().collect::<Vec<_>>(), vec![&1]);
+        assert_eq!(poll(&mut queue, 2), vec!["b", "c"]);
+        assert!(queue.is_idle());
+        assert_eq!(queue.take_fence(), Some(1));
+        assert_eq!(queue.take_fence(), Some(0));
+        assert_eq!(queue.take_fence(), None);
+    }
+
+    #[test]
+    fn test_stop_at_incomplete() {
+        let mut queue = Queue::new();
+        queue.submit(1);
+        queue.submit(0);
+        queue.retire("a");
+        // The second submission completed first, but the first one still uses "a".
+        assert_eq!(poll(&mut queue, 1), Vec::<&str>::new());
+        assert_eq!(queue.in_flight().count(), 2);
+
+        let (fences, resources) = queue.drain();
+        assert_eq!(fences, vec![1, 0]);
+        assert_eq!(resources, vec!["a"]);
+    }
+}
@@ -0,0 +1,626 @@
+use crate::{
+    deferred::Deferred,
+    resource::{
+        Buffer,
+        ComputePipeline,
+        DescriptorPool,
+        DescriptorSetLayout,
+        GraphicsPipeline,
+        Image,
+        ImageView,
+        Resource,
+    },
+};
+use gfx_memory::{Allocation, Allocator};
+use hal::{
+    adapter::MemoryProperties,
+    buffer,
+    device::{
+        AllocationError,
+        BindError,
+        Device as _,
+        DeviceLost,
+        OomOrDeviceLost,
+        OutOfMemory,
+        WaitFor,
+    },
+    format::{Format, Swizzle},
+    image,
+    pso,
+    queue::CommandQueue,
+    Backend,
+    Limits,
+};
+use std::{
+    borrow::Borrow,
+    fmt,
+    iter,
+    sync::{Arc, Mutex},
+};
+
+/// State shared by a device and the handles it created.
+#[derive(Debug)]
+pub(crate) struct Shared<B: Backend> {
+    pub raw: B::Device,
+    allocator: Mutex<Allocator<B>>,
+    deferred: Mutex<Deferred<B::Fence, Resource<B>>>,
+}
+
+impl<B: Backend> Shared<B> {
+    /// Destroy `resource` once the submissions made so far have completed.
+    pub fn retire(&self, resource: Resource<B>) {
+        self.deferred.lock().unwrap().retire(resource);
+    }
+}
+
+impl<B: Backend> Drop for Shared<B> {
+    fn drop(&mut self) {
+        let deferred = self.deferred.get_mut().unwrap();
+        let idle = deferred.is_idle()
+            || unsafe {
+                self.raw
+                    .wait_for_fences(deferred.in_flight(), WaitFor::All, !0)
+            } == Ok(true);
+        // Leak everything rather than destroying resources still in use.
+        if idle {
+            let (fences, resources) = deferred.drain();
+            let allocator = self.allocator.get_mut().unwrap();
+            unsafe {
+                // Sets are retired before their pool, so no pool is left behind,
+                // and freeing the last allocation of a block frees its memory.
+                for resource in resources {
+                    let _ = resource.destroy(&self.raw, allocator);
+                }
+                for fence in fences {
+                    self.raw.destroy_fence(fence);
+                }
+            }
+        }
+    }
+}
+
+/// Possible cause of a resource creation failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum CreationError {
+    /// None of the memory types supported by the resource has the requested properties.
+    NoCompatibleMemoryType,
+    Buffer(buffer::CreationError),
+    Image(image::CreationError),
+    Allocation(AllocationError),
+    Bind(BindError),
+}
+
+impl From<buffer::CreationError> for CreationError {
+    fn from(error: buffer::CreationError) -> Self {
+        CreationError::Buffer(error)
+    }
+}
+
+impl From<image::CreationError> for CreationError {
+    fn from(error: image::CreationError) -> Self {
+        CreationError::Image(error)
+    }
+}
+
+impl From<AllocationError> for CreationError {
+    fn from(error: AllocationError) -> Self {
+        CreationError::Allocation(error)
+    }
+}
+
+impl From<BindError> for CreationError {
+    fn from(error: BindError) -> Self {
+        CreationError::Bind(error)
+    }
+}
+
+impl From<gfx_memory::AllocationError> for CreationError {
+    fn from(error: gfx_memory::AllocationError) -> Self {
+        match error {
+            gfx_memory::AllocationError::NoCompatibleMemoryType => {
+                CreationError::NoCompatibleMemoryType
+            }
+            gfx_memory::AllocationError::Device(error) => CreationError::Allocation(error),
+        }
+    }
+}
+
+impl fmt::Display for CreationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CreationError::NoCompatibleMemoryType => {
+                write!(fmt, "Failed to create resource: No compatible memory type")
+            }
+            CreationError::Buffer(err) => write!(fmt, "Failed to create resource: {}", err),
+            CreationError::Image(err) => write!(fmt, "Failed to create resource: {}", err),
+            CreationError::Allocation(err) => write!(fmt, "Failed to create resource: {}", err),
+            CreationError::Bind(err) => write!(fmt, "Failed to create resource: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for CreationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CreationError::NoCompatibleMemoryType => None,
+            CreationError::Buffer(err) => Some(err),
+            CreationError::Image(err) => Some(err),
+            CreationError::Allocation(err) => Some(err),
+            CreationError::Bind(err) => Some(err),
+        }
+    }
+}
+
+/// Logical device creating owned handles.
+///
+/// Buffers and images are sub-allocated from larger memory blocks by a `gfx_memory::Allocator`.
+/// The device and all the handles share the raw device, which is destroyed along with
+/// the last of them once the submissions are complete.
+#[derive(Debug)]
+pub struct Device<B: Backend> {
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> Clone for Device<B> {
+    fn clone(&self) -> Self {
+        Device {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<B: Backend> Device<B> {
+    /// Wrap `raw`, which allocates memory with the given properties and limits.
+    pub fn new(
+        raw: B::Device,
+        memory_properties: MemoryProperties,
+        limits: &Limits,
+        config: gfx_memory::Config,
+    ) -> Self {
+        Device {
+            shared: Arc::new(Shared {
+                raw,
+                allocator: Mutex::new(Allocator::new(memory_properties, limits, config)),
+                deferred: Mutex::new(Deferred::new()),
+            }),
+        }
+    }
+
+    pub fn raw(&self) -> &B::Device {
+        &self.shared.raw
+    }
+
+    /// Call `f` with the memory object `allocation` lives in, at `Allocation::offset`.
+    ///
+    /// The allocator stays locked meanwhile, so `f` must not create nor drop resources.
+    pub fn with_memory<T>(&self, allocation: &Allocation, f: impl FnOnce(&B::Memory) -> T) -> T {
+        f(self.shared.allocator.lock().unwrap().memory(allocation))
+    }
+
+    /// Create a buffer bound to memory with `memory_usage`.
+    pub fn create_buffer(
+        &self,
+        size: u64,
+        usage: buffer::Usage,
+        memory_usage: gfx_memory::Usage,
+    ) -> Result<Buffer<B>, CreationError> {
+        let raw = &self.shared.raw;
+        let mut allocator = self.shared.allocator.lock().unwrap();
+        unsafe {
+            let mut buffer = raw.create_buffer(size, usage)?;
+            let requirements = raw.get_buffer_requirements(&buffer);
+            let kind = gfx_memory::Kind::Linear;
+            let allocation = match allocator.allocate(raw, &requirements, memory_usage, kind) {
+                Ok(allocation) => allocation,
+                Err(err) => {
+                    raw.destroy_buffer(buffer);
+                    return Err(err.into());
+                }
+            };
+            let memory = allocator.memory(&allocation);
+            if let Err(err) = raw.bind_buffer_memory(memory, allocation.offset(), &mut buffer) {
+                raw.destroy_buffer(buffer);
+                allocator.free(raw, allocation);
+                return Err(err.into());
+            }
+            Ok(Buffer::new(&self.shared, buffer, allocation, size, usage))
+        }
+    }
+
+    /// Create an image bound to memory with `memory_usage`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+        memory_usage: gfx_memory::Usage,
+    ) -> Result<Image<B>, CreationError> {
+        let raw = &self.shared.raw;
+        let mut allocator = self.shared.allocator.lock().unwrap();
+        unsafe {
+            let mut image = raw.create_image(kind, mip_levels, format, tiling, usage, view_caps)?;
+            let requirements = raw.get_image_requirements(&image);
+            let layout = match tiling {
+                image::Tiling::Linear => gfx_memory::Kind::Linear,
+                image::Tiling::Optimal => gfx_memory::Kind::Optimal,
+            };
+            let allocation = match allocator.allocate(raw, &requirements, memory_usage, layout) {
+                Ok(allocation) => allocation,
+                Err(err) => {
+                    raw.destroy_image(image);
+                    return Err(err.into());
+                }
+            };
+            let memory = allocator.memory(&allocation);
+            if let Err(err) = raw.bind_image_memory(memory, allocation.offset(), &mut image) {
+                raw.destroy_image(image);
+                allocator.free(raw, allocation);
+                return Err(err.into());
+            }
+            Ok(Image::new(
+                &self.shared,
+                image,
+                allocation,
+                kind,
+                mip_levels,
+                format,
+                usage,
+                view_caps,
+            ))
+        }
+    }
+
+    /// Create a view of `range` of `image`, which has to be within the image.
+    pub fn create_image_view<'a>(
+        &self,
+        image: &'a Image<B>,
+        view_kind: image::ViewKind,
+        format: Format,
+        swizzle: Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<ImageView<'a, B>, image::ViewCreationError> {
+        if range.levels.start >= range.levels.end || range.levels.end > image.levels() {
+            return Err(image::ViewCreationError::Level(range.levels.end.max(1) - 1));
+        }
+        if range.layers.start >= range.layers.end || range.layers.end > image.kind().num_layers() {
+            return Err(image::ViewCreationError::Layer(
+                image::LayerError::OutOfBounds(range.layers),
+            ));
+        }
+        if !format.surface_desc().aspects.contains(range.aspects)
+            || (format != image.format()
+                && !image
+                    .view_caps()
+                    .contains(image::ViewCapabilities::MUTABLE_FORMAT))
+        {
+            return Err(image::ViewCreationError::BadFormat(format));
+        }
+
+        let view = unsafe {
+            self.shared
+                .raw
+                .create_image_view(image.raw(), view_kind, format, swizzle, range)?
+        };
+        Ok(ImageView::new(view, image))
+    }
+
+    /// Create a graphics pipeline.
+    ///
+    /// # Safety
+    ///
+    /// The description has to be valid for `hal::device::Device::create_graphics_pipeline`.
+    pub unsafe fn create_graphics_pipeline(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<B>,
+        cache: Option<&B::PipelineCache>,
+    ) -> Result<GraphicsPipeline<B>, pso::CreationError> {
+        let pipeline = self.shared.raw.create_graphics_pipeline(desc, cache)?;
+        Ok(GraphicsPipeline::new(&self.shared, pipeline))
+    }
+
+    /// Create a compute pipeline.
+    ///
+    /// # Safety
+    ///
+    /// The description has to be valid for `hal::device::Device::create_compute_pipeline`.
+    pub unsafe fn create_compute_pipeline(
+        &self,
+        desc: &pso::ComputePipelineDesc<B>,
+        cache: Option<&B::PipelineCache>,
+    ) -> Result<ComputePipeline<B>, pso::CreationError> {
+        let pipeline = self.shared.raw.create_compute_pipeline(desc, cache)?;
+        Ok(ComputePipeline::new(&self.shared, pipeline))
+    }
+
+    /// Create a descriptor set layout without immutable samplers.
+    pub fn create_descriptor_set_layout(
+        &self,
+        bindings: &[pso::DescriptorSetLayoutBinding],
+    ) -> Result<DescriptorSetLayout<B>, OutOfMemory> {
+        let layout = unsafe {
+            self.shared
+                .raw
+                .create_descriptor_set_layout(bindings, iter::empty::<B::Sampler>())?
+        };
+        Ok(DescriptorSetLayout::new(&self.shared, layout))
+    }
+
+    /// Create a descriptor pool, which always allows freeing individual sets.
+    pub fn create_descriptor_pool(
+        &self,
+        max_sets: usize,
+        ranges: &[pso::DescriptorRangeDesc],
+    ) -> Result<DescriptorPool<B>, OutOfMemory> {
+        let pool = unsafe {
+            self.shared.raw.create_descriptor_pool(
+                max_sets,
+                ranges,
+                pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+            )?
+        };
+        Ok(DescriptorPool::new(&self.shared, pool))
+    }
+
+    /// Submit `command_buffers` to `queue`, tracking their completion with a fence.
+    ///
+    /// # Safety
+    ///
+    /// The command buffers have to be valid for submission, and the handles they use
+    /// must not be dropped before this call.
+    pub unsafe fn submit<'a, T, Ic>(
+        &self,
+        queue: &mut B::CommandQueue,
+        command_buffers: Ic,
+    ) -> Result<(), OutOfMemory>
+    where
+        T: 'a + Borrow<B::CommandBuffer>,
+        Ic: IntoIterator<Item = &'a T>,
+    {
+        let raw = &self.shared.raw;
+        // Keep the lock, so that handles dropped meanwhile wait for this submission.
+        let mut deferred = self.shared.deferred.lock().unwrap();
+        let fence = match deferred.take_fence() {
+            Some(fence) => match raw.reset_fence(&fence) {
+                Ok(()) => fence,
+                Err(err) => {
+                    raw.destroy_fence(fence);
+                    return Err(err);
+                }
+            },
+            None => raw.create_fence(false)?,
+        };
+        queue.submit_without_semaphores(command_buffers, Some(&fence));
+        deferred.submit(fence);
+        Ok(())
+    }
+
+    /// Destroy the retired resources which aren't used by submissions anymore.
+    pub fn maintain(&self) -> Result<(), DeviceLost> {
+        let raw = &self.shared.raw;
+        let released = self
+            .shared
+            .deferred
+            .lock()
+            .unwrap()
+            .poll(|fence| unsafe { raw.get_fence_status(fence) })?;
+        let mut allocator = self.shared.allocator.lock().unwrap();
+        for resource in released {
+            // A pool is retired again until the last of its sets has been freed.
+            if let Err(resource) = unsafe { resource.destroy(raw, &mut allocator) } {
+                self.shared.retire(resource);
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait for all the submissions to complete, and destroy the retired resources.
+    pub fn wait_idle(&self) -> Result<(), OomOrDeviceLost> {
+        {
+            let deferred = self.shared.deferred.lock().unwrap();
+            if !deferred.is_idle() {
+                unsafe {
+                    self.shared
+                        .raw
+                        .wait_for_fences(deferred.in_flight(), WaitFor::All, !0)?;
+                }
+            }
+        }
+        self.maintain()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::adapter::PhysicalDevice as _;
+    use std::iter;
+
+    fn device() -> Device<mock::Backend> {
+        let physical_device = mock::PhysicalDevice;
+        let device = Device::new(
+            mock::Device,
+            physical_device.memory_properties(),
+            &physical_device.limits(),
+            gfx_memory::Config::default(),
+        );
+        mock::take_calls();
+        device
+    }
+
+    fn submit(device: &Device<mock::Backend>, queue: &mut mock::CommandQueue) {
+        unsafe {
+            device
+                .submit(queue, iter::empty::<&mock::CommandBuffer>())
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_sub_allocate() {
+        let device = device();
+        let a = device
+            .create_buffer(16, buffer::Usage::VERTEX, gfx_memory::Usage::UPLOAD)
+            .unwrap();
+        let b = device
+            .create_buffer(16, buffer::Usage::VERTEX, gfx_memory::Usage::UPLOAD)
+            .unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "create_buffer",
+                "get_buffer_requirements",
+                "allocate_memory",
+                "bind_buffer_memory",
+                "create_buffer",
+                "get_buffer_requirements",
+                "bind_buffer_memory",
+            ]
+        );
+        assert_eq!(a.allocation().memory_type(), mock::HOST_VISIBLE);
+        assert_ne!(a.allocation().offset(), b.allocation().offset());
+
+        drop(a);
+        drop(b);
+        device.maintain().unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            ["destroy_buffer", "destroy_buffer", "free_memory"]
+        );
+    }
+
+    #[test]
+    fn test_no_compatible_memory_type() {
+        let device = device();
+        let usage = gfx_memory::Usage {
+            required: hal::memory::Properties::CPU_VISIBLE,
+            preferred: hal::memory::Properties::empty(),
+        };
+        let result = device.create_image(
+            image::Kind::D2(4, 4, 1, 1),
+            1,
+            Format::Rgba8Unorm,
+            image::Tiling::Optimal,
+            image::Usage::SAMPLED,
+            image::ViewCapabilities::empty(),
+            usage,
+        );
+        assert_eq!(result.unwrap_err(), CreationError::NoCompatibleMemoryType);
+        assert_eq!(
+            mock::take_calls(),
+            ["create_image", "get_image_requirements", "destroy_image"]
+        );
+    }
+
+    #[test]
+    fn test_deferred_destruction() {
+        let device = device();
+        let mut queue = mock::CommandQueue::default();
+        let buffer = device
+            .create_buffer(16, buffer::Usage::VERTEX, gfx_memory::Usage::DEVICE)
+            .unwrap();
+        queue.hold_fences();
+        submit(&device, &mut queue);
+        drop(buffer);
+        mock::take_calls();
+
+        // The submission may still use the buffer.
+        device.maintain().unwrap();
+        assert_eq!(mock::take_calls(), ["get_fence_status"]);
+
+        queue.release_fences();
+        device.maintain().unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            ["get_fence_status", "destroy_buffer", "free_memory"]
+        );
+
+        // Resources dropped after the last submission don't wait for anything,
+        // and the fence of a completed submission is reused.
+        submit(&device, &mut queue);
+        assert_eq!(mock::take_calls(), ["reset_fence", "submit"]);
+        let layout = device.create_descriptor_set_layout(&[]).unwrap();
+        drop(layout);
+        device.maintain().unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "create_descriptor_set_layout",
+                "get_fence_status",
+                "destroy_descriptor_set_layout",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_drop_order() {
+        let device = device();
+        let image = device
+            .create_image(
+                image::Kind::D2(4, 4, 1, 1),
+                1,
+                Format::Rgba8Unorm,
+                image::Tiling::Optimal,
+                image::Usage::SAMPLED,
+                image::ViewCapabilities::empty(),
+                gfx_memory::Usage::DEVICE,
+            )
+            .unwrap();
+        let view = device
+            .create_image_view(
+                &image,
+                image::ViewKind::D2,
+                Format::Rgba8Unorm,
+                Swizzle::NO,
+                image::SubresourceRange {
+                    aspects: hal::format::Aspects::COLOR,
+                    levels: 0 .. 1,
+                    layers: 0 .. 1,
+                },
+            )
+            .unwrap();
+        let layout = device.create_descriptor_set_layout(&[]).unwrap();
+        let pool = device.create_descriptor_pool(1, &[]).unwrap();
+        let set = pool.allocate(&layout).unwrap();
+        mock::take_calls();
+
+        // Views and sets can't outlive what they borrow, so they're destroyed first.
+        drop(view);
+        drop(image);
+        drop(set);
+        drop(pool);
+        device.maintain().unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "destroy_image_view",
+                "destroy_image",
+                "free_memory",
+                "free_sets",
+                "destroy_descriptor_pool",
+            ]
+        );
+
+        // The last handle of the device destroys what's left once the queue is idle.
+        let mut queue = mock::CommandQueue::default();
+        queue.hold_fences();
+        submit(&device, &mut queue);
+        drop(layout);
+        queue.release_fences();
+        mock::take_calls();
+        drop(device);
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "wait_for_fences",
+                "destroy_descriptor_set_layout",
+                "destroy_fence",
+            ]
+        );
+    }
+}
@@ -0,0 +1,34 @@
+//! Owned handles over gfx-hal resources.
+//!
+//! Resources are created through a `Device`, which keeps the raw device alive as long
+//! as any of its handles. Dropping a handle doesn't destroy the resource right away:
+//! it's retired until every submission made with `Device::submit` before the drop
+//! has completed, which `Device::maintain` checks on the fences of the submissions.
+//! Views and descriptor sets borrow the image and pool they come from, so they're
+//! always destroyed first.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+mod deferred;
+mod device;
+mod resource;
+
+pub use crate::{
+    device::{CreationError, Device},
+    resource::{
+        Buffer,
+        ComputePipeline,
+        DescriptorPool,
+        DescriptorSet,
+        DescriptorSetLayout,
+        GraphicsPipeline,
+        Image,
+        ImageView,
+    },
+};
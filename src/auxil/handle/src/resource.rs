@@ -0,0 +1,351 @@
+use crate::device::Shared;
+use gfx_memory::{Allocation, Allocator};
+use hal::{
+    buffer,
+    device::Device as _,
+    format::Format,
+    image,
+    pso::{AllocationError, DescriptorPool as _},
+    Backend,
+};
+use std::sync::{Arc, Mutex};
+
+/// Resource waiting for its destruction.
+#[derive(Debug)]
+pub(crate) enum Resource<B: Backend> {
+    Buffer(B::Buffer, Allocation),
+    Image(B::Image, Allocation),
+    ImageView(B::ImageView),
+    GraphicsPipeline(B::GraphicsPipeline),
+    ComputePipeline(B::ComputePipeline),
+    DescriptorSetLayout(B::DescriptorSetLayout),
+    DescriptorPool(Arc<Mutex<B::DescriptorPool>>),
+    DescriptorSet(Arc<Mutex<B::DescriptorPool>>, B::DescriptorSet),
+}
+
+impl<B: Backend> Resource<B> {
+    /// Destroy the resource, returning its memory to `allocator`.
+    ///
+    /// A descriptor pool whose sets aren't all freed yet is returned back instead.
+    pub unsafe fn destroy(
+        self,
+        device: &B::Device,
+        allocator: &mut Allocator<B>,
+    ) -> Result<(), Self> {
+        match self {
+            Resource::Buffer(buffer, allocation) => {
+                device.destroy_buffer(buffer);
+                allocator.free(device, allocation);
+            }
+            Resource::Image(image, allocation) => {
+                device.destroy_image(image);
+                allocator.free(device, allocation);
+            }
+            Resource::ImageView(view) => device.destroy_image_view(view),
+            Resource::GraphicsPipeline(pipeline) => device.destroy_graphics_pipeline(pipeline),
+            Resource::ComputePipeline(pipeline) => device.destroy_compute_pipeline(pipeline),
+            Resource::DescriptorSetLayout(layout) => device.destroy_descriptor_set_layout(layout),
+            Resource::DescriptorPool(pool) => {
+                let pool = Arc::try_unwrap(pool).map_err(Resource::DescriptorPool)?;
+                device.destroy_descriptor_pool(pool.into_inner().unwrap());
+            }
+            Resource::DescriptorSet(pool, set) => {
+                pool.lock().unwrap().free(Some(set));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Buffer bound to memory sub-allocated by its device.
+#[derive(Debug)]
+pub struct Buffer<B: Backend> {
+    raw: Option<(B::Buffer, Allocation)>,
+    size: u64,
+    usage: buffer::Usage,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> Buffer<B> {
+    pub(crate) fn new(
+        shared: &Arc<Shared<B>>,
+        raw: B::Buffer,
+        allocation: Allocation,
+        size: u64,
+        usage: buffer::Usage,
+    ) -> Self {
+        Buffer {
+            raw: Some((raw, allocation)),
+            size,
+            usage,
+            shared: Arc::clone(shared),
+        }
+    }
+
+    pub fn raw(&self) -> &B::Buffer {
+        &self.raw.as_ref().unwrap().0
+    }
+
+    /// Memory the buffer is bound to, see `Device::with_memory`.
+    pub fn allocation(&self) -> &Allocation {
+        &self.raw.as_ref().unwrap().1
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn usage(&self) -> buffer::Usage {
+        self.usage
+    }
+}
+
+impl<B: Backend> Drop for Buffer<B> {
+    fn drop(&mut self) {
+        let (buffer, allocation) = self.raw.take().unwrap();
+        self.shared.retire(Resource::Buffer(buffer, allocation));
+    }
+}
+
+/// Image bound to memory sub-allocated by its device.
+#[derive(Debug)]
+pub struct Image<B: Backend> {
+    raw: Option<(B::Image, Allocation)>,
+    kind: image::Kind,
+    levels: image::Level,
+    format: Format,
+    usage: image::Usage,
+    view_caps: image::ViewCapabilities,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> Image<B> {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        shared: &Arc<Shared<B>>,
+        raw: B::Image,
+        allocation: Allocation,
+        kind: image::Kind,
+        levels: image::Level,
+        format: Format,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Self {
+        Image {
+            raw: Some((raw, allocation)),
+            kind,
+            levels,
+            format,
+            usage,
+            view_caps,
+            shared: Arc::clone(shared),
+        }
+    }
+
+    pub fn raw(&self) -> &B::Image {
+        &self.raw.as_ref().unwrap().0
+    }
+
+    /// Memory the image is bound to, see `Device::with_memory`.
+    pub fn allocation(&self) -> &Allocation {
+        &self.raw.as_ref().unwrap().1
+    }
+
+    pub fn kind(&self) -> image::Kind {
+        self.kind
+    }
+
+    /// Number of mip levels.
+    pub fn levels(&self) -> image::Level {
+        self.levels
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn usage(&self) -> image::Usage {
+        self.usage
+    }
+
+    pub fn view_caps(&self) -> image::ViewCapabilities {
+        self.view_caps
+    }
+}
+
+impl<B: Backend> Drop for Image<B> {
+    fn drop(&mut self) {
+        let (image, allocation) = self.raw.take().unwrap();
+        self.shared.retire(Resource::Image(image, allocation));
+    }
+}
+
+/// View of an image, which can't outlive it.
+#[derive(Debug)]
+pub struct ImageView<'a, B: Backend> {
+    raw: Option<B::ImageView>,
+    image: &'a Image<B>,
+}
+
+impl<'a, B: Backend> ImageView<'a, B> {
+    pub(crate) fn new(raw: B::ImageView, image: &'a Image<B>) -> Self {
+        ImageView {
+            raw: Some(raw),
+            image,
+        }
+    }
+
+    pub fn raw(&self) -> &B::ImageView {
+        self.raw.as_ref().unwrap()
+    }
+
+    pub fn image(&self) -> &'a Image<B> {
+        self.image
+    }
+}
+
+impl<'a, B: Backend> Drop for ImageView<'a, B> {
+    fn drop(&mut self) {
+        let view = self.raw.take().unwrap();
+        self.image.shared.retire(Resource::ImageView(view));
+    }
+}
+
+#[derive(Debug)]
+pub struct GraphicsPipeline<B: Backend> {
+    raw: Option<B::GraphicsPipeline>,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> GraphicsPipeline<B> {
+    pub(crate) fn new(shared: &Arc<Shared<B>>, raw: B::GraphicsPipeline) -> Self {
+        GraphicsPipeline {
+            raw: Some(raw),
+            shared: Arc::clone(shared),
+        }
+    }
+
+    pub fn raw(&self) -> &B::GraphicsPipeline {
+        self.raw.as_ref().unwrap()
+    }
+}
+
+impl<B: Backend> Drop for GraphicsPipeline<B> {
+    fn drop(&mut self) {
+        let pipeline = self.raw.take().unwrap();
+        self.shared.retire(Resource::GraphicsPipeline(pipeline));
+    }
+}
+
+#[derive(Debug)]
+pub struct ComputePipeline<B: Backend> {
+    raw: Option<B::ComputePipeline>,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> ComputePipeline<B> {
+    pub(crate) fn new(shared: &Arc<Shared<B>>, raw: B::ComputePipeline) -> Self {
+        ComputePipeline {
+            raw: Some(raw),
+            shared: Arc::clone(shared),
+        }
+    }
+
+    pub fn raw(&self) -> &B::ComputePipeline {
+        self.raw.as_ref().unwrap()
+    }
+}
+
+impl<B: Backend> Drop for ComputePipeline<B> {
+    fn drop(&mut self) {
+        let pipeline = self.raw.take().unwrap();
+        self.shared.retire(Resource::ComputePipeline(pipeline));
+    }
+}
+
+#[derive(Debug)]
+pub struct DescriptorSetLayout<B: Backend> {
+    raw: Option<B::DescriptorSetLayout>,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> DescriptorSetLayout<B> {
+    pub(crate) fn new(shared: &Arc<Shared<B>>, raw: B::DescriptorSetLayout) -> Self {
+        DescriptorSetLayout {
+            raw: Some(raw),
+            shared: Arc::clone(shared),
+        }
+    }
+
+    pub fn raw(&self) -> &B::DescriptorSetLayout {
+        self.raw.as_ref().unwrap()
+    }
+}
+
+impl<B: Backend> Drop for DescriptorSetLayout<B> {
+    fn drop(&mut self) {
+        let layout = self.raw.take().unwrap();
+        self.shared.retire(Resource::DescriptorSetLayout(layout));
+    }
+}
+
+/// Descriptor pool allocating sets which can't outlive it.
+#[derive(Debug)]
+pub struct DescriptorPool<B: Backend> {
+    raw: Arc<Mutex<B::DescriptorPool>>,
+    shared: Arc<Shared<B>>,
+}
+
+impl<B: Backend> DescriptorPool<B> {
+    pub(crate) fn new(shared: &Arc<Shared<B>>, raw: B::DescriptorPool) -> Self {
+        DescriptorPool {
+            raw: Arc::new(Mutex::new(raw)),
+            shared: Arc::clone(shared),
+        }
+    }
+
+    /// Allocate a set with `layout`.
+    pub fn allocate(
+        &self,
+        layout: &DescriptorSetLayout<B>,
+    ) -> Result<DescriptorSet<'_, B>, AllocationError> {
+        let set = unsafe { self.raw.lock().unwrap().allocate_set(layout.raw())? };
+        Ok(DescriptorSet {
+            raw: Some(set),
+            pool: self,
+        })
+    }
+}
+
+impl<B: Backend> Drop for DescriptorPool<B> {
+    fn drop(&mut self) {
+        self.shared
+            .retire(Resource::DescriptorPool(Arc::clone(&self.raw)));
+    }
+}
+
+/// Descriptor set, freed to its pool when dropped.
+#[derive(Debug)]
+pub struct DescriptorSet<'a, B: Backend> {
+    raw: Option<B::DescriptorSet>,
+    pool: &'a DescriptorPool<B>,
+}
+
+impl<'a, B: Backend> DescriptorSet<'a, B> {
+    pub fn raw(&self) -> &B::DescriptorSet {
+        self.raw.as_ref().unwrap()
+    }
+
+    pub fn pool(&self) -> &'a DescriptorPool<B> {
+        self.pool
+    }
+}
+
+impl<'a, B: Backend> Drop for DescriptorSet<'a, B> {
+    fn drop(&mut self) {
+        let set = self.raw.take().unwrap();
+        self.pool
+            .shared
+            .retire(Resource::DescriptorSet(Arc::clone(&self.pool.raw), set));
+    }
+}
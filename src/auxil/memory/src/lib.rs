@@ -0,0 +1,750 @@
+//! Memory sub-allocator for gfx-hal.
+//!
+//! Device memory allocations are expensive and their number is limited by
+//! `Limits::max_memory_allocation_count`, so resources are placed into large
+//! blocks which are sub-allocated with a `RangeAllocator`, or any other
+//! `RangeAllocate` strategy such as `TlsfAllocator`. Resources at least
+//! as large as `Config::dedicated_threshold` get a device allocation of their own.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use hal::{
+    adapter::MemoryProperties,
+    device::{self, Device as _},
+    memory,
+    Backend,
+    Limits,
+    MemoryTypeId,
+};
+use range_alloc::{RangeAllocate, RangeAllocator};
+use std::{cmp::Reverse, fmt, ops::Range};
+
+/// How a resource lays out its data in memory.
+///
+/// Linear and non-linear resources sharing a block would have to be
+/// `Limits::buffer_image_granularity` apart, so instead they never share one.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Kind {
+    /// Buffers and images with linear tiling.
+    Linear,
+    /// Images with optimal tiling.
+    Optimal,
+}
+
+/// Memory properties a resource needs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Usage {
+    /// Properties the memory type must have.
+    pub required: memory::Properties,
+    /// Properties used to rank the memory types having the required ones.
+    pub preferred: memory::Properties,
+}
+
+impl Usage {
+    /// Memory only accessed by the device.
+    pub const DEVICE: Self = Usage {
+        required: memory::Properties::DEVICE_LOCAL,
+        preferred: memory::Properties::empty(),
+    };
+    /// Memory written by the host and read by the device.
+    pub const UPLOAD: Self = Usage {
+        required: memory::Properties::CPU_VISIBLE,
+        preferred: memory::Properties::COHERENT,
+    };
+    /// Memory written by the device and read by the host.
+    pub const DOWNLOAD: Self = Usage {
+        required: memory::Properties::CPU_VISIBLE,
+        preferred: memory::Properties::CPU_CACHED,
+    };
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Config {
+    /// Size of the blocks requested from the device.
+    pub block_size: u64,
+    /// Resources of at least this size get a dedicated allocation.
+    pub dedicated_threshold: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            block_size: 64 << 20,
+            dedicated_threshold: 32 << 20,
+        }
+    }
+}
+
+/// Possible cause of sub-allocation failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AllocationError {
+    /// No memory type matches both the type mask and the required properties.
+    NoCompatibleMemoryType,
+    /// Allocating memory from the device failed.
+    Device(device::AllocationError),
+}
+
+impl From<device::AllocationError> for AllocationError {
+    fn from(error: device::AllocationError) -> Self {
+        AllocationError::Device(error)
+    }
+}
+
+impl fmt::Display for AllocationError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AllocationError::NoCompatibleMemoryType => {
+                write!(fmt, "Failed to allocate memory: No compatible memory type")
+            }
+            AllocationError::Device(err) => write!(fmt, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for AllocationError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AllocationError::Device(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+/// Memory usage of a single heap.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct HeapStats {
+    /// Size of the heap in bytes.
+    pub size: u64,
+    /// Bytes allocated from the device, including the unused parts of blocks.
+    pub allocated: u64,
+    /// Bytes handed out to resources.
+    pub used: u64,
+    /// Number of live device allocations.
+    pub device_allocations: usize,
+    /// Number of live resource allocations.
+    pub allocations: usize,
+}
+
+#[derive(Debug)]
+enum Location {
+    Block {
+        kind: Kind,
+        index: usize,
+        range: Range<u64>,
+    },
+    Dedicated {
+        index: usize,
+    },
+}
+
+/// Memory bound to a single resource.
+///
+/// Must be returned with `Allocator::free` to the allocator it came from.
+#[derive(Debug)]
+pub struct Allocation {
+    memory_type: MemoryTypeId,
+    location: Location,
+    offset: u64,
+    size: u64,
+}
+
+impl Allocation {
+    pub fn memory_type(&self) -> MemoryTypeId {
+        self.memory_type
+    }
+
+    /// Offset of the resource in `Allocator::memory`.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_dedicated(&self) -> bool {
+        match self.location {
+            Location::Dedicated { .. } => true,
+            Location::Block { .. } => false,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Block<B: Backend, R> {
+    memory: B::Memory,
+    size: u64,
+    ranges: R,
+}
+
+/// Blocks of a single memory type and kind. Freed blocks leave a `None`
+/// behind, so that the indices stored in allocations stay valid.
+#[derive(Debug)]
+struct Pool<B: Backend, R> {
+    blocks: Vec<Option<Block<B, R>>>,
+}
+
+fn insert<T>(slots: &mut Vec<Option<T>>, value: T) -> usize {
+    match slots.iter().position(Option::is_none) {
+        Some(index) => {
+            slots[index] = Some(value);
+            index
+        }
+        None => {
+            slots.push(Some(value));
+            slots.len() - 1
+        }
+    }
+}
+
+/// Sub-allocates blocks with the `R` strategy, the best-fit `RangeAllocator` by default.
+#[derive(Debug)]
+pub struct Allocator<B: Backend, R = RangeAllocator<u64>> {
+    properties: MemoryProperties,
+    config: Config,
+    buffer_image_granularity: u64,
+    max_allocation_count: usize,
+    allocation_count: usize,
+    /// Two pools per memory type, indexed by `type * 2 + kind`.
+    pools: Vec<Pool<B, R>>,
+    dedicated: Vec<Option<(B::Memory, u64)>>,
+    heaps: Vec<HeapStats>,
+}
+
+impl<B: Backend, R: RangeAllocate<u64>> Allocator<B, R> {
+    pub fn new(properties: MemoryProperties, limits: &Limits, config: Config) -> Self {
+        let pools = (0 .. properties.memory_types.len() * 2)
+            .map(|_| Pool { blocks: Vec::new() })
+            .collect();
+        let heaps = properties
+            .memory_heaps
+            .iter()
+            .map(|&size| HeapStats {
+                size,
+                ..HeapStats::default()
+            })
+            .collect();
+        Allocator {
+            properties,
+            config,
+            buffer_image_granularity: limits.buffer_image_granularity,
+            // Backends without such a limit report 0.
+            max_allocation_count: match limits.max_memory_allocation_count {
+                0 => usize::max_value(),
+                count => count,
+            },
+            allocation_count: 0,
+            pools,
+            dedicated: Vec::new(),
+            heaps,
+        }
+    }
+
+    /// Memory types allowed by `type_mask` with the required properties,
+    /// the best match for the preferred properties first.
+    fn candidates(&self, type_mask: u64, usage: Usage) -> Vec<MemoryTypeId> {
+        let mut candidates = self
+            .properties
+            .memory_types
+            .iter()
+            .enumerate()
+            .filter(|&(id, ty)| {
+                type_mask & (1 << id) != 0 && ty.properties.contains(usage.required)
+            })
+            .map(|(id, _)| MemoryTypeId(id))
+            .collect::<Vec<_>>();
+        candidates.sort_by_key(|id| {
+            let properties = self.properties.memory_types[id.0].properties;
+            let preferred = (properties & usage.preferred).bits().count_ones();
+            let unwanted = (properties - usage.required - usage.preferred)
+                .bits()
+                .count_ones();
+            (Reverse(preferred), unwanted)
+        });
+        candidates
+    }
+
+    /// Pick the memory type a resource with `type_mask` would be allocated from.
+    pub fn find_memory_type(&self, type_mask: u64, usage: Usage) -> Option<MemoryTypeId> {
+        self.candidates(type_mask, usage).into_iter().next()
+    }
+
+    /// Allocate memory for a resource. Memory types are tried in order of preference,
+    /// falling back to the next one when the device runs out of memory.
+    pub unsafe fn allocate(
+        &mut self,
+        device: &B::Device,
+        requirements: &memory::Requirements,
+        usage: Usage,
+        kind: Kind,
+    ) -> Result<Allocation, AllocationError> {
+        let mut result = Err(AllocationError::NoCompatibleMemoryType);
+        for memory_type in self.candidates(requirements.type_mask, usage) {
+            result = if requirements.size >= self.config.dedicated_threshold {
+                self.allocate_dedicated(device, memory_type, requirements)
+            } else {
+                self.allocate_from_block(device, memory_type, requirements, kind)
+            }
+            .map_err(AllocationError::Device);
+            match result {
+                Err(AllocationError::Device(device::AllocationError::OutOfMemory(_))) => continue,
+                _ => break,
+            }
+        }
+        result
+    }
+
+    unsafe fn allocate_device_memory(
+        &mut self,
+        device: &B::Device,
+        memory_type: MemoryTypeId,
+        size: u64,
+    ) -> Result<B::Memory, device::AllocationError> {
+        if self.allocation_count >= self.max_allocation_count {
+            return Err(device::AllocationError::TooManyObjects);
+        }
+        let memory = device.allocate_memory(memory_type, size)?;
+        self.allocation_count += 1;
+        let heap = &mut self.heaps[self.properties.memory_types[memory_type.0].heap_index];
+        heap.allocated += size;
+        heap.device_allocations += 1;
+        Ok(memory)
+    }
+
+    unsafe fn free_device_memory(
+        &mut self,
+        device: &B::Device,
+        memory_type: MemoryTypeId,
+        memory: B::Memory,
+        size: u64,
+    ) {
+        device.free_memory(memory);
+        self.allocation_count -= 1;
+        let heap = &mut self.heaps[self.properties.memory_types[memory_type.0].heap_index];
+        heap.allocated -= size;
+        heap.device_allocations -= 1;
+    }
+
+    fn heap_mut(&mut self, memory_type: MemoryTypeId) -> &mut HeapStats {
+        &mut self.heaps[self.properties.memory_types[memory_type.0].heap_index]
+    }
+
+    fn pool_index(&self, memory_type: MemoryTypeId, kind: Kind) -> usize {
+        // Without a granularity requirement both kinds can share blocks.
+        let kind = if self.buffer_image_granularity > 1 {
+            kind
+        } else {
+            Kind::Linear
+        };
+        memory_type.0 * 2 + kind as usize
+    }
+
+    unsafe fn allocate_dedicated(
+        &mut self,
+        device: &B::Device,
+        memory_type: MemoryTypeId,
+        requirements: &memory::Requirements,
+    ) -> Result<Allocation, device::AllocationError> {
+        let memory = self.allocate_device_memory(device, memory_type, requirements.size)?;
+        let index = insert(&mut self.dedicated, (memory, requirements.size));
+        let heap = self.heap_mut(memory_type);
+        heap.used += requirements.size;
+        heap.allocations += 1;
+        Ok(Allocation {
+            memory_type,
+            location: Location::Dedicated { index },
+            offset: 0,
+            size: requirements.size,
+        })
+    }
+
+    unsafe fn allocate_from_block(
+        &mut self,
+        device: &B::Device,
+        memory_type: MemoryTypeId,
+        requirements: &memory::Requirements,
+        kind: Kind,
+    ) -> Result<Allocation, device::AllocationError> {
+        let alignment = requirements.alignment.max(1);
+        let length = requirements.size.max(1);
+        let pool_index = self.pool_index(memory_type, kind);
+
+        let existing = self.pools[pool_index]
+            .blocks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(index, slot)| slot.as_mut().map(|block| (index, block)))
+            .find_map(|(index, block)| {
+                block
+                    .ranges
+                    .allocate_range_aligned(length, alignment)
+                    .ok()
+                    .map(|range| (index, range))
+            });
+
+        let (index, range) = match existing {
+            Some(found) => found,
+            None => {
+                let heap_size = self.heap_mut(memory_type).size;
+                let size = self.config.block_size.min(heap_size).max(length);
+                let memory = self.allocate_device_memory(device, memory_type, size)?;
+                let mut ranges = R::new(0 .. size);
+                let range = ranges.allocate_range_aligned(length, alignment).unwrap();
+                let block = Block {
+                    memory,
+                    size,
+                    ranges,
+                };
+                (insert(&mut self.pools[pool_index].blocks, block), range)
+            }
+        };
+
+        let heap = self.heap_mut(memory_type);
+        heap.used += length;
+        heap.allocations += 1;
+        Ok(Allocation {
+            memory_type,
+            offset: range.start,
+            size: requirements.size,
+            location: Location::Block { kind, index, range },
+        })
+    }
+
+    /// Return an allocation. Blocks left without allocations are freed immediately.
+    pub unsafe fn free(&mut self, device: &B::Device, allocation: Allocation) {
+        let memory_type = allocation.memory_type;
+        match allocation.location {
+            Location::Dedicated { index } => {
+                let (memory, size) = self.dedicated[index].take().unwrap();
+                let heap = self.heap_mut(memory_type);
+                heap.used -= size;
+                heap.allocations -= 1;
+                self.free_device_memory(device, memory_type, memory, size);
+            }
+            Location::Block { kind, index, range } => {
+                let pool_index = self.pool_index(memory_type, kind);
+                let slot = &mut self.pools[pool_index].blocks[index];
+                let block = slot.as_mut().unwrap();
+                block.ranges.free_range(range.clone());
+                let empty = if block.ranges.is_empty() {
+                    slot.take()
+                } else {
+                    None
+                };
+
+                let heap = self.heap_mut(memory_type);
+                heap.used -= range.end - range.start;
+                heap.allocations -= 1;
+                if let Some(block) = empty {
+                    self.free_device_memory(device, memory_type, block.memory, block.size);
+                }
+            }
+        }
+    }
+
+    /// Memory object the allocation lives in, to bind the resource at `Allocation::offset`.
+    pub fn memory(&self, allocation: &Allocation) -> &B::Memory {
+        match allocation.location {
+            Location::Dedicated { index } => &self.dedicated[index].as_ref().unwrap().0,
+            Location::Block { kind, index, .. } => {
+                let pool_index = self.pool_index(allocation.memory_type, kind);
+                &self.pools[pool_index].blocks[index]
+                    .as_ref()
+                    .unwrap()
+                    .memory
+            }
+        }
+    }
+
+    /// Usage statistics, indexed like `MemoryProperties::memory_heaps`.
+    pub fn heap_stats(&self) -> &[HeapStats] {
+        &self.heaps
+    }
+
+    /// Number of live device allocations across all heaps.
+    pub fn device_allocation_count(&self) -> usize {
+        self.allocation_count
+    }
+
+    /// Free all device memory. Outstanding allocations become invalid.
+    pub unsafe fn dispose(self, device: &B::Device) {
+        for pool in self.pools {
+            for block in pool.blocks.into_iter().flatten() {
+                device.free_memory(block.memory);
+            }
+        }
+        for (memory, _) in self.dedicated.into_iter().flatten() {
+            device.free_memory(memory);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::adapter::MemoryType;
+    use range_alloc::TlsfAllocator;
+
+    fn properties() -> MemoryProperties {
+        use memory::Properties as P;
+        MemoryProperties {
+            memory_types: vec![
+                MemoryType {
+                    properties: P::DEVICE_LOCAL,
+                    heap_index: 0,
+                },
+                MemoryType {
+                    properties: P::CPU_VISIBLE | P::COHERENT,
+                    heap_index: 1,
+                },
+                MemoryType {
+                    properties: P::CPU_VISIBLE | P::COHERENT | P::CPU_CACHED,
+                    heap_index: 2,
+                },
+            ],
+            // Heap 1 is larger than the mock device can allocate at once.
+            memory_heaps: vec![1 << 30, 2 << 30, 256 << 20],
+        }
+    }
+
+    fn allocator(granularity: u64, max_allocation_count: usize) -> Allocator<mock::Backend> {
+        let limits = Limits {
+            buffer_image_granularity: granularity,
+            max_memory_allocation_count: max_allocation_count,
+            ..Limits::default()
+        };
+        let config = Config {
+            block_size: 1024,
+            dedicated_threshold: 512,
+        };
+        Allocator::new(properties(), &limits, config)
+    }
+
+    fn requirements(size: u64, alignment: u64) -> memory::Requirements {
+        memory::Requirements {
+            size,
+            alignment,
+            type_mask: !0,
+        }
+    }
+
+    #[test]
+    fn test_memory_type_selection() {
+        let alloc = allocator(1, 16);
+        assert_eq!(
+            alloc.find_memory_type(!0, Usage::DEVICE),
+            Some(MemoryTypeId(0))
+        );
+        assert_eq!(
+            alloc.find_memory_type(!0, Usage::UPLOAD),
+            Some(MemoryTypeId(1))
+        );
+        assert_eq!(
+            alloc.find_memory_type(!0, Usage::DOWNLOAD),
+            Some(MemoryTypeId(2))
+        );
+        assert_eq!(
+            alloc.find_memory_type(0b100, Usage::UPLOAD),
+            Some(MemoryTypeId(2))
+        );
+        assert_eq!(alloc.find_memory_type(0b110, Usage::DEVICE), None);
+    }
+
+    #[test]
+    fn test_sub_allocation() {
+        let device = mock::Device;
+        let mut alloc = allocator(1, 16);
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(100, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            let b = alloc
+                .allocate(
+                    &device,
+                    &requirements(100, 64),
+                    Usage::DEVICE,
+                    Kind::Optimal,
+                )
+                .unwrap();
+            assert_eq!(mock::take_calls(), ["allocate_memory"]);
+            assert_eq!(a.offset(), 0);
+            assert_eq!(b.offset(), 128);
+            assert_eq!(alloc.heap_stats()[0].allocated, 1024);
+            assert_eq!(alloc.heap_stats()[0].allocations, 2);
+            assert_eq!(alloc.heap_stats()[0].used, 200);
+
+            alloc.free(&device, a);
+            assert!(mock::take_calls().is_empty());
+            alloc.free(&device, b);
+            assert_eq!(mock::take_calls(), ["free_memory"]);
+            assert_eq!(
+                alloc.heap_stats()[0],
+                HeapStats {
+                    size: 1 << 30,
+                    ..HeapStats::default()
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn test_dedicated_allocation() {
+        let device = mock::Device;
+        let mut alloc = allocator(1, 16);
+        unsafe {
+            let a = alloc
+                .allocate(
+                    &device,
+                    &requirements(600, 256),
+                    Usage::UPLOAD,
+                    Kind::Linear,
+                )
+                .unwrap();
+            assert!(a.is_dedicated());
+            assert_eq!(a.memory_type(), MemoryTypeId(1));
+            assert_eq!(mock::take_calls(), ["allocate_memory"]);
+            assert_eq!(alloc.heap_stats()[1].allocated, 600);
+            alloc.free(&device, a);
+            assert_eq!(mock::take_calls(), ["free_memory"]);
+        }
+    }
+
+    #[test]
+    fn test_granularity_separates_kinds() {
+        let device = mock::Device;
+        let mut alloc = allocator(1024, 16);
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(100, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            let b = alloc
+                .allocate(&device, &requirements(100, 1), Usage::DEVICE, Kind::Optimal)
+                .unwrap();
+            assert_eq!(mock::take_calls(), ["allocate_memory", "allocate_memory"]);
+            assert_eq!(alloc.heap_stats()[0].device_allocations, 2);
+            alloc.free(&device, a);
+            alloc.free(&device, b);
+            assert_eq!(mock::take_calls(), ["free_memory", "free_memory"]);
+        }
+    }
+
+    #[test]
+    fn test_allocation_count_limit() {
+        let device = mock::Device;
+        let mut alloc = allocator(1, 1);
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(1000, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            assert_eq!(
+                alloc
+                    .allocate(&device, &requirements(1000, 1), Usage::DEVICE, Kind::Linear)
+                    .unwrap_err(),
+                AllocationError::Device(device::AllocationError::TooManyObjects)
+            );
+            assert_eq!(mock::take_calls(), ["allocate_memory"]);
+            alloc.free(&device, a);
+        }
+    }
+
+    #[test]
+    fn test_no_allocation_count_limit() {
+        let device = mock::Device;
+        let config = Config {
+            block_size: 1024,
+            dedicated_threshold: 512,
+        };
+        // Backends without an allocation count limit report the default of 0.
+        let mut alloc = Allocator::<mock::Backend>::new(properties(), &Limits::default(), config);
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(100, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            let b = alloc
+                .allocate(&device, &requirements(600, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            assert_eq!(alloc.device_allocation_count(), 2);
+            alloc.free(&device, a);
+            alloc.free(&device, b);
+            assert_eq!(alloc.device_allocation_count(), 0);
+        }
+    }
+
+    #[test]
+    fn test_out_of_memory_fallback() {
+        let device = mock::Device;
+        // Blocks fill their heap, so the one of memory type 1 can't be allocated.
+        let config = Config {
+            block_size: !0,
+            dedicated_threshold: 512,
+        };
+        let mut alloc = Allocator::<mock::Backend>::new(properties(), &Limits::default(), config);
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(100, 1), Usage::UPLOAD, Kind::Linear)
+                .unwrap();
+            assert_eq!(a.memory_type(), MemoryTypeId(2));
+            assert_eq!(mock::take_calls(), ["allocate_memory", "allocate_memory"]);
+            assert_eq!(alloc.heap_stats()[1].allocated, 0);
+            assert_eq!(alloc.heap_stats()[2].allocated, 256 << 20);
+            assert_eq!(
+                alloc
+                    .allocate(
+                        &device,
+                        &memory::Requirements {
+                            type_mask: 0b010,
+                            ..requirements(100, 1)
+                        },
+                        Usage::UPLOAD,
+                        Kind::Linear
+                    )
+                    .unwrap_err(),
+                AllocationError::Device(device::OutOfMemory::Device.into())
+            );
+            alloc.free(&device, a);
+            assert_eq!(mock::take_calls(), ["allocate_memory", "free_memory"]);
+            let b = alloc
+                .allocate(&device, &requirements(100, 1), Usage::UPLOAD, Kind::Linear)
+                .unwrap();
+            assert_eq!(b.memory_type(), MemoryTypeId(2));
+            mock::take_calls();
+            alloc.dispose(&device);
+            assert_eq!(mock::take_calls(), ["free_memory"]);
+        }
+    }
+
+    #[test]
+    fn test_tlsf_strategy() {
+        let device = mock::Device;
+        let config = Config {
+            block_size: 1024,
+            dedicated_threshold: 512,
+        };
+        let mut alloc = Allocator::<mock::Backend, TlsfAllocator<u64>>::new(
+            properties(),
+            &Limits::default(),
+            config,
+        );
+        unsafe {
+            let a = alloc
+                .allocate(&device, &requirements(100, 1), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            let b = alloc
+                .allocate(&device, &requirements(100, 64), Usage::DEVICE, Kind::Linear)
+                .unwrap();
+            assert_eq!(mock::take_calls(), ["allocate_memory"]);
+            assert_eq!(a.offset(), 0);
+            assert_eq!(b.offset() % 64, 0);
+            alloc.free(&device, a);
+            alloc.free(&device, b);
+            assert_eq!(mock::take_calls(), ["free_memory"]);
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Compares the best-fit `RangeAllocator` with the `TlsfAllocator` on many
+//! small sub-allocations, as done for descriptor heaps and uniform rings.
+
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use range_alloc::{RangeAllocate, RangeAllocator, TlsfAllocator};
+use std::{collections::VecDeque, ops::Range};
+
+const COUNTS: [usize; 3] = [100, 1_000, 10_000];
+
+/// Lengths of the allocations, mixing sizes from 1 to 64.
+fn lengths(count: usize) -> Vec<u64> {
+    (0 .. count as u64).map(|i| 1 + (i * 7919) % 64).collect()
+}
+
+/// Allocate `lengths` and free every other range, leaving as many holes behind.
+fn fragmented<A: RangeAllocate<u64>>(lengths: &[u64]) -> (A, Vec<Range<u64>>) {
+    let total = lengths.iter().sum::<u64>();
+    let mut allocator = A::new(0 .. total + 1024);
+    let mut live = Vec::new();
+    for (i, &length) in lengths.iter().enumerate() {
+        let range = allocator.allocate_range(length).unwrap();
+        if i % 2 == 0 {
+            allocator.free_range(range);
+        } else {
+            live.push(range);
+        }
+    }
+    assert_eq!(allocator.free_range_count(), lengths.len() / 2 + 1);
+    (allocator, live)
+}
+
+/// Allocate and free a range larger than any hole, while many others are live.
+fn allocate_free(c: &mut Criterion) {
+    let mut group = c.benchmark_group("allocate-free");
+    for &count in &COUNTS {
+        let lengths = lengths(count);
+        let (mut best_fit, _) = fragmented::<RangeAllocator<u64>>(&lengths);
+        group.bench_function(BenchmarkId::new("best-fit", count), |b| {
+            b.iter(|| {
+                let range = best_fit.allocate_range(black_box(100)).unwrap();
+                best_fit.free_range(range);
+            })
+        });
+        let (mut tlsf, _) = fragmented::<TlsfAllocator<u64>>(&lengths);
+        group.bench_function(BenchmarkId::new("tlsf", count), |b| {
+            b.iter(|| {
+                let range = tlsf.allocate_range(black_box(100)).unwrap();
+                tlsf.free_range(range);
+            })
+        });
+    }
+    group.finish();
+}
+
+/// Replace every live range in turn, oldest first, like a ring
+/// of per-frame allocations would.
+fn replace_all<A: RangeAllocate<u64>>(
+    lengths: &[u64],
+    (mut allocator, live): (A, Vec<Range<u64>>),
+) -> A {
+    let mut live = live.into_iter().collect::<VecDeque<_>>();
+    for &length in lengths.iter().take(live.len()) {
+        allocator.free_range(live.pop_front().unwrap());
+        live.push_back(allocator.allocate_range(length).unwrap());
+    }
+    allocator
+}
+
+fn churn(c: &mut Criterion) {
+    let mut group = c.benchmark_group("churn");
+    for &count in &COUNTS {
+        let lengths = lengths(count);
+        group.bench_function(BenchmarkId::new("best-fit", count), |b| {
+            b.iter_batched(
+                || fragmented::<RangeAllocator<u64>>(&lengths),
+                |state| replace_all(&lengths, state),
+                BatchSize::LargeInput,
+            )
+        });
+        group.bench_function(BenchmarkId::new("tlsf", count), |b| {
+            b.iter_batched(
+                || fragmented::<TlsfAllocator<u64>>(&lengths),
+                |state| replace_all(&lengths, state),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, allocate_free, churn);
+criterion_main!(benches);
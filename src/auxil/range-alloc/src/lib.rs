@@ -6,12 +6,19 @@
     unused_qualifications
 )]
 
+mod tlsf;
+
+pub use crate::tlsf::TlsfAllocator;
+
 use std::{
+    convert::TryInto,
     fmt::Debug,
     iter::Sum,
-    ops::{Add, AddAssign, Range, Sub},
+    ops::{Add, AddAssign, Range, Rem, Sub},
 };
 
+/// Best-fit range allocator. Allocation and free search through the sorted
+/// free ranges, see `TlsfAllocator` for a constant time alternative.
 #[derive(Debug)]
 pub struct RangeAllocator<T> {
     /// The range this allocator covers.
@@ -27,6 +34,108 @@ pub struct RangeAllocationError<T> {
     pub fragmented_free_length: T,
 }
 
+/// Relocation of a live range, produced by `RangeAllocator::plan_defragmentation`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RangeMove<T> {
+    pub from: Range<T>,
+    pub to: Range<T>,
+}
+
+/// Operations shared by `RangeAllocator` and `TlsfAllocator`,
+/// for users that take the allocation strategy as a type parameter.
+pub trait RangeAllocate<T>: Sized {
+    /// Create an allocator managing `range`, which is entirely free.
+    fn new(range: Range<T>) -> Self;
+
+    fn allocate_range(&mut self, length: T) -> Result<Range<T>, RangeAllocationError<T>>;
+
+    /// Allocate a range starting at a multiple of `alignment`.
+    fn allocate_range_aligned(
+        &mut self,
+        length: T,
+        alignment: T,
+    ) -> Result<Range<T>, RangeAllocationError<T>>;
+
+    fn free_range(&mut self, range: Range<T>);
+
+    fn is_empty(&self) -> bool;
+
+    fn free_range_count(&self) -> usize;
+}
+
+/// Zero of `T`, derived from any value so that `T` needs no `Default`.
+#[allow(clippy::eq_op)]
+fn zero<T: Copy + Sub<Output = T>>(value: T) -> T {
+    value - value
+}
+
+fn align_up<T>(value: T, alignment: T) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Rem<Output = T> + Eq,
+{
+    let misalignment = value % alignment;
+    if misalignment == zero(value) {
+        value
+    } else {
+        value + (alignment - misalignment)
+    }
+}
+
+fn to_u64<T: Copy + Debug + TryInto<u64>>(length: T) -> u64 {
+    length
+        .try_into()
+        .unwrap_or_else(|_| panic!("length {:?} does not fit in u64", length))
+}
+
+/// Share of the free space lying outside of the largest free range.
+fn fragmentation_ratio(free: u64, largest: u64) -> f32 {
+    if free == 0 {
+        0.0
+    } else {
+        (1.0 - largest as f64 / free as f64) as f32
+    }
+}
+
+/// Moves compacting the `live` ranges towards `start`, see `RangeAllocator::plan_defragmentation`.
+fn plan_moves<T>(start: T, live: &[Range<T>], alignment: T) -> Vec<RangeMove<T>>
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Rem<Output = T> + Eq + PartialOrd + Debug,
+{
+    assert_ne!(alignment + alignment, alignment);
+    let mut live = live.to_vec();
+    live.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+
+    let mut moves = Vec::new();
+    let mut cursor = start;
+    for range in live {
+        let start = align_up(cursor, alignment);
+        if start < range.start {
+            // A range moving by less than its length would overwrite itself,
+            // so copy it in chunks no longer than the distance moved. Each chunk
+            // only lands on the source of chunks that were moved before it.
+            let distance = range.start - start;
+            let mut from = range.start;
+            while from < range.end {
+                let end = if range.end - from > distance {
+                    from + distance
+                } else {
+                    range.end
+                };
+                let to = from - distance;
+                moves.push(RangeMove {
+                    from: from .. end,
+                    to: to .. to + (end - from),
+                });
+                from = end;
+            }
+            cursor = start + (range.end - range.start);
+        } else {
+            cursor = range.end;
+        }
+    }
+    moves
+}
+
 impl<T> RangeAllocator<T>
 where
     T: Clone + Copy + Add<Output = T> + AddAssign + Sub<Output = T> + Eq + PartialOrd + Debug,
@@ -41,7 +150,7 @@ where
     pub fn allocate_range(&mut self, length: T) -> Result<Range<T>, RangeAllocationError<T>> {
         assert_ne!(length + length, length);
         let mut best_fit: Option<(usize, Range<T>)> = None;
-        let mut fragmented_free_length = length - length;
+        let mut fragmented_free_length = zero(length);
         for (index, range) in self.free_ranges.iter().cloned().enumerate() {
             let range_length = range.end - range.start;
             fragmented_free_length += range_length;
@@ -79,6 +188,65 @@ where
         }
     }
 
+    /// Reserve a specific range, which must be entirely free.
+    pub fn allocate_at(&mut self, range: Range<T>) -> Result<(), RangeAllocationError<T>> {
+        assert!(self.initial_range.start <= range.start && range.end <= self.initial_range.end);
+        assert!(range.start < range.end);
+
+        let index = self
+            .free_ranges
+            .iter()
+            .position(|r| r.start <= range.start && range.end <= r.end);
+        match index {
+            Some(index) => {
+                self.split_free_range(index, range);
+                Ok(())
+            }
+            None => Err(RangeAllocationError {
+                fragmented_free_length: self.free_length(),
+            }),
+        }
+    }
+
+    /// Extend the end of the managed range. The new space is free.
+    pub fn grow_to(&mut self, new_end: T) {
+        assert!(self.initial_range.end <= new_end);
+        if new_end == self.initial_range.end {
+            return;
+        }
+
+        match self.free_ranges.last_mut() {
+            Some(last) if last.end == self.initial_range.end => last.end = new_end,
+            _ => self.free_ranges.push(self.initial_range.end .. new_end),
+        }
+        self.initial_range.end = new_end;
+    }
+
+    fn free_length(&self) -> T {
+        let mut length = zero(self.initial_range.start);
+        for range in &self.free_ranges {
+            length += range.end - range.start;
+        }
+        length
+    }
+
+    /// Remove `range` from the free range at `index`, which must contain it.
+    /// Whatever remains on either side stays free.
+    fn split_free_range(&mut self, index: usize, range: Range<T>) {
+        let free = self.free_ranges[index].clone();
+        match (free.start < range.start, range.end < free.end) {
+            (true, true) => {
+                self.free_ranges[index].end = range.start;
+                self.free_ranges.insert(index + 1, range.end .. free.end);
+            }
+            (true, false) => self.free_ranges[index].end = range.start,
+            (false, true) => self.free_ranges[index].start = range.end,
+            (false, false) => {
+                self.free_ranges.remove(index);
+            }
+        }
+    }
+
     pub fn free_range(&mut self, range: Range<T>) {
         assert!(self.initial_range.start <= range.start && range.end <= self.initial_range.end);
         assert!(range.start < range.end);
@@ -159,6 +327,146 @@ where
     pub fn is_empty(&self) -> bool {
         self.free_ranges.len() == 1 && self.free_ranges[0] == self.initial_range
     }
+
+    /// Length of the largest free range, which is the largest length
+    /// `allocate_range` can currently succeed with.
+    pub fn largest_free_length(&self) -> T {
+        let mut largest = zero(self.initial_range.start);
+        for range in &self.free_ranges {
+            if range.end - range.start > largest {
+                largest = range.end - range.start;
+            }
+        }
+        largest
+    }
+
+    pub fn free_range_count(&self) -> usize {
+        self.free_ranges.len()
+    }
+
+    pub fn total_allocated(&self) -> T {
+        (self.initial_range.end - self.initial_range.start) - self.free_length()
+    }
+
+    /// Share of the free space lying outside of the largest free range, from 0 to 1.
+    /// At 0 a single allocation can take all of the free space.
+    pub fn fragmentation(&self) -> f32
+    where
+        T: TryInto<u64>,
+    {
+        fragmentation_ratio(
+            to_u64(self.free_length()),
+            to_u64(self.largest_free_length()),
+        )
+    }
+}
+
+impl<T> RangeAllocator<T>
+where
+    T: Clone
+        + Copy
+        + Add<Output = T>
+        + AddAssign
+        + Sub<Output = T>
+        + Rem<Output = T>
+        + Eq
+        + PartialOrd
+        + Debug,
+{
+    /// Allocate a range starting at a multiple of `alignment`.
+    ///
+    /// Unlike over-allocating with `allocate_range`, the space skipped
+    /// to reach the alignment stays free for later allocations.
+    pub fn allocate_range_aligned(
+        &mut self,
+        length: T,
+        alignment: T,
+    ) -> Result<Range<T>, RangeAllocationError<T>> {
+        assert_ne!(length + length, length);
+        assert_ne!(alignment + alignment, alignment);
+        let mut best_fit: Option<(usize, T, T)> = None;
+        let mut fragmented_free_length = zero(length);
+        for (index, range) in self.free_ranges.iter().enumerate() {
+            let range_length = range.end - range.start;
+            fragmented_free_length += range_length;
+            let start = align_up(range.start, alignment);
+            if start > range.end || range.end - start < length {
+                continue;
+            }
+            match best_fit {
+                // Prefer the smallest range to reduce memory fragmentation.
+                Some((_, _, best_length)) if best_length <= range_length => {}
+                _ => best_fit = Some((index, start, range_length)),
+            }
+            if range_length == length {
+                // Found a perfect fit, so stop looking.
+                break;
+            }
+        }
+        match best_fit {
+            Some((index, start, _)) => {
+                let range = start .. (start + length);
+                self.split_free_range(index, range.clone());
+                Ok(range)
+            }
+            None => Err(RangeAllocationError {
+                fragmented_free_length,
+            }),
+        }
+    }
+
+    /// Plan moves compacting the `live` ranges towards the start of the managed range,
+    /// keeping the start of every range aligned to `alignment`.
+    ///
+    /// The source and destination of a move never overlap, ranges moving by less than
+    /// their length are split into several moves. Carrying out the moves in order never
+    /// overwrites a range that has not been moved yet.
+    /// The allocator itself is left untouched: after a move, `free_range(from)`
+    /// followed by `allocate_at(to)` brings it up to date.
+    pub fn plan_defragmentation(&self, live: &[Range<T>], alignment: T) -> Vec<RangeMove<T>> {
+        plan_moves(self.initial_range.start, live, alignment)
+    }
+}
+
+impl<T> RangeAllocate<T> for RangeAllocator<T>
+where
+    T: Clone
+        + Copy
+        + Add<Output = T>
+        + AddAssign
+        + Sub<Output = T>
+        + Rem<Output = T>
+        + Eq
+        + PartialOrd
+        + Debug,
+{
+    fn new(range: Range<T>) -> Self {
+        RangeAllocator::new(range)
+    }
+
+    fn allocate_range(&mut self, length: T) -> Result<Range<T>, RangeAllocationError<T>> {
+        RangeAllocator::allocate_range(self, length)
+    }
+
+    fn allocate_range_aligned(
+        &mut self,
+        length: T,
+        alignment: T,
+    ) -> Result<Range<T>, RangeAllocationError<T>> {
+        RangeAllocator::allocate_range_aligned(self, length, alignment)
+    }
+
+    fn free_range(&mut self, range: Range<T>) {
+        RangeAllocator::free_range(self, range)
+    }
+
+    fn is_empty(&self) -> bool {
+        RangeAllocator::is_empty(self)
+    }
+
+    fn free_range_count(&self) -> usize {
+        RangeAllocator::free_range_count(self)
+    }
 }
 
 impl<T: Copy + Sub<Output = T> + Sum> RangeAllocator<T> {
@@ -268,6 +576,127 @@ mod tests {
         assert_eq!(alloc.allocate_range(1), Ok(9 .. 10));
     }
 
+    #[test]
+    fn test_aligned_allocation_keeps_padding() {
+        let mut alloc = RangeAllocator::new(0 .. 64);
+        assert_eq!(alloc.allocate_range_aligned(4, 1), Ok(0 .. 4));
+        assert_eq!(alloc.allocate_range_aligned(8, 16), Ok(16 .. 24));
+        assert_eq!(alloc.free_ranges, vec![4 .. 16, 24 .. 64]);
+        // The padding before the aligned range is reused.
+        assert_eq!(alloc.allocate_range_aligned(8, 8), Ok(8 .. 16));
+        assert_eq!(alloc.allocate_range_aligned(4, 4), Ok(4 .. 8));
+        assert_eq!(alloc.free_ranges, vec![24 .. 64]);
+        assert_eq!(alloc.allocate_range_aligned(16, 32), Ok(32 .. 48));
+        assert!(alloc.allocate_range_aligned(16, 32).is_err());
+        alloc.free_range(32 .. 48);
+        alloc.free_range(4 .. 16);
+        alloc.free_range(16 .. 24);
+        alloc.free_range(0 .. 4);
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn test_allocate_at() {
+        let mut alloc = RangeAllocator::new(0 .. 10);
+        assert_eq!(alloc.allocate_at(4 .. 6), Ok(()));
+        assert_eq!(alloc.free_ranges, vec![0 .. 4, 6 .. 10]);
+        assert!(alloc.allocate_at(3 .. 5).is_err());
+        assert_eq!(alloc.allocate_at(0 .. 4), Ok(()));
+        assert_eq!(alloc.allocate_range(4), Ok(6 .. 10));
+        assert_eq!(alloc.free_ranges, vec![]);
+    }
+
+    #[test]
+    fn test_grow_to() {
+        let mut alloc = RangeAllocator::new(0 .. 10);
+        assert_eq!(alloc.allocate_range(10), Ok(0 .. 10));
+        alloc.grow_to(20);
+        assert_eq!(alloc.free_ranges, vec![10 .. 20]);
+        alloc.grow_to(30);
+        assert_eq!(alloc.free_ranges, vec![10 .. 30]);
+        alloc.free_range(0 .. 10);
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn test_statistics() {
+        let mut alloc = RangeAllocator::new(0u64 .. 100);
+        assert_eq!(alloc.fragmentation(), 0.0);
+        for _ in 0 .. 10 {
+            alloc.allocate_range(10).unwrap();
+        }
+        assert_eq!(alloc.fragmentation(), 0.0);
+        alloc.free_range(10 .. 20);
+        alloc.free_range(40 .. 70);
+        assert_eq!(alloc.largest_free_length(), 30);
+        assert_eq!(alloc.free_range_count(), 2);
+        assert_eq!(alloc.total_allocated(), 60);
+        assert_eq!(alloc.fragmentation(), 0.25);
+    }
+
+    #[test]
+    fn test_plan_defragmentation() {
+        let mut alloc = RangeAllocator::new(0 .. 100);
+        let live = vec![8 .. 12, 30 .. 34, 40 .. 48, 48 .. 50];
+        for range in &live {
+            alloc.allocate_at(range.clone()).unwrap();
+        }
+        let moves = alloc.plan_defragmentation(&live, 4);
+        assert_eq!(
+            moves,
+            vec![
+                RangeMove {
+                    from: 8 .. 12,
+                    to: 0 .. 4,
+                },
+                RangeMove {
+                    from: 30 .. 34,
+                    to: 4 .. 8,
+                },
+                RangeMove {
+                    from: 40 .. 48,
+                    to: 8 .. 16,
+                },
+                RangeMove {
+                    from: 48 .. 50,
+                    to: 16 .. 18,
+                },
+            ]
+        );
+        for RangeMove { from, to } in moves {
+            alloc.free_range(from);
+            alloc.allocate_at(to).unwrap();
+        }
+        assert_eq!(alloc.free_ranges, vec![18 .. 100]);
+        assert!(alloc.plan_defragmentation(&[0 .. 4, 4 .. 10], 4).is_empty());
+    }
+
+    #[test]
+    fn test_plan_defragmentation_overlapping() {
+        let alloc = RangeAllocator::new(0 .. 32);
+        let live = vec![2 .. 10, 12 .. 20, 21 .. 23];
+        let moves = alloc.plan_defragmentation(&live, 1);
+        for RangeMove { from, to } in &moves {
+            assert_eq!(from.end - from.start, to.end - to.start);
+            assert!(from.end <= to.start || to.end <= from.start);
+        }
+
+        // Carry out the moves on memory tagged with the start of each live range.
+        let mut memory = vec![0; 32];
+        for range in &live {
+            for i in range.clone() {
+                memory[i] = range.start;
+            }
+        }
+        for RangeMove { from, to } in moves {
+            let chunk = memory[from].to_vec();
+            memory[to].copy_from_slice(&chunk);
+        }
+        assert_eq!(&memory[.. 8], &[2; 8]);
+        assert_eq!(&memory[8 .. 16], &[12; 8]);
+        assert_eq!(&memory[16 .. 18], &[21; 2]);
+    }
+
     #[test]
     fn test_merge_neighbors() {
         let mut alloc = RangeAllocator::new(0 .. 9);
@@ -0,0 +1,565 @@
+//! Two-level segregated fit allocation.
+//!
+//! Free ranges are kept in buckets indexed by the power of two of their length
+//! (first level) and a linear subdivision of it (second level). Bitmaps of the
+//! non-empty buckets make finding a large enough free range a couple of bit scans,
+//! and hash maps of the free range bounds make merging neighbours on free constant
+//! time. In exchange, allocations are only a good fit rather than the best fit.
+
+use crate::{
+    align_up,
+    fragmentation_ratio,
+    plan_moves,
+    to_u64,
+    RangeAllocate,
+    RangeAllocationError,
+    RangeMove,
+};
+use std::{
+    collections::HashMap,
+    convert::TryInto,
+    fmt::Debug,
+    hash::{BuildHasherDefault, Hash, Hasher},
+    ops::{Add, AddAssign, Range, Rem, Sub},
+};
+
+/// Each power of two is split into `1 << SL_BITS` buckets.
+const SL_BITS: u32 = 4;
+const SL_COUNT: usize = 1 << SL_BITS;
+const FL_COUNT: usize = 64 - SL_BITS as usize + 1;
+
+/// Bucket holding free ranges of the given length.
+fn bucket_of(length: u64) -> usize {
+    let log = 63 - length.leading_zeros();
+    if log < SL_BITS {
+        // Small lengths get one bucket each.
+        length as usize
+    } else {
+        let fl = (log - SL_BITS + 1) as usize;
+        let sl = (length >> (log - SL_BITS)) as usize - SL_COUNT;
+        fl * SL_COUNT + sl
+    }
+}
+
+/// First bucket whose free ranges are all at least `length` long.
+fn bucket_at_least(length: u64) -> Option<usize> {
+    let log = 63 - length.leading_zeros();
+    if log < SL_BITS {
+        Some(length as usize)
+    } else {
+        let round = (1 << (log - SL_BITS)) - 1;
+        length.checked_add(round).map(bucket_of)
+    }
+}
+
+/// Multiplicative hasher for the integer bounds of free ranges,
+/// which are looked up on every allocation and free.
+#[derive(Default)]
+struct BoundHasher(u64);
+
+impl Hasher for BoundHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.write_u64(byte as u64);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.0 = (self.0.rotate_left(5) ^ value).wrapping_mul(0x51_7c_c1_b7_27_22_0a_95);
+    }
+
+    fn write_u32(&mut self, value: u32) {
+        self.write_u64(value as u64);
+    }
+
+    fn write_usize(&mut self, value: usize) {
+        self.write_u64(value as u64);
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+type BoundMap<T> = HashMap<T, usize, BuildHasherDefault<BoundHasher>>;
+
+#[derive(Debug)]
+struct FreeBlock<T> {
+    range: Range<T>,
+    bucket: usize,
+    /// Position of this block in its bucket.
+    position: usize,
+}
+
+/// Range allocator with constant time allocation and free.
+///
+/// Mirrors the API of `RangeAllocator`, which does a best-fit search
+/// over a sorted list instead.
+#[derive(Debug)]
+pub struct TlsfAllocator<T> {
+    /// The range this allocator covers.
+    initial_range: Range<T>,
+    /// Bit per first level with any non-empty bucket.
+    first_level: u64,
+    /// Bit per non-empty bucket, for each first level.
+    second_level: Vec<u32>,
+    /// Indices into `blocks` of the free ranges, per bucket.
+    buckets: Vec<Vec<usize>>,
+    blocks: Vec<Option<FreeBlock<T>>>,
+    vacant_blocks: Vec<usize>,
+    by_start: BoundMap<T>,
+    by_end: BoundMap<T>,
+    free_length: T,
+}
+
+impl<T> TlsfAllocator<T>
+where
+    T: Clone
+        + Copy
+        + Default
+        + Add<Output = T>
+        + AddAssign
+        + Sub<Output = T>
+        + Rem<Output = T>
+        + Eq
+        + Hash
+        + PartialOrd
+        + Debug
+        + TryInto<u64>,
+{
+    pub fn new(range: Range<T>) -> Self {
+        let mut allocator = TlsfAllocator {
+            initial_range: range.clone(),
+            first_level: 0,
+            second_level: vec![0; FL_COUNT],
+            buckets: (0 .. FL_COUNT * SL_COUNT).map(|_| Vec::new()).collect(),
+            blocks: Vec::new(),
+            vacant_blocks: Vec::new(),
+            by_start: BoundMap::default(),
+            by_end: BoundMap::default(),
+            free_length: T::default(),
+        };
+        if range.start < range.end {
+            allocator.insert_free(range);
+        }
+        allocator
+    }
+
+    fn block(&self, id: usize) -> &FreeBlock<T> {
+        self.blocks[id].as_ref().unwrap()
+    }
+
+    fn insert_free(&mut self, range: Range<T>) {
+        let bucket = bucket_of(to_u64(range.end - range.start));
+        let block = FreeBlock {
+            range: range.clone(),
+            bucket,
+            position: self.buckets[bucket].len(),
+        };
+        let id = match self.vacant_blocks.pop() {
+            Some(id) => {
+                self.blocks[id] = Some(block);
+                id
+            }
+            None => {
+                self.blocks.push(Some(block));
+                self.blocks.len() - 1
+            }
+        };
+
+        self.buckets[bucket].push(id);
+        self.first_level |= 1 << (bucket / SL_COUNT);
+        self.second_level[bucket / SL_COUNT] |= 1 << (bucket % SL_COUNT);
+        self.by_start.insert(range.start, id);
+        self.by_end.insert(range.end, id);
+        self.free_length += range.end - range.start;
+    }
+
+    fn remove_free(&mut self, id: usize) -> Range<T> {
+        let block = self.blocks[id].take().unwrap();
+        self.vacant_blocks.push(id);
+
+        let bucket = &mut self.buckets[block.bucket];
+        bucket.swap_remove(block.position);
+        if let Some(&moved) = bucket.get(block.position) {
+            self.blocks[moved].as_mut().unwrap().position = block.position;
+        }
+        if bucket.is_empty() {
+            let fl = block.bucket / SL_COUNT;
+            self.second_level[fl] &= !(1 << (block.bucket % SL_COUNT));
+            if self.second_level[fl] == 0 {
+                self.first_level &= !(1 << fl);
+            }
+        }
+
+        self.by_start.remove(&block.range.start);
+        self.by_end.remove(&block.range.end);
+        self.free_length = self.free_length - (block.range.end - block.range.start);
+        block.range
+    }
+
+    /// First non-empty bucket at or above `bucket`.
+    fn find_bucket(&self, bucket: usize) -> Option<usize> {
+        let fl = bucket / SL_COUNT;
+        let sl_map = self.second_level[fl] & (!0 << (bucket % SL_COUNT));
+        if sl_map != 0 {
+            return Some(fl * SL_COUNT + sl_map.trailing_zeros() as usize);
+        }
+        let fl_map = self.first_level & (!0 << (fl + 1));
+        if fl_map == 0 {
+            return None;
+        }
+        let fl = fl_map.trailing_zeros() as usize;
+        Some(fl * SL_COUNT + self.second_level[fl].trailing_zeros() as usize)
+    }
+
+    fn allocate(
+        &mut self,
+        length: T,
+        alignment: Option<T>,
+    ) -> Result<Range<T>, RangeAllocationError<T>> {
+        assert_ne!(length + length, length);
+        let start_in = |range: &Range<T>| {
+            let start = match alignment {
+                Some(alignment) => align_up(range.start, alignment),
+                None => range.start,
+            };
+            if start <= range.end && range.end - start >= length {
+                Some(start)
+            } else {
+                None
+            }
+        };
+
+        let length_u64 = to_u64(length);
+        let padding = alignment.map_or(0, |alignment| to_u64(alignment) - 1);
+        // Any free range in the buckets found through the bitmaps fits.
+        let found = length_u64
+            .checked_add(padding)
+            .and_then(bucket_at_least)
+            .and_then(|bucket| self.find_bucket(bucket))
+            .map(|bucket| *self.buckets[bucket].last().unwrap());
+        // Free ranges in the few buckets below may fit as well, depending on
+        // their exact length and alignment. Only the last range of each bucket is
+        // tried, so that allocating stays constant time at the cost of a worse fit.
+        let found = found.or_else(|| {
+            let highest = bucket_of(length_u64.saturating_add(padding));
+            let mut bucket = bucket_of(length_u64);
+            // Only visit the non-empty ones, as found through the bitmaps.
+            while bucket <= highest {
+                let next = self.find_bucket(bucket).filter(|&next| next <= highest)?;
+                let id = *self.buckets[next].last().unwrap();
+                if start_in(&self.block(id).range).is_some() {
+                    return Some(id);
+                }
+                bucket = next + 1;
+            }
+            None
+        });
+
+        match found {
+            Some(id) => {
+                let free = self.remove_free(id);
+                let start = start_in(&free).unwrap();
+                let range = start .. start + length;
+                if free.start != range.start {
+                    self.insert_free(free.start .. range.start);
+                }
+                if range.end != free.end {
+                    self.insert_free(range.end .. free.end);
+                }
+                Ok(range)
+            }
+            None => Err(RangeAllocationError {
+                fragmented_free_length: self.free_length,
+            }),
+        }
+    }
+
+    pub fn allocate_range(&mut self, length: T) -> Result<Range<T>, RangeAllocationError<T>> {
+        self.allocate(length, None)
+    }
+
+    /// Allocate a range starting at a multiple of `alignment`.
+    /// The space skipped to reach the alignment stays free.
+    pub fn allocate_range_aligned(
+        &mut self,
+        length: T,
+        alignment: T,
+    ) -> Result<Range<T>, RangeAllocationError<T>> {
+        assert_ne!(alignment + alignment, alignment);
+        self.allocate(length, Some(alignment))
+    }
+
+    /// Reserve a specific range, which must be entirely free.
+    ///
+    /// Unlike the other operations, this searches through all the free ranges.
+    pub fn allocate_at(&mut self, range: Range<T>) -> Result<(), RangeAllocationError<T>> {
+        assert!(self.initial_range.start <= range.start && range.end <= self.initial_range.end);
+        assert!(range.start < range.end);
+
+        let id = self.blocks.iter().position(|block| match *block {
+            Some(ref block) => block.range.start <= range.start && range.end <= block.range.end,
+            None => false,
+        });
+        match id {
+            Some(id) => {
+                let free = self.remove_free(id);
+                if free.start != range.start {
+                    self.insert_free(free.start .. range.start);
+                }
+                if range.end != free.end {
+                    self.insert_free(range.end .. free.end);
+                }
+                Ok(())
+            }
+            None => Err(RangeAllocationError {
+                fragmented_free_length: self.free_length,
+            }),
+        }
+    }
+
+    /// Extend the end of the managed range. The new space is free.
+    pub fn grow_to(&mut self, new_end: T) {
+        assert!(self.initial_range.end <= new_end);
+        if new_end == self.initial_range.end {
+            return;
+        }
+
+        let start = match self.by_end.get(&self.initial_range.end) {
+            Some(&id) => self.remove_free(id).start,
+            None => self.initial_range.end,
+        };
+        self.insert_free(start .. new_end);
+        self.initial_range.end = new_end;
+    }
+
+    pub fn free_range(&mut self, range: Range<T>) {
+        assert!(self.initial_range.start <= range.start && range.end <= self.initial_range.end);
+        assert!(range.start < range.end);
+
+        // Merge with the free ranges right before and after.
+        let mut range = range;
+        if let Some(&left) = self.by_end.get(&range.start) {
+            range.start = self.remove_free(left).start;
+        }
+        if let Some(&right) = self.by_start.get(&range.end) {
+            range.end = self.remove_free(right).end;
+        }
+        assert!(!self.by_start.contains_key(&range.start) && !self.by_end.contains_key(&range.end));
+        self.insert_free(range);
+    }
+
+    fn sorted_free_ranges(&self) -> Vec<Range<T>> {
+        let mut ranges = self
+            .blocks
+            .iter()
+            .flatten()
+            .map(|block| block.range.clone())
+            .collect::<Vec<_>>();
+        ranges.sort_by(|a, b| a.start.partial_cmp(&b.start).unwrap());
+        ranges
+    }
+
+    /// Returns an iterator over allocated non-empty ranges
+    pub fn allocated_ranges(&self) -> impl Iterator<Item = Range<T>> {
+        let mut allocated = Vec::new();
+        let mut cursor = self.initial_range.start;
+        for free in self.sorted_free_ranges() {
+            if cursor < free.start {
+                allocated.push(cursor .. free.start);
+            }
+            cursor = free.end;
+        }
+        if cursor < self.initial_range.end {
+            allocated.push(cursor .. self.initial_range.end);
+        }
+        allocated.into_iter()
+    }
+
+    pub fn reset(&mut self) {
+        self.first_level = 0;
+        for level in &mut self.second_level {
+            *level = 0;
+        }
+        for bucket in &mut self.buckets {
+            bucket.clear();
+        }
+        self.blocks.clear();
+        self.vacant_blocks.clear();
+        self.by_start.clear();
+        self.by_end.clear();
+        self.free_length = T::default();
+        if self.initial_range.start < self.initial_range.end {
+            self.insert_free(self.initial_range.clone());
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.free_length == self.initial_range.end - self.initial_range.start
+    }
+
+    pub fn total_available(&self) -> T {
+        self.free_length
+    }
+
+    /// Length of the largest free range, which is the largest length
+    /// `allocate_range` can currently succeed with.
+    pub fn largest_free_length(&self) -> T {
+        let mut largest = T::default();
+        if self.first_level != 0 {
+            let fl = 63 - self.first_level.leading_zeros() as usize;
+            let sl = 31 - self.second_level[fl].leading_zeros() as usize;
+            for &id in &self.buckets[fl * SL_COUNT + sl] {
+                let range = &self.block(id).range;
+                if range.end - range.start > largest {
+                    largest = range.end - range.start;
+                }
+            }
+        }
+        largest
+    }
+
+    pub fn free_range_count(&self) -> usize {
+        self.by_start.len()
+    }
+
+    pub fn total_allocated(&self) -> T {
+        (self.initial_range.end - self.initial_range.start) - self.free_length
+    }
+
+    /// Share of the free space lying outside of the largest free range, from 0 to 1.
+    /// At 0 a single allocation can take all of the free space.
+    pub fn fragmentation(&self) -> f32 {
+        fragmentation_ratio(to_u64(self.free_length), to_u64(self.largest_free_length()))
+    }
+
+    /// Plan moves compacting the `live` ranges, see `RangeAllocator::plan_defragmentation`.
+    pub fn plan_defragmentation(&self, live: &[Range<T>], alignment: T) -> Vec<RangeMove<T>> {
+        plan_moves(self.initial_range.start, live, alignment)
+    }
+}
+
+impl<T> RangeAllocate<T> for TlsfAllocator<T>
+where
+    T: Clone
+        + Copy
+        + Default
+        + Add<Output = T>
+        + AddAssign
+        + Sub<Output = T>
+        + Rem<Output = T>
+        + Eq
+        + Hash
+        + PartialOrd
+        + Debug
+        + TryInto<u64>,
+{
+    fn new(range: Range<T>) -> Self {
+        TlsfAllocator::new(range)
+    }
+
+    fn allocate_range(&mut self, length: T) -> Result<Range<T>, RangeAllocationError<T>> {
+        TlsfAllocator::allocate_range(self, length)
+    }
+
+    fn allocate_range_aligned(
+        &mut self,
+        length: T,
+        alignment: T,
+    ) -> Result<Range<T>, RangeAllocationError<T>> {
+        TlsfAllocator::allocate_range_aligned(self, length, alignment)
+    }
+
+    fn free_range(&mut self, range: Range<T>) {
+        TlsfAllocator::free_range(self, range)
+    }
+
+    fn is_empty(&self) -> bool {
+        TlsfAllocator::is_empty(self)
+    }
+
+    fn free_range_count(&self) -> usize {
+        TlsfAllocator::free_range_count(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_buckets() {
+        assert_eq!(bucket_of(1), 1);
+        assert_eq!(bucket_of(15), 15);
+        assert_eq!(bucket_of(16), SL_COUNT);
+        assert_eq!(bucket_of(31), SL_COUNT + 15);
+        assert_eq!(bucket_of(32), 2 * SL_COUNT);
+        assert_eq!(bucket_of(33), 2 * SL_COUNT);
+        assert_eq!(bucket_at_least(33), Some(2 * SL_COUNT + 1));
+        assert_eq!(bucket_of(!0), FL_COUNT * SL_COUNT - 1);
+        assert_eq!(bucket_at_least(!0), None);
+    }
+
+    #[test]
+    fn test_allocate_and_merge() {
+        let mut alloc = TlsfAllocator::new(0u64 .. 100);
+        assert_eq!(alloc.allocate_range(10), Ok(0 .. 10));
+        assert_eq!(alloc.allocate_range(10), Ok(10 .. 20));
+        assert_eq!(alloc.allocate_range(10), Ok(20 .. 30));
+        alloc.free_range(0 .. 10);
+        alloc.free_range(20 .. 30);
+        assert_eq!(alloc.free_range_count(), 2);
+        assert_eq!(alloc.allocated_ranges().collect::<Vec<_>>(), vec![10 .. 20]);
+        alloc.free_range(10 .. 20);
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.free_range_count(), 1);
+        assert_eq!(alloc.allocate_range(100), Ok(0 .. 100));
+        assert!(alloc.allocate_range(1).is_err());
+    }
+
+    #[test]
+    fn test_fallback_to_lower_bucket() {
+        // A free range of 33 lives in the bucket of 32..34, which the
+        // rounded up search for 33 skips.
+        let mut alloc = TlsfAllocator::new(0u64 .. 33);
+        assert_eq!(alloc.allocate_range(33), Ok(0 .. 33));
+    }
+
+    #[test]
+    fn test_aligned_allocation() {
+        let mut alloc = TlsfAllocator::new(0u64 .. 64);
+        assert_eq!(alloc.allocate_range(4), Ok(0 .. 4));
+        assert_eq!(alloc.allocate_range_aligned(8, 16), Ok(16 .. 24));
+        // Good fit: the padding left before 16 is too small for the rounded up search.
+        assert_eq!(alloc.allocate_range_aligned(8, 8), Ok(24 .. 32));
+        assert_eq!(alloc.total_available(), 44);
+        assert_eq!(alloc.largest_free_length(), 32);
+        alloc.allocate_at(32 .. 48).unwrap();
+        assert!(alloc.allocate_at(30 .. 34).is_err());
+        assert_eq!(
+            alloc.allocated_ranges().collect::<Vec<_>>(),
+            vec![0 .. 4, 16 .. 48]
+        );
+        alloc.grow_to(80);
+        assert_eq!(alloc.largest_free_length(), 32);
+        alloc.reset();
+        assert!(alloc.is_empty());
+    }
+
+    #[test]
+    fn test_many_allocations() {
+        let mut alloc = TlsfAllocator::new(0u32 .. 1 << 16);
+        let ranges = (1 .. 300)
+            .map(|i| alloc.allocate_range(i % 37 + 1).unwrap())
+            .collect::<Vec<_>>();
+        for range in ranges.iter().step_by(2) {
+            alloc.free_range(range.clone());
+        }
+        for range in ranges.iter().skip(1).step_by(2) {
+            alloc.free_range(range.clone());
+        }
+        assert!(alloc.is_empty());
+        assert_eq!(alloc.free_range_count(), 1);
+    }
+}
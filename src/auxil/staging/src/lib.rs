@@ -0,0 +1,147 @@
+//! Transfers between host memory and gfx-hal resources.
+//!
+//! `UploadRing` streams data into buffers and images through a persistently
+//! mapped staging buffer, reusing its space once the device is done with it.
+//! `Readback` copies a buffer range or an image region back to host memory.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use hal::{
+    device::{DeviceLost, OomOrDeviceLost, OutOfMemory},
+    format::{Aspects, Format},
+    image,
+};
+use std::fmt;
+
+mod readback;
+mod upload;
+
+pub use crate::{
+    readback::{ImageReadback, Readback, ReadbackError},
+    upload::{ImageUpload, UploadRing},
+};
+
+/// Possible cause of a transfer failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TransferError {
+    OutOfMemory(OutOfMemory),
+    DeviceLost(DeviceLost),
+    /// A single row of image data is larger than the staging buffer.
+    TooLarge,
+}
+
+impl From<OutOfMemory> for TransferError {
+    fn from(error: OutOfMemory) -> Self {
+        TransferError::OutOfMemory(error)
+    }
+}
+
+impl From<DeviceLost> for TransferError {
+    fn from(error: DeviceLost) -> Self {
+        TransferError::DeviceLost(error)
+    }
+}
+
+impl From<OomOrDeviceLost> for TransferError {
+    fn from(error: OomOrDeviceLost) -> Self {
+        match error {
+            OomOrDeviceLost::OutOfMemory(error) => TransferError::OutOfMemory(error),
+            OomOrDeviceLost::DeviceLost(error) => TransferError::DeviceLost(error),
+        }
+    }
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::OutOfMemory(err) => write!(fmt, "Failed to transfer data: {}", err),
+            TransferError::DeviceLost(err) => write!(fmt, "Failed to transfer data: {}", err),
+            TransferError::TooLarge => write!(
+                fmt,
+                "Failed to transfer data: Image row is larger than the staging buffer"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            TransferError::OutOfMemory(err) => Some(err),
+            TransferError::DeviceLost(err) => Some(err),
+            TransferError::TooLarge => None,
+        }
+    }
+}
+
+/// Size in bytes of a texel block of a single aspect in buffer copies.
+///
+/// Depth is copied as 2 bytes for 16-bit formats and 4 bytes otherwise,
+/// and stencil as a single byte.
+pub fn block_size(format: Format, aspect: Aspects) -> u64 {
+    let bits = format.base_format().0.describe_bits();
+    match aspect {
+        Aspects::DEPTH if bits.depth == 16 => 2,
+        Aspects::DEPTH => 4,
+        Aspects::STENCIL => 1,
+        _ => format.surface_desc().bits as u64 / 8,
+    }
+}
+
+/// Layout of image data in a buffer, with rows of texel blocks `row_pitch` apart.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct RowLayout {
+    block_width: u32,
+    block_height: u32,
+    block_size: u64,
+    /// Number of block rows in each depth slice.
+    rows: u32,
+    /// Size of the data of a row.
+    row_size: u64,
+    row_pitch: u64,
+}
+
+impl RowLayout {
+    fn new(format: Format, aspect: Aspects, extent: image::Extent, pitch_alignment: u64) -> Self {
+        let (block_width, block_height) = format.surface_desc().dim;
+        let (block_width, block_height) = (block_width as u32, block_height as u32);
+        let block_size = block_size(format, aspect);
+        let row_size = ((extent.width + block_width - 1) / block_width) as u64 * block_size;
+        RowLayout {
+            block_width,
+            block_height,
+            block_size,
+            rows: (extent.height + block_height - 1) / block_height,
+            row_size,
+            row_pitch: align_up(row_size, lcm(pitch_alignment.max(1), block_size)),
+        }
+    }
+
+    /// Value of `BufferImageCopy::buffer_width`.
+    fn buffer_width(&self) -> u32 {
+        (self.row_pitch / self.block_size) as u32 * self.block_width
+    }
+}
+
+fn align_up(value: u64, alignment: u64) -> u64 {
+    (value + alignment - 1) / alignment * alignment
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        let r = a % b;
+        a = b;
+        b = r;
+    }
+    a
+}
+
+fn lcm(a: u64, b: u64) -> u64 {
+    a / gcd(a, b) * b
+}
@@ -0,0 +1,535 @@
+use crate::{align_up, lcm, RowLayout};
+use hal::{
+    adapter::MemoryType,
+    buffer,
+    command::{BufferCopy, BufferImageCopy, CommandBuffer as _},
+    device::{AllocationError, BindError, Device as _, DeviceLost, MapError, OomOrDeviceLost},
+    format::Format,
+    image,
+    memory::{Barrier, Dependencies, Properties, Segment},
+    pso::PipelineStage,
+    Backend,
+    Limits,
+    MemoryTypeId,
+};
+use std::{fmt, iter, ops::Range, ptr};
+
+/// Possible cause of a readback failure.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ReadbackError {
+    /// None of the memory types supported by the buffer is `CPU_VISIBLE`.
+    NoCompatibleMemoryType,
+    Creation(buffer::CreationError),
+    Allocation(AllocationError),
+    Bind(BindError),
+}
+
+impl From<buffer::CreationError> for ReadbackError {
+    fn from(error: buffer::CreationError) -> Self {
+        ReadbackError::Creation(error)
+    }
+}
+
+impl From<AllocationError> for ReadbackError {
+    fn from(error: AllocationError) -> Self {
+        ReadbackError::Allocation(error)
+    }
+}
+
+impl From<BindError> for ReadbackError {
+    fn from(error: BindError) -> Self {
+        ReadbackError::Bind(error)
+    }
+}
+
+impl fmt::Display for ReadbackError {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadbackError::NoCompatibleMemoryType => write!(
+                fmt,
+                "Failed to create readback buffer: No compatible host visible memory type"
+            ),
+            ReadbackError::Creation(err) => {
+                write!(fmt, "Failed to create readback buffer: {}", err)
+            }
+            ReadbackError::Allocation(err) => {
+                write!(fmt, "Failed to create readback buffer: {}", err)
+            }
+            ReadbackError::Bind(err) => write!(fmt, "Failed to create readback buffer: {}", err),
+        }
+    }
+}
+
+impl std::error::Error for ReadbackError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadbackError::NoCompatibleMemoryType => None,
+            ReadbackError::Creation(err) => Some(err),
+            ReadbackError::Allocation(err) => Some(err),
+            ReadbackError::Bind(err) => Some(err),
+        }
+    }
+}
+
+/// Image region to read back.
+#[derive(Clone, Debug)]
+pub struct ImageReadback {
+    pub format: Format,
+    /// A single aspect, with its level and array layers.
+    pub layers: image::SubresourceLayers,
+    pub offset: image::Offset,
+    pub extent: image::Extent,
+}
+
+/// Placement of the copied data in the readback buffer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Packing {
+    /// Size of the data of a row.
+    row_size: u64,
+    row_pitch: u64,
+    /// Number of rows in each array layer.
+    rows: u32,
+    layers: u32,
+    layer_pitch: u64,
+}
+
+impl Packing {
+    fn size(&self) -> u64 {
+        self.layers as u64 * self.layer_pitch
+    }
+}
+
+/// Copy of a buffer range or an image region in host visible memory.
+///
+/// The copy is recorded into the given command buffer along with a barrier making it
+/// visible to the host. After submitting it, the readback is told about the fence
+/// of the submission with `track`, and `read` returns the data once the fence
+/// is signalled, tightly packed in the same layout as `ImageUpload`.
+#[derive(Debug)]
+pub struct Readback<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    coherent: bool,
+    packing: Packing,
+    fence: Option<B::Fence>,
+}
+
+impl<B: Backend> Readback<B> {
+    /// Record a copy of `range` of `src`, which needs the `TRANSFER_SRC` usage.
+    pub unsafe fn buffer(
+        device: &B::Device,
+        cmd_buffer: &mut B::CommandBuffer,
+        memory_types: &[MemoryType],
+        src: &B::Buffer,
+        range: Range<u64>,
+    ) -> Result<Self, ReadbackError> {
+        let size = range.end - range.start;
+        let packing = Packing {
+            row_size: size,
+            row_pitch: size,
+            rows: 1,
+            layers: 1,
+            layer_pitch: size,
+        };
+        let readback = Self::create(device, memory_types, packing)?;
+        let copy = BufferCopy {
+            src: range.start,
+            dst: 0,
+            size,
+        };
+        cmd_buffer.copy_buffer(src, &readback.buffer, iter::once(copy));
+        readback.make_visible(cmd_buffer);
+        Ok(readback)
+    }
+
+    /// Record a copy of a region of `src`, which is in `src_layout`.
+    pub unsafe fn image(
+        device: &B::Device,
+        cmd_buffer: &mut B::CommandBuffer,
+        memory_types: &[MemoryType],
+        limits: &Limits,
+        src: &B::Image,
+        src_layout: image::Layout,
+        region: &ImageReadback,
+    ) -> Result<Self, ReadbackError> {
+        let rows = RowLayout::new(
+            region.format,
+            region.layers.aspects,
+            region.extent,
+            limits.optimal_buffer_copy_pitch_alignment,
+        );
+        let alignment = lcm(
+            limits.optimal_buffer_copy_offset_alignment.max(1),
+            lcm(rows.block_size, 4),
+        );
+        let layer_rows = rows.rows * region.extent.depth;
+        let packing = Packing {
+            row_size: rows.row_size,
+            row_pitch: rows.row_pitch,
+            rows: layer_rows,
+            layers: (region.layers.layers.end - region.layers.layers.start) as u32,
+            layer_pitch: align_up(layer_rows as u64 * rows.row_pitch, alignment),
+        };
+        let readback = Self::create(device, memory_types, packing)?;
+
+        let regions = region
+            .layers
+            .layers
+            .clone()
+            .enumerate()
+            .map(|(index, layer)| BufferImageCopy {
+                buffer_offset: index as u64 * packing.layer_pitch,
+                buffer_width: rows.buffer_width(),
+                buffer_height: rows.rows * rows.block_height,
+                image_layers: image::SubresourceLayers {
+                    aspects: region.layers.aspects,
+                    level: region.layers.level,
+                    layers: layer .. layer + 1,
+                },
+                image_offset: region.offset,
+                image_extent: region.extent,
+            })
+            .collect::<Vec<_>>();
+        cmd_buffer.copy_image_to_buffer(src, src_layout, &readback.buffer, &regions);
+        readback.make_visible(cmd_buffer);
+        Ok(readback)
+    }
+
+    /// Create the buffer, bound to memory of a host visible type, preferably cached.
+    unsafe fn create(
+        device: &B::Device,
+        memory_types: &[MemoryType],
+        packing: Packing,
+    ) -> Result<Self, ReadbackError> {
+        let mut buffer = device.create_buffer(packing.size(), buffer::Usage::TRANSFER_DST)?;
+        let requirements = device.get_buffer_requirements(&buffer);
+        let find = |properties: Properties| {
+            (0 .. memory_types.len()).find(|&id| {
+                requirements.type_mask & (1 << id) != 0
+                    && memory_types[id].properties.contains(properties)
+            })
+        };
+        let id = match find(Properties::CPU_VISIBLE | Properties::CPU_CACHED)
+            .or_else(|| find(Properties::CPU_VISIBLE))
+        {
+            Some(id) => id,
+            None => {
+                device.destroy_buffer(buffer);
+                return Err(ReadbackError::NoCompatibleMemoryType);
+            }
+        };
+
+        let memory = match device.allocate_memory(MemoryTypeId(id), requirements.size) {
+            Ok(memory) => memory,
+            Err(err) => {
+                device.destroy_buffer(buffer);
+                return Err(err.into());
+            }
+        };
+        if let Err(err) = device.bind_buffer_memory(&memory, 0, &mut buffer) {
+            device.destroy_buffer(buffer);
+            device.free_memory(memory);
+            return Err(err.into());
+        }
+
+        Ok(Readback {
+            buffer,
+            memory,
+            coherent: memory_types[id].properties.contains(Properties::COHERENT),
+            packing,
+            fence: None,
+        })
+    }
+
+    unsafe fn make_visible(&self, cmd_buffer: &mut B::CommandBuffer) {
+        let barrier = Barrier::whole_buffer(
+            &self.buffer,
+            buffer::Access::TRANSFER_WRITE .. buffer::Access::HOST_READ,
+        );
+        cmd_buffer.pipeline_barrier(
+            PipelineStage::TRANSFER .. PipelineStage::HOST,
+            Dependencies::empty(),
+            &[barrier],
+        );
+    }
+
+    /// Size in bytes of the data returned by `read`.
+    pub fn size(&self) -> u64 {
+        self.packing.row_size * self.packing.rows as u64 * self.packing.layers as u64
+    }
+
+    /// Fence of the submission containing the copy, if it's tracked.
+    pub fn fence(&self) -> Option<&B::Fence> {
+        self.fence.as_ref()
+    }
+
+    /// Keep track of `fence`, signalled by the submission containing the copy.
+    pub fn track(&mut self, fence: B::Fence) {
+        assert!(self.fence.is_none(), "Readback is already tracked");
+        self.fence = Some(fence);
+    }
+
+    /// Check whether the copy is complete. Always false if no fence is tracked.
+    pub unsafe fn is_ready(&self, device: &B::Device) -> Result<bool, DeviceLost> {
+        match self.fence {
+            Some(ref fence) => device.get_fence_status(fence),
+            None => Ok(false),
+        }
+    }
+
+    /// Block until the copy is complete.
+    pub unsafe fn wait(&self, device: &B::Device) -> Result<(), OomOrDeviceLost> {
+        let fence = self.fence.as_ref().expect("Readback is not tracked");
+        device.wait_for_fence(fence, !0).map(|_| ())
+    }
+
+    /// Return the copied data, with the row pitch removed. The copy has to be complete.
+    pub unsafe fn read(&self, device: &B::Device) -> Result<Vec<u8>, MapError> {
+        let mapping = device.map_memory(&self.memory, Segment::ALL)?;
+        if !self.coherent {
+            let range = (&self.memory, Segment::ALL);
+            if let Err(err) = device.invalidate_mapped_memory_ranges(iter::once(range)) {
+                device.unmap_memory(&self.memory);
+                return Err(err.into());
+            }
+        }
+
+        let packing = &self.packing;
+        let mut data = Vec::with_capacity(self.size() as usize);
+        for layer in 0 .. packing.layers as u64 {
+            for row in 0 .. packing.rows as u64 {
+                let offset = layer * packing.layer_pitch + row * packing.row_pitch;
+                let start = data.len();
+                data.resize(start + packing.row_size as usize, 0);
+                ptr::copy_nonoverlapping(
+                    mapping.offset(offset as isize),
+                    data[start ..].as_mut_ptr(),
+                    packing.row_size as usize,
+                );
+            }
+        }
+        device.unmap_memory(&self.memory);
+        Ok(data)
+    }
+
+    /// Destroy the buffer and free its memory, returning the fence if it's tracked.
+    /// The copy has to be complete or never submitted.
+    pub unsafe fn dispose(self, device: &B::Device) -> Option<B::Fence> {
+        device.destroy_buffer(self.buffer);
+        device.free_memory(self.memory);
+        self.fence
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::{format::Aspects, queue::CommandQueue as _};
+
+    fn memory_types(properties: Properties) -> Vec<MemoryType> {
+        vec![
+            MemoryType {
+                properties: Properties::DEVICE_LOCAL,
+                heap_index: 0,
+            },
+            MemoryType {
+                properties,
+                heap_index: 1,
+            },
+        ]
+    }
+
+    fn limits() -> Limits {
+        Limits {
+            optimal_buffer_copy_offset_alignment: 64,
+            optimal_buffer_copy_pitch_alignment: 16,
+            ..Limits::default()
+        }
+    }
+
+    fn image(format: Format) -> mock::Image {
+        let image = unsafe {
+            mock::Device.create_image(
+                image::Kind::D2(4, 4, 4, 1),
+                2,
+                format,
+                image::Tiling::Optimal,
+                image::Usage::TRANSFER_SRC,
+                image::ViewCapabilities::empty(),
+            )
+        };
+        mock::take_calls();
+        image.unwrap()
+    }
+
+    /// Write `data` at `offset` of the readback buffer, as the copy would.
+    fn write(readback: &Readback<mock::Backend>, offset: usize, data: &[u8]) {
+        unsafe {
+            let mapping = mock::Device
+                .map_memory(&readback.memory, Segment::ALL)
+                .unwrap();
+            ptr::copy_nonoverlapping(data.as_ptr(), mapping.add(offset), data.len());
+        }
+    }
+
+    #[test]
+    fn test_read_buffer() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let types = memory_types(Properties::CPU_VISIBLE | Properties::COHERENT);
+        let src = unsafe { device.create_buffer(32, buffer::Usage::TRANSFER_SRC) }.unwrap();
+        mock::take_calls();
+        let mut readback = unsafe {
+            Readback::<mock::Backend>::buffer(&device, &mut cmd_buffer, &types, &src, 16 .. 26)
+        }
+        .unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "create_buffer",
+                "get_buffer_requirements",
+                "allocate_memory",
+                "bind_buffer_memory",
+                "copy_buffer",
+                "pipeline_barrier",
+            ]
+        );
+
+        write(&readback, 0, &[7; 10]);
+        unsafe {
+            assert!(!readback.is_ready(&device).unwrap());
+            queue.hold_fences();
+            let fence = device.create_fence(false).unwrap();
+            queue.submit_without_semaphores(iter::once(&cmd_buffer), Some(&fence));
+            readback.track(fence);
+            assert!(!readback.is_ready(&device).unwrap());
+            queue.release_fences();
+            assert!(readback.is_ready(&device).unwrap());
+            mock::take_calls();
+            assert_eq!(readback.read(&device).unwrap(), vec![7; 10]);
+            assert_eq!(mock::take_calls(), ["map_memory", "unmap_memory"]);
+            assert!(readback.dispose(&device).is_some());
+        }
+    }
+
+    #[test]
+    fn test_read_image() {
+        let device = mock::Device;
+        let mut cmd_buffer = mock::CommandBuffer;
+        let types = memory_types(Properties::CPU_VISIBLE | Properties::CPU_CACHED);
+        let src = image(Format::Rgba8Unorm);
+        let region = ImageReadback {
+            format: Format::Rgba8Unorm,
+            layers: image::SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: 1,
+                layers: 2 .. 4,
+            },
+            offset: image::Offset { x: 1, y: 2, z: 0 },
+            extent: image::Extent {
+                width: 3,
+                height: 2,
+                depth: 1,
+            },
+        };
+        let readback = unsafe {
+            Readback::<mock::Backend>::image(
+                &device,
+                &mut cmd_buffer,
+                &types,
+                &limits(),
+                &src,
+                image::Layout::TransferSrcOptimal,
+                &region,
+            )
+        }
+        .unwrap();
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "create_buffer",
+                "get_buffer_requirements",
+                "allocate_memory",
+                "bind_buffer_memory",
+                "copy_image_to_buffer",
+                "pipeline_barrier",
+            ]
+        );
+
+        // Rows of 12 bytes are 16 bytes apart, and layers 64 bytes apart.
+        for (i, &offset) in [0, 16, 64, 80].iter().enumerate() {
+            write(&readback, offset, &[i as u8; 16]);
+        }
+        mock::take_calls();
+        let data = unsafe { readback.read(&device) }.unwrap();
+        let expected = (0 .. 4).flat_map(|i| vec![i; 12]).collect::<Vec<u8>>();
+        assert_eq!(data, expected);
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "map_memory",
+                "invalidate_mapped_memory_ranges",
+                "unmap_memory",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_depth() {
+        let device = mock::Device;
+        let mut cmd_buffer = mock::CommandBuffer;
+        let types = memory_types(Properties::CPU_VISIBLE | Properties::COHERENT);
+        let src = image(Format::D32SfloatS8Uint);
+        let region = ImageReadback {
+            format: Format::D32SfloatS8Uint,
+            layers: image::SubresourceLayers {
+                aspects: Aspects::DEPTH,
+                level: 0,
+                layers: 0 .. 1,
+            },
+            offset: image::Offset::ZERO,
+            extent: image::Extent {
+                width: 2,
+                height: 2,
+                depth: 1,
+            },
+        };
+        let readback = unsafe {
+            Readback::<mock::Backend>::image(
+                &device,
+                &mut cmd_buffer,
+                &types,
+                &limits(),
+                &src,
+                image::Layout::TransferSrcOptimal,
+                &region,
+            )
+        }
+        .unwrap();
+        assert_eq!(readback.size(), 16);
+
+        write(&readback, 0, &[1; 8]);
+        write(&readback, 16, &[2; 8]);
+        let data = unsafe { readback.read(&device) }.unwrap();
+        assert_eq!(data, [[1; 8], [2; 8]].concat());
+    }
+
+    #[test]
+    fn test_no_compatible_memory() {
+        let device = mock::Device;
+        let mut cmd_buffer = mock::CommandBuffer;
+        let types = memory_types(Properties::DEVICE_LOCAL);
+        let src = unsafe { device.create_buffer(16, buffer::Usage::TRANSFER_SRC) }.unwrap();
+        mock::take_calls();
+        let result = unsafe {
+            Readback::<mock::Backend>::buffer(&device, &mut cmd_buffer, &types, &src, 0 .. 16)
+        };
+        assert_eq!(result.unwrap_err(), ReadbackError::NoCompatibleMemoryType);
+        assert_eq!(
+            mock::take_calls(),
+            ["create_buffer", "get_buffer_requirements", "destroy_buffer"]
+        );
+    }
+}
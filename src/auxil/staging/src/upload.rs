@@ -0,0 +1,635 @@
+use crate::{align_up, lcm, RowLayout, TransferError};
+use hal::{
+    command::{BufferCopy, BufferImageCopy, CommandBuffer as _},
+    device::{Device as _, DeviceLost, MapError, OutOfMemory},
+    format::Format,
+    image,
+    memory::Segment,
+    Backend,
+    Limits,
+};
+use std::{collections::VecDeque, iter, ops::Range, ptr};
+
+/// Image data to upload, tightly packed: rows of texel blocks, then depth slices,
+/// then array layers.
+#[derive(Clone, Debug)]
+pub struct ImageUpload<'a> {
+    pub data: &'a [u8],
+    pub format: Format,
+    /// A single aspect, with its level and array layers.
+    pub layers: image::SubresourceLayers,
+    pub offset: image::Offset,
+    pub extent: image::Extent,
+}
+
+/// Ring of staging memory for uploads.
+///
+/// Data is written into a persistently mapped buffer and copy commands are recorded
+/// into the given command buffer. Positions in the ring grow forever: offsets into the buffer
+/// are the positions modulo its size, and each write is contiguous in the buffer.
+///
+/// After submitting the recorded copies, the ring is told about the fence of
+/// the submission with `track`, and `reclaim` releases the space of completed
+/// submissions. When an upload doesn't fit, the commands recorded so far are
+/// submitted through the `submit` callback and the ring waits for the oldest
+/// submission in flight, which lets uploads larger than the ring be split.
+#[derive(Debug)]
+pub struct UploadRing<B: Backend> {
+    buffer: B::Buffer,
+    memory: B::Memory,
+    mapping: *mut u8,
+    size: u64,
+    coherent: bool,
+    offset_alignment: u64,
+    pitch_alignment: u64,
+    atom_size: u64,
+    /// Position of the next write.
+    head: u64,
+    /// Position of the first write not covered by a fence yet.
+    untracked: u64,
+    /// Position of the first write not flushed yet.
+    unflushed: u64,
+    /// Position of the oldest write which may still be in use by the device.
+    tail: u64,
+    /// Fences of the submissions in flight, with the end of their writes.
+    in_flight: VecDeque<(u64, B::Fence)>,
+    /// Fences found signalled, waiting to be returned by `reclaim`.
+    signalled: Vec<B::Fence>,
+}
+
+impl<B: Backend> UploadRing<B> {
+    /// Create a ring using the first `size` bytes of `buffer`, which needs the `TRANSFER_SRC`
+    /// usage and to be bound at the start of `memory`. The memory has to be `CPU_VISIBLE`,
+    /// and `coherent` tells whether it's also `COHERENT`.
+    pub unsafe fn new(
+        device: &B::Device,
+        buffer: B::Buffer,
+        memory: B::Memory,
+        size: u64,
+        coherent: bool,
+        limits: &Limits,
+    ) -> Result<Self, MapError> {
+        let atom_size = (limits.non_coherent_atom_size as u64).max(1);
+        assert!(
+            coherent || size % atom_size == 0,
+            "Size of non-coherent ring must be a multiple of the non-coherent atom size"
+        );
+        let mapping = device.map_memory(
+            &memory,
+            Segment {
+                offset: 0,
+                size: Some(size),
+            },
+        )?;
+        Ok(UploadRing {
+            buffer,
+            memory,
+            mapping,
+            size,
+            coherent,
+            offset_alignment: limits.optimal_buffer_copy_offset_alignment.max(1),
+            pitch_alignment: limits.optimal_buffer_copy_pitch_alignment.max(1),
+            atom_size,
+            head: 0,
+            untracked: 0,
+            unflushed: 0,
+            tail: 0,
+            in_flight: VecDeque::new(),
+            signalled: Vec::new(),
+        })
+    }
+
+    pub fn buffer(&self) -> &B::Buffer {
+        &self.buffer
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes which may still be in use, including alignment padding.
+    pub fn used(&self) -> u64 {
+        self.head - self.tail
+    }
+
+    /// Write `data` into `dst` at `offset`, splitting it if it's larger than the ring.
+    pub unsafe fn upload_buffer<S>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut B::CommandBuffer,
+        submit: &mut S,
+        data: &[u8],
+        dst: &B::Buffer,
+        offset: u64,
+    ) -> Result<(), TransferError>
+    where
+        S: FnMut(&mut B::CommandBuffer) -> B::Fence,
+    {
+        for (index, chunk) in data.chunks(self.size as usize).enumerate() {
+            let position = self.allocate(
+                device,
+                cmd_buffer,
+                submit,
+                chunk.len() as u64,
+                self.offset_alignment,
+            )?;
+            let src = position % self.size;
+            ptr::copy_nonoverlapping(
+                chunk.as_ptr(),
+                self.mapping.offset(src as isize),
+                chunk.len(),
+            );
+            let copy = BufferCopy {
+                src,
+                dst: offset + index as u64 * self.size,
+                size: chunk.len() as u64,
+            };
+            cmd_buffer.copy_buffer(&self.buffer, dst, iter::once(copy));
+        }
+        Ok(())
+    }
+
+    /// Write image data into `dst`, which is in `layout`. The data is split by rows
+    /// of texel blocks if it's larger than the ring.
+    pub unsafe fn upload_image<S>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut B::CommandBuffer,
+        submit: &mut S,
+        upload: &ImageUpload,
+        dst: &B::Image,
+        layout: image::Layout,
+    ) -> Result<(), TransferError>
+    where
+        S: FnMut(&mut B::CommandBuffer) -> B::Fence,
+    {
+        let rows = RowLayout::new(
+            upload.format,
+            upload.layers.aspects,
+            upload.extent,
+            self.pitch_alignment,
+        );
+        let slices =
+            upload.extent.depth * (upload.layers.layers.end - upload.layers.layers.start) as u32;
+        let total_rows = rows.rows * slices;
+        assert_eq!(
+            upload.data.len() as u64,
+            total_rows as u64 * rows.row_size,
+            "Image data is not tightly packed"
+        );
+
+        let alignment = lcm(self.offset_alignment, lcm(rows.block_size, 4));
+        let max_rows = (self.size / rows.row_pitch) as u32;
+        if max_rows == 0 {
+            return Err(TransferError::TooLarge);
+        }
+
+        let mut row = 0;
+        while row < total_rows {
+            let count = max_rows.min(total_rows - row);
+            let position = self.allocate(
+                device,
+                cmd_buffer,
+                submit,
+                count as u64 * rows.row_pitch,
+                alignment,
+            )?;
+            let base = position % self.size;
+            for i in 0 .. count {
+                let src = (row + i) as usize * rows.row_size as usize;
+                ptr::copy_nonoverlapping(
+                    upload.data[src ..].as_ptr(),
+                    self.mapping
+                        .offset((base + i as u64 * rows.row_pitch) as isize),
+                    rows.row_size as usize,
+                );
+            }
+
+            // A region for each depth slice or array layer the rows belong to.
+            let mut regions = Vec::new();
+            let mut first = row;
+            while first < row + count {
+                let slice = first / rows.rows;
+                let slice_row = first % rows.rows;
+                let last = (row + count).min((slice + 1) * rows.rows);
+                let layer =
+                    upload.layers.layers.start + (slice / upload.extent.depth) as image::Layer;
+                let y = slice_row * rows.block_height;
+                regions.push(BufferImageCopy {
+                    buffer_offset: base + (first - row) as u64 * rows.row_pitch,
+                    buffer_width: rows.buffer_width(),
+                    buffer_height: (last - first) * rows.block_height,
+                    image_layers: image::SubresourceLayers {
+                        aspects: upload.layers.aspects,
+                        level: upload.layers.level,
+                        layers: layer .. layer + 1,
+                    },
+                    image_offset: image::Offset {
+                        x: upload.offset.x,
+                        y: upload.offset.y + y as i32,
+                        z: upload.offset.z + (slice % upload.extent.depth) as i32,
+                    },
+                    image_extent: image::Extent {
+                        width: upload.extent.width,
+                        height: ((last - first) * rows.block_height).min(upload.extent.height - y),
+                        depth: 1,
+                    },
+                });
+                first = last;
+            }
+            cmd_buffer.copy_buffer_to_image(&self.buffer, dst, layout, &regions);
+            row += count;
+        }
+        Ok(())
+    }
+
+    /// Reserve `size` contiguous bytes, returning their position.
+    unsafe fn allocate<S>(
+        &mut self,
+        device: &B::Device,
+        cmd_buffer: &mut B::CommandBuffer,
+        submit: &mut S,
+        size: u64,
+        alignment: u64,
+    ) -> Result<u64, TransferError>
+    where
+        S: FnMut(&mut B::CommandBuffer) -> B::Fence,
+    {
+        debug_assert!(size <= self.size);
+        loop {
+            let mut start = align_up(self.head, alignment);
+            if start % self.size + size > self.size {
+                // Skip the end of the buffer instead of wrapping around.
+                start = align_up(start, self.size);
+            }
+            if start + size <= self.tail + self.size {
+                self.head = start + size;
+                return Ok(start);
+            }
+
+            if self.untracked == self.head && self.in_flight.is_empty() {
+                // The ring is unused, start over from the beginning of the buffer.
+                let start = align_up(self.head, self.size);
+                self.head = start;
+                self.untracked = start;
+                self.unflushed = start;
+                self.tail = start;
+            } else if let Some((end, fence)) = self.in_flight.pop_front() {
+                if let Err(err) = device.wait_for_fence(&fence, !0) {
+                    self.in_flight.push_front((end, fence));
+                    return Err(err.into());
+                }
+                self.tail = end;
+                self.signalled.push(fence);
+            } else {
+                self.flush(device)?;
+                let fence = submit(cmd_buffer);
+                self.track(fence);
+            }
+        }
+    }
+
+    /// Segments of the buffer covering `range` of positions.
+    fn segments(&self, range: Range<u64>) -> Vec<Segment> {
+        let atom = |start: u64, end: u64| {
+            let offset = start / self.atom_size * self.atom_size;
+            Segment {
+                offset,
+                size: Some(align_up(end, self.atom_size).min(self.size) - offset),
+            }
+        };
+        if range.start == range.end {
+            return Vec::new();
+        }
+        let start = range.start % self.size;
+        let end = (range.end - 1) % self.size + 1;
+        if start < end {
+            vec![atom(start, end)]
+        } else {
+            vec![atom(start, self.size), atom(0, end)]
+        }
+    }
+
+    /// Make the writes visible to the device, needed before submitting the recorded copies.
+    pub unsafe fn flush(&mut self, device: &B::Device) -> Result<(), OutOfMemory> {
+        if !self.coherent && self.unflushed != self.head {
+            let segments = self.segments(self.unflushed .. self.head);
+            let ranges = segments.into_iter().map(|segment| (&self.memory, segment));
+            device.flush_mapped_memory_ranges(ranges)?;
+        }
+        self.unflushed = self.head;
+        Ok(())
+    }
+
+    /// Keep the space of the copies recorded so far until `fence` is signalled.
+    pub fn track(&mut self, fence: B::Fence) {
+        self.in_flight.push_back((self.head, fence));
+        self.untracked = self.head;
+    }
+
+    /// Release the space of the completed submissions, returning their fences.
+    pub unsafe fn reclaim(&mut self, device: &B::Device) -> Result<Vec<B::Fence>, DeviceLost> {
+        while let Some(&(end, ref fence)) = self.in_flight.front() {
+            if !device.get_fence_status(fence)? {
+                break;
+            }
+            self.tail = end;
+            let (_, fence) = self.in_flight.pop_front().unwrap();
+            self.signalled.push(fence);
+        }
+        Ok(self.signalled.drain(..).collect())
+    }
+
+    /// Unmap the buffer, returning it along with its memory and the fences still tracked.
+    /// The device has to be done with the submissions.
+    pub unsafe fn dispose(self, device: &B::Device) -> (B::Buffer, B::Memory, Vec<B::Fence>) {
+        device.unmap_memory(&self.memory);
+        let fences = self
+            .signalled
+            .into_iter()
+            .chain(self.in_flight.into_iter().map(|(_, fence)| fence))
+            .collect();
+        (self.buffer, self.memory, fences)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::{buffer, format::Aspects, queue::CommandQueue as _};
+    use std::slice;
+
+    fn ring(size: u64, coherent: bool) -> UploadRing<mock::Backend> {
+        let limits = Limits {
+            optimal_buffer_copy_offset_alignment: 64,
+            optimal_buffer_copy_pitch_alignment: 16,
+            non_coherent_atom_size: 32,
+            ..Limits::default()
+        };
+        let device = mock::Device;
+        unsafe {
+            let mut buffer = device
+                .create_buffer(size, buffer::Usage::TRANSFER_SRC)
+                .unwrap();
+            let memory = device.allocate_memory(mock::HOST_VISIBLE, size).unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+            let ring = UploadRing::new(&device, buffer, memory, size, coherent, &limits).unwrap();
+            mock::take_calls();
+            ring
+        }
+    }
+
+    fn buffer(size: u64) -> mock::Buffer {
+        let buffer = unsafe { mock::Device.create_buffer(size, buffer::Usage::TRANSFER_DST) };
+        mock::take_calls();
+        buffer.unwrap()
+    }
+
+    /// Data written to the ring so far.
+    fn contents(ring: &UploadRing<mock::Backend>) -> &[u8] {
+        unsafe { slice::from_raw_parts(ring.mapping, ring.size as usize) }
+    }
+
+    /// Submit the recorded copies to `queue`.
+    fn submit(
+        queue: &mut mock::CommandQueue,
+    ) -> impl FnMut(&mut mock::CommandBuffer) -> mock::Fence + '_ {
+        move |cmd_buffer| {
+            let fence = mock::Device.create_fence(false).unwrap();
+            unsafe { queue.submit_without_semaphores(iter::once(&*cmd_buffer), Some(&fence)) };
+            fence
+        }
+    }
+
+    #[test]
+    fn test_upload_buffer() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let mut ring = ring(256, true);
+        let dst = buffer(1024);
+        let data = (0 .. 100).collect::<Vec<u8>>();
+        unsafe {
+            let mut submit = submit(&mut queue);
+            ring.upload_buffer(&device, &mut cmd_buffer, &mut submit, &data, &dst, 0)
+                .unwrap();
+            ring.upload_buffer(
+                &device,
+                &mut cmd_buffer,
+                &mut submit,
+                &data[.. 10],
+                &dst,
+                1000,
+            )
+            .unwrap();
+        }
+        assert_eq!(mock::take_calls(), ["copy_buffer", "copy_buffer"]);
+        assert_eq!(&contents(&ring)[.. 100], &data[..]);
+        assert_eq!(&contents(&ring)[128 .. 138], &data[.. 10]);
+        assert_eq!(ring.used(), 138);
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let mut ring = ring(256, true);
+        let dst = buffer(256);
+        unsafe {
+            ring.upload_buffer(
+                &device,
+                &mut cmd_buffer,
+                &mut submit(&mut queue),
+                &[0; 100],
+                &dst,
+                0,
+            )
+            .unwrap();
+            queue.hold_fences();
+            let fence = submit(&mut queue)(&mut cmd_buffer);
+            ring.track(fence);
+            assert!(ring.reclaim(&device).unwrap().is_empty());
+            queue.release_fences();
+            assert_eq!(ring.reclaim(&device).unwrap().len(), 1);
+            assert_eq!(ring.used(), 0);
+
+            // The next write wraps around instead of crossing the end of the buffer.
+            ring.upload_buffer(
+                &device,
+                &mut cmd_buffer,
+                &mut submit(&mut queue),
+                &[1; 200],
+                &dst,
+                0,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "copy_buffer",
+                "create_fence",
+                "submit",
+                "get_fence_status",
+                "get_fence_status",
+                "copy_buffer",
+            ]
+        );
+        assert_eq!(&contents(&ring)[.. 200], &[1; 200][..]);
+    }
+
+    #[test]
+    fn test_split_upload() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let mut ring = ring(256, true);
+        let dst = buffer(1024);
+        let data = (0 .. 600).map(|i| i as u8).collect::<Vec<_>>();
+        unsafe {
+            ring.upload_buffer(
+                &device,
+                &mut cmd_buffer,
+                &mut submit(&mut queue),
+                &data,
+                &dst,
+                0,
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "copy_buffer",
+                "create_fence",
+                "submit",
+                "wait_for_fence",
+                "copy_buffer",
+                "create_fence",
+                "submit",
+                "wait_for_fence",
+                "copy_buffer",
+            ]
+        );
+        assert_eq!(&contents(&ring)[.. 88], &data[512 ..]);
+        unsafe {
+            assert_eq!(ring.reclaim(&device).unwrap().len(), 2);
+        }
+    }
+
+    #[test]
+    fn test_flush_non_coherent() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let mut ring = ring(256, false);
+        let dst = buffer(256);
+        unsafe {
+            let mut submit = submit(&mut queue);
+            ring.upload_buffer(&device, &mut cmd_buffer, &mut submit, &[0; 10], &dst, 0)
+                .unwrap();
+            ring.upload_buffer(&device, &mut cmd_buffer, &mut submit, &[0; 40], &dst, 0)
+                .unwrap();
+            ring.flush(&device).unwrap();
+            ring.track(submit(&mut cmd_buffer));
+            ring.reclaim(&device).unwrap();
+            ring.upload_buffer(&device, &mut cmd_buffer, &mut submit, &[0; 100], &dst, 0)
+                .unwrap();
+            // Doesn't fit before the end of the buffer, so it's written at its start.
+            ring.upload_buffer(&device, &mut cmd_buffer, &mut submit, &[0; 100], &dst, 0)
+                .unwrap();
+            ring.flush(&device).unwrap();
+        }
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "copy_buffer",
+                "copy_buffer",
+                "flush_mapped_memory_ranges",
+                "create_fence",
+                "submit",
+                "get_fence_status",
+                "copy_buffer",
+                "copy_buffer",
+                "flush_mapped_memory_ranges",
+                "flush_mapped_memory_ranges",
+            ]
+        );
+
+        // The flushed ranges, widened to whole atoms.
+        let segment = |offset, size| Segment {
+            offset,
+            size: Some(size),
+        };
+        assert_eq!(ring.segments(0 .. 104), vec![segment(0, 128)]);
+        assert_eq!(
+            ring.segments(104 .. 356),
+            vec![segment(96, 160), segment(0, 128)]
+        );
+    }
+
+    #[test]
+    fn test_upload_image() {
+        let device = mock::Device;
+        let mut queue = mock::CommandQueue::default();
+        let mut cmd_buffer = mock::CommandBuffer;
+        let mut ring = ring(64, true);
+        let dst = unsafe {
+            device.create_image(
+                image::Kind::D2(4, 5, 4, 1),
+                2,
+                Format::Rgba8Unorm,
+                image::Tiling::Optimal,
+                image::Usage::TRANSFER_DST,
+                image::ViewCapabilities::empty(),
+            )
+        }
+        .unwrap();
+        mock::take_calls();
+        // 3 texels wide rows of 12 bytes, 16 bytes apart in the ring.
+        let data = (0 .. 3 * 4 * 3 * 2).collect::<Vec<u8>>();
+        let upload = ImageUpload {
+            data: &data,
+            format: Format::Rgba8Unorm,
+            layers: image::SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: 1,
+                layers: 2 .. 4,
+            },
+            offset: image::Offset { x: 1, y: 2, z: 0 },
+            extent: image::Extent {
+                width: 3,
+                height: 3,
+                depth: 1,
+            },
+        };
+        unsafe {
+            ring.upload_image(
+                &device,
+                &mut cmd_buffer,
+                &mut submit(&mut queue),
+                &upload,
+                &dst,
+                image::Layout::TransferDstOptimal,
+            )
+            .unwrap();
+        }
+
+        // The first 4 rows fill the ring, the last 2 are copied after a submission.
+        assert_eq!(
+            mock::take_calls(),
+            [
+                "copy_buffer_to_image",
+                "create_fence",
+                "submit",
+                "wait_for_fence",
+                "copy_buffer_to_image",
+            ]
+        );
+        // The last two rows, written over the first ones.
+        assert_eq!(&contents(&ring)[.. 12], &data[48 .. 60]);
+        assert_eq!(&contents(&ring)[16 .. 28], &data[60 ..]);
+    }
+}
@@ -0,0 +1,689 @@
+//! Tracking of buffer and image states, producing the barriers needed between
+//! consecutive uses of the resources.
+//!
+//! Resources are registered with a `Tracker`, which hands out ids for them.
+//! Every use of a resource is announced with `Tracker::use_buffer` or
+//! `Tracker::use_image`, collecting the required transitions into a `Transitions`
+//! batch. The batch is then recorded with a single `pipeline_barrier` before the
+//! commands using the resources.
+
+#![warn(
+    trivial_casts,
+    trivial_numeric_casts,
+    unused_extern_crates,
+    unused_import_braces,
+    unused_qualifications
+)]
+
+use hal::{
+    buffer,
+    command::CommandBuffer,
+    format::Aspects,
+    image,
+    memory::{Barrier, Dependencies},
+    pso::PipelineStage,
+    queue::QueueFamilyId,
+    Backend,
+};
+use std::ops::{BitOr, Range};
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct BufferId(usize);
+
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub struct ImageId(usize);
+
+/// How the next commands access a buffer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BufferUsage {
+    pub access: buffer::Access,
+    pub stages: PipelineStage,
+}
+
+/// How the next commands access an image.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ImageUsage {
+    pub access: image::Access,
+    pub layout: image::Layout,
+    pub stages: PipelineStage,
+}
+
+trait Access: Copy + Eq + BitOr<Output = Self> {
+    fn is_write(self) -> bool;
+    fn includes(self, other: Self) -> bool;
+}
+
+impl Access for buffer::Access {
+    fn is_write(self) -> bool {
+        self.intersects(
+            buffer::Access::SHADER_WRITE
+                | buffer::Access::TRANSFER_WRITE
+                | buffer::Access::HOST_WRITE
+                | buffer::Access::MEMORY_WRITE,
+        )
+    }
+
+    fn includes(self, other: Self) -> bool {
+        self.contains(other)
+    }
+}
+
+impl Access for image::Access {
+    fn is_write(self) -> bool {
+        self.intersects(
+            image::Access::SHADER_WRITE
+                | image::Access::COLOR_ATTACHMENT_WRITE
+                | image::Access::DEPTH_STENCIL_ATTACHMENT_WRITE
+                | image::Access::TRANSFER_WRITE
+                | image::Access::HOST_WRITE
+                | image::Access::MEMORY_WRITE,
+        )
+    }
+
+    fn includes(self, other: Self) -> bool {
+        self.contains(other)
+    }
+}
+
+/// Last known use of a buffer or an image subresource.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct State<A, L> {
+    access: A,
+    layout: L,
+    /// Stages accessing the resource since the last barrier, or all the
+    /// stages reading it since the last write. Empty if never used.
+    stages: PipelineStage,
+    family: Option<QueueFamilyId>,
+}
+
+/// Source and destination queue families of an ownership transfer.
+type Families = Option<Range<QueueFamilyId>>;
+
+/// Move `state` to the next use, returning the state to transition from
+/// and the ownership transfer if a barrier is needed.
+fn transition<A: Access, L: Copy + Eq>(
+    state: &mut State<A, L>,
+    next: State<A, L>,
+) -> Option<(State<A, L>, Families)> {
+    let old = *state;
+    let families = match (old.family, next.family) {
+        (Some(from), Some(to)) if from != to => Some(from .. to),
+        _ => None,
+    };
+    let family = next.family.or(old.family);
+
+    if families.is_none() && old.layout == next.layout {
+        if old.stages.is_empty() {
+            // First use, there is nothing to wait for.
+            *state = State { family, ..next };
+            return None;
+        }
+        if !old.access.is_write() && !next.access.is_write() {
+            // Reads only need to wait for the previous write, which the barrier
+            // in front of the previous reads already covered, unless the new
+            // reads happen with other accesses or in other stages.
+            state.access = old.access | next.access;
+            state.stages = old.stages | next.stages;
+            if old.access.includes(next.access) && old.stages.contains(next.stages) {
+                return None;
+            }
+            return Some((old, None));
+        }
+    }
+
+    *state = State { family, ..next };
+    Some((old, families))
+}
+
+/// Buffer barrier collected by the `Tracker`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BufferTransition {
+    pub buffer: BufferId,
+    pub states: Range<buffer::State>,
+    pub families: Option<Range<QueueFamilyId>>,
+}
+
+/// Image barrier collected by the `Tracker`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ImageTransition {
+    pub image: ImageId,
+    pub states: Range<image::State>,
+    pub range: image::SubresourceRange,
+    pub families: Option<Range<QueueFamilyId>>,
+}
+
+/// Transitions to be recorded in a single `pipeline_barrier`, in front of
+/// commands executing on a queue of the given family.
+///
+/// Ownership transfers between queue families need the same barriers to be
+/// recorded on the releasing queue as well.
+///
+/// Each resource is expected to be used once per batch: the accesses
+/// of the following commands have to be combined into a single usage.
+#[derive(Clone, Debug)]
+pub struct Transitions {
+    family: Option<QueueFamilyId>,
+    src_stages: PipelineStage,
+    dst_stages: PipelineStage,
+    pub buffers: Vec<BufferTransition>,
+    pub images: Vec<ImageTransition>,
+}
+
+impl Transitions {
+    /// Start a batch for a queue of `family`, or `None` if all resources
+    /// are shared between queue families or only a single one is used.
+    pub fn new(family: Option<QueueFamilyId>) -> Self {
+        Transitions {
+            family,
+            src_stages: PipelineStage::empty(),
+            dst_stages: PipelineStage::empty(),
+            buffers: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    pub fn family(&self) -> Option<QueueFamilyId> {
+        self.family
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffers.is_empty() && self.images.is_empty() && self.src_stages.is_empty()
+    }
+
+    /// Add an execution dependency without memory barriers, for example
+    /// between resources placed in the same memory.
+    pub fn wait(&mut self, stages: Range<PipelineStage>) {
+        self.src_stages |= stages.start;
+        self.dst_stages |= stages.end;
+    }
+
+    /// Stages to pass to `pipeline_barrier`.
+    pub fn stages(&self) -> Range<PipelineStage> {
+        let src = if self.src_stages.is_empty() {
+            PipelineStage::TOP_OF_PIPE
+        } else {
+            self.src_stages
+        };
+        let dst = if self.dst_stages.is_empty() {
+            PipelineStage::BOTTOM_OF_PIPE
+        } else {
+            self.dst_stages
+        };
+        src .. dst
+    }
+
+    /// Clear the batch, keeping the queue family.
+    pub fn clear(&mut self) {
+        self.src_stages = PipelineStage::empty();
+        self.dst_stages = PipelineStage::empty();
+        self.buffers.clear();
+        self.images.clear();
+    }
+
+    /// Barriers for `pipeline_barrier`, looking the resources up by id.
+    pub fn barriers<'a, B, F, G>(
+        &'a self,
+        buffers: F,
+        images: G,
+    ) -> impl Iterator<Item = Barrier<'a, B>> + 'a
+    where
+        B: Backend,
+        F: 'a + Fn(BufferId) -> &'a B::Buffer,
+        G: 'a + Fn(ImageId) -> &'a B::Image,
+    {
+        let buffer_barriers = self.buffers.iter().map(move |t| Barrier::Buffer {
+            states: t.states.clone(),
+            target: buffers(t.buffer),
+            range: buffer::SubRange::WHOLE,
+            families: t.families.clone(),
+        });
+        let image_barriers = self.images.iter().map(move |t| Barrier::Image {
+            states: t.states.clone(),
+            target: images(t.image),
+            range: t.range.clone(),
+            families: t.families.clone(),
+        });
+        buffer_barriers.chain(image_barriers)
+    }
+
+    /// Record the batch into a command buffer, if there is anything to record.
+    ///
+    /// # Safety
+    ///
+    /// `command_buffer` has to be in the recording state, outside of a render pass.
+    /// `buffers` and `images` have to return the resources the ids were tracked for,
+    /// which must stay alive until the command buffer has completed execution.
+    pub unsafe fn record<'a, B, C, F, G>(&'a self, command_buffer: &mut C, buffers: F, images: G)
+    where
+        B: Backend,
+        C: CommandBuffer<B>,
+        F: 'a + Fn(BufferId) -> &'a B::Buffer,
+        G: 'a + Fn(ImageId) -> &'a B::Image,
+    {
+        if !self.is_empty() {
+            command_buffer.pipeline_barrier(
+                self.stages(),
+                Dependencies::empty(),
+                self.barriers(buffers, images),
+            );
+        }
+    }
+}
+
+type BufferState = State<buffer::Access, ()>;
+type ImageState = State<image::Access, image::Layout>;
+
+#[derive(Debug)]
+struct ImageStates {
+    /// Single aspects of the image, in the order of `states`.
+    aspects: Vec<Aspects>,
+    levels: image::Level,
+    layers: image::Layer,
+    /// Per subresource, ordered by aspect, level and then layer.
+    states: Vec<ImageState>,
+}
+
+impl ImageStates {
+    fn index(&self, aspect: usize, level: image::Level, layer: image::Layer) -> usize {
+        (aspect * self.levels as usize + level as usize) * self.layers as usize + layer as usize
+    }
+}
+
+/// Subresources sharing the same previous state.
+struct Piece {
+    from: image::State,
+    families: Option<Range<QueueFamilyId>>,
+    aspects: Aspects,
+    levels: Range<image::Level>,
+    layers: Range<image::Layer>,
+}
+
+fn insert<T>(slots: &mut Vec<Option<T>>, value: T) -> usize {
+    match slots.iter().position(Option::is_none) {
+        Some(index) => {
+            slots[index] = Some(value);
+            index
+        }
+        None => {
+            slots.push(Some(value));
+            slots.len() - 1
+        }
+    }
+}
+
+/// Last known states of buffers and image subresources.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    buffers: Vec<Option<BufferState>>,
+    images: Vec<Option<ImageStates>>,
+}
+
+impl Tracker {
+    pub fn new() -> Self {
+        Tracker::default()
+    }
+
+    /// Start tracking a buffer which has not been used yet.
+    pub fn add_buffer(&mut self) -> BufferId {
+        let state = State {
+            access: buffer::Access::empty(),
+            layout: (),
+            stages: PipelineStage::empty(),
+            family: None,
+        };
+        BufferId(insert(&mut self.buffers, state))
+    }
+
+    pub fn remove_buffer(&mut self, id: BufferId) {
+        self.buffers[id.0] = None;
+    }
+
+    /// Start tracking an image which has not been used yet, in the
+    /// `Undefined` or `Preinitialized` layout.
+    pub fn add_image(
+        &mut self,
+        aspects: Aspects,
+        levels: image::Level,
+        layers: image::Layer,
+        layout: image::Layout,
+    ) -> ImageId {
+        let aspects = [Aspects::COLOR, Aspects::DEPTH, Aspects::STENCIL]
+            .iter()
+            .cloned()
+            .filter(|&aspect| aspects.contains(aspect))
+            .collect::<Vec<_>>();
+        let state = State {
+            access: image::Access::empty(),
+            layout,
+            stages: PipelineStage::empty(),
+            family: None,
+        };
+        let count = aspects.len() * levels as usize * layers as usize;
+        let states = ImageStates {
+            aspects,
+            levels,
+            layers,
+            states: vec![state; count],
+        };
+        ImageId(insert(&mut self.images, states))
+    }
+
+    pub fn remove_image(&mut self, id: ImageId) {
+        self.images[id.0] = None;
+    }
+
+    pub fn buffer_state(&self, id: BufferId) -> buffer::State {
+        self.buffers[id.0].as_ref().unwrap().access
+    }
+
+    pub fn image_state(
+        &self,
+        id: ImageId,
+        aspect: Aspects,
+        level: image::Level,
+        layer: image::Layer,
+    ) -> image::State {
+        let image = self.images[id.0].as_ref().unwrap();
+        let aspect = image.aspects.iter().position(|&a| a == aspect).unwrap();
+        let state = &image.states[image.index(aspect, level, layer)];
+        (state.access, state.layout)
+    }
+
+    /// Announce the next use of a buffer, adding the transition it needs to `transitions`.
+    pub fn use_buffer(&mut self, transitions: &mut Transitions, id: BufferId, usage: BufferUsage) {
+        let state = self.buffers[id.0].as_mut().unwrap();
+        let next = State {
+            access: usage.access,
+            layout: (),
+            stages: usage.stages,
+            family: transitions.family,
+        };
+        if let Some((from, families)) = transition(state, next) {
+            transitions.src_stages |= from.stages;
+            transitions.dst_stages |= usage.stages;
+            transitions.buffers.push(BufferTransition {
+                buffer: id,
+                states: from.access .. usage.access,
+                families,
+            });
+        }
+    }
+
+    /// Announce the next use of image subresources, adding the transitions they need
+    /// to `transitions`. Subresources coming from different states get separate barriers,
+    /// while the ones sharing a state are merged into as few ranges as possible.
+    pub fn use_image(
+        &mut self,
+        transitions: &mut Transitions,
+        id: ImageId,
+        range: &image::SubresourceRange,
+        usage: ImageUsage,
+    ) {
+        let image = self.images[id.0].as_mut().unwrap();
+        let next = State {
+            access: usage.access,
+            layout: usage.layout,
+            stages: usage.stages,
+            family: transitions.family,
+        };
+
+        let mut pieces: Vec<Piece> = Vec::new();
+        for aspect_index in 0 .. image.aspects.len() {
+            let aspect = image.aspects[aspect_index];
+            if !range.aspects.contains(aspect) {
+                continue;
+            }
+            for level in range.levels.clone() {
+                let mut runs: Vec<Piece> = Vec::new();
+                for layer in range.layers.clone() {
+                    let index = image.index(aspect_index, level, layer);
+                    let (from, families) = match transition(&mut image.states[index], next) {
+                        Some(found) => found,
+                        None => continue,
+                    };
+                    transitions.src_stages |= from.stages;
+                    let from = (from.access, from.layout);
+                    match runs.last_mut() {
+                        Some(run)
+                            if run.from == from
+                                && run.families == families
+                                && run.layers.end == layer =>
+                        {
+                            run.layers.end += 1
+                        }
+                        _ => runs.push(Piece {
+                            from,
+                            families,
+                            aspects: aspect,
+                            levels: level .. level + 1,
+                            layers: layer .. layer + 1,
+                        }),
+                    }
+                }
+                // Extend the matching pieces of the previous level.
+                for run in runs {
+                    let previous = pieces.iter_mut().find(|piece| {
+                        piece.aspects == run.aspects
+                            && piece.from == run.from
+                            && piece.families == run.families
+                            && piece.layers == run.layers
+                            && piece.levels.end == level
+                    });
+                    match previous {
+                        Some(piece) => piece.levels.end += 1,
+                        None => pieces.push(run),
+                    }
+                }
+            }
+        }
+
+        // Merge the aspects covering the same levels and layers.
+        let mut merged: Vec<Piece> = Vec::new();
+        for piece in pieces {
+            let same = merged.iter_mut().find(|other| {
+                other.from == piece.from
+                    && other.families == piece.families
+                    && other.levels == piece.levels
+                    && other.layers == piece.layers
+            });
+            match same {
+                Some(other) => other.aspects |= piece.aspects,
+                None => merged.push(piece),
+            }
+        }
+
+        if !merged.is_empty() {
+            transitions.dst_stages |= usage.stages;
+        }
+        for piece in merged {
+            transitions.images.push(ImageTransition {
+                image: id,
+                states: piece.from .. (usage.access, usage.layout),
+                range: image::SubresourceRange {
+                    aspects: piece.aspects,
+                    levels: piece.levels,
+                    layers: piece.layers,
+                },
+                families: piece.families,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::image::{Access as Ia, Layout};
+
+    fn subresources(
+        aspects: Aspects,
+        levels: Range<image::Level>,
+        layers: Range<image::Layer>,
+    ) -> image::SubresourceRange {
+        image::SubresourceRange {
+            aspects,
+            levels,
+            layers,
+        }
+    }
+
+    #[test]
+    fn test_buffer_transitions() {
+        let mut tracker = Tracker::new();
+        let id = tracker.add_buffer();
+        let write = BufferUsage {
+            access: buffer::Access::TRANSFER_WRITE,
+            stages: PipelineStage::TRANSFER,
+        };
+        let vertex = BufferUsage {
+            access: buffer::Access::VERTEX_BUFFER_READ,
+            stages: PipelineStage::VERTEX_INPUT,
+        };
+        let uniform = BufferUsage {
+            access: buffer::Access::UNIFORM_READ,
+            stages: PipelineStage::VERTEX_SHADER,
+        };
+
+        // Nothing to wait for on the first use.
+        let mut transitions = Transitions::new(None);
+        tracker.use_buffer(&mut transitions, id, write);
+        assert!(transitions.is_empty());
+
+        tracker.use_buffer(&mut transitions, id, vertex);
+        assert_eq!(
+            transitions.buffers,
+            vec![BufferTransition {
+                buffer: id,
+                states: buffer::Access::TRANSFER_WRITE .. buffer::Access::VERTEX_BUFFER_READ,
+                families: None,
+            }]
+        );
+        assert_eq!(
+            transitions.stages(),
+            PipelineStage::TRANSFER .. PipelineStage::VERTEX_INPUT
+        );
+
+        // Repeated reads don't need barriers, new kinds of reads do.
+        transitions.clear();
+        tracker.use_buffer(&mut transitions, id, vertex);
+        assert!(transitions.is_empty());
+        tracker.use_buffer(&mut transitions, id, uniform);
+        assert_eq!(transitions.buffers.len(), 1);
+
+        // Writing waits for all the reads.
+        transitions.clear();
+        tracker.use_buffer(&mut transitions, id, write);
+        assert_eq!(
+            transitions.stages(),
+            PipelineStage::VERTEX_INPUT | PipelineStage::VERTEX_SHADER .. PipelineStage::TRANSFER
+        );
+        assert_eq!(tracker.buffer_state(id), buffer::Access::TRANSFER_WRITE);
+    }
+
+    #[test]
+    fn test_split_subresource_ranges() {
+        let mut tracker = Tracker::new();
+        let id = tracker.add_image(Aspects::COLOR, 4, 2, Layout::Undefined);
+        let upload = ImageUsage {
+            access: Ia::TRANSFER_WRITE,
+            layout: Layout::TransferDstOptimal,
+            stages: PipelineStage::TRANSFER,
+        };
+        let sample = ImageUsage {
+            access: Ia::SHADER_READ,
+            layout: Layout::ShaderReadOnlyOptimal,
+            stages: PipelineStage::FRAGMENT_SHADER,
+        };
+
+        let mut transitions = Transitions::new(None);
+        tracker.use_image(
+            &mut transitions,
+            id,
+            &subresources(Aspects::COLOR, 0 .. 1, 0 .. 2),
+            upload,
+        );
+        assert_eq!(transitions.images.len(), 1);
+        assert_eq!(transitions.stages().start, PipelineStage::TOP_OF_PIPE);
+
+        transitions.clear();
+        tracker.use_image(
+            &mut transitions,
+            id,
+            &subresources(Aspects::COLOR, 0 .. 4, 0 .. 2),
+            sample,
+        );
+        assert_eq!(
+            transitions.images,
+            vec![
+                ImageTransition {
+                    image: id,
+                    states: (Ia::TRANSFER_WRITE, Layout::TransferDstOptimal)
+                        .. (Ia::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                    range: subresources(Aspects::COLOR, 0 .. 1, 0 .. 2),
+                    families: None,
+                },
+                ImageTransition {
+                    image: id,
+                    states: (Ia::empty(), Layout::Undefined)
+                        .. (Ia::SHADER_READ, Layout::ShaderReadOnlyOptimal),
+                    range: subresources(Aspects::COLOR, 1 .. 4, 0 .. 2),
+                    families: None,
+                },
+            ]
+        );
+        assert_eq!(
+            tracker.image_state(id, Aspects::COLOR, 3, 1),
+            (Ia::SHADER_READ, Layout::ShaderReadOnlyOptimal)
+        );
+    }
+
+    #[test]
+    fn test_merge_aspects() {
+        let mut tracker = Tracker::new();
+        let aspects = Aspects::DEPTH | Aspects::STENCIL;
+        let id = tracker.add_image(aspects, 1, 1, Layout::Undefined);
+        let mut transitions = Transitions::new(None);
+        tracker.use_image(
+            &mut transitions,
+            id,
+            &subresources(aspects, 0 .. 1, 0 .. 1),
+            ImageUsage {
+                access: Ia::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                layout: Layout::DepthStencilAttachmentOptimal,
+                stages: PipelineStage::EARLY_FRAGMENT_TESTS,
+            },
+        );
+        assert_eq!(transitions.images.len(), 1);
+        assert_eq!(transitions.images[0].range.aspects, aspects);
+    }
+
+    #[test]
+    fn test_queue_family_transfer() {
+        let mut tracker = Tracker::new();
+        let id = tracker.add_buffer();
+        let graphics = QueueFamilyId(0);
+        let transfer = QueueFamilyId(1);
+
+        let mut transitions = Transitions::new(Some(transfer));
+        tracker.use_buffer(
+            &mut transitions,
+            id,
+            BufferUsage {
+                access: buffer::Access::TRANSFER_WRITE,
+                stages: PipelineStage::TRANSFER,
+            },
+        );
+        assert!(transitions.is_empty());
+
+        let mut transitions = Transitions::new(Some(graphics));
+        tracker.use_buffer(
+            &mut transitions,
+            id,
+            BufferUsage {
+                access: buffer::Access::TRANSFER_WRITE,
+                stages: PipelineStage::TRANSFER,
+            },
+        );
+        assert_eq!(transitions.buffers[0].families, Some(transfer .. graphics));
+    }
+}
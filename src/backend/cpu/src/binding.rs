@@ -0,0 +1,150 @@
+//! Descriptor sets and push constants bound to a command buffer.
+
+use hal::{buffer, command as com, pso};
+
+use crate::interp;
+use crate::native as n;
+
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::Arc;
+
+fn is_dynamic(binding: &pso::DescriptorSetLayoutBinding) -> bool {
+    match binding.ty {
+        pso::DescriptorType::Buffer {
+            format: pso::BufferDescriptorFormat::Structured { dynamic_offset },
+            ..
+        } => dynamic_offset,
+        _ => false,
+    }
+}
+
+/// A descriptor set bound to a command buffer, with its dynamic offsets.
+#[derive(Clone, Debug)]
+pub(crate) struct BoundSet {
+    pub(crate) set: n::DescriptorSet,
+    pub(crate) offsets: Vec<com::DescriptorSetOffset>,
+}
+
+impl BoundSet {
+    /// Dynamic offset of the descriptor `index` of the binding at
+    /// `binding_index` in the layout, if it has one.
+    ///
+    /// Like in Vulkan, the offsets are ordered by binding number.
+    fn dynamic_offset(&self, binding_index: usize, index: u32) -> Option<buffer::Offset> {
+        let binding = &self.set.layout[binding_index];
+        if !is_dynamic(binding) {
+            return None;
+        }
+        let preceding: usize = self
+            .set
+            .layout
+            .iter()
+            .filter(|b| b.binding < binding.binding && is_dynamic(b))
+            .map(|b| b.count)
+            .sum();
+        self.offsets
+            .get(preceding + index as usize)
+            .map(|&offset| offset as buffer::Offset)
+    }
+}
+
+/// Resources bound for one pipeline bind point of a command buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Bindings {
+    pub(crate) sets: Vec<Option<BoundSet>>,
+    /// Push constant data, in words.
+    pub(crate) push_constants: Vec<u32>,
+}
+
+impl Bindings {
+    pub(crate) fn bind_sets<I, J>(&mut self, first_set: usize, sets: I, offsets: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let mut offsets = offsets.into_iter().map(|offset| *offset.borrow());
+        for (i, set) in sets.into_iter().enumerate() {
+            let set = set.borrow().clone();
+            let dynamic_count = set
+                .layout
+                .iter()
+                .filter(|binding| is_dynamic(binding))
+                .map(|binding| binding.count)
+                .sum();
+            let bound = BoundSet {
+                set,
+                offsets: offsets.by_ref().take(dynamic_count).collect(),
+            };
+            let index = first_set + i;
+            if self.sets.len() <= index {
+                self.sets.resize(index + 1, None);
+            }
+            self.sets[index] = Some(bound);
+        }
+    }
+
+    pub(crate) fn push_constants(&mut self, offset: u32, constants: &[u32]) {
+        let start = offset as usize / 4;
+        let end = start + constants.len();
+        if self.push_constants.len() < end {
+            self.push_constants.resize(end, 0);
+        }
+        self.push_constants[start .. end].copy_from_slice(constants);
+    }
+
+    /// Snapshot of the bound resources for shader execution.
+    pub(crate) fn resources(&self) -> Resources<'_> {
+        Resources::new(&self.sets, &self.push_constants)
+    }
+}
+
+/// Resources of a dispatch or draw, as seen by the interpreter.
+pub(crate) struct Resources<'a> {
+    sets: &'a [Option<BoundSet>],
+    push_constants: Option<Arc<n::Block>>,
+}
+
+impl<'a> Resources<'a> {
+    fn new(sets: &'a [Option<BoundSet>], push_constants: &[u32]) -> Self {
+        let push_constants = if push_constants.is_empty() {
+            None
+        } else {
+            let block = n::Block::new(push_constants.len() * 4);
+            let bytes = unsafe { block.slice_mut(0 .. block.len()) };
+            for (chunk, word) in bytes.chunks_exact_mut(4).zip(push_constants) {
+                chunk.copy_from_slice(&word.to_le_bytes());
+            }
+            Some(Arc::new(block))
+        };
+        Resources {
+            sets,
+            push_constants,
+        }
+    }
+}
+
+impl<'a> interp::Resources for Resources<'a> {
+    fn buffer(&self, set: u32, binding: u32, index: u32) -> Option<(Arc<n::Block>, Range<u64>)> {
+        let bound = self.sets.get(set as usize)?.as_ref()?;
+        let binding_index = bound.set.binding_index(binding)?;
+        let descriptors = bound.set.bindings.lock();
+        match *descriptors[binding_index].get(index as usize)? {
+            Some(n::Descriptor::Buffer(ref buffer, ref sub)) => {
+                let range = buffer.resolve(sub);
+                let offset = bound.dynamic_offset(binding_index, index).unwrap_or(0);
+                Some((
+                    buffer.block.clone(),
+                    range.start + offset .. range.end + offset,
+                ))
+            }
+            _ => None,
+        }
+    }
+
+    fn push_constants(&self) -> Option<Arc<n::Block>> {
+        self.push_constants.clone()
+    }
+}
@@ -0,0 +1,805 @@
+use hal::format::Aspects;
+use hal::{buffer, command as com, image, memory, pass, pso, query};
+
+use crate::compute::ComputeState;
+use crate::raster::{GraphicsState, Targets, Vertices};
+use crate::{native as n, Backend};
+
+use std::borrow::Borrow;
+use std::ops::Range;
+
+// Command buffer implementation details:
+//
+// Recording only captures the commands together with the memory of the
+// resources they touch. Everything is executed on the host once the command
+// buffer gets submitted to a queue, see `queue.rs`.
+
+/// A command recorded for host execution.
+#[derive(Clone, Debug)]
+pub enum Command {
+    CopyBuffer {
+        src: n::BoundBuffer,
+        dst: n::BoundBuffer,
+        regions: Vec<com::BufferCopy>,
+    },
+    FillBuffer {
+        dst: n::BoundBuffer,
+        range: Range<buffer::Offset>,
+        data: u32,
+    },
+    UpdateBuffer {
+        dst: n::BoundBuffer,
+        offset: buffer::Offset,
+        data: Vec<u8>,
+    },
+    CopyImage {
+        src: n::BoundImage,
+        dst: n::BoundImage,
+        regions: Vec<com::ImageCopy>,
+    },
+    CopyBufferToImage {
+        src: n::BoundBuffer,
+        dst: n::BoundImage,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    CopyImageToBuffer {
+        src: n::BoundImage,
+        dst: n::BoundBuffer,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    BlitImage {
+        src: n::BoundImage,
+        dst: n::BoundImage,
+        filter: image::Filter,
+        regions: Vec<com::ImageBlit>,
+    },
+    ClearImage {
+        dst: n::BoundImage,
+        value: com::ClearValue,
+        ranges: Vec<image::SubresourceRange>,
+    },
+    SetEvent(n::Event, bool),
+    ResetQueries(n::QueryPool, Range<query::Id>),
+    /// Start counting samples for an occlusion query.
+    BeginQuery(n::QueryPool, query::Id),
+    EndQuery(n::QueryPool, query::Id),
+    /// Write the time the command executes at, in nanoseconds.
+    WriteTimestamp(n::QueryPool, query::Id),
+    CopyQueryResults {
+        pool: n::QueryPool,
+        queries: Range<query::Id>,
+        dst: n::BoundBuffer,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    },
+    Dispatch {
+        state: ComputeState,
+        count: hal::WorkGroupCount,
+    },
+    DispatchIndirect {
+        state: ComputeState,
+        buffer: n::BoundBuffer,
+        offset: buffer::Offset,
+    },
+    /// Clear the given aspects of attachment views inside the rectangles.
+    ClearAttachments {
+        clears: Vec<(n::ImageView, Aspects, com::ClearValue)>,
+        rects: Vec<pso::ClearRect>,
+    },
+    Draw {
+        state: GraphicsState,
+        vertices: Vertices,
+        instances: Range<hal::InstanceCount>,
+    },
+    DrawIndirect {
+        state: GraphicsState,
+        buffer: n::BoundBuffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+        indexed: bool,
+    },
+}
+
+/// The render pass instance being recorded.
+#[derive(Debug)]
+struct PassState {
+    render_pass: n::RenderPass,
+    attachments: Vec<n::ImageView>,
+    area: pso::Rect,
+    layers: image::Layer,
+    subpass: usize,
+}
+
+/// A command buffer recording commands for host execution.
+#[derive(Debug)]
+pub struct CommandBuffer {
+    pub(crate) commands: Vec<Command>,
+    compute: ComputeState,
+    graphics: GraphicsState,
+    pass: Option<PassState>,
+}
+
+impl CommandBuffer {
+    pub(crate) fn new() -> Self {
+        CommandBuffer {
+            commands: Vec::new(),
+            compute: ComputeState::default(),
+            graphics: GraphicsState::default(),
+            pass: None,
+        }
+    }
+
+    fn reset_state(&mut self) {
+        self.commands.clear();
+        self.compute = ComputeState::default();
+        self.graphics = GraphicsState::default();
+        self.pass = None;
+    }
+
+    /// Point the draw targets at the attachments of the current subpass.
+    fn begin_subpass(&mut self) {
+        let pass = self.pass.as_ref().expect("Not inside a render pass");
+        let subpass = &pass.render_pass.subpasses[pass.subpass];
+        self.graphics.targets = Some(Targets {
+            colors: subpass
+                .colors
+                .iter()
+                .map(|&(id, _)| pass.attachments[id].clone())
+                .collect(),
+            depth_stencil: subpass
+                .depth_stencil
+                .map(|(id, _)| pass.attachments[id].clone()),
+            area: pass.area,
+        });
+    }
+
+    /// Resolve the color attachments of the current subpass. Attachments
+    /// are never multisampled, so resolving is a copy of the render area.
+    fn end_subpass(&mut self) {
+        let pass = self.pass.as_ref().expect("Not inside a render pass");
+        let subpass = &pass.render_pass.subpasses[pass.subpass];
+        for (&(src, _), &(dst, _)) in subpass.colors.iter().zip(&subpass.resolves) {
+            let (src, dst) = (&pass.attachments[src], &pass.attachments[dst]);
+            let layers = |view: &n::ImageView| image::SubresourceLayers {
+                aspects: Aspects::COLOR,
+                level: view.range.levels.start,
+                layers: view.range.layers.start .. view.range.layers.start + pass.layers,
+            };
+            let offset = image::Offset {
+                x: pass.area.x as i32,
+                y: pass.area.y as i32,
+                z: 0,
+            };
+            self.commands.push(Command::CopyImage {
+                src: src.image.clone(),
+                dst: dst.image.clone(),
+                regions: vec![com::ImageCopy {
+                    src_subresource: layers(src),
+                    src_offset: offset,
+                    dst_subresource: layers(dst),
+                    dst_offset: offset,
+                    extent: image::Extent {
+                        width: pass.area.w as u32,
+                        height: pass.area.h as u32,
+                        depth: 1,
+                    },
+                }],
+            });
+        }
+    }
+}
+
+impl com::CommandBuffer<Backend> for CommandBuffer {
+    unsafe fn begin(
+        &mut self,
+        _flags: com::CommandBufferFlags,
+        _inheritance_info: com::CommandBufferInheritanceInfo<Backend>,
+    ) {
+        // Beginning a command buffer implicitly resets it.
+        self.reset_state();
+    }
+
+    unsafe fn finish(&mut self) {}
+
+    unsafe fn reset(&mut self, _release_resources: bool) {
+        self.reset_state();
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        _stages: Range<pso::PipelineStage>,
+        _dependencies: memory::Dependencies,
+        _barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        // Commands are executed in order on a single thread.
+    }
+
+    unsafe fn fill_buffer(&mut self, buffer: &n::Buffer, range: buffer::SubRange, data: u32) {
+        let bound = buffer.as_bound();
+        // Ranges running past the end of the buffer are clamped to it, and
+        // rounded down to a multiple of 4 like a whole-size range.
+        let start = bound.range.start + range.offset;
+        let end = range
+            .size
+            .map_or(bound.range.end, |size| (start + size).min(bound.range.end));
+        let range = start .. end - (end - start) % 4;
+        self.commands.push(Command::FillBuffer {
+            dst: bound.clone(),
+            range,
+            data,
+        });
+    }
+
+    unsafe fn update_buffer(&mut self, buffer: &n::Buffer, offset: buffer::Offset, data: &[u8]) {
+        self.commands.push(Command::UpdateBuffer {
+            dst: buffer.as_bound().clone(),
+            offset,
+            data: data.to_vec(),
+        });
+    }
+
+    unsafe fn clear_image<T>(
+        &mut self,
+        image: &n::Image,
+        _layout: image::Layout,
+        value: com::ClearValue,
+        subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        self.commands.push(Command::ClearImage {
+            dst: image.as_bound().clone(),
+            value,
+            ranges: subresource_ranges
+                .into_iter()
+                .map(|range| range.borrow().clone())
+                .collect(),
+        });
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, clears: T, rects: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        let targets = match self.graphics.targets {
+            Some(ref targets) => targets,
+            None => {
+                error!("Clearing attachments outside of a render pass");
+                return;
+            }
+        };
+        let clears = clears
+            .into_iter()
+            .filter_map(|clear| match *clear.borrow() {
+                com::AttachmentClear::Color { index, value } => Some((
+                    targets.colors[index].clone(),
+                    Aspects::COLOR,
+                    com::ClearValue { color: value },
+                )),
+                com::AttachmentClear::DepthStencil { depth, stencil } => {
+                    let mut aspects = Aspects::empty();
+                    if depth.is_some() {
+                        aspects |= Aspects::DEPTH;
+                    }
+                    if stencil.is_some() {
+                        aspects |= Aspects::STENCIL;
+                    }
+                    let value = com::ClearValue {
+                        depth_stencil: com::ClearDepthStencil {
+                            depth: depth.unwrap_or(0.0),
+                            stencil: stencil.unwrap_or(0),
+                        },
+                    };
+                    Some((targets.depth_stencil.clone()?, aspects, value))
+                }
+            })
+            .collect();
+        self.commands.push(Command::ClearAttachments {
+            clears,
+            rects: rects
+                .into_iter()
+                .map(|rect| rect.borrow().clone())
+                .collect(),
+        });
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        src: &n::Image,
+        _src_layout: image::Layout,
+        dst: &n::Image,
+        _dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageResolve>,
+    {
+        // Images have a single sample, which is the average of all of them.
+        self.commands.push(Command::CopyImage {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            regions: regions
+                .into_iter()
+                .map(|r| {
+                    let r = r.borrow();
+                    com::ImageCopy {
+                        src_subresource: r.src_subresource.clone(),
+                        src_offset: r.src_offset,
+                        dst_subresource: r.dst_subresource.clone(),
+                        dst_offset: r.dst_offset,
+                        extent: r.extent,
+                    }
+                })
+                .collect(),
+        });
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        src: &n::Image,
+        _src_layout: image::Layout,
+        dst: &n::Image,
+        _dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageBlit>,
+    {
+        self.commands.push(Command::BlitImage {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            filter,
+            regions: regions.into_iter().map(|r| r.borrow().clone()).collect(),
+        });
+    }
+
+    unsafe fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
+        let bound = ibv.buffer.as_bound();
+        let buffer = n::BoundBuffer {
+            block: bound.block.clone(),
+            range: bound.resolve(&ibv.range),
+        };
+        self.graphics.index_buffer = Some((buffer, ibv.index_type));
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, first_binding: pso::BufferIndex, buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::SubRange)>,
+        T: Borrow<n::Buffer>,
+    {
+        let buffers = buffers.into_iter().map(|(buffer, sub)| {
+            let bound = buffer.borrow().as_bound();
+            n::BoundBuffer {
+                block: bound.block.clone(),
+                range: bound.resolve(&sub),
+            }
+        });
+        self.graphics
+            .bind_vertex_buffers(first_binding as usize, buffers);
+    }
+
+    unsafe fn set_viewports<T>(&mut self, first_viewport: u32, viewports: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        // Only a single viewport is supported.
+        if first_viewport == 0 {
+            if let Some(viewport) = viewports.into_iter().next() {
+                self.graphics.viewport = Some(viewport.borrow().clone());
+            }
+        }
+    }
+
+    unsafe fn set_scissors<T>(&mut self, first_scissor: u32, scissors: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        // Only a single scissor is supported.
+        if first_scissor == 0 {
+            if let Some(scissor) = scissors.into_iter().next() {
+                self.graphics.scissor = Some(*scissor.borrow());
+            }
+        }
+    }
+
+    unsafe fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        set_sided(&mut self.graphics.stencil_reference, faces, value);
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        set_sided(&mut self.graphics.stencil_read_mask, faces, value);
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        set_sided(&mut self.graphics.stencil_write_mask, faces, value);
+    }
+
+    unsafe fn set_blend_constants(&mut self, color: pso::ColorValue) {
+        self.graphics.blend_constants = color;
+    }
+
+    unsafe fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        self.graphics.depth_bounds = bounds;
+    }
+
+    unsafe fn set_line_width(&mut self, _width: f32) {
+        // Lines are always rasterized one pixel wide.
+    }
+
+    unsafe fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        self.graphics.depth_bias = depth_bias;
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &n::RenderPass,
+        framebuffer: &n::Framebuffer,
+        render_area: pso::Rect,
+        clear_values: T,
+        _first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValue>,
+    {
+        // Attachments with a `Clear` load operation are cleared all at once
+        // when the render pass begins.
+        let clears = render_pass
+            .attachments
+            .iter()
+            .zip(&framebuffer.attachments)
+            .zip(clear_values)
+            .filter_map(|((attachment, view), value)| {
+                let format = attachment.format.unwrap_or(view.format);
+                let mut aspects = Aspects::empty();
+                if attachment.ops.load == pass::AttachmentLoadOp::Clear {
+                    aspects |= format.surface_desc().aspects & !Aspects::STENCIL;
+                }
+                if attachment.stencil_ops.load == pass::AttachmentLoadOp::Clear {
+                    aspects |= format.surface_desc().aspects & Aspects::STENCIL;
+                }
+                if aspects.is_empty() {
+                    None
+                } else {
+                    Some((view.clone(), aspects, *value.borrow()))
+                }
+            })
+            .collect::<Vec<_>>();
+        if !clears.is_empty() {
+            self.commands.push(Command::ClearAttachments {
+                clears,
+                rects: vec![pso::ClearRect {
+                    rect: render_area,
+                    layers: 0 .. framebuffer.extent.depth as image::Layer,
+                }],
+            });
+        }
+
+        self.pass = Some(PassState {
+            render_pass: render_pass.clone(),
+            attachments: framebuffer.attachments.clone(),
+            area: render_area,
+            layers: framebuffer.extent.depth as image::Layer,
+            subpass: 0,
+        });
+        self.begin_subpass();
+    }
+
+    unsafe fn next_subpass(&mut self, _contents: com::SubpassContents) {
+        self.end_subpass();
+        if let Some(ref mut pass) = self.pass {
+            pass.subpass += 1;
+        }
+        self.begin_subpass();
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        self.end_subpass();
+        self.pass = None;
+        self.graphics.targets = None;
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline) {
+        self.graphics.pipeline = Some(pipeline.clone());
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(
+        &mut self,
+        _layout: &n::PipelineLayout,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        self.graphics.bindings.bind_sets(first_set, sets, offsets);
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline) {
+        self.compute.pipeline = Some(pipeline.clone());
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(
+        &mut self,
+        _layout: &n::PipelineLayout,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        self.compute.bindings.bind_sets(first_set, sets, offsets);
+    }
+
+    unsafe fn dispatch(&mut self, count: hal::WorkGroupCount) {
+        self.commands.push(Command::Dispatch {
+            state: self.compute.clone(),
+            count,
+        });
+    }
+
+    unsafe fn dispatch_indirect(&mut self, buffer: &n::Buffer, offset: buffer::Offset) {
+        self.commands.push(Command::DispatchIndirect {
+            state: self.compute.clone(),
+            buffer: buffer.as_bound().clone(),
+            offset,
+        });
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, src: &n::Buffer, dst: &n::Buffer, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferCopy>,
+    {
+        self.commands.push(Command::CopyBuffer {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            regions: regions.into_iter().map(|r| *r.borrow()).collect(),
+        });
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        src: &n::Image,
+        _src_layout: image::Layout,
+        dst: &n::Image,
+        _dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageCopy>,
+    {
+        self.commands.push(Command::CopyImage {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            regions: regions.into_iter().map(|r| r.borrow().clone()).collect(),
+        });
+    }
+
+    unsafe fn copy_buffer_to_image<T>(
+        &mut self,
+        src: &n::Buffer,
+        dst: &n::Image,
+        _dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        self.commands.push(Command::CopyBufferToImage {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            regions: regions.into_iter().map(|r| r.borrow().clone()).collect(),
+        });
+    }
+
+    unsafe fn copy_image_to_buffer<T>(
+        &mut self,
+        src: &n::Image,
+        _src_layout: image::Layout,
+        dst: &n::Buffer,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        self.commands.push(Command::CopyImageToBuffer {
+            src: src.as_bound().clone(),
+            dst: dst.as_bound().clone(),
+            regions: regions.into_iter().map(|r| r.borrow().clone()).collect(),
+        });
+    }
+
+    unsafe fn draw(
+        &mut self,
+        vertices: Range<hal::VertexCount>,
+        instances: Range<hal::InstanceCount>,
+    ) {
+        self.commands.push(Command::Draw {
+            state: self.graphics.clone(),
+            vertices: Vertices::Sequential(vertices),
+            instances,
+        });
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        indices: Range<hal::IndexCount>,
+        base_vertex: hal::VertexOffset,
+        instances: Range<hal::InstanceCount>,
+    ) {
+        self.commands.push(Command::Draw {
+            state: self.graphics.clone(),
+            vertices: Vertices::Indexed {
+                indices,
+                base_vertex,
+            },
+            instances,
+        });
+    }
+
+    unsafe fn draw_indirect(
+        &mut self,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    ) {
+        self.commands.push(Command::DrawIndirect {
+            state: self.graphics.clone(),
+            buffer: buffer.as_bound().clone(),
+            offset,
+            draw_count,
+            stride,
+            indexed: false,
+        });
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    ) {
+        self.commands.push(Command::DrawIndirect {
+            state: self.graphics.clone(),
+            buffer: buffer.as_bound().clone(),
+            offset,
+            draw_count,
+            stride,
+            indexed: true,
+        });
+    }
+
+    unsafe fn set_event(&mut self, event: &n::Event, _stages: pso::PipelineStage) {
+        self.commands.push(Command::SetEvent(event.clone(), true));
+    }
+
+    unsafe fn reset_event(&mut self, event: &n::Event, _stages: pso::PipelineStage) {
+        self.commands.push(Command::SetEvent(event.clone(), false));
+    }
+
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        _events: I,
+        _stages: Range<pso::PipelineStage>,
+        _barriers: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::Event>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        // Events are set by earlier commands or on the host before submission,
+        // and everything executes in submission order.
+    }
+
+    unsafe fn begin_query(&mut self, query: query::Query<Backend>, _flags: query::ControlFlags) {
+        // Only occlusion queries can be begun, and their counts are always precise.
+        self.commands
+            .push(Command::BeginQuery(query.pool.clone(), query.id));
+        self.graphics.occlusion_query = Some((query.pool.clone(), query.id));
+    }
+
+    unsafe fn end_query(&mut self, query: query::Query<Backend>) {
+        self.commands
+            .push(Command::EndQuery(query.pool.clone(), query.id));
+        self.graphics.occlusion_query = None;
+    }
+
+    unsafe fn reset_query_pool(&mut self, pool: &n::QueryPool, queries: Range<query::Id>) {
+        self.commands
+            .push(Command::ResetQueries(pool.clone(), queries));
+    }
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) {
+        self.commands.push(Command::CopyQueryResults {
+            pool: pool.clone(),
+            queries,
+            dst: buffer.as_bound().clone(),
+            offset,
+            stride,
+            flags,
+        });
+    }
+
+    unsafe fn write_timestamp(&mut self, _stage: pso::PipelineStage, query: query::Query<Backend>) {
+        self.commands
+            .push(Command::WriteTimestamp(query.pool.clone(), query.id));
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        _layout: &n::PipelineLayout,
+        _stages: pso::ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.graphics.bindings.push_constants(offset, constants);
+    }
+
+    unsafe fn push_compute_constants(
+        &mut self,
+        _layout: &n::PipelineLayout,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.compute.bindings.push_constants(offset, constants);
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, buffers: I)
+    where
+        T: 'a + Borrow<CommandBuffer>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        for buffer in buffers {
+            self.commands
+                .extend(buffer.borrow().commands.iter().cloned());
+        }
+    }
+
+    unsafe fn insert_debug_marker(&mut self, _name: &str, _color: u32) {}
+
+    unsafe fn begin_debug_marker(&mut self, _name: &str, _color: u32) {}
+
+    unsafe fn end_debug_marker(&mut self) {}
+}
+
+fn set_sided(
+    sided: &mut pso::Sided<pso::StencilValue>,
+    faces: pso::Face,
+    value: pso::StencilValue,
+) {
+    if faces.contains(pso::Face::FRONT) {
+        sided.front = value;
+    }
+    if faces.contains(pso::Face::BACK) {
+        sided.back = value;
+    }
+}
@@ -0,0 +1,99 @@
+//! Host execution of compute dispatches.
+
+use hal::buffer;
+
+use crate::binding::{Bindings, Resources};
+use crate::interp::{self, BuiltIn, Invocation, Status, Workgroup};
+use crate::native as n;
+
+/// Resources bound for the compute pipeline of a command buffer.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ComputeState {
+    pub(crate) pipeline: Option<n::ComputePipeline>,
+    pub(crate) bindings: Bindings,
+}
+
+/// Run all invocations of the workgroup `id`.
+fn run_workgroup(
+    pipeline: &n::ComputePipeline,
+    resources: &Resources,
+    id: [u32; 3],
+    count: hal::WorkGroupCount,
+) -> Result<(), interp::Error> {
+    let module = &*pipeline.module;
+    let size = pipeline.local_size;
+    let mut workgroup = Workgroup::new(module)?;
+
+    let mut invocations = Vec::new();
+    for z in 0 .. size[2] {
+        for y in 0 .. size[1] {
+            for x in 0 .. size[0] {
+                let mut invocation =
+                    Invocation::new(module, &pipeline.entry, resources, &workgroup)?;
+                let global = [
+                    id[0] * size[0] + x,
+                    id[1] * size[1] + y,
+                    id[2] * size[2] + z,
+                ];
+                let index = (z * size[1] + y) * size[0] + x;
+                invocation.set_builtin(BuiltIn::LocalInvocationId, &[x, y, z])?;
+                invocation.set_builtin(BuiltIn::GlobalInvocationId, &global)?;
+                invocation.set_builtin(BuiltIn::LocalInvocationIndex, &[index])?;
+                invocation.set_builtin(BuiltIn::WorkgroupId, &id)?;
+                invocation.set_builtin(BuiltIn::NumWorkgroups, &count)?;
+                invocations.push(invocation);
+            }
+        }
+    }
+
+    // Invocations run one after the other up to the next barrier, until all
+    // of them returned.
+    let mut waiting = invocations.len();
+    while waiting != 0 {
+        waiting = 0;
+        for invocation in &mut invocations {
+            if invocation.run(&mut workgroup)? == Status::Barrier {
+                waiting += 1;
+            }
+        }
+    }
+    Ok(())
+}
+
+pub(crate) fn dispatch(state: &ComputeState, count: hal::WorkGroupCount) {
+    let pipeline = match state.pipeline {
+        Some(ref pipeline) => pipeline,
+        None => {
+            error!("Dispatch without a bound compute pipeline");
+            return;
+        }
+    };
+    let resources = state.bindings.resources();
+
+    for z in 0 .. count[2] {
+        for y in 0 .. count[1] {
+            for x in 0 .. count[0] {
+                if let Err(err) = run_workgroup(pipeline, &resources, [x, y, z], count) {
+                    error!("Compute shader execution failed: {}", err);
+                    return;
+                }
+            }
+        }
+    }
+}
+
+pub(crate) unsafe fn dispatch_indirect(
+    state: &ComputeState,
+    buffer: &n::BoundBuffer,
+    offset: buffer::Offset,
+) {
+    let start = buffer.range.start + offset;
+    let bytes = buffer.block.slice(start .. start + 12);
+    let mut count = [0; 3];
+    for (count, chunk) in count.iter_mut().zip(bytes.chunks_exact(4)) {
+        let mut word = [0; 4];
+        word.copy_from_slice(chunk);
+        *count = u32::from_le_bytes(word);
+    }
+    dispatch(state, count);
+}
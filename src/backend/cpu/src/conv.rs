@@ -0,0 +1,351 @@
+use hal::command::ClearColor;
+use hal::format::{Aspects, ChannelType, Format, SurfaceType};
+
+/// Size of a texel block in bytes.
+pub fn block_size(format: Format) -> usize {
+    let desc = format.surface_desc();
+    (desc.bits as usize).div_ceil(8)
+}
+
+/// Placement of a single aspect inside a texel.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AspectLayout {
+    /// Byte offset of the aspect in the image texel.
+    pub offset: usize,
+    /// Number of bytes the aspect occupies in the image texel.
+    pub size: usize,
+    /// Number of bytes the aspect occupies in a buffer when copied.
+    pub buffer_size: usize,
+}
+
+/// Returns where the given aspects live inside a texel of `format`.
+///
+/// Copies between buffers and images only address one aspect of a combined
+/// depth-stencil format at a time, and use the tightly packed Vulkan
+/// representation of the aspect on the buffer side.
+pub fn aspect_layout(format: Format, aspects: Aspects) -> AspectLayout {
+    let texel = block_size(format);
+    let base = format.base_format().0;
+    let depth = match base {
+        SurfaceType::D16 | SurfaceType::D16_S8 => 2,
+        SurfaceType::X8D24 => 4,
+        SurfaceType::D24_S8 => 3,
+        SurfaceType::D32 | SurfaceType::D32_S8 => 4,
+        _ => 0,
+    };
+
+    if aspects == Aspects::DEPTH && depth != 0 {
+        AspectLayout {
+            offset: 0,
+            size: depth,
+            buffer_size: if depth == 3 { 4 } else { depth },
+        }
+    } else if aspects == Aspects::STENCIL && format.is_stencil() {
+        AspectLayout {
+            offset: depth,
+            size: 1,
+            buffer_size: 1,
+        }
+    } else {
+        AspectLayout {
+            offset: 0,
+            size: texel,
+            buffer_size: texel,
+        }
+    }
+}
+
+fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+
+    if exp == 0xFF {
+        // Infinity or NaN
+        let nan = if mantissa != 0 { 0x200 } else { 0 };
+        return sign | 0x7C00 | nan;
+    }
+    let exp = exp - 127 + 15;
+    if exp >= 0x1F {
+        sign | 0x7C00
+    } else if exp <= 0 {
+        if exp < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exp) as u32;
+        let round = 1 << (shift - 1);
+        sign | ((mantissa + round) >> shift) as u16
+    } else {
+        let half = sign as u32 | (exp as u32) << 10 | mantissa >> 13;
+        // round to nearest, may carry into the exponent
+        (half + ((mantissa >> 12) & 1)) as u16
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.003_130_8 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Encode a single channel of `bits` size.
+fn encode_channel(channel: ChannelType, bits: usize, value: f32, int: u32, out: &mut [u8]) {
+    let raw: u64 = match channel {
+        ChannelType::Unorm | ChannelType::Srgb => {
+            let max = ((1u64 << bits) - 1) as f32;
+            let value = if channel == ChannelType::Srgb {
+                linear_to_srgb(value)
+            } else {
+                value
+            };
+            (value.clamp(0.0, 1.0) * max).round() as u64
+        }
+        ChannelType::Snorm => {
+            let max = ((1u64 << (bits - 1)) - 1) as f32;
+            let v = (value.clamp(-1.0, 1.0) * max).round() as i64;
+            v as u64
+        }
+        ChannelType::Uscaled => value.max(0.0) as u64,
+        ChannelType::Sscaled => value as i64 as u64,
+        ChannelType::Uint | ChannelType::Sint => int as i32 as i64 as u64,
+        ChannelType::Ufloat | ChannelType::Sfloat => match bits {
+            16 => f32_to_f16(value) as u64,
+            32 => value.to_bits() as u64,
+            64 => (value as f64).to_bits(),
+            _ => unimplemented!("{}-bit float channel", bits),
+        },
+    };
+    out.copy_from_slice(&raw.to_le_bytes()[.. out.len()]);
+}
+
+/// Channel count, bits per channel and whether the first three channels are
+/// stored in BGR order, for formats the host can encode and decode.
+fn color_layout(format: Format) -> Option<(usize, usize, bool)> {
+    Some(match format.base_format().0 {
+        SurfaceType::R8 => (1, 8, false),
+        SurfaceType::R8_G8 => (2, 8, false),
+        SurfaceType::R8_G8_B8 => (3, 8, false),
+        SurfaceType::B8_G8_R8 => (3, 8, true),
+        SurfaceType::R8_G8_B8_A8 | SurfaceType::A8_B8_G8_R8 => (4, 8, false),
+        SurfaceType::B8_G8_R8_A8 => (4, 8, true),
+        SurfaceType::R16 => (1, 16, false),
+        SurfaceType::R16_G16 => (2, 16, false),
+        SurfaceType::R16_G16_B16 => (3, 16, false),
+        SurfaceType::R16_G16_B16_A16 => (4, 16, false),
+        SurfaceType::R32 => (1, 32, false),
+        SurfaceType::R32_G32 => (2, 32, false),
+        SurfaceType::R32_G32_B32 => (3, 32, false),
+        SurfaceType::R32_G32_B32_A32 => (4, 32, false),
+        _ => return None,
+    })
+}
+
+/// Whether texels of `format` can be encoded and decoded on the host.
+pub fn is_color_supported(format: Format) -> bool {
+    color_layout(format).is_some()
+}
+
+/// Alpha is never sRGB encoded.
+fn channel_type(channel: ChannelType, index: usize) -> ChannelType {
+    match channel {
+        ChannelType::Srgb if index == 3 => ChannelType::Unorm,
+        other => other,
+    }
+}
+
+/// Encode a clear color into the texel representation of `format`.
+///
+/// Returns `None` for formats which can't be cleared on the host.
+pub fn encode_color(format: Format, color: ClearColor) -> Option<Vec<u8>> {
+    let (count, bits, bgra) = color_layout(format)?;
+    let channel = format.base_format().1;
+    let (floats, ints) = unsafe { (color.float32, color.uint32) };
+    let bytes = bits / 8;
+    let mut texel = vec![0; count * bytes];
+    for i in 0 .. count {
+        let source = if bgra && i < 3 { 2 - i } else { i };
+        encode_channel(
+            channel_type(channel, source),
+            bits,
+            floats[source],
+            ints[source],
+            &mut texel[i * bytes .. (i + 1) * bytes],
+        );
+    }
+    Some(texel)
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = (half as u32 & 0x8000) << 16;
+    let exp = (half as u32 >> 10) & 0x1F;
+    let mantissa = half as u32 & 0x3FF;
+    let bits = match exp {
+        0 => {
+            // Zero or subnormal
+            let value = mantissa as f32 / (1 << 24) as f32;
+            return if sign != 0 { -value } else { value };
+        }
+        0x1F => sign | 0x7F80_0000 | mantissa << 13,
+        _ => sign | (exp + 127 - 15) << 23 | mantissa << 13,
+    };
+    f32::from_bits(bits)
+}
+
+fn srgb_to_linear(value: f32) -> f32 {
+    if value <= 0.040_45 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Decode a single channel of `bits` size into the bits of the value a
+/// shader sees: a float for normalized, scaled and float channels, and the
+/// integer for integer channels.
+fn decode_channel(channel: ChannelType, bits: usize, bytes: &[u8]) -> u32 {
+    let mut raw = [0; 8];
+    raw[.. bytes.len()].copy_from_slice(bytes);
+    let raw = u64::from_le_bytes(raw);
+    let signed = ((raw << (64 - bits)) as i64) >> (64 - bits);
+    match channel {
+        ChannelType::Unorm => (raw as f32 / ((1u64 << bits) - 1) as f32).to_bits(),
+        ChannelType::Srgb => srgb_to_linear(raw as f32 / ((1u64 << bits) - 1) as f32).to_bits(),
+        ChannelType::Snorm => {
+            let max = ((1u64 << (bits - 1)) - 1) as f32;
+            (signed as f32 / max).max(-1.0).to_bits()
+        }
+        ChannelType::Uscaled => (raw as f32).to_bits(),
+        ChannelType::Sscaled => (signed as f32).to_bits(),
+        ChannelType::Uint => raw as u32,
+        ChannelType::Sint => signed as u32,
+        ChannelType::Ufloat | ChannelType::Sfloat => match bits {
+            16 => f16_to_f32(raw as u16).to_bits(),
+            32 => raw as u32,
+            64 => (f64::from_bits(raw) as f32).to_bits(),
+            _ => unimplemented!("{}-bit float channel", bits),
+        },
+    }
+}
+
+/// Decode a texel of `format` into the four components a shader reads.
+///
+/// Missing components are filled in with `(0, 0, 0, 1)`. Returns `None` for
+/// formats which can't be decoded on the host.
+pub fn decode_color(format: Format, texel: &[u8]) -> Option<[u32; 4]> {
+    let (count, bits, bgra) = color_layout(format)?;
+    let channel = format.base_format().1;
+    let one = match channel {
+        ChannelType::Uint | ChannelType::Sint => 1,
+        _ => 1f32.to_bits(),
+    };
+    let bytes = bits / 8;
+    let mut color = [0, 0, 0, one];
+    for i in 0 .. count {
+        let target = if bgra && i < 3 { 2 - i } else { i };
+        color[target] = decode_channel(
+            channel_type(channel, target),
+            bits,
+            &texel[i * bytes .. (i + 1) * bytes],
+        );
+    }
+    Some(color)
+}
+
+/// Encode a depth value for the depth aspect of `format`.
+pub fn encode_depth(format: Format, depth: f32) -> Vec<u8> {
+    let depth = depth.clamp(0.0, 1.0);
+    match format.base_format().0 {
+        SurfaceType::D16 | SurfaceType::D16_S8 => {
+            ((depth * 65535.0).round() as u16).to_le_bytes().to_vec()
+        }
+        SurfaceType::X8D24 | SurfaceType::D24_S8 => {
+            let value = (depth * 16_777_215.0).round() as u32;
+            value.to_le_bytes()[.. 3].to_vec()
+        }
+        SurfaceType::D32 | SurfaceType::D32_S8 => depth.to_bits().to_le_bytes().to_vec(),
+        other => panic!("Surface type {:?} has no depth aspect", other),
+    }
+}
+
+/// Decode the depth aspect of a texel of `format`.
+pub fn decode_depth(format: Format, texel: &[u8]) -> f32 {
+    match format.base_format().0 {
+        SurfaceType::D16 | SurfaceType::D16_S8 => {
+            u16::from_le_bytes([texel[0], texel[1]]) as f32 / 65535.0
+        }
+        SurfaceType::X8D24 | SurfaceType::D24_S8 => {
+            u32::from_le_bytes([texel[0], texel[1], texel[2], 0]) as f32 / 16_777_215.0
+        }
+        SurfaceType::D32 | SurfaceType::D32_S8 => {
+            f32::from_le_bytes([texel[0], texel[1], texel[2], texel[3]])
+        }
+        other => panic!("Surface type {:?} has no depth aspect", other),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(f32_to_f16(0.0), 0);
+        assert_eq!(f32_to_f16(1.0), 0x3C00);
+        assert_eq!(f32_to_f16(-2.0), 0xC000);
+        assert_eq!(f32_to_f16(65504.0), 0x7BFF);
+        assert_eq!(f32_to_f16(1.0e6), 0x7C00);
+        assert_eq!(f32_to_f16(0.5), 0x3800);
+    }
+
+    #[test]
+    fn clear_colors() {
+        let color = ClearColor {
+            float32: [1.0, 0.5, 0.0, 1.0],
+        };
+        assert_eq!(
+            encode_color(Format::Rgba8Unorm, color),
+            Some(vec![255, 128, 0, 255])
+        );
+        assert_eq!(
+            encode_color(Format::Bgra8Unorm, color),
+            Some(vec![0, 128, 255, 255])
+        );
+        let color = ClearColor {
+            uint32: [7, 300, 0, 0],
+        };
+        assert_eq!(encode_color(Format::Rg8Uint, color), Some(vec![7, 44]));
+    }
+
+    #[test]
+    fn decode_colors() {
+        let one = 1f32.to_bits();
+        assert_eq!(
+            decode_color(Format::Bgra8Unorm, &[0, 0, 255, 255]),
+            Some([one, 0, 0, one])
+        );
+        assert_eq!(
+            decode_color(Format::Rg16Sfloat, &[0x00, 0x3C, 0x00, 0xC0]),
+            Some([one, (-2f32).to_bits(), 0, one])
+        );
+        assert_eq!(decode_color(Format::R8Sint, &[0xFF]), Some([!0, 0, 0, 1]));
+        let depth = encode_depth(Format::D24UnormS8Uint, 0.5);
+        assert!((decode_depth(Format::D24UnormS8Uint, &depth) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn depth_stencil_aspects() {
+        let depth = aspect_layout(Format::D24UnormS8Uint, Aspects::DEPTH);
+        assert_eq!((depth.offset, depth.size, depth.buffer_size), (0, 3, 4));
+        let stencil = aspect_layout(Format::D24UnormS8Uint, Aspects::STENCIL);
+        assert_eq!(
+            (stencil.offset, stencil.size, stencil.buffer_size),
+            (3, 1, 1)
+        );
+        let color = aspect_layout(Format::Rgba8Unorm, Aspects::COLOR);
+        assert_eq!((color.offset, color.size, color.buffer_size), (0, 4, 4));
+    }
+}
@@ -0,0 +1,1611 @@
+use hal::{
+    buffer,
+    device as d,
+    format,
+    image as i,
+    memory,
+    pass,
+    pool::CommandPoolCreateFlags,
+    pso,
+    query,
+    queue,
+    window,
+};
+
+use parking_lot::Mutex;
+
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::{thread, time};
+
+use crate::command::CommandBuffer;
+use crate::{
+    interp,
+    native as n,
+    pool::CommandPool,
+    Backend,
+    Share,
+    Surface,
+    Swapchain,
+    MEMORY_TYPES,
+};
+
+/// Alignment of buffer and image placements inside memory objects.
+const RESOURCE_ALIGNMENT: u64 = 16;
+
+#[derive(Debug)]
+pub struct Device {
+    share: Arc<Share>,
+}
+
+impl Device {
+    pub(crate) fn new(share: Arc<Share>) -> Self {
+        Device { share }
+    }
+
+    fn memory_type_mask(&self) -> u64 {
+        (1 << MEMORY_TYPES.len()) - 1
+    }
+
+    /// Resolve a mapped segment into a byte range of the memory.
+    fn segment_range(memory: &n::Memory, segment: &memory::Segment) -> Range<u64> {
+        let end = segment
+            .size
+            .map_or(memory.block.len(), |size| segment.offset + size);
+        segment.offset .. end.min(memory.block.len())
+    }
+
+    fn convert_descriptor<'a>(descriptor: &pso::Descriptor<'a, Backend>) -> n::Descriptor {
+        match *descriptor {
+            pso::Descriptor::Buffer(buffer, ref sub) => {
+                n::Descriptor::Buffer(buffer.as_bound().clone(), sub.clone())
+            }
+            pso::Descriptor::Sampler(_)
+            | pso::Descriptor::Image(..)
+            | pso::Descriptor::CombinedImageSampler(..)
+            | pso::Descriptor::TexelBuffer(_) => n::Descriptor::Unsupported,
+        }
+    }
+}
+
+/// Walks the descriptors of a set starting at the given binding and array element.
+///
+/// Like in Vulkan, running past the end of a binding continues with the next one.
+struct DescriptorCursor {
+    binding: usize,
+    element: usize,
+}
+
+impl DescriptorCursor {
+    fn next(&mut self, bindings: &[Vec<Option<n::Descriptor>>]) -> Option<(usize, usize)> {
+        while self.binding < bindings.len() && self.element >= bindings[self.binding].len() {
+            self.binding += 1;
+            self.element = 0;
+        }
+        if self.binding < bindings.len() {
+            let position = (self.binding, self.element);
+            self.element += 1;
+            Some(position)
+        } else {
+            None
+        }
+    }
+}
+
+/// Specialize the module of a pipeline shader and look up its entry point.
+fn shader_stage(
+    shader: &pso::EntryPoint<Backend>,
+    model: interp::ExecutionModel,
+) -> Result<n::ShaderStage, pso::CreationError> {
+    let module = shader
+        .module
+        .module
+        .specialize(&shader.specialization)
+        .map_err(|err| {
+            pso::CreationError::Shader(d::ShaderError::CompilationFailed(err.to_string()))
+        })?;
+    let entry = match module.entry_point(shader.entry) {
+        Some(entry) if entry.model == model => entry.clone(),
+        _ => {
+            return Err(pso::CreationError::Shader(
+                d::ShaderError::MissingEntryPoint(shader.entry.to_string()),
+            ))
+        }
+    };
+    Ok(n::ShaderStage {
+        module: Arc::new(module),
+        entry,
+    })
+}
+
+impl d::Device<Backend> for Device {
+    unsafe fn create_command_pool(
+        &self,
+        _family: queue::QueueFamilyId,
+        _flags: CommandPoolCreateFlags,
+    ) -> Result<CommandPool, d::OutOfMemory> {
+        Ok(CommandPool)
+    }
+
+    unsafe fn destroy_command_pool(&self, _pool: CommandPool) {}
+
+    unsafe fn allocate_memory(
+        &self,
+        mem_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<n::Memory, d::AllocationError> {
+        let memory_type = MEMORY_TYPES[mem_type.0];
+        if !self.share.reserve(memory_type.heap_index, size) {
+            return Err(d::OutOfMemory::Device.into());
+        }
+
+        Ok(n::Memory {
+            block: Arc::new(n::Block::new(size as usize)),
+            properties: memory_type.properties,
+            heap_index: memory_type.heap_index,
+            mapping: Mutex::new(None),
+        })
+    }
+
+    unsafe fn free_memory(&self, memory: n::Memory) {
+        self.share.release(memory.heap_index, memory.block.len());
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        _dependencies: ID,
+    ) -> Result<n::RenderPass, d::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        let subpasses = subpasses
+            .into_iter()
+            .map(|subpass| {
+                let subpass = subpass.borrow();
+                n::SubpassDesc {
+                    colors: subpass.colors.to_vec(),
+                    depth_stencil: subpass.depth_stencil.cloned(),
+                    resolves: subpass.resolves.to_vec(),
+                }
+            })
+            .collect();
+
+        Ok(n::RenderPass {
+            attachments: attachments
+                .into_iter()
+                .map(|attachment| attachment.borrow().clone())
+                .collect(),
+            subpasses,
+        })
+    }
+
+    unsafe fn destroy_render_pass(&self, _rp: n::RenderPass) {}
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        _set_layouts: IS,
+        _push_constant_ranges: IR,
+    ) -> Result<n::PipelineLayout, d::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<n::DescriptorSetLayout>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        Ok(n::PipelineLayout)
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, _layout: n::PipelineLayout) {}
+
+    unsafe fn create_pipeline_cache(&self, _data: Option<&[u8]>) -> Result<(), d::OutOfMemory> {
+        Ok(())
+    }
+
+    unsafe fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, d::OutOfMemory> {
+        Ok(Vec::new())
+    }
+
+    unsafe fn merge_pipeline_caches<I>(&self, _: &(), _: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        Ok(())
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _: ()) {}
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, Backend>,
+        _cache: Option<&()>,
+    ) -> Result<n::GraphicsPipeline, pso::CreationError> {
+        let shaders = &desc.shaders;
+        if shaders.hull.is_some() || shaders.domain.is_some() || shaders.geometry.is_some() {
+            error!("Only vertex and fragment shaders are supported");
+            return Err(pso::CreationError::Other);
+        }
+        let fragment = match shaders.fragment {
+            Some(ref entry) => Some(shader_stage(entry, interp::ExecutionModel::Fragment)?),
+            None => None,
+        };
+        Ok(n::GraphicsPipeline {
+            vertex: shader_stage(&shaders.vertex, interp::ExecutionModel::Vertex)?,
+            fragment,
+            vertex_buffers: desc.vertex_buffers.clone(),
+            attributes: desc.attributes.clone(),
+            input_assembler: desc.input_assembler.clone(),
+            rasterizer: desc.rasterizer,
+            blender: desc.blender.clone(),
+            depth_stencil: desc.depth_stencil,
+            baked_states: desc.baked_states.clone(),
+        })
+    }
+
+    unsafe fn destroy_graphics_pipeline(&self, _pipeline: n::GraphicsPipeline) {}
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, Backend>,
+        _cache: Option<&()>,
+    ) -> Result<n::ComputePipeline, pso::CreationError> {
+        let n::ShaderStage { module, entry } =
+            shader_stage(&desc.shader, interp::ExecutionModel::GLCompute)?;
+        Ok(n::ComputePipeline {
+            local_size: module.workgroup_size(&entry),
+            module,
+            entry,
+        })
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, _pipeline: n::ComputePipeline) {}
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _render_pass: &n::RenderPass,
+        attachments: I,
+        extent: i::Extent,
+    ) -> Result<n::Framebuffer, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView>,
+    {
+        Ok(n::Framebuffer {
+            attachments: attachments
+                .into_iter()
+                .map(|view| view.borrow().clone())
+                .collect(),
+            extent,
+        })
+    }
+
+    unsafe fn destroy_framebuffer(&self, _framebuffer: n::Framebuffer) {}
+
+    unsafe fn create_shader_module(
+        &self,
+        spirv: &[u32],
+    ) -> Result<n::ShaderModule, d::ShaderError> {
+        match interp::Module::parse(spirv) {
+            Ok(module) => Ok(n::ShaderModule {
+                module: Arc::new(module),
+            }),
+            Err(err) => Err(d::ShaderError::CompilationFailed(err.to_string())),
+        }
+    }
+
+    unsafe fn destroy_shader_module(&self, _module: n::ShaderModule) {}
+
+    unsafe fn create_sampler(
+        &self,
+        _desc: &i::SamplerDesc,
+    ) -> Result<n::Sampler, d::AllocationError> {
+        Ok(n::Sampler)
+    }
+
+    unsafe fn destroy_sampler(&self, _sampler: n::Sampler) {}
+
+    unsafe fn create_buffer(
+        &self,
+        size: u64,
+        usage: buffer::Usage,
+    ) -> Result<n::Buffer, buffer::CreationError> {
+        Ok(n::Buffer::Unbound { size, usage })
+    }
+
+    unsafe fn get_buffer_requirements(&self, buffer: &n::Buffer) -> memory::Requirements {
+        let size = match *buffer {
+            n::Buffer::Unbound { size, .. } => size,
+            n::Buffer::Bound { ref bound, .. } => bound.range.end - bound.range.start,
+        };
+        memory::Requirements {
+            size,
+            alignment: RESOURCE_ALIGNMENT,
+            type_mask: self.memory_type_mask(),
+        }
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        memory: &n::Memory,
+        offset: u64,
+        buffer: &mut n::Buffer,
+    ) -> Result<(), d::BindError> {
+        let (size, usage) = match *buffer {
+            n::Buffer::Unbound { size, usage } => (size, usage),
+            n::Buffer::Bound { .. } => panic!("Buffer is already bound"),
+        };
+        if offset + size > memory.block.len() {
+            return Err(d::BindError::OutOfBounds);
+        }
+
+        *buffer = n::Buffer::Bound {
+            bound: n::BoundBuffer {
+                block: memory.block.clone(),
+                range: offset .. offset + size,
+            },
+            usage,
+        };
+        Ok(())
+    }
+
+    unsafe fn destroy_buffer(&self, _buffer: n::Buffer) {}
+
+    unsafe fn create_buffer_view(
+        &self,
+        buffer: &n::Buffer,
+        _format: Option<format::Format>,
+        sub: buffer::SubRange,
+    ) -> Result<n::BufferView, buffer::ViewCreationError> {
+        buffer.as_bound().resolve(&sub);
+        Ok(n::BufferView)
+    }
+
+    unsafe fn destroy_buffer_view(&self, _view: n::BufferView) {}
+
+    unsafe fn create_image(
+        &self,
+        kind: i::Kind,
+        levels: i::Level,
+        format: format::Format,
+        _tiling: i::Tiling,
+        _usage: i::Usage,
+        _view_caps: i::ViewCapabilities,
+    ) -> Result<n::Image, i::CreationError> {
+        if kind.num_samples() > 1 {
+            return Err(i::CreationError::Samples(kind.num_samples()));
+        }
+
+        let desc = n::ImageDesc {
+            kind,
+            format,
+            levels,
+        };
+        Ok(n::Image {
+            desc,
+            requirements: memory::Requirements {
+                size: desc.size(),
+                alignment: RESOURCE_ALIGNMENT,
+                type_mask: self.memory_type_mask(),
+            },
+            bound: None,
+        })
+    }
+
+    unsafe fn get_image_requirements(&self, image: &n::Image) -> memory::Requirements {
+        image.requirements
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &n::Image,
+        sub: i::Subresource,
+    ) -> i::SubresourceFootprint {
+        let mut footprint = image.desc.footprint(sub.level);
+        let start = footprint.slice.start + sub.layer as u64 * footprint.array_pitch;
+        footprint.slice = start .. start + footprint.array_pitch;
+        footprint
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        memory: &n::Memory,
+        offset: u64,
+        image: &mut n::Image,
+    ) -> Result<(), d::BindError> {
+        assert!(image.bound.is_none(), "Image is already bound");
+        if offset + image.requirements.size > memory.block.len() {
+            return Err(d::BindError::OutOfBounds);
+        }
+
+        image.bound = Some(n::BoundImage {
+            block: memory.block.clone(),
+            offset,
+            desc: image.desc,
+        });
+        Ok(())
+    }
+
+    unsafe fn destroy_image(&self, _image: n::Image) {}
+
+    unsafe fn create_image_view(
+        &self,
+        image: &n::Image,
+        _kind: i::ViewKind,
+        format: format::Format,
+        _swizzle: format::Swizzle,
+        range: i::SubresourceRange,
+    ) -> Result<n::ImageView, i::ViewCreationError> {
+        if range.levels.end > image.desc.levels {
+            return Err(i::ViewCreationError::Level(range.levels.end));
+        }
+
+        Ok(n::ImageView {
+            image: image.as_bound().clone(),
+            format,
+            range,
+        })
+    }
+
+    unsafe fn destroy_image_view(&self, _view: n::ImageView) {}
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        _max_sets: usize,
+        _descriptor_ranges: I,
+        _flags: pso::DescriptorPoolCreateFlags,
+    ) -> Result<n::DescriptorPool, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        Ok(n::DescriptorPool)
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, _pool: n::DescriptorPool) {}
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        bindings: I,
+        _immutable_samplers: J,
+    ) -> Result<n::DescriptorSetLayout, d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<n::Sampler>,
+    {
+        let mut bindings = bindings
+            .into_iter()
+            .map(|binding| binding.borrow().clone())
+            .collect::<Vec<_>>();
+        bindings.sort_by_key(|binding| binding.binding);
+        Ok(n::DescriptorSetLayout { bindings })
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _layout: n::DescriptorSetLayout) {}
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, writes: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend>>,
+    {
+        for write in writes {
+            let binding = match write.set.binding_index(write.binding) {
+                Some(index) => index,
+                None => {
+                    error!(
+                        "Descriptor binding {} is not part of the layout",
+                        write.binding
+                    );
+                    continue;
+                }
+            };
+            let mut bindings = write.set.bindings.lock();
+            let mut cursor = DescriptorCursor {
+                binding,
+                element: write.array_offset,
+            };
+            for descriptor in write.descriptors {
+                let (binding, element) = cursor
+                    .next(&bindings)
+                    .expect("Descriptor write overflows the set");
+                bindings[binding][element] = Some(Self::convert_descriptor(descriptor.borrow()));
+            }
+        }
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, copies: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend>>,
+    {
+        for copy in copies {
+            let copy = copy.borrow();
+            let (src_binding, dst_binding) = match (
+                copy.src_set.binding_index(copy.src_binding),
+                copy.dst_set.binding_index(copy.dst_binding),
+            ) {
+                (Some(src), Some(dst)) => (src, dst),
+                _ => {
+                    error!("Descriptor copy refers to bindings outside of the layouts");
+                    continue;
+                }
+            };
+
+            // Collect first, since the source and destination may be the same set.
+            let descriptors = {
+                let bindings = copy.src_set.bindings.lock();
+                let mut cursor = DescriptorCursor {
+                    binding: src_binding,
+                    element: copy.src_array_offset,
+                };
+                (0 .. copy.count)
+                    .map(|_| {
+                        let (binding, element) = cursor
+                            .next(&bindings)
+                            .expect("Descriptor copy overflows the source set");
+                        bindings[binding][element].clone()
+                    })
+                    .collect::<Vec<_>>()
+            };
+
+            let mut bindings = copy.dst_set.bindings.lock();
+            let mut cursor = DescriptorCursor {
+                binding: dst_binding,
+                element: copy.dst_array_offset,
+            };
+            for descriptor in descriptors {
+                let (binding, element) = cursor
+                    .next(&bindings)
+                    .expect("Descriptor copy overflows the destination set");
+                bindings[binding][element] = descriptor;
+            }
+        }
+    }
+
+    unsafe fn map_memory(
+        &self,
+        memory: &n::Memory,
+        segment: memory::Segment,
+    ) -> Result<*mut u8, d::MapError> {
+        if !memory.properties.contains(memory::Properties::CPU_VISIBLE) {
+            return Err(d::MapError::MappingFailed);
+        }
+        let range = Self::segment_range(memory, &segment);
+        if segment.offset > memory.block.len() || range.end < range.start {
+            return Err(d::MapError::OutOfBounds);
+        }
+
+        if memory.properties.contains(memory::Properties::COHERENT) {
+            Ok(memory.block.as_ptr().add(range.start as usize))
+        } else {
+            let mut mapping = memory.mapping.lock();
+            let shadow =
+                mapping.get_or_insert_with(|| memory.block.slice(0 .. memory.block.len()).into());
+            Ok(shadow.as_mut_ptr().add(range.start as usize))
+        }
+    }
+
+    unsafe fn unmap_memory(&self, memory: &n::Memory) {
+        // Writes which haven't been flushed are lost, as they would be on a GPU.
+        *memory.mapping.lock() = None;
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory, memory::Segment)>,
+    {
+        for range in ranges {
+            let (memory, ref segment) = *range.borrow();
+            if let Some(ref shadow) = *memory.mapping.lock() {
+                let range = Self::segment_range(memory, segment);
+                memory
+                    .block
+                    .slice_mut(range.clone())
+                    .copy_from_slice(&shadow[range.start as usize .. range.end as usize]);
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), d::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory, memory::Segment)>,
+    {
+        for range in ranges {
+            let (memory, ref segment) = *range.borrow();
+            if let Some(ref mut shadow) = *memory.mapping.lock() {
+                let range = Self::segment_range(memory, segment);
+                shadow[range.start as usize .. range.end as usize]
+                    .copy_from_slice(memory.block.slice(range));
+            }
+        }
+        Ok(())
+    }
+
+    fn create_semaphore(&self) -> Result<n::Semaphore, d::OutOfMemory> {
+        Ok(n::Semaphore)
+    }
+
+    unsafe fn destroy_semaphore(&self, _semaphore: n::Semaphore) {}
+
+    fn create_fence(&self, signaled: bool) -> Result<n::Fence, d::OutOfMemory> {
+        Ok(n::Fence(AtomicBool::new(signaled)))
+    }
+
+    unsafe fn reset_fence(&self, fence: &n::Fence) -> Result<(), d::OutOfMemory> {
+        fence.set(false);
+        Ok(())
+    }
+
+    unsafe fn wait_for_fence(
+        &self,
+        fence: &n::Fence,
+        timeout_ns: u64,
+    ) -> Result<bool, d::OomOrDeviceLost> {
+        // The fence can only be signaled by a submission on another thread.
+        let start = time::Instant::now();
+        let timeout = time::Duration::from_nanos(timeout_ns);
+        while !fence.is_signaled() {
+            if start.elapsed() >= timeout {
+                return Ok(false);
+            }
+            thread::yield_now();
+        }
+        Ok(true)
+    }
+
+    unsafe fn get_fence_status(&self, fence: &n::Fence) -> Result<bool, d::DeviceLost> {
+        Ok(fence.is_signaled())
+    }
+
+    unsafe fn destroy_fence(&self, _fence: n::Fence) {}
+
+    fn create_event(&self) -> Result<n::Event, d::OutOfMemory> {
+        Ok(n::Event(Arc::new(AtomicBool::new(false))))
+    }
+
+    unsafe fn get_event_status(&self, event: &n::Event) -> Result<bool, d::OomOrDeviceLost> {
+        Ok(event.is_set())
+    }
+
+    unsafe fn set_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        event.set(true);
+        Ok(())
+    }
+
+    unsafe fn reset_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        event.set(false);
+        Ok(())
+    }
+
+    unsafe fn destroy_event(&self, _event: n::Event) {}
+
+    unsafe fn create_query_pool(
+        &self,
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool, query::CreationError> {
+        match ty {
+            query::Type::Occlusion | query::Type::Timestamp => Ok(n::QueryPool::new(count)),
+            // The rasterizer doesn't count pipeline statistics.
+            query::Type::PipelineStatistics(_) => Err(query::CreationError::Unsupported(ty)),
+        }
+    }
+
+    unsafe fn destroy_query_pool(&self, _pool: n::QueryPool) {}
+
+    unsafe fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> Result<bool, d::OomOrDeviceLost> {
+        Ok(pool.write_results(queries, data, stride, flags))
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut Surface,
+        _config: window::SwapchainConfig,
+        _old_swapchain: Option<Swapchain>,
+    ) -> Result<(Swapchain, Vec<n::Image>), window::CreationError> {
+        match *surface {}
+    }
+
+    unsafe fn destroy_swapchain(&self, swapchain: Swapchain) {
+        match swapchain {}
+    }
+
+    fn wait_idle(&self) -> Result<(), d::OutOfMemory> {
+        // Submissions are complete by the time `submit` returns.
+        Ok(())
+    }
+
+    unsafe fn set_image_name(&self, _image: &mut n::Image, _name: &str) {}
+
+    unsafe fn set_buffer_name(&self, _buffer: &mut n::Buffer, _name: &str) {}
+
+    unsafe fn set_command_buffer_name(&self, _command_buffer: &mut CommandBuffer, _name: &str) {}
+
+    unsafe fn set_semaphore_name(&self, _semaphore: &mut n::Semaphore, _name: &str) {}
+
+    unsafe fn set_fence_name(&self, _fence: &mut n::Fence, _name: &str) {}
+
+    unsafe fn set_framebuffer_name(&self, _framebuffer: &mut n::Framebuffer, _name: &str) {}
+
+    unsafe fn set_render_pass_name(&self, _render_pass: &mut n::RenderPass, _name: &str) {}
+
+    unsafe fn set_descriptor_set_name(&self, _descriptor_set: &mut n::DescriptorSet, _name: &str) {}
+
+    unsafe fn set_descriptor_set_layout_name(
+        &self,
+        _descriptor_set_layout: &mut n::DescriptorSetLayout,
+        _name: &str,
+    ) {
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hal::adapter::PhysicalDevice as _;
+    use hal::command::CommandBuffer as _;
+    use hal::device::Device as _;
+    use hal::pool::CommandPool as _;
+    use hal::pso::DescriptorPool as _;
+    use hal::queue::{CommandQueue as _, QueueFamily as _};
+    use hal::Instance as _;
+    use spirv_headers as spirv;
+
+    /// The `main` entry point name, as a SPIR-V literal string.
+    const MAIN: u32 = u32::from_le_bytes(*b"main");
+
+    /// Open the device with a single queue, and create a command pool for it.
+    fn open() -> (Device, crate::queue::CommandQueue, CommandPool) {
+        let instance = crate::Instance::create("test", 1).unwrap();
+        let adapter = instance.enumerate_adapters().remove(0);
+        let family = &adapter.queue_families[0];
+        let mut gpu = unsafe {
+            adapter
+                .physical_device
+                .open(&[(family, &[1.0])], hal::Features::empty())
+                .unwrap()
+        };
+        let queue = gpu.queue_groups.remove(0).queues.remove(0);
+        let pool = unsafe {
+            gpu.device
+                .create_command_pool(family.id(), CommandPoolCreateFlags::empty())
+                .unwrap()
+        };
+        (gpu.device, queue, pool)
+    }
+
+    /// Start a SPIR-V module whose ids are all below `bound`.
+    fn module(bound: u32) -> Vec<u32> {
+        vec![spirv::MAGIC_NUMBER, 0x0001_0000, 0, bound, 0]
+    }
+
+    /// Append an instruction to a SPIR-V module.
+    fn op(module: &mut Vec<u32>, op: spirv::Op, operands: &[u32]) {
+        module.push((operands.len() as u32 + 1) << 16 | op as u32);
+        module.extend_from_slice(operands);
+    }
+
+    /// Begin recording a one-time primary command buffer.
+    unsafe fn begin(pool: &mut CommandPool) -> CommandBuffer {
+        let mut cmd = pool.allocate_one(hal::command::Level::Primary);
+        cmd.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+        cmd
+    }
+
+    /// Finish the command buffer, submit it and wait for it to complete.
+    unsafe fn submit(
+        device: &Device,
+        queue: &mut crate::queue::CommandQueue,
+        mut cmd: CommandBuffer,
+    ) {
+        cmd.finish();
+        let fence = device.create_fence(false).unwrap();
+        queue.submit_without_semaphores(Some(&cmd), Some(&fence));
+        assert!(device.wait_for_fence(&fence, 0).unwrap());
+    }
+
+    #[test]
+    fn format_features() {
+        use hal::format::{BufferFeature as Bf, Format, ImageFeature as If};
+
+        let instance = crate::Instance::create("test", 1).unwrap();
+        let physical_device = instance.enumerate_adapters().remove(0).physical_device;
+        let properties = |format| physical_device.format_properties(Some(format));
+
+        let color = properties(Format::Rgba8Srgb);
+        assert!(color.optimal_tiling.contains(
+            If::COLOR_ATTACHMENT_BLEND | If::BLIT_SRC | If::BLIT_DST | If::SAMPLED_LINEAR
+        ));
+        assert!(!color.optimal_tiling.contains(If::SAMPLED));
+        assert_eq!(color.buffer_features, Bf::VERTEX);
+        let integer = properties(Format::R32Uint).optimal_tiling;
+        assert!(integer.contains(If::COLOR_ATTACHMENT));
+        assert!(!integer.intersects(If::COLOR_ATTACHMENT_BLEND | If::SAMPLED_LINEAR));
+        let depth = properties(Format::D24UnormS8Uint).optimal_tiling;
+        assert!(depth.contains(If::DEPTH_STENCIL_ATTACHMENT));
+        assert_eq!(
+            properties(Format::Bc1RgbUnorm),
+            format::Properties::default()
+        );
+    }
+
+    #[test]
+    fn transfer_round_trip() {
+        let (device, mut queue, mut command_pool) = open();
+
+        unsafe {
+            let memory = device.allocate_memory(hal::MemoryTypeId(0), 256).unwrap();
+            let mut buffer = device
+                .create_buffer(64, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+            let mut staging = device
+                .create_buffer(16, buffer::Usage::TRANSFER_SRC)
+                .unwrap();
+            device
+                .bind_buffer_memory(&memory, 64, &mut staging)
+                .unwrap();
+            let mut image = device
+                .create_image(
+                    i::Kind::D2(2, 2, 1, 1),
+                    1,
+                    format::Format::Rgba8Unorm,
+                    i::Tiling::Linear,
+                    i::Usage::TRANSFER_DST | i::Usage::TRANSFER_SRC,
+                    i::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            device.bind_image_memory(&memory, 128, &mut image).unwrap();
+
+            let mut cmd = begin(&mut command_pool);
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0x0101_0101);
+            cmd.update_buffer(
+                &staging,
+                0,
+                &[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16],
+            );
+            cmd.clear_image(
+                &image,
+                i::Layout::TransferDstOptimal,
+                hal::command::ClearValue {
+                    color: hal::command::ClearColor {
+                        float32: [1.0, 0.0, 0.0, 1.0],
+                    },
+                },
+                &[i::SubresourceRange {
+                    aspects: format::Aspects::COLOR,
+                    levels: 0 .. 1,
+                    layers: 0 .. 1,
+                }],
+            );
+            cmd.copy_buffer_to_image(
+                &staging,
+                &image,
+                i::Layout::TransferDstOptimal,
+                &[hal::command::BufferImageCopy {
+                    buffer_offset: 0,
+                    buffer_width: 0,
+                    buffer_height: 0,
+                    image_layers: i::SubresourceLayers {
+                        aspects: format::Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: i::Offset { x: 1, y: 0, z: 0 },
+                    image_extent: i::Extent {
+                        width: 1,
+                        height: 2,
+                        depth: 1,
+                    },
+                }],
+            );
+            cmd.copy_image_to_buffer(
+                &image,
+                i::Layout::TransferSrcOptimal,
+                &buffer,
+                &[hal::command::BufferImageCopy {
+                    buffer_offset: 16,
+                    buffer_width: 0,
+                    buffer_height: 0,
+                    image_layers: i::SubresourceLayers {
+                        aspects: format::Aspects::COLOR,
+                        level: 0,
+                        layers: 0 .. 1,
+                    },
+                    image_offset: i::Offset::ZERO,
+                    image_extent: i::Extent {
+                        width: 2,
+                        height: 2,
+                        depth: 1,
+                    },
+                }],
+            );
+            submit(&device, &mut queue, cmd);
+
+            let ptr = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            let data = std::slice::from_raw_parts(ptr, 64);
+            assert_eq!(&data[.. 16], &[1; 16]);
+            assert_eq!(
+                &data[16 .. 32],
+                &[255, 0, 0, 255, 1, 2, 3, 4, 255, 0, 0, 255, 5, 6, 7, 8]
+            );
+            assert_eq!(&data[32 ..], &[1; 32][..]);
+            device.unmap_memory(&memory);
+        }
+    }
+
+    #[test]
+    fn compute_fill() {
+        // layout(local_size_x = 1) in;
+        // layout(push_constant) uniform Push { uint value; };
+        // layout(set = 0, binding = 0) buffer Output { uint data[]; };
+        // void main() {
+        //     data[gl_GlobalInvocationID.x] = value + gl_GlobalInvocationID.x;
+        // }
+        let mut cs = module(26);
+        op(
+            &mut cs,
+            spirv::Op::Capability,
+            &[spirv::Capability::Shader as u32],
+        );
+        op(
+            &mut cs,
+            spirv::Op::MemoryModel,
+            &[0, spirv::MemoryModel::GLSL450 as u32],
+        );
+        op(
+            &mut cs,
+            spirv::Op::EntryPoint,
+            &[spirv::ExecutionModel::GLCompute as u32, 1, MAIN, 0, 7],
+        );
+        op(
+            &mut cs,
+            spirv::Op::ExecutionMode,
+            &[1, spirv::ExecutionMode::LocalSize as u32, 1, 1, 1],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[
+                7,
+                spirv::Decoration::BuiltIn as u32,
+                spirv::BuiltIn::GlobalInvocationId as u32,
+            ],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[8, spirv::Decoration::ArrayStride as u32, 4],
+        );
+        op(
+            &mut cs,
+            spirv::Op::MemberDecorate,
+            &[9, 0, spirv::Decoration::Offset as u32, 0],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[9, spirv::Decoration::BufferBlock as u32],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[11, spirv::Decoration::DescriptorSet as u32, 0],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[11, spirv::Decoration::Binding as u32, 0],
+        );
+        op(
+            &mut cs,
+            spirv::Op::MemberDecorate,
+            &[12, 0, spirv::Decoration::Offset as u32, 0],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Decorate,
+            &[12, spirv::Decoration::Block as u32],
+        );
+        op(&mut cs, spirv::Op::TypeVoid, &[2]);
+        op(&mut cs, spirv::Op::TypeFunction, &[3, 2]);
+        op(&mut cs, spirv::Op::TypeInt, &[4, 32, 0]);
+        op(&mut cs, spirv::Op::TypeVector, &[5, 4, 3]);
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[6, spirv::StorageClass::Input as u32, 5],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Variable,
+            &[6, 7, spirv::StorageClass::Input as u32],
+        );
+        op(&mut cs, spirv::Op::TypeRuntimeArray, &[8, 4]);
+        op(&mut cs, spirv::Op::TypeStruct, &[9, 8]);
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[10, spirv::StorageClass::Uniform as u32, 9],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Variable,
+            &[10, 11, spirv::StorageClass::Uniform as u32],
+        );
+        op(&mut cs, spirv::Op::TypeStruct, &[12, 4]);
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[13, spirv::StorageClass::PushConstant as u32, 12],
+        );
+        op(
+            &mut cs,
+            spirv::Op::Variable,
+            &[13, 14, spirv::StorageClass::PushConstant as u32],
+        );
+        op(&mut cs, spirv::Op::Constant, &[4, 15, 0]);
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[16, spirv::StorageClass::PushConstant as u32, 4],
+        );
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[17, spirv::StorageClass::Uniform as u32, 4],
+        );
+        op(
+            &mut cs,
+            spirv::Op::TypePointer,
+            &[18, spirv::StorageClass::Input as u32, 4],
+        );
+        op(&mut cs, spirv::Op::Function, &[2, 1, 0, 3]);
+        op(&mut cs, spirv::Op::Label, &[19]);
+        op(&mut cs, spirv::Op::AccessChain, &[18, 20, 7, 15]);
+        op(&mut cs, spirv::Op::Load, &[4, 21, 20]);
+        op(&mut cs, spirv::Op::AccessChain, &[16, 22, 14, 15]);
+        op(&mut cs, spirv::Op::Load, &[4, 23, 22]);
+        op(&mut cs, spirv::Op::IAdd, &[4, 24, 23, 21]);
+        op(&mut cs, spirv::Op::AccessChain, &[17, 25, 11, 15, 21]);
+        op(&mut cs, spirv::Op::Store, &[25, 24]);
+        op(&mut cs, spirv::Op::Return, &[]);
+        op(&mut cs, spirv::Op::FunctionEnd, &[]);
+
+        let (device, mut queue, mut command_pool) = open();
+
+        unsafe {
+            let memory = device.allocate_memory(hal::MemoryTypeId(0), 16).unwrap();
+            let mut buffer = device.create_buffer(16, buffer::Usage::STORAGE).unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+
+            let binding = pso::DescriptorSetLayoutBinding {
+                binding: 0,
+                ty: pso::DescriptorType::Buffer {
+                    ty: pso::BufferDescriptorType::Storage { read_only: false },
+                    format: pso::BufferDescriptorFormat::Structured {
+                        dynamic_offset: false,
+                    },
+                },
+                count: 1,
+                stage_flags: pso::ShaderStageFlags::COMPUTE,
+                immutable_samplers: false,
+            };
+            let set_layout = device
+                .create_descriptor_set_layout(Some(binding), None::<n::Sampler>)
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    1,
+                    None::<pso::DescriptorRangeDesc>,
+                    pso::DescriptorPoolCreateFlags::empty(),
+                )
+                .unwrap();
+            let set = pool.allocate_set(&set_layout).unwrap();
+            device.write_descriptor_sets(Some(pso::DescriptorSetWrite {
+                set: &set,
+                binding: 0,
+                array_offset: 0,
+                descriptors: Some(pso::Descriptor::Buffer(&buffer, buffer::SubRange::WHOLE)),
+            }));
+            let layout = device
+                .create_pipeline_layout(
+                    Some(&set_layout),
+                    &[(pso::ShaderStageFlags::COMPUTE, 0 .. 4)],
+                )
+                .unwrap();
+            let module = device.create_shader_module(&cs).unwrap();
+            let pipeline = device
+                .create_compute_pipeline(
+                    &pso::ComputePipelineDesc::new(
+                        pso::EntryPoint {
+                            entry: "main",
+                            module: &module,
+                            specialization: pso::Specialization::default(),
+                        },
+                        &layout,
+                    ),
+                    None,
+                )
+                .unwrap();
+
+            let mut cmd = begin(&mut command_pool);
+            cmd.bind_compute_pipeline(&pipeline);
+            cmd.bind_compute_descriptor_sets(&layout, 0, Some(&set), &[]);
+            cmd.push_compute_constants(&layout, 0, &[10]);
+            cmd.dispatch([3, 1, 1]);
+            submit(&device, &mut queue, cmd);
+
+            let ptr = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            let data = std::slice::from_raw_parts(ptr as *const u32, 4);
+            assert_eq!(data, &[10, 11, 12, 0]);
+            device.unmap_memory(&memory);
+        }
+    }
+
+    #[test]
+    fn draw_triangle() {
+        // layout(location = 0) in vec2 a_Pos;
+        // void main() {
+        //     gl_Position = vec4(a_Pos, 0.0, 1.0);
+        // }
+        let mut vs = module(18);
+        op(
+            &mut vs,
+            spirv::Op::Capability,
+            &[spirv::Capability::Shader as u32],
+        );
+        op(
+            &mut vs,
+            spirv::Op::MemoryModel,
+            &[0, spirv::MemoryModel::GLSL450 as u32],
+        );
+        op(
+            &mut vs,
+            spirv::Op::EntryPoint,
+            &[spirv::ExecutionModel::Vertex as u32, 1, MAIN, 0, 8, 10],
+        );
+        op(
+            &mut vs,
+            spirv::Op::Decorate,
+            &[8, spirv::Decoration::Location as u32, 0],
+        );
+        op(
+            &mut vs,
+            spirv::Op::Decorate,
+            &[
+                10,
+                spirv::Decoration::BuiltIn as u32,
+                spirv::BuiltIn::Position as u32,
+            ],
+        );
+        op(&mut vs, spirv::Op::TypeVoid, &[2]);
+        op(&mut vs, spirv::Op::TypeFunction, &[3, 2]);
+        op(&mut vs, spirv::Op::TypeFloat, &[4, 32]);
+        op(&mut vs, spirv::Op::TypeVector, &[5, 4, 2]);
+        op(&mut vs, spirv::Op::TypeVector, &[6, 4, 4]);
+        op(
+            &mut vs,
+            spirv::Op::TypePointer,
+            &[7, spirv::StorageClass::Input as u32, 5],
+        );
+        op(
+            &mut vs,
+            spirv::Op::Variable,
+            &[7, 8, spirv::StorageClass::Input as u32],
+        );
+        op(
+            &mut vs,
+            spirv::Op::TypePointer,
+            &[9, spirv::StorageClass::Output as u32, 6],
+        );
+        op(
+            &mut vs,
+            spirv::Op::Variable,
+            &[9, 10, spirv::StorageClass::Output as u32],
+        );
+        op(&mut vs, spirv::Op::Constant, &[4, 11, 0f32.to_bits()]);
+        op(&mut vs, spirv::Op::Constant, &[4, 12, 1f32.to_bits()]);
+        op(&mut vs, spirv::Op::Function, &[2, 1, 0, 3]);
+        op(&mut vs, spirv::Op::Label, &[13]);
+        op(&mut vs, spirv::Op::Load, &[5, 14, 8]);
+        op(&mut vs, spirv::Op::CompositeExtract, &[4, 15, 14, 0]);
+        op(&mut vs, spirv::Op::CompositeExtract, &[4, 16, 14, 1]);
+        op(
+            &mut vs,
+            spirv::Op::CompositeConstruct,
+            &[6, 17, 15, 16, 11, 12],
+        );
+        op(&mut vs, spirv::Op::Store, &[10, 17]);
+        op(&mut vs, spirv::Op::Return, &[]);
+        op(&mut vs, spirv::Op::FunctionEnd, &[]);
+
+        // layout(location = 0) out vec4 o_Color;
+        // void main() {
+        //     o_Color = vec4(1.0, 0.0, 0.0, 1.0);
+        // }
+        let mut fs = module(12);
+        op(
+            &mut fs,
+            spirv::Op::Capability,
+            &[spirv::Capability::Shader as u32],
+        );
+        op(
+            &mut fs,
+            spirv::Op::MemoryModel,
+            &[0, spirv::MemoryModel::GLSL450 as u32],
+        );
+        op(
+            &mut fs,
+            spirv::Op::EntryPoint,
+            &[spirv::ExecutionModel::Fragment as u32, 1, MAIN, 0, 7],
+        );
+        op(
+            &mut fs,
+            spirv::Op::ExecutionMode,
+            &[1, spirv::ExecutionMode::OriginUpperLeft as u32],
+        );
+        op(
+            &mut fs,
+            spirv::Op::Decorate,
+            &[7, spirv::Decoration::Location as u32, 0],
+        );
+        op(&mut fs, spirv::Op::TypeVoid, &[2]);
+        op(&mut fs, spirv::Op::TypeFunction, &[3, 2]);
+        op(&mut fs, spirv::Op::TypeFloat, &[4, 32]);
+        op(&mut fs, spirv::Op::TypeVector, &[5, 4, 4]);
+        op(
+            &mut fs,
+            spirv::Op::TypePointer,
+            &[6, spirv::StorageClass::Output as u32, 5],
+        );
+        op(
+            &mut fs,
+            spirv::Op::Variable,
+            &[6, 7, spirv::StorageClass::Output as u32],
+        );
+        op(&mut fs, spirv::Op::Constant, &[4, 8, 0f32.to_bits()]);
+        op(&mut fs, spirv::Op::Constant, &[4, 9, 1f32.to_bits()]);
+        op(&mut fs, spirv::Op::ConstantComposite, &[5, 10, 9, 8, 8, 9]);
+        op(&mut fs, spirv::Op::Function, &[2, 1, 0, 3]);
+        op(&mut fs, spirv::Op::Label, &[11]);
+        op(&mut fs, spirv::Op::Store, &[7, 10]);
+        op(&mut fs, spirv::Op::Return, &[]);
+        op(&mut fs, spirv::Op::FunctionEnd, &[]);
+
+        let (device, mut queue, mut command_pool) = open();
+
+        unsafe {
+            let memory = device.allocate_memory(hal::MemoryTypeId(0), 128).unwrap();
+            let mut vertex_buffer = device.create_buffer(24, buffer::Usage::VERTEX).unwrap();
+            device
+                .bind_buffer_memory(&memory, 0, &mut vertex_buffer)
+                .unwrap();
+            let mut image = device
+                .create_image(
+                    i::Kind::D2(4, 4, 1, 1),
+                    1,
+                    format::Format::Rgba8Unorm,
+                    i::Tiling::Linear,
+                    i::Usage::COLOR_ATTACHMENT,
+                    i::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            device.bind_image_memory(&memory, 32, &mut image).unwrap();
+            let view = device
+                .create_image_view(
+                    &image,
+                    i::ViewKind::D2,
+                    format::Format::Rgba8Unorm,
+                    format::Swizzle::NO,
+                    i::SubresourceRange {
+                        aspects: format::Aspects::COLOR,
+                        levels: 0 .. 1,
+                        layers: 0 .. 1,
+                    },
+                )
+                .unwrap();
+
+            let ptr = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            let positions = [-1.0f32, -1.0, 1.0, -1.0, -1.0, 1.0];
+            std::ptr::copy_nonoverlapping(positions.as_ptr(), ptr as *mut f32, positions.len());
+            device.unmap_memory(&memory);
+
+            let attachment = pass::Attachment {
+                format: Some(format::Format::Rgba8Unorm),
+                samples: 1,
+                ops: pass::AttachmentOps::new(
+                    pass::AttachmentLoadOp::Clear,
+                    pass::AttachmentStoreOp::Store,
+                ),
+                stencil_ops: pass::AttachmentOps::DONT_CARE,
+                layouts: i::Layout::General .. i::Layout::General,
+            };
+            let subpass = pass::SubpassDesc {
+                colors: &[(0, i::Layout::General)],
+                depth_stencil: None,
+                inputs: &[],
+                resolves: &[],
+                preserves: &[],
+            };
+            let render_pass = device
+                .create_render_pass(
+                    Some(attachment),
+                    Some(subpass),
+                    None::<pass::SubpassDependency>,
+                )
+                .unwrap();
+            let extent = i::Extent {
+                width: 4,
+                height: 4,
+                depth: 1,
+            };
+            let framebuffer = device
+                .create_framebuffer(&render_pass, Some(&view), extent)
+                .unwrap();
+
+            let layout = device
+                .create_pipeline_layout(None::<&n::DescriptorSetLayout>, &[])
+                .unwrap();
+            let vs = device.create_shader_module(&vs).unwrap();
+            let fs = device.create_shader_module(&fs).unwrap();
+            let entry = |module| pso::EntryPoint {
+                entry: "main",
+                module,
+                specialization: pso::Specialization::default(),
+            };
+            let mut desc = pso::GraphicsPipelineDesc::new(
+                pso::GraphicsShaderSet {
+                    vertex: entry(&vs),
+                    hull: None,
+                    domain: None,
+                    geometry: None,
+                    fragment: Some(entry(&fs)),
+                },
+                pso::Primitive::TriangleList,
+                pso::Rasterizer::FILL,
+                &layout,
+                pass::Subpass {
+                    index: 0,
+                    main_pass: &render_pass,
+                },
+            );
+            desc.vertex_buffers.push(pso::VertexBufferDesc {
+                binding: 0,
+                stride: 8,
+                rate: pso::VertexInputRate::Vertex,
+            });
+            desc.attributes.push(pso::AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: pso::Element {
+                    format: format::Format::Rg32Sfloat,
+                    offset: 0,
+                },
+            });
+            desc.blender.targets.push(pso::ColorBlendDesc::EMPTY);
+            let rect = pso::Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            };
+            desc.baked_states.viewport = Some(pso::Viewport {
+                rect,
+                depth: 0.0 .. 1.0,
+            });
+            let pipeline = device.create_graphics_pipeline(&desc, None).unwrap();
+
+            let queries = device.create_query_pool(query::Type::Occlusion, 1).unwrap();
+            let mut cmd = begin(&mut command_pool);
+            cmd.reset_query_pool(&queries, 0 .. 1);
+            cmd.begin_render_pass(
+                &render_pass,
+                &framebuffer,
+                rect,
+                [hal::command::ClearValue {
+                    color: hal::command::ClearColor {
+                        float32: [0.0, 0.0, 1.0, 1.0],
+                    },
+                }],
+                hal::command::SubpassContents::Inline,
+            );
+            cmd.bind_graphics_pipeline(&pipeline);
+            cmd.bind_vertex_buffers(0, Some((&vertex_buffer, buffer::SubRange::WHOLE)));
+            let occlusion = query::Query {
+                pool: &queries,
+                id: 0,
+            };
+            cmd.begin_query(occlusion, query::ControlFlags::PRECISE);
+            cmd.draw(0 .. 3, 0 .. 1);
+            cmd.end_query(query::Query {
+                pool: &queries,
+                id: 0,
+            });
+            cmd.end_render_pass();
+            submit(&device, &mut queue, cmd);
+
+            // The triangle covers the pixel centres above the diagonal, the
+            // ones lying on it belong to the right edge and are not covered.
+            let ptr = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            let pixels = std::slice::from_raw_parts((ptr as *const u32).add(8), 16);
+            let (red, blue) = (0xFF00_00FF, 0xFFFF_0000);
+            for y in 0 .. 4 {
+                for x in 0 .. 4 {
+                    let expected = if x + y < 3 { red } else { blue };
+                    assert_eq!(pixels[y * 4 + x], expected, "pixel ({}, {})", x, y);
+                }
+            }
+            device.unmap_memory(&memory);
+
+            let mut samples = [0; 8];
+            let available = device
+                .get_query_pool_results(
+                    &queries,
+                    0 .. 1,
+                    &mut samples,
+                    8,
+                    query::ResultFlags::BITS_64,
+                )
+                .unwrap();
+            assert!(available);
+            assert_eq!(u64::from_le_bytes(samples), 6);
+        }
+    }
+
+    #[test]
+    fn blit_and_timestamps() {
+        let (device, mut queue, mut command_pool) = open();
+
+        unsafe {
+            let memory = device.allocate_memory(hal::MemoryTypeId(0), 256).unwrap();
+            let mut staging = device
+                .create_buffer(
+                    64,
+                    buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+                )
+                .unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut staging).unwrap();
+            let mut images = Vec::new();
+            for (i, &width) in [2, 3].iter().enumerate() {
+                let mut image = device
+                    .create_image(
+                        i::Kind::D2(width, 1, 1, 1),
+                        1,
+                        format::Format::Rgba8Unorm,
+                        i::Tiling::Linear,
+                        i::Usage::TRANSFER_DST | i::Usage::TRANSFER_SRC,
+                        i::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                device
+                    .bind_image_memory(&memory, 64 + 64 * i as u64, &mut image)
+                    .unwrap();
+                images.push(image);
+            }
+            let queries = device.create_query_pool(query::Type::Timestamp, 2).unwrap();
+
+            let layers = i::SubresourceLayers {
+                aspects: format::Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            };
+            let bounds = |start: i32, end: i32| {
+                i::Offset {
+                    x: start,
+                    y: 0,
+                    z: 0,
+                } .. i::Offset { x: end, y: 1, z: 1 }
+            };
+            let buffer_copy = |width: u32| hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers: layers.clone(),
+                image_offset: i::Offset::ZERO,
+                image_extent: i::Extent {
+                    width,
+                    height: 1,
+                    depth: 1,
+                },
+            };
+
+            let mut cmd = begin(&mut command_pool);
+            cmd.reset_query_pool(&queries, 0 .. 2);
+            cmd.write_timestamp(
+                pso::PipelineStage::TOP_OF_PIPE,
+                query::Query {
+                    pool: &queries,
+                    id: 0,
+                },
+            );
+            cmd.update_buffer(&staging, 0, &[0, 0, 0, 255, 255, 255, 255, 255]);
+            cmd.copy_buffer_to_image(
+                &staging,
+                &images[0],
+                i::Layout::TransferDstOptimal,
+                &[buffer_copy(2)],
+            );
+            // Downscale both texels into the first one, then mirror them into the others.
+            cmd.blit_image(
+                &images[0],
+                i::Layout::TransferSrcOptimal,
+                &images[1],
+                i::Layout::TransferDstOptimal,
+                i::Filter::Linear,
+                &[
+                    hal::command::ImageBlit {
+                        src_subresource: layers.clone(),
+                        src_bounds: bounds(0, 2),
+                        dst_subresource: layers.clone(),
+                        dst_bounds: bounds(0, 1),
+                    },
+                    hal::command::ImageBlit {
+                        src_subresource: layers.clone(),
+                        src_bounds: bounds(0, 2),
+                        dst_subresource: layers.clone(),
+                        dst_bounds: bounds(3, 1),
+                    },
+                ],
+            );
+            cmd.copy_image_to_buffer(
+                &images[1],
+                i::Layout::TransferSrcOptimal,
+                &staging,
+                &[buffer_copy(3)],
+            );
+            cmd.write_timestamp(
+                pso::PipelineStage::BOTTOM_OF_PIPE,
+                query::Query {
+                    pool: &queries,
+                    id: 1,
+                },
+            );
+            cmd.copy_query_pool_results(
+                &queries,
+                0 .. 2,
+                &staging,
+                16,
+                16,
+                query::ResultFlags::BITS_64 | query::ResultFlags::WITH_AVAILABILITY,
+            );
+            submit(&device, &mut queue, cmd);
+
+            let ptr = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            let data = std::slice::from_raw_parts(ptr, 48);
+            assert_eq!(
+                &data[.. 12],
+                &[128, 128, 128, 255, 255, 255, 255, 255, 0, 0, 0, 255]
+            );
+            let word = |offset: usize| {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(&data[offset .. offset + 8]);
+                u64::from_le_bytes(bytes)
+            };
+            assert!(word(16) <= word(32));
+            assert_eq!((word(24), word(40)), (1, 1));
+            device.unmap_memory(&memory);
+        }
+    }
+}
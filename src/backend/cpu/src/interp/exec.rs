@@ -0,0 +1,1200 @@
+use spirv_headers::{BuiltIn, Op, StorageClass};
+
+use std::collections::HashMap;
+use std::ops::Range;
+use std::sync::Arc;
+
+use super::module::{EntryPoint, Function, Id, Instruction, Module, Type};
+use super::value::{map1, map2, map3, MemoryPointer, Pointer};
+use super::{glsl, Error, Value};
+use crate::native::Block;
+
+/// Memory accessible to the shader invocations.
+pub trait Resources {
+    /// Memory range of the buffer descriptor `index` of `binding` in `set`.
+    fn buffer(&self, set: u32, binding: u32, index: u32) -> Option<(Arc<Block>, Range<u64>)>;
+    /// Push constant data.
+    fn push_constants(&self) -> Option<Arc<Block>>;
+}
+
+/// Reason an invocation stopped running.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    /// The invocation reached a control barrier and can be resumed once all
+    /// invocations of the workgroup did so.
+    Barrier,
+    /// The entry point returned.
+    Done,
+    /// The invocation got killed by `OpKill`.
+    Killed,
+}
+
+/// Variables shared by the invocations of a workgroup.
+#[derive(Debug)]
+pub struct Workgroup {
+    values: Vec<Value>,
+    slots: HashMap<Id, usize>,
+}
+
+impl Workgroup {
+    pub fn new(module: &Module) -> Result<Self, Error> {
+        let mut workgroup = Workgroup {
+            values: Vec::new(),
+            slots: HashMap::new(),
+        };
+        for (&id, variable) in &module.variables {
+            if variable.class == StorageClass::Workgroup {
+                workgroup.slots.insert(id, workgroup.values.len());
+                workgroup.values.push(module.zero(variable.ty)?);
+            }
+        }
+        Ok(workgroup)
+    }
+}
+
+#[derive(Debug)]
+struct Frame<'m> {
+    function: &'m Function,
+    block: usize,
+    instruction: usize,
+    values: HashMap<Id, Value>,
+    /// Id receiving the return value in the calling frame.
+    result: Id,
+    /// Length of the invocation storage when the function got called, the
+    /// function variables get released on return.
+    storage: usize,
+}
+
+/// A single shader invocation.
+#[derive(Debug)]
+pub struct Invocation<'m> {
+    module: &'m Module,
+    frames: Vec<Frame<'m>>,
+    /// Contents of the variables owned by the invocation.
+    storage: Vec<Value>,
+    /// Pointers to the module-level variables.
+    globals: HashMap<Id, Value>,
+}
+
+/// Read the 32-bit scalars of a value of type `ty` from `words`.
+///
+/// Missing words leave the corresponding scalars zeroed.
+fn from_words(
+    module: &Module,
+    ty: Id,
+    words: &mut dyn Iterator<Item = u32>,
+) -> Result<Value, Error> {
+    Ok(match *module.ty(ty)? {
+        Type::Bool => Value::Bool(words.next().unwrap_or(0) != 0),
+        Type::Int { .. } => Value::Int(words.next().unwrap_or(0)),
+        Type::Float => Value::Float(f32::from_bits(words.next().unwrap_or(0))),
+        Type::Vector { component, count } => Value::Composite(
+            (0 .. count)
+                .map(|_| from_words(module, component, words))
+                .collect::<Result<_, _>>()?,
+        ),
+        Type::Matrix {
+            column: element,
+            count,
+        }
+        | Type::Array {
+            element,
+            length: count,
+        } => Value::Composite(
+            (0 .. count)
+                .map(|_| from_words(module, element, words))
+                .collect::<Result<_, _>>()?,
+        ),
+        Type::Struct { ref members } => Value::Composite(
+            members
+                .iter()
+                .map(|&member| from_words(module, member, words))
+                .collect::<Result<_, _>>()?,
+        ),
+        ref other => return Err(invalid!("{:?} in the shader interface", other)),
+    })
+}
+
+fn float_binary(a: &Value, b: &Value, f: fn(f32, f32) -> f32) -> Result<Value, Error> {
+    map2(a, b, &|a, b| {
+        Ok(Value::Float(f(a.as_float()?, b.as_float()?)))
+    })
+}
+
+fn int_binary(a: &Value, b: &Value, f: fn(u32, u32) -> u32) -> Result<Value, Error> {
+    map2(a, b, &|a, b| Ok(Value::Int(f(a.as_int()?, b.as_int()?))))
+}
+
+fn int_compare(a: &Value, b: &Value, f: fn(u32, u32) -> bool) -> Result<Value, Error> {
+    map2(a, b, &|a, b| Ok(Value::Bool(f(a.as_int()?, b.as_int()?))))
+}
+
+/// Compare floats, `unordered` is the result when either operand is NaN.
+fn float_compare(
+    a: &Value,
+    b: &Value,
+    unordered: bool,
+    f: fn(f32, f32) -> bool,
+) -> Result<Value, Error> {
+    map2(a, b, &|a, b| {
+        let (a, b) = (a.as_float()?, b.as_float()?);
+        Ok(Value::Bool(if a.is_nan() || b.is_nan() {
+            unordered
+        } else {
+            f(a, b)
+        }))
+    })
+}
+
+fn bool_binary(a: &Value, b: &Value, f: fn(bool, bool) -> bool) -> Result<Value, Error> {
+    map2(a, b, &|a, b| Ok(Value::Bool(f(a.as_bool()?, b.as_bool()?))))
+}
+
+fn bits(value: &Value) -> Result<u32, Error> {
+    match *value {
+        Value::Bool(value) => Ok(value as u32),
+        Value::Int(value) => Ok(value),
+        Value::Float(value) => Ok(value.to_bits()),
+        ref other => Err(invalid!("expected a scalar, got {:?}", other)),
+    }
+}
+
+fn signed_div(a: u32, b: u32) -> u32 {
+    // Division by zero is undefined, return zero instead of panicking.
+    (a as i32).checked_div(b as i32).unwrap_or(0) as u32
+}
+
+fn signed_rem(a: u32, b: u32) -> u32 {
+    (a as i32).checked_rem(b as i32).unwrap_or(0) as u32
+}
+
+fn signed_mod(a: u32, b: u32) -> u32 {
+    let (a, b) = (a as i32, b as i32);
+    match a.checked_rem(b) {
+        Some(rem) if rem != 0 && (rem < 0) != (b < 0) => rem.wrapping_add(b) as u32,
+        Some(rem) => rem as u32,
+        None => 0,
+    }
+}
+
+fn matrix_times_vector(matrix: &Value, vector: &Value) -> Result<Value, Error> {
+    let columns = matrix.components()?;
+    let vector = glsl::floats(vector)?;
+    let rows = columns
+        .first()
+        .map_or(Ok(0), |column| column.components().map(<[_]>::len))?;
+    let mut result = vec![0.0; rows];
+    for (column, factor) in columns.iter().zip(vector) {
+        for (row, value) in result.iter_mut().zip(glsl::floats(column)?) {
+            *row += value * factor;
+        }
+    }
+    Ok(Value::Composite(
+        result.into_iter().map(Value::Float).collect(),
+    ))
+}
+
+impl<'m> Invocation<'m> {
+    /// Prepare an invocation of `entry`, bound to the given resources.
+    pub fn new(
+        module: &'m Module,
+        entry: &EntryPoint,
+        resources: &dyn Resources,
+        workgroup: &Workgroup,
+    ) -> Result<Self, Error> {
+        let mut invocation = Invocation {
+            module,
+            frames: Vec::new(),
+            storage: Vec::new(),
+            globals: HashMap::new(),
+        };
+
+        for (&id, variable) in &module.variables {
+            let pointer = match variable.class {
+                StorageClass::Input
+                | StorageClass::Output
+                | StorageClass::Private
+                | StorageClass::Function => {
+                    let value = match variable.initializer {
+                        Some(initializer) => invocation.value(initializer)?,
+                        None => module.zero(variable.ty)?,
+                    };
+                    invocation.storage.push(value);
+                    Pointer::Private {
+                        slot: invocation.storage.len() - 1,
+                        path: Vec::new(),
+                    }
+                }
+                StorageClass::Workgroup => Pointer::Shared {
+                    slot: workgroup.slots[&id],
+                    path: Vec::new(),
+                },
+                StorageClass::Uniform | StorageClass::StorageBuffer => {
+                    let decorations = module.decorations(id);
+                    let set = decorations.and_then(|d| d.set).unwrap_or(0);
+                    let binding = decorations
+                        .and_then(|d| d.binding)
+                        .ok_or_else(|| invalid!("buffer variable %{} has no binding", id))?;
+                    let buffer = |ty, index| {
+                        resources.buffer(set, binding, index).map(|(block, range)| {
+                            Value::Pointer(Pointer::Memory(MemoryPointer {
+                                block,
+                                offset: range.start,
+                                end: range.end,
+                                ty,
+                                matrix: None,
+                            }))
+                        })
+                    };
+                    match *module.ty(variable.ty)? {
+                        Type::Array { element, length } => {
+                            let descriptors = (0 .. length)
+                                .map(|i| buffer(element, i).unwrap_or(Value::Undef))
+                                .collect();
+                            invocation.storage.push(Value::Composite(descriptors));
+                            Pointer::Descriptors {
+                                slot: invocation.storage.len() - 1,
+                            }
+                        }
+                        Type::RuntimeArray { .. } => {
+                            return Err(unsupported!("run-time arrays of buffers"))
+                        }
+                        // Unbound buffers are only an error once accessed.
+                        _ => match buffer(variable.ty, 0) {
+                            Some(Value::Pointer(pointer)) => pointer,
+                            _ => continue,
+                        },
+                    }
+                }
+                StorageClass::PushConstant => match resources.push_constants() {
+                    Some(block) => Pointer::Memory(MemoryPointer {
+                        end: block.len(),
+                        block,
+                        offset: 0,
+                        ty: variable.ty,
+                        matrix: None,
+                    }),
+                    None => continue,
+                },
+                // Images and samplers are handled by the instructions using them.
+                _ => continue,
+            };
+            invocation.globals.insert(id, Value::Pointer(pointer));
+        }
+
+        let function = module.function(entry.function)?;
+        invocation.frames.push(Frame {
+            function,
+            block: 0,
+            instruction: 0,
+            values: HashMap::new(),
+            result: 0,
+            storage: invocation.storage.len(),
+        });
+        Ok(invocation)
+    }
+
+    /// Set the input variable decorated with `builtin`, if the entry point
+    /// uses it.
+    pub fn set_builtin(&mut self, builtin: BuiltIn, words: &[u32]) -> Result<(), Error> {
+        let module = self.module;
+        let variable = module.variables.iter().find(|&(&id, variable)| {
+            variable.class == StorageClass::Input
+                && module.decorations(id).and_then(|d| d.builtin) == Some(builtin)
+        });
+        if let Some((&id, variable)) = variable {
+            let value = from_words(module, variable.ty, &mut words.iter().cloned())?;
+            self.store(&Value::Pointer(self.global_pointer(id)?), value, None)?;
+        }
+        Ok(())
+    }
+
+    /// Set the input variable at `location`, if the entry point uses it.
+    pub fn set_input(&mut self, location: u32, words: &[u32]) -> Result<(), Error> {
+        let module = self.module;
+        if let Some((id, ty)) = module.interface_variable(StorageClass::Input, location) {
+            let value = from_words(module, ty, &mut words.iter().cloned())?;
+            self.store(&Value::Pointer(self.global_pointer(id)?), value, None)?;
+        }
+        Ok(())
+    }
+
+    /// Value written to the output decorated with `builtin`, either a
+    /// variable or a member of an output block.
+    pub fn builtin_output(&self, builtin: BuiltIn) -> Result<Option<Vec<u32>>, Error> {
+        let module = self.module;
+        for (&id, variable) in &module.variables {
+            if variable.class != StorageClass::Output {
+                continue;
+            }
+            let pointer = self.global_pointer(id)?;
+            if module.decorations(id).and_then(|d| d.builtin) == Some(builtin) {
+                return Ok(Some(self.load(&Value::Pointer(pointer), None)?.flatten()));
+            }
+            if let Type::Struct { ref members } = *module.ty(variable.ty)? {
+                for member in 0 .. members.len() as u32 {
+                    let decorations = module.member_decorations(variable.ty, member);
+                    if decorations.and_then(|d| d.builtin) == Some(builtin) {
+                        let pointer = self.access(&pointer, &[member])?;
+                        return Ok(Some(self.load(&Value::Pointer(pointer), None)?.flatten()));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// Values of the output variables with a location, ordered by location.
+    pub fn outputs(&self) -> Result<Vec<(u32, Vec<u32>)>, Error> {
+        let module = self.module;
+        let mut outputs = Vec::new();
+        for (&id, variable) in &module.variables {
+            let location = module.decorations(id).and_then(|d| d.location);
+            if let (StorageClass::Output, Some(location)) = (variable.class, location) {
+                let value = self.load(&Value::Pointer(self.global_pointer(id)?), None)?;
+                outputs.push((location, value.flatten()));
+            }
+        }
+        outputs.sort_by_key(|&(location, _)| location);
+        Ok(outputs)
+    }
+
+    fn global_pointer(&self, id: Id) -> Result<Pointer, Error> {
+        match self.globals.get(&id) {
+            Some(Value::Pointer(pointer)) => Ok(pointer.clone()),
+            _ => Err(invalid!("variable %{} is not bound", id)),
+        }
+    }
+
+    fn frame(&self) -> Result<&Frame<'m>, Error> {
+        self.frames
+            .last()
+            .ok_or_else(|| invalid!("invocation is not running"))
+    }
+
+    fn frame_mut(&mut self) -> Result<&mut Frame<'m>, Error> {
+        self.frames
+            .last_mut()
+            .ok_or_else(|| invalid!("invocation is not running"))
+    }
+
+    fn value(&self, id: Id) -> Result<Value, Error> {
+        if let Some(value) = self.frames.last().and_then(|frame| frame.values.get(&id)) {
+            return Ok(value.clone());
+        }
+        self.module
+            .constants
+            .get(&id)
+            .or_else(|| self.globals.get(&id))
+            .cloned()
+            .ok_or_else(|| invalid!("value %{} is not defined", id))
+    }
+
+    fn define(&mut self, id: Id, value: Value) -> Result<(), Error> {
+        self.frame_mut()?.values.insert(id, value);
+        Ok(())
+    }
+
+    /// Type of the scalar components of `ty`.
+    fn scalar_type(&self, ty: Id) -> Result<&'m Type, Error> {
+        let module = self.module;
+        match *module.ty(ty)? {
+            Type::Vector { component, .. } => module.ty(component),
+            ref scalar => Ok(scalar),
+        }
+    }
+
+    fn load(&self, pointer: &Value, workgroup: Option<&Workgroup>) -> Result<Value, Error> {
+        match *pointer.as_pointer()? {
+            Pointer::Private { slot, ref path } => self.storage[slot].member(path).cloned(),
+            Pointer::Shared { slot, ref path } => workgroup
+                .ok_or_else(|| invalid!("workgroup variable outside of a compute shader"))?
+                .values[slot]
+                .member(path)
+                .cloned(),
+            Pointer::Memory(ref pointer) => pointer.load(self.module),
+            Pointer::Descriptors { .. } => Err(invalid!("loading an array of descriptors")),
+        }
+    }
+
+    fn store(
+        &mut self,
+        pointer: &Value,
+        value: Value,
+        workgroup: Option<&mut Workgroup>,
+    ) -> Result<(), Error> {
+        match *pointer.as_pointer()? {
+            Pointer::Private { slot, ref path } => *self.storage[slot].member_mut(path)? = value,
+            Pointer::Shared { slot, ref path } => {
+                *workgroup
+                    .ok_or_else(|| invalid!("workgroup variable outside of a compute shader"))?
+                    .values[slot]
+                    .member_mut(path)? = value
+            }
+            Pointer::Memory(ref pointer) => pointer.store(self.module, &value)?,
+            Pointer::Descriptors { .. } => {
+                return Err(invalid!("storing to an array of descriptors"))
+            }
+        }
+        Ok(())
+    }
+
+    fn access(&self, base: &Pointer, indices: &[u32]) -> Result<Pointer, Error> {
+        Ok(match *base {
+            Pointer::Private { slot, ref path } => Pointer::Private {
+                slot,
+                path: path.iter().chain(indices).cloned().collect(),
+            },
+            Pointer::Shared { slot, ref path } => Pointer::Shared {
+                slot,
+                path: path.iter().chain(indices).cloned().collect(),
+            },
+            Pointer::Memory(ref pointer) => {
+                let mut pointer = pointer.clone();
+                for &index in indices {
+                    pointer = pointer.element(self.module, index)?;
+                }
+                Pointer::Memory(pointer)
+            }
+            Pointer::Descriptors { slot } => {
+                let (&first, rest) = indices
+                    .split_first()
+                    .ok_or_else(|| invalid!("pointer to an array of descriptors"))?;
+                match self.storage[slot].member(&[first])? {
+                    Value::Pointer(ref pointer) => self.access(pointer, rest)?,
+                    _ => return Err(invalid!("buffer descriptor {} is not bound", first)),
+                }
+            }
+        })
+    }
+
+    /// Continue execution at the block `label` of the current function,
+    /// evaluating the `OpPhi` instructions at its start.
+    fn jump(&mut self, label: Id) -> Result<(), Error> {
+        let frame = self.frame()?;
+        let function = frame.function;
+        let previous = function.blocks[frame.block].label;
+        let block = function.block_index(label)?;
+
+        let mut incoming = Vec::new();
+        for instruction in &function.blocks[block].instructions {
+            if instruction.op != Op::Phi {
+                break;
+            }
+            let operands = &instruction.operands;
+            let source = operands
+                .get(2 ..)
+                .unwrap_or(&[])
+                .chunks(2)
+                .find(|pair| pair.len() == 2 && pair[1] == previous)
+                .ok_or_else(|| invalid!("phi %{} has no value for %{}", operands[1], previous))?;
+            incoming.push((operands[1], self.value(source[0])?));
+        }
+
+        let frame = self.frame_mut()?;
+        frame.block = block;
+        frame.instruction = incoming.len();
+        frame.values.extend(incoming);
+        Ok(())
+    }
+
+    fn ret(&mut self, value: Option<Value>) -> Result<(), Error> {
+        let frame = self
+            .frames
+            .pop()
+            .ok_or_else(|| invalid!("return outside of a function"))?;
+        self.storage.truncate(frame.storage);
+        if let (Some(caller), Some(value)) = (self.frames.last_mut(), value) {
+            caller.values.insert(frame.result, value);
+        }
+        Ok(())
+    }
+
+    /// Run the invocation until it finishes or reaches a barrier.
+    pub fn run(&mut self, workgroup: &mut Workgroup) -> Result<Status, Error> {
+        loop {
+            let instruction = match self.frames.last_mut() {
+                Some(frame) => {
+                    let block = &frame.function.blocks[frame.block];
+                    let instruction = block
+                        .instructions
+                        .get(frame.instruction)
+                        .ok_or_else(|| invalid!("block %{} has no terminator", block.label))?;
+                    frame.instruction += 1;
+                    instruction
+                }
+                None => return Ok(Status::Done),
+            };
+            if let Some(status) = self.step(instruction, workgroup)? {
+                return Ok(status);
+            }
+        }
+    }
+
+    fn step(
+        &mut self,
+        instruction: &'m Instruction,
+        workgroup: &mut Workgroup,
+    ) -> Result<Option<Status>, Error> {
+        let module = self.module;
+        let ops = &instruction.operands;
+        let operand = |index: usize| {
+            ops.get(index)
+                .cloned()
+                .ok_or_else(|| invalid!("missing operand {} of {:?}", index, instruction.op))
+        };
+        let arg = |index: usize| self.value(operand(index)?);
+
+        let result = match instruction.op {
+            Op::Nop | Op::SelectionMerge | Op::LoopMerge | Op::MemoryBarrier => None,
+            Op::Undef => Some(module.zero(operand(0)?)?),
+            Op::CopyObject => Some(arg(2)?),
+
+            // Arithmetic
+            Op::SNegate => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Int(a.as_int()?.wrapping_neg()))
+            })?),
+            Op::FNegate => Some(map1(&arg(2)?, &|a| Ok(Value::Float(-a.as_float()?)))?),
+            Op::Not => Some(map1(&arg(2)?, &|a| Ok(Value::Int(!a.as_int()?)))?),
+            Op::IAdd => Some(int_binary(&arg(2)?, &arg(3)?, u32::wrapping_add)?),
+            Op::ISub => Some(int_binary(&arg(2)?, &arg(3)?, u32::wrapping_sub)?),
+            Op::IMul => Some(int_binary(&arg(2)?, &arg(3)?, u32::wrapping_mul)?),
+            Op::UDiv => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| {
+                a.checked_div(b).unwrap_or(0)
+            })?),
+            Op::UMod => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| {
+                a.checked_rem(b).unwrap_or(0)
+            })?),
+            Op::SDiv => Some(int_binary(&arg(2)?, &arg(3)?, signed_div)?),
+            Op::SRem => Some(int_binary(&arg(2)?, &arg(3)?, signed_rem)?),
+            Op::SMod => Some(int_binary(&arg(2)?, &arg(3)?, signed_mod)?),
+            Op::FAdd => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a + b)?),
+            Op::FSub => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a - b)?),
+            Op::FMul => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a * b)?),
+            Op::FDiv => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a / b)?),
+            Op::FRem => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a % b)?),
+            Op::FMod => Some(float_binary(&arg(2)?, &arg(3)?, |a, b| {
+                a - b * (a / b).floor()
+            })?),
+            Op::ShiftLeftLogical => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| a << (b & 31))?),
+            Op::ShiftRightLogical => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| a >> (b & 31))?),
+            Op::ShiftRightArithmetic => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| {
+                ((a as i32) >> (b & 31)) as u32
+            })?),
+            Op::BitwiseOr => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| a | b)?),
+            Op::BitwiseXor => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| a ^ b)?),
+            Op::BitwiseAnd => Some(int_binary(&arg(2)?, &arg(3)?, |a, b| a & b)?),
+            Op::BitCount => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Int(a.as_int()?.count_ones()))
+            })?),
+            Op::BitReverse => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Int(a.as_int()?.reverse_bits()))
+            })?),
+
+            // Comparisons and logic
+            Op::IEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a == b)?),
+            Op::INotEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a != b)?),
+            Op::UGreaterThan => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a > b)?),
+            Op::UGreaterThanEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a >= b)?),
+            Op::ULessThan => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a < b)?),
+            Op::ULessThanEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a <= b)?),
+            Op::SGreaterThan => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| a as i32 > b as i32)?),
+            Op::SGreaterThanEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| {
+                a as i32 >= b as i32
+            })?),
+            Op::SLessThan => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| {
+                (a as i32) < b as i32
+            })?),
+            Op::SLessThanEqual => Some(int_compare(&arg(2)?, &arg(3)?, |a, b| {
+                a as i32 <= b as i32
+            })?),
+            Op::FOrdEqual => Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a == b)?),
+            Op::FUnordEqual => Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a == b)?),
+            Op::FOrdNotEqual => Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a != b)?),
+            Op::FUnordNotEqual => Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a != b)?),
+            Op::FOrdLessThan => Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a < b)?),
+            Op::FUnordLessThan => Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a < b)?),
+            Op::FOrdGreaterThan => Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a > b)?),
+            Op::FUnordGreaterThan => Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a > b)?),
+            Op::FOrdLessThanEqual => Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a <= b)?),
+            Op::FUnordLessThanEqual => {
+                Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a <= b)?)
+            }
+            Op::FOrdGreaterThanEqual => {
+                Some(float_compare(&arg(2)?, &arg(3)?, false, |a, b| a >= b)?)
+            }
+            Op::FUnordGreaterThanEqual => {
+                Some(float_compare(&arg(2)?, &arg(3)?, true, |a, b| a >= b)?)
+            }
+            Op::IsNan => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Bool(a.as_float()?.is_nan()))
+            })?),
+            Op::IsInf => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Bool(a.as_float()?.is_infinite()))
+            })?),
+            Op::LogicalEqual => Some(bool_binary(&arg(2)?, &arg(3)?, |a, b| a == b)?),
+            Op::LogicalNotEqual => Some(bool_binary(&arg(2)?, &arg(3)?, |a, b| a != b)?),
+            Op::LogicalOr => Some(bool_binary(&arg(2)?, &arg(3)?, |a, b| a || b)?),
+            Op::LogicalAnd => Some(bool_binary(&arg(2)?, &arg(3)?, |a, b| a && b)?),
+            Op::LogicalNot => Some(map1(&arg(2)?, &|a| Ok(Value::Bool(!a.as_bool()?)))?),
+            Op::Any | Op::All => {
+                let components = arg(2)?
+                    .components()?
+                    .iter()
+                    .map(Value::as_bool)
+                    .collect::<Result<Vec<_>, _>>()?;
+                Some(Value::Bool(if instruction.op == Op::Any {
+                    components.iter().any(|&c| c)
+                } else {
+                    components.iter().all(|&c| c)
+                }))
+            }
+            Op::Select => {
+                let (condition, a, b) = (arg(2)?, arg(3)?, arg(4)?);
+                match condition {
+                    Value::Bool(condition) => Some(if condition { a } else { b }),
+                    _ => Some(map3(&condition, &a, &b, &|c, a, b| {
+                        Ok(if c.as_bool()? { a.clone() } else { b.clone() })
+                    })?),
+                }
+            }
+
+            // Conversions
+            Op::ConvertFToU => Some(map1(&arg(2)?, &|a| Ok(Value::Int(a.as_float()? as u32)))?),
+            Op::ConvertFToS => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Int(a.as_float()? as i32 as u32))
+            })?),
+            Op::ConvertUToF => Some(map1(&arg(2)?, &|a| Ok(Value::Float(a.as_int()? as f32)))?),
+            Op::ConvertSToF => Some(map1(&arg(2)?, &|a| {
+                Ok(Value::Float(a.as_int()? as i32 as f32))
+            })?),
+            Op::UConvert | Op::SConvert | Op::FConvert | Op::QuantizeToF16 => Some(arg(2)?),
+            Op::Bitcast => {
+                let float = *self.scalar_type(operand(0)?)? == Type::Float;
+                Some(map1(&arg(2)?, &|a| {
+                    let bits = bits(a)?;
+                    Ok(if float {
+                        Value::Float(f32::from_bits(bits))
+                    } else {
+                        Value::Int(bits)
+                    })
+                })?)
+            }
+
+            // Composites
+            Op::CompositeConstruct => {
+                let vector = matches!(*module.ty(operand(0)?)?, Type::Vector { .. });
+                let mut components = Vec::new();
+                for index in 2 .. ops.len() {
+                    match arg(index)? {
+                        // Vectors get constructed from the components of
+                        // vector constituents.
+                        Value::Composite(constituent) if vector => components.extend(constituent),
+                        constituent => components.push(constituent),
+                    }
+                }
+                Some(Value::Composite(components))
+            }
+            Op::CompositeExtract => Some(arg(2)?.member(ops.get(3 ..).unwrap_or(&[]))?.clone()),
+            Op::CompositeInsert => {
+                let mut composite = arg(3)?;
+                *composite.member_mut(ops.get(4 ..).unwrap_or(&[]))? = arg(2)?;
+                Some(composite)
+            }
+            Op::VectorExtractDynamic => {
+                let index = arg(3)?.as_int()?;
+                // Out of bounds indices are undefined, return zero.
+                Some(match arg(2)?.components()?.get(index as usize) {
+                    Some(component) => component.clone(),
+                    None => module.zero(operand(0)?)?,
+                })
+            }
+            Op::VectorInsertDynamic => {
+                let mut vector = arg(2)?;
+                let index = arg(4)?.as_int()?;
+                if let Ok(component) = vector.member_mut(&[index]) {
+                    *component = arg(3)?;
+                }
+                Some(vector)
+            }
+            Op::VectorShuffle => {
+                let (a, b) = (arg(2)?, arg(3)?);
+                let (a, b) = (a.components()?, b.components()?);
+                let components = ops[4 ..]
+                    .iter()
+                    .map(|&index| match index {
+                        0xFFFF_FFFF => Ok(Value::Undef),
+                        index if (index as usize) < a.len() => Ok(a[index as usize].clone()),
+                        index => b
+                            .get(index as usize - a.len())
+                            .cloned()
+                            .ok_or_else(|| invalid!("shuffle index {} is out of bounds", index)),
+                    })
+                    .collect::<Result<_, _>>()?;
+                Some(Value::Composite(components))
+            }
+
+            // Linear algebra
+            Op::VectorTimesScalar | Op::MatrixTimesScalar => {
+                Some(float_binary(&arg(2)?, &arg(3)?, |a, b| a * b)?)
+            }
+            Op::Dot => Some(Value::Float(glsl::dot(&arg(2)?, &arg(3)?)?)),
+            Op::MatrixTimesVector => Some(matrix_times_vector(&arg(2)?, &arg(3)?)?),
+            Op::VectorTimesMatrix => {
+                let vector = arg(2)?;
+                let columns = arg(3)?
+                    .components()?
+                    .iter()
+                    .map(|column| Ok(Value::Float(glsl::dot(&vector, column)?)))
+                    .collect::<Result<_, Error>>()?;
+                Some(Value::Composite(columns))
+            }
+            Op::MatrixTimesMatrix => {
+                let left = arg(2)?;
+                let columns = arg(3)?
+                    .components()?
+                    .iter()
+                    .map(|column| matrix_times_vector(&left, column))
+                    .collect::<Result<_, _>>()?;
+                Some(Value::Composite(columns))
+            }
+            Op::OuterProduct => {
+                let left = arg(2)?;
+                let columns = arg(3)?
+                    .components()?
+                    .iter()
+                    .map(|factor| float_binary(&left, factor, |a, b| a * b))
+                    .collect::<Result<_, _>>()?;
+                Some(Value::Composite(columns))
+            }
+            Op::Transpose => {
+                let matrix = arg(2)?;
+                let columns = matrix
+                    .components()?
+                    .iter()
+                    .map(glsl::floats)
+                    .collect::<Result<Vec<_>, _>>()?;
+                let rows = columns.first().map_or(0, Vec::len);
+                Some(Value::Composite(
+                    (0 .. rows)
+                        .map(|row| {
+                            Value::Composite(
+                                columns
+                                    .iter()
+                                    .map(|column| Value::Float(column[row]))
+                                    .collect(),
+                            )
+                        })
+                        .collect(),
+                ))
+            }
+
+            // Derivatives are zero, as every invocation runs on its own.
+            Op::DPdx
+            | Op::DPdy
+            | Op::Fwidth
+            | Op::DPdxFine
+            | Op::DPdyFine
+            | Op::FwidthFine
+            | Op::DPdxCoarse
+            | Op::DPdyCoarse
+            | Op::FwidthCoarse => Some(map1(&arg(2)?, &|_| Ok(Value::Float(0.0)))?),
+
+            Op::ExtInst => {
+                if Some(operand(2)?) != module.glsl_std {
+                    return Err(invalid!(
+                        "unknown extended instruction set %{}",
+                        operand(2)?
+                    ));
+                }
+                let args = (4 .. ops.len()).map(arg).collect::<Result<Vec<_>, _>>()?;
+                Some(glsl::eval(operand(3)?, &args)?)
+            }
+
+            // Memory
+            Op::Variable => {
+                let value = match ops.get(3) {
+                    Some(&initializer) => self.value(initializer)?,
+                    None => match *module.ty(operand(0)?)? {
+                        Type::Pointer { pointee, .. } => module.zero(pointee)?,
+                        ref other => return Err(invalid!("variable of type {:?}", other)),
+                    },
+                };
+                self.storage.push(value);
+                Some(Value::Pointer(Pointer::Private {
+                    slot: self.storage.len() - 1,
+                    path: Vec::new(),
+                }))
+            }
+            Op::Load | Op::AtomicLoad => Some(self.load(&arg(2)?, Some(workgroup))?),
+            Op::Store => {
+                let (pointer, value) = (arg(0)?, arg(1)?);
+                self.store(&pointer, value, Some(workgroup))?;
+                None
+            }
+            Op::AtomicStore => {
+                let (pointer, value) = (arg(0)?, arg(3)?);
+                self.store(&pointer, value, Some(workgroup))?;
+                None
+            }
+            Op::CopyMemory => {
+                let (target, source) = (arg(0)?, arg(1)?);
+                let value = self.load(&source, Some(workgroup))?;
+                self.store(&target, value, Some(workgroup))?;
+                None
+            }
+            Op::AccessChain | Op::InBoundsAccessChain => {
+                let base = arg(2)?;
+                let indices = (3 .. ops.len())
+                    .map(|index| arg(index)?.as_int())
+                    .collect::<Result<Vec<_>, _>>()?;
+                Some(Value::Pointer(self.access(base.as_pointer()?, &indices)?))
+            }
+            Op::ArrayLength => match *arg(2)?.as_pointer()? {
+                Pointer::Memory(ref pointer) => Some(Value::Int(
+                    pointer
+                        .element(module, operand(3)?)?
+                        .runtime_length(module)?,
+                )),
+                ref other => return Err(invalid!("length of a run-time array in {:?}", other)),
+            },
+
+            // Atomics, invocations run one at a time so a plain
+            // read-modify-write is atomic.
+            Op::AtomicExchange
+            | Op::AtomicIIncrement
+            | Op::AtomicIDecrement
+            | Op::AtomicIAdd
+            | Op::AtomicISub
+            | Op::AtomicSMin
+            | Op::AtomicUMin
+            | Op::AtomicSMax
+            | Op::AtomicUMax
+            | Op::AtomicAnd
+            | Op::AtomicOr
+            | Op::AtomicXor
+            | Op::AtomicCompareExchange => {
+                let pointer = arg(2)?;
+                let original = self.load(&pointer, Some(workgroup))?;
+                let a = original.as_int()?;
+                let b = match instruction.op {
+                    Op::AtomicIIncrement | Op::AtomicIDecrement => 1,
+                    Op::AtomicCompareExchange => arg(6)?.as_int()?,
+                    _ => arg(5)?.as_int()?,
+                };
+                let value = match instruction.op {
+                    Op::AtomicExchange => b,
+                    Op::AtomicIIncrement | Op::AtomicIAdd => a.wrapping_add(b),
+                    Op::AtomicIDecrement | Op::AtomicISub => a.wrapping_sub(b),
+                    Op::AtomicSMin => (a as i32).min(b as i32) as u32,
+                    Op::AtomicUMin => a.min(b),
+                    Op::AtomicSMax => (a as i32).max(b as i32) as u32,
+                    Op::AtomicUMax => a.max(b),
+                    Op::AtomicAnd => a & b,
+                    Op::AtomicOr => a | b,
+                    Op::AtomicXor => a ^ b,
+                    _ => {
+                        if a == arg(7)?.as_int()? {
+                            b
+                        } else {
+                            a
+                        }
+                    }
+                };
+                self.store(&pointer, Value::Int(value), Some(workgroup))?;
+                Some(original)
+            }
+
+            // Control flow
+            Op::Branch => {
+                self.jump(operand(0)?)?;
+                None
+            }
+            Op::BranchConditional => {
+                let target = if arg(0)?.as_bool()? {
+                    operand(1)?
+                } else {
+                    operand(2)?
+                };
+                self.jump(target)?;
+                None
+            }
+            Op::Switch => {
+                let selector = arg(0)?.as_int()?;
+                let target = ops
+                    .get(2 ..)
+                    .unwrap_or(&[])
+                    .chunks(2)
+                    .find(|case| case.len() == 2 && case[0] == selector)
+                    .map_or(operand(1), |case| Ok(case[1]))?;
+                self.jump(target)?;
+                None
+            }
+            Op::FunctionCall => {
+                let function = module.function(operand(2)?)?;
+                if function.params.len() + 3 != ops.len() {
+                    return Err(invalid!("wrong number of arguments for %{}", operand(2)?));
+                }
+                let values = function
+                    .params
+                    .iter()
+                    .enumerate()
+                    .map(|(i, &param)| Ok((param, arg(3 + i)?)))
+                    .collect::<Result<_, Error>>()?;
+                self.frames.push(Frame {
+                    function,
+                    block: 0,
+                    instruction: 0,
+                    values,
+                    result: operand(1)?,
+                    storage: self.storage.len(),
+                });
+                return Ok(None);
+            }
+            Op::Return => {
+                self.ret(None)?;
+                return Ok(if self.frames.is_empty() {
+                    Some(Status::Done)
+                } else {
+                    None
+                });
+            }
+            Op::ReturnValue => {
+                let value = arg(0)?;
+                self.ret(Some(value))?;
+                return Ok(if self.frames.is_empty() {
+                    Some(Status::Done)
+                } else {
+                    None
+                });
+            }
+            Op::Kill => {
+                self.frames.clear();
+                return Ok(Some(Status::Killed));
+            }
+            Op::ControlBarrier => return Ok(Some(Status::Barrier)),
+            Op::Unreachable => return Err(invalid!("reached OpUnreachable")),
+
+            op => return Err(unsupported!("instruction {:?}", op)),
+        };
+
+        if let Some(value) = result {
+            self.define(operand(1)?, value)?;
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spirv_headers::{Decoration, ExecutionModel, MAGIC_NUMBER};
+
+    struct NoResources;
+
+    impl Resources for NoResources {
+        fn buffer(&self, _: u32, _: u32, _: u32) -> Option<(Arc<Block>, Range<u64>)> {
+            None
+        }
+
+        fn push_constants(&self) -> Option<Arc<Block>> {
+            None
+        }
+    }
+
+    // Ids shared by the test modules.
+    const VOID: Id = 1;
+    const MAIN_TYPE: Id = 2;
+    const INT: Id = 3;
+    const FLOAT: Id = 4;
+    const BOOL: Id = 5;
+    const INT_INPUT: Id = 6;
+    const INT_OUTPUT: Id = 7;
+    const FLOAT_INPUT: Id = 8;
+    const FLOAT_OUTPUT: Id = 9;
+    const MAIN: Id = 10;
+
+    /// Assemble a vertex shader module, `main` being the entry point.
+    fn assemble(instructions: &[(Op, &[u32])]) -> Module {
+        let name = u32::from_le_bytes(*b"main");
+        let mut words = vec![MAGIC_NUMBER, 0x0001_0000, 0, 100, 0];
+        let header: &[(Op, &[u32])] = &[
+            (
+                Op::EntryPoint,
+                &[ExecutionModel::Vertex as u32, MAIN, name, 0],
+            ),
+            (Op::TypeVoid, &[VOID]),
+            (Op::TypeFunction, &[MAIN_TYPE, VOID]),
+            (Op::TypeInt, &[INT, 32, 1]),
+            (Op::TypeFloat, &[FLOAT, 32]),
+            (Op::TypeBool, &[BOOL]),
+            (
+                Op::TypePointer,
+                &[INT_INPUT, StorageClass::Input as u32, INT],
+            ),
+            (
+                Op::TypePointer,
+                &[INT_OUTPUT, StorageClass::Output as u32, INT],
+            ),
+            (
+                Op::TypePointer,
+                &[FLOAT_INPUT, StorageClass::Input as u32, FLOAT],
+            ),
+            (
+                Op::TypePointer,
+                &[FLOAT_OUTPUT, StorageClass::Output as u32, FLOAT],
+            ),
+        ];
+        for &(op, operands) in header.iter().chain(instructions) {
+            words.push((operands.len() as u32 + 1) << 16 | op as u32);
+            words.extend_from_slice(operands);
+        }
+        Module::parse(&words).unwrap()
+    }
+
+    /// Run `main` with the given inputs, returning the outputs.
+    fn run(module: &Module, inputs: &[(u32, u32)]) -> Vec<(u32, Vec<u32>)> {
+        let entry = module.entry_point("main").unwrap();
+        let mut workgroup = Workgroup::new(module).unwrap();
+        let mut invocation = Invocation::new(module, entry, &NoResources, &workgroup).unwrap();
+        for &(location, value) in inputs {
+            invocation.set_input(location, &[value]).unwrap();
+        }
+        assert_eq!(invocation.run(&mut workgroup), Ok(Status::Done));
+        invocation.outputs().unwrap()
+    }
+
+    fn location(id: Id, location: u32) -> [u32; 3] {
+        [id, Decoration::Location as u32, location]
+    }
+
+    #[test]
+    fn arithmetic() {
+        let module = assemble(&[
+            (Op::Decorate, &location(20, 0)),
+            (Op::Decorate, &location(21, 1)),
+            (Op::Decorate, &location(22, 0)),
+            (Op::Decorate, &location(23, 1)),
+            (Op::Decorate, &location(24, 2)),
+            (Op::Decorate, &location(25, 3)),
+            (Op::Decorate, &location(26, 4)),
+            (Op::Constant, &[INT, 30, 3]),
+            (Op::Constant, &[INT, 31, 25]),
+            (Op::Constant, &[FLOAT, 32, 2.5f32.to_bits()]),
+            (Op::Constant, &[FLOAT, 33, 0.25f32.to_bits()]),
+            (Op::Variable, &[INT_INPUT, 20, StorageClass::Input as u32]),
+            (Op::Variable, &[FLOAT_INPUT, 21, StorageClass::Input as u32]),
+            (Op::Variable, &[INT_OUTPUT, 22, StorageClass::Output as u32]),
+            (Op::Variable, &[INT_OUTPUT, 23, StorageClass::Output as u32]),
+            (Op::Variable, &[INT_OUTPUT, 24, StorageClass::Output as u32]),
+            (
+                Op::Variable,
+                &[FLOAT_OUTPUT, 25, StorageClass::Output as u32],
+            ),
+            (Op::Variable, &[INT_OUTPUT, 26, StorageClass::Output as u32]),
+            (Op::Function, &[VOID, MAIN, 0, MAIN_TYPE]),
+            (Op::Label, &[40]),
+            (Op::Load, &[INT, 41, 20]),
+            (Op::IMul, &[INT, 42, 41, 30]),
+            (Op::ISub, &[INT, 43, 42, 31]),
+            (Op::SDiv, &[INT, 44, 43, 30]),
+            (Op::SMod, &[INT, 45, 43, 30]),
+            (Op::SRem, &[INT, 46, 43, 30]),
+            (Op::Store, &[22, 44]),
+            (Op::Store, &[23, 45]),
+            (Op::Store, &[24, 46]),
+            (Op::Load, &[FLOAT, 47, 21]),
+            (Op::FMul, &[FLOAT, 48, 47, 32]),
+            (Op::FSub, &[FLOAT, 49, 48, 33]),
+            (Op::Store, &[25, 49]),
+            (Op::FNegate, &[FLOAT, 50, 49]),
+            (Op::ConvertFToS, &[INT, 51, 50]),
+            (Op::Store, &[26, 51]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        let outputs = run(&module, &[(0, 7), (1, 1.5f32.to_bits())]);
+        // 7 * 3 - 25 = -4, divided and taken modulo 3.
+        assert_eq!(
+            outputs,
+            vec![
+                (0, vec![-1i32 as u32]),
+                (1, vec![2]),
+                (2, vec![-1i32 as u32]),
+                (3, vec![3.5f32.to_bits()]),
+                (4, vec![-3i32 as u32]),
+            ]
+        );
+    }
+
+    #[test]
+    fn control_flow() {
+        const SQUARE_TYPE: Id = 11;
+        const SQUARE: Id = 12;
+        // Sum the numbers from 1 to n in a loop, square n in a function
+        // and check whether n is 4 with a switch.
+        let module = assemble(&[
+            (Op::Decorate, &location(20, 0)),
+            (Op::Decorate, &location(22, 0)),
+            (Op::Decorate, &location(23, 1)),
+            (Op::Decorate, &location(24, 2)),
+            (Op::TypeFunction, &[SQUARE_TYPE, INT, INT]),
+            (Op::Constant, &[INT, 30, 0]),
+            (Op::Constant, &[INT, 31, 1]),
+            (Op::Variable, &[INT_INPUT, 20, StorageClass::Input as u32]),
+            (Op::Variable, &[INT_OUTPUT, 22, StorageClass::Output as u32]),
+            (Op::Variable, &[INT_OUTPUT, 23, StorageClass::Output as u32]),
+            (Op::Variable, &[INT_OUTPUT, 24, StorageClass::Output as u32]),
+            (Op::Function, &[INT, SQUARE, 0, SQUARE_TYPE]),
+            (Op::FunctionParameter, &[INT, 60]),
+            (Op::Label, &[61]),
+            (Op::IMul, &[INT, 62, 60, 60]),
+            (Op::ReturnValue, &[62]),
+            (Op::FunctionEnd, &[]),
+            (Op::Function, &[VOID, MAIN, 0, MAIN_TYPE]),
+            (Op::Label, &[40]),
+            (Op::Load, &[INT, 41, 20]),
+            (Op::Branch, &[42]),
+            // Loop header.
+            (Op::Label, &[42]),
+            (Op::Phi, &[INT, 43, 31, 40, 46, 44]),
+            (Op::Phi, &[INT, 47, 30, 40, 48, 44]),
+            (Op::SLessThanEqual, &[BOOL, 49, 43, 41]),
+            (Op::LoopMerge, &[45, 44, 0]),
+            (Op::BranchConditional, &[49, 44, 45]),
+            // Loop body and continue target.
+            (Op::Label, &[44]),
+            (Op::IAdd, &[INT, 48, 47, 43]),
+            (Op::IAdd, &[INT, 46, 43, 31]),
+            (Op::Branch, &[42]),
+            // After the loop.
+            (Op::Label, &[45]),
+            (Op::Store, &[22, 47]),
+            (Op::FunctionCall, &[INT, 50, SQUARE, 41]),
+            (Op::Store, &[23, 50]),
+            (Op::SelectionMerge, &[53, 0]),
+            (Op::Switch, &[41, 52, 4, 51]),
+            (Op::Label, &[51]),
+            (Op::Store, &[24, 31]),
+            (Op::Branch, &[53]),
+            (Op::Label, &[52]),
+            (Op::Store, &[24, 30]),
+            (Op::Branch, &[53]),
+            (Op::Label, &[53]),
+            (Op::Return, &[]),
+            (Op::FunctionEnd, &[]),
+        ]);
+        assert_eq!(
+            run(&module, &[(0, 4)]),
+            vec![(0, vec![10]), (1, vec![16]), (2, vec![1])]
+        );
+        assert_eq!(
+            run(&module, &[(0, 3)]),
+            vec![(0, vec![6]), (1, vec![9]), (2, vec![0])]
+        );
+        assert_eq!(
+            run(&module, &[(0, 0)]),
+            vec![(0, vec![0]), (1, vec![0]), (2, vec![0])]
+        );
+    }
+}
@@ -0,0 +1,229 @@
+//! Instructions of the `GLSL.std.450` extended instruction set.
+
+use num_traits::FromPrimitive;
+use spirv_headers::GLOp;
+
+use super::value::{map1, map2, map3};
+use super::{Error, Value};
+
+fn float1(a: &Value, f: fn(f32) -> f32) -> Result<Value, Error> {
+    map1(a, &|a| Ok(Value::Float(f(a.as_float()?))))
+}
+
+fn float2(a: &Value, b: &Value, f: fn(f32, f32) -> f32) -> Result<Value, Error> {
+    map2(a, b, &|a, b| {
+        Ok(Value::Float(f(a.as_float()?, b.as_float()?)))
+    })
+}
+
+fn float3(a: &Value, b: &Value, c: &Value, f: fn(f32, f32, f32) -> f32) -> Result<Value, Error> {
+    map3(a, b, c, &|a, b, c| {
+        Ok(Value::Float(f(a.as_float()?, b.as_float()?, c.as_float()?)))
+    })
+}
+
+fn int1(a: &Value, f: fn(u32) -> u32) -> Result<Value, Error> {
+    map1(a, &|a| Ok(Value::Int(f(a.as_int()?))))
+}
+
+fn int2(a: &Value, b: &Value, f: fn(u32, u32) -> u32) -> Result<Value, Error> {
+    map2(a, b, &|a, b| Ok(Value::Int(f(a.as_int()?, b.as_int()?))))
+}
+
+fn int3(a: &Value, b: &Value, c: &Value, f: fn(u32, u32, u32) -> u32) -> Result<Value, Error> {
+    map3(a, b, c, &|a, b, c| {
+        Ok(Value::Int(f(a.as_int()?, b.as_int()?, c.as_int()?)))
+    })
+}
+
+pub(super) fn floats(value: &Value) -> Result<Vec<f32>, Error> {
+    match *value {
+        Value::Composite(ref components) => components.iter().map(Value::as_float).collect(),
+        ref scalar => Ok(vec![scalar.as_float()?]),
+    }
+}
+
+pub(super) fn dot(a: &Value, b: &Value) -> Result<f32, Error> {
+    Ok(floats(a)?.iter().zip(floats(b)?).map(|(a, b)| a * b).sum())
+}
+
+fn length(a: &Value) -> Result<f32, Error> {
+    dot(a, a).map(f32::sqrt)
+}
+
+fn scale(a: &Value, factor: f32) -> Result<Value, Error> {
+    map1(a, &|a| Ok(Value::Float(a.as_float()? * factor)))
+}
+
+fn smooth_step(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn round_even(x: f32) -> f32 {
+    let rounded = x.round();
+    if (x - x.trunc()).abs() == 0.5 && rounded % 2.0 != 0.0 {
+        rounded - x.signum()
+    } else {
+        rounded
+    }
+}
+
+fn nan_min(a: f32, b: f32) -> f32 {
+    if a.is_nan() {
+        b
+    } else if b.is_nan() {
+        a
+    } else {
+        a.min(b)
+    }
+}
+
+fn nan_max(a: f32, b: f32) -> f32 {
+    -nan_min(-a, -b)
+}
+
+/// Evaluate the extended instruction `instruction` on the operands.
+pub fn eval(instruction: u32, args: &[Value]) -> Result<Value, Error> {
+    let op =
+        GLOp::from_u32(instruction).ok_or_else(|| invalid!("GLSL instruction {}", instruction))?;
+    let arg = |i: usize| {
+        args.get(i)
+            .ok_or_else(|| invalid!("missing operand {} of {:?}", i, op))
+    };
+
+    match op {
+        GLOp::Round => float1(arg(0)?, f32::round),
+        GLOp::RoundEven => float1(arg(0)?, round_even),
+        GLOp::Trunc => float1(arg(0)?, f32::trunc),
+        GLOp::FAbs => float1(arg(0)?, f32::abs),
+        GLOp::SAbs => int1(arg(0)?, |a| (a as i32).wrapping_abs() as u32),
+        GLOp::FSign => float1(arg(0)?, |a| {
+            if a > 0.0 {
+                1.0
+            } else if a < 0.0 {
+                -1.0
+            } else {
+                0.0
+            }
+        }),
+        GLOp::SSign => int1(arg(0)?, |a| (a as i32).signum() as u32),
+        GLOp::Floor => float1(arg(0)?, f32::floor),
+        GLOp::Ceil => float1(arg(0)?, f32::ceil),
+        GLOp::Fract => float1(arg(0)?, |a| a - a.floor()),
+        GLOp::Radians => float1(arg(0)?, f32::to_radians),
+        GLOp::Degrees => float1(arg(0)?, f32::to_degrees),
+        GLOp::Sin => float1(arg(0)?, f32::sin),
+        GLOp::Cos => float1(arg(0)?, f32::cos),
+        GLOp::Tan => float1(arg(0)?, f32::tan),
+        GLOp::Asin => float1(arg(0)?, f32::asin),
+        GLOp::Acos => float1(arg(0)?, f32::acos),
+        GLOp::Atan => float1(arg(0)?, f32::atan),
+        GLOp::Sinh => float1(arg(0)?, f32::sinh),
+        GLOp::Cosh => float1(arg(0)?, f32::cosh),
+        GLOp::Tanh => float1(arg(0)?, f32::tanh),
+        GLOp::Asinh => float1(arg(0)?, f32::asinh),
+        GLOp::Acosh => float1(arg(0)?, f32::acosh),
+        GLOp::Atanh => float1(arg(0)?, f32::atanh),
+        GLOp::Atan2 => float2(arg(0)?, arg(1)?, f32::atan2),
+        GLOp::Pow => float2(arg(0)?, arg(1)?, f32::powf),
+        GLOp::Exp => float1(arg(0)?, f32::exp),
+        GLOp::Log => float1(arg(0)?, f32::ln),
+        GLOp::Exp2 => float1(arg(0)?, f32::exp2),
+        GLOp::Log2 => float1(arg(0)?, f32::log2),
+        GLOp::Sqrt => float1(arg(0)?, f32::sqrt),
+        GLOp::InverseSqrt => float1(arg(0)?, |a| 1.0 / a.sqrt()),
+        GLOp::FMin => float2(arg(0)?, arg(1)?, f32::min),
+        GLOp::UMin => int2(arg(0)?, arg(1)?, u32::min),
+        GLOp::SMin => int2(arg(0)?, arg(1)?, |a, b| (a as i32).min(b as i32) as u32),
+        GLOp::FMax => float2(arg(0)?, arg(1)?, f32::max),
+        GLOp::UMax => int2(arg(0)?, arg(1)?, u32::max),
+        GLOp::SMax => int2(arg(0)?, arg(1)?, |a, b| (a as i32).max(b as i32) as u32),
+        GLOp::FClamp => float3(arg(0)?, arg(1)?, arg(2)?, |x, lo, hi| x.max(lo).min(hi)),
+        GLOp::UClamp => int3(arg(0)?, arg(1)?, arg(2)?, |x, lo, hi| x.max(lo).min(hi)),
+        GLOp::SClamp => int3(arg(0)?, arg(1)?, arg(2)?, |x, lo, hi| {
+            (x as i32).max(lo as i32).min(hi as i32) as u32
+        }),
+        GLOp::NMin => float2(arg(0)?, arg(1)?, nan_min),
+        GLOp::NMax => float2(arg(0)?, arg(1)?, nan_max),
+        GLOp::NClamp => float3(arg(0)?, arg(1)?, arg(2)?, |x, lo, hi| {
+            nan_min(nan_max(x, lo), hi)
+        }),
+        GLOp::FMix => float3(arg(0)?, arg(1)?, arg(2)?, |x, y, a| x * (1.0 - a) + y * a),
+        GLOp::Step => float2(arg(0)?, arg(1)?, |edge, x| if x < edge { 0.0 } else { 1.0 }),
+        GLOp::SmoothStep => float3(arg(0)?, arg(1)?, arg(2)?, smooth_step),
+        GLOp::Fma => float3(arg(0)?, arg(1)?, arg(2)?, f32::mul_add),
+        GLOp::Length => Ok(Value::Float(length(arg(0)?)?)),
+        GLOp::Distance => {
+            let difference = float2(arg(0)?, arg(1)?, |a, b| a - b)?;
+            Ok(Value::Float(length(&difference)?))
+        }
+        GLOp::Normalize => {
+            let value = arg(0)?;
+            scale(value, 1.0 / length(value)?)
+        }
+        GLOp::Cross => {
+            let (a, b) = (floats(arg(0)?)?, floats(arg(1)?)?);
+            if a.len() != 3 || b.len() != 3 {
+                return Err(invalid!("cross product of {}-vectors", a.len()));
+            }
+            Ok(Value::Composite(vec![
+                Value::Float(a[1] * b[2] - b[1] * a[2]),
+                Value::Float(a[2] * b[0] - b[2] * a[0]),
+                Value::Float(a[0] * b[1] - b[0] * a[1]),
+            ]))
+        }
+        GLOp::FaceForward => {
+            let (n, i, reference) = (arg(0)?, arg(1)?, arg(2)?);
+            if dot(reference, i)? < 0.0 {
+                Ok(n.clone())
+            } else {
+                scale(n, -1.0)
+            }
+        }
+        GLOp::Reflect => {
+            let (i, n) = (arg(0)?, arg(1)?);
+            let factor = 2.0 * dot(n, i)?;
+            map2(i, &scale(n, factor)?, &|i, n| {
+                Ok(Value::Float(i.as_float()? - n.as_float()?))
+            })
+        }
+        GLOp::Refract => {
+            let (i, n, eta) = (arg(0)?, arg(1)?, arg(2)?.as_float()?);
+            let d = dot(n, i)?;
+            let k = 1.0 - eta * eta * (1.0 - d * d);
+            if k < 0.0 {
+                scale(i, 0.0)
+            } else {
+                let n = scale(n, eta * d + k.sqrt())?;
+                map2(&scale(i, eta)?, &n, &|i, n| {
+                    Ok(Value::Float(i.as_float()? - n.as_float()?))
+                })
+            }
+        }
+        GLOp::FindILsb => int1(arg(0)?, |a| if a == 0 { !0 } else { a.trailing_zeros() }),
+        GLOp::FindSMsb => int1(arg(0)?, |a| {
+            let a = if (a as i32) < 0 { !a } else { a };
+            31u32.wrapping_sub(a.leading_zeros())
+        }),
+        GLOp::FindUMsb => int1(arg(0)?, |a| 31u32.wrapping_sub(a.leading_zeros())),
+        GLOp::PackUnorm4x8 => {
+            let word = floats(arg(0)?)?
+                .iter()
+                .enumerate()
+                .fold(0, |word, (i, &value)| {
+                    word | ((value.clamp(0.0, 1.0) * 255.0).round() as u32) << (i * 8)
+                });
+            Ok(Value::Int(word))
+        }
+        GLOp::UnpackUnorm4x8 => {
+            let word = arg(0)?.as_int()?;
+            Ok(Value::Composite(
+                (0 .. 4)
+                    .map(|i| Value::Float(((word >> (i * 8)) & 0xFF) as f32 / 255.0))
+                    .collect(),
+            ))
+        }
+        other => Err(unsupported!("GLSL instruction {:?}", other)),
+    }
+}
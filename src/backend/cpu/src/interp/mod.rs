@@ -0,0 +1,54 @@
+//! SPIR-V interpreter executing shaders on the host.
+//!
+//! Modules are parsed once when the shader module gets created. Every shader
+//! invocation then walks the instructions of the entry point, keeping SSA
+//! values and variables in plain Rust values. Memory of bound buffers and push
+//! constants is accessed directly, following the explicit layout decorations.
+//!
+//! Only 32-bit scalar types are supported, which covers shaders produced from
+//! GLSL without extensions.
+
+macro_rules! invalid {
+    ($($arg:tt)*) => {
+        crate::interp::Error::Invalid(format!($($arg)*))
+    };
+}
+
+macro_rules! unsupported {
+    ($($arg:tt)*) => {
+        crate::interp::Error::Unsupported(format!($($arg)*))
+    };
+}
+
+mod exec;
+mod glsl;
+mod module;
+mod value;
+
+pub use self::exec::{Invocation, Resources, Status, Workgroup};
+pub use self::module::{EntryPoint, Module};
+pub use self::value::Value;
+
+pub use spirv_headers::{BuiltIn, ExecutionModel};
+
+use std::fmt;
+
+/// Error raised while parsing or executing a module.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The module is not valid SPIR-V.
+    Invalid(String),
+    /// The module uses a feature the interpreter doesn't implement.
+    Unsupported(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Invalid(ref message) => write!(fmt, "Invalid SPIR-V: {}", message),
+            Error::Unsupported(ref message) => write!(fmt, "Unsupported SPIR-V: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
@@ -0,0 +1,607 @@
+use num_traits::FromPrimitive;
+use spirv_headers as spirv;
+
+use hal::pso;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::{Error, Value};
+
+/// Result id of an instruction.
+pub type Id = u32;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Void,
+    Bool,
+    Int {
+        signed: bool,
+    },
+    Float,
+    Vector {
+        component: Id,
+        count: u32,
+    },
+    Matrix {
+        column: Id,
+        count: u32,
+    },
+    Array {
+        element: Id,
+        length: u32,
+    },
+    RuntimeArray {
+        element: Id,
+    },
+    Struct {
+        members: Vec<Id>,
+    },
+    Pointer {
+        class: spirv::StorageClass,
+        pointee: Id,
+    },
+    Function,
+    /// Images, samplers and other handles the interpreter can't look into.
+    Opaque,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Decorations {
+    pub binding: Option<u32>,
+    pub set: Option<u32>,
+    pub location: Option<u32>,
+    pub builtin: Option<spirv::BuiltIn>,
+    pub offset: Option<u32>,
+    pub array_stride: Option<u32>,
+    pub matrix_stride: Option<u32>,
+    pub row_major: bool,
+    pub spec_id: Option<u32>,
+    pub flat: bool,
+}
+
+#[derive(Clone, Debug)]
+pub struct Instruction {
+    pub op: spirv::Op,
+    pub operands: Vec<u32>,
+}
+
+#[derive(Debug)]
+pub struct Block {
+    pub label: Id,
+    pub instructions: Vec<Instruction>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub params: Vec<Id>,
+    pub blocks: Vec<Block>,
+    labels: HashMap<Id, usize>,
+}
+
+impl Function {
+    pub fn block_index(&self, label: Id) -> Result<usize, Error> {
+        self.labels
+            .get(&label)
+            .cloned()
+            .ok_or_else(|| invalid!("unknown label %{}", label))
+    }
+}
+
+/// A variable declared outside of functions.
+#[derive(Clone, Debug)]
+pub struct Variable {
+    pub class: spirv::StorageClass,
+    /// Type of the variable contents.
+    pub ty: Id,
+    pub initializer: Option<Id>,
+}
+
+#[derive(Clone, Debug)]
+pub struct EntryPoint {
+    pub model: spirv::ExecutionModel,
+    pub name: String,
+    pub function: Id,
+    pub local_size: [u32; 3],
+    pub origin_lower_left: bool,
+    pub depth_replacing: bool,
+}
+
+/// A parsed SPIR-V module.
+#[derive(Clone, Debug)]
+pub struct Module {
+    pub(crate) types: HashMap<Id, Type>,
+    pub(crate) constants: HashMap<Id, Value>,
+    pub(crate) variables: HashMap<Id, Variable>,
+    pub(crate) functions: Arc<HashMap<Id, Function>>,
+    pub(crate) decorations: HashMap<Id, Decorations>,
+    pub(crate) member_decorations: HashMap<(Id, u32), Decorations>,
+    pub(crate) entry_points: Vec<EntryPoint>,
+    /// Id of the imported `GLSL.std.450` instruction set.
+    pub(crate) glsl_std: Option<Id>,
+    /// Type of every specialization constant.
+    spec_constants: Vec<(Id, Id)>,
+    /// Composite specialization constants with their constituents, in
+    /// declaration order so that they can be rebuilt after specialization.
+    spec_composites: Vec<(Id, Vec<Id>)>,
+}
+
+/// Decode a literal string starting at the beginning of `words`.
+///
+/// Returns the string and the number of words it occupies.
+fn parse_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = Vec::new();
+    for (i, word) in words.iter().enumerate() {
+        for &byte in &word.to_le_bytes() {
+            if byte == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), i + 1);
+            }
+            bytes.push(byte);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+fn operand(operands: &[u32], index: usize) -> Result<u32, Error> {
+    operands
+        .get(index)
+        .cloned()
+        .ok_or_else(|| invalid!("missing operand {}", index))
+}
+
+impl Module {
+    pub fn parse(words: &[u32]) -> Result<Self, Error> {
+        if words.len() < 5 || words[0] != spirv::MAGIC_NUMBER {
+            return Err(invalid!("bad module header"));
+        }
+
+        let mut module = Module {
+            types: HashMap::new(),
+            constants: HashMap::new(),
+            variables: HashMap::new(),
+            functions: Arc::new(HashMap::new()),
+            decorations: HashMap::new(),
+            member_decorations: HashMap::new(),
+            entry_points: Vec::new(),
+            glsl_std: None,
+            spec_constants: Vec::new(),
+            spec_composites: Vec::new(),
+        };
+        let mut functions = HashMap::new();
+        let mut modes = Vec::new();
+        // Function being parsed: (id, parameters, blocks)
+        let mut current: Option<(Id, Vec<Id>, Vec<Block>)> = None;
+
+        let mut position = 5;
+        while position < words.len() {
+            let count = (words[position] >> 16) as usize;
+            let opcode = words[position] & 0xFFFF;
+            if count == 0 || position + count > words.len() {
+                return Err(invalid!("truncated instruction at word {}", position));
+            }
+            let op = spirv::Op::from_u32(opcode).ok_or_else(|| invalid!("opcode {}", opcode))?;
+            let operands = &words[position + 1 .. position + count];
+            position += count;
+
+            if let Some((_, ref mut params, ref mut blocks)) = current {
+                match op {
+                    spirv::Op::FunctionParameter => params.push(operand(operands, 1)?),
+                    spirv::Op::Label => blocks.push(Block {
+                        label: operand(operands, 0)?,
+                        instructions: Vec::new(),
+                    }),
+                    spirv::Op::FunctionEnd => {
+                        let (id, params, blocks) = current.take().unwrap();
+                        let labels = blocks
+                            .iter()
+                            .enumerate()
+                            .map(|(i, block)| (block.label, i))
+                            .collect();
+                        functions.insert(
+                            id,
+                            Function {
+                                params,
+                                blocks,
+                                labels,
+                            },
+                        );
+                    }
+                    spirv::Op::Line | spirv::Op::NoLine => {}
+                    _ => match blocks.last_mut() {
+                        Some(block) => block.instructions.push(Instruction {
+                            op,
+                            operands: operands.to_vec(),
+                        }),
+                        None => return Err(invalid!("{:?} outside of a block", op)),
+                    },
+                }
+                continue;
+            }
+
+            match op {
+                spirv::Op::ExtInstImport => {
+                    let (name, _) = parse_string(&operands[1 ..]);
+                    if name == "GLSL.std.450" {
+                        module.glsl_std = Some(operand(operands, 0)?);
+                    } else {
+                        return Err(unsupported!("extended instruction set {}", name));
+                    }
+                }
+                spirv::Op::MemoryModel
+                    if operand(operands, 0)? != spirv::AddressingModel::Logical as u32 =>
+                {
+                    return Err(unsupported!("physical addressing"));
+                }
+                spirv::Op::EntryPoint => {
+                    let model = spirv::ExecutionModel::from_u32(operand(operands, 0)?)
+                        .ok_or_else(|| invalid!("execution model"))?;
+                    let (name, _) = parse_string(&operands[2 ..]);
+                    module.entry_points.push(EntryPoint {
+                        model,
+                        name,
+                        function: operand(operands, 1)?,
+                        local_size: [1; 3],
+                        origin_lower_left: false,
+                        depth_replacing: false,
+                    });
+                }
+                spirv::Op::ExecutionMode => modes.push(operands.to_vec()),
+                spirv::Op::Decorate => {
+                    let decorations = module.decorations.entry(operand(operands, 0)?).or_default();
+                    Self::decorate(decorations, &operands[1 ..])?;
+                }
+                spirv::Op::MemberDecorate => {
+                    let key = (operand(operands, 0)?, operand(operands, 1)?);
+                    let decorations = module.member_decorations.entry(key).or_default();
+                    Self::decorate(decorations, &operands[2 ..])?;
+                }
+                spirv::Op::TypeVoid => module.add_type(operands, Type::Void)?,
+                spirv::Op::TypeBool => module.add_type(operands, Type::Bool)?,
+                spirv::Op::TypeInt | spirv::Op::TypeFloat => {
+                    if operand(operands, 1)? != 32 {
+                        return Err(unsupported!("{}-bit {:?}", operands[1], op));
+                    }
+                    let ty = if op == spirv::Op::TypeInt {
+                        Type::Int {
+                            signed: operand(operands, 2)? != 0,
+                        }
+                    } else {
+                        Type::Float
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeVector => {
+                    let ty = Type::Vector {
+                        component: operand(operands, 1)?,
+                        count: operand(operands, 2)?,
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeMatrix => {
+                    let ty = Type::Matrix {
+                        column: operand(operands, 1)?,
+                        count: operand(operands, 2)?,
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeArray => {
+                    let length = match module.constants.get(&operand(operands, 2)?) {
+                        Some(&Value::Int(length)) => length,
+                        _ => return Err(unsupported!("array length %{}", operands[2])),
+                    };
+                    let ty = Type::Array {
+                        element: operand(operands, 1)?,
+                        length,
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeRuntimeArray => {
+                    let ty = Type::RuntimeArray {
+                        element: operand(operands, 1)?,
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeStruct => {
+                    let ty = Type::Struct {
+                        members: operands[1 ..].to_vec(),
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypePointer => {
+                    let class = spirv::StorageClass::from_u32(operand(operands, 1)?)
+                        .ok_or_else(|| invalid!("storage class"))?;
+                    let ty = Type::Pointer {
+                        class,
+                        pointee: operand(operands, 2)?,
+                    };
+                    module.add_type(operands, ty)?;
+                }
+                spirv::Op::TypeForwardPointer => {}
+                spirv::Op::TypeFunction => module.add_type(operands, Type::Function)?,
+                spirv::Op::TypeImage | spirv::Op::TypeSampler | spirv::Op::TypeSampledImage => {
+                    module.add_type(operands, Type::Opaque)?
+                }
+                spirv::Op::Constant | spirv::Op::SpecConstant => {
+                    let (ty, id) = (operand(operands, 0)?, operand(operands, 1)?);
+                    let bits = operand(operands, 2)?;
+                    let value = match module.types.get(&ty) {
+                        Some(Type::Int { .. }) => Value::Int(bits),
+                        Some(Type::Float) => Value::Float(f32::from_bits(bits)),
+                        _ => return Err(invalid!("constant of type %{}", ty)),
+                    };
+                    if op == spirv::Op::SpecConstant {
+                        module.spec_constants.push((id, ty));
+                    }
+                    module.constants.insert(id, value);
+                }
+                spirv::Op::ConstantTrue
+                | spirv::Op::ConstantFalse
+                | spirv::Op::SpecConstantTrue
+                | spirv::Op::SpecConstantFalse => {
+                    let (ty, id) = (operand(operands, 0)?, operand(operands, 1)?);
+                    let value = op == spirv::Op::ConstantTrue || op == spirv::Op::SpecConstantTrue;
+                    if op == spirv::Op::SpecConstantTrue || op == spirv::Op::SpecConstantFalse {
+                        module.spec_constants.push((id, ty));
+                    }
+                    module.constants.insert(id, Value::Bool(value));
+                }
+                spirv::Op::ConstantComposite | spirv::Op::SpecConstantComposite => {
+                    let id = operand(operands, 1)?;
+                    let constituents = operands[2 ..].to_vec();
+                    module.build_composite(id, &constituents)?;
+                    if op == spirv::Op::SpecConstantComposite {
+                        module.spec_composites.push((id, constituents));
+                    }
+                }
+                spirv::Op::ConstantNull | spirv::Op::Undef => {
+                    let value = module.zero(operand(operands, 0)?)?;
+                    module.constants.insert(operand(operands, 1)?, value);
+                }
+                spirv::Op::SpecConstantOp => {
+                    return Err(unsupported!("specialization constant operations"));
+                }
+                spirv::Op::Variable => {
+                    let (ty, id) = (operand(operands, 0)?, operand(operands, 1)?);
+                    let (class, pointee) = match module.types.get(&ty) {
+                        Some(&Type::Pointer { class, pointee }) => (class, pointee),
+                        _ => return Err(invalid!("variable %{} is not a pointer", id)),
+                    };
+                    module.variables.insert(
+                        id,
+                        Variable {
+                            class,
+                            ty: pointee,
+                            initializer: operands.get(3).cloned(),
+                        },
+                    );
+                }
+                spirv::Op::Function => {
+                    current = Some((operand(operands, 1)?, Vec::new(), Vec::new()));
+                }
+                _ => {
+                    // Debug information, capabilities and extensions.
+                }
+            }
+        }
+
+        if current.is_some() {
+            return Err(invalid!("unterminated function"));
+        }
+
+        for mode in modes {
+            let entry = match module
+                .entry_points
+                .iter_mut()
+                .find(|entry| entry.function == mode[0])
+            {
+                Some(entry) => entry,
+                None => continue,
+            };
+            match spirv::ExecutionMode::from_u32(operand(&mode, 1)?) {
+                Some(spirv::ExecutionMode::LocalSize) => {
+                    entry.local_size = [operand(&mode, 2)?, operand(&mode, 3)?, operand(&mode, 4)?];
+                }
+                Some(spirv::ExecutionMode::OriginLowerLeft) => entry.origin_lower_left = true,
+                Some(spirv::ExecutionMode::DepthReplacing) => entry.depth_replacing = true,
+                _ => {}
+            }
+        }
+
+        module.functions = Arc::new(functions);
+        Ok(module)
+    }
+
+    fn decorate(decorations: &mut Decorations, operands: &[u32]) -> Result<(), Error> {
+        let literal = operands.get(1).cloned();
+        match spirv::Decoration::from_u32(operand(operands, 0)?) {
+            Some(spirv::Decoration::Binding) => decorations.binding = literal,
+            Some(spirv::Decoration::DescriptorSet) => decorations.set = literal,
+            Some(spirv::Decoration::Location) => decorations.location = literal,
+            Some(spirv::Decoration::Offset) => decorations.offset = literal,
+            Some(spirv::Decoration::ArrayStride) => decorations.array_stride = literal,
+            Some(spirv::Decoration::MatrixStride) => decorations.matrix_stride = literal,
+            Some(spirv::Decoration::SpecId) => decorations.spec_id = literal,
+            Some(spirv::Decoration::RowMajor) => decorations.row_major = true,
+            Some(spirv::Decoration::Flat) => decorations.flat = true,
+            Some(spirv::Decoration::BuiltIn) => {
+                decorations.builtin = spirv::BuiltIn::from_u32(operand(operands, 1)?);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn add_type(&mut self, operands: &[u32], ty: Type) -> Result<(), Error> {
+        self.types.insert(operand(operands, 0)?, ty);
+        Ok(())
+    }
+
+    fn build_composite(&mut self, id: Id, constituents: &[Id]) -> Result<(), Error> {
+        let values = constituents
+            .iter()
+            .map(|constituent| {
+                self.constants
+                    .get(constituent)
+                    .cloned()
+                    .ok_or_else(|| invalid!("constant %{} is not defined", constituent))
+            })
+            .collect::<Result<_, _>>()?;
+        self.constants.insert(id, Value::Composite(values));
+        Ok(())
+    }
+
+    pub(crate) fn ty(&self, id: Id) -> Result<&Type, Error> {
+        self.types
+            .get(&id)
+            .ok_or_else(|| invalid!("type %{} is not defined", id))
+    }
+
+    pub(crate) fn decorations(&self, id: Id) -> Option<&Decorations> {
+        self.decorations.get(&id)
+    }
+
+    pub(crate) fn member_decorations(&self, id: Id, member: u32) -> Option<&Decorations> {
+        self.member_decorations.get(&(id, member))
+    }
+
+    pub(crate) fn function(&self, id: Id) -> Result<&Function, Error> {
+        self.functions
+            .get(&id)
+            .ok_or_else(|| invalid!("function %{} is not defined", id))
+    }
+
+    /// The zero value of a type, also used for undefined values.
+    pub(crate) fn zero(&self, ty: Id) -> Result<Value, Error> {
+        Ok(match *self.ty(ty)? {
+            Type::Bool => Value::Bool(false),
+            Type::Int { .. } => Value::Int(0),
+            Type::Float => Value::Float(0.0),
+            Type::Vector { component, count } => {
+                Value::Composite(vec![self.zero(component)?; count as usize])
+            }
+            Type::Matrix { column, count } => {
+                Value::Composite(vec![self.zero(column)?; count as usize])
+            }
+            Type::Array { element, length } => {
+                Value::Composite(vec![self.zero(element)?; length as usize])
+            }
+            Type::Struct { ref members } => Value::Composite(
+                members
+                    .iter()
+                    .map(|&member| self.zero(member))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::RuntimeArray { .. } => Value::Composite(Vec::new()),
+            Type::Void | Type::Pointer { .. } | Type::Function | Type::Opaque => Value::Undef,
+        })
+    }
+
+    /// Interface variable of the storage class at `location`, returning its
+    /// id and the type of its contents.
+    pub(crate) fn interface_variable(
+        &self,
+        class: spirv::StorageClass,
+        location: u32,
+    ) -> Option<(Id, Id)> {
+        self.variables.iter().find_map(|(&id, variable)| {
+            let decorations = self.decorations(id)?;
+            if variable.class == class && decorations.location == Some(location) {
+                Some((id, variable.ty))
+            } else {
+                None
+            }
+        })
+    }
+
+    /// Whether the input at `location` is not interpolated, either because
+    /// it is decorated as flat or because it holds integers.
+    pub fn is_flat_input(&self, location: u32) -> bool {
+        let (id, mut ty) = match self.interface_variable(spirv::StorageClass::Input, location) {
+            Some(variable) => variable,
+            None => return false,
+        };
+        if self.decorations(id).map(|d| d.flat).unwrap_or(false) {
+            return true;
+        }
+        loop {
+            match self.types.get(&ty) {
+                Some(&Type::Vector { component, .. }) => ty = component,
+                Some(&Type::Matrix { column, .. }) => ty = column,
+                Some(&Type::Array { element, .. }) => ty = element,
+                Some(&Type::Float) => return false,
+                _ => return true,
+            }
+        }
+    }
+
+    /// Find the entry point with the given name.
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPoint> {
+        self.entry_points.iter().find(|entry| entry.name == name)
+    }
+
+    /// Local workgroup size of a compute entry point.
+    ///
+    /// A constant decorated with `WorkgroupSize` takes precedence over the
+    /// execution mode, which matters for specialized workgroup sizes.
+    pub fn workgroup_size(&self, entry: &EntryPoint) -> [u32; 3] {
+        let constant =
+            self.decorations
+                .iter()
+                .find_map(|(id, decorations)| match decorations.builtin {
+                    Some(spirv::BuiltIn::WorkgroupSize) => self.constants.get(id),
+                    _ => None,
+                });
+        match constant {
+            Some(Value::Composite(ref components)) if components.len() == 3 => {
+                let mut size = [1; 3];
+                for (size, component) in size.iter_mut().zip(components) {
+                    if let Value::Int(value) = *component {
+                        *size = value;
+                    }
+                }
+                size
+            }
+            _ => entry.local_size,
+        }
+    }
+
+    /// Apply specialization constants, returning the specialized module.
+    pub fn specialize(&self, specialization: &pso::Specialization) -> Result<Self, Error> {
+        let mut module = self.clone();
+        if specialization.constants.is_empty() {
+            return Ok(module);
+        }
+
+        for &(id, ty) in &self.spec_constants {
+            let spec_id = match self.decorations.get(&id).and_then(|d| d.spec_id) {
+                Some(spec_id) => spec_id,
+                None => continue,
+            };
+            let constant = match specialization.constants.iter().find(|c| c.id == spec_id) {
+                Some(constant) => constant,
+                None => continue,
+            };
+            let range = constant.range.start as usize .. constant.range.end as usize;
+            let data = specialization
+                .data
+                .get(range)
+                .ok_or_else(|| invalid!("specialization data of constant {}", spec_id))?;
+            let mut bytes = [0; 4];
+            let size = data.len().min(4);
+            bytes[.. size].copy_from_slice(&data[.. size]);
+            let bits = u32::from_le_bytes(bytes);
+            let value = match *self.ty(ty)? {
+                Type::Bool => Value::Bool(bits != 0),
+                Type::Int { .. } => Value::Int(bits),
+                Type::Float => Value::Float(f32::from_bits(bits)),
+                _ => return Err(invalid!("specialization constant of type %{}", ty)),
+            };
+            module.constants.insert(id, value);
+        }
+
+        for &(id, ref constituents) in &self.spec_composites {
+            module.build_composite(id, constituents)?;
+        }
+        Ok(module)
+    }
+}
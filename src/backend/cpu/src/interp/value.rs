@@ -0,0 +1,399 @@
+use std::sync::Arc;
+
+use super::module::{Id, Module, Type};
+use super::Error;
+use crate::native::Block;
+
+/// A value computed by an instruction or stored in a variable.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Undef,
+    Bool(bool),
+    /// 32-bit integer, the signedness is decided by the instructions using it.
+    Int(u32),
+    Float(f32),
+    /// Vectors, matrices, arrays and structures.
+    Composite(Vec<Value>),
+    Pointer(Pointer),
+}
+
+impl Value {
+    pub fn as_bool(&self) -> Result<bool, Error> {
+        match *self {
+            Value::Bool(value) => Ok(value),
+            ref other => Err(invalid!("expected a boolean, got {:?}", other)),
+        }
+    }
+
+    pub fn as_int(&self) -> Result<u32, Error> {
+        match *self {
+            Value::Int(value) => Ok(value),
+            ref other => Err(invalid!("expected an integer, got {:?}", other)),
+        }
+    }
+
+    pub fn as_float(&self) -> Result<f32, Error> {
+        match *self {
+            Value::Float(value) => Ok(value),
+            ref other => Err(invalid!("expected a float, got {:?}", other)),
+        }
+    }
+
+    pub fn as_pointer(&self) -> Result<&Pointer, Error> {
+        match *self {
+            Value::Pointer(ref pointer) => Ok(pointer),
+            ref other => Err(invalid!("expected a pointer, got {:?}", other)),
+        }
+    }
+
+    pub fn components(&self) -> Result<&[Value], Error> {
+        match *self {
+            Value::Composite(ref components) => Ok(components),
+            ref other => Err(invalid!("expected a composite, got {:?}", other)),
+        }
+    }
+
+    /// Follow a path of indices into nested composites.
+    pub fn member(&self, path: &[u32]) -> Result<&Value, Error> {
+        path.iter().try_fold(self, |value, &index| {
+            value
+                .components()?
+                .get(index as usize)
+                .ok_or_else(|| invalid!("index {} is out of bounds", index))
+        })
+    }
+
+    pub fn member_mut(&mut self, path: &[u32]) -> Result<&mut Value, Error> {
+        let mut value = self;
+        for &index in path {
+            value = match *value {
+                Value::Composite(ref mut components) => components
+                    .get_mut(index as usize)
+                    .ok_or_else(|| invalid!("index {} is out of bounds", index))?,
+                ref other => return Err(invalid!("expected a composite, got {:?}", other)),
+            };
+        }
+        Ok(value)
+    }
+
+    /// Flatten the scalars of the value into raw 32-bit words.
+    pub fn flatten(&self) -> Vec<u32> {
+        fn push(value: &Value, words: &mut Vec<u32>) {
+            match *value {
+                Value::Bool(value) => words.push(value as u32),
+                Value::Int(value) => words.push(value),
+                Value::Float(value) => words.push(value.to_bits()),
+                Value::Composite(ref components) => {
+                    for component in components {
+                        push(component, words);
+                    }
+                }
+                Value::Undef | Value::Pointer(_) => words.push(0),
+            }
+        }
+        let mut words = Vec::new();
+        push(self, &mut words);
+        words
+    }
+}
+
+/// Layout of a matrix in explicitly laid out memory.
+#[derive(Clone, Copy, Debug)]
+pub struct MatrixLayout {
+    pub stride: u32,
+    pub row_major: bool,
+}
+
+/// Pointer to explicitly laid out memory, like buffers and push constants.
+#[derive(Clone, Debug)]
+pub struct MemoryPointer {
+    pub block: Arc<Block>,
+    pub offset: u64,
+    /// End of the accessible range. Accesses past it read zeros and drop
+    /// writes, the same as robust buffer access.
+    pub end: u64,
+    pub ty: Id,
+    pub matrix: Option<MatrixLayout>,
+}
+
+#[derive(Clone, Debug)]
+pub enum Pointer {
+    /// Member at `path` of a variable owned by the invocation.
+    Private {
+        slot: usize,
+        path: Vec<u32>,
+    },
+    /// Member at `path` of a variable shared by the workgroup.
+    Shared {
+        slot: usize,
+        path: Vec<u32>,
+    },
+    Memory(MemoryPointer),
+    /// Array of buffer descriptors stored as pointers in an invocation slot,
+    /// the first index of an access chain selects the descriptor.
+    Descriptors {
+        slot: usize,
+    },
+}
+
+/// Apply `f` to every scalar of `a`.
+pub fn map1<F>(a: &Value, f: &F) -> Result<Value, Error>
+where
+    F: Fn(&Value) -> Result<Value, Error>,
+{
+    match *a {
+        Value::Composite(ref components) => Ok(Value::Composite(
+            components
+                .iter()
+                .map(|a| map1(a, f))
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => f(a),
+    }
+}
+
+/// Apply `f` component-wise, broadcasting scalar operands.
+pub fn map2<F>(a: &Value, b: &Value, f: &F) -> Result<Value, Error>
+where
+    F: Fn(&Value, &Value) -> Result<Value, Error>,
+{
+    let components = match (a, b) {
+        (Value::Composite(a), Value::Composite(b)) => {
+            if a.len() != b.len() {
+                return Err(invalid!("operands of different sizes"));
+            }
+            a.iter()
+                .zip(b)
+                .map(|(a, b)| map2(a, b, f))
+                .collect::<Result<_, _>>()?
+        }
+        (Value::Composite(a), b) => a.iter().map(|a| map2(a, b, f)).collect::<Result<_, _>>()?,
+        (a, Value::Composite(b)) => b.iter().map(|b| map2(a, b, f)).collect::<Result<_, _>>()?,
+        (a, b) => return f(a, b),
+    };
+    Ok(Value::Composite(components))
+}
+
+/// Apply `f` component-wise to three operands, broadcasting scalar operands.
+pub fn map3<F>(a: &Value, b: &Value, c: &Value, f: &F) -> Result<Value, Error>
+where
+    F: Fn(&Value, &Value, &Value) -> Result<Value, Error>,
+{
+    let count = [a, b, c]
+        .iter()
+        .filter_map(|value| match **value {
+            Value::Composite(ref components) => Some(components.len()),
+            _ => None,
+        })
+        .max();
+    match count {
+        Some(count) => {
+            let component = |value: &Value, i: usize| -> Result<Value, Error> {
+                match *value {
+                    Value::Composite(ref components) => components
+                        .get(i)
+                        .cloned()
+                        .ok_or_else(|| invalid!("operands of different sizes")),
+                    ref scalar => Ok(scalar.clone()),
+                }
+            };
+            Ok(Value::Composite(
+                (0 .. count)
+                    .map(|i| map3(&component(a, i)?, &component(b, i)?, &component(c, i)?, f))
+                    .collect::<Result<_, _>>()?,
+            ))
+        }
+        None => f(a, b, c),
+    }
+}
+
+const SCALAR_SIZE: u64 = 4;
+
+impl MemoryPointer {
+    /// Default stride of arrays and matrices lacking explicit decorations.
+    fn default_size(module: &Module, ty: Id) -> Result<u64, Error> {
+        Ok(match *module.ty(ty)? {
+            Type::Bool | Type::Int { .. } | Type::Float => SCALAR_SIZE,
+            Type::Vector { count, .. } => count as u64 * SCALAR_SIZE,
+            Type::Matrix { column, count } => count as u64 * Self::default_size(module, column)?,
+            Type::Array { element, length } => {
+                length as u64 * Self::array_stride(module, ty, element)?
+            }
+            Type::Struct { ref members } => {
+                let mut size = 0;
+                for (i, &member) in members.iter().enumerate() {
+                    let offset = module
+                        .member_decorations(ty, i as u32)
+                        .and_then(|d| d.offset)
+                        .map_or(size, u64::from);
+                    size = size.max(offset + Self::default_size(module, member)?);
+                }
+                size
+            }
+            ref other => return Err(unsupported!("{:?} in explicitly laid out memory", other)),
+        })
+    }
+
+    fn array_stride(module: &Module, array: Id, element: Id) -> Result<u64, Error> {
+        match module.decorations(array).and_then(|d| d.array_stride) {
+            Some(stride) => Ok(stride as u64),
+            None => Self::default_size(module, element),
+        }
+    }
+
+    fn matrix_layout(&self, module: &Module, column: Id) -> Result<MatrixLayout, Error> {
+        match self.matrix {
+            Some(layout) => Ok(layout),
+            None => Ok(MatrixLayout {
+                stride: Self::default_size(module, column)? as u32,
+                row_major: false,
+            }),
+        }
+    }
+
+    /// Pointer to the element `index` of the pointee.
+    pub fn element(&self, module: &Module, index: u32) -> Result<Self, Error> {
+        let (offset, ty, matrix) = match *module.ty(self.ty)? {
+            Type::Struct { ref members } => {
+                let member = *members
+                    .get(index as usize)
+                    .ok_or_else(|| invalid!("member {} is out of bounds", index))?;
+                let decorations = module.member_decorations(self.ty, index);
+                let offset = decorations
+                    .and_then(|d| d.offset)
+                    .ok_or_else(|| invalid!("member {} of %{} has no offset", index, self.ty))?;
+                let matrix = decorations
+                    .and_then(|d| d.matrix_stride.map(|stride| (stride, d.row_major)))
+                    .map(|(stride, row_major)| MatrixLayout { stride, row_major });
+                (offset as u64, member, matrix)
+            }
+            Type::Array { element, .. } | Type::RuntimeArray { element } => {
+                let stride = Self::array_stride(module, self.ty, element)?;
+                (index as u64 * stride, element, self.matrix)
+            }
+            Type::Matrix { column, .. } => {
+                let layout = self.matrix_layout(module, column)?;
+                if layout.row_major {
+                    return Err(unsupported!("pointers to columns of row-major matrices"));
+                }
+                (index as u64 * layout.stride as u64, column, None)
+            }
+            Type::Vector { component, .. } => (index as u64 * SCALAR_SIZE, component, None),
+            ref other => return Err(invalid!("indexing into {:?}", other)),
+        };
+
+        Ok(MemoryPointer {
+            block: self.block.clone(),
+            offset: self.offset + offset,
+            end: self.end,
+            ty,
+            matrix,
+        })
+    }
+
+    /// Number of elements of a run-time array starting at this pointer.
+    pub fn runtime_length(&self, module: &Module) -> Result<u32, Error> {
+        match *module.ty(self.ty)? {
+            Type::RuntimeArray { element } => {
+                let stride = Self::array_stride(module, self.ty, element)?;
+                Ok((self.end.saturating_sub(self.offset) / stride.max(1)) as u32)
+            }
+            ref other => Err(invalid!("length of {:?}", other)),
+        }
+    }
+
+    fn read_word(&self, offset: u64) -> u32 {
+        if offset + SCALAR_SIZE > self.end {
+            return 0;
+        }
+        let mut bytes = [0; 4];
+        bytes.copy_from_slice(unsafe { self.block.slice(offset .. offset + SCALAR_SIZE) });
+        u32::from_le_bytes(bytes)
+    }
+
+    fn write_word(&self, offset: u64, word: u32) {
+        if offset + SCALAR_SIZE <= self.end {
+            unsafe {
+                self.block
+                    .slice_mut(offset .. offset + SCALAR_SIZE)
+                    .copy_from_slice(&word.to_le_bytes());
+            }
+        }
+    }
+
+    pub fn load(&self, module: &Module) -> Result<Value, Error> {
+        Ok(match *module.ty(self.ty)? {
+            Type::Bool => Value::Bool(self.read_word(self.offset) != 0),
+            Type::Int { .. } => Value::Int(self.read_word(self.offset)),
+            Type::Float => Value::Float(f32::from_bits(self.read_word(self.offset))),
+            Type::Matrix { column, count } if self.matrix_layout(module, column)?.row_major => {
+                let layout = self.matrix_layout(module, column)?;
+                let rows = match *module.ty(column)? {
+                    Type::Vector { count, .. } => count,
+                    ref other => return Err(invalid!("matrix column {:?}", other)),
+                };
+                let columns = (0 .. count)
+                    .map(|c| {
+                        let components = (0 .. rows)
+                            .map(|r| {
+                                let offset = self.offset
+                                    + r as u64 * layout.stride as u64
+                                    + c as u64 * SCALAR_SIZE;
+                                Value::Float(f32::from_bits(self.read_word(offset)))
+                            })
+                            .collect();
+                        Value::Composite(components)
+                    })
+                    .collect();
+                Value::Composite(columns)
+            }
+            Type::Vector { count, .. }
+            | Type::Matrix { count, .. }
+            | Type::Array { length: count, .. } => Value::Composite(
+                (0 .. count)
+                    .map(|i| self.element(module, i)?.load(module))
+                    .collect::<Result<_, _>>()?,
+            ),
+            Type::Struct { ref members } => Value::Composite(
+                (0 .. members.len() as u32)
+                    .map(|i| self.element(module, i)?.load(module))
+                    .collect::<Result<_, _>>()?,
+            ),
+            ref other => return Err(unsupported!("loading {:?} from memory", other)),
+        })
+    }
+
+    pub fn store(&self, module: &Module, value: &Value) -> Result<(), Error> {
+        match *module.ty(self.ty)? {
+            Type::Bool | Type::Int { .. } | Type::Float => {
+                let word = match *value {
+                    Value::Bool(value) => value as u32,
+                    Value::Int(value) => value,
+                    Value::Float(value) => value.to_bits(),
+                    ref other => return Err(invalid!("storing {:?} as a scalar", other)),
+                };
+                self.write_word(self.offset, word);
+            }
+            Type::Matrix { column, .. } if self.matrix_layout(module, column)?.row_major => {
+                let layout = self.matrix_layout(module, column)?;
+                for (c, column) in value.components()?.iter().enumerate() {
+                    for (r, component) in column.components()?.iter().enumerate() {
+                        let offset =
+                            self.offset + r as u64 * layout.stride as u64 + c as u64 * SCALAR_SIZE;
+                        self.write_word(offset, component.as_float()?.to_bits());
+                    }
+                }
+            }
+            Type::Vector { .. }
+            | Type::Matrix { .. }
+            | Type::Array { .. }
+            | Type::Struct { .. } => {
+                for (i, component) in value.components()?.iter().enumerate() {
+                    self.element(module, i as u32)?.store(module, component)?;
+                }
+            }
+            ref other => return Err(unsupported!("storing {:?} to memory", other)),
+        }
+        Ok(())
+    }
+}
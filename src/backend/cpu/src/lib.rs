@@ -0,0 +1,424 @@
+//! Host memory implementation of a device.
+//!
+//! Every resource lives in system memory and command buffers are executed
+//! on the host when they are submitted, which allows running code written
+//! against `gfx-hal` on machines without any graphics hardware.
+
+#![allow(missing_docs, missing_copy_implementations)]
+
+#[macro_use]
+extern crate log;
+extern crate gfx_hal as hal;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hal::{adapter, format, image, memory, queue as q, window};
+
+pub use self::device::Device;
+pub use self::queue::CommandQueue;
+
+mod binding;
+mod command;
+mod compute;
+mod conv;
+mod device;
+mod interp;
+mod native;
+mod pool;
+mod queue;
+mod raster;
+mod transfer;
+
+const DEVICE_LOCAL_HEAP: usize = 0;
+const CPU_VISIBLE_HEAP: usize = 1;
+/// Size reported for each of the memory heaps.
+const HEAP_SIZE: u64 = 1 << 30;
+
+// Mimicking vulkan, memory types with more flags come before those with fewer flags.
+const MEMORY_TYPES: [adapter::MemoryType; 3] = [
+    adapter::MemoryType {
+        properties: memory::Properties::CPU_VISIBLE.union(memory::Properties::COHERENT),
+        heap_index: CPU_VISIBLE_HEAP,
+    },
+    adapter::MemoryType {
+        properties: memory::Properties::CPU_VISIBLE.union(memory::Properties::CPU_CACHED),
+        heap_index: CPU_VISIBLE_HEAP,
+    },
+    adapter::MemoryType {
+        properties: memory::Properties::DEVICE_LOCAL,
+        heap_index: DEVICE_LOCAL_HEAP,
+    },
+];
+
+#[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Backend {}
+
+impl hal::Backend for Backend {
+    type Instance = Instance;
+    type PhysicalDevice = PhysicalDevice;
+    type Device = Device;
+
+    type Surface = Surface;
+    type Swapchain = Swapchain;
+
+    type QueueFamily = QueueFamily;
+    type CommandQueue = queue::CommandQueue;
+    type CommandBuffer = command::CommandBuffer;
+
+    type Memory = native::Memory;
+    type CommandPool = pool::CommandPool;
+
+    type ShaderModule = native::ShaderModule;
+    type RenderPass = native::RenderPass;
+    type Framebuffer = native::Framebuffer;
+
+    type Buffer = native::Buffer;
+    type BufferView = native::BufferView;
+    type Image = native::Image;
+    type ImageView = native::ImageView;
+    type Sampler = native::Sampler;
+
+    type ComputePipeline = native::ComputePipeline;
+    type GraphicsPipeline = native::GraphicsPipeline;
+    type PipelineLayout = native::PipelineLayout;
+    type PipelineCache = ();
+    type DescriptorSetLayout = native::DescriptorSetLayout;
+    type DescriptorPool = native::DescriptorPool;
+    type DescriptorSet = native::DescriptorSet;
+
+    type Fence = native::Fence;
+    type Semaphore = native::Semaphore;
+    type Event = native::Event;
+    type QueryPool = native::QueryPool;
+}
+
+/// Internal struct of shared data between the physical and logical device.
+#[derive(Debug)]
+struct Share {
+    limits: hal::Limits,
+    features: hal::Features,
+    /// Bytes currently allocated from each memory heap.
+    heap_usage: [AtomicU64; 2],
+}
+
+impl Share {
+    fn new() -> Self {
+        let limits = hal::Limits {
+            max_image_1d_size: 1 << 14,
+            max_image_2d_size: 1 << 14,
+            max_image_3d_size: 1 << 11,
+            max_image_cube_size: 1 << 14,
+            max_image_array_layers: 1 << 11,
+            max_texel_elements: 1 << 27,
+            max_uniform_buffer_range: 1 << 16,
+            max_storage_buffer_range: 1 << 27,
+            max_push_constants_size: 128,
+            max_memory_allocation_count: 4096,
+            max_sampler_allocation_count: 4000,
+            max_bound_descriptor_sets: 8,
+            max_framebuffer_layers: 1 << 11,
+            max_per_stage_descriptor_samplers: 16,
+            max_per_stage_descriptor_uniform_buffers: 12,
+            max_per_stage_descriptor_storage_buffers: 8,
+            max_per_stage_descriptor_sampled_images: 16,
+            max_per_stage_descriptor_storage_images: 8,
+            max_per_stage_descriptor_input_attachments: 8,
+            max_per_stage_resources: 128,
+            max_descriptor_set_samplers: 96,
+            max_descriptor_set_uniform_buffers: 72,
+            max_descriptor_set_uniform_buffers_dynamic: 8,
+            max_descriptor_set_storage_buffers: 24,
+            max_descriptor_set_storage_buffers_dynamic: 4,
+            max_descriptor_set_sampled_images: 96,
+            max_descriptor_set_storage_images: 24,
+            max_descriptor_set_input_attachments: 8,
+            max_vertex_input_attributes: 16,
+            max_vertex_input_bindings: 16,
+            max_vertex_input_attribute_offset: 2047,
+            max_vertex_input_binding_stride: 2048,
+            max_vertex_output_components: 64,
+            max_fragment_input_components: 64,
+            max_fragment_output_attachments: 4,
+            max_fragment_combined_output_resources: 4,
+            max_compute_shared_memory_size: 1 << 15,
+            max_compute_work_group_count: [1 << 16; 3],
+            max_compute_work_group_invocations: 1024,
+            max_compute_work_group_size: [1024, 1024, 64],
+            max_draw_indexed_index_value: !0,
+            max_draw_indirect_count: !0,
+            max_sampler_lod_bias: 16.0,
+            max_sampler_anisotropy: 1.0,
+            max_viewports: 1,
+            max_viewport_dimensions: [1 << 14; 2],
+            max_framebuffer_extent: image::Extent {
+                width: 1 << 14,
+                height: 1 << 14,
+                depth: 1 << 11,
+            },
+            min_memory_map_alignment: 16,
+            buffer_image_granularity: 1,
+            min_texel_buffer_offset_alignment: 16,
+            min_uniform_buffer_offset_alignment: 16,
+            min_storage_buffer_offset_alignment: 16,
+            framebuffer_color_sample_counts: 1,
+            framebuffer_depth_sample_counts: 1,
+            framebuffer_stencil_sample_counts: 1,
+            max_color_attachments: 4,
+            standard_sample_locations: true,
+            optimal_buffer_copy_offset_alignment: 4,
+            optimal_buffer_copy_pitch_alignment: 4,
+            non_coherent_atom_size: 64,
+            min_vertex_input_binding_stride_alignment: 1,
+            ..hal::Limits::default()
+        };
+
+        Share {
+            limits,
+            // Occlusion queries count every sample that passes.
+            features: hal::Features::PRECISE_OCCLUSION_QUERY,
+            heap_usage: [AtomicU64::new(0), AtomicU64::new(0)],
+        }
+    }
+
+    /// Reserve `size` bytes of the given heap, failing if the heap is exhausted.
+    fn reserve(&self, heap: usize, size: u64) -> bool {
+        let usage = &self.heap_usage[heap];
+        let mut current = usage.load(Ordering::Relaxed);
+        loop {
+            if current + size > HEAP_SIZE {
+                return false;
+            }
+            match usage.compare_exchange_weak(
+                current,
+                current + size,
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => current = actual,
+            }
+        }
+    }
+
+    fn release(&self, heap: usize, size: u64) {
+        self.heap_usage[heap].fetch_sub(size, Ordering::Relaxed);
+    }
+}
+
+#[derive(Debug)]
+pub struct Instance;
+
+impl hal::Instance<Backend> for Instance {
+    fn create(_name: &str, _version: u32) -> Result<Self, hal::UnsupportedBackend> {
+        Ok(Instance)
+    }
+
+    fn enumerate_adapters(&self) -> Vec<adapter::Adapter<Backend>> {
+        vec![adapter::Adapter {
+            info: adapter::AdapterInfo {
+                name: "Host memory device".to_string(),
+                vendor: 0,
+                device: 0,
+                device_type: adapter::DeviceType::Cpu,
+            },
+            physical_device: PhysicalDevice(Arc::new(Share::new())),
+            queue_families: vec![QueueFamily],
+        }]
+    }
+
+    unsafe fn create_surface(
+        &self,
+        _: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Surface, window::InitError> {
+        Err(window::InitError::UnsupportedWindowHandle)
+    }
+
+    unsafe fn destroy_surface(&self, surface: Surface) {
+        match surface {}
+    }
+}
+
+#[derive(Debug)]
+pub struct PhysicalDevice(Arc<Share>);
+
+impl adapter::PhysicalDevice<Backend> for PhysicalDevice {
+    unsafe fn open(
+        &self,
+        families: &[(&QueueFamily, &[q::QueuePriority])],
+        requested_features: hal::Features,
+    ) -> Result<adapter::Gpu<Backend>, hal::device::CreationError> {
+        if !self.features().contains(requested_features) {
+            return Err(hal::device::CreationError::MissingFeature);
+        }
+
+        Ok(adapter::Gpu {
+            device: Device::new(self.0.clone()),
+            queue_groups: families
+                .iter()
+                .map(|&(_family, priorities)| {
+                    let mut family = q::QueueGroup::new(q::QueueFamilyId(0));
+                    for _ in priorities {
+                        family.add_queue(queue::CommandQueue);
+                    }
+                    family
+                })
+                .collect(),
+        })
+    }
+
+    fn format_properties(&self, format: Option<format::Format>) -> format::Properties {
+        use hal::format::{BufferFeature as Bf, ChannelType, ImageFeature as If};
+
+        let format = match format {
+            Some(format) => format,
+            None => return format::Properties::default(),
+        };
+        // Shaders can't access images or texel buffers, so formats are never
+        // sampled or used for storage.
+        let (image, buffer) = if format.is_depth() || format.is_stencil() {
+            (
+                If::DEPTH_STENCIL_ATTACHMENT | If::BLIT_SRC | If::BLIT_DST,
+                Bf::empty(),
+            )
+        } else if conv::is_color_supported(format) {
+            let mut image = If::COLOR_ATTACHMENT | If::BLIT_SRC | If::BLIT_DST;
+            match format.base_format().1 {
+                // Integer values are neither blended nor filtered.
+                ChannelType::Uint | ChannelType::Sint => {}
+                _ => image |= If::COLOR_ATTACHMENT_BLEND | If::SAMPLED_LINEAR,
+            }
+            (image, Bf::VERTEX)
+        } else {
+            // Other formats, like compressed ones, can only be copied.
+            (If::empty(), Bf::empty())
+        };
+
+        // Images are laid out linearly whatever tiling is requested.
+        format::Properties {
+            linear_tiling: image,
+            optimal_tiling: image,
+            buffer_features: buffer,
+        }
+    }
+
+    fn image_format_properties(
+        &self,
+        format: format::Format,
+        dimensions: u8,
+        _tiling: image::Tiling,
+        _usage: image::Usage,
+        _view_caps: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        if format.surface_desc().is_compressed() {
+            return None;
+        }
+        let limits = &self.0.limits;
+        let max_dimension = match dimensions {
+            1 => limits.max_image_1d_size,
+            2 => limits.max_image_2d_size,
+            _ => limits.max_image_3d_size,
+        };
+        Some(image::FormatProperties {
+            max_extent: image::Extent {
+                width: max_dimension,
+                height: if dimensions >= 2 { max_dimension } else { 1 },
+                depth: if dimensions >= 3 { max_dimension } else { 1 },
+            },
+            max_levels: 15,
+            max_layers: limits.max_image_array_layers,
+            sample_count_mask: 1,
+            max_resource_size: HEAP_SIZE as usize,
+        })
+    }
+
+    fn memory_properties(&self) -> adapter::MemoryProperties {
+        adapter::MemoryProperties {
+            memory_types: MEMORY_TYPES.to_vec(),
+            // heap 0 is DEVICE_LOCAL, heap 1 is CPU_VISIBLE
+            memory_heaps: vec![HEAP_SIZE, HEAP_SIZE],
+        }
+    }
+
+    fn features(&self) -> hal::Features {
+        self.0.features
+    }
+
+    fn hints(&self) -> hal::Hints {
+        hal::Hints::BASE_VERTEX_INSTANCE_DRAWING
+    }
+
+    fn limits(&self) -> hal::Limits {
+        self.0.limits
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct QueueFamily;
+
+impl q::QueueFamily for QueueFamily {
+    fn queue_type(&self) -> q::QueueType {
+        q::QueueType::General
+    }
+    fn max_queues(&self) -> usize {
+        1
+    }
+    fn id(&self) -> q::QueueFamilyId {
+        q::QueueFamilyId(0)
+    }
+}
+
+/// Surfaces are not supported, the backend only renders off-screen.
+#[derive(Debug)]
+pub enum Surface {}
+
+impl window::Surface<Backend> for Surface {
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        match *self {}
+    }
+
+    fn capabilities(&self, _: &PhysicalDevice) -> window::SurfaceCapabilities {
+        match *self {}
+    }
+
+    fn supported_formats(&self, _: &PhysicalDevice) -> Option<Vec<format::Format>> {
+        match *self {}
+    }
+}
+
+impl window::PresentationSurface<Backend> for Surface {
+    type SwapchainImage = native::ImageView;
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        _: &Device,
+        _: window::SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        match *self {}
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, _: &Device) {
+        match *self {}
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+    ) -> Result<(native::ImageView, Option<window::Suboptimal>), window::AcquireError> {
+        match *self {}
+    }
+}
+
+#[derive(Debug)]
+pub enum Swapchain {}
+
+impl window::Swapchain<Backend> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+        _: Option<&native::Semaphore>,
+        _: Option<&native::Fence>,
+    ) -> Result<(window::SwapImageIndex, Option<window::Suboptimal>), window::AcquireError> {
+        match *self {}
+    }
+}
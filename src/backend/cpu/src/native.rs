@@ -0,0 +1,468 @@
+use parking_lot::Mutex;
+use std::fmt;
+use std::ops::Range;
+use std::slice;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hal::memory::{Properties, Requirements};
+use hal::{buffer, format, image as i, pass, pso, query};
+
+use crate::{conv, interp};
+
+/// A chunk of host memory backing a `Memory` object.
+///
+/// The contents are accessed without synchronization, the same way a GPU
+/// would access device memory. It's up to the user to insert the required
+/// fences and barriers, the backend only guarantees that the storage stays
+/// alive as long as any resource is bound to it.
+pub struct Block {
+    ptr: *mut u8,
+    len: usize,
+}
+
+unsafe impl Send for Block {}
+unsafe impl Sync for Block {}
+
+impl fmt::Debug for Block {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "Block({} bytes)", self.len)
+    }
+}
+
+impl Drop for Block {
+    fn drop(&mut self) {
+        unsafe {
+            let slice = slice::from_raw_parts_mut(self.ptr, self.len);
+            drop(Box::from_raw(slice as *mut [u8]));
+        }
+    }
+}
+
+impl Block {
+    pub(crate) fn new(size: usize) -> Self {
+        let data = Box::into_raw(vec![0u8; size].into_boxed_slice());
+        Block {
+            ptr: data as *mut u8,
+            len: size,
+        }
+    }
+
+    pub(crate) fn len(&self) -> u64 {
+        self.len as u64
+    }
+
+    pub(crate) fn as_ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub(crate) unsafe fn slice(&self, range: Range<u64>) -> &[u8] {
+        debug_assert!(range.start <= range.end && range.end <= self.len as u64);
+        slice::from_raw_parts(
+            self.ptr.add(range.start as usize),
+            (range.end - range.start) as usize,
+        )
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    pub(crate) unsafe fn slice_mut(&self, range: Range<u64>) -> &mut [u8] {
+        debug_assert!(range.start <= range.end && range.end <= self.len as u64);
+        slice::from_raw_parts_mut(
+            self.ptr.add(range.start as usize),
+            (range.end - range.start) as usize,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct Memory {
+    pub(crate) block: Arc<Block>,
+    pub(crate) properties: Properties,
+    pub(crate) heap_index: usize,
+    /// Host side copy of the memory handed out by `map_memory` for memory types
+    /// which are not `COHERENT`. Writes only reach the block when flushed.
+    pub(crate) mapping: Mutex<Option<Box<[u8]>>>,
+}
+
+/// A buffer range bound to a memory block.
+#[derive(Clone, Debug)]
+pub struct BoundBuffer {
+    pub(crate) block: Arc<Block>,
+    pub(crate) range: Range<buffer::Offset>,
+}
+
+impl BoundBuffer {
+    /// Resolve a sub-range of the buffer into a range of the memory block.
+    pub(crate) fn resolve(&self, sub: &buffer::SubRange) -> Range<buffer::Offset> {
+        let start = self.range.start + sub.offset;
+        let end = sub.size.map_or(self.range.end, |size| start + size);
+        debug_assert!(end <= self.range.end);
+        start .. end
+    }
+}
+
+#[derive(Debug)]
+pub enum Buffer {
+    Unbound {
+        size: buffer::Offset,
+        usage: buffer::Usage,
+    },
+    Bound {
+        bound: BoundBuffer,
+        usage: buffer::Usage,
+    },
+}
+
+impl Buffer {
+    // Asserts that the buffer is bound and returns the memory it is bound to.
+    pub(crate) fn as_bound(&self) -> &BoundBuffer {
+        match self {
+            Buffer::Unbound { .. } => panic!("Expected bound buffer!"),
+            Buffer::Bound { bound, .. } => bound,
+        }
+    }
+}
+
+// Shaders can't access texel buffers yet.
+#[derive(Clone, Debug)]
+pub struct BufferView;
+
+/// Describes how the texels of an image are laid out in memory.
+///
+/// All subresources are stored linearly: level after level, and within
+/// each level all array layers one after another. Rows are tightly packed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImageDesc {
+    pub(crate) kind: i::Kind,
+    pub(crate) format: format::Format,
+    pub(crate) levels: i::Level,
+}
+
+impl ImageDesc {
+    /// Size of a texel block in bytes.
+    pub(crate) fn block_size(&self) -> u64 {
+        conv::block_size(self.format) as u64
+    }
+
+    /// Dimensions of a texel block.
+    pub(crate) fn block_dim(&self) -> (u32, u32) {
+        let (w, h) = self.format.surface_desc().dim;
+        (w as u32, h as u32)
+    }
+
+    pub(crate) fn footprint(&self, level: i::Level) -> i::SubresourceFootprint {
+        let extent = self.kind.level_extent(level);
+        let (bw, bh) = self.block_dim();
+        let row_pitch = extent.width.div_ceil(bw) as u64 * self.block_size();
+        let depth_pitch = extent.height.div_ceil(bh) as u64 * row_pitch;
+        let array_pitch = extent.depth as u64 * depth_pitch;
+        let start = (0 .. level).map(|l| self.level_size(l)).sum();
+        i::SubresourceFootprint {
+            slice: start .. start + self.level_size(level),
+            row_pitch,
+            array_pitch,
+            depth_pitch,
+        }
+    }
+
+    fn level_size(&self, level: i::Level) -> u64 {
+        let extent = self.kind.level_extent(level);
+        let (bw, bh) = self.block_dim();
+        let blocks = extent.width.div_ceil(bw) as u64 * extent.height.div_ceil(bh) as u64;
+        blocks * extent.depth as u64 * self.block_size() * self.kind.num_layers() as u64
+    }
+
+    pub(crate) fn size(&self) -> u64 {
+        (0 .. self.levels).map(|l| self.level_size(l)).sum()
+    }
+
+    /// Byte offset of a texel block, relative to the start of the image.
+    ///
+    /// Coordinates are given in texels and have to be aligned to the block dimensions.
+    pub(crate) fn offset_of(
+        &self,
+        level: i::Level,
+        layer: i::Layer,
+        x: u32,
+        y: u32,
+        z: u32,
+    ) -> u64 {
+        let footprint = self.footprint(level);
+        let (bw, bh) = self.block_dim();
+        footprint.slice.start
+            + layer as u64 * footprint.array_pitch
+            + z as u64 * footprint.depth_pitch
+            + (y / bh) as u64 * footprint.row_pitch
+            + (x / bw) as u64 * self.block_size()
+    }
+}
+
+/// An image bound to a memory block.
+#[derive(Clone, Debug)]
+pub struct BoundImage {
+    pub(crate) block: Arc<Block>,
+    pub(crate) offset: u64,
+    pub(crate) desc: ImageDesc,
+}
+
+impl BoundImage {
+    /// Absolute byte offset of a texel block inside the memory block.
+    pub(crate) fn texel_offset(
+        &self,
+        level: i::Level,
+        layer: i::Layer,
+        x: u32,
+        y: u32,
+        z: u32,
+    ) -> u64 {
+        self.offset + self.desc.offset_of(level, layer, x, y, z)
+    }
+}
+
+#[derive(Debug)]
+pub struct Image {
+    pub(crate) desc: ImageDesc,
+    pub(crate) requirements: Requirements,
+    pub(crate) bound: Option<BoundImage>,
+}
+
+impl Image {
+    // Asserts that the image is bound and returns the memory it is bound to.
+    pub(crate) fn as_bound(&self) -> &BoundImage {
+        self.bound.as_ref().expect("Expected bound image!")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ImageView {
+    pub(crate) image: BoundImage,
+    pub(crate) format: format::Format,
+    pub(crate) range: i::SubresourceRange,
+}
+
+// Shaders can't sample images yet.
+#[derive(Clone, Debug)]
+pub struct Sampler;
+
+#[derive(Debug)]
+pub struct ShaderModule {
+    pub(crate) module: Arc<interp::Module>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ComputePipeline {
+    /// Module with the specialization constants applied.
+    pub(crate) module: Arc<interp::Module>,
+    pub(crate) entry: interp::EntryPoint,
+    pub(crate) local_size: [u32; 3],
+}
+
+/// A shader entry point of a graphics pipeline.
+#[derive(Clone, Debug)]
+pub struct ShaderStage {
+    /// Module with the specialization constants applied.
+    pub(crate) module: Arc<interp::Module>,
+    pub(crate) entry: interp::EntryPoint,
+}
+
+#[derive(Clone, Debug)]
+pub struct GraphicsPipeline {
+    pub(crate) vertex: ShaderStage,
+    pub(crate) fragment: Option<ShaderStage>,
+    pub(crate) vertex_buffers: Vec<pso::VertexBufferDesc>,
+    pub(crate) attributes: Vec<pso::AttributeDesc>,
+    pub(crate) input_assembler: pso::InputAssemblerDesc,
+    pub(crate) rasterizer: pso::Rasterizer,
+    pub(crate) blender: pso::BlendDesc,
+    pub(crate) depth_stencil: pso::DepthStencilDesc,
+    pub(crate) baked_states: pso::BakedStates,
+}
+
+#[derive(Clone, Debug)]
+pub struct RenderPass {
+    pub(crate) attachments: Vec<pass::Attachment>,
+    pub(crate) subpasses: Vec<SubpassDesc>,
+}
+
+#[derive(Clone, Debug)]
+pub struct SubpassDesc {
+    pub(crate) colors: Vec<pass::AttachmentRef>,
+    pub(crate) depth_stencil: Option<pass::AttachmentRef>,
+    pub(crate) resolves: Vec<pass::AttachmentRef>,
+}
+
+#[derive(Debug)]
+pub struct Framebuffer {
+    pub(crate) attachments: Vec<ImageView>,
+    pub(crate) extent: i::Extent,
+}
+
+#[derive(Debug)]
+pub struct DescriptorSetLayout {
+    pub(crate) bindings: Vec<pso::DescriptorSetLayoutBinding>,
+}
+
+// Descriptor sets are bound by index and push constants by offset, so the
+// layout has nothing to remember.
+#[derive(Debug)]
+pub struct PipelineLayout;
+
+#[derive(Clone, Debug)]
+pub enum Descriptor {
+    Buffer(BoundBuffer, buffer::SubRange),
+    /// Images, samplers and texel buffers, which shaders can't access yet.
+    Unsupported,
+}
+
+#[derive(Clone, Debug)]
+pub struct DescriptorSet {
+    pub(crate) layout: Arc<Vec<pso::DescriptorSetLayoutBinding>>,
+    /// Descriptors of each layout binding, indexed the same way as `layout`.
+    pub(crate) bindings: Arc<Mutex<Vec<Vec<Option<Descriptor>>>>>,
+}
+
+impl DescriptorSet {
+    pub(crate) fn new(layout: &DescriptorSetLayout) -> Self {
+        let bindings = layout
+            .bindings
+            .iter()
+            .map(|binding| vec![None; binding.count])
+            .collect();
+        DescriptorSet {
+            layout: Arc::new(layout.bindings.clone()),
+            bindings: Arc::new(Mutex::new(bindings)),
+        }
+    }
+
+    /// Index of the given binding number in the layout.
+    pub(crate) fn binding_index(&self, binding: pso::DescriptorBinding) -> Option<usize> {
+        self.layout.iter().position(|b| b.binding == binding)
+    }
+}
+
+#[derive(Debug)]
+pub struct DescriptorPool;
+
+impl pso::DescriptorPool<crate::Backend> for DescriptorPool {
+    unsafe fn allocate_set(
+        &mut self,
+        layout: &DescriptorSetLayout,
+    ) -> Result<DescriptorSet, pso::AllocationError> {
+        Ok(DescriptorSet::new(layout))
+    }
+
+    unsafe fn free<I>(&mut self, _descriptor_sets: I)
+    where
+        I: IntoIterator<Item = DescriptorSet>,
+    {
+        // Descriptor sets own their storage, dropping them is enough.
+    }
+
+    unsafe fn reset(&mut self) {}
+}
+
+#[derive(Debug)]
+pub struct Fence(pub(crate) AtomicBool);
+
+impl Fence {
+    pub(crate) fn is_signaled(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set(&self, signaled: bool) {
+        self.0.store(signaled, Ordering::Release)
+    }
+}
+
+#[derive(Debug)]
+// Submissions are executed in order on the host, so there is nothing to wait for.
+pub struct Semaphore;
+
+#[derive(Clone, Debug)]
+pub struct Event(pub(crate) Arc<AtomicBool>);
+
+impl Event {
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.load(Ordering::Acquire)
+    }
+
+    pub(crate) fn set(&self, value: bool) {
+        self.0.store(value, Ordering::Release)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub(crate) struct QueryResult {
+    pub(crate) value: u64,
+    pub(crate) available: bool,
+}
+
+/// Occlusion or timestamp queries, shared with the command buffers writing them.
+#[derive(Clone, Debug)]
+pub struct QueryPool(pub(crate) Arc<Mutex<Vec<QueryResult>>>);
+
+impl QueryPool {
+    pub(crate) fn new(count: query::Id) -> Self {
+        QueryPool(Arc::new(Mutex::new(vec![
+            QueryResult::default();
+            count as usize
+        ])))
+    }
+
+    pub(crate) fn reset(&self, queries: Range<query::Id>) {
+        for result in &mut self.0.lock()[queries.start as usize .. queries.end as usize] {
+            *result = QueryResult::default();
+        }
+    }
+
+    /// Add samples which passed the depth and stencil tests to an active query.
+    pub(crate) fn add(&self, id: query::Id, samples: u64) {
+        self.0.lock()[id as usize].value += samples;
+    }
+
+    /// Make a query available, optionally replacing its value.
+    pub(crate) fn end(&self, id: query::Id, value: Option<u64>) {
+        let result = &mut self.0.lock()[id as usize];
+        if let Some(value) = value {
+            result.value = value;
+        }
+        result.available = true;
+    }
+
+    /// Write the results of `queries` into `data`, in the layout Vulkan
+    /// describes for `flags`. Returns whether all of them were available.
+    ///
+    /// Everything submitted has executed by now, so `WAIT` can't change the
+    /// outcome and is ignored.
+    pub(crate) fn write_results(
+        &self,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> bool {
+        let size = if flags.contains(query::ResultFlags::BITS_64) {
+            8
+        } else {
+            4
+        };
+        let results = self.0.lock();
+        let mut all_available = true;
+        for (i, id) in queries.enumerate() {
+            let result = results[id as usize];
+            all_available &= result.available;
+            let start = i * stride as usize;
+            let mut write = |offset: usize, value: u64| {
+                let bytes = value.to_le_bytes();
+                data[start + offset .. start + offset + size].copy_from_slice(&bytes[.. size]);
+            };
+            if result.available || flags.contains(query::ResultFlags::PARTIAL) {
+                write(0, result.value);
+            }
+            if flags.contains(query::ResultFlags::WITH_AVAILABILITY) {
+                write(size, result.available as u64);
+            }
+        }
+        all_available
+    }
+}
@@ -0,0 +1,22 @@
+use crate::command::CommandBuffer;
+use crate::Backend;
+
+#[derive(Debug)]
+pub struct CommandPool;
+
+impl hal::pool::CommandPool<Backend> for CommandPool {
+    unsafe fn reset(&mut self, _release_resources: bool) {
+        // Command buffers own their recorded commands and are reset
+        // individually when they begin recording again.
+    }
+
+    unsafe fn allocate_one(&mut self, _level: hal::command::Level) -> CommandBuffer {
+        CommandBuffer::new()
+    }
+
+    unsafe fn free<I>(&mut self, _buffers: I)
+    where
+        I: IntoIterator<Item = CommandBuffer>,
+    {
+    }
+}
@@ -0,0 +1,189 @@
+use hal::{device, pso, queue, window};
+
+use crate::command::{Command, CommandBuffer};
+use crate::{compute, native as n, raster, transfer, Backend, Surface, Swapchain};
+
+use std::borrow::Borrow;
+use std::time;
+
+/// Queue executing submitted command buffers on the calling thread.
+#[derive(Debug)]
+pub struct CommandQueue;
+
+impl CommandQueue {
+    unsafe fn execute(&mut self, command: &Command) {
+        match *command {
+            Command::CopyBuffer {
+                ref src,
+                ref dst,
+                ref regions,
+            } => {
+                for region in regions {
+                    transfer::copy_buffer(src, dst, region);
+                }
+            }
+            Command::FillBuffer {
+                ref dst,
+                ref range,
+                data,
+            } => transfer::fill_buffer(dst, range, data),
+            Command::UpdateBuffer {
+                ref dst,
+                offset,
+                ref data,
+            } => transfer::update_buffer(dst, offset, data),
+            Command::CopyImage {
+                ref src,
+                ref dst,
+                ref regions,
+            } => {
+                for region in regions {
+                    transfer::copy_image(src, dst, region);
+                }
+            }
+            Command::CopyBufferToImage {
+                ref src,
+                ref dst,
+                ref regions,
+            } => {
+                for region in regions {
+                    transfer::copy_buffer_image(src, dst, region, true);
+                }
+            }
+            Command::CopyImageToBuffer {
+                ref src,
+                ref dst,
+                ref regions,
+            } => {
+                for region in regions {
+                    transfer::copy_buffer_image(dst, src, region, false);
+                }
+            }
+            Command::BlitImage {
+                ref src,
+                ref dst,
+                filter,
+                ref regions,
+            } => {
+                for region in regions {
+                    transfer::blit_image(src, dst, filter, region);
+                }
+            }
+            Command::ClearImage {
+                ref dst,
+                value,
+                ref ranges,
+            } => {
+                for range in ranges {
+                    transfer::clear_image(dst, value, range);
+                }
+            }
+            Command::SetEvent(ref event, value) => event.set(value),
+            Command::ResetQueries(ref pool, ref queries) => pool.reset(queries.clone()),
+            Command::BeginQuery(ref pool, id) => pool.reset(id .. id + 1),
+            Command::EndQuery(ref pool, id) => pool.end(id, None),
+            Command::WriteTimestamp(ref pool, id) => {
+                let time = time::SystemTime::now()
+                    .duration_since(time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                pool.end(id, Some(time.as_nanos() as u64));
+            }
+            Command::CopyQueryResults {
+                ref pool,
+                ref queries,
+                ref dst,
+                offset,
+                stride,
+                flags,
+            } => {
+                let start = dst.range.start + offset;
+                let data = dst.block.slice_mut(start .. dst.range.end);
+                pool.write_results(queries.clone(), data, stride, flags);
+            }
+            Command::Dispatch { ref state, count } => compute::dispatch(state, count),
+            Command::DispatchIndirect {
+                ref state,
+                ref buffer,
+                offset,
+            } => compute::dispatch_indirect(state, buffer, offset),
+            Command::ClearAttachments {
+                ref clears,
+                ref rects,
+            } => {
+                for &(ref view, aspects, value) in clears {
+                    for rect in rects {
+                        transfer::clear_rect(view, aspects, value, rect.rect, rect.layers.clone());
+                    }
+                }
+            }
+            Command::Draw {
+                ref state,
+                ref vertices,
+                ref instances,
+            } => raster::draw(state, vertices.clone(), instances.clone()),
+            Command::DrawIndirect {
+                ref state,
+                ref buffer,
+                offset,
+                draw_count,
+                stride,
+                indexed,
+            } => raster::draw_indirect(state, buffer, offset, draw_count, stride, indexed),
+        }
+    }
+}
+
+impl queue::CommandQueue<Backend> for CommandQueue {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        submit_info: queue::Submission<Ic, Iw, Is>,
+        fence: Option<&n::Fence>,
+    ) where
+        T: 'a + Borrow<CommandBuffer>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<n::Semaphore>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        for cmd_buffer in submit_info.command_buffers {
+            for command in &cmd_buffer.borrow().commands {
+                self.execute(command);
+            }
+        }
+
+        if let Some(fence) = fence {
+            fence.set(true);
+        }
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        swapchains: Is,
+        _wait_semaphores: Iw,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError>
+    where
+        W: 'a + Borrow<Swapchain>,
+        Is: IntoIterator<Item = (&'a W, window::SwapImageIndex)>,
+        S: 'a + Borrow<n::Semaphore>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        match swapchains.into_iter().next() {
+            Some((swapchain, _)) => match *swapchain.borrow() {},
+            None => Ok(None),
+        }
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        surface: &mut Surface,
+        _image: n::ImageView,
+        _wait_semaphore: Option<&n::Semaphore>,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError> {
+        match *surface {}
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        // Submissions are complete by the time `submit` returns.
+        Ok(())
+    }
+}
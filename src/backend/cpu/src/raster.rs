@@ -0,0 +1,1217 @@
+//! Host rasterization of draw calls.
+//!
+//! Primitives go through the fixed function stages in the order Vulkan
+//! describes them: vertex shading, clipping against the near and far planes,
+//! the viewport transform, culling and rasterization at pixel centres.
+//! Fragments are then shaded one by one and written to the attachments of the
+//! current subpass after the depth and stencil tests and blending.
+
+use hal::format::{Aspects, ChannelType};
+use hal::{buffer, command as com, pso, query, IndexType};
+
+use crate::binding::{Bindings, Resources};
+use crate::interp::{self, BuiltIn, Invocation, Status, Workgroup};
+use crate::{conv, native as n};
+
+use std::cell::Cell;
+use std::collections::hash_map::{Entry, HashMap};
+use std::ops::Range;
+
+/// Attachments of the current subpass.
+#[derive(Clone, Debug)]
+pub(crate) struct Targets {
+    pub(crate) colors: Vec<n::ImageView>,
+    pub(crate) depth_stencil: Option<n::ImageView>,
+    /// Render area of the render pass.
+    pub(crate) area: pso::Rect,
+}
+
+/// Pipeline, resources and dynamic state bound for drawing.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct GraphicsState {
+    pub(crate) pipeline: Option<n::GraphicsPipeline>,
+    pub(crate) bindings: Bindings,
+    /// Bound vertex buffers, with the binding offset applied.
+    pub(crate) vertex_buffers: Vec<Option<n::BoundBuffer>>,
+    pub(crate) index_buffer: Option<(n::BoundBuffer, IndexType)>,
+    pub(crate) viewport: Option<pso::Viewport>,
+    pub(crate) scissor: Option<pso::Rect>,
+    pub(crate) blend_constants: pso::ColorValue,
+    pub(crate) stencil_reference: pso::Sided<pso::StencilValue>,
+    pub(crate) stencil_read_mask: pso::Sided<pso::StencilValue>,
+    pub(crate) stencil_write_mask: pso::Sided<pso::StencilValue>,
+    pub(crate) depth_bounds: Range<f32>,
+    pub(crate) depth_bias: pso::DepthBias,
+    pub(crate) targets: Option<Targets>,
+    /// Occlusion query counting the samples of the draws.
+    pub(crate) occlusion_query: Option<(n::QueryPool, query::Id)>,
+}
+
+impl GraphicsState {
+    pub(crate) fn bind_vertex_buffers<I>(&mut self, first_binding: usize, buffers: I)
+    where
+        I: IntoIterator<Item = n::BoundBuffer>,
+    {
+        for (i, buffer) in buffers.into_iter().enumerate() {
+            let index = first_binding + i;
+            if self.vertex_buffers.len() <= index {
+                self.vertex_buffers.resize(index + 1, None);
+            }
+            self.vertex_buffers[index] = Some(buffer);
+        }
+    }
+}
+
+/// Where the vertices of a draw come from.
+#[derive(Clone, Debug)]
+pub(crate) enum Vertices {
+    Sequential(Range<hal::VertexCount>),
+    Indexed {
+        indices: Range<hal::IndexCount>,
+        base_vertex: hal::VertexOffset,
+    },
+}
+
+/// Output of the vertex shader.
+#[derive(Clone, Debug)]
+struct Vertex {
+    /// Position in clip space.
+    position: [f32; 4],
+    point_size: f32,
+    /// Outputs with a location, ordered by location.
+    outputs: Vec<(u32, Vec<u32>)>,
+}
+
+impl Vertex {
+    fn lerp(&self, other: &Vertex, t: f32) -> Vertex {
+        let mix = |a: f32, b: f32| a + (b - a) * t;
+        let mut position = [0.0; 4];
+        for (i, position) in position.iter_mut().enumerate() {
+            *position = mix(self.position[i], other.position[i]);
+        }
+        let outputs = self
+            .outputs
+            .iter()
+            .zip(&other.outputs)
+            .map(|((location, a), (_, b))| {
+                let words = a
+                    .iter()
+                    .zip(b)
+                    .map(|(&a, &b)| mix(f32::from_bits(a), f32::from_bits(b)).to_bits())
+                    .collect();
+                (*location, words)
+            })
+            .collect();
+        Vertex {
+            position,
+            point_size: mix(self.point_size, other.point_size),
+            outputs,
+        }
+    }
+}
+
+/// A vertex after the viewport transform.
+struct Screen<'a> {
+    x: f32,
+    y: f32,
+    z: f32,
+    /// Reciprocal of the clip space `w`.
+    w: f32,
+    point_size: f32,
+    outputs: &'a [(u32, Vec<u32>)],
+}
+
+/// Edge function of the edge `a -> b`, positive to its right in framebuffer
+/// coordinates.
+fn edge(a: &Screen, b: &Screen, x: f32, y: f32) -> f32 {
+    (b.x - a.x) * (y - a.y) - (b.y - a.y) * (x - a.x)
+}
+
+/// Whether the edge `a -> b` of a triangle with positive area is a top or a
+/// left edge, which own the pixel centres lying exactly on them.
+fn is_top_left(a: &Screen, b: &Screen) -> bool {
+    (a.y == b.y && b.x > a.x) || b.y < a.y
+}
+
+/// Pixels within `bounds` whose centre is covered by the triangle `a, b, c`,
+/// with the barycentric weights of the centre. The edge functions must be
+/// positive inside the triangle, `area` being twice its area.
+fn coverage(
+    a: &Screen,
+    b: &Screen,
+    c: &Screen,
+    area: f32,
+    bounds: &(Range<i32>, Range<i32>),
+) -> Vec<(i32, i32, [f32; 3])> {
+    let min_x = a.x.min(b.x).min(c.x).floor() as i32;
+    let max_x = a.x.max(b.x).max(c.x).ceil() as i32;
+    let min_y = a.y.min(b.y).min(c.y).floor() as i32;
+    let max_y = a.y.max(b.y).max(c.y).ceil() as i32;
+    let edges = [(b, c), (c, a), (a, b)];
+    let mut pixels = Vec::new();
+    for y in min_y.max(bounds.1.start) .. max_y.min(bounds.1.end) {
+        for x in min_x.max(bounds.0.start) .. max_x.min(bounds.0.end) {
+            let (px, py) = (x as f32 + 0.5, y as f32 + 0.5);
+            let mut weights = [0.0; 3];
+            let inside = edges.iter().zip(&mut weights).all(|(&(p, q), weight)| {
+                let value = edge(p, q, px, py);
+                *weight = value / area;
+                value > 0.0 || (value == 0.0 && is_top_left(p, q))
+            });
+            if inside {
+                pixels.push((x, y, weights));
+            }
+        }
+    }
+    pixels
+}
+
+/// Perspective correct the screen space barycentric `weights` of `vertices`.
+fn perspective_weights(vertices: &[&Screen], weights: &[f32]) -> Vec<f32> {
+    let weights = vertices
+        .iter()
+        .zip(weights)
+        .map(|(vertex, weight)| weight * vertex.w)
+        .collect::<Vec<_>>();
+    let sum: f32 = weights.iter().sum();
+    weights.into_iter().map(|weight| weight / sum).collect()
+}
+
+/// Clip a polygon against the plane where `distance` is positive.
+fn clip(polygon: Vec<Vertex>, distance: impl Fn(&[f32; 4]) -> f32) -> Vec<Vertex> {
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, a) in polygon.iter().enumerate() {
+        let b = &polygon[(i + 1) % polygon.len()];
+        let (da, db) = (distance(&a.position), distance(&b.position));
+        if da >= 0.0 {
+            clipped.push(a.clone());
+        }
+        if (da >= 0.0) != (db >= 0.0) {
+            clipped.push(a.lerp(b, da / (da - db)));
+        }
+    }
+    clipped
+}
+
+fn compare<T: PartialOrd>(fun: pso::Comparison, a: T, b: T) -> bool {
+    match fun {
+        pso::Comparison::Never => false,
+        pso::Comparison::Less => a < b,
+        pso::Comparison::Equal => a == b,
+        pso::Comparison::LessEqual => a <= b,
+        pso::Comparison::Greater => a > b,
+        pso::Comparison::NotEqual => a != b,
+        pso::Comparison::GreaterEqual => a >= b,
+        pso::Comparison::Always => true,
+    }
+}
+
+fn stencil_op(op: pso::StencilOp, value: u8, reference: u8) -> u8 {
+    match op {
+        pso::StencilOp::Keep => value,
+        pso::StencilOp::Zero => 0,
+        pso::StencilOp::Replace => reference,
+        pso::StencilOp::IncrementClamp => value.saturating_add(1),
+        pso::StencilOp::DecrementClamp => value.saturating_sub(1),
+        pso::StencilOp::Invert => !value,
+        pso::StencilOp::IncrementWrap => value.wrapping_add(1),
+        pso::StencilOp::DecrementWrap => value.wrapping_sub(1),
+    }
+}
+
+/// Blend factor for `channel`. Dual source blending is not supported, the
+/// second source is the same as the first one.
+fn blend_factor(
+    factor: pso::Factor,
+    src: &[f32; 4],
+    dst: &[f32; 4],
+    constant: &[f32; 4],
+    channel: usize,
+) -> f32 {
+    use hal::pso::Factor::*;
+    match factor {
+        Zero => 0.0,
+        One => 1.0,
+        SrcColor | Src1Color => src[channel],
+        OneMinusSrcColor | OneMinusSrc1Color => 1.0 - src[channel],
+        DstColor => dst[channel],
+        OneMinusDstColor => 1.0 - dst[channel],
+        SrcAlpha | Src1Alpha => src[3],
+        OneMinusSrcAlpha | OneMinusSrc1Alpha => 1.0 - src[3],
+        DstAlpha => dst[3],
+        OneMinusDstAlpha => 1.0 - dst[3],
+        ConstColor => constant[channel],
+        OneMinusConstColor => 1.0 - constant[channel],
+        ConstAlpha => constant[3],
+        OneMinusConstAlpha => 1.0 - constant[3],
+        SrcAlphaSaturate if channel == 3 => 1.0,
+        SrcAlphaSaturate => src[3].min(1.0 - dst[3]),
+    }
+}
+
+fn blend(state: &pso::BlendState, src: &[f32; 4], dst: &[f32; 4], constant: &[f32; 4]) -> [f32; 4] {
+    let mut color = [0.0; 4];
+    for (channel, color) in color.iter_mut().enumerate() {
+        let op = if channel == 3 {
+            state.alpha
+        } else {
+            state.color
+        };
+        let factor = |factor| blend_factor(factor, src, dst, constant, channel);
+        let (s, d) = (src[channel], dst[channel]);
+        *color = match op {
+            pso::BlendOp::Add { src, dst } => s * factor(src) + d * factor(dst),
+            pso::BlendOp::Sub { src, dst } => s * factor(src) - d * factor(dst),
+            pso::BlendOp::RevSub { src, dst } => d * factor(dst) - s * factor(src),
+            pso::BlendOp::Min => s.min(d),
+            pso::BlendOp::Max => s.max(d),
+        };
+    }
+    color
+}
+
+unsafe fn read_words(buffer: &n::BoundBuffer, offset: buffer::Offset, count: usize) -> Vec<u32> {
+    let start = buffer.range.start + offset;
+    let bytes = buffer.block.slice(start .. start + count as u64 * 4);
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Everything needed to rasterize the primitives of a draw.
+struct Context<'a> {
+    state: &'a GraphicsState,
+    pipeline: &'a n::GraphicsPipeline,
+    targets: &'a Targets,
+    resources: Resources<'a>,
+    viewport: pso::Viewport,
+    /// Pixels which may be written, limited by the scissor, the render area
+    /// and the size of the attachments.
+    bounds: (Range<i32>, Range<i32>),
+    blend_constants: pso::ColorValue,
+    depth_bounds: Range<f32>,
+    depth_bias: Option<pso::DepthBias>,
+    stencil_reference: pso::Sided<pso::StencilValue>,
+    stencil_read_mask: pso::Sided<pso::StencilValue>,
+    stencil_write_mask: pso::Sided<pso::StencilValue>,
+    /// Samples which passed the depth and stencil tests.
+    samples_passed: Cell<u64>,
+}
+
+impl<'a> Context<'a> {
+    fn new(
+        state: &'a GraphicsState,
+        pipeline: &'a n::GraphicsPipeline,
+        targets: &'a Targets,
+    ) -> Option<Self> {
+        let baked = &pipeline.baked_states;
+        let viewport = match baked.viewport.clone().or_else(|| state.viewport.clone()) {
+            Some(viewport) => viewport,
+            None => {
+                error!("Draw without a viewport");
+                return None;
+            }
+        };
+
+        let area = targets.area;
+        let scissor = baked.scissor.or(state.scissor).unwrap_or(area);
+        let (mut width, mut height) = (i32::MAX, i32::MAX);
+        for view in targets.colors.iter().chain(&targets.depth_stencil) {
+            let extent = view.image.desc.kind.level_extent(view.range.levels.start);
+            width = width.min(extent.width as i32);
+            height = height.min(extent.height as i32);
+        }
+        let bounds = (
+            (scissor.x as i32).max(area.x as i32).max(0)
+                ..(scissor.x as i32 + scissor.w as i32)
+                    .min(area.x as i32 + area.w as i32)
+                    .min(width),
+            (scissor.y as i32).max(area.y as i32).max(0)
+                ..(scissor.y as i32 + scissor.h as i32)
+                    .min(area.y as i32 + area.h as i32)
+                    .min(height),
+        );
+
+        let stencil = pipeline.depth_stencil.stencil;
+        Some(Context {
+            state,
+            pipeline,
+            targets,
+            resources: state.bindings.resources(),
+            viewport,
+            bounds,
+            blend_constants: baked.blend_color.unwrap_or(state.blend_constants),
+            depth_bounds: baked
+                .depth_bounds
+                .clone()
+                .unwrap_or_else(|| state.depth_bounds.clone()),
+            depth_bias: pipeline
+                .rasterizer
+                .depth_bias
+                .map(|bias| bias.static_or(state.depth_bias)),
+            stencil_reference: stencil.map_or(state.stencil_reference, |s| {
+                s.reference_values.static_or(state.stencil_reference)
+            }),
+            stencil_read_mask: stencil.map_or(state.stencil_read_mask, |s| {
+                s.read_masks.static_or(state.stencil_read_mask)
+            }),
+            stencil_write_mask: stencil.map_or(state.stencil_write_mask, |s| {
+                s.write_masks.static_or(state.stencil_write_mask)
+            }),
+            samples_passed: Cell::new(0),
+        })
+    }
+
+    /// Indices of the vertices to assemble, with `None` restarting the
+    /// primitive.
+    unsafe fn indices(&self, vertices: &Vertices) -> Result<Vec<Option<u32>>, interp::Error> {
+        match *vertices {
+            Vertices::Sequential(ref range) => Ok(range.clone().map(Some).collect()),
+            Vertices::Indexed {
+                ref indices,
+                base_vertex,
+            } => {
+                let (buffer, ty) = self.state.index_buffer.as_ref().ok_or_else(|| {
+                    interp::Error::Invalid("indexed draw without an index buffer".to_string())
+                })?;
+                let (size, restart) = match *ty {
+                    IndexType::U16 => (2, 0xFFFF),
+                    IndexType::U32 => (4, !0),
+                };
+                let restart = self.pipeline.input_assembler.restart_index.map(|_| restart);
+                let start = buffer.range.start + indices.start as u64 * size;
+                let end = buffer.range.start + indices.end as u64 * size;
+                let bytes = buffer.block.slice(start .. end.min(buffer.range.end));
+                Ok(bytes
+                    .chunks_exact(size as usize)
+                    .map(|chunk| {
+                        let mut word = [0; 4];
+                        word[.. chunk.len()].copy_from_slice(chunk);
+                        let index = u32::from_le_bytes(word);
+                        if Some(index) == restart {
+                            None
+                        } else {
+                            Some(index.wrapping_add(base_vertex as u32))
+                        }
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    unsafe fn draw_instance(
+        &self,
+        vertices: &Vertices,
+        instance: u32,
+    ) -> Result<(), interp::Error> {
+        let mut cache = HashMap::new();
+        let mut primitive = Vec::new();
+        for index in self.indices(vertices)? {
+            match index {
+                Some(index) => {
+                    let vertex = match cache.entry(index) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(self.shade_vertex(index, instance)?),
+                    };
+                    primitive.push(vertex.clone());
+                }
+                None => {
+                    self.assemble(&primitive)?;
+                    primitive.clear();
+                }
+            }
+        }
+        self.assemble(&primitive)
+    }
+
+    /// Fetch the attribute of the vertex `index`.
+    unsafe fn fetch(
+        &self,
+        attribute: &pso::AttributeDesc,
+        index: u32,
+        instance: u32,
+    ) -> Result<[u32; 4], interp::Error> {
+        let missing = || {
+            interp::Error::Invalid(format!(
+                "no vertex buffer bound for attribute {}",
+                attribute.location
+            ))
+        };
+        let desc = self
+            .pipeline
+            .vertex_buffers
+            .iter()
+            .find(|desc| desc.binding == attribute.binding)
+            .ok_or_else(missing)?;
+        let buffer = self
+            .state
+            .vertex_buffers
+            .get(attribute.binding as usize)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        let element = match desc.rate {
+            pso::VertexInputRate::Vertex => index,
+            pso::VertexInputRate::Instance(0) => 0,
+            pso::VertexInputRate::Instance(divisor) => instance / divisor as u32,
+        };
+
+        let format = attribute.element.format;
+        let size = conv::block_size(format) as u64;
+        let start = buffer.range.start
+            + element as u64 * desc.stride as u64
+            + attribute.element.offset as u64;
+        // Like with robust buffer access, reads out of bounds return zero.
+        let bytes = if start + size <= buffer.range.end {
+            buffer.block.slice(start .. start + size).to_vec()
+        } else {
+            vec![0; size as usize]
+        };
+        conv::decode_color(format, &bytes).ok_or_else(|| {
+            interp::Error::Unsupported(format!("vertex attributes of format {:?}", format))
+        })
+    }
+
+    unsafe fn shade_vertex(&self, index: u32, instance: u32) -> Result<Vertex, interp::Error> {
+        let stage = &self.pipeline.vertex;
+        let module = &*stage.module;
+        let mut workgroup = Workgroup::new(module)?;
+        let mut invocation = Invocation::new(module, &stage.entry, &self.resources, &workgroup)?;
+        invocation.set_builtin(BuiltIn::VertexIndex, &[index])?;
+        invocation.set_builtin(BuiltIn::InstanceIndex, &[instance])?;
+        for attribute in &self.pipeline.attributes {
+            let words = self.fetch(attribute, index, instance)?;
+            invocation.set_input(attribute.location, &words)?;
+        }
+        invocation.run(&mut workgroup)?;
+
+        let mut position = [0.0, 0.0, 0.0, 1.0];
+        let words = invocation
+            .builtin_output(BuiltIn::Position)?
+            .unwrap_or_default();
+        for (position, &word) in position.iter_mut().zip(&words) {
+            *position = f32::from_bits(word);
+        }
+        let point_size = invocation
+            .builtin_output(BuiltIn::PointSize)?
+            .and_then(|words| words.first().map(|&word| f32::from_bits(word)))
+            .unwrap_or(1.0);
+        Ok(Vertex {
+            position,
+            point_size,
+            outputs: invocation.outputs()?,
+        })
+    }
+
+    unsafe fn assemble(&self, vertices: &[Vertex]) -> Result<(), interp::Error> {
+        match self.pipeline.input_assembler.primitive {
+            pso::Primitive::PointList => {
+                for vertex in vertices {
+                    self.point(vertex)?;
+                }
+            }
+            pso::Primitive::LineList => {
+                for line in vertices.chunks_exact(2) {
+                    self.line(&line[0], &line[1], &line[0])?;
+                }
+            }
+            pso::Primitive::LineStrip => {
+                for line in vertices.windows(2) {
+                    self.line(&line[0], &line[1], &line[0])?;
+                }
+            }
+            pso::Primitive::TriangleList => {
+                for triangle in vertices.chunks_exact(3) {
+                    self.triangle(&triangle[0], &triangle[1], &triangle[2])?;
+                }
+            }
+            pso::Primitive::TriangleStrip => {
+                for (i, triangle) in vertices.windows(3).enumerate() {
+                    if i % 2 == 0 {
+                        self.triangle(&triangle[0], &triangle[1], &triangle[2])?;
+                    } else {
+                        self.triangle(&triangle[1], &triangle[0], &triangle[2])?;
+                    }
+                }
+            }
+            pso::Primitive::PatchList(_) => {
+                return Err(interp::Error::Unsupported("patch lists".to_string()))
+            }
+        }
+        Ok(())
+    }
+
+    /// Clip space distances to the planes primitives are clipped against.
+    fn clip_distances(&self, position: &[f32; 4]) -> [f32; 3] {
+        let [_, _, z, w] = *position;
+        if self.pipeline.rasterizer.depth_clamping {
+            [w - f32::EPSILON, f32::INFINITY, f32::INFINITY]
+        } else {
+            [w - f32::EPSILON, z, w - z]
+        }
+    }
+
+    fn project<'v>(&self, vertex: &'v Vertex) -> Screen<'v> {
+        let [x, y, z, w] = vertex.position;
+        let rect = &self.viewport.rect;
+        let depth = &self.viewport.depth;
+        let (half_width, half_height) = (rect.w as f32 / 2.0, rect.h as f32 / 2.0);
+        Screen {
+            x: rect.x as f32 + half_width * (1.0 + x / w),
+            y: rect.y as f32 + half_height * (1.0 + y / w),
+            z: depth.start + (depth.end - depth.start) * z / w,
+            w: 1.0 / w,
+            point_size: vertex.point_size,
+            outputs: &vertex.outputs,
+        }
+    }
+
+    /// Interpolate the outputs of the vertices with the given barycentric
+    /// weights, in screen space.
+    fn interpolate(
+        &self,
+        vertices: &[&Screen],
+        weights: &[f32],
+        provoking: &[(u32, Vec<u32>)],
+    ) -> Vec<(u32, Vec<u32>)> {
+        let weights = perspective_weights(vertices, weights);
+        let fragment = self.pipeline.fragment.as_ref();
+
+        provoking
+            .iter()
+            .enumerate()
+            .map(|(i, &(location, ref words))| {
+                if fragment
+                    .map(|stage| stage.module.is_flat_input(location))
+                    .unwrap_or(true)
+                {
+                    return (location, words.clone());
+                }
+                let words = (0 .. words.len())
+                    .map(|c| {
+                        let value: f32 = vertices
+                            .iter()
+                            .zip(&weights)
+                            .map(|(vertex, weight)| f32::from_bits(vertex.outputs[i].1[c]) * weight)
+                            .sum();
+                        value.to_bits()
+                    })
+                    .collect();
+                (location, words)
+            })
+            .collect()
+    }
+
+    unsafe fn point(&self, vertex: &Vertex) -> Result<(), interp::Error> {
+        if self
+            .clip_distances(&vertex.position)
+            .iter()
+            .any(|&d| d < 0.0)
+        {
+            return Ok(());
+        }
+        let point = self.project(vertex);
+        let size = point.point_size.max(1.0);
+        let (x0, y0) = (point.x - size / 2.0, point.y - size / 2.0);
+        let xs = ((x0.round() as i32).max(self.bounds.0.start))
+            ..((x0 + size).round() as i32).min(self.bounds.0.end);
+        for y in (y0.round() as i32).max(self.bounds.1.start)
+            ..((y0 + size).round() as i32).min(self.bounds.1.end)
+        {
+            for x in xs.clone() {
+                let coord = [(x as f32 + 0.5 - x0) / size, (y as f32 + 0.5 - y0) / size];
+                let inputs = point.outputs.to_vec();
+                self.fragment(x, y, point.z, point.w, inputs, true, Some(coord))?;
+            }
+        }
+        Ok(())
+    }
+
+    unsafe fn line(&self, a: &Vertex, b: &Vertex, provoking: &Vertex) -> Result<(), interp::Error> {
+        // Clip the segment parametrically.
+        let (mut t0, mut t1) = (0.0f32, 1.0f32);
+        let (da, db) = (
+            self.clip_distances(&a.position),
+            self.clip_distances(&b.position),
+        );
+        for (&da, &db) in da.iter().zip(&db) {
+            if da < 0.0 && db < 0.0 {
+                return Ok(());
+            } else if da < 0.0 {
+                t0 = t0.max(da / (da - db));
+            } else if db < 0.0 {
+                t1 = t1.min(da / (da - db));
+            }
+        }
+        let (a, b) = (a.lerp(b, t0), a.lerp(b, t1));
+        let (a, b) = (self.project(&a), self.project(&b));
+
+        let (dx, dy) = (b.x - a.x, b.y - a.y);
+        let steps = dx.abs().max(dy.abs()).ceil().max(1.0) as u32;
+        for step in 0 .. steps {
+            let t = (step as f32 + 0.5) / steps as f32;
+            let (x, y) = ((a.x + dx * t).floor() as i32, (a.y + dy * t).floor() as i32);
+            if !self.bounds.0.contains(&x) || !self.bounds.1.contains(&y) {
+                continue;
+            }
+            let weights = [1.0 - t, t];
+            let inputs = self.interpolate(&[&a, &b], &weights, &provoking.outputs);
+            let z = a.z + (b.z - a.z) * t;
+            let w = a.w + (b.w - a.w) * t;
+            self.fragment(x, y, z, w, inputs, true, None)?;
+        }
+        Ok(())
+    }
+
+    unsafe fn triangle(&self, a: &Vertex, b: &Vertex, c: &Vertex) -> Result<(), interp::Error> {
+        let mut polygon = vec![a.clone(), b.clone(), c.clone()];
+        for plane in 0 .. 3 {
+            polygon = clip(polygon, |position| self.clip_distances(position)[plane]);
+        }
+        if polygon.len() < 3 {
+            return Ok(());
+        }
+        let screen = polygon
+            .iter()
+            .map(|vertex| self.project(vertex))
+            .collect::<Vec<_>>();
+
+        // The sign of the area in framebuffer coordinates gives the facing,
+        // positive for counter-clockwise triangles.
+        let area: f32 = -0.5
+            * (0 .. screen.len())
+                .map(|i| {
+                    let (p, q) = (&screen[i], &screen[(i + 1) % screen.len()]);
+                    p.x * q.y - q.x * p.y
+                })
+                .sum::<f32>();
+        let rasterizer = &self.pipeline.rasterizer;
+        let front = match rasterizer.front_face {
+            pso::FrontFace::CounterClockwise => area > 0.0,
+            pso::FrontFace::Clockwise => area < 0.0,
+        };
+        let face = if front {
+            pso::Face::FRONT
+        } else {
+            pso::Face::BACK
+        };
+        if rasterizer.cull_face.contains(face) {
+            return Ok(());
+        }
+
+        match rasterizer.polygon_mode {
+            pso::PolygonMode::Point => {
+                for vertex in &polygon {
+                    self.point(vertex)?;
+                }
+            }
+            pso::PolygonMode::Line => {
+                for (i, vertex) in polygon.iter().enumerate() {
+                    self.line(vertex, &polygon[(i + 1) % polygon.len()], a)?;
+                }
+            }
+            pso::PolygonMode::Fill => {
+                for i in 1 .. screen.len() - 1 {
+                    self.fill(&screen[0], &screen[i], &screen[i + 1], front, &a.outputs)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Constant depth bias of a triangle, following the Vulkan formula.
+    fn depth_bias(&self, vertices: [&Screen; 3], area: f32) -> f32 {
+        let bias = match self.depth_bias {
+            Some(bias) => bias,
+            None => return 0.0,
+        };
+        let [a, b, c] = vertices;
+        let dzdx = ((b.z - a.z) * (c.y - a.y) - (c.z - a.z) * (b.y - a.y)) / area;
+        let dzdy = ((c.z - a.z) * (b.x - a.x) - (b.z - a.z) * (c.x - a.x)) / area;
+        let slope = dzdx.abs().max(dzdy.abs());
+        // Minimum resolvable difference of the depth attachment
+        let resolution = match self.targets.depth_stencil {
+            Some(ref view) if view.format.base_format().1 != ChannelType::Sfloat => {
+                let bits = conv::aspect_layout(view.format, Aspects::DEPTH).size * 8;
+                1.0 / ((1u32 << bits) - 1) as f32
+            }
+            _ => {
+                let max = a.z.abs().max(b.z.abs()).max(c.z.abs());
+                if max > 0.0 {
+                    2f32.powi(max.log2().floor() as i32 - 23)
+                } else {
+                    0.0
+                }
+            }
+        };
+        let value = bias.const_factor * resolution + bias.slope_factor * slope;
+        if bias.clamp > 0.0 {
+            value.min(bias.clamp)
+        } else if bias.clamp < 0.0 {
+            value.max(bias.clamp)
+        } else {
+            value
+        }
+    }
+
+    unsafe fn fill(
+        &self,
+        a: &Screen,
+        b: &Screen,
+        c: &Screen,
+        front: bool,
+        provoking: &[(u32, Vec<u32>)],
+    ) -> Result<(), interp::Error> {
+        // Order the vertices so that the edge functions are positive inside.
+        let area = edge(a, b, c.x, c.y);
+        let (b, c) = match area {
+            area if area > 0.0 => (b, c),
+            area if area < 0.0 => (c, b),
+            _ => return Ok(()),
+        };
+        let area = area.abs();
+        let bias = self.depth_bias([a, b, c], area);
+
+        for (x, y, weights) in coverage(a, b, c, area, &self.bounds) {
+            let z = a.z * weights[0] + b.z * weights[1] + c.z * weights[2] + bias;
+            let w = a.w * weights[0] + b.w * weights[1] + c.w * weights[2];
+            let inputs = self.interpolate(&[a, b, c], &weights, provoking);
+            self.fragment(x, y, z, w, inputs, front, None)?;
+        }
+        Ok(())
+    }
+
+    /// Shade the fragment at pixel `(x, y)` and write it to the attachments.
+    #[allow(clippy::too_many_arguments)]
+    unsafe fn fragment(
+        &self,
+        x: i32,
+        y: i32,
+        z: f32,
+        w: f32,
+        inputs: Vec<(u32, Vec<u32>)>,
+        front: bool,
+        point_coord: Option<[f32; 2]>,
+    ) -> Result<(), interp::Error> {
+        let mut depth = z;
+        if self.pipeline.rasterizer.depth_clamping {
+            let range = &self.viewport.depth;
+            depth = depth
+                .max(range.start.min(range.end))
+                .min(range.start.max(range.end));
+        }
+
+        let mut outputs = Vec::new();
+        if let Some(ref stage) = self.pipeline.fragment {
+            let module = &*stage.module;
+            let mut workgroup = Workgroup::new(module)?;
+            let mut invocation =
+                Invocation::new(module, &stage.entry, &self.resources, &workgroup)?;
+            let coord = [x as f32 + 0.5, y as f32 + 0.5, depth, w];
+            invocation.set_builtin(BuiltIn::FragCoord, &coord.map(f32::to_bits))?;
+            invocation.set_builtin(BuiltIn::FrontFacing, &[front as u32])?;
+            if let Some(coord) = point_coord {
+                invocation.set_builtin(BuiltIn::PointCoord, &coord.map(f32::to_bits))?;
+            }
+            for (location, words) in inputs {
+                invocation.set_input(location, &words)?;
+            }
+            if invocation.run(&mut workgroup)? == Status::Killed {
+                return Ok(());
+            }
+            if stage.entry.depth_replacing {
+                if let Some(words) = invocation.builtin_output(BuiltIn::FragDepth)? {
+                    depth = f32::from_bits(words.first().cloned().unwrap_or(0));
+                }
+            }
+            outputs = invocation.outputs()?;
+        }
+
+        if !self.depth_stencil(x, y, depth, front) {
+            return Ok(());
+        }
+        self.samples_passed.set(self.samples_passed.get() + 1);
+        self.write_colors(x, y, &outputs)
+    }
+
+    /// Run the depth bounds, stencil and depth tests, updating the
+    /// attachment. Returns whether the fragment passed.
+    unsafe fn depth_stencil(&self, x: i32, y: i32, depth: f32, front: bool) -> bool {
+        let view = match self.targets.depth_stencil {
+            Some(ref view) => view,
+            None => return true,
+        };
+        let desc = &self.pipeline.depth_stencil;
+        let format = view.format;
+        let offset = view.image.texel_offset(
+            view.range.levels.start,
+            view.range.layers.start,
+            x as u32,
+            y as u32,
+            0,
+        );
+        let texel = view
+            .image
+            .block
+            .slice_mut(offset .. offset + conv::block_size(format) as u64);
+
+        let stored_depth = if format.is_depth() {
+            Some(conv::decode_depth(format, texel))
+        } else {
+            None
+        };
+        if let (true, Some(stored)) = (desc.depth_bounds, stored_depth) {
+            if stored < self.depth_bounds.start || stored > self.depth_bounds.end {
+                return false;
+            }
+        }
+
+        let stencil = match desc.stencil {
+            Some(ref test) if format.is_stencil() => {
+                let (face, reference, read_mask, write_mask) = if front {
+                    (
+                        test.faces.front,
+                        self.stencil_reference.front,
+                        self.stencil_read_mask.front,
+                        self.stencil_write_mask.front,
+                    )
+                } else {
+                    (
+                        test.faces.back,
+                        self.stencil_reference.back,
+                        self.stencil_read_mask.back,
+                        self.stencil_write_mask.back,
+                    )
+                };
+                let offset = conv::aspect_layout(format, Aspects::STENCIL).offset;
+                Some((
+                    face,
+                    reference as u8,
+                    read_mask as u8,
+                    write_mask as u8,
+                    offset,
+                ))
+            }
+            _ => None,
+        };
+        let update_stencil = |texel: &mut [u8], op: fn(&pso::StencilFace) -> pso::StencilOp| {
+            if let Some((ref face, reference, _, write_mask, offset)) = stencil {
+                let value = texel[offset];
+                let new = stencil_op(op(face), value, reference);
+                texel[offset] = (new & write_mask) | (value & !write_mask);
+            }
+        };
+
+        if let Some((ref face, reference, read_mask, _, offset)) = stencil {
+            if !compare(face.fun, reference & read_mask, texel[offset] & read_mask) {
+                update_stencil(texel, |face| face.op_fail);
+                return false;
+            }
+        }
+        if let (Some(test), Some(stored)) = (desc.depth, stored_depth) {
+            if !compare(test.fun, depth, stored) {
+                update_stencil(texel, |face| face.op_depth_fail);
+                return false;
+            }
+            if test.write {
+                let bytes = conv::encode_depth(format, depth);
+                texel[.. bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+        update_stencil(texel, |face| face.op_pass);
+        true
+    }
+
+    unsafe fn write_colors(
+        &self,
+        x: i32,
+        y: i32,
+        outputs: &[(u32, Vec<u32>)],
+    ) -> Result<(), interp::Error> {
+        for (location, view) in self.targets.colors.iter().enumerate() {
+            let words = match outputs.iter().find(|&&(l, _)| l == location as u32) {
+                Some((_, words)) => words,
+                None => continue,
+            };
+            let target = self
+                .pipeline
+                .blender
+                .targets
+                .get(location)
+                .cloned()
+                .unwrap_or(pso::ColorBlendDesc::EMPTY);
+            if target.mask.is_empty() {
+                continue;
+            }
+
+            let format = view.format;
+            let offset = view.image.texel_offset(
+                view.range.levels.start,
+                view.range.layers.start,
+                x as u32,
+                y as u32,
+                0,
+            );
+            let texel = view
+                .image
+                .block
+                .slice_mut(offset .. offset + conv::block_size(format) as u64);
+            let stored = conv::decode_color(format, texel).ok_or_else(|| {
+                interp::Error::Unsupported(format!("color attachments of format {:?}", format))
+            })?;
+
+            let mut color = [0, 0, 0, 1f32.to_bits()];
+            for (color, &word) in color.iter_mut().zip(words) {
+                *color = word;
+            }
+            let channel = format.base_format().1;
+            let is_float = !matches!(channel, ChannelType::Uint | ChannelType::Sint);
+            if let (true, Some(ref state)) = (is_float, target.blend) {
+                let range = match channel {
+                    ChannelType::Unorm | ChannelType::Srgb => 0.0 .. 1.0,
+                    ChannelType::Snorm => -1.0 .. 1.0,
+                    _ => f32::MIN .. f32::MAX,
+                };
+                let floats = |words: &[u32; 4]| {
+                    let mut floats = [0.0; 4];
+                    for (float, &word) in floats.iter_mut().zip(words) {
+                        *float = f32::from_bits(word).max(range.start).min(range.end);
+                    }
+                    floats
+                };
+                let mut constants = self.blend_constants;
+                for constant in &mut constants {
+                    *constant = constant.max(range.start).min(range.end);
+                }
+                let blended = blend(state, &floats(&color), &floats(&stored), &constants);
+                color = blended.map(f32::to_bits);
+            }
+
+            let channels = [
+                pso::ColorMask::RED,
+                pso::ColorMask::GREEN,
+                pso::ColorMask::BLUE,
+                pso::ColorMask::ALPHA,
+            ];
+            for (i, &channel) in channels.iter().enumerate() {
+                if !target.mask.contains(channel) {
+                    color[i] = stored[i];
+                }
+            }
+            if let Some(bytes) = conv::encode_color(format, com::ClearColor { uint32: color }) {
+                texel[.. bytes.len()].copy_from_slice(&bytes);
+            }
+        }
+        Ok(())
+    }
+}
+
+pub(crate) unsafe fn draw(
+    state: &GraphicsState,
+    vertices: Vertices,
+    instances: Range<hal::InstanceCount>,
+) {
+    let pipeline = match state.pipeline {
+        Some(ref pipeline) => pipeline,
+        None => {
+            error!("Draw without a bound graphics pipeline");
+            return;
+        }
+    };
+    let targets = match state.targets {
+        Some(ref targets) => targets,
+        None => {
+            error!("Draw outside of a render pass");
+            return;
+        }
+    };
+    let context = match Context::new(state, pipeline, targets) {
+        Some(context) => context,
+        None => return,
+    };
+
+    for instance in instances {
+        if let Err(err) = context.draw_instance(&vertices, instance) {
+            error!("Graphics shader execution failed: {}", err);
+            break;
+        }
+    }
+    if let Some((ref pool, id)) = state.occlusion_query {
+        pool.add(id, context.samples_passed.get());
+    }
+}
+
+pub(crate) unsafe fn draw_indirect(
+    state: &GraphicsState,
+    buffer: &n::BoundBuffer,
+    offset: buffer::Offset,
+    draw_count: hal::DrawCount,
+    stride: u32,
+    indexed: bool,
+) {
+    for i in 0 .. draw_count {
+        let offset = offset + i as buffer::Offset * stride as buffer::Offset;
+        if indexed {
+            let words = read_words(buffer, offset, 5);
+            let vertices = Vertices::Indexed {
+                indices: words[2] .. words[2] + words[0],
+                base_vertex: words[3] as hal::VertexOffset,
+            };
+            draw(state, vertices, words[4] .. words[4] + words[1]);
+        } else {
+            let words = read_words(buffer, offset, 4);
+            let vertices = Vertices::Sequential(words[2] .. words[2] + words[0]);
+            draw(state, vertices, words[3] .. words[3] + words[1]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(x: f32, y: f32, w: f32) -> Screen<'static> {
+        Screen {
+            x,
+            y,
+            z: 0.0,
+            w,
+            point_size: 1.0,
+            outputs: &[],
+        }
+    }
+
+    /// Number of times each pixel of an 8x8 target is covered by the triangles,
+    /// ordered like `Context::fill` does.
+    fn coverage_counts(triangles: &[[(f32, f32); 3]]) -> [[u32; 8]; 8] {
+        let mut counts = [[0; 8]; 8];
+        for triangle in triangles {
+            let [a, b, c] = triangle.map(|(x, y)| screen(x, y, 1.0));
+            let area = edge(&a, &b, c.x, c.y);
+            let (b, c) = if area > 0.0 { (b, c) } else { (c, b) };
+            for (x, y, weights) in coverage(&a, &b, &c, area.abs(), &(0 .. 8, 0 .. 8)) {
+                assert!((weights.iter().sum::<f32>() - 1.0).abs() < 1e-6);
+                counts[y as usize][x as usize] += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn triangle_coverage() {
+        let counts = coverage_counts(&[[(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)]]);
+        for (y, row) in counts.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                // Pixel centres on the diagonal belong to a bottom right edge.
+                assert_eq!(count, (x + y < 3) as u32, "pixel ({}, {})", x, y);
+            }
+        }
+        // Winding doesn't matter.
+        assert_eq!(
+            coverage_counts(&[[(0.0, 4.0), (4.0, 0.0), (0.0, 0.0)]]),
+            counts
+        );
+    }
+
+    #[test]
+    fn top_left_fill_rule() {
+        // Triangles sharing an edge through pixel centres cover each pixel once.
+        let square = coverage_counts(&[
+            [(0.0, 0.0), (4.0, 0.0), (0.0, 4.0)],
+            [(4.0, 0.0), (4.0, 4.0), (0.0, 4.0)],
+        ]);
+        for (y, row) in square.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                assert_eq!(count, (x < 4 && y < 4) as u32, "pixel ({}, {})", x, y);
+            }
+        }
+
+        // The top edge owns the centres lying on it, the bottom edge doesn't.
+        let rectangle = coverage_counts(&[
+            [(0.0, 0.5), (4.0, 0.5), (4.0, 2.5)],
+            [(0.0, 0.5), (4.0, 2.5), (0.0, 2.5)],
+        ]);
+        for (y, row) in rectangle.iter().enumerate() {
+            for (x, &count) in row.iter().enumerate() {
+                assert_eq!(count, (x < 4 && y < 2) as u32, "pixel ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn depth_test() {
+        use hal::pso::Comparison;
+        assert!(compare(Comparison::Less, 0.25, 0.5));
+        assert!(!compare(Comparison::Less, 0.5, 0.5));
+        assert!(compare(Comparison::LessEqual, 0.5, 0.5));
+        assert!(compare(Comparison::Greater, 0.75, 0.5));
+        assert!(!compare(Comparison::GreaterEqual, 0.25, 0.5));
+        assert!(compare(Comparison::NotEqual, 0.25, 0.5));
+        assert!(!compare(Comparison::Never, 0.25, 0.5));
+        assert!(compare(Comparison::Always, 0.75, 0.5));
+        assert_eq!(stencil_op(pso::StencilOp::IncrementClamp, 255, 0), 255);
+        assert_eq!(stencil_op(pso::StencilOp::IncrementWrap, 255, 0), 0);
+        assert_eq!(stencil_op(pso::StencilOp::Replace, 3, 7), 7);
+    }
+
+    #[test]
+    fn blending() {
+        let (src, dst) = ([1.0, 0.0, 0.0, 0.25], [0.0, 0.0, 1.0, 1.0]);
+        let constant = [0.5; 4];
+        assert_eq!(
+            blend(&pso::BlendState::ALPHA, &src, &dst, &constant),
+            [0.25, 0.0, 0.75, 1.0]
+        );
+        assert_eq!(
+            blend(&pso::BlendState::ADD, &src, &dst, &constant),
+            [1.0, 0.0, 1.0, 1.25]
+        );
+        let state = pso::BlendState {
+            color: pso::BlendOp::RevSub {
+                src: pso::Factor::ConstColor,
+                dst: pso::Factor::One,
+            },
+            alpha: pso::BlendOp::Min,
+        };
+        assert_eq!(blend(&state, &src, &dst, &constant), [-0.5, 0.0, 1.0, 0.25]);
+    }
+
+    #[test]
+    fn interpolation() {
+        // Halfway in screen space between vertices at clip space w 1 and 3
+        // lies a quarter of the way in clip space.
+        let (a, b) = (screen(0.0, 0.0, 1.0), screen(4.0, 0.0, 1.0 / 3.0));
+        assert_eq!(
+            perspective_weights(&[&a, &b], &[0.5, 0.5]),
+            vec![0.75, 0.25]
+        );
+        let (a, b, c) = (
+            screen(0.0, 0.0, 0.5),
+            screen(4.0, 0.0, 0.5),
+            screen(0.0, 4.0, 0.5),
+        );
+        assert_eq!(
+            perspective_weights(&[&a, &b, &c], &[0.5, 0.25, 0.25]),
+            vec![0.5, 0.25, 0.25]
+        );
+
+        // Clipping interpolates the outputs linearly in clip space.
+        let vertex = |position, value: f32| Vertex {
+            position,
+            point_size: 1.0,
+            outputs: vec![(0, vec![value.to_bits()])],
+        };
+        let polygon = vec![
+            vertex([-1.0, -1.0, -1.0, 1.0], 0.0),
+            vertex([1.0, -1.0, 1.0, 1.0], 1.0),
+            vertex([-1.0, 1.0, 1.0, 1.0], 1.0),
+        ];
+        let clipped = clip(polygon, |position| position[2]);
+        assert_eq!(clipped.len(), 4);
+        let values = clipped
+            .iter()
+            .map(|vertex| f32::from_bits(vertex.outputs[0].1[0]))
+            .collect::<Vec<_>>();
+        assert_eq!(values, vec![0.5, 1.0, 1.0, 0.5]);
+    }
+}
@@ -0,0 +1,425 @@
+//! Host implementation of the transfer commands.
+
+use hal::format::{self, Aspects};
+use hal::{buffer, command as com, image, pso};
+
+use crate::{conv, native as n};
+
+use std::ops::Range;
+use std::ptr;
+
+pub(crate) unsafe fn copy_buffer(
+    src: &n::BoundBuffer,
+    dst: &n::BoundBuffer,
+    region: &com::BufferCopy,
+) {
+    let src_start = src.range.start + region.src;
+    let dst_start = dst.range.start + region.dst;
+    debug_assert!(src_start + region.size <= src.range.end);
+    debug_assert!(dst_start + region.size <= dst.range.end);
+    // Regions must not overlap, but the buffers may share a memory block.
+    ptr::copy(
+        src.block.as_ptr().add(src_start as usize),
+        dst.block.as_ptr().add(dst_start as usize),
+        region.size as usize,
+    );
+}
+
+pub(crate) unsafe fn fill_buffer(dst: &n::BoundBuffer, range: &Range<buffer::Offset>, data: u32) {
+    let bytes = data.to_le_bytes();
+    for chunk in dst.block.slice_mut(range.clone()).chunks_exact_mut(4) {
+        chunk.copy_from_slice(&bytes);
+    }
+}
+
+pub(crate) unsafe fn update_buffer(dst: &n::BoundBuffer, offset: buffer::Offset, data: &[u8]) {
+    let start = dst.range.start + offset;
+    debug_assert!(start + data.len() as u64 <= dst.range.end);
+    dst.block
+        .slice_mut(start .. start + data.len() as u64)
+        .copy_from_slice(data);
+}
+
+/// Copy `count` texels of `size` bytes each between two strided locations.
+unsafe fn copy_texels(
+    src: *const u8,
+    src_stride: usize,
+    dst: *mut u8,
+    dst_stride: usize,
+    count: usize,
+    size: usize,
+) {
+    if src_stride == size && dst_stride == size {
+        ptr::copy(src, dst, count * size);
+    } else {
+        for i in 0 .. count {
+            ptr::copy(src.add(i * src_stride), dst.add(i * dst_stride), size);
+        }
+    }
+}
+
+pub(crate) unsafe fn copy_image(src: &n::BoundImage, dst: &n::BoundImage, region: &com::ImageCopy) {
+    let (bw, bh) = src.desc.block_dim();
+    let src_aspect = conv::aspect_layout(src.desc.format, region.src_subresource.aspects);
+    let dst_aspect = conv::aspect_layout(dst.desc.format, region.dst_subresource.aspects);
+    let size = src_aspect.size.min(dst_aspect.size);
+    let blocks_x = region.extent.width.div_ceil(bw) as usize;
+    let rows = region.extent.height.div_ceil(bh);
+    let layers = region
+        .src_subresource
+        .layers
+        .clone()
+        .zip(region.dst_subresource.layers.clone());
+
+    for (src_layer, dst_layer) in layers {
+        for z in 0 .. region.extent.depth {
+            for row in 0 .. rows {
+                let src_offset = src.texel_offset(
+                    region.src_subresource.level,
+                    src_layer,
+                    region.src_offset.x as u32,
+                    region.src_offset.y as u32 + row * bh,
+                    region.src_offset.z as u32 + z,
+                );
+                let dst_offset = dst.texel_offset(
+                    region.dst_subresource.level,
+                    dst_layer,
+                    region.dst_offset.x as u32,
+                    region.dst_offset.y as u32 + row * bh,
+                    region.dst_offset.z as u32 + z,
+                );
+                copy_texels(
+                    src.block
+                        .as_ptr()
+                        .add(src_offset as usize + src_aspect.offset),
+                    src.desc.block_size() as usize,
+                    dst.block
+                        .as_ptr()
+                        .add(dst_offset as usize + dst_aspect.offset),
+                    dst.desc.block_size() as usize,
+                    blocks_x,
+                    size,
+                );
+            }
+        }
+    }
+}
+
+/// Source texels to sample along one axis for the coordinate `coord`, with
+/// their weights. Coordinates outside the image are clamped to its edge.
+fn filter_axis(coord: f32, size: u32, filter: image::Filter) -> [(u32, f32); 2] {
+    let max = size as i64 - 1;
+    match filter {
+        image::Filter::Nearest => {
+            let i = (coord.floor() as i64).clamp(0, max) as u32;
+            [(i, 1.0), (i, 0.0)]
+        }
+        image::Filter::Linear => {
+            let coord = coord - 0.5;
+            let i = coord.floor();
+            let t = coord - i;
+            let i = i as i64;
+            [
+                (i.clamp(0, max) as u32, 1.0 - t),
+                ((i + 1).clamp(0, max) as u32, t),
+            ]
+        }
+    }
+}
+
+/// Map the centre of texel `d` in `dst` to a coordinate in `src`.
+///
+/// Either range may be reversed, which mirrors the blit.
+fn blit_coord(d: i32, src: Range<i32>, dst: Range<i32>) -> f32 {
+    let t = (d as f32 + 0.5 - dst.start as f32) / (dst.end - dst.start) as f32;
+    src.start as f32 + t * (src.end - src.start) as f32
+}
+
+fn ordered(range: Range<i32>) -> Range<i32> {
+    range.start.min(range.end) .. range.start.max(range.end)
+}
+
+pub(crate) unsafe fn blit_image(
+    src: &n::BoundImage,
+    dst: &n::BoundImage,
+    filter: image::Filter,
+    region: &com::ImageBlit,
+) {
+    let (src_format, dst_format) = (src.desc.format, dst.desc.format);
+    let aspects = region.src_subresource.aspects;
+    let color = aspects.contains(Aspects::COLOR);
+    let src_texel = src.desc.block_size() as usize;
+    if color
+        && (conv::decode_color(src_format, &vec![0; src_texel]).is_none()
+            || conv::encode_color(dst_format, com::ClearColor { uint32: [0; 4] }).is_none())
+    {
+        error!(
+            "Blitting from {:?} to {:?} is not supported",
+            src_format, dst_format
+        );
+        return;
+    }
+    // Integer, depth and stencil values can't be filtered.
+    let filter = match src_format.base_format().1 {
+        _ if !color => image::Filter::Nearest,
+        format::ChannelType::Uint | format::ChannelType::Sint => image::Filter::Nearest,
+        _ => filter,
+    };
+    let aspect = conv::aspect_layout(src_format, aspects);
+
+    let (src_bounds, dst_bounds) = (&region.src_bounds, &region.dst_bounds);
+    let src_level = region.src_subresource.level;
+    let dst_level = region.dst_subresource.level;
+    let extent = src.desc.kind.level_extent(src_level);
+    let layers = region
+        .src_subresource
+        .layers
+        .clone()
+        .zip(region.dst_subresource.layers.clone());
+
+    for (src_layer, dst_layer) in layers {
+        for z in ordered(dst_bounds.start.z .. dst_bounds.end.z) {
+            let zs = filter_axis(
+                blit_coord(
+                    z,
+                    src_bounds.start.z .. src_bounds.end.z,
+                    dst_bounds.start.z .. dst_bounds.end.z,
+                ),
+                extent.depth,
+                filter,
+            );
+            for y in ordered(dst_bounds.start.y .. dst_bounds.end.y) {
+                let ys = filter_axis(
+                    blit_coord(
+                        y,
+                        src_bounds.start.y .. src_bounds.end.y,
+                        dst_bounds.start.y .. dst_bounds.end.y,
+                    ),
+                    extent.height,
+                    filter,
+                );
+                for x in ordered(dst_bounds.start.x .. dst_bounds.end.x) {
+                    let xs = filter_axis(
+                        blit_coord(
+                            x,
+                            src_bounds.start.x .. src_bounds.end.x,
+                            dst_bounds.start.x .. dst_bounds.end.x,
+                        ),
+                        extent.width,
+                        filter,
+                    );
+                    let texel = |x: u32, y: u32, z: u32| {
+                        let offset = src.texel_offset(src_level, src_layer, x, y, z);
+                        src.block.slice(offset .. offset + src_texel as u64)
+                    };
+                    let dst_offset =
+                        dst.texel_offset(dst_level, dst_layer, x as u32, y as u32, z as u32);
+
+                    let (x0, y0, z0) = (xs[0].0, ys[0].0, zs[0].0);
+                    if !color || (filter == image::Filter::Nearest && src_format == dst_format) {
+                        let start = dst_offset + aspect.offset as u64;
+                        dst.block
+                            .slice_mut(start .. start + aspect.size as u64)
+                            .copy_from_slice(
+                                &texel(x0, y0, z0)[aspect.offset .. aspect.offset + aspect.size],
+                            );
+                        continue;
+                    }
+
+                    let bits = if filter == image::Filter::Nearest {
+                        conv::decode_color(src_format, texel(x0, y0, z0)).unwrap()
+                    } else {
+                        let mut sum = [0f32; 4];
+                        for &(tz, wz) in &zs {
+                            for &(ty, wy) in &ys {
+                                for &(tx, wx) in &xs {
+                                    let weight = wx * wy * wz;
+                                    if weight == 0.0 {
+                                        continue;
+                                    }
+                                    let bits =
+                                        conv::decode_color(src_format, texel(tx, ty, tz)).unwrap();
+                                    for (sum, bits) in sum.iter_mut().zip(&bits) {
+                                        *sum += f32::from_bits(*bits) * weight;
+                                    }
+                                }
+                            }
+                        }
+                        sum.map(f32::to_bits)
+                    };
+                    let encoded =
+                        conv::encode_color(dst_format, com::ClearColor { uint32: bits }).unwrap();
+                    dst.block
+                        .slice_mut(dst_offset .. dst_offset + encoded.len() as u64)
+                        .copy_from_slice(&encoded);
+                }
+            }
+        }
+    }
+}
+
+/// Copy between a buffer and an image in either direction.
+pub(crate) unsafe fn copy_buffer_image(
+    buffer: &n::BoundBuffer,
+    image: &n::BoundImage,
+    region: &com::BufferImageCopy,
+    to_image: bool,
+) {
+    let (bw, bh) = image.desc.block_dim();
+    let aspect = conv::aspect_layout(image.desc.format, region.image_layers.aspects);
+    let block_size = image.desc.block_size() as usize;
+    let extent = region.image_extent;
+
+    let buffer_width = if region.buffer_width == 0 {
+        extent.width
+    } else {
+        region.buffer_width
+    };
+    let buffer_height = if region.buffer_height == 0 {
+        extent.height
+    } else {
+        region.buffer_height
+    };
+    let row_pitch = buffer_width.div_ceil(bw) as u64 * aspect.buffer_size as u64;
+    let slice_pitch = buffer_height.div_ceil(bh) as u64 * row_pitch;
+    let blocks_x = extent.width.div_ceil(bw) as usize;
+    let rows = extent.height.div_ceil(bh);
+    let size = aspect.size.min(aspect.buffer_size);
+
+    for (i, layer) in region.image_layers.layers.clone().enumerate() {
+        for z in 0 .. extent.depth {
+            for row in 0 .. rows {
+                let slice = i as u64 * extent.depth as u64 + z as u64;
+                let buffer_offset = buffer.range.start
+                    + region.buffer_offset
+                    + slice * slice_pitch
+                    + row as u64 * row_pitch;
+                debug_assert!(
+                    buffer_offset + blocks_x as u64 * aspect.buffer_size as u64 <= buffer.range.end
+                );
+                let image_offset = image.texel_offset(
+                    region.image_layers.level,
+                    layer,
+                    region.image_offset.x as u32,
+                    region.image_offset.y as u32 + row * bh,
+                    region.image_offset.z as u32 + z,
+                ) as usize
+                    + aspect.offset;
+
+                let buffer_ptr = buffer.block.as_ptr().add(buffer_offset as usize);
+                let image_ptr = image.block.as_ptr().add(image_offset);
+                if to_image {
+                    copy_texels(
+                        buffer_ptr,
+                        aspect.buffer_size,
+                        image_ptr,
+                        block_size,
+                        blocks_x,
+                        size,
+                    );
+                } else {
+                    if size != aspect.buffer_size {
+                        // Padding bytes of the aspect are left zeroed.
+                        ptr::write_bytes(buffer_ptr, 0, blocks_x * aspect.buffer_size);
+                    }
+                    copy_texels(
+                        image_ptr,
+                        block_size,
+                        buffer_ptr,
+                        aspect.buffer_size,
+                        blocks_x,
+                        size,
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Encoded bytes to write into each texel of `format` to clear `aspects`,
+/// as pairs of byte offset in the texel and data.
+fn clear_parts(
+    format: format::Format,
+    aspects: Aspects,
+    value: com::ClearValue,
+) -> Option<Vec<(usize, Vec<u8>)>> {
+    let mut parts = Vec::new();
+    if aspects.contains(Aspects::COLOR) {
+        match conv::encode_color(format, unsafe { value.color }) {
+            Some(bytes) => parts.push((0, bytes)),
+            None => {
+                error!("Clearing images of format {:?} is not supported", format);
+                return None;
+            }
+        }
+    }
+    let depth_stencil = unsafe { value.depth_stencil };
+    if aspects.contains(Aspects::DEPTH) && format.is_depth() {
+        parts.push((0, conv::encode_depth(format, depth_stencil.depth)));
+    }
+    if aspects.contains(Aspects::STENCIL) && format.is_stencil() {
+        let layout = conv::aspect_layout(format, Aspects::STENCIL);
+        parts.push((layout.offset, vec![depth_stencil.stencil as u8]));
+    }
+    Some(parts)
+}
+
+pub(crate) unsafe fn clear_image(
+    dst: &n::BoundImage,
+    value: com::ClearValue,
+    range: &image::SubresourceRange,
+) {
+    let parts = match clear_parts(dst.desc.format, range.aspects, value) {
+        Some(parts) => parts,
+        None => return,
+    };
+
+    let block_size = dst.desc.block_size();
+    for level in range.levels.clone() {
+        let footprint = dst.desc.footprint(level);
+        for layer in range.layers.clone() {
+            let start = dst.offset + footprint.slice.start + layer as u64 * footprint.array_pitch;
+            let texels = dst.block.slice_mut(start .. start + footprint.array_pitch);
+            for texel in texels.chunks_exact_mut(block_size as usize) {
+                for &(offset, ref bytes) in &parts {
+                    texel[offset .. offset + bytes.len()].copy_from_slice(bytes);
+                }
+            }
+        }
+    }
+}
+
+/// Clear a rectangle of the first level of an attachment view.
+///
+/// `layers` are relative to the first layer of the view.
+pub(crate) unsafe fn clear_rect(
+    view: &n::ImageView,
+    aspects: Aspects,
+    value: com::ClearValue,
+    rect: pso::Rect,
+    layers: Range<image::Layer>,
+) {
+    let dst = &view.image;
+    let parts = match clear_parts(dst.desc.format, aspects, value) {
+        Some(parts) => parts,
+        None => return,
+    };
+
+    let level = view.range.levels.start;
+    let extent = dst.desc.kind.level_extent(level);
+    let x_end = (rect.x as u32 + rect.w as u32).min(extent.width);
+    let y_end = (rect.y as u32 + rect.h as u32).min(extent.height);
+    let block_size = dst.desc.block_size() as usize;
+    for layer in layers {
+        let layer = view.range.layers.start + layer;
+        for y in rect.y as u32 .. y_end {
+            let start = dst.texel_offset(level, layer, rect.x as u32, y, 0);
+            let end = dst.texel_offset(level, layer, x_end, y, 0);
+            for texel in dst.block.slice_mut(start .. end).chunks_exact_mut(block_size) {
+                for &(offset, ref bytes) in &parts {
+                    texel[offset .. offset + bytes.len()].copy_from_slice(bytes);
+                }
+            }
+        }
+    }
+}
@@ -5,8 +5,8 @@ use crate::GlContext;
 use hal::format::ChannelType;
 use hal::{self, buffer, command, image, memory, pass, pso, query};
 
-use crate::info;
 use crate::pool::{self, BufferMemory};
+use crate::{conv, info};
 use crate::{native as n, Backend, ColorSlot};
 
 use parking_lot::Mutex;
@@ -68,6 +68,22 @@ pub enum Command {
         base_vertex: hal::VertexOffset,
         instances: Range<hal::InstanceCount>,
     },
+    DrawIndirect {
+        primitive: u32,
+        buffer: n::RawBuffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        primitive: u32,
+        index_type: u32,
+        index_buffer_offset: buffer::Offset,
+        buffer: n::RawBuffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
     BindIndexBuffer(n::RawBuffer),
     //BindVertexBuffers(BufferSlice),
     BindUniform {
@@ -111,6 +127,10 @@ pub enum Command {
     BindAttribute(n::AttributeDesc, n::RawBuffer, i32, u32),
     //UnbindAttribute(n::AttributeDesc),
     CopyBufferToBuffer(n::RawBuffer, n::RawBuffer, command::BufferCopy),
+    /// Fill a range of a buffer with a repeated 4-byte word.
+    FillBuffer(n::RawBuffer, Range<buffer::Offset>, u32),
+    /// Write the data slice into a buffer at the given offset.
+    UpdateBuffer(n::RawBuffer, buffer::Offset, BufferSlice),
     CopyBufferToTexture {
         src_buffer: n::RawBuffer,
         dst_texture: n::Texture,
@@ -119,7 +139,14 @@ pub enum Command {
         pixel_type: n::DataType,
         data: command::BufferImageCopy,
     },
-    CopyBufferToRenderbuffer(n::RawBuffer, n::Renderbuffer, command::BufferImageCopy),
+    CopyBufferToRenderbuffer {
+        src_buffer: n::RawBuffer,
+        dst_renderbuffer: n::Renderbuffer,
+        internal_format: n::TextureFormat,
+        texture_format: n::TextureFormat,
+        pixel_type: n::DataType,
+        data: command::BufferImageCopy,
+    },
     CopyTextureToBuffer {
         src_texture: n::Texture,
         texture_target: n::TextureTarget,
@@ -128,18 +155,42 @@ pub enum Command {
         dst_buffer: n::RawBuffer,
         data: command::BufferImageCopy,
     },
-    CopyRenderbufferToBuffer(n::Renderbuffer, n::RawBuffer, command::BufferImageCopy),
-    CopyImageToTexture(
-        n::ImageKind,
-        n::Texture,
-        n::TextureTarget,
-        command::ImageCopy,
-    ),
-    CopyImageToRenderbuffer {
+    CopyRenderbufferToBuffer {
+        src_renderbuffer: n::Renderbuffer,
+        texture_format: n::TextureFormat,
+        pixel_type: n::DataType,
+        dst_buffer: n::RawBuffer,
+        data: command::BufferImageCopy,
+    },
+    SetEvent(n::Event),
+    ResetEvent(n::Event),
+    WaitEvent(n::Event),
+    MemoryBarrier(u32),
+    BeginQuery(n::Query, u32),
+    EndQuery {
+        target: u32,
+        ended: Arc<Mutex<Box<[bool]>>>,
+        id: query::Id,
+    },
+    ResetQueries(Arc<Mutex<Box<[bool]>>>, Range<query::Id>),
+    /// Copy the results of the queries, stored in the slice, into a buffer.
+    /// `first` is the id of the first query in its pool.
+    CopyQueryResults {
+        queries: BufferSlice,
+        ended: Arc<Mutex<Box<[bool]>>>,
+        first: query::Id,
+        buffer: n::RawBuffer,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    },
+    /// Blit a region between two images through framebuffers,
+    /// with a `NEAREST` or `LINEAR` filter.
+    BlitImage {
         src_image: n::ImageKind,
-        dst_renderbuffer: n::Renderbuffer,
-        dst_format: n::TextureFormat,
-        data: command::ImageCopy,
+        dst_image: n::ImageKind,
+        filter: u32,
+        data: command::ImageBlit,
     },
 
     BindBufferRange(u32, u32, n::RawBuffer, i32, i32),
@@ -205,6 +256,8 @@ struct Cache {
     depth_mask: Option<bool>,
     // Current stencil mask
     stencil_mask: Option<pso::Sided<pso::StencilValue>>,
+    // Target of the active occlusion query.
+    occlusion_query: Option<u32>,
 }
 
 impl Cache {
@@ -225,6 +278,7 @@ impl Cache {
             uniforms: Vec::new(),
             depth_mask: None,
             stencil_mask: None,
+            occlusion_query: None,
         }
     }
 }
@@ -645,6 +699,20 @@ impl CommandBuffer {
             }
         }
     }
+
+    /// Record a `glMemoryBarrier` with the bits needed by `barriers`, if any.
+    fn push_memory_barrier<'a, T>(&mut self, barriers: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        let bits = barriers.into_iter().fold(0, |bits, barrier| {
+            bits | conv::barrier_to_gl(barrier.borrow())
+        });
+        if bits != 0 {
+            self.data.push_cmd(Command::MemoryBarrier(bits));
+        }
+    }
 }
 
 impl command::CommandBuffer<Backend> for CommandBuffer {
@@ -680,20 +748,38 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
         &mut self,
         _stages: Range<pso::PipelineStage>,
         _dependencies: memory::Dependencies,
-        _barriers: T,
+        barriers: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        // TODO
+        self.push_memory_barrier(barriers);
     }
 
-    unsafe fn fill_buffer(&mut self, _buffer: &n::Buffer, _range: buffer::SubRange, _data: u32) {
-        unimplemented!()
+    unsafe fn fill_buffer(&mut self, buffer: &n::Buffer, range: buffer::SubRange, data: u32) {
+        let (raw, buffer_range) = buffer.as_bound();
+        let start = buffer_range.start + range.offset;
+        let end = match range.size {
+            Some(size) => start + size,
+            // The whole remaining range, rounded down to a multiple of 4 bytes.
+            None => start + (buffer_range.end - start) / 4 * 4,
+        };
+        assert!(end <= buffer_range.end);
+
+        self.data
+            .push_cmd(Command::FillBuffer(raw, start .. end, data));
     }
 
-    unsafe fn update_buffer(&mut self, _buffer: &n::Buffer, _offset: buffer::Offset, _data: &[u8]) {
-        unimplemented!()
+    unsafe fn update_buffer(&mut self, buffer: &n::Buffer, offset: buffer::Offset, data: &[u8]) {
+        let (raw, buffer_range) = buffer.as_bound();
+        assert!(buffer_range.start + offset + data.len() as u64 <= buffer_range.end);
+
+        let data = self.data.add_raw(data);
+        self.data.push_cmd(Command::UpdateBuffer(
+            raw,
+            buffer_range.start + offset,
+            data,
+        ));
     }
 
     unsafe fn begin_render_pass<T>(
@@ -864,31 +950,69 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
 
     unsafe fn resolve_image<T>(
         &mut self,
-        _src: &n::Image,
+        src: &n::Image,
         _src_layout: image::Layout,
-        _dst: &n::Image,
+        dst: &n::Image,
         _dst_layout: image::Layout,
-        _regions: T,
+        regions: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<command::ImageResolve>,
     {
-        unimplemented!()
+        let old_size = self.data.buf.size;
+
+        // Blitting from a multisampled framebuffer resolves it,
+        // as long as the source and destination bounds are the same size.
+        for region in regions {
+            let r = region.borrow();
+            self.data.push_cmd(Command::BlitImage {
+                src_image: src.kind,
+                dst_image: dst.kind,
+                filter: glow::NEAREST,
+                data: command::ImageBlit {
+                    src_subresource: r.src_subresource.clone(),
+                    src_bounds: r.src_offset.into_bounds(&r.extent),
+                    dst_subresource: r.dst_subresource.clone(),
+                    dst_bounds: r.dst_offset.into_bounds(&r.extent),
+                },
+            });
+        }
+
+        if self.data.buf.size == old_size {
+            error!("At least one region must be specified");
+        }
     }
 
     unsafe fn blit_image<T>(
         &mut self,
-        _src: &n::Image,
+        src: &n::Image,
         _src_layout: image::Layout,
-        _dst: &n::Image,
+        dst: &n::Image,
         _dst_layout: image::Layout,
-        _filter: image::Filter,
-        _regions: T,
+        filter: image::Filter,
+        regions: T,
     ) where
         T: IntoIterator,
         T::Item: Borrow<command::ImageBlit>,
     {
-        unimplemented!()
+        let old_size = self.data.buf.size;
+        let filter = match filter {
+            image::Filter::Nearest => glow::NEAREST,
+            image::Filter::Linear => glow::LINEAR,
+        };
+
+        for region in regions {
+            self.data.push_cmd(Command::BlitImage {
+                src_image: src.kind,
+                dst_image: dst.kind,
+                filter,
+                data: region.borrow().clone(),
+            });
+        }
+
+        if self.data.buf.size == old_size {
+            error!("At least one region must be specified");
+        }
     }
 
     unsafe fn bind_index_buffer(&mut self, ibv: buffer::IndexBufferView<Backend>) {
@@ -1270,23 +1394,20 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
     {
         let old_size = self.data.buf.size;
 
+        // Copies are blits of equal bounds, which don't need any filtering.
         for region in regions {
-            let r = region.borrow().clone();
-            let cmd = match dst.kind {
-                n::ImageKind::Renderbuffer {
-                    renderbuffer,
-                    format,
-                } => Command::CopyImageToRenderbuffer {
-                    src_image: src.kind,
-                    dst_renderbuffer: renderbuffer,
-                    dst_format: format,
-                    data: r,
+            let r = region.borrow();
+            self.data.push_cmd(Command::BlitImage {
+                src_image: src.kind,
+                dst_image: dst.kind,
+                filter: glow::NEAREST,
+                data: command::ImageBlit {
+                    src_subresource: r.src_subresource.clone(),
+                    src_bounds: r.src_offset.into_bounds(&r.extent),
+                    dst_subresource: r.dst_subresource.clone(),
+                    dst_bounds: r.dst_offset.into_bounds(&r.extent),
                 },
-                n::ImageKind::Texture {
-                    texture, target, ..
-                } => Command::CopyImageToTexture(src.kind, texture, target, r),
-            };
-            self.data.push_cmd(cmd);
+            });
         }
 
         if self.data.buf.size == old_size {
@@ -1311,9 +1432,19 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
             let mut r = region.borrow().clone();
             r.buffer_offset += src_range.start;
             let cmd = match dst.kind {
-                n::ImageKind::Renderbuffer { renderbuffer, .. } => {
-                    Command::CopyBufferToRenderbuffer(src_raw, renderbuffer, r)
-                }
+                n::ImageKind::Renderbuffer {
+                    renderbuffer,
+                    internal_format,
+                    format,
+                    pixel_type,
+                } => Command::CopyBufferToRenderbuffer {
+                    src_buffer: src_raw,
+                    dst_renderbuffer: renderbuffer,
+                    internal_format,
+                    texture_format: format,
+                    pixel_type,
+                    data: r,
+                },
                 n::ImageKind::Texture {
                     texture,
                     target,
@@ -1353,9 +1484,18 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
             let mut r = region.borrow().clone();
             r.buffer_offset += dst_range.start;
             let cmd = match src.kind {
-                n::ImageKind::Renderbuffer { renderbuffer, .. } => {
-                    Command::CopyRenderbufferToBuffer(renderbuffer, dst_raw, r)
-                }
+                n::ImageKind::Renderbuffer {
+                    renderbuffer,
+                    format,
+                    pixel_type,
+                    ..
+                } => Command::CopyRenderbufferToBuffer {
+                    src_renderbuffer: renderbuffer,
+                    texture_format: format,
+                    pixel_type,
+                    dst_buffer: dst_raw,
+                    data: r,
+                },
                 n::ImageKind::Texture {
                     texture,
                     target,
@@ -1466,68 +1606,159 @@ impl command::CommandBuffer<Backend> for CommandBuffer {
 
     unsafe fn draw_indirect(
         &mut self,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _draw_count: hal::DrawCount,
-        _stride: u32,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
     ) {
-        unimplemented!()
+        // The first instance is read by GL from the buffer, if supported at all.
+        self.bind_attributes(0);
+
+        let (raw_buffer, range) = buffer.as_bound();
+        match self.cache.primitive {
+            Some(primitive) => {
+                self.data.push_cmd(Command::DrawIndirect {
+                    primitive,
+                    buffer: raw_buffer,
+                    offset: range.start + offset,
+                    draw_count,
+                    stride,
+                });
+            }
+            None => {
+                warn!("No primitive bound. An active pipeline needs to be bound before calling `draw_indirect`.");
+                self.cache.error_state = true;
+            }
+        }
     }
 
     unsafe fn draw_indexed_indirect(
         &mut self,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _draw_count: hal::DrawCount,
-        _stride: u32,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
     ) {
-        unimplemented!()
+        self.bind_attributes(0);
+
+        let (index_type, index_range) = match &self.cache.index_type_range {
+            Some((index_type, index_range)) => (index_type, index_range),
+            None => {
+                warn!("No index type bound. An index buffer needs to be bound before calling `draw_indexed_indirect`.");
+                self.cache.error_state = true;
+                return;
+            }
+        };
+        let index_buffer_offset = index_range.start;
+        let index_type = match index_type {
+            hal::IndexType::U16 => glow::UNSIGNED_SHORT,
+            hal::IndexType::U32 => glow::UNSIGNED_INT,
+        };
+
+        let (raw_buffer, range) = buffer.as_bound();
+        match self.cache.primitive {
+            Some(primitive) => {
+                self.data.push_cmd(Command::DrawIndexedIndirect {
+                    primitive,
+                    index_type,
+                    index_buffer_offset,
+                    buffer: raw_buffer,
+                    offset: range.start + offset,
+                    draw_count,
+                    stride,
+                });
+            }
+            None => {
+                warn!("No primitive bound. An active pipeline needs to be bound before calling `draw_indexed_indirect`.");
+                self.cache.error_state = true;
+            }
+        }
     }
 
-    unsafe fn set_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
+    unsafe fn set_event(&mut self, event: &n::Event, _: pso::PipelineStage) {
+        self.data.push_cmd(Command::SetEvent(event.clone()));
     }
 
-    unsafe fn reset_event(&mut self, _: &(), _: pso::PipelineStage) {
-        unimplemented!()
+    unsafe fn reset_event(&mut self, event: &n::Event, _: pso::PipelineStage) {
+        self.data.push_cmd(Command::ResetEvent(event.clone()));
     }
 
-    unsafe fn wait_events<'a, I, J>(&mut self, _: I, _: Range<pso::PipelineStage>, _: J)
-    where
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        events: I,
+        _stages: Range<pso::PipelineStage>,
+        barriers: J,
+    ) where
         I: IntoIterator,
-        I::Item: Borrow<()>,
+        I::Item: Borrow<n::Event>,
         J: IntoIterator,
         J::Item: Borrow<memory::Barrier<'a, Backend>>,
     {
-        unimplemented!()
+        for event in events {
+            self.data
+                .push_cmd(Command::WaitEvent(event.borrow().clone()));
+        }
+
+        // Make the writes before the events visible to the commands after them.
+        self.push_memory_barrier(barriers);
     }
 
-    unsafe fn begin_query(&mut self, _query: query::Query<Backend>, _flags: query::ControlFlags) {
-        unimplemented!()
+    unsafe fn begin_query(&mut self, query: query::Query<Backend>, _flags: query::ControlFlags) {
+        let raw = query.pool.queries[query.id as usize];
+        let target = query.pool.target;
+        self.cache.occlusion_query = Some(target);
+        self.data.push_cmd(Command::BeginQuery(raw, target));
     }
 
     unsafe fn copy_query_pool_results(
         &mut self,
-        _pool: &(),
-        _queries: Range<query::Id>,
-        _buffer: &n::Buffer,
-        _offset: buffer::Offset,
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        buffer: &n::Buffer,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
     ) {
-        unimplemented!()
+        let (raw, range) = buffer.as_bound();
+        let first = queries.start;
+        let queries = self
+            .data
+            .add(&pool.queries[queries.start as usize .. queries.end as usize]);
+        self.data.push_cmd(Command::CopyQueryResults {
+            queries,
+            ended: Arc::clone(&pool.ended),
+            first,
+            buffer: raw,
+            offset: range.start + offset,
+            stride,
+            flags,
+        });
     }
 
-    unsafe fn end_query(&mut self, _query: query::Query<Backend>) {
-        unimplemented!()
+    unsafe fn end_query(&mut self, query: query::Query<Backend>) {
+        match self.cache.occlusion_query.take() {
+            Some(target) => self.data.push_cmd(Command::EndQuery {
+                target,
+                ended: Arc::clone(&query.pool.ended),
+                id: query.id,
+            }),
+            None => {
+                warn!("Ending a query which wasn't begun.");
+                self.cache.error_state = true;
+            }
+        }
     }
 
-    unsafe fn reset_query_pool(&mut self, _pool: &(), _queries: Range<query::Id>) {
-        unimplemented!()
+    unsafe fn reset_query_pool(&mut self, pool: &n::QueryPool, queries: Range<query::Id>) {
+        // GL queries are reset when they begin, only their availability needs to be cleared.
+        self.data
+            .push_cmd(Command::ResetQueries(Arc::clone(&pool.ended), queries));
     }
 
-    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, _: query::Query<Backend>) {
-        unimplemented!()
+    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, _query: query::Query<Backend>) {
+        // Timestamp query pools can't be created, see `Device::create_query_pool`.
+        warn!("Timestamp queries aren't supported.");
+        self.cache.error_state = true;
     }
 
     unsafe fn push_graphics_constants(
@@ -1,6 +1,7 @@
 use crate::native::VertexAttribFunction;
+use crate::Backend;
 use hal::format::Format;
-use hal::{image as i, pso};
+use hal::{buffer as b, image as i, memory, pso};
 
 /*
 pub fn _image_kind_to_gl(kind: i::Kind) -> t::GLenum {
@@ -62,6 +63,122 @@ pub fn input_assember_to_gl_primitive(ia: &pso::InputAssemblerDesc) -> u32 {
     }
 }
 
+/// `glMemoryBarrier` bits making shader writes visible to the given buffer accesses.
+pub fn buffer_access_to_barrier(access: b::Access) -> u32 {
+    [
+        // Indirect draw parameters are also read back with `glGetBufferSubData`.
+        (
+            b::Access::INDIRECT_COMMAND_READ,
+            glow::COMMAND_BARRIER_BIT | glow::BUFFER_UPDATE_BARRIER_BIT,
+        ),
+        (
+            b::Access::INDEX_BUFFER_READ,
+            glow::ELEMENT_ARRAY_BARRIER_BIT,
+        ),
+        (
+            b::Access::VERTEX_BUFFER_READ,
+            glow::VERTEX_ATTRIB_ARRAY_BARRIER_BIT,
+        ),
+        (b::Access::UNIFORM_READ, glow::UNIFORM_BARRIER_BIT),
+        // Storage buffers, and texel buffers read as textures or images.
+        (
+            b::Access::SHADER_READ | b::Access::SHADER_WRITE,
+            glow::SHADER_STORAGE_BARRIER_BIT
+                | glow::TEXTURE_FETCH_BARRIER_BIT
+                | glow::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+        ),
+        // Copies, updates, mappings and pixel transfers from or to buffers.
+        (
+            b::Access::TRANSFER_READ
+                | b::Access::TRANSFER_WRITE
+                | b::Access::HOST_READ
+                | b::Access::HOST_WRITE,
+            glow::BUFFER_UPDATE_BARRIER_BIT | glow::PIXEL_BUFFER_BARRIER_BIT,
+        ),
+        (
+            b::Access::MEMORY_READ | b::Access::MEMORY_WRITE,
+            glow::ALL_BARRIER_BITS,
+        ),
+    ]
+    .iter()
+    .filter(|&&(flags, _)| access.intersects(flags))
+    .fold(0, |bits, &(_, bit)| bits | bit)
+}
+
+/// `glMemoryBarrier` bits making shader writes visible to the given image accesses.
+pub fn image_access_to_barrier(access: i::Access) -> u32 {
+    [
+        (
+            i::Access::INPUT_ATTACHMENT_READ | i::Access::SHADER_READ,
+            glow::TEXTURE_FETCH_BARRIER_BIT | glow::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+        ),
+        (
+            i::Access::SHADER_WRITE,
+            glow::SHADER_IMAGE_ACCESS_BARRIER_BIT,
+        ),
+        (
+            i::Access::COLOR_ATTACHMENT_READ
+                | i::Access::COLOR_ATTACHMENT_WRITE
+                | i::Access::DEPTH_STENCIL_ATTACHMENT_READ
+                | i::Access::DEPTH_STENCIL_ATTACHMENT_WRITE,
+            glow::FRAMEBUFFER_BARRIER_BIT,
+        ),
+        // Image transfers go through texture uploads and framebuffer reads or blits.
+        (
+            i::Access::TRANSFER_READ
+                | i::Access::TRANSFER_WRITE
+                | i::Access::HOST_READ
+                | i::Access::HOST_WRITE,
+            glow::TEXTURE_UPDATE_BARRIER_BIT | glow::FRAMEBUFFER_BARRIER_BIT,
+        ),
+        (
+            i::Access::MEMORY_READ | i::Access::MEMORY_WRITE,
+            glow::ALL_BARRIER_BITS,
+        ),
+    ]
+    .iter()
+    .filter(|&&(flags, _)| access.intersects(flags))
+    .fold(0, |bits, &(_, bit)| bits | bit)
+}
+
+/// `glMemoryBarrier` bits needed by `barrier`.
+///
+/// Only writes from shaders are incoherent, other commands are already ordered by OpenGL.
+pub fn barrier_to_gl(barrier: &memory::Barrier<Backend>) -> u32 {
+    let buffer_writes = b::Access::SHADER_WRITE | b::Access::MEMORY_WRITE;
+    let image_writes = i::Access::SHADER_WRITE | i::Access::MEMORY_WRITE;
+    match *barrier {
+        memory::Barrier::AllBuffers(ref access) => {
+            if access.start.intersects(buffer_writes) {
+                buffer_access_to_barrier(access.end)
+            } else {
+                0
+            }
+        }
+        memory::Barrier::Buffer { ref states, .. } => {
+            if states.start.intersects(buffer_writes) {
+                buffer_access_to_barrier(states.end)
+            } else {
+                0
+            }
+        }
+        memory::Barrier::AllImages(ref access) => {
+            if access.start.intersects(image_writes) {
+                image_access_to_barrier(access.end)
+            } else {
+                0
+            }
+        }
+        memory::Barrier::Image { ref states, .. } => {
+            if states.start.0.intersects(image_writes) {
+                image_access_to_barrier(states.end.0)
+            } else {
+                0
+            }
+        }
+    }
+}
+
 pub struct FormatDescription {
     pub tex_internal: u32,
     pub tex_external: u32,
@@ -3,6 +3,7 @@ use parking_lot::{Mutex, RwLock};
 use spirv_cross::{glsl, spirv, ErrorCode as SpirvErrorCode};
 use std::borrow::Borrow;
 use std::cell::Cell;
+use std::mem;
 use std::ops::Range;
 use std::slice;
 use std::sync::Arc;
@@ -460,6 +461,71 @@ pub(crate) unsafe fn set_sampler_info<SetParamFloat, SetParamFloatVec, SetParamI
     }
 }
 
+/// Read the result of a query, or `None` if it hasn't `ended` since it was reset,
+/// or isn't available yet and `WAIT` isn't set.
+///
+/// glow has no 64-bit getter, so results are read with `glGetQueryObjectuiv` and widened
+/// for `BITS_64`. GL clamps sample counts which don't fit in 32 bits to `u32::max_value()`.
+pub(crate) unsafe fn get_query_result(
+    gl: &GlContext,
+    query: n::Query,
+    ended: bool,
+    flags: query::ResultFlags,
+) -> Option<u64> {
+    // Waiting on a query which hasn't ended would never complete.
+    if !ended {
+        return None;
+    }
+    if !flags.contains(query::ResultFlags::WAIT)
+        && gl.get_query_parameter_u32(query, glow::QUERY_RESULT_AVAILABLE) == 0
+    {
+        return None;
+    }
+
+    Some(gl.get_query_parameter_u32(query, glow::QUERY_RESULT) as u64)
+}
+
+/// Write a query result into `data`, laid out as requested by `flags`,
+/// and return the range of `data` which was written.
+pub(crate) fn write_query_result(
+    data: &mut [u8],
+    result: Option<u64>,
+    flags: query::ResultFlags,
+) -> Range<usize> {
+    let size = if flags.contains(query::ResultFlags::BITS_64) {
+        8
+    } else {
+        4
+    };
+    let mut write = |offset: usize, value: u64| {
+        if size == 8 {
+            data[offset .. offset + 8].copy_from_slice(&value.to_ne_bytes());
+        } else {
+            data[offset .. offset + 4].copy_from_slice(&(value as u32).to_ne_bytes());
+        }
+    };
+
+    // Unavailable results are left untouched, unless partial results are allowed.
+    let start = match result {
+        Some(value) => {
+            write(0, value);
+            0
+        }
+        None if flags.contains(query::ResultFlags::PARTIAL) => {
+            write(0, 0);
+            0
+        }
+        None => size,
+    };
+    let end = if flags.contains(query::ResultFlags::WITH_AVAILABILITY) {
+        write(size, result.is_some() as u64);
+        2 * size
+    } else {
+        size
+    };
+    start .. end
+}
+
 impl d::Device<B> for Device {
     unsafe fn allocate_memory(
         &self,
@@ -508,11 +574,15 @@ impl d::Device<B> for Device {
                 if self.share.private_caps.buffer_storage {
                     let mut storage_flags = 0;
 
+                    // `fill_buffer` and `update_buffer` write with `glBufferSubData`,
+                    // which requires dynamic storage.
+                    if buffer_usage.contains(buffer::Usage::TRANSFER_DST) {
+                        storage_flags |= glow::DYNAMIC_STORAGE_BIT;
+                    }
+
                     if is_cpu_visible_memory {
                         map_flags |= glow::MAP_PERSISTENT_BIT;
-                        storage_flags |= glow::MAP_WRITE_BIT
-                            | glow::MAP_PERSISTENT_BIT
-                            | glow::DYNAMIC_STORAGE_BIT;
+                        storage_flags |= glow::MAP_WRITE_BIT | glow::MAP_PERSISTENT_BIT;
 
                         if is_readable_memory {
                             storage_flags |= glow::MAP_READ_BIT;
@@ -1397,7 +1467,9 @@ impl d::Device<B> for Device {
             };
             n::ImageKind::Renderbuffer {
                 renderbuffer: name,
+                internal_format: desc.tex_internal,
                 format: desc.tex_external,
+                pixel_type: desc.data_type,
             }
         };
 
@@ -1727,20 +1799,42 @@ impl d::Device<B> for Device {
         })
     }
 
-    fn create_event(&self) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    fn create_event(&self) -> Result<n::Event, d::OutOfMemory> {
+        let inner = n::EventInner::Idle { set: false };
+        Ok(n::Event(Arc::new(Mutex::new(inner))))
     }
 
-    unsafe fn get_event_status(&self, _event: &()) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
+    unsafe fn get_event_status(&self, event: &n::Event) -> Result<bool, d::OomOrDeviceLost> {
+        let gl = &self.share.context;
+        let mut inner = event.0.lock();
+        Ok(match *inner {
+            n::EventInner::Idle { set } => set,
+            n::EventInner::Pending(sync) => {
+                if gl.get_sync_status(sync) == glow::SIGNALED {
+                    gl.delete_sync(sync);
+                    *inner = n::EventInner::Idle { set: true };
+                    true
+                } else {
+                    false
+                }
+            }
+        })
     }
 
-    unsafe fn set_event(&self, _event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    unsafe fn set_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        let inner = mem::replace(&mut *event.0.lock(), n::EventInner::Idle { set: true });
+        if let n::EventInner::Pending(sync) = inner {
+            self.share.context.delete_sync(sync);
+        }
+        Ok(())
     }
 
-    unsafe fn reset_event(&self, _event: &()) -> Result<(), d::OutOfMemory> {
-        unimplemented!()
+    unsafe fn reset_event(&self, event: &n::Event) -> Result<(), d::OutOfMemory> {
+        let inner = mem::replace(&mut *event.0.lock(), n::EventInner::Idle { set: false });
+        if let n::EventInner::Pending(sync) = inner {
+            self.share.context.delete_sync(sync);
+        }
+        Ok(())
     }
 
     unsafe fn free_memory(&self, memory: n::Memory) {
@@ -1751,25 +1845,75 @@ impl d::Device<B> for Device {
 
     unsafe fn create_query_pool(
         &self,
-        _ty: query::Type,
-        _count: query::Id,
-    ) -> Result<(), query::CreationError> {
-        unimplemented!()
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool, query::CreationError> {
+        let caps = &self.share.private_caps;
+        let target = match ty {
+            // Counting the samples is slower than telling whether any passed.
+            query::Type::Occlusion if caps.occlusion_query => {
+                if self
+                    .features
+                    .contains(hal::Features::PRECISE_OCCLUSION_QUERY)
+                {
+                    glow::SAMPLES_PASSED
+                } else {
+                    glow::ANY_SAMPLES_PASSED
+                }
+            }
+            // Timestamps need `glQueryCounter`, which glow doesn't expose. Pipeline statistics
+            // need `ARB_pipeline_statistics_query`, which isn't used, so
+            // `Features::PIPELINE_STATISTICS_QUERY` is never advertised either.
+            _ => return Err(query::CreationError::Unsupported(ty)),
+        };
+
+        let gl = &self.share.context;
+        let mut queries = Vec::with_capacity(count as usize);
+        for _ in 0 .. count {
+            match gl.create_query() {
+                Ok(query) => queries.push(query),
+                Err(_) => {
+                    for query in queries {
+                        gl.delete_query(query);
+                    }
+                    return Err(d::OutOfMemory::Host.into());
+                }
+            }
+        }
+
+        Ok(n::QueryPool {
+            queries: queries.into_boxed_slice(),
+            target,
+            ended: Arc::new(Mutex::new(vec![false; count as usize].into_boxed_slice())),
+        })
     }
 
-    unsafe fn destroy_query_pool(&self, _: ()) {
-        unimplemented!()
+    unsafe fn destroy_query_pool(&self, pool: n::QueryPool) {
+        let gl = &self.share.context;
+        for &query in pool.queries.iter() {
+            gl.delete_query(query);
+        }
     }
 
     unsafe fn get_query_pool_results(
         &self,
-        _pool: &(),
-        _queries: Range<query::Id>,
-        _data: &mut [u8],
-        _stride: buffer::Offset,
-        _flags: query::ResultFlags,
+        pool: &n::QueryPool,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
     ) -> Result<bool, d::OomOrDeviceLost> {
-        unimplemented!()
+        let gl = &self.share.context;
+        let mut all_available = true;
+
+        let ended = pool.ended.lock();
+        for (i, id) in (queries.start .. queries.end).enumerate() {
+            let id = id as usize;
+            let result = get_query_result(gl, pool.queries[id], ended[id], flags);
+            all_available &= result.is_some();
+            write_query_result(&mut data[i * stride as usize ..], result, flags);
+        }
+        Ok(all_available)
     }
 
     unsafe fn destroy_shader_module(&self, _: n::ShaderModule) {
@@ -1850,8 +1994,10 @@ impl d::Device<B> for Device {
         // Nothing to do
     }
 
-    unsafe fn destroy_event(&self, _event: ()) {
-        unimplemented!()
+    unsafe fn destroy_event(&self, event: n::Event) {
+        if let n::EventInner::Pending(sync) = *event.0.lock() {
+            self.share.context.delete_sync(sync);
+        }
     }
 
     unsafe fn create_swapchain(
@@ -199,6 +199,10 @@ pub struct PrivateCaps {
     pub depth_range_f64_precision: bool,
     /// Whether draw buffers are supported
     pub draw_buffers: bool,
+    /// Whether occlusion queries are supported
+    pub occlusion_query: bool,
+    /// Whether `glMemoryBarrier` is supported
+    pub memory_barrier: bool,
 }
 
 /// OpenGL implementation information
@@ -424,11 +428,18 @@ pub(crate) fn query_all(
     if info.is_supported(&[Core(4, 0), Es(3, 2), Ext("GL_EXT_draw_buffers2")]) && !info.is_webgl() {
         features |= Features::INDEPENDENT_BLENDING;
     }
+    if info.is_supported(&[Core(3, 3)]) {
+        // Occlusion queries count samples with `GL_SAMPLES_PASSED`, which isn't in GLES.
+        features |= Features::PRECISE_OCCLUSION_QUERY;
+    }
 
-    // TODO
-    if false && info.is_supported(&[Core(4, 3), Es(3, 1)]) {
-        // TODO: extension
+    if info.is_supported(&[Core(4, 0), Es(3, 1), Ext("GL_ARB_draw_indirect")]) {
         legacy |= LegacyFeatures::INDIRECT_EXECUTION;
+        // Indirect draws are read back and issued one by one.
+        features |= Features::MULTI_DRAW_INDIRECT;
+    }
+    if info.is_supported(&[Core(4, 2), Ext("GL_ARB_base_instance")]) {
+        features |= Features::DRAW_INDIRECT_FIRST_INSTANCE;
     }
     if info.is_supported(&[Core(3, 1), Es(3, 0), Ext("GL_ARB_draw_instanced")]) {
         legacy |= LegacyFeatures::DRAW_INSTANCED;
@@ -509,6 +520,12 @@ pub(crate) fn query_all(
         emulate_map,                    // TODO
         depth_range_f64_precision: !info.version.is_embedded, // TODO
         draw_buffers: info.is_supported(&[Core(2, 0), Es(3, 0)]),
+        occlusion_query: info.is_supported(&[Core(3, 3), Es(3, 0)]),
+        memory_barrier: info.is_supported(&[
+            Core(4, 2),
+            Es(3, 1),
+            Ext("GL_ARB_shader_image_load_store"),
+        ]),
     };
 
     (info, features, legacy, hints, limits, private)
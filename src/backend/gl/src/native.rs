@@ -27,6 +27,7 @@ pub type UniformLocation = crate::Starc<<GlContext as glow::HasContext>::Uniform
 pub type DescriptorSetLayout = Vec<pso::DescriptorSetLayoutBinding>;
 
 pub type RawFrameBuffer = <GlContext as glow::HasContext>::Framebuffer;
+pub type Query = <GlContext as glow::HasContext>::Query;
 
 #[derive(Clone, Debug)]
 pub struct FrameBuffer {
@@ -58,6 +59,16 @@ impl Buffer {
 #[derive(Debug)]
 pub struct BufferView;
 
+#[derive(Debug)]
+pub struct QueryPool {
+    pub(crate) queries: Box<[Query]>,
+    /// `GL_SAMPLES_PASSED` if precise occlusion queries are enabled, `GL_ANY_SAMPLES_PASSED`
+    /// otherwise. A GL query keeps the target of its first `glBeginQuery`.
+    pub(crate) target: u32,
+    /// Whether each query has ended since it was last reset, shared with the command buffers.
+    pub(crate) ended: Arc<Mutex<Box<[bool]>>>,
+}
+
 #[derive(Copy, Clone, Debug)]
 pub(crate) enum FenceInner {
     Idle { signaled: bool },
@@ -69,6 +80,21 @@ pub struct Fence(pub(crate) Cell<FenceInner>);
 unsafe impl Send for Fence {}
 unsafe impl Sync for Fence {}
 
+#[derive(Copy, Clone, Debug)]
+pub(crate) enum EventInner {
+    Idle {
+        set: bool,
+    },
+    /// Set by a command buffer, once the GPU reaches the sync object.
+    Pending(<GlContext as glow::HasContext>::Fence),
+}
+
+/// Event shared with the command buffers recording it.
+#[derive(Clone, Debug)]
+pub struct Event(pub(crate) Arc<Mutex<EventInner>>);
+unsafe impl Send for Event {}
+unsafe impl Sync for Event {}
+
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
 pub enum BindingTypes {
     Images,
@@ -177,7 +203,9 @@ pub struct Image {
 pub enum ImageKind {
     Renderbuffer {
         renderbuffer: Renderbuffer,
+        internal_format: TextureFormat,
         format: TextureFormat,
+        pixel_type: DataType,
     },
     Texture {
         texture: Texture,
@@ -1,4 +1,5 @@
 use std::borrow::Borrow;
+use std::ops::Range;
 use std::{mem, slice};
 
 use glow::HasContext;
@@ -189,6 +190,163 @@ impl CommandQueue {
         unsafe { gl.framebuffer_texture(point, attachment, None, 0) };
     }
 
+    /// View of a single layer of an image, for attaching it to a framebuffer.
+    fn layer_view(
+        image: native::ImageKind,
+        level: hal::image::Level,
+        layer: hal::image::Layer,
+    ) -> native::ImageView {
+        match image {
+            native::ImageKind::Renderbuffer { renderbuffer, .. } => {
+                native::ImageView::Renderbuffer(renderbuffer)
+            }
+            native::ImageKind::Texture {
+                texture, target, ..
+            } => Self::texture_layer_view(texture, target, level, layer),
+        }
+    }
+
+    fn texture_layer_view(
+        texture: native::Texture,
+        target: native::TextureTarget,
+        level: hal::image::Level,
+        layer: hal::image::Layer,
+    ) -> native::ImageView {
+        match target {
+            glow::TEXTURE_2D | glow::TEXTURE_2D_MULTISAMPLE => {
+                debug_assert_eq!(layer, 0);
+                native::ImageView::Texture(texture, target, level)
+            }
+            _ => native::ImageView::TextureLayer(texture, target, level, layer),
+        }
+    }
+
+    /// Layers of a region of an array image, or its depth slices for a 3D texture.
+    fn slices(
+        image: native::ImageKind,
+        subresource: &hal::image::SubresourceLayers,
+        bounds: &Range<hal::image::Offset>,
+    ) -> Range<u32> {
+        match image {
+            native::ImageKind::Texture {
+                target: glow::TEXTURE_3D,
+                ..
+            } => bounds.start.z as u32 .. bounds.end.z as u32,
+            _ => subresource.layers.start as u32 .. subresource.layers.end as u32,
+        }
+    }
+
+    /// Blit a region between two images, one layer or depth slice at a time.
+    fn blit_image(
+        &mut self,
+        src_image: native::ImageKind,
+        dst_image: native::ImageKind,
+        filter: u32,
+        data: &hal::command::ImageBlit,
+    ) {
+        let src_slices = Self::slices(src_image, &data.src_subresource, &data.src_bounds);
+        let dst_slices = Self::slices(dst_image, &data.dst_subresource, &data.dst_bounds);
+        let src_count = src_slices.end - src_slices.start;
+        let dst_count = dst_slices.end - dst_slices.start;
+
+        for i in 0 .. dst_count {
+            // Framebuffer blits are 2D, so scaling along the depth picks the nearest slice.
+            let src_slice = src_slices.start + i * src_count / dst_count;
+            let src = Self::layer_view(
+                src_image,
+                data.src_subresource.level,
+                src_slice as hal::image::Layer,
+            );
+            let dst = Self::layer_view(
+                dst_image,
+                data.dst_subresource.level,
+                (dst_slices.start + i) as hal::image::Layer,
+            );
+            self.blit_framebuffer(
+                &src,
+                &dst,
+                data.src_subresource.aspects,
+                &data.src_bounds,
+                &data.dst_bounds,
+                filter,
+            );
+        }
+    }
+
+    /// Framebuffer attachment point and buffer mask of the aspects of an image.
+    fn attachment(aspects: hal::format::Aspects) -> (u32, u32) {
+        use hal::format::Aspects;
+
+        if aspects.contains(Aspects::COLOR) {
+            (glow::COLOR_ATTACHMENT0, glow::COLOR_BUFFER_BIT)
+        } else if aspects.contains(Aspects::DEPTH | Aspects::STENCIL) {
+            (
+                glow::DEPTH_STENCIL_ATTACHMENT,
+                glow::DEPTH_BUFFER_BIT | glow::STENCIL_BUFFER_BIT,
+            )
+        } else if aspects.contains(Aspects::DEPTH) {
+            (glow::DEPTH_ATTACHMENT, glow::DEPTH_BUFFER_BIT)
+        } else {
+            (glow::STENCIL_ATTACHMENT, glow::STENCIL_BUFFER_BIT)
+        }
+    }
+
+    /// Blit a region between two views, attached to temporary read and draw framebuffers.
+    fn blit_framebuffer(
+        &mut self,
+        src: &native::ImageView,
+        dst: &native::ImageView,
+        aspects: hal::format::Aspects,
+        src_bounds: &Range<hal::image::Offset>,
+        dst_bounds: &Range<hal::image::Offset>,
+        filter: u32,
+    ) {
+        if !self.share.private_caps.framebuffer {
+            error!("Tried to blit images without FBO support!");
+            return;
+        }
+
+        let (attachment, mask) = Self::attachment(aspects);
+        // Depth and stencil can only be blitted without filtering.
+        let filter = if mask == glow::COLOR_BUFFER_BIT {
+            filter
+        } else {
+            glow::NEAREST
+        };
+
+        let (src_fbo, dst_fbo) = unsafe {
+            let gl = &self.share.context;
+            let src_fbo = gl.create_framebuffer().unwrap();
+            let dst_fbo = gl.create_framebuffer().unwrap();
+            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(src_fbo));
+            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(dst_fbo));
+            (src_fbo, dst_fbo)
+        };
+        self.bind_target(glow::READ_FRAMEBUFFER, attachment, src);
+        self.bind_target(glow::DRAW_FRAMEBUFFER, attachment, dst);
+
+        let gl = &self.share.context;
+        unsafe {
+            gl.blit_framebuffer(
+                src_bounds.start.x,
+                src_bounds.start.y,
+                src_bounds.end.x,
+                src_bounds.end.y,
+                dst_bounds.start.x,
+                dst_bounds.start.y,
+                dst_bounds.end.x,
+                dst_bounds.end.y,
+                mask,
+                filter,
+            );
+
+            gl.bind_framebuffer(glow::FRAMEBUFFER, self.state.fbo);
+
+            gl.delete_framebuffer(src_fbo);
+            gl.delete_framebuffer(dst_fbo);
+        }
+    }
+
     /// Return a reference to a stored data object.
     fn get<T>(data: &[u8], ptr: com::BufferSlice) -> &[T] {
         let u32_size = mem::size_of::<T>();
@@ -335,6 +493,32 @@ impl CommandQueue {
         }
     }
 
+    /// Read back the parameters of `draw_count` indirect draws, `words` 32-bit values each.
+    fn read_indirect_params(
+        &self,
+        buffer: native::RawBuffer,
+        offset: hal::buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+        words: usize,
+    ) -> Vec<u32> {
+        if draw_count == 0 {
+            return Vec::new();
+        }
+        let size = words * mem::size_of::<u32>();
+        let mut data = vec![0u8; (draw_count as usize - 1) * stride as usize + size];
+        let gl = &self.share.context;
+        unsafe {
+            gl.bind_buffer(glow::COPY_READ_BUFFER, Some(buffer));
+            gl.get_buffer_sub_data(glow::COPY_READ_BUFFER, offset as i32, &mut data);
+            gl.bind_buffer(glow::COPY_READ_BUFFER, None);
+        }
+        (0 .. draw_count as usize)
+            .flat_map(|i| data[i * stride as usize ..][.. size].chunks(4))
+            .map(|b| u32::from_ne_bytes([b[0], b[1], b[2], b[3]]))
+            .collect()
+    }
+
     fn process(&mut self, cmd: &com::Command, data_buf: &[u8]) {
         match *cmd {
             com::Command::BindIndexBuffer(buffer) => {
@@ -467,6 +651,51 @@ impl CommandQueue {
                     error!("Instanced indexed drawing is not supported");
                 }
             }
+            com::Command::DrawIndirect {
+                primitive,
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => {
+                // glow has no indirect draw entry points, so the parameters are read back
+                // and the draws are issued directly.
+                let params = self.read_indirect_params(buffer, offset, draw_count, stride, 4);
+                for p in params.chunks(4) {
+                    let draw = com::Command::Draw {
+                        primitive,
+                        vertices: p[2] .. p[2] + p[0],
+                        instances: p[3] .. p[3] + p[1],
+                    };
+                    self.process(&draw, data_buf);
+                }
+            }
+            com::Command::DrawIndexedIndirect {
+                primitive,
+                index_type,
+                index_buffer_offset,
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => {
+                let index_size = match index_type {
+                    glow::UNSIGNED_SHORT => 2,
+                    _ => 4,
+                };
+                let params = self.read_indirect_params(buffer, offset, draw_count, stride, 5);
+                for p in params.chunks(5) {
+                    let draw = com::Command::DrawIndexed {
+                        primitive,
+                        index_type,
+                        index_count: p[0],
+                        index_buffer_offset: index_buffer_offset + p[2] as u64 * index_size,
+                        base_vertex: p[3] as hal::VertexOffset,
+                        instances: p[4] .. p[4] + p[1],
+                    };
+                    self.process(&draw, data_buf);
+                }
+            }
             com::Command::Dispatch(count) => {
                 // Capability support is given by which queue types will be exposed.
                 // If there is no compute support, this pattern should never be reached
@@ -683,6 +912,41 @@ impl CommandQueue {
                 gl.bind_buffer(glow::COPY_READ_BUFFER, None);
                 gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
             },
+            com::Command::FillBuffer(buffer, ref range, data) => unsafe {
+                // Write the pattern in chunks, to bound the size of the temporary data.
+                const CHUNK_SIZE: u64 = 1 << 16;
+                let gl = &self.share.context;
+                let chunk = data
+                    .to_ne_bytes()
+                    .iter()
+                    .cloned()
+                    .cycle()
+                    .take((range.end - range.start).min(CHUNK_SIZE) as usize)
+                    .collect::<Vec<u8>>();
+
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+                let mut offset = range.start;
+                while offset < range.end {
+                    let size = (range.end - offset).min(CHUNK_SIZE) as usize;
+                    gl.buffer_sub_data_u8_slice(
+                        glow::COPY_WRITE_BUFFER,
+                        offset as i32,
+                        &chunk[.. size],
+                    );
+                    offset += size as u64;
+                }
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            },
+            com::Command::UpdateBuffer(buffer, offset, data) => unsafe {
+                let gl = &self.share.context;
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+                gl.buffer_sub_data_u8_slice(
+                    glow::COPY_WRITE_BUFFER,
+                    offset as i32,
+                    Self::get_raw(data_buf, data),
+                );
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            },
             com::Command::CopyBufferToTexture {
                 src_buffer,
                 dst_texture,
@@ -736,8 +1000,67 @@ impl CommandQueue {
 
                 gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
             },
-            com::Command::CopyBufferToRenderbuffer(..) => {
-                unimplemented!() //TODO: use FBO
+            com::Command::CopyBufferToRenderbuffer {
+                src_buffer,
+                dst_renderbuffer,
+                internal_format,
+                texture_format,
+                pixel_type,
+                ref data,
+            } => {
+                // Renderbuffers can't be uploaded to, so go through a scratch texture.
+                let width = data.image_extent.width as i32;
+                let height = data.image_extent.height as i32;
+                let texture = unsafe {
+                    let gl = &self.share.context;
+                    let texture = gl.create_texture().unwrap();
+                    gl.active_texture(glow::TEXTURE0);
+                    gl.bind_texture(glow::TEXTURE_2D, Some(texture));
+                    if self.share.private_caps.image_storage {
+                        gl.tex_storage_2d(glow::TEXTURE_2D, 1, internal_format, width, height);
+                    } else {
+                        gl.tex_image_2d(
+                            glow::TEXTURE_2D,
+                            0,
+                            internal_format as i32,
+                            width,
+                            height,
+                            0,
+                            texture_format,
+                            pixel_type,
+                            None,
+                        );
+                    }
+
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, Some(src_buffer));
+                    gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, data.buffer_width as i32);
+                    gl.tex_sub_image_2d_pixel_buffer_offset(
+                        glow::TEXTURE_2D,
+                        0,
+                        0,
+                        0,
+                        width,
+                        height,
+                        texture_format,
+                        pixel_type,
+                        data.buffer_offset as i32,
+                    );
+                    gl.pixel_store_i32(glow::UNPACK_ROW_LENGTH, 0);
+                    gl.bind_buffer(glow::PIXEL_UNPACK_BUFFER, None);
+                    texture
+                };
+
+                let origin = hal::image::Offset { x: 0, y: 0, z: 0 };
+                self.blit_framebuffer(
+                    &native::ImageView::Texture(texture, glow::TEXTURE_2D, 0),
+                    &native::ImageView::Renderbuffer(dst_renderbuffer),
+                    data.image_layers.aspects,
+                    &origin.into_bounds(&data.image_extent),
+                    &data.image_offset.into_bounds(&data.image_extent),
+                    glow::NEAREST,
+                );
+
+                unsafe { self.share.context.delete_texture(texture) };
             }
             com::Command::CopyTextureToBuffer {
                 src_texture,
@@ -768,76 +1091,134 @@ impl CommandQueue {
                 );
                 gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
             },
-            com::Command::CopyRenderbufferToBuffer(..) => {
-                unimplemented!() //TODO: use FBO
-            }
-            com::Command::CopyImageToTexture(..) => {
-                unimplemented!() //TODO: use FBO
-            }
-            com::Command::CopyImageToRenderbuffer {
-                src_image,
-                dst_renderbuffer,
-                dst_format,
+            com::Command::CopyRenderbufferToBuffer {
+                src_renderbuffer,
+                texture_format,
+                pixel_type,
+                dst_buffer,
                 ref data,
-            } => {
+            } => unsafe {
                 let gl = &self.share.context;
+                let (attachment, _) = Self::attachment(data.image_layers.aspects);
 
-                if data.src_subresource.aspects != hal::format::Aspects::COLOR
-                    || data.dst_subresource.aspects != hal::format::Aspects::COLOR
-                {
-                    unimplemented!()
-                }
-
-                match src_image {
-                    native::ImageKind::Texture { .. } => unimplemented!(),
-                    native::ImageKind::Renderbuffer {
-                        renderbuffer: src_renderbuffer,
-                        format: src_format,
-                    } => {
-                        if src_format != dst_format {
-                            unimplemented!()
-                        }
-
-                        unsafe {
-                            let src_fbo = gl.create_framebuffer().unwrap();
-                            gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(src_fbo));
-                            gl.framebuffer_renderbuffer(
-                                glow::READ_FRAMEBUFFER,
-                                glow::COLOR_ATTACHMENT0,
-                                glow::RENDERBUFFER,
-                                Some(src_renderbuffer),
-                            );
-
-                            let dst_fbo = gl.create_framebuffer().unwrap();
-                            gl.bind_framebuffer(glow::DRAW_FRAMEBUFFER, Some(dst_fbo));
-                            gl.framebuffer_renderbuffer(
-                                glow::DRAW_FRAMEBUFFER,
-                                glow::COLOR_ATTACHMENT0,
-                                glow::RENDERBUFFER,
-                                Some(dst_renderbuffer),
-                            );
-
-                            gl.blit_framebuffer(
-                                data.src_offset.x,
-                                data.src_offset.y,
-                                data.src_offset.x + data.extent.width as i32,
-                                data.src_offset.y + data.extent.height as i32,
-                                data.dst_offset.x,
-                                data.dst_offset.y,
-                                data.dst_offset.x + data.extent.width as i32,
-                                data.dst_offset.y + data.extent.height as i32,
-                                glow::COLOR_BUFFER_BIT,
-                                glow::NEAREST,
-                            );
+                let fbo = gl.create_framebuffer().unwrap();
+                gl.bind_framebuffer(glow::READ_FRAMEBUFFER, Some(fbo));
+                gl.framebuffer_renderbuffer(
+                    glow::READ_FRAMEBUFFER,
+                    attachment,
+                    glow::RENDERBUFFER,
+                    Some(src_renderbuffer),
+                );
 
-                            gl.bind_framebuffer(glow::FRAMEBUFFER, None);
+                gl.bind_buffer(glow::PIXEL_PACK_BUFFER, Some(dst_buffer));
+                gl.pixel_store_i32(glow::PACK_ROW_LENGTH, data.buffer_width as i32);
+                gl.read_pixels_pixel_buffer_offset(
+                    data.image_offset.x,
+                    data.image_offset.y,
+                    data.image_extent.width as i32,
+                    data.image_extent.height as i32,
+                    texture_format,
+                    pixel_type,
+                    data.buffer_offset as i32,
+                );
+                gl.pixel_store_i32(glow::PACK_ROW_LENGTH, 0);
+                gl.bind_buffer(glow::PIXEL_PACK_BUFFER, None);
 
-                            gl.delete_framebuffer(src_fbo);
-                            gl.delete_framebuffer(dst_fbo);
-                        }
-                    }
+                gl.bind_framebuffer(glow::FRAMEBUFFER, self.state.fbo);
+                gl.delete_framebuffer(fbo);
+            },
+            com::Command::SetEvent(ref event) => unsafe {
+                let gl = &self.share.context;
+                let set = if self.share.private_caps.sync {
+                    native::EventInner::Pending(
+                        gl.fence_sync(glow::SYNC_GPU_COMMANDS_COMPLETE, 0).unwrap(),
+                    )
+                } else {
+                    // Without sync objects, the commands are only known to complete in order.
+                    gl.flush();
+                    native::EventInner::Idle { set: true }
+                };
+                let old = mem::replace(&mut *event.0.lock(), set);
+                if let native::EventInner::Pending(sync) = old {
+                    gl.delete_sync(sync);
+                }
+            },
+            com::Command::ResetEvent(ref event) => unsafe {
+                let reset = native::EventInner::Idle { set: false };
+                let old = mem::replace(&mut *event.0.lock(), reset);
+                if let native::EventInner::Pending(sync) = old {
+                    self.share.context.delete_sync(sync);
+                }
+            },
+            com::Command::WaitEvent(ref event) => match *event.0.lock() {
+                native::EventInner::Idle { set: true } => {}
+                native::EventInner::Idle { set: false } => {
+                    // Commands are replayed on submission, so the host can't set it later.
+                    // Carry on as if it was, since the wait can't complete otherwise.
+                    warn!("Waiting on an event which isn't set, ignoring the wait");
+                }
+                native::EventInner::Pending(sync) => unsafe {
+                    self.share.context.wait_sync(sync, 0, glow::TIMEOUT_IGNORED);
+                },
+            },
+            com::Command::MemoryBarrier(barriers) => {
+                if self.share.private_caps.memory_barrier {
+                    unsafe { self.share.context.memory_barrier(barriers) };
+                }
+            }
+            com::Command::BeginQuery(query, target) => unsafe {
+                self.share.context.begin_query(target, query);
+            },
+            com::Command::EndQuery {
+                target,
+                ref ended,
+                id,
+            } => {
+                unsafe { self.share.context.end_query(target) };
+                ended.lock()[id as usize] = true;
+            }
+            com::Command::ResetQueries(ref ended, ref queries) => {
+                for ended in &mut ended.lock()[queries.start as usize .. queries.end as usize] {
+                    *ended = false;
                 }
             }
+            com::Command::CopyQueryResults {
+                queries,
+                ref ended,
+                first,
+                buffer,
+                offset,
+                stride,
+                flags,
+            } => unsafe {
+                let gl = &self.share.context;
+                let queries = Self::get::<native::Query>(data_buf, queries);
+                let ended = ended.lock();
+
+                // Read the results back and upload them, which stalls until they're
+                // available if `WAIT` is set.
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, Some(buffer));
+                for (i, &query) in queries.iter().enumerate() {
+                    let mut entry = [0; 16];
+                    let result =
+                        device::get_query_result(gl, query, ended[first as usize + i], flags);
+                    let range = device::write_query_result(&mut entry, result, flags);
+                    if range.start < range.end {
+                        gl.buffer_sub_data_u8_slice(
+                            glow::COPY_WRITE_BUFFER,
+                            (offset + i as u64 * stride) as i32 + range.start as i32,
+                            &entry[range],
+                        );
+                    }
+                }
+                gl.bind_buffer(glow::COPY_WRITE_BUFFER, None);
+            },
+            com::Command::BlitImage {
+                src_image,
+                dst_image,
+                filter,
+                ref data,
+            } => self.blit_image(src_image, dst_image, filter, data),
             com::Command::BindBufferRange(target, index, buffer, offset, size) => unsafe {
                 let gl = &self.share.context;
                 gl.bind_buffer_range(target, index, Some(buffer), offset, size);
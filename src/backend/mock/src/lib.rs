@@ -0,0 +1,1373 @@
+//! Mock backend recording the calls made to it.
+//!
+//! Meant for testing the layers built on top of `gfx-hal`, such as the
+//! validation and trace backends, without any hardware. Every call is appended
+//! to a log of the calling thread, see `take_calls`.
+//!
+//! Objects only carry the state needed to answer queries consistently: CPU
+//! visible memory is backed by host storage so it can be mapped, and fences
+//! and events keep their status. Commands are recorded but never executed.
+
+extern crate gfx_hal as hal;
+
+use hal::{
+    adapter,
+    buffer,
+    command,
+    device,
+    format,
+    image,
+    memory,
+    pass,
+    pool,
+    pso,
+    query,
+    queue,
+    window,
+};
+use std::borrow::Borrow;
+use std::cell::{RefCell, UnsafeCell};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+thread_local! {
+    static CALLS: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+fn record(call: &'static str) {
+    CALLS.with(|calls| calls.borrow_mut().push(call));
+}
+
+/// Removes and returns the names of the methods called on this thread so far.
+///
+/// Methods sharing their name with one of another object are prefixed with
+/// the object, such as `queue_wait_idle` or `cmd_set_event`. Methods taking a
+/// list of descriptor writes, copies or mapped ranges are recorded once per
+/// element, so that the elements dropped by a layer can be told apart.
+pub fn take_calls() -> Vec<&'static str> {
+    CALLS.with(|calls| std::mem::take(&mut *calls.borrow_mut()))
+}
+
+/// Memory type only buffers can be bound to.
+pub const HOST_VISIBLE: hal::MemoryTypeId = hal::MemoryTypeId(1);
+/// Memory type of images, which can't be mapped.
+pub const DEVICE_LOCAL: hal::MemoryTypeId = hal::MemoryTypeId(0);
+
+const BUFFER_ALIGNMENT: u64 = 4;
+const IMAGE_ALIGNMENT: u64 = 256;
+const HEAP_SIZE: u64 = 1 << 30;
+
+/// Mock backend.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Backend {}
+impl hal::Backend for Backend {
+    type Instance = Instance;
+    type PhysicalDevice = PhysicalDevice;
+    type Device = Device;
+
+    type Surface = Surface;
+    type Swapchain = Swapchain;
+
+    type QueueFamily = QueueFamily;
+    type CommandQueue = CommandQueue;
+    type CommandBuffer = CommandBuffer;
+
+    type Memory = Memory;
+    type CommandPool = CommandPool;
+
+    type ShaderModule = ();
+    type RenderPass = ();
+    type Framebuffer = ();
+
+    type Buffer = Buffer;
+    type BufferView = ();
+    type Image = Image;
+    type ImageView = ();
+    type Sampler = ();
+
+    type ComputePipeline = ();
+    type GraphicsPipeline = ();
+    type PipelineCache = ();
+    type PipelineLayout = ();
+    type DescriptorSetLayout = ();
+    type DescriptorPool = DescriptorPool;
+    type DescriptorSet = ();
+
+    type Fence = Fence;
+    type Semaphore = ();
+    type Event = Event;
+    type QueryPool = ();
+}
+
+/// Mock physical device, exposing one device local and one host visible
+/// memory type.
+#[derive(Debug)]
+pub struct PhysicalDevice;
+impl adapter::PhysicalDevice<Backend> for PhysicalDevice {
+    unsafe fn open(
+        &self,
+        families: &[(&QueueFamily, &[queue::QueuePriority])],
+        _: hal::Features,
+    ) -> Result<adapter::Gpu<Backend>, device::CreationError> {
+        record("open");
+        let queue_groups = families
+            .iter()
+            .map(|&(family, priorities)| {
+                let mut group = queue::QueueGroup::new(queue::QueueFamily::id(family));
+                for _ in priorities {
+                    group.add_queue(CommandQueue::default());
+                }
+                group
+            })
+            .collect();
+        Ok(adapter::Gpu {
+            device: Device,
+            queue_groups,
+        })
+    }
+
+    fn format_properties(&self, _: Option<format::Format>) -> format::Properties {
+        record("format_properties");
+        format::Properties {
+            linear_tiling: format::ImageFeature::all(),
+            optimal_tiling: format::ImageFeature::all(),
+            buffer_features: format::BufferFeature::all(),
+        }
+    }
+
+    fn image_format_properties(
+        &self,
+        _: format::Format,
+        _dim: u8,
+        _: image::Tiling,
+        _: image::Usage,
+        _: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        record("image_format_properties");
+        Some(image::FormatProperties {
+            max_extent: image::Extent {
+                width: 4096,
+                height: 4096,
+                depth: 256,
+            },
+            max_levels: 13,
+            max_layers: 256,
+            sample_count_mask: 1,
+            max_resource_size: HEAP_SIZE as usize,
+        })
+    }
+
+    fn memory_properties(&self) -> adapter::MemoryProperties {
+        record("memory_properties");
+        adapter::MemoryProperties {
+            memory_types: vec![
+                adapter::MemoryType {
+                    properties: memory::Properties::DEVICE_LOCAL,
+                    heap_index: 0,
+                },
+                adapter::MemoryType {
+                    properties: memory::Properties::CPU_VISIBLE | memory::Properties::COHERENT,
+                    heap_index: 1,
+                },
+            ],
+            memory_heaps: vec![HEAP_SIZE, HEAP_SIZE],
+        }
+    }
+
+    fn features(&self) -> hal::Features {
+        record("features");
+        hal::Features::empty()
+    }
+
+    fn hints(&self) -> hal::Hints {
+        record("hints");
+        hal::Hints::empty()
+    }
+
+    fn limits(&self) -> hal::Limits {
+        record("limits");
+        hal::Limits {
+            max_image_2d_size: 4096,
+            max_bound_descriptor_sets: 4,
+            buffer_image_granularity: 1,
+            min_texel_buffer_offset_alignment: BUFFER_ALIGNMENT,
+            min_uniform_buffer_offset_alignment: BUFFER_ALIGNMENT,
+            min_storage_buffer_offset_alignment: BUFFER_ALIGNMENT,
+            optimal_buffer_copy_offset_alignment: BUFFER_ALIGNMENT,
+            optimal_buffer_copy_pitch_alignment: BUFFER_ALIGNMENT,
+            non_coherent_atom_size: 1,
+            min_vertex_input_binding_stride_alignment: 1,
+            ..hal::Limits::default()
+        }
+    }
+}
+
+/// Mock command queue.
+///
+/// Fences passed to `submit` are signaled right away, unless the queue is
+/// holding them back to simulate work in flight.
+#[derive(Debug, Default)]
+pub struct CommandQueue {
+    held: Option<Vec<Fence>>,
+}
+
+impl CommandQueue {
+    /// Keeps the fences of the following submissions unsignaled until
+    /// `release_fences` is called.
+    pub fn hold_fences(&mut self) {
+        self.held.get_or_insert_with(Vec::new);
+    }
+
+    /// Signals the fences held back since `hold_fences`, finishing the work
+    /// submitted in between.
+    pub fn release_fences(&mut self) {
+        for fence in self.held.take().unwrap_or_default() {
+            fence.0.store(true, Ordering::Release);
+        }
+    }
+}
+
+impl queue::CommandQueue<Backend> for CommandQueue {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        _: queue::Submission<Ic, Iw, Is>,
+        fence: Option<&Fence>,
+    ) where
+        T: 'a + Borrow<CommandBuffer>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<()>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        record("submit");
+        if let Some(fence) = fence {
+            match self.held {
+                Some(ref mut held) => held.push(fence.clone()),
+                None => fence.0.store(true, Ordering::Release),
+            }
+        }
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        _: Is,
+        _: Iw,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError>
+    where
+        W: 'a + Borrow<Swapchain>,
+        Is: IntoIterator<Item = (&'a W, window::SwapImageIndex)>,
+        S: 'a + Borrow<()>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        record("present");
+        Ok(None)
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        surface: &mut Surface,
+        _image: (),
+        _wait_semaphore: Option<&()>,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError> {
+        match *surface {}
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        record("queue_wait_idle");
+        Ok(())
+    }
+}
+
+/// Mock memory object.
+#[derive(Debug)]
+pub struct Memory {
+    /// Host storage backing the mappings, `None` for device local memory.
+    data: Option<Box<[UnsafeCell<u8>]>>,
+}
+unsafe impl Send for Memory {}
+unsafe impl Sync for Memory {}
+
+/// Mock buffer.
+#[derive(Debug)]
+pub struct Buffer {
+    size: u64,
+}
+
+/// Mock image, laid out level by level, then layer by layer.
+#[derive(Debug)]
+pub struct Image {
+    kind: image::Kind,
+    levels: image::Level,
+    format: format::Format,
+}
+
+impl Image {
+    fn level_footprint(&self, level: image::Level) -> image::SubresourceFootprint {
+        let desc = self.format.surface_desc();
+        let extent = self.kind.level_extent(level);
+        let row_pitch = extent.width.div_ceil(desc.dim.0 as u32) as u64 * desc.bits as u64 / 8;
+        let depth_pitch = extent.height.div_ceil(desc.dim.1 as u32) as u64 * row_pitch;
+        let array_pitch = depth_pitch * extent.depth as u64;
+        let start = match level {
+            0 => 0,
+            _ => self.level_footprint(level - 1).slice.end,
+        };
+        image::SubresourceFootprint {
+            slice: start .. start + array_pitch * self.kind.num_layers() as u64,
+            row_pitch,
+            array_pitch,
+            depth_pitch,
+        }
+    }
+}
+
+/// Mock fence, shared with the queue it is submitted to.
+#[derive(Clone, Debug)]
+pub struct Fence(Arc<AtomicBool>);
+
+/// Mock event.
+#[derive(Debug)]
+pub struct Event(AtomicBool);
+
+/// Mock device.
+#[derive(Debug)]
+pub struct Device;
+impl device::Device<Backend> for Device {
+    unsafe fn create_command_pool(
+        &self,
+        _: queue::QueueFamilyId,
+        _: pool::CommandPoolCreateFlags,
+    ) -> Result<CommandPool, device::OutOfMemory> {
+        record("create_command_pool");
+        Ok(CommandPool)
+    }
+
+    unsafe fn destroy_command_pool(&self, _: CommandPool) {
+        record("destroy_command_pool");
+    }
+
+    unsafe fn allocate_memory(
+        &self,
+        memory_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<Memory, device::AllocationError> {
+        record("allocate_memory");
+        if size > HEAP_SIZE {
+            return Err(device::OutOfMemory::Device.into());
+        }
+        let data = if memory_type == HOST_VISIBLE {
+            let data = vec![0u8; size as usize].into_boxed_slice();
+            // `UnsafeCell<u8>` has the same layout as `u8`.
+            Some(Box::from_raw(Box::into_raw(data) as *mut [UnsafeCell<u8>]))
+        } else {
+            None
+        };
+        Ok(Memory { data })
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        _: IA,
+        _: IS,
+        _: ID,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        record("create_render_pass");
+        Ok(())
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(&self, _: IS, _: IR) -> Result<(), device::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<()>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        record("create_pipeline_layout");
+        Ok(())
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        _data: Option<&[u8]>,
+    ) -> Result<(), device::OutOfMemory> {
+        record("create_pipeline_cache");
+        Ok(())
+    }
+
+    unsafe fn get_pipeline_cache_data(&self, _cache: &()) -> Result<Vec<u8>, device::OutOfMemory> {
+        record("get_pipeline_cache_data");
+        Ok(Vec::new())
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, _: ()) {
+        record("destroy_pipeline_cache");
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        _: &pso::GraphicsPipelineDesc<'a, Backend>,
+        _: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        record("create_graphics_pipeline");
+        Ok(())
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        _: &pso::ComputePipelineDesc<'a, Backend>,
+        _: Option<&()>,
+    ) -> Result<(), pso::CreationError> {
+        record("create_compute_pipeline");
+        Ok(())
+    }
+
+    unsafe fn merge_pipeline_caches<I>(&self, _: &(), _: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        record("merge_pipeline_caches");
+        Ok(())
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        _: &(),
+        _: I,
+        _: image::Extent,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+    {
+        record("create_framebuffer");
+        Ok(())
+    }
+
+    unsafe fn create_shader_module(&self, _: &[u32]) -> Result<(), device::ShaderError> {
+        record("create_shader_module");
+        Ok(())
+    }
+
+    unsafe fn create_sampler(&self, _: &image::SamplerDesc) -> Result<(), device::AllocationError> {
+        record("create_sampler");
+        Ok(())
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        size: u64,
+        _: buffer::Usage,
+    ) -> Result<Buffer, buffer::CreationError> {
+        record("create_buffer");
+        Ok(Buffer { size })
+    }
+
+    unsafe fn get_buffer_requirements(&self, buffer: &Buffer) -> memory::Requirements {
+        record("get_buffer_requirements");
+        memory::Requirements {
+            size: (buffer.size + BUFFER_ALIGNMENT - 1) & !(BUFFER_ALIGNMENT - 1),
+            alignment: BUFFER_ALIGNMENT,
+            type_mask: 1 << DEVICE_LOCAL.0 | 1 << HOST_VISIBLE.0,
+        }
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        _: &Memory,
+        _: u64,
+        _: &mut Buffer,
+    ) -> Result<(), device::BindError> {
+        record("bind_buffer_memory");
+        Ok(())
+    }
+
+    unsafe fn create_buffer_view(
+        &self,
+        _: &Buffer,
+        _: Option<format::Format>,
+        _: buffer::SubRange,
+    ) -> Result<(), buffer::ViewCreationError> {
+        record("create_buffer_view");
+        Ok(())
+    }
+
+    unsafe fn create_image(
+        &self,
+        kind: image::Kind,
+        levels: image::Level,
+        format: format::Format,
+        _: image::Tiling,
+        _: image::Usage,
+        _: image::ViewCapabilities,
+    ) -> Result<Image, image::CreationError> {
+        record("create_image");
+        Ok(Image {
+            kind,
+            levels,
+            format,
+        })
+    }
+
+    unsafe fn get_image_requirements(&self, image: &Image) -> memory::Requirements {
+        record("get_image_requirements");
+        let size = image.level_footprint(image.levels - 1).slice.end;
+        memory::Requirements {
+            size: (size + IMAGE_ALIGNMENT - 1) & !(IMAGE_ALIGNMENT - 1),
+            alignment: IMAGE_ALIGNMENT,
+            type_mask: 1 << DEVICE_LOCAL.0,
+        }
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &Image,
+        subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        record("get_image_subresource_footprint");
+        let level = image.level_footprint(subresource.level);
+        let start = level.slice.start + level.array_pitch * subresource.layer as u64;
+        image::SubresourceFootprint {
+            slice: start .. start + level.array_pitch,
+            ..level
+        }
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        _: &Memory,
+        _: u64,
+        _: &mut Image,
+    ) -> Result<(), device::BindError> {
+        record("bind_image_memory");
+        Ok(())
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        _: &Image,
+        _: image::ViewKind,
+        _: format::Format,
+        _: format::Swizzle,
+        _: image::SubresourceRange,
+    ) -> Result<(), image::ViewCreationError> {
+        record("create_image_view");
+        Ok(())
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        _: usize,
+        _: I,
+        _: pso::DescriptorPoolCreateFlags,
+    ) -> Result<DescriptorPool, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        record("create_descriptor_pool");
+        Ok(DescriptorPool)
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        _: I,
+        _: J,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<()>,
+    {
+        record("create_descriptor_set_layout");
+        Ok(())
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, writes: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend>>,
+    {
+        for _ in writes {
+            record("write_descriptor_sets");
+        }
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, copies: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend>>,
+    {
+        for _ in copies {
+            record("copy_descriptor_sets");
+        }
+    }
+
+    fn create_semaphore(&self) -> Result<(), device::OutOfMemory> {
+        record("create_semaphore");
+        Ok(())
+    }
+
+    fn create_fence(&self, signaled: bool) -> Result<Fence, device::OutOfMemory> {
+        record("create_fence");
+        Ok(Fence(Arc::new(AtomicBool::new(signaled))))
+    }
+
+    unsafe fn reset_fence(&self, fence: &Fence) -> Result<(), device::OutOfMemory> {
+        record("reset_fence");
+        fence.0.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    unsafe fn reset_fences<I>(&self, fences: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Fence>,
+    {
+        record("reset_fences");
+        for fence in fences {
+            fence.borrow().0.store(false, Ordering::Release);
+        }
+        Ok(())
+    }
+
+    unsafe fn wait_for_fence(
+        &self,
+        fence: &Fence,
+        _timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        // Nothing can signal a held fence while waiting, so don't block.
+        record("wait_for_fence");
+        Ok(fence.0.load(Ordering::Acquire))
+    }
+
+    unsafe fn wait_for_fences<I>(
+        &self,
+        fences: I,
+        wait: device::WaitFor,
+        _timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Fence>,
+    {
+        record("wait_for_fences");
+        let mut status = fences
+            .into_iter()
+            .map(|fence| fence.borrow().0.load(Ordering::Acquire));
+        Ok(match wait {
+            device::WaitFor::All => status.all(|signaled| signaled),
+            device::WaitFor::Any => status.any(|signaled| signaled),
+        })
+    }
+
+    unsafe fn get_fence_status(&self, fence: &Fence) -> Result<bool, device::DeviceLost> {
+        record("get_fence_status");
+        Ok(fence.0.load(Ordering::Acquire))
+    }
+
+    fn create_event(&self) -> Result<Event, device::OutOfMemory> {
+        record("create_event");
+        Ok(Event(AtomicBool::new(false)))
+    }
+
+    unsafe fn get_event_status(&self, event: &Event) -> Result<bool, device::OomOrDeviceLost> {
+        record("get_event_status");
+        Ok(event.0.load(Ordering::Acquire))
+    }
+
+    unsafe fn set_event(&self, event: &Event) -> Result<(), device::OutOfMemory> {
+        record("set_event");
+        event.0.store(true, Ordering::Release);
+        Ok(())
+    }
+
+    unsafe fn reset_event(&self, event: &Event) -> Result<(), device::OutOfMemory> {
+        record("reset_event");
+        event.0.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    unsafe fn create_query_pool(&self, _: query::Type, _: u32) -> Result<(), query::CreationError> {
+        record("create_query_pool");
+        Ok(())
+    }
+
+    unsafe fn destroy_query_pool(&self, _: ()) {
+        record("destroy_query_pool");
+    }
+
+    unsafe fn get_query_pool_results(
+        &self,
+        _: &(),
+        _: Range<query::Id>,
+        data: &mut [u8],
+        _: buffer::Offset,
+        _: query::ResultFlags,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        record("get_query_pool_results");
+        for byte in data {
+            *byte = 0;
+        }
+        Ok(true)
+    }
+
+    unsafe fn map_memory(
+        &self,
+        memory: &Memory,
+        segment: memory::Segment,
+    ) -> Result<*mut u8, device::MapError> {
+        record("map_memory");
+        match memory.data {
+            Some(ref data) if segment.offset <= data.len() as u64 => {
+                Ok(data.as_ptr().add(segment.offset as usize) as *mut u8)
+            }
+            Some(_) => Err(device::MapError::OutOfBounds),
+            None => Err(device::MapError::Access),
+        }
+    }
+
+    unsafe fn unmap_memory(&self, _: &Memory) {
+        record("unmap_memory");
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a Memory, memory::Segment)>,
+    {
+        for _ in ranges {
+            record("flush_mapped_memory_ranges");
+        }
+        Ok(())
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I>(
+        &self,
+        ranges: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a Memory, memory::Segment)>,
+    {
+        for _ in ranges {
+            record("invalidate_mapped_memory_ranges");
+        }
+        Ok(())
+    }
+
+    unsafe fn free_memory(&self, _: Memory) {
+        record("free_memory");
+    }
+
+    unsafe fn destroy_shader_module(&self, _: ()) {
+        record("destroy_shader_module");
+    }
+
+    unsafe fn destroy_render_pass(&self, _: ()) {
+        record("destroy_render_pass");
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, _: ()) {
+        record("destroy_pipeline_layout");
+    }
+    unsafe fn destroy_graphics_pipeline(&self, _: ()) {
+        record("destroy_graphics_pipeline");
+    }
+    unsafe fn destroy_compute_pipeline(&self, _: ()) {
+        record("destroy_compute_pipeline");
+    }
+    unsafe fn destroy_framebuffer(&self, _: ()) {
+        record("destroy_framebuffer");
+    }
+
+    unsafe fn destroy_buffer(&self, _: Buffer) {
+        record("destroy_buffer");
+    }
+    unsafe fn destroy_buffer_view(&self, _: ()) {
+        record("destroy_buffer_view");
+    }
+    unsafe fn destroy_image(&self, _: Image) {
+        record("destroy_image");
+    }
+    unsafe fn destroy_image_view(&self, _: ()) {
+        record("destroy_image_view");
+    }
+    unsafe fn destroy_sampler(&self, _: ()) {
+        record("destroy_sampler");
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, _: DescriptorPool) {
+        record("destroy_descriptor_pool");
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, _: ()) {
+        record("destroy_descriptor_set_layout");
+    }
+
+    unsafe fn destroy_fence(&self, _: Fence) {
+        record("destroy_fence");
+    }
+
+    unsafe fn destroy_semaphore(&self, _: ()) {
+        record("destroy_semaphore");
+    }
+
+    unsafe fn destroy_event(&self, _: Event) {
+        record("destroy_event");
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut Surface,
+        _: window::SwapchainConfig,
+        _: Option<Swapchain>,
+    ) -> Result<(Swapchain, Vec<Image>), hal::window::CreationError> {
+        match *surface {}
+    }
+
+    unsafe fn destroy_swapchain(&self, swapchain: Swapchain) {
+        match swapchain {}
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        record("wait_idle");
+        Ok(())
+    }
+
+    unsafe fn set_image_name(&self, _: &mut Image, _: &str) {
+        record("set_image_name");
+    }
+
+    unsafe fn set_buffer_name(&self, _: &mut Buffer, _: &str) {
+        record("set_buffer_name");
+    }
+
+    unsafe fn set_command_buffer_name(&self, _: &mut CommandBuffer, _: &str) {
+        record("set_command_buffer_name");
+    }
+
+    unsafe fn set_semaphore_name(&self, _: &mut (), _: &str) {
+        record("set_semaphore_name");
+    }
+
+    unsafe fn set_fence_name(&self, _: &mut Fence, _: &str) {
+        record("set_fence_name");
+    }
+
+    unsafe fn set_framebuffer_name(&self, _: &mut (), _: &str) {
+        record("set_framebuffer_name");
+    }
+
+    unsafe fn set_render_pass_name(&self, _: &mut (), _: &str) {
+        record("set_render_pass_name");
+    }
+
+    unsafe fn set_descriptor_set_name(&self, _: &mut (), _: &str) {
+        record("set_descriptor_set_name");
+    }
+
+    unsafe fn set_descriptor_set_layout_name(&self, _: &mut (), _: &str) {
+        record("set_descriptor_set_layout_name");
+    }
+}
+
+/// Mock queue family, supporting all operations.
+#[derive(Debug)]
+pub struct QueueFamily;
+impl queue::QueueFamily for QueueFamily {
+    fn queue_type(&self) -> queue::QueueType {
+        queue::QueueType::General
+    }
+    fn max_queues(&self) -> usize {
+        1
+    }
+    fn id(&self) -> queue::QueueFamilyId {
+        queue::QueueFamilyId(0)
+    }
+}
+
+/// Mock command pool.
+#[derive(Debug)]
+pub struct CommandPool;
+impl pool::CommandPool<Backend> for CommandPool {
+    unsafe fn reset(&mut self, _: bool) {
+        record("reset_command_pool");
+    }
+
+    unsafe fn allocate_one(&mut self, _: command::Level) -> CommandBuffer {
+        record("allocate_command_buffer");
+        CommandBuffer
+    }
+
+    unsafe fn free<I>(&mut self, _: I)
+    where
+        I: IntoIterator<Item = CommandBuffer>,
+    {
+        record("free_command_buffers");
+    }
+}
+
+/// Mock command buffer, recording the commands without executing them.
+#[derive(Debug)]
+pub struct CommandBuffer;
+impl command::CommandBuffer<Backend> for CommandBuffer {
+    unsafe fn begin(
+        &mut self,
+        _: command::CommandBufferFlags,
+        _: command::CommandBufferInheritanceInfo<Backend>,
+    ) {
+        record("begin");
+    }
+
+    unsafe fn finish(&mut self) {
+        record("finish");
+    }
+
+    unsafe fn reset(&mut self, _: bool) {
+        record("reset");
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        _: Range<pso::PipelineStage>,
+        _: memory::Dependencies,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        record("pipeline_barrier");
+    }
+
+    unsafe fn fill_buffer(&mut self, _: &Buffer, _: buffer::SubRange, _: u32) {
+        record("fill_buffer");
+    }
+
+    unsafe fn update_buffer(&mut self, _: &Buffer, _: buffer::Offset, _: &[u8]) {
+        record("update_buffer");
+    }
+
+    unsafe fn clear_image<T>(&mut self, _: &Image, _: image::Layout, _: command::ClearValue, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        record("clear_image");
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, _: T, _: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        record("clear_attachments");
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        _: &Image,
+        _: image::Layout,
+        _: &Image,
+        _: image::Layout,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageResolve>,
+    {
+        record("resolve_image");
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        _: &Image,
+        _: image::Layout,
+        _: &Image,
+        _: image::Layout,
+        _: image::Filter,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageBlit>,
+    {
+        record("blit_image");
+    }
+
+    unsafe fn bind_index_buffer(&mut self, _: buffer::IndexBufferView<Backend>) {
+        record("bind_index_buffer");
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, _: u32, _: I)
+    where
+        I: IntoIterator<Item = (T, buffer::SubRange)>,
+        T: Borrow<Buffer>,
+    {
+        record("bind_vertex_buffers");
+    }
+
+    unsafe fn set_viewports<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        record("set_viewports");
+    }
+
+    unsafe fn set_scissors<T>(&mut self, _: u32, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        record("set_scissors");
+    }
+
+    unsafe fn set_stencil_reference(&mut self, _: pso::Face, _: pso::StencilValue) {
+        record("set_stencil_reference");
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        record("set_stencil_read_mask");
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, _: pso::Face, _: pso::StencilValue) {
+        record("set_stencil_write_mask");
+    }
+
+    unsafe fn set_blend_constants(&mut self, _: pso::ColorValue) {
+        record("set_blend_constants");
+    }
+
+    unsafe fn set_depth_bounds(&mut self, _: Range<f32>) {
+        record("set_depth_bounds");
+    }
+
+    unsafe fn set_line_width(&mut self, _: f32) {
+        record("set_line_width");
+    }
+
+    unsafe fn set_depth_bias(&mut self, _: pso::DepthBias) {
+        record("set_depth_bias");
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        _: &(),
+        _: &(),
+        _: pso::Rect,
+        _: T,
+        _: command::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ClearValue>,
+    {
+        record("begin_render_pass");
+    }
+
+    unsafe fn next_subpass(&mut self, _: command::SubpassContents) {
+        record("next_subpass");
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        record("end_render_pass");
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, _: &()) {
+        record("bind_graphics_pipeline");
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(&mut self, _: &(), _: usize, _: I, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+        J: IntoIterator,
+        J::Item: Borrow<command::DescriptorSetOffset>,
+    {
+        record("bind_graphics_descriptor_sets");
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, _: &()) {
+        record("bind_compute_pipeline");
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(&mut self, _: &(), _: usize, _: I, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<()>,
+        J: IntoIterator,
+        J::Item: Borrow<command::DescriptorSetOffset>,
+    {
+        record("bind_compute_descriptor_sets");
+    }
+
+    unsafe fn dispatch(&mut self, _: hal::WorkGroupCount) {
+        record("dispatch");
+    }
+
+    unsafe fn dispatch_indirect(&mut self, _: &Buffer, _: buffer::Offset) {
+        record("dispatch_indirect");
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, _: &Buffer, _: &Buffer, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferCopy>,
+    {
+        record("copy_buffer");
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        _: &Image,
+        _: image::Layout,
+        _: &Image,
+        _: image::Layout,
+        _: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<command::ImageCopy>,
+    {
+        record("copy_image");
+    }
+
+    unsafe fn copy_buffer_to_image<T>(&mut self, _: &Buffer, _: &Image, _: image::Layout, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferImageCopy>,
+    {
+        record("copy_buffer_to_image");
+    }
+
+    unsafe fn copy_image_to_buffer<T>(&mut self, _: &Image, _: image::Layout, _: &Buffer, _: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<command::BufferImageCopy>,
+    {
+        record("copy_image_to_buffer");
+    }
+
+    unsafe fn draw(&mut self, _: Range<hal::VertexCount>, _: Range<hal::InstanceCount>) {
+        record("draw");
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        _: Range<hal::IndexCount>,
+        _: hal::VertexOffset,
+        _: Range<hal::InstanceCount>,
+    ) {
+        record("draw_indexed");
+    }
+
+    unsafe fn draw_indirect(&mut self, _: &Buffer, _: buffer::Offset, _: hal::DrawCount, _: u32) {
+        record("draw_indirect");
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        _: &Buffer,
+        _: buffer::Offset,
+        _: hal::DrawCount,
+        _: u32,
+    ) {
+        record("draw_indexed_indirect");
+    }
+
+    unsafe fn set_event(&mut self, _: &Event, _: pso::PipelineStage) {
+        record("cmd_set_event");
+    }
+
+    unsafe fn reset_event(&mut self, _: &Event, _: pso::PipelineStage) {
+        record("cmd_reset_event");
+    }
+
+    unsafe fn wait_events<'a, I, J>(&mut self, _: I, _: Range<pso::PipelineStage>, _: J)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<Event>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend>>,
+    {
+        record("wait_events");
+    }
+
+    unsafe fn begin_query(&mut self, _: query::Query<Backend>, _: query::ControlFlags) {
+        record("begin_query");
+    }
+
+    unsafe fn end_query(&mut self, _: query::Query<Backend>) {
+        record("end_query");
+    }
+
+    unsafe fn reset_query_pool(&mut self, _: &(), _: Range<query::Id>) {
+        record("reset_query_pool");
+    }
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        _: &(),
+        _: Range<query::Id>,
+        _: &Buffer,
+        _: buffer::Offset,
+        _: buffer::Offset,
+        _: query::ResultFlags,
+    ) {
+        record("copy_query_pool_results");
+    }
+
+    unsafe fn write_timestamp(&mut self, _: pso::PipelineStage, _: query::Query<Backend>) {
+        record("write_timestamp");
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        _: &(),
+        _: pso::ShaderStageFlags,
+        _: u32,
+        _: &[u32],
+    ) {
+        record("push_graphics_constants");
+    }
+
+    unsafe fn push_compute_constants(&mut self, _: &(), _: u32, _: &[u32]) {
+        record("push_compute_constants");
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, _: I)
+    where
+        T: 'a + Borrow<CommandBuffer>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        record("execute_commands");
+    }
+
+    unsafe fn insert_debug_marker(&mut self, _: &str, _: u32) {
+        record("insert_debug_marker");
+    }
+    unsafe fn begin_debug_marker(&mut self, _: &str, _: u32) {
+        record("begin_debug_marker");
+    }
+    unsafe fn end_debug_marker(&mut self) {
+        record("end_debug_marker");
+    }
+}
+
+/// Mock descriptor pool, without any limit on the sets allocated.
+#[derive(Debug)]
+pub struct DescriptorPool;
+impl pso::DescriptorPool<Backend> for DescriptorPool {
+    unsafe fn allocate_set(&mut self, _: &()) -> Result<(), pso::AllocationError> {
+        record("allocate_set");
+        Ok(())
+    }
+
+    unsafe fn free<I>(&mut self, _descriptor_sets: I)
+    where
+        I: IntoIterator<Item = ()>,
+    {
+        record("free_sets");
+    }
+
+    unsafe fn reset(&mut self) {
+        record("reset_descriptor_pool");
+    }
+}
+
+/// Mock surface, which can't be created.
+#[derive(Debug)]
+pub enum Surface {}
+impl window::Surface<Backend> for Surface {
+    fn supports_queue_family(&self, _: &QueueFamily) -> bool {
+        match *self {}
+    }
+
+    fn capabilities(&self, _: &PhysicalDevice) -> window::SurfaceCapabilities {
+        match *self {}
+    }
+
+    fn supported_formats(&self, _: &PhysicalDevice) -> Option<Vec<format::Format>> {
+        match *self {}
+    }
+}
+impl window::PresentationSurface<Backend> for Surface {
+    type SwapchainImage = ();
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        _: &Device,
+        _: window::SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        match *self {}
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, _: &Device) {
+        match *self {}
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+    ) -> Result<((), Option<window::Suboptimal>), window::AcquireError> {
+        match *self {}
+    }
+}
+
+/// Mock swapchain, which can't be created.
+#[derive(Debug)]
+pub enum Swapchain {}
+impl window::Swapchain<Backend> for Swapchain {
+    unsafe fn acquire_image(
+        &mut self,
+        _: u64,
+        _: Option<&()>,
+        _: Option<&Fence>,
+    ) -> Result<(window::SwapImageIndex, Option<window::Suboptimal>), window::AcquireError> {
+        match *self {}
+    }
+}
+
+/// Mock instance, exposing a single adapter.
+#[derive(Debug)]
+pub struct Instance;
+
+impl hal::Instance<Backend> for Instance {
+    fn create(_name: &str, _version: u32) -> Result<Self, hal::UnsupportedBackend> {
+        Ok(Instance)
+    }
+
+    fn enumerate_adapters(&self) -> Vec<adapter::Adapter<Backend>> {
+        vec![adapter::Adapter {
+            info: adapter::AdapterInfo {
+                name: "Mock".to_string(),
+                vendor: 0,
+                device: 0,
+                device_type: adapter::DeviceType::Other,
+            },
+            physical_device: PhysicalDevice,
+            queue_families: vec![QueueFamily],
+        }]
+    }
+
+    unsafe fn create_surface(
+        &self,
+        _: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Surface, hal::window::InitError> {
+        Err(hal::window::InitError::UnsupportedWindowHandle)
+    }
+
+    unsafe fn destroy_surface(&self, surface: Surface) {
+        match surface {}
+    }
+}
@@ -0,0 +1,662 @@
+//! Serializable description of the calls made through the trace backend.
+//!
+//! Objects are referred to by the `Id` assigned when they got created, and
+//! bulk data like memory contents and SPIR-V is stored in separate files next
+//! to the trace, referred to by their `DataFile` name.
+
+use std::ops::Range;
+
+use hal::{
+    buffer,
+    command as com,
+    device,
+    format,
+    image,
+    memory,
+    pass,
+    pool,
+    pso,
+    query,
+    queue,
+    window,
+};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Identifier of an object created through the trace backend.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize, Deserialize)]
+pub struct Id(pub u64);
+
+/// Name of a file next to the trace, holding binary data.
+pub type DataFile = String;
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct QueueFamily {
+    pub id: queue::QueueFamilyId,
+    pub ty: queue::QueueType,
+    /// Queues opened from the family, along with their priorities.
+    pub queues: Vec<(Id, queue::QueuePriority)>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SubpassDesc {
+    pub colors: Vec<pass::AttachmentRef>,
+    pub depth_stencil: Option<pass::AttachmentRef>,
+    pub inputs: Vec<pass::AttachmentRef>,
+    pub resolves: Vec<pass::AttachmentRef>,
+    pub preserves: Vec<pass::AttachmentId>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Subpass {
+    pub index: pass::SubpassId,
+    pub main_pass: Id,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Specialization {
+    pub constants: Vec<pso::SpecializationConstant>,
+    pub data: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct EntryPoint {
+    pub entry: String,
+    pub module: Id,
+    pub specialization: Specialization,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GraphicsShaderSet {
+    pub vertex: EntryPoint,
+    pub hull: Option<EntryPoint>,
+    pub domain: Option<EntryPoint>,
+    pub geometry: Option<EntryPoint>,
+    pub fragment: Option<EntryPoint>,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum BasePipeline {
+    Pipeline(Id),
+    Index(usize),
+    None,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GraphicsPipelineDesc {
+    pub shaders: GraphicsShaderSet,
+    pub rasterizer: pso::Rasterizer,
+    pub vertex_buffers: Vec<pso::VertexBufferDesc>,
+    pub attributes: Vec<pso::AttributeDesc>,
+    pub input_assembler: pso::InputAssemblerDesc,
+    pub blender: pso::BlendDesc,
+    pub depth_stencil: pso::DepthStencilDesc,
+    pub multisampling: Option<pso::Multisampling>,
+    pub baked_states: pso::BakedStates,
+    pub layout: Id,
+    pub subpass: Subpass,
+    pub flags: pso::PipelineCreationFlags,
+    pub parent: BasePipeline,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ComputePipelineDesc {
+    pub shader: EntryPoint,
+    pub layout: Id,
+    pub flags: pso::PipelineCreationFlags,
+    pub parent: BasePipeline,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Descriptor {
+    Sampler(Id),
+    Image(Id, image::Layout),
+    CombinedImageSampler(Id, image::Layout, Id),
+    Buffer(Id, buffer::SubRange),
+    TexelBuffer(Id),
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DescriptorSetWrite {
+    pub set: Id,
+    pub binding: pso::DescriptorBinding,
+    pub array_offset: pso::DescriptorArrayIndex,
+    pub descriptors: Vec<Descriptor>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DescriptorSetCopy {
+    pub src_set: Id,
+    pub src_binding: pso::DescriptorBinding,
+    pub src_array_offset: pso::DescriptorArrayIndex,
+    pub dst_set: Id,
+    pub dst_binding: pso::DescriptorBinding,
+    pub dst_array_offset: pso::DescriptorArrayIndex,
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Barrier {
+    AllBuffers(Range<buffer::Access>),
+    AllImages(Range<image::Access>),
+    Buffer {
+        states: Range<buffer::State>,
+        target: Id,
+        range: buffer::SubRange,
+        families: Option<Range<queue::QueueFamilyId>>,
+    },
+    Image {
+        states: Range<image::State>,
+        target: Id,
+        range: image::SubresourceRange,
+        families: Option<Range<queue::QueueFamilyId>>,
+    },
+}
+
+/// Bits of a `ClearValue`, which is a union of the color and depth-stencil values.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ClearValue(pub [u32; 4]);
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum AttachmentClear {
+    Color {
+        index: usize,
+        /// Bits of the `ClearColor` union.
+        value: [u32; 4],
+    },
+    DepthStencil {
+        depth: Option<pso::DepthValue>,
+        stencil: Option<pso::StencilValue>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InheritanceInfo {
+    pub subpass: Option<Subpass>,
+    pub framebuffer: Option<Id>,
+    pub occlusion_query_enable: bool,
+    pub occlusion_query_flags: query::ControlFlags,
+    pub pipeline_statistics: query::PipelineStatistic,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Query {
+    pub pool: Id,
+    pub id: query::Id,
+}
+
+/// Call recorded into a command buffer.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Command {
+    Begin {
+        flags: com::CommandBufferFlags,
+        inheritance: InheritanceInfo,
+    },
+    Finish,
+    Reset {
+        release_resources: bool,
+    },
+    PipelineBarrier {
+        stages: Range<pso::PipelineStage>,
+        dependencies: memory::Dependencies,
+        barriers: Vec<Barrier>,
+    },
+    FillBuffer {
+        buffer: Id,
+        range: buffer::SubRange,
+        data: u32,
+    },
+    UpdateBuffer {
+        buffer: Id,
+        offset: buffer::Offset,
+        data: DataFile,
+    },
+    ClearImage {
+        image: Id,
+        layout: image::Layout,
+        value: ClearValue,
+        ranges: Vec<image::SubresourceRange>,
+    },
+    ClearAttachments {
+        clears: Vec<AttachmentClear>,
+        rects: Vec<pso::ClearRect>,
+    },
+    ResolveImage {
+        src: Id,
+        src_layout: image::Layout,
+        dst: Id,
+        dst_layout: image::Layout,
+        regions: Vec<com::ImageResolve>,
+    },
+    BlitImage {
+        src: Id,
+        src_layout: image::Layout,
+        dst: Id,
+        dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: Vec<com::ImageBlit>,
+    },
+    BindIndexBuffer {
+        buffer: Id,
+        range: buffer::SubRange,
+        index_type: hal::IndexType,
+    },
+    BindVertexBuffers {
+        first_binding: pso::BufferIndex,
+        buffers: Vec<(Id, buffer::SubRange)>,
+    },
+    SetViewports {
+        first_viewport: u32,
+        viewports: Vec<pso::Viewport>,
+    },
+    SetScissors {
+        first_scissor: u32,
+        rects: Vec<pso::Rect>,
+    },
+    SetStencilReference {
+        faces: pso::Face,
+        value: pso::StencilValue,
+    },
+    SetStencilReadMask {
+        faces: pso::Face,
+        value: pso::StencilValue,
+    },
+    SetStencilWriteMask {
+        faces: pso::Face,
+        value: pso::StencilValue,
+    },
+    SetBlendConstants(pso::ColorValue),
+    SetDepthBounds(Range<f32>),
+    SetLineWidth(f32),
+    SetDepthBias(pso::DepthBias),
+    BeginRenderPass {
+        render_pass: Id,
+        framebuffer: Id,
+        render_area: pso::Rect,
+        clear_values: Vec<ClearValue>,
+        first_subpass: com::SubpassContents,
+    },
+    NextSubpass(com::SubpassContents),
+    EndRenderPass,
+    BindGraphicsPipeline(Id),
+    BindGraphicsDescriptorSets {
+        layout: Id,
+        first_set: usize,
+        sets: Vec<Id>,
+        offsets: Vec<com::DescriptorSetOffset>,
+    },
+    BindComputePipeline(Id),
+    BindComputeDescriptorSets {
+        layout: Id,
+        first_set: usize,
+        sets: Vec<Id>,
+        offsets: Vec<com::DescriptorSetOffset>,
+    },
+    Dispatch(hal::WorkGroupCount),
+    DispatchIndirect {
+        buffer: Id,
+        offset: buffer::Offset,
+    },
+    CopyBuffer {
+        src: Id,
+        dst: Id,
+        regions: Vec<com::BufferCopy>,
+    },
+    CopyImage {
+        src: Id,
+        src_layout: image::Layout,
+        dst: Id,
+        dst_layout: image::Layout,
+        regions: Vec<com::ImageCopy>,
+    },
+    CopyBufferToImage {
+        src: Id,
+        dst: Id,
+        dst_layout: image::Layout,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    CopyImageToBuffer {
+        src: Id,
+        src_layout: image::Layout,
+        dst: Id,
+        regions: Vec<com::BufferImageCopy>,
+    },
+    Draw {
+        vertices: Range<hal::VertexCount>,
+        instances: Range<hal::InstanceCount>,
+    },
+    DrawIndexed {
+        indices: Range<hal::IndexCount>,
+        base_vertex: hal::VertexOffset,
+        instances: Range<hal::InstanceCount>,
+    },
+    DrawIndirect {
+        buffer: Id,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: Id,
+        offset: buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    SetEvent {
+        event: Id,
+        stages: pso::PipelineStage,
+    },
+    ResetEvent {
+        event: Id,
+        stages: pso::PipelineStage,
+    },
+    WaitEvents {
+        events: Vec<Id>,
+        stages: Range<pso::PipelineStage>,
+        barriers: Vec<Barrier>,
+    },
+    BeginQuery {
+        query: Query,
+        flags: query::ControlFlags,
+    },
+    EndQuery(Query),
+    ResetQueryPool {
+        pool: Id,
+        queries: Range<query::Id>,
+    },
+    CopyQueryPoolResults {
+        pool: Id,
+        queries: Range<query::Id>,
+        buffer: Id,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    },
+    WriteTimestamp {
+        stage: pso::PipelineStage,
+        query: Query,
+    },
+    PushGraphicsConstants {
+        layout: Id,
+        stages: pso::ShaderStageFlags,
+        offset: u32,
+        constants: Vec<u32>,
+    },
+    PushComputeConstants {
+        layout: Id,
+        offset: u32,
+        constants: Vec<u32>,
+    },
+    ExecuteCommands(Vec<Id>),
+    InsertDebugMarker {
+        name: String,
+        color: u32,
+    },
+    BeginDebugMarker {
+        name: String,
+        color: u32,
+    },
+    EndDebugMarker,
+}
+
+/// Call recorded into a trace.
+///
+/// Calls which don't change any state, like querying requirements or
+/// fence statuses, are forwarded without being recorded.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum Action {
+    /// Opening of the device, which starts every trace.
+    Open {
+        families: Vec<QueueFamily>,
+        #[serde(with = "features")]
+        features: hal::Features,
+    },
+    AllocateMemory {
+        id: Id,
+        memory_type: hal::MemoryTypeId,
+        /// Properties of the memory type, used to pick one when replaying
+        /// on an adapter with different memory types.
+        properties: memory::Properties,
+        size: u64,
+    },
+    FreeMemory(Id),
+    /// Contents written by the host to mapped memory, captured when the
+    /// range is flushed or unmapped, and before every submission.
+    WriteMemory {
+        memory: Id,
+        offset: u64,
+        data: DataFile,
+    },
+    CreateCommandPool {
+        id: Id,
+        family: queue::QueueFamilyId,
+        flags: pool::CommandPoolCreateFlags,
+    },
+    ResetCommandPool {
+        pool: Id,
+        release_resources: bool,
+    },
+    DestroyCommandPool(Id),
+    AllocateCommandBuffer {
+        id: Id,
+        pool: Id,
+        level: com::Level,
+    },
+    FreeCommandBuffers {
+        pool: Id,
+        buffers: Vec<Id>,
+    },
+    Command(Id, Command),
+    CreateRenderPass {
+        id: Id,
+        attachments: Vec<pass::Attachment>,
+        subpasses: Vec<SubpassDesc>,
+        dependencies: Vec<pass::SubpassDependency>,
+    },
+    DestroyRenderPass(Id),
+    CreatePipelineLayout {
+        id: Id,
+        set_layouts: Vec<Id>,
+        push_constants: Vec<(pso::ShaderStageFlags, Range<u32>)>,
+    },
+    DestroyPipelineLayout(Id),
+    /// Creation of a pipeline cache. The initial data is specific to the
+    /// captured backend, so it isn't recorded.
+    CreatePipelineCache(Id),
+    MergePipelineCaches {
+        target: Id,
+        sources: Vec<Id>,
+    },
+    DestroyPipelineCache(Id),
+    CreateGraphicsPipeline {
+        id: Id,
+        desc: Box<GraphicsPipelineDesc>,
+        cache: Option<Id>,
+    },
+    DestroyGraphicsPipeline(Id),
+    CreateComputePipeline {
+        id: Id,
+        desc: ComputePipelineDesc,
+        cache: Option<Id>,
+    },
+    DestroyComputePipeline(Id),
+    CreateFramebuffer {
+        id: Id,
+        pass: Id,
+        attachments: Vec<Id>,
+        extent: image::Extent,
+    },
+    DestroyFramebuffer(Id),
+    /// Creation of a shader module from the SPIR-V stored in `data`.
+    CreateShaderModule {
+        id: Id,
+        data: DataFile,
+    },
+    DestroyShaderModule(Id),
+    CreateBuffer {
+        id: Id,
+        size: u64,
+        usage: buffer::Usage,
+    },
+    BindBufferMemory {
+        buffer: Id,
+        memory: Id,
+        offset: u64,
+    },
+    DestroyBuffer(Id),
+    CreateBufferView {
+        id: Id,
+        buffer: Id,
+        format: Option<format::Format>,
+        range: buffer::SubRange,
+    },
+    DestroyBufferView(Id),
+    CreateImage {
+        id: Id,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    },
+    BindImageMemory {
+        image: Id,
+        memory: Id,
+        offset: u64,
+    },
+    DestroyImage(Id),
+    CreateImageView {
+        id: Id,
+        image: Id,
+        kind: image::ViewKind,
+        format: format::Format,
+        swizzle: format::Swizzle,
+        range: image::SubresourceRange,
+    },
+    DestroyImageView(Id),
+    CreateSampler {
+        id: Id,
+        desc: image::SamplerDesc,
+    },
+    DestroySampler(Id),
+    CreateDescriptorPool {
+        id: Id,
+        max_sets: usize,
+        ranges: Vec<pso::DescriptorRangeDesc>,
+        flags: pso::DescriptorPoolCreateFlags,
+    },
+    DestroyDescriptorPool(Id),
+    AllocateDescriptorSet {
+        id: Id,
+        pool: Id,
+        layout: Id,
+    },
+    FreeDescriptorSets {
+        pool: Id,
+        sets: Vec<Id>,
+    },
+    ResetDescriptorPool(Id),
+    CreateDescriptorSetLayout {
+        id: Id,
+        bindings: Vec<pso::DescriptorSetLayoutBinding>,
+        immutable_samplers: Vec<Id>,
+    },
+    DestroyDescriptorSetLayout(Id),
+    WriteDescriptorSets(Vec<DescriptorSetWrite>),
+    CopyDescriptorSets(Vec<DescriptorSetCopy>),
+    CreateSemaphore(Id),
+    DestroySemaphore(Id),
+    CreateFence {
+        id: Id,
+        signaled: bool,
+    },
+    ResetFences(Vec<Id>),
+    /// Wait for fences which completed within the timeout when captured.
+    WaitForFences {
+        fences: Vec<Id>,
+        wait: device::WaitFor,
+    },
+    DestroyFence(Id),
+    CreateEvent(Id),
+    SetEvent(Id),
+    ResetEvent(Id),
+    DestroyEvent(Id),
+    CreateQueryPool {
+        id: Id,
+        ty: query::Type,
+        count: query::Id,
+    },
+    DestroyQueryPool(Id),
+    /// Creation of a swapchain, which is replayed with offscreen images.
+    CreateSwapchain {
+        id: Id,
+        format: format::Format,
+        extent: window::Extent2D,
+        usage: image::Usage,
+        images: Vec<Id>,
+    },
+    DestroySwapchain(Id),
+    AcquireImage {
+        swapchain: Id,
+        semaphore: Option<Id>,
+        fence: Option<Id>,
+    },
+    /// Configuration of a presentation surface, which is replayed with an
+    /// offscreen image.
+    ConfigureSurface {
+        surface: Id,
+        format: format::Format,
+        extent: window::Extent2D,
+        usage: image::Usage,
+    },
+    UnconfigureSurface(Id),
+    /// Acquisition of the image of a surface, through the view `view`.
+    AcquireSurfaceImage {
+        surface: Id,
+        view: Id,
+    },
+    SetName {
+        object: Id,
+        name: String,
+    },
+    Submit {
+        queue: Id,
+        command_buffers: Vec<Id>,
+        wait_semaphores: Vec<(Id, pso::PipelineStage)>,
+        signal_semaphores: Vec<Id>,
+        fence: Option<Id>,
+    },
+    Present {
+        queue: Id,
+        wait_semaphores: Vec<Id>,
+    },
+    PresentSurface {
+        queue: Id,
+        view: Id,
+        wait_semaphore: Option<Id>,
+    },
+    QueueWaitIdle(Id),
+    WaitIdle,
+}
+
+/// Serialization of `hal::Features` as two halves, as RON lacks 128-bit integers.
+mod features {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        features: &hal::Features,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let bits = features.bits();
+        ((bits >> 64) as u64, bits as u64).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<hal::Features, D::Error> {
+        let (high, low) = <(u64, u64)>::deserialize(deserializer)?;
+        Ok(hal::Features::from_bits_truncate(
+            (high as u128) << 64 | low as u128,
+        ))
+    }
+}
@@ -0,0 +1,758 @@
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::Arc;
+
+use hal::{
+    buffer,
+    command as com,
+    image,
+    memory,
+    pso,
+    query,
+    DrawCount,
+    IndexCount,
+    InstanceCount,
+    VertexCount,
+    VertexOffset,
+    WorkGroupCount,
+};
+
+use crate::{
+    action::{self as a, Action, Command, Id},
+    native::{self as n, collect},
+    recorder::Recorder,
+    Backend,
+};
+
+#[derive(Debug)]
+pub struct CommandBuffer<B: hal::Backend> {
+    pub(crate) raw: B::CommandBuffer,
+    pub(crate) id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> CommandBuffer<B> {
+    pub(crate) fn new(raw: B::CommandBuffer, id: Id, recorder: Arc<Recorder>) -> Self {
+        CommandBuffer { raw, id, recorder }
+    }
+
+    fn record(&self, command: Command) {
+        self.recorder.record(Action::Command(self.id, command));
+    }
+}
+
+fn raw_query<'a, B: hal::Backend>(query: &query::Query<'a, Backend<B>>) -> query::Query<'a, B> {
+    query::Query {
+        pool: &query.pool.raw,
+        id: query.id,
+    }
+}
+
+fn collect_cloned<T, I>(iter: I) -> Vec<T>
+where
+    T: Clone,
+    I: IntoIterator,
+    I::Item: Borrow<T>,
+{
+    iter.into_iter().map(|item| item.borrow().clone()).collect()
+}
+
+impl<B: hal::Backend> com::CommandBuffer<Backend<B>> for CommandBuffer<B> {
+    unsafe fn begin(
+        &mut self,
+        flags: com::CommandBufferFlags,
+        inheritance_info: com::CommandBufferInheritanceInfo<Backend<B>>,
+    ) {
+        self.record(Command::Begin {
+            flags,
+            inheritance: a::InheritanceInfo {
+                subpass: inheritance_info.subpass.as_ref().map(n::trace_subpass),
+                framebuffer: inheritance_info
+                    .framebuffer
+                    .map(|framebuffer| framebuffer.id),
+                occlusion_query_enable: inheritance_info.occlusion_query_enable,
+                occlusion_query_flags: inheritance_info.occlusion_query_flags,
+                pipeline_statistics: inheritance_info.pipeline_statistics,
+            },
+        });
+        let raw_info = com::CommandBufferInheritanceInfo {
+            subpass: inheritance_info.subpass.as_ref().map(n::raw_subpass),
+            framebuffer: inheritance_info
+                .framebuffer
+                .map(|framebuffer| &framebuffer.raw),
+            occlusion_query_enable: inheritance_info.occlusion_query_enable,
+            occlusion_query_flags: inheritance_info.occlusion_query_flags,
+            pipeline_statistics: inheritance_info.pipeline_statistics,
+        };
+        self.raw.begin(flags, raw_info)
+    }
+
+    unsafe fn finish(&mut self) {
+        self.record(Command::Finish);
+        self.raw.finish()
+    }
+
+    unsafe fn reset(&mut self, release_resources: bool) {
+        self.record(Command::Reset { release_resources });
+        self.raw.reset(release_resources)
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        stages: Range<pso::PipelineStage>,
+        dependencies: memory::Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend<B>>>,
+    {
+        let barriers = collect(barriers);
+        self.record(Command::PipelineBarrier {
+            stages: stages.clone(),
+            dependencies,
+            barriers: barriers
+                .iter()
+                .map(|barrier| n::trace_barrier(barrier.borrow()))
+                .collect(),
+        });
+        self.raw.pipeline_barrier(
+            stages,
+            dependencies,
+            barriers
+                .iter()
+                .map(|barrier| n::raw_barrier(barrier.borrow())),
+        )
+    }
+
+    unsafe fn fill_buffer(&mut self, buffer: &n::Buffer<B>, range: buffer::SubRange, data: u32) {
+        self.record(Command::FillBuffer {
+            buffer: buffer.id,
+            range: range.clone(),
+            data,
+        });
+        self.raw.fill_buffer(&buffer.raw, range, data)
+    }
+
+    unsafe fn update_buffer(&mut self, buffer: &n::Buffer<B>, offset: buffer::Offset, data: &[u8]) {
+        self.record(Command::UpdateBuffer {
+            buffer: buffer.id,
+            offset,
+            data: self.recorder.write_data(data),
+        });
+        self.raw.update_buffer(&buffer.raw, offset, data)
+    }
+
+    unsafe fn clear_image<T>(
+        &mut self,
+        image: &n::Image<B>,
+        layout: image::Layout,
+        value: com::ClearValue,
+        subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        let ranges = collect_cloned(subresource_ranges);
+        self.record(Command::ClearImage {
+            image: image.id,
+            layout,
+            value: n::trace_clear_value(&value),
+            ranges: ranges.clone(),
+        });
+        self.raw.clear_image(&image.raw, layout, value, ranges)
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, clears: T, rects: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        let clears = collect_cloned::<com::AttachmentClear, _>(clears);
+        let rects = collect_cloned(rects);
+        self.record(Command::ClearAttachments {
+            clears: clears
+                .iter()
+                .map(|clear| match *clear {
+                    com::AttachmentClear::Color { index, value } => a::AttachmentClear::Color {
+                        index,
+                        value: value.uint32,
+                    },
+                    com::AttachmentClear::DepthStencil { depth, stencil } => {
+                        a::AttachmentClear::DepthStencil { depth, stencil }
+                    }
+                })
+                .collect(),
+            rects: rects.clone(),
+        });
+        self.raw.clear_attachments(clears, rects)
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageResolve>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::ResolveImage {
+            src: src.id,
+            src_layout,
+            dst: dst.id,
+            dst_layout,
+            regions: regions.clone(),
+        });
+        self.raw
+            .resolve_image(&src.raw, src_layout, &dst.raw, dst_layout, regions)
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageBlit>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::BlitImage {
+            src: src.id,
+            src_layout,
+            dst: dst.id,
+            dst_layout,
+            filter,
+            regions: regions.clone(),
+        });
+        self.raw
+            .blit_image(&src.raw, src_layout, &dst.raw, dst_layout, filter, regions)
+    }
+
+    unsafe fn bind_index_buffer(&mut self, view: buffer::IndexBufferView<Backend<B>>) {
+        self.record(Command::BindIndexBuffer {
+            buffer: view.buffer.id,
+            range: view.range.clone(),
+            index_type: view.index_type,
+        });
+        self.raw.bind_index_buffer(buffer::IndexBufferView {
+            buffer: &view.buffer.raw,
+            range: view.range,
+            index_type: view.index_type,
+        })
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, first_binding: pso::BufferIndex, buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::SubRange)>,
+        T: Borrow<n::Buffer<B>>,
+    {
+        let buffers = collect(buffers);
+        self.record(Command::BindVertexBuffers {
+            first_binding,
+            buffers: buffers
+                .iter()
+                .map(|(buffer, range)| (buffer.borrow().id, range.clone()))
+                .collect(),
+        });
+        self.raw.bind_vertex_buffers(
+            first_binding,
+            buffers
+                .iter()
+                .map(|(buffer, range)| (&buffer.borrow().raw, range.clone())),
+        )
+    }
+
+    unsafe fn set_viewports<T>(&mut self, first_viewport: u32, viewports: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        let viewports = collect_cloned(viewports);
+        self.record(Command::SetViewports {
+            first_viewport,
+            viewports: viewports.clone(),
+        });
+        self.raw.set_viewports(first_viewport, viewports)
+    }
+
+    unsafe fn set_scissors<T>(&mut self, first_scissor: u32, rects: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        let rects = collect_cloned(rects);
+        self.record(Command::SetScissors {
+            first_scissor,
+            rects: rects.clone(),
+        });
+        self.raw.set_scissors(first_scissor, rects)
+    }
+
+    unsafe fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.record(Command::SetStencilReference { faces, value });
+        self.raw.set_stencil_reference(faces, value)
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.record(Command::SetStencilReadMask { faces, value });
+        self.raw.set_stencil_read_mask(faces, value)
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        self.record(Command::SetStencilWriteMask { faces, value });
+        self.raw.set_stencil_write_mask(faces, value)
+    }
+
+    unsafe fn set_blend_constants(&mut self, color: pso::ColorValue) {
+        self.record(Command::SetBlendConstants(color));
+        self.raw.set_blend_constants(color)
+    }
+
+    unsafe fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        self.record(Command::SetDepthBounds(bounds.clone()));
+        self.raw.set_depth_bounds(bounds)
+    }
+
+    unsafe fn set_line_width(&mut self, width: f32) {
+        self.record(Command::SetLineWidth(width));
+        self.raw.set_line_width(width)
+    }
+
+    unsafe fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        self.record(Command::SetDepthBias(depth_bias));
+        self.raw.set_depth_bias(depth_bias)
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &n::RenderPass<B>,
+        framebuffer: &n::Framebuffer<B>,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValue>,
+    {
+        let clear_values = collect_cloned::<com::ClearValue, _>(clear_values);
+        self.record(Command::BeginRenderPass {
+            render_pass: render_pass.id,
+            framebuffer: framebuffer.id,
+            render_area,
+            clear_values: clear_values.iter().map(n::trace_clear_value).collect(),
+            first_subpass,
+        });
+        self.raw.begin_render_pass(
+            &render_pass.raw,
+            &framebuffer.raw,
+            render_area,
+            clear_values,
+            first_subpass,
+        )
+    }
+
+    unsafe fn next_subpass(&mut self, contents: com::SubpassContents) {
+        self.record(Command::NextSubpass(contents));
+        self.raw.next_subpass(contents)
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        self.record(Command::EndRenderPass);
+        self.raw.end_render_pass()
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline<B>) {
+        self.record(Command::BindGraphicsPipeline(pipeline.id));
+        self.raw.bind_graphics_pipeline(&pipeline.raw)
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = collect(sets);
+        let offsets = collect_cloned(offsets);
+        self.record(Command::BindGraphicsDescriptorSets {
+            layout: layout.id,
+            first_set,
+            sets: sets.iter().map(|set| set.borrow().id).collect(),
+            offsets: offsets.clone(),
+        });
+        self.raw.bind_graphics_descriptor_sets(
+            &layout.raw,
+            first_set,
+            sets.iter().map(|set| &set.borrow().raw),
+            offsets,
+        )
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline<B>) {
+        self.record(Command::BindComputePipeline(pipeline.id));
+        self.raw.bind_compute_pipeline(&pipeline.raw)
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = collect(sets);
+        let offsets = collect_cloned(offsets);
+        self.record(Command::BindComputeDescriptorSets {
+            layout: layout.id,
+            first_set,
+            sets: sets.iter().map(|set| set.borrow().id).collect(),
+            offsets: offsets.clone(),
+        });
+        self.raw.bind_compute_descriptor_sets(
+            &layout.raw,
+            first_set,
+            sets.iter().map(|set| &set.borrow().raw),
+            offsets,
+        )
+    }
+
+    unsafe fn dispatch(&mut self, count: WorkGroupCount) {
+        self.record(Command::Dispatch(count));
+        self.raw.dispatch(count)
+    }
+
+    unsafe fn dispatch_indirect(&mut self, buffer: &n::Buffer<B>, offset: buffer::Offset) {
+        self.record(Command::DispatchIndirect {
+            buffer: buffer.id,
+            offset,
+        });
+        self.raw.dispatch_indirect(&buffer.raw, offset)
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, src: &n::Buffer<B>, dst: &n::Buffer<B>, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferCopy>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::CopyBuffer {
+            src: src.id,
+            dst: dst.id,
+            regions: regions.clone(),
+        });
+        self.raw.copy_buffer(&src.raw, &dst.raw, regions)
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageCopy>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::CopyImage {
+            src: src.id,
+            src_layout,
+            dst: dst.id,
+            dst_layout,
+            regions: regions.clone(),
+        });
+        self.raw
+            .copy_image(&src.raw, src_layout, &dst.raw, dst_layout, regions)
+    }
+
+    unsafe fn copy_buffer_to_image<T>(
+        &mut self,
+        src: &n::Buffer<B>,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::CopyBufferToImage {
+            src: src.id,
+            dst: dst.id,
+            dst_layout,
+            regions: regions.clone(),
+        });
+        self.raw
+            .copy_buffer_to_image(&src.raw, &dst.raw, dst_layout, regions)
+    }
+
+    unsafe fn copy_image_to_buffer<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Buffer<B>,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        let regions = collect_cloned(regions);
+        self.record(Command::CopyImageToBuffer {
+            src: src.id,
+            src_layout,
+            dst: dst.id,
+            regions: regions.clone(),
+        });
+        self.raw
+            .copy_image_to_buffer(&src.raw, src_layout, &dst.raw, regions)
+    }
+
+    unsafe fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        self.record(Command::Draw {
+            vertices: vertices.clone(),
+            instances: instances.clone(),
+        });
+        self.raw.draw(vertices, instances)
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        indices: Range<IndexCount>,
+        base_vertex: VertexOffset,
+        instances: Range<InstanceCount>,
+    ) {
+        self.record(Command::DrawIndexed {
+            indices: indices.clone(),
+            base_vertex,
+            instances: instances.clone(),
+        });
+        self.raw.draw_indexed(indices, base_vertex, instances)
+    }
+
+    unsafe fn draw_indirect(
+        &mut self,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        self.record(Command::DrawIndirect {
+            buffer: buffer.id,
+            offset,
+            draw_count,
+            stride,
+        });
+        self.raw
+            .draw_indirect(&buffer.raw, offset, draw_count, stride)
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        self.record(Command::DrawIndexedIndirect {
+            buffer: buffer.id,
+            offset,
+            draw_count,
+            stride,
+        });
+        self.raw
+            .draw_indexed_indirect(&buffer.raw, offset, draw_count, stride)
+    }
+
+    unsafe fn set_event(&mut self, event: &n::Event<B>, stages: pso::PipelineStage) {
+        self.record(Command::SetEvent {
+            event: event.id,
+            stages,
+        });
+        self.raw.set_event(&event.raw, stages)
+    }
+
+    unsafe fn reset_event(&mut self, event: &n::Event<B>, stages: pso::PipelineStage) {
+        self.record(Command::ResetEvent {
+            event: event.id,
+            stages,
+        });
+        self.raw.reset_event(&event.raw, stages)
+    }
+
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        events: I,
+        stages: Range<pso::PipelineStage>,
+        barriers: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::Event<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend<B>>>,
+    {
+        let events = collect(events);
+        let barriers = collect(barriers);
+        self.record(Command::WaitEvents {
+            events: events.iter().map(|event| event.borrow().id).collect(),
+            stages: stages.clone(),
+            barriers: barriers
+                .iter()
+                .map(|barrier| n::trace_barrier(barrier.borrow()))
+                .collect(),
+        });
+        self.raw.wait_events(
+            events.iter().map(|event| &event.borrow().raw),
+            stages,
+            barriers
+                .iter()
+                .map(|barrier| n::raw_barrier(barrier.borrow())),
+        )
+    }
+
+    unsafe fn begin_query(&mut self, query: query::Query<Backend<B>>, flags: query::ControlFlags) {
+        self.record(Command::BeginQuery {
+            query: n::trace_query(&query),
+            flags,
+        });
+        self.raw.begin_query(raw_query(&query), flags)
+    }
+
+    unsafe fn end_query(&mut self, query: query::Query<Backend<B>>) {
+        self.record(Command::EndQuery(n::trace_query(&query)));
+        self.raw.end_query(raw_query(&query))
+    }
+
+    unsafe fn reset_query_pool(&mut self, pool: &n::QueryPool<B>, queries: Range<query::Id>) {
+        self.record(Command::ResetQueryPool {
+            pool: pool.id,
+            queries: queries.clone(),
+        });
+        self.raw.reset_query_pool(&pool.raw, queries)
+    }
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        pool: &n::QueryPool<B>,
+        queries: Range<query::Id>,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) {
+        self.record(Command::CopyQueryPoolResults {
+            pool: pool.id,
+            queries: queries.clone(),
+            buffer: buffer.id,
+            offset,
+            stride,
+            flags,
+        });
+        self.raw
+            .copy_query_pool_results(&pool.raw, queries, &buffer.raw, offset, stride, flags)
+    }
+
+    unsafe fn write_timestamp(
+        &mut self,
+        stage: pso::PipelineStage,
+        query: query::Query<Backend<B>>,
+    ) {
+        self.record(Command::WriteTimestamp {
+            stage,
+            query: n::trace_query(&query),
+        });
+        self.raw.write_timestamp(stage, raw_query(&query))
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        stages: pso::ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.record(Command::PushGraphicsConstants {
+            layout: layout.id,
+            stages,
+            offset,
+            constants: constants.to_vec(),
+        });
+        self.raw
+            .push_graphics_constants(&layout.raw, stages, offset, constants)
+    }
+
+    unsafe fn push_compute_constants(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        self.record(Command::PushComputeConstants {
+            layout: layout.id,
+            offset,
+            constants: constants.to_vec(),
+        });
+        self.raw
+            .push_compute_constants(&layout.raw, offset, constants)
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, cmd_buffers: I)
+    where
+        T: 'a + Borrow<CommandBuffer<B>>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let cmd_buffers = collect(cmd_buffers);
+        self.record(Command::ExecuteCommands(
+            cmd_buffers
+                .iter()
+                .map(|&cmd_buffer| cmd_buffer.borrow().id)
+                .collect(),
+        ));
+        self.raw.execute_commands(
+            cmd_buffers
+                .into_iter()
+                .map(|cmd_buffer| &cmd_buffer.borrow().raw),
+        )
+    }
+
+    unsafe fn insert_debug_marker(&mut self, name: &str, color: u32) {
+        self.record(Command::InsertDebugMarker {
+            name: name.to_string(),
+            color,
+        });
+        self.raw.insert_debug_marker(name, color)
+    }
+
+    unsafe fn begin_debug_marker(&mut self, name: &str, color: u32) {
+        self.record(Command::BeginDebugMarker {
+            name: name.to_string(),
+            color,
+        });
+        self.raw.begin_debug_marker(name, color)
+    }
+
+    unsafe fn end_debug_marker(&mut self) {
+        self.record(Command::EndDebugMarker);
+        self.raw.end_debug_marker()
+    }
+}
@@ -0,0 +1,1599 @@
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::Arc;
+
+use hal::{adapter, buffer, device, format, image, memory, pass, pool, pso, query, queue, window};
+
+use crate::{
+    action::{self as a, Action, Id},
+    command::CommandBuffer,
+    native::{self as n, collect},
+    pool::{CommandPool, DescriptorPool},
+    recorder::Recorder,
+    Backend,
+    Surface,
+    Swapchain,
+};
+
+#[derive(Debug)]
+pub struct Device<B: hal::Backend> {
+    raw: B::Device,
+    memory_properties: adapter::MemoryProperties,
+    pub(crate) recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> Device<B> {
+    pub(crate) fn new(
+        raw: B::Device,
+        memory_properties: adapter::MemoryProperties,
+        recorder: Arc<Recorder>,
+    ) -> Self {
+        Device {
+            raw,
+            memory_properties,
+            recorder,
+        }
+    }
+
+    pub fn raw(&self) -> &B::Device {
+        &self.raw
+    }
+
+    fn record_name(&self, object: Id, name: &str) {
+        self.recorder.record(Action::SetName {
+            object,
+            name: name.to_string(),
+        });
+    }
+}
+
+fn raw_descriptor<'a, B: hal::Backend>(
+    descriptor: &pso::Descriptor<'a, Backend<B>>,
+) -> pso::Descriptor<'a, B> {
+    match *descriptor {
+        pso::Descriptor::Sampler(sampler) => pso::Descriptor::Sampler(&sampler.raw),
+        pso::Descriptor::Image(view, layout) => pso::Descriptor::Image(view.raw(), layout),
+        pso::Descriptor::CombinedImageSampler(view, layout, sampler) => {
+            pso::Descriptor::CombinedImageSampler(view.raw(), layout, &sampler.raw)
+        }
+        pso::Descriptor::Buffer(buffer, ref range) => {
+            pso::Descriptor::Buffer(&buffer.raw, range.clone())
+        }
+        pso::Descriptor::TexelBuffer(view) => pso::Descriptor::TexelBuffer(&view.raw),
+    }
+}
+
+fn raw_base<'a, P, R>(
+    parent: &pso::BasePipeline<'a, P>,
+    raw: impl FnOnce(&'a P) -> &'a R,
+) -> pso::BasePipeline<'a, R> {
+    match *parent {
+        pso::BasePipeline::Pipeline(pipeline) => pso::BasePipeline::Pipeline(raw(pipeline)),
+        pso::BasePipeline::Index(index) => pso::BasePipeline::Index(index),
+        pso::BasePipeline::None => pso::BasePipeline::None,
+    }
+}
+
+impl<B: hal::Backend> device::Device<Backend<B>> for Device<B> {
+    unsafe fn allocate_memory(
+        &self,
+        memory_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<n::Memory<B>, device::AllocationError> {
+        let raw = self.raw.allocate_memory(memory_type, size)?;
+        let id = self.recorder.new_id();
+        let properties = self
+            .memory_properties
+            .memory_types
+            .get(memory_type.0)
+            .map(|ty| ty.properties)
+            .unwrap_or_else(memory::Properties::empty);
+        self.recorder.record(Action::AllocateMemory {
+            id,
+            memory_type,
+            properties,
+            size,
+        });
+        Ok(n::Memory { raw, id, size })
+    }
+
+    unsafe fn free_memory(&self, memory: n::Memory<B>) {
+        self.recorder.forget(memory.id);
+        self.recorder.record(Action::FreeMemory(memory.id));
+        self.raw.free_memory(memory.raw)
+    }
+
+    unsafe fn create_command_pool(
+        &self,
+        family: queue::QueueFamilyId,
+        create_flags: pool::CommandPoolCreateFlags,
+    ) -> Result<CommandPool<B>, device::OutOfMemory> {
+        let raw = self.raw.create_command_pool(family, create_flags)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateCommandPool {
+            id,
+            family,
+            flags: create_flags,
+        });
+        Ok(CommandPool::new(raw, id, Arc::clone(&self.recorder)))
+    }
+
+    unsafe fn destroy_command_pool(&self, pool: CommandPool<B>) {
+        self.recorder.record(Action::DestroyCommandPool(pool.id));
+        self.raw.destroy_command_pool(pool.raw)
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+    ) -> Result<n::RenderPass<B>, device::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        let attachments = attachments
+            .into_iter()
+            .map(|attachment| attachment.borrow().clone())
+            .collect::<Vec<_>>();
+        let subpasses = collect(subpasses);
+        let dependencies = dependencies
+            .into_iter()
+            .map(|dependency| dependency.borrow().clone())
+            .collect::<Vec<_>>();
+        let raw = self.raw.create_render_pass(
+            &attachments,
+            subpasses.iter().map(|subpass| subpass.borrow()),
+            &dependencies,
+        )?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateRenderPass {
+            id,
+            attachments,
+            subpasses: subpasses
+                .iter()
+                .map(|subpass| {
+                    let subpass = subpass.borrow();
+                    a::SubpassDesc {
+                        colors: subpass.colors.to_vec(),
+                        depth_stencil: subpass.depth_stencil.cloned(),
+                        inputs: subpass.inputs.to_vec(),
+                        resolves: subpass.resolves.to_vec(),
+                        preserves: subpass.preserves.to_vec(),
+                    }
+                })
+                .collect(),
+            dependencies,
+        });
+        Ok(n::RenderPass { raw, id })
+    }
+
+    unsafe fn destroy_render_pass(&self, rp: n::RenderPass<B>) {
+        self.recorder.record(Action::DestroyRenderPass(rp.id));
+        self.raw.destroy_render_pass(rp.raw)
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        set_layouts: IS,
+        push_constant: IR,
+    ) -> Result<n::PipelineLayout<B>, device::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<n::DescriptorSetLayout<B>>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        let set_layouts = collect(set_layouts);
+        let push_constants = push_constant
+            .into_iter()
+            .map(|constant| constant.borrow().clone())
+            .collect::<Vec<_>>();
+        let raw = self.raw.create_pipeline_layout(
+            set_layouts.iter().map(|layout| &layout.borrow().raw),
+            &push_constants,
+        )?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreatePipelineLayout {
+            id,
+            set_layouts: set_layouts
+                .iter()
+                .map(|layout| layout.borrow().id)
+                .collect(),
+            push_constants,
+        });
+        Ok(n::PipelineLayout { raw, id })
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, layout: n::PipelineLayout<B>) {
+        self.recorder
+            .record(Action::DestroyPipelineLayout(layout.id));
+        self.raw.destroy_pipeline_layout(layout.raw)
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        data: Option<&[u8]>,
+    ) -> Result<n::PipelineCache<B>, device::OutOfMemory> {
+        let raw = self.raw.create_pipeline_cache(data)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreatePipelineCache(id));
+        Ok(n::PipelineCache { raw, id })
+    }
+
+    unsafe fn get_pipeline_cache_data(
+        &self,
+        cache: &n::PipelineCache<B>,
+    ) -> Result<Vec<u8>, device::OutOfMemory> {
+        self.raw.get_pipeline_cache_data(&cache.raw)
+    }
+
+    unsafe fn merge_pipeline_caches<I>(
+        &self,
+        target: &n::PipelineCache<B>,
+        sources: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::PipelineCache<B>>,
+    {
+        let sources = collect(sources);
+        self.recorder.record(Action::MergePipelineCaches {
+            target: target.id,
+            sources: sources.iter().map(|cache| cache.borrow().id).collect(),
+        });
+        self.raw
+            .merge_pipeline_caches(&target.raw, sources.iter().map(|cache| &cache.borrow().raw))
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, cache: n::PipelineCache<B>) {
+        self.recorder.record(Action::DestroyPipelineCache(cache.id));
+        self.raw.destroy_pipeline_cache(cache.raw)
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, Backend<B>>,
+        cache: Option<&n::PipelineCache<B>>,
+    ) -> Result<n::GraphicsPipeline<B>, pso::CreationError> {
+        let shaders = &desc.shaders;
+        let raw_desc = pso::GraphicsPipelineDesc {
+            shaders: pso::GraphicsShaderSet {
+                vertex: n::raw_entry(&shaders.vertex),
+                hull: shaders.hull.as_ref().map(n::raw_entry),
+                domain: shaders.domain.as_ref().map(n::raw_entry),
+                geometry: shaders.geometry.as_ref().map(n::raw_entry),
+                fragment: shaders.fragment.as_ref().map(n::raw_entry),
+            },
+            rasterizer: desc.rasterizer,
+            vertex_buffers: desc.vertex_buffers.clone(),
+            attributes: desc.attributes.clone(),
+            input_assembler: desc.input_assembler.clone(),
+            blender: desc.blender.clone(),
+            depth_stencil: desc.depth_stencil,
+            multisampling: desc.multisampling.clone(),
+            baked_states: desc.baked_states.clone(),
+            layout: &desc.layout.raw,
+            subpass: n::raw_subpass(&desc.subpass),
+            flags: desc.flags,
+            parent: raw_base(&desc.parent, |pipeline: &'a n::GraphicsPipeline<B>| {
+                &pipeline.raw
+            }),
+        };
+        let raw = self
+            .raw
+            .create_graphics_pipeline(&raw_desc, cache.map(|cache| &cache.raw))?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateGraphicsPipeline {
+            id,
+            desc: Box::new(a::GraphicsPipelineDesc {
+                shaders: a::GraphicsShaderSet {
+                    vertex: n::trace_entry(&shaders.vertex),
+                    hull: shaders.hull.as_ref().map(n::trace_entry),
+                    domain: shaders.domain.as_ref().map(n::trace_entry),
+                    geometry: shaders.geometry.as_ref().map(n::trace_entry),
+                    fragment: shaders.fragment.as_ref().map(n::trace_entry),
+                },
+                rasterizer: desc.rasterizer,
+                vertex_buffers: desc.vertex_buffers.clone(),
+                attributes: desc.attributes.clone(),
+                input_assembler: desc.input_assembler.clone(),
+                blender: desc.blender.clone(),
+                depth_stencil: desc.depth_stencil,
+                multisampling: desc.multisampling.clone(),
+                baked_states: desc.baked_states.clone(),
+                layout: desc.layout.id,
+                subpass: n::trace_subpass(&desc.subpass),
+                flags: desc.flags,
+                parent: n::trace_base(&desc.parent, |pipeline: &n::GraphicsPipeline<B>| {
+                    pipeline.id
+                }),
+            }),
+            cache: cache.map(|cache| cache.id),
+        });
+        Ok(n::GraphicsPipeline { raw, id })
+    }
+
+    unsafe fn destroy_graphics_pipeline(&self, pipeline: n::GraphicsPipeline<B>) {
+        self.recorder
+            .record(Action::DestroyGraphicsPipeline(pipeline.id));
+        self.raw.destroy_graphics_pipeline(pipeline.raw)
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, Backend<B>>,
+        cache: Option<&n::PipelineCache<B>>,
+    ) -> Result<n::ComputePipeline<B>, pso::CreationError> {
+        let raw_desc = pso::ComputePipelineDesc {
+            shader: n::raw_entry(&desc.shader),
+            layout: &desc.layout.raw,
+            flags: desc.flags,
+            parent: raw_base(&desc.parent, |pipeline: &'a n::ComputePipeline<B>| {
+                &pipeline.raw
+            }),
+        };
+        let raw = self
+            .raw
+            .create_compute_pipeline(&raw_desc, cache.map(|cache| &cache.raw))?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateComputePipeline {
+            id,
+            desc: a::ComputePipelineDesc {
+                shader: n::trace_entry(&desc.shader),
+                layout: desc.layout.id,
+                flags: desc.flags,
+                parent: n::trace_base(&desc.parent, |pipeline: &n::ComputePipeline<B>| pipeline.id),
+            },
+            cache: cache.map(|cache| cache.id),
+        });
+        Ok(n::ComputePipeline { raw, id })
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, pipeline: n::ComputePipeline<B>) {
+        self.recorder
+            .record(Action::DestroyComputePipeline(pipeline.id));
+        self.raw.destroy_compute_pipeline(pipeline.raw)
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        pass: &n::RenderPass<B>,
+        attachments: I,
+        extent: image::Extent,
+    ) -> Result<n::Framebuffer<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView<B>>,
+    {
+        let attachments = collect(attachments);
+        let raw = self.raw.create_framebuffer(
+            &pass.raw,
+            attachments.iter().map(|view| view.borrow().raw()),
+            extent,
+        )?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateFramebuffer {
+            id,
+            pass: pass.id,
+            attachments: attachments.iter().map(|view| view.borrow().id).collect(),
+            extent,
+        });
+        Ok(n::Framebuffer { raw, id })
+    }
+
+    unsafe fn destroy_framebuffer(&self, fb: n::Framebuffer<B>) {
+        self.recorder.record(Action::DestroyFramebuffer(fb.id));
+        self.raw.destroy_framebuffer(fb.raw)
+    }
+
+    unsafe fn create_shader_module(
+        &self,
+        spirv_data: &[u32],
+    ) -> Result<n::ShaderModule<B>, device::ShaderError> {
+        let raw = self.raw.create_shader_module(spirv_data)?;
+        let id = self.recorder.new_id();
+        let bytes = spirv_data
+            .iter()
+            .flat_map(|word| word.to_le_bytes().to_vec())
+            .collect::<Vec<_>>();
+        let data = self.recorder.write_data(&bytes);
+        self.recorder
+            .record(Action::CreateShaderModule { id, data });
+        Ok(n::ShaderModule { raw, id })
+    }
+
+    unsafe fn destroy_shader_module(&self, shader: n::ShaderModule<B>) {
+        self.recorder.record(Action::DestroyShaderModule(shader.id));
+        self.raw.destroy_shader_module(shader.raw)
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        size: u64,
+        usage: buffer::Usage,
+    ) -> Result<n::Buffer<B>, buffer::CreationError> {
+        let raw = self.raw.create_buffer(size, usage)?;
+        let id = self.recorder.new_id();
+        self.recorder
+            .record(Action::CreateBuffer { id, size, usage });
+        Ok(n::Buffer { raw, id })
+    }
+
+    unsafe fn get_buffer_requirements(&self, buf: &n::Buffer<B>) -> memory::Requirements {
+        self.raw.get_buffer_requirements(&buf.raw)
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        memory: &n::Memory<B>,
+        offset: u64,
+        buf: &mut n::Buffer<B>,
+    ) -> Result<(), device::BindError> {
+        self.raw
+            .bind_buffer_memory(&memory.raw, offset, &mut buf.raw)?;
+        self.recorder.record(Action::BindBufferMemory {
+            buffer: buf.id,
+            memory: memory.id,
+            offset,
+        });
+        Ok(())
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: n::Buffer<B>) {
+        self.recorder.record(Action::DestroyBuffer(buffer.id));
+        self.raw.destroy_buffer(buffer.raw)
+    }
+
+    unsafe fn create_buffer_view(
+        &self,
+        buf: &n::Buffer<B>,
+        fmt: Option<format::Format>,
+        range: buffer::SubRange,
+    ) -> Result<n::BufferView<B>, buffer::ViewCreationError> {
+        let raw = self.raw.create_buffer_view(&buf.raw, fmt, range.clone())?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateBufferView {
+            id,
+            buffer: buf.id,
+            format: fmt,
+            range,
+        });
+        Ok(n::BufferView { raw, id })
+    }
+
+    unsafe fn destroy_buffer_view(&self, view: n::BufferView<B>) {
+        self.recorder.record(Action::DestroyBufferView(view.id));
+        self.raw.destroy_buffer_view(view.raw)
+    }
+
+    unsafe fn create_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Result<n::Image<B>, image::CreationError> {
+        let raw = self
+            .raw
+            .create_image(kind, mip_levels, format, tiling, usage, view_caps)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateImage {
+            id,
+            kind,
+            mip_levels,
+            format,
+            tiling,
+            usage,
+            view_caps,
+        });
+        Ok(n::Image { raw, id })
+    }
+
+    unsafe fn get_image_requirements(&self, image: &n::Image<B>) -> memory::Requirements {
+        self.raw.get_image_requirements(&image.raw)
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &n::Image<B>,
+        subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        self.raw
+            .get_image_subresource_footprint(&image.raw, subresource)
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        memory: &n::Memory<B>,
+        offset: u64,
+        image: &mut n::Image<B>,
+    ) -> Result<(), device::BindError> {
+        self.raw
+            .bind_image_memory(&memory.raw, offset, &mut image.raw)?;
+        self.recorder.record(Action::BindImageMemory {
+            image: image.id,
+            memory: memory.id,
+            offset,
+        });
+        Ok(())
+    }
+
+    unsafe fn destroy_image(&self, image: n::Image<B>) {
+        self.recorder.record(Action::DestroyImage(image.id));
+        self.raw.destroy_image(image.raw)
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        image: &n::Image<B>,
+        view_kind: image::ViewKind,
+        format: format::Format,
+        swizzle: format::Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<n::ImageView<B>, image::ViewCreationError> {
+        let raw =
+            self.raw
+                .create_image_view(&image.raw, view_kind, format, swizzle, range.clone())?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateImageView {
+            id,
+            image: image.id,
+            kind: view_kind,
+            format,
+            swizzle,
+            range,
+        });
+        Ok(n::ImageView {
+            raw: n::RawImageView::Owned(raw),
+            id,
+        })
+    }
+
+    unsafe fn destroy_image_view(&self, view: n::ImageView<B>) {
+        self.recorder.record(Action::DestroyImageView(view.id));
+        match view.raw {
+            n::RawImageView::Owned(raw) => self.raw.destroy_image_view(raw),
+            // The view is owned by the image acquired from the surface.
+            n::RawImageView::Swapchain(_) => {}
+        }
+    }
+
+    unsafe fn create_sampler(
+        &self,
+        desc: &image::SamplerDesc,
+    ) -> Result<n::Sampler<B>, device::AllocationError> {
+        let raw = self.raw.create_sampler(desc)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateSampler {
+            id,
+            desc: desc.clone(),
+        });
+        Ok(n::Sampler { raw, id })
+    }
+
+    unsafe fn destroy_sampler(&self, sampler: n::Sampler<B>) {
+        self.recorder.record(Action::DestroySampler(sampler.id));
+        self.raw.destroy_sampler(sampler.raw)
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        max_sets: usize,
+        descriptor_ranges: I,
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> Result<DescriptorPool<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        let ranges = descriptor_ranges
+            .into_iter()
+            .map(|range| *range.borrow())
+            .collect::<Vec<_>>();
+        let raw = self.raw.create_descriptor_pool(max_sets, &ranges, flags)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateDescriptorPool {
+            id,
+            max_sets,
+            ranges,
+            flags,
+        });
+        Ok(DescriptorPool::new(raw, id, Arc::clone(&self.recorder)))
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, pool: DescriptorPool<B>) {
+        self.recorder.record(Action::DestroyDescriptorPool(pool.id));
+        self.raw.destroy_descriptor_pool(pool.raw)
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        bindings: I,
+        immutable_samplers: J,
+    ) -> Result<n::DescriptorSetLayout<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<n::Sampler<B>>,
+    {
+        let bindings = bindings
+            .into_iter()
+            .map(|binding| binding.borrow().clone())
+            .collect::<Vec<_>>();
+        let immutable_samplers = collect(immutable_samplers);
+        let raw = self.raw.create_descriptor_set_layout(
+            &bindings,
+            immutable_samplers
+                .iter()
+                .map(|sampler| &sampler.borrow().raw),
+        )?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateDescriptorSetLayout {
+            id,
+            bindings,
+            immutable_samplers: immutable_samplers
+                .iter()
+                .map(|sampler| sampler.borrow().id)
+                .collect(),
+        });
+        Ok(n::DescriptorSetLayout { raw, id })
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, layout: n::DescriptorSetLayout<B>) {
+        self.recorder
+            .record(Action::DestroyDescriptorSetLayout(layout.id));
+        self.raw.destroy_descriptor_set_layout(layout.raw)
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend<B>, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend<B>>>,
+    {
+        let mut writes = Vec::new();
+        let mut trace_writes = Vec::new();
+        for write in write_iter {
+            let descriptors = collect(write.descriptors);
+            trace_writes.push(a::DescriptorSetWrite {
+                set: write.set.id,
+                binding: write.binding,
+                array_offset: write.array_offset,
+                descriptors: descriptors
+                    .iter()
+                    .map(|descriptor| n::trace_descriptor(descriptor.borrow()))
+                    .collect(),
+            });
+            writes.push(pso::DescriptorSetWrite {
+                set: &write.set.raw,
+                binding: write.binding,
+                array_offset: write.array_offset,
+                descriptors: descriptors
+                    .iter()
+                    .map(|descriptor| raw_descriptor(descriptor.borrow()))
+                    .collect::<Vec<_>>(),
+            });
+        }
+        self.recorder
+            .record(Action::WriteDescriptorSets(trace_writes));
+        self.raw.write_descriptor_sets(writes)
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend<B>>>,
+    {
+        let copies = collect(copy_iter);
+        self.recorder.record(Action::CopyDescriptorSets(
+            copies
+                .iter()
+                .map(|copy| {
+                    let copy = copy.borrow();
+                    a::DescriptorSetCopy {
+                        src_set: copy.src_set.id,
+                        src_binding: copy.src_binding,
+                        src_array_offset: copy.src_array_offset,
+                        dst_set: copy.dst_set.id,
+                        dst_binding: copy.dst_binding,
+                        dst_array_offset: copy.dst_array_offset,
+                        count: copy.count,
+                    }
+                })
+                .collect(),
+        ));
+        self.raw.copy_descriptor_sets(copies.iter().map(|copy| {
+            let copy = copy.borrow();
+            pso::DescriptorSetCopy {
+                src_set: &copy.src_set.raw,
+                src_binding: copy.src_binding,
+                src_array_offset: copy.src_array_offset,
+                dst_set: &copy.dst_set.raw,
+                dst_binding: copy.dst_binding,
+                dst_array_offset: copy.dst_array_offset,
+                count: copy.count,
+            }
+        }))
+    }
+
+    unsafe fn map_memory(
+        &self,
+        memory: &n::Memory<B>,
+        segment: memory::Segment,
+    ) -> Result<*mut u8, device::MapError> {
+        let range = n::segment_range(&segment, memory.size);
+        let ptr = self.raw.map_memory(&memory.raw, segment)?;
+        self.recorder.map(memory.id, ptr, range);
+        Ok(ptr)
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory<B>, memory::Segment)>,
+    {
+        let ranges = collect(ranges);
+        for range in &ranges {
+            let (memory, ref segment) = *range.borrow();
+            self.recorder
+                .capture(memory.id, n::segment_range(segment, memory.size));
+        }
+        self.raw
+            .flush_mapped_memory_ranges(ranges.iter().map(|range| {
+                let (memory, ref segment) = *range.borrow();
+                (&memory.raw, segment.clone())
+            }))
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I>(
+        &self,
+        ranges: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory<B>, memory::Segment)>,
+    {
+        let ranges = collect(ranges);
+        self.raw
+            .invalidate_mapped_memory_ranges(ranges.iter().map(|range| {
+                let (memory, ref segment) = *range.borrow();
+                (&memory.raw, segment.clone())
+            }))
+    }
+
+    unsafe fn unmap_memory(&self, memory: &n::Memory<B>) {
+        self.recorder.unmap(memory.id);
+        self.raw.unmap_memory(&memory.raw)
+    }
+
+    fn create_semaphore(&self) -> Result<n::Semaphore<B>, device::OutOfMemory> {
+        let raw = self.raw.create_semaphore()?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateSemaphore(id));
+        Ok(n::Semaphore { raw, id })
+    }
+
+    unsafe fn destroy_semaphore(&self, semaphore: n::Semaphore<B>) {
+        self.recorder.record(Action::DestroySemaphore(semaphore.id));
+        self.raw.destroy_semaphore(semaphore.raw)
+    }
+
+    fn create_fence(&self, signaled: bool) -> Result<n::Fence<B>, device::OutOfMemory> {
+        let raw = self.raw.create_fence(signaled)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateFence { id, signaled });
+        Ok(n::Fence { raw, id })
+    }
+
+    unsafe fn reset_fence(&self, fence: &n::Fence<B>) -> Result<(), device::OutOfMemory> {
+        self.recorder.record(Action::ResetFences(vec![fence.id]));
+        self.raw.reset_fence(&fence.raw)
+    }
+
+    unsafe fn reset_fences<I>(&self, fences: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::Fence<B>>,
+    {
+        let fences = collect(fences);
+        self.recorder.record(Action::ResetFences(
+            fences.iter().map(|fence| fence.borrow().id).collect(),
+        ));
+        self.raw
+            .reset_fences(fences.iter().map(|fence| &fence.borrow().raw))
+    }
+
+    unsafe fn wait_for_fence(
+        &self,
+        fence: &n::Fence<B>,
+        timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        let signaled = self.raw.wait_for_fence(&fence.raw, timeout_ns)?;
+        if signaled {
+            self.recorder.record(Action::WaitForFences {
+                fences: vec![fence.id],
+                wait: device::WaitFor::All,
+            });
+        }
+        Ok(signaled)
+    }
+
+    unsafe fn wait_for_fences<I>(
+        &self,
+        fences: I,
+        wait: device::WaitFor,
+        timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::Fence<B>>,
+    {
+        let fences = collect(fences);
+        let signaled = self.raw.wait_for_fences(
+            fences.iter().map(|fence| &fence.borrow().raw),
+            wait.clone(),
+            timeout_ns,
+        )?;
+        if signaled {
+            self.recorder.record(Action::WaitForFences {
+                fences: fences.iter().map(|fence| fence.borrow().id).collect(),
+                wait,
+            });
+        }
+        Ok(signaled)
+    }
+
+    unsafe fn get_fence_status(&self, fence: &n::Fence<B>) -> Result<bool, device::DeviceLost> {
+        self.raw.get_fence_status(&fence.raw)
+    }
+
+    unsafe fn destroy_fence(&self, fence: n::Fence<B>) {
+        self.recorder.record(Action::DestroyFence(fence.id));
+        self.raw.destroy_fence(fence.raw)
+    }
+
+    fn create_event(&self) -> Result<n::Event<B>, device::OutOfMemory> {
+        let raw = self.raw.create_event()?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateEvent(id));
+        Ok(n::Event { raw, id })
+    }
+
+    unsafe fn destroy_event(&self, event: n::Event<B>) {
+        self.recorder.record(Action::DestroyEvent(event.id));
+        self.raw.destroy_event(event.raw)
+    }
+
+    unsafe fn get_event_status(
+        &self,
+        event: &n::Event<B>,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        self.raw.get_event_status(&event.raw)
+    }
+
+    unsafe fn set_event(&self, event: &n::Event<B>) -> Result<(), device::OutOfMemory> {
+        self.recorder.record(Action::SetEvent(event.id));
+        self.raw.set_event(&event.raw)
+    }
+
+    unsafe fn reset_event(&self, event: &n::Event<B>) -> Result<(), device::OutOfMemory> {
+        self.recorder.record(Action::ResetEvent(event.id));
+        self.raw.reset_event(&event.raw)
+    }
+
+    unsafe fn create_query_pool(
+        &self,
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool<B>, query::CreationError> {
+        let raw = self.raw.create_query_pool(ty, count)?;
+        let id = self.recorder.new_id();
+        self.recorder
+            .record(Action::CreateQueryPool { id, ty, count });
+        Ok(n::QueryPool { raw, id })
+    }
+
+    unsafe fn destroy_query_pool(&self, pool: n::QueryPool<B>) {
+        self.recorder.record(Action::DestroyQueryPool(pool.id));
+        self.raw.destroy_query_pool(pool.raw)
+    }
+
+    unsafe fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool<B>,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        self.raw
+            .get_query_pool_results(&pool.raw, queries, data, stride, flags)
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut Surface<B>,
+        config: window::SwapchainConfig,
+        old_swapchain: Option<Swapchain<B>>,
+    ) -> Result<(Swapchain<B>, Vec<n::Image<B>>), window::CreationError> {
+        let old_id = old_swapchain.as_ref().map(|swapchain| swapchain.id);
+        let (format, extent, usage) = (config.format, config.extent, config.image_usage);
+        let (raw, images) = self.raw.create_swapchain(
+            &mut surface.raw,
+            config,
+            old_swapchain.map(|swapchain| swapchain.raw),
+        )?;
+        let images = images
+            .into_iter()
+            .map(|raw| n::Image {
+                raw,
+                id: self.recorder.new_id(),
+            })
+            .collect::<Vec<_>>();
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::CreateSwapchain {
+            id,
+            format,
+            extent,
+            usage,
+            images: images.iter().map(|image| image.id).collect(),
+        });
+        if let Some(old_id) = old_id {
+            self.recorder.record(Action::DestroySwapchain(old_id));
+        }
+        let swapchain = Swapchain {
+            raw,
+            id,
+            recorder: Arc::clone(&self.recorder),
+        };
+        Ok((swapchain, images))
+    }
+
+    unsafe fn destroy_swapchain(&self, swapchain: Swapchain<B>) {
+        self.recorder.record(Action::DestroySwapchain(swapchain.id));
+        self.raw.destroy_swapchain(swapchain.raw)
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        self.recorder.record(Action::WaitIdle);
+        self.raw.wait_idle()
+    }
+
+    unsafe fn set_image_name(&self, image: &mut n::Image<B>, name: &str) {
+        self.record_name(image.id, name);
+        self.raw.set_image_name(&mut image.raw, name)
+    }
+
+    unsafe fn set_buffer_name(&self, buffer: &mut n::Buffer<B>, name: &str) {
+        self.record_name(buffer.id, name);
+        self.raw.set_buffer_name(&mut buffer.raw, name)
+    }
+
+    unsafe fn set_command_buffer_name(&self, command_buffer: &mut CommandBuffer<B>, name: &str) {
+        self.record_name(command_buffer.id, name);
+        self.raw
+            .set_command_buffer_name(&mut command_buffer.raw, name)
+    }
+
+    unsafe fn set_semaphore_name(&self, semaphore: &mut n::Semaphore<B>, name: &str) {
+        self.record_name(semaphore.id, name);
+        self.raw.set_semaphore_name(&mut semaphore.raw, name)
+    }
+
+    unsafe fn set_fence_name(&self, fence: &mut n::Fence<B>, name: &str) {
+        self.record_name(fence.id, name);
+        self.raw.set_fence_name(&mut fence.raw, name)
+    }
+
+    unsafe fn set_framebuffer_name(&self, framebuffer: &mut n::Framebuffer<B>, name: &str) {
+        self.record_name(framebuffer.id, name);
+        self.raw.set_framebuffer_name(&mut framebuffer.raw, name)
+    }
+
+    unsafe fn set_render_pass_name(&self, render_pass: &mut n::RenderPass<B>, name: &str) {
+        self.record_name(render_pass.id, name);
+        self.raw.set_render_pass_name(&mut render_pass.raw, name)
+    }
+
+    unsafe fn set_descriptor_set_name(&self, descriptor_set: &mut n::DescriptorSet<B>, name: &str) {
+        self.record_name(descriptor_set.id, name);
+        self.raw
+            .set_descriptor_set_name(&mut descriptor_set.raw, name)
+    }
+
+    unsafe fn set_descriptor_set_layout_name(
+        &self,
+        descriptor_set_layout: &mut n::DescriptorSetLayout<B>,
+        name: &str,
+    ) {
+        self.record_name(descriptor_set_layout.id, name);
+        self.raw
+            .set_descriptor_set_layout_name(&mut descriptor_set_layout.raw, name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{iter, ptr};
+
+    use gfx_backend_mock as mock;
+    use hal::adapter::PhysicalDevice as _;
+    use hal::command::CommandBuffer as _;
+    use hal::device::Device as _;
+    use hal::pool::CommandPool as _;
+    use hal::pso::DescriptorPool as _;
+    use hal::queue::{CommandQueue as _, QueueFamily as _};
+    use hal::Instance as _;
+
+    use crate::CommandQueue;
+
+    /// Run `record` on a traced mock device, then replay the trace on another
+    /// mock device and check that it makes the same calls.
+    ///
+    /// Returns the calls made while recording.
+    fn replay(
+        name: &str,
+        record: impl FnOnce(
+            &Device<mock::Backend>,
+            &mut CommandQueue<mock::Backend>,
+            &mut CommandPool<mock::Backend>,
+        ),
+    ) -> Vec<&'static str> {
+        let dir =
+            std::env::temp_dir().join(format!("gfx-backend-trace-{}-{}", std::process::id(), name));
+
+        let captured = unsafe {
+            let raw = mock::Instance::create("test", 1).unwrap();
+            let instance = crate::Instance::<mock::Backend>::new(raw, &dir).unwrap();
+            let adapter = instance.enumerate_adapters().remove(0);
+            let family = &adapter.queue_families[0];
+            mock::take_calls();
+            let mut gpu = adapter
+                .physical_device
+                .open(&[(family, &[1.0])], hal::Features::empty())
+                .unwrap();
+            let device = &gpu.device;
+            let mut command_pool = device
+                .create_command_pool(family.id(), pool::CommandPoolCreateFlags::empty())
+                .unwrap();
+            record(
+                device,
+                &mut gpu.queue_groups[0].queues[0],
+                &mut command_pool,
+            );
+            device.destroy_command_pool(command_pool);
+            mock::take_calls()
+        };
+
+        let replayed = unsafe {
+            let instance = mock::Instance::create("test", 1).unwrap();
+            let adapter = instance.enumerate_adapters().remove(0);
+            mock::take_calls();
+            drop(crate::Replayer::from_trace(&adapter, &dir).unwrap());
+            mock::take_calls()
+        };
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        // Replaying makes the same calls, followed by waiting for the device
+        // before destroying what the trace left alive.
+        assert_eq!(replayed, [&captured[..], &["wait_idle"]].concat());
+        captured
+    }
+
+    /// Record a primary command buffer, submit it and wait for it to complete.
+    unsafe fn submit(
+        device: &Device<mock::Backend>,
+        queue: &mut CommandQueue<mock::Backend>,
+        command_pool: &mut CommandPool<mock::Backend>,
+        record: impl FnOnce(&mut CommandBuffer<mock::Backend>),
+    ) {
+        let mut cmd = command_pool.allocate_one(hal::command::Level::Primary);
+        cmd.begin_primary(hal::command::CommandBufferFlags::ONE_TIME_SUBMIT);
+        record(&mut cmd);
+        cmd.finish();
+
+        let fence = device.create_fence(false).unwrap();
+        queue.submit_without_semaphores(Some(&cmd), Some(&fence));
+        assert!(device
+            .wait_for_fences(iter::once(&fence), hal::device::WaitFor::All, !0)
+            .unwrap());
+        device.destroy_fence(fence);
+        command_pool.free(Some(cmd));
+    }
+
+    #[test]
+    fn buffers() {
+        let calls = replay("buffers", |device, queue, command_pool| unsafe {
+            let memory = device.allocate_memory(mock::HOST_VISIBLE, 128).unwrap();
+            let mut src = device
+                .create_buffer(64, buffer::Usage::TRANSFER_SRC)
+                .unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut src).unwrap();
+            let mut dst = device
+                .create_buffer(64, buffer::Usage::TRANSFER_DST)
+                .unwrap();
+            device.bind_buffer_memory(&memory, 64, &mut dst).unwrap();
+
+            // Mappings aren't traced, only the contents written through them, which
+            // get replayed by mapping, flushing and unmapping the written range.
+            let mapping = device.map_memory(&memory, memory::Segment::ALL).unwrap();
+            for i in 0 .. 64 {
+                ptr::write(mapping.add(i), i as u8);
+            }
+            device
+                .flush_mapped_memory_ranges(iter::once((&memory, memory::Segment::ALL)))
+                .unwrap();
+            device.unmap_memory(&memory);
+
+            submit(device, queue, command_pool, |cmd| {
+                cmd.copy_buffer(
+                    &src,
+                    &dst,
+                    Some(hal::command::BufferCopy {
+                        src: 0,
+                        dst: 0,
+                        size: 64,
+                    }),
+                );
+            });
+
+            device.destroy_buffer(src);
+            device.destroy_buffer(dst);
+            device.free_memory(memory);
+        });
+        assert!(calls.contains(&"flush_mapped_memory_ranges"));
+        assert!(calls.contains(&"copy_buffer"));
+    }
+
+    #[test]
+    fn images() {
+        let calls = replay("images", |device, queue, command_pool| unsafe {
+            let staging_memory = device.allocate_memory(mock::HOST_VISIBLE, 64).unwrap();
+            let mut staging = device
+                .create_buffer(
+                    64,
+                    buffer::Usage::TRANSFER_SRC | buffer::Usage::TRANSFER_DST,
+                )
+                .unwrap();
+            device
+                .bind_buffer_memory(&staging_memory, 0, &mut staging)
+                .unwrap();
+
+            let memory = device.allocate_memory(mock::DEVICE_LOCAL, 512).unwrap();
+            let mut images = Vec::new();
+            for i in 0 .. 2 {
+                let mut image = device
+                    .create_image(
+                        image::Kind::D2(4, 4, 1, 1),
+                        1,
+                        format::Format::Rgba8Unorm,
+                        image::Tiling::Optimal,
+                        image::Usage::TRANSFER_SRC | image::Usage::TRANSFER_DST,
+                        image::ViewCapabilities::empty(),
+                    )
+                    .unwrap();
+                device
+                    .bind_image_memory(&memory, i * 256, &mut image)
+                    .unwrap();
+                images.push(image);
+            }
+            let range = image::SubresourceRange {
+                aspects: format::Aspects::COLOR,
+                levels: 0 .. 1,
+                layers: 0 .. 1,
+            };
+            let view = device
+                .create_image_view(
+                    &images[0],
+                    image::ViewKind::D2,
+                    format::Format::Rgba8Unorm,
+                    format::Swizzle::NO,
+                    range.clone(),
+                )
+                .unwrap();
+            let sampler = device
+                .create_sampler(&image::SamplerDesc::new(
+                    image::Filter::Linear,
+                    image::WrapMode::Clamp,
+                ))
+                .unwrap();
+
+            let layers = image::SubresourceLayers {
+                aspects: format::Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            };
+            let extent = image::Extent {
+                width: 4,
+                height: 4,
+                depth: 1,
+            };
+            let buffer_copy = hal::command::BufferImageCopy {
+                buffer_offset: 0,
+                buffer_width: 0,
+                buffer_height: 0,
+                image_layers: layers.clone(),
+                image_offset: image::Offset::ZERO,
+                image_extent: extent,
+            };
+            submit(device, queue, command_pool, |cmd| {
+                cmd.pipeline_barrier(
+                    pso::PipelineStage::TOP_OF_PIPE .. pso::PipelineStage::TRANSFER,
+                    memory::Dependencies::empty(),
+                    images.iter().map(|image| memory::Barrier::Image {
+                        states: (image::Access::empty(), image::Layout::Undefined)
+                            .. (image::Access::TRANSFER_WRITE, image::Layout::General),
+                        target: image,
+                        range: range.clone(),
+                        families: None,
+                    }),
+                );
+                cmd.clear_image(
+                    &images[0],
+                    image::Layout::General,
+                    hal::command::ClearValue {
+                        color: hal::command::ClearColor {
+                            float32: [0.0, 0.5, 1.0, 1.0],
+                        },
+                    },
+                    Some(range.clone()),
+                );
+                cmd.copy_buffer_to_image(
+                    &staging,
+                    &images[0],
+                    image::Layout::General,
+                    Some(buffer_copy.clone()),
+                );
+                cmd.copy_image(
+                    &images[0],
+                    image::Layout::General,
+                    &images[1],
+                    image::Layout::General,
+                    Some(hal::command::ImageCopy {
+                        src_subresource: layers.clone(),
+                        src_offset: image::Offset::ZERO,
+                        dst_subresource: layers.clone(),
+                        dst_offset: image::Offset::ZERO,
+                        extent,
+                    }),
+                );
+                cmd.blit_image(
+                    &images[1],
+                    image::Layout::General,
+                    &images[0],
+                    image::Layout::General,
+                    image::Filter::Linear,
+                    Some(hal::command::ImageBlit {
+                        src_subresource: layers.clone(),
+                        src_bounds: image::Offset::ZERO .. image::Offset { x: 4, y: 4, z: 1 },
+                        dst_subresource: layers.clone(),
+                        dst_bounds: image::Offset::ZERO .. image::Offset { x: 2, y: 2, z: 1 },
+                    }),
+                );
+                cmd.copy_image_to_buffer(
+                    &images[0],
+                    image::Layout::General,
+                    &staging,
+                    Some(buffer_copy.clone()),
+                );
+            });
+
+            device.destroy_sampler(sampler);
+            device.destroy_image_view(view);
+            for image in images {
+                device.destroy_image(image);
+            }
+            device.free_memory(memory);
+            device.destroy_buffer(staging);
+            device.free_memory(staging_memory);
+        });
+        for call in &[
+            "create_image_view",
+            "create_sampler",
+            "clear_image",
+            "copy_buffer_to_image",
+            "copy_image",
+            "blit_image",
+            "copy_image_to_buffer",
+        ] {
+            assert!(calls.contains(call), "{} wasn't replayed", call);
+        }
+    }
+
+    #[test]
+    fn descriptor_sets() {
+        let calls = replay("descriptor_sets", |device, _queue, _command_pool| unsafe {
+            let memory = device.allocate_memory(mock::HOST_VISIBLE, 256).unwrap();
+            let mut uniforms = device.create_buffer(256, buffer::Usage::UNIFORM).unwrap();
+            device
+                .bind_buffer_memory(&memory, 0, &mut uniforms)
+                .unwrap();
+            let sampler = device
+                .create_sampler(&image::SamplerDesc::new(
+                    image::Filter::Nearest,
+                    image::WrapMode::Tile,
+                ))
+                .unwrap();
+
+            let bindings = [
+                pso::DescriptorSetLayoutBinding {
+                    binding: 0,
+                    ty: pso::DescriptorType::Buffer {
+                        ty: pso::BufferDescriptorType::Uniform,
+                        format: pso::BufferDescriptorFormat::Structured {
+                            dynamic_offset: false,
+                        },
+                    },
+                    count: 1,
+                    stage_flags: pso::ShaderStageFlags::ALL,
+                    immutable_samplers: false,
+                },
+                pso::DescriptorSetLayoutBinding {
+                    binding: 1,
+                    ty: pso::DescriptorType::Sampler,
+                    count: 1,
+                    stage_flags: pso::ShaderStageFlags::FRAGMENT,
+                    immutable_samplers: false,
+                },
+            ];
+            let layout = device
+                .create_descriptor_set_layout(&bindings, None::<&n::Sampler<mock::Backend>>)
+                .unwrap();
+            let mut pool = device
+                .create_descriptor_pool(
+                    2,
+                    bindings.iter().map(|binding| pso::DescriptorRangeDesc {
+                        ty: binding.ty,
+                        count: 2,
+                    }),
+                    pso::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET,
+                )
+                .unwrap();
+            let src = pool.allocate_set(&layout).unwrap();
+            let dst = pool.allocate_set(&layout).unwrap();
+
+            device.write_descriptor_sets(vec![
+                pso::DescriptorSetWrite {
+                    set: &src,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(pso::Descriptor::Buffer(&uniforms, buffer::SubRange::WHOLE)),
+                },
+                pso::DescriptorSetWrite {
+                    set: &src,
+                    binding: 1,
+                    array_offset: 0,
+                    descriptors: Some(pso::Descriptor::Sampler(&sampler)),
+                },
+            ]);
+            device.copy_descriptor_sets(Some(pso::DescriptorSetCopy {
+                src_set: &src,
+                src_binding: 0,
+                src_array_offset: 0,
+                dst_set: &dst,
+                dst_binding: 0,
+                dst_array_offset: 0,
+                count: 1,
+            }));
+
+            pool.free(Some(dst));
+            pool.reset();
+            device.destroy_descriptor_pool(pool);
+            device.destroy_descriptor_set_layout(layout);
+            device.destroy_sampler(sampler);
+            device.destroy_buffer(uniforms);
+            device.free_memory(memory);
+        });
+        for call in &[
+            "create_descriptor_set_layout",
+            "allocate_set",
+            "write_descriptor_sets",
+            "copy_descriptor_sets",
+            "free_sets",
+            "reset_descriptor_pool",
+        ] {
+            assert!(calls.contains(call), "{} wasn't replayed", call);
+        }
+    }
+
+    #[test]
+    fn draw_and_dispatch() {
+        let calls = replay("draw_and_dispatch", |device, queue, command_pool| unsafe {
+            let memory = device.allocate_memory(mock::HOST_VISIBLE, 256).unwrap();
+            let mut buffer = device
+                .create_buffer(
+                    256,
+                    buffer::Usage::VERTEX | buffer::Usage::INDEX | buffer::Usage::INDIRECT,
+                )
+                .unwrap();
+            device.bind_buffer_memory(&memory, 0, &mut buffer).unwrap();
+            let image_memory = device.allocate_memory(mock::DEVICE_LOCAL, 256).unwrap();
+            let mut target = device
+                .create_image(
+                    image::Kind::D2(4, 4, 1, 1),
+                    1,
+                    format::Format::Rgba8Unorm,
+                    image::Tiling::Optimal,
+                    image::Usage::COLOR_ATTACHMENT,
+                    image::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            device
+                .bind_image_memory(&image_memory, 0, &mut target)
+                .unwrap();
+            let view = device
+                .create_image_view(
+                    &target,
+                    image::ViewKind::D2,
+                    format::Format::Rgba8Unorm,
+                    format::Swizzle::NO,
+                    image::SubresourceRange {
+                        aspects: format::Aspects::COLOR,
+                        levels: 0 .. 1,
+                        layers: 0 .. 1,
+                    },
+                )
+                .unwrap();
+
+            let render_pass = device
+                .create_render_pass(
+                    Some(pass::Attachment {
+                        format: Some(format::Format::Rgba8Unorm),
+                        samples: 1,
+                        ops: pass::AttachmentOps::new(
+                            pass::AttachmentLoadOp::Clear,
+                            pass::AttachmentStoreOp::Store,
+                        ),
+                        stencil_ops: pass::AttachmentOps::DONT_CARE,
+                        layouts: image::Layout::Undefined .. image::Layout::General,
+                    }),
+                    Some(pass::SubpassDesc {
+                        colors: &[(0, image::Layout::ColorAttachmentOptimal)],
+                        depth_stencil: None,
+                        inputs: &[],
+                        resolves: &[],
+                        preserves: &[],
+                    }),
+                    None::<pass::SubpassDependency>,
+                )
+                .unwrap();
+            let extent = image::Extent {
+                width: 4,
+                height: 4,
+                depth: 1,
+            };
+            let framebuffer = device
+                .create_framebuffer(&render_pass, Some(&view), extent)
+                .unwrap();
+            let layout = device
+                .create_pipeline_layout(
+                    None::<&n::DescriptorSetLayout<mock::Backend>>,
+                    &[(pso::ShaderStageFlags::COMPUTE, 0 .. 4)],
+                )
+                .unwrap();
+            // The mock backend doesn't look into shaders.
+            let module = device.create_shader_module(&[0x0723_0203]).unwrap();
+            let entry = pso::EntryPoint {
+                entry: "main",
+                module: &module,
+                specialization: pso::Specialization::default(),
+            };
+
+            let mut desc = pso::GraphicsPipelineDesc::new(
+                pso::GraphicsShaderSet {
+                    vertex: entry.clone(),
+                    hull: None,
+                    domain: None,
+                    geometry: None,
+                    fragment: Some(entry.clone()),
+                },
+                pso::Primitive::TriangleList,
+                pso::Rasterizer::FILL,
+                &layout,
+                pass::Subpass {
+                    index: 0,
+                    main_pass: &render_pass,
+                },
+            );
+            desc.vertex_buffers.push(pso::VertexBufferDesc {
+                binding: 0,
+                stride: 8,
+                rate: pso::VertexInputRate::Vertex,
+            });
+            desc.attributes.push(pso::AttributeDesc {
+                location: 0,
+                binding: 0,
+                element: pso::Element {
+                    format: format::Format::Rg32Sfloat,
+                    offset: 0,
+                },
+            });
+            desc.blender.targets.push(pso::ColorBlendDesc::EMPTY);
+            let graphics = device.create_graphics_pipeline(&desc, None).unwrap();
+            let compute = device
+                .create_compute_pipeline(&pso::ComputePipelineDesc::new(entry, &layout), None)
+                .unwrap();
+
+            let rect = pso::Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            };
+            submit(device, queue, command_pool, |cmd| {
+                cmd.begin_render_pass(
+                    &render_pass,
+                    &framebuffer,
+                    rect,
+                    Some(hal::command::ClearValue {
+                        color: hal::command::ClearColor {
+                            float32: [0.0, 0.0, 0.0, 1.0],
+                        },
+                    }),
+                    hal::command::SubpassContents::Inline,
+                );
+                cmd.bind_graphics_pipeline(&graphics);
+                cmd.set_viewports(
+                    0,
+                    Some(pso::Viewport {
+                        rect,
+                        depth: 0.0 .. 1.0,
+                    }),
+                );
+                cmd.set_scissors(0, Some(rect));
+                cmd.bind_vertex_buffers(0, Some((&buffer, buffer::SubRange::WHOLE)));
+                cmd.bind_index_buffer(buffer::IndexBufferView {
+                    buffer: &buffer,
+                    range: buffer::SubRange {
+                        offset: 128,
+                        size: Some(64),
+                    },
+                    index_type: hal::IndexType::U16,
+                });
+                cmd.draw(0 .. 3, 0 .. 1);
+                cmd.draw_indexed(0 .. 3, 0, 0 .. 1);
+                cmd.draw_indirect(&buffer, 192, 1, 16);
+                cmd.end_render_pass();
+
+                cmd.bind_compute_pipeline(&compute);
+                cmd.push_compute_constants(&layout, 0, &[1]);
+                cmd.dispatch([1, 2, 3]);
+                cmd.dispatch_indirect(&buffer, 240);
+            });
+
+            device.destroy_compute_pipeline(compute);
+            device.destroy_graphics_pipeline(graphics);
+            device.destroy_shader_module(module);
+            device.destroy_pipeline_layout(layout);
+            device.destroy_framebuffer(framebuffer);
+            device.destroy_render_pass(render_pass);
+            device.destroy_image_view(view);
+            device.destroy_image(target);
+            device.free_memory(image_memory);
+            device.destroy_buffer(buffer);
+            device.free_memory(memory);
+        });
+        for call in &[
+            "create_graphics_pipeline",
+            "create_compute_pipeline",
+            "begin_render_pass",
+            "draw",
+            "draw_indexed",
+            "draw_indirect",
+            "dispatch",
+            "dispatch_indirect",
+        ] {
+            assert!(calls.contains(call), "{} wasn't replayed", call);
+        }
+    }
+}
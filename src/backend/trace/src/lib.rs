@@ -0,0 +1,400 @@
+//! Backend capturing the calls made to another backend into a trace.
+//!
+//! `Backend<B>` forwards every call to the backend `B`, and records the ones
+//! changing any state as [`Action`](action/enum.Action.html)s, one per line
+//! of a RON file in the trace directory. Shaders, buffer updates and the
+//! contents written by the host to mapped memory are stored as separate
+//! binary files next to it. A [`Replayer`](struct.Replayer.html) feeds the
+//! trace back into any other backend.
+
+#![allow(missing_docs, missing_copy_implementations)]
+
+#[macro_use]
+extern crate log;
+extern crate gfx_hal as hal;
+
+use std::borrow::Borrow;
+use std::io;
+use std::marker::PhantomData;
+use std::path::Path;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+use hal::{adapter, format, image, queue as q, window};
+
+use crate::action::{Action, Id};
+use crate::recorder::Recorder;
+
+pub use self::command::CommandBuffer;
+pub use self::device::Device;
+pub use self::native::*;
+pub use self::pool::{CommandPool, DescriptorPool};
+pub use self::queue::CommandQueue;
+pub use self::recorder::{read_trace, TRACE_FILE};
+pub use self::replay::Replayer;
+
+pub mod action;
+mod command;
+mod device;
+mod native;
+mod pool;
+mod queue;
+mod recorder;
+mod replay;
+
+/// Environment variable naming the directory `hal::Instance::create` writes
+/// the trace to.
+pub const TRACE_DIR_VAR: &str = "GFX_TRACE_DIR";
+
+/// Trace backend wrapping the backend `B`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Backend<B>(PhantomData<B>);
+
+impl<B: hal::Backend> hal::Backend for Backend<B> {
+    type Instance = Instance<B>;
+    type PhysicalDevice = PhysicalDevice<B>;
+    type Device = Device<B>;
+
+    type Surface = Surface<B>;
+    type Swapchain = Swapchain<B>;
+
+    type QueueFamily = QueueFamily<B>;
+    type CommandQueue = CommandQueue<B>;
+    type CommandBuffer = CommandBuffer<B>;
+
+    type Memory = Memory<B>;
+    type CommandPool = CommandPool<B>;
+
+    type ShaderModule = ShaderModule<B>;
+    type RenderPass = RenderPass<B>;
+    type Framebuffer = Framebuffer<B>;
+
+    type Buffer = Buffer<B>;
+    type BufferView = BufferView<B>;
+    type Image = Image<B>;
+    type ImageView = ImageView<B>;
+    type Sampler = Sampler<B>;
+
+    type ComputePipeline = ComputePipeline<B>;
+    type GraphicsPipeline = GraphicsPipeline<B>;
+    type PipelineCache = PipelineCache<B>;
+    type PipelineLayout = PipelineLayout<B>;
+    type DescriptorSetLayout = DescriptorSetLayout<B>;
+    type DescriptorPool = DescriptorPool<B>;
+    type DescriptorSet = DescriptorSet<B>;
+
+    type Fence = Fence<B>;
+    type Semaphore = Semaphore<B>;
+    type Event = Event<B>;
+    type QueryPool = QueryPool<B>;
+}
+
+#[derive(Debug)]
+pub struct Instance<B: hal::Backend> {
+    raw: B::Instance,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> Instance<B> {
+    /// Wraps an instance of the inner backend, writing the trace to `dir`.
+    ///
+    /// An existing trace in `dir` is overwritten.
+    pub fn new(raw: B::Instance, dir: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Instance {
+            raw,
+            recorder: Arc::new(Recorder::create(dir.as_ref())?),
+        })
+    }
+
+    pub fn raw(&self) -> &B::Instance {
+        &self.raw
+    }
+}
+
+impl<B: hal::Backend> hal::Instance<Backend<B>> for Instance<B> {
+    fn create(name: &str, version: u32) -> Result<Self, hal::UnsupportedBackend> {
+        let raw = B::Instance::create(name, version)?;
+        let dir = std::env::var(TRACE_DIR_VAR).unwrap_or_else(|_| "trace".to_string());
+        Instance::new(raw, &dir).map_err(|e| {
+            error!("Failed to create the trace in {}: {}", dir, e);
+            hal::UnsupportedBackend
+        })
+    }
+
+    fn enumerate_adapters(&self) -> Vec<adapter::Adapter<Backend<B>>> {
+        self.raw
+            .enumerate_adapters()
+            .into_iter()
+            .map(|adapter| adapter::Adapter {
+                info: adapter.info,
+                physical_device: PhysicalDevice {
+                    raw: adapter.physical_device,
+                    recorder: Arc::clone(&self.recorder),
+                },
+                queue_families: adapter
+                    .queue_families
+                    .into_iter()
+                    .map(|raw| QueueFamily { raw })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    unsafe fn create_surface(
+        &self,
+        has_handle: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Surface<B>, window::InitError> {
+        self.raw.create_surface(has_handle).map(|raw| Surface {
+            raw,
+            id: self.recorder.new_id(),
+            recorder: Arc::clone(&self.recorder),
+        })
+    }
+
+    unsafe fn destroy_surface(&self, surface: Surface<B>) {
+        self.raw.destroy_surface(surface.raw)
+    }
+}
+
+#[derive(Debug)]
+pub struct PhysicalDevice<B: hal::Backend> {
+    raw: B::PhysicalDevice,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> adapter::PhysicalDevice<Backend<B>> for PhysicalDevice<B> {
+    unsafe fn open(
+        &self,
+        families: &[(&QueueFamily<B>, &[q::QueuePriority])],
+        requested_features: hal::Features,
+    ) -> Result<adapter::Gpu<Backend<B>>, hal::device::CreationError> {
+        let raw_families = families
+            .iter()
+            .map(|&(family, priorities)| (&family.raw, priorities))
+            .collect::<Vec<_>>();
+        let gpu = self.raw.open(&raw_families, requested_features)?;
+
+        let recorder = &self.recorder;
+        let trace_families = families
+            .iter()
+            .map(|&(family, priorities)| action::QueueFamily {
+                id: q::QueueFamily::id(&family.raw),
+                ty: q::QueueFamily::queue_type(&family.raw),
+                queues: priorities
+                    .iter()
+                    .map(|&priority| (recorder.new_id(), priority))
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        let queue_groups = gpu
+            .queue_groups
+            .into_iter()
+            .map(|group| {
+                let family = trace_families
+                    .iter()
+                    .find(|family| family.id == group.family)
+                    .expect("Queue group of an unknown family");
+                q::QueueGroup {
+                    family: group.family,
+                    queues: group
+                        .queues
+                        .into_iter()
+                        .zip(&family.queues)
+                        .map(|(raw, &(id, _))| CommandQueue::new(raw, id, Arc::clone(recorder)))
+                        .collect(),
+                }
+            })
+            .collect();
+        recorder.record(Action::Open {
+            families: trace_families,
+            features: requested_features,
+        });
+
+        Ok(adapter::Gpu {
+            device: Device::new(
+                gpu.device,
+                self.raw.memory_properties(),
+                Arc::clone(recorder),
+            ),
+            queue_groups,
+        })
+    }
+
+    fn format_properties(&self, format: Option<format::Format>) -> format::Properties {
+        self.raw.format_properties(format)
+    }
+
+    fn image_format_properties(
+        &self,
+        format: format::Format,
+        dimensions: u8,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        self.raw
+            .image_format_properties(format, dimensions, tiling, usage, view_caps)
+    }
+
+    fn memory_properties(&self) -> adapter::MemoryProperties {
+        self.raw.memory_properties()
+    }
+
+    fn features(&self) -> hal::Features {
+        self.raw.features()
+    }
+
+    fn hints(&self) -> hal::Hints {
+        self.raw.hints()
+    }
+
+    fn limits(&self) -> hal::Limits {
+        self.raw.limits()
+    }
+
+    fn is_valid_cache(&self, cache: &[u8]) -> bool {
+        self.raw.is_valid_cache(cache)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueFamily<B: hal::Backend> {
+    raw: B::QueueFamily,
+}
+
+impl<B: hal::Backend> q::QueueFamily for QueueFamily<B> {
+    fn queue_type(&self) -> q::QueueType {
+        self.raw.queue_type()
+    }
+    fn max_queues(&self) -> usize {
+        self.raw.max_queues()
+    }
+    fn id(&self) -> q::QueueFamilyId {
+        self.raw.id()
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface<B: hal::Backend> {
+    raw: B::Surface,
+    id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> window::Surface<Backend<B>> for Surface<B> {
+    fn supports_queue_family(&self, family: &QueueFamily<B>) -> bool {
+        self.raw.supports_queue_family(&family.raw)
+    }
+
+    fn capabilities(&self, physical_device: &PhysicalDevice<B>) -> window::SurfaceCapabilities {
+        self.raw.capabilities(&physical_device.raw)
+    }
+
+    fn supported_formats(
+        &self,
+        physical_device: &PhysicalDevice<B>,
+    ) -> Option<Vec<format::Format>> {
+        self.raw.supported_formats(&physical_device.raw)
+    }
+}
+
+impl<B: hal::Backend> window::PresentationSurface<Backend<B>> for Surface<B> {
+    type SwapchainImage = SwapchainImage<B>;
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        device: &Device<B>,
+        config: window::SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        let action = Action::ConfigureSurface {
+            surface: self.id,
+            format: config.format,
+            extent: config.extent,
+            usage: config.image_usage,
+        };
+        self.raw.configure_swapchain(device.raw(), config)?;
+        self.recorder.record(action);
+        Ok(())
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, device: &Device<B>) {
+        self.recorder.record(Action::UnconfigureSurface(self.id));
+        self.raw.unconfigure_swapchain(device.raw())
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+    ) -> Result<(SwapchainImage<B>, Option<window::Suboptimal>), window::AcquireError> {
+        let (raw, suboptimal) = self.raw.acquire_image(timeout_ns)?;
+        let image = SwapchainImage::new(raw, self.recorder.new_id());
+        self.recorder.record(Action::AcquireSurfaceImage {
+            surface: self.id,
+            view: image.view.id,
+        });
+        Ok((image, suboptimal))
+    }
+}
+
+type RawSwapchainImage<B> =
+    <<B as hal::Backend>::Surface as window::PresentationSurface<B>>::SwapchainImage;
+
+/// Image acquired from a surface, along with a view of it.
+#[derive(Debug)]
+pub struct SwapchainImage<B: hal::Backend> {
+    // Declared first so the view is dropped before the image it points into.
+    view: ImageView<B>,
+    raw: Box<RawSwapchainImage<B>>,
+}
+
+impl<B: hal::Backend> SwapchainImage<B> {
+    fn new(raw: RawSwapchainImage<B>, id: Id) -> Self {
+        let raw = Box::new(raw);
+        let view = NonNull::from((*raw).borrow());
+        SwapchainImage {
+            view: ImageView {
+                raw: RawImageView::Swapchain(view),
+                id,
+            },
+            raw,
+        }
+    }
+
+    fn into_raw(self) -> RawSwapchainImage<B> {
+        *self.raw
+    }
+}
+
+impl<B: hal::Backend> Borrow<ImageView<B>> for SwapchainImage<B> {
+    fn borrow(&self) -> &ImageView<B> {
+        &self.view
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain<B: hal::Backend> {
+    raw: B::Swapchain,
+    id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> window::Swapchain<Backend<B>> for Swapchain<B> {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        semaphore: Option<&Semaphore<B>>,
+        fence: Option<&Fence<B>>,
+    ) -> Result<(window::SwapImageIndex, Option<window::Suboptimal>), window::AcquireError> {
+        let result = self.raw.acquire_image(
+            timeout_ns,
+            semaphore.map(|semaphore| &semaphore.raw),
+            fence.map(|fence| &fence.raw),
+        )?;
+        self.recorder.record(Action::AcquireImage {
+            swapchain: self.id,
+            semaphore: semaphore.map(|semaphore| semaphore.id),
+            fence: fence.map(|fence| fence.id),
+        });
+        Ok(result)
+    }
+}
@@ -0,0 +1,298 @@
+use std::ops::Range;
+use std::ptr::NonNull;
+
+use hal::{command as com, memory, pso, query};
+
+use crate::action::{self as a, Id};
+
+#[derive(Debug)]
+pub struct Memory<B: hal::Backend> {
+    pub(crate) raw: B::Memory,
+    pub(crate) id: Id,
+    pub(crate) size: u64,
+}
+
+#[derive(Debug)]
+pub struct Buffer<B: hal::Backend> {
+    pub(crate) raw: B::Buffer,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct BufferView<B: hal::Backend> {
+    pub(crate) raw: B::BufferView,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct Image<B: hal::Backend> {
+    pub(crate) raw: B::Image,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub(crate) enum RawImageView<B: hal::Backend> {
+    Owned(B::ImageView),
+    /// View owned by a swapchain image of the inner backend, which is kept
+    /// at a stable address by the `SwapchainImage` wrapping both.
+    Swapchain(NonNull<B::ImageView>),
+}
+
+#[derive(Debug)]
+pub struct ImageView<B: hal::Backend> {
+    pub(crate) raw: RawImageView<B>,
+    pub(crate) id: Id,
+}
+
+// The swapchain view pointer is only a borrow of a `Send + Sync` object.
+unsafe impl<B: hal::Backend> Send for ImageView<B> {}
+unsafe impl<B: hal::Backend> Sync for ImageView<B> {}
+
+impl<B: hal::Backend> ImageView<B> {
+    pub(crate) fn raw(&self) -> &B::ImageView {
+        match self.raw {
+            RawImageView::Owned(ref view) => view,
+            RawImageView::Swapchain(view) => unsafe { &*view.as_ptr() },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sampler<B: hal::Backend> {
+    pub(crate) raw: B::Sampler,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct ShaderModule<B: hal::Backend> {
+    pub(crate) raw: B::ShaderModule,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct RenderPass<B: hal::Backend> {
+    pub(crate) raw: B::RenderPass,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct Framebuffer<B: hal::Backend> {
+    pub(crate) raw: B::Framebuffer,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct PipelineCache<B: hal::Backend> {
+    pub(crate) raw: B::PipelineCache,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct PipelineLayout<B: hal::Backend> {
+    pub(crate) raw: B::PipelineLayout,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct GraphicsPipeline<B: hal::Backend> {
+    pub(crate) raw: B::GraphicsPipeline,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct ComputePipeline<B: hal::Backend> {
+    pub(crate) raw: B::ComputePipeline,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct DescriptorSetLayout<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorSetLayout,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct DescriptorSet<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorSet,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct Fence<B: hal::Backend> {
+    pub(crate) raw: B::Fence,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct Semaphore<B: hal::Backend> {
+    pub(crate) raw: B::Semaphore,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct Event<B: hal::Backend> {
+    pub(crate) raw: B::Event,
+    pub(crate) id: Id,
+}
+
+#[derive(Debug)]
+pub struct QueryPool<B: hal::Backend> {
+    pub(crate) raw: B::QueryPool,
+    pub(crate) id: Id,
+}
+
+/// Collects the items of an iterator of borrowed objects, so that references
+/// to their inner objects can be handed to the inner backend.
+pub(crate) fn collect<I>(iter: I) -> Vec<I::Item>
+where
+    I: IntoIterator,
+{
+    iter.into_iter().collect()
+}
+
+pub(crate) fn raw_entry<'a, B: hal::Backend>(
+    entry: &pso::EntryPoint<'a, crate::Backend<B>>,
+) -> pso::EntryPoint<'a, B> {
+    pso::EntryPoint {
+        entry: entry.entry,
+        module: &entry.module.raw,
+        specialization: entry.specialization.clone(),
+    }
+}
+
+pub(crate) fn raw_subpass<'a, B: hal::Backend>(
+    subpass: &hal::pass::Subpass<'a, crate::Backend<B>>,
+) -> hal::pass::Subpass<'a, B> {
+    hal::pass::Subpass {
+        index: subpass.index,
+        main_pass: &subpass.main_pass.raw,
+    }
+}
+
+pub(crate) fn raw_barrier<'a, B: hal::Backend>(
+    barrier: &memory::Barrier<'a, crate::Backend<B>>,
+) -> memory::Barrier<'a, B> {
+    match *barrier {
+        memory::Barrier::AllBuffers(ref access) => memory::Barrier::AllBuffers(access.clone()),
+        memory::Barrier::AllImages(ref access) => memory::Barrier::AllImages(access.clone()),
+        memory::Barrier::Buffer {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => memory::Barrier::Buffer {
+            states: states.clone(),
+            target: &target.raw,
+            range: range.clone(),
+            families: families.clone(),
+        },
+        memory::Barrier::Image {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => memory::Barrier::Image {
+            states: states.clone(),
+            target: &target.raw,
+            range: range.clone(),
+            families: families.clone(),
+        },
+    }
+}
+
+pub(crate) fn trace_entry<B: hal::Backend>(
+    entry: &pso::EntryPoint<crate::Backend<B>>,
+) -> a::EntryPoint {
+    a::EntryPoint {
+        entry: entry.entry.to_string(),
+        module: entry.module.id,
+        specialization: a::Specialization {
+            constants: entry.specialization.constants.to_vec(),
+            data: entry.specialization.data.to_vec(),
+        },
+    }
+}
+
+pub(crate) fn trace_subpass<B: hal::Backend>(
+    subpass: &hal::pass::Subpass<crate::Backend<B>>,
+) -> a::Subpass {
+    a::Subpass {
+        index: subpass.index,
+        main_pass: subpass.main_pass.id,
+    }
+}
+
+pub(crate) fn trace_base<P>(
+    parent: &pso::BasePipeline<P>,
+    id: impl FnOnce(&P) -> Id,
+) -> a::BasePipeline {
+    match *parent {
+        pso::BasePipeline::Pipeline(pipeline) => a::BasePipeline::Pipeline(id(pipeline)),
+        pso::BasePipeline::Index(index) => a::BasePipeline::Index(index),
+        pso::BasePipeline::None => a::BasePipeline::None,
+    }
+}
+
+pub(crate) fn trace_barrier<B: hal::Backend>(
+    barrier: &memory::Barrier<crate::Backend<B>>,
+) -> a::Barrier {
+    match *barrier {
+        memory::Barrier::AllBuffers(ref access) => a::Barrier::AllBuffers(access.clone()),
+        memory::Barrier::AllImages(ref access) => a::Barrier::AllImages(access.clone()),
+        memory::Barrier::Buffer {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => a::Barrier::Buffer {
+            states: states.clone(),
+            target: target.id,
+            range: range.clone(),
+            families: families.clone(),
+        },
+        memory::Barrier::Image {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => a::Barrier::Image {
+            states: states.clone(),
+            target: target.id,
+            range: range.clone(),
+            families: families.clone(),
+        },
+    }
+}
+
+pub(crate) fn trace_descriptor<B: hal::Backend>(
+    descriptor: &pso::Descriptor<crate::Backend<B>>,
+) -> a::Descriptor {
+    match *descriptor {
+        pso::Descriptor::Sampler(sampler) => a::Descriptor::Sampler(sampler.id),
+        pso::Descriptor::Image(view, layout) => a::Descriptor::Image(view.id, layout),
+        pso::Descriptor::CombinedImageSampler(view, layout, sampler) => {
+            a::Descriptor::CombinedImageSampler(view.id, layout, sampler.id)
+        }
+        pso::Descriptor::Buffer(buffer, ref range) => {
+            a::Descriptor::Buffer(buffer.id, range.clone())
+        }
+        pso::Descriptor::TexelBuffer(view) => a::Descriptor::TexelBuffer(view.id),
+    }
+}
+
+pub(crate) fn trace_query<B: hal::Backend>(query: &query::Query<crate::Backend<B>>) -> a::Query {
+    a::Query {
+        pool: query.pool.id,
+        id: query.id,
+    }
+}
+
+pub(crate) fn trace_clear_value(value: &com::ClearValue) -> a::ClearValue {
+    // Every member of the union is made of 32-bit values.
+    a::ClearValue(unsafe { value.color.uint32 })
+}
+
+/// Returns the range of memory covered by `segment`.
+pub(crate) fn segment_range(segment: &memory::Segment, size: u64) -> Range<u64> {
+    segment.offset .. segment.size.map_or(size, |len| segment.offset + len)
+}
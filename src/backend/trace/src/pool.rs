@@ -0,0 +1,101 @@
+use std::sync::Arc;
+
+use hal::{command, pso};
+
+use crate::action::{Action, Id};
+use crate::command::CommandBuffer;
+use crate::native::{DescriptorSet, DescriptorSetLayout};
+use crate::recorder::Recorder;
+use crate::Backend;
+
+#[derive(Debug)]
+pub struct CommandPool<B: hal::Backend> {
+    pub(crate) raw: B::CommandPool,
+    pub(crate) id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> CommandPool<B> {
+    pub(crate) fn new(raw: B::CommandPool, id: Id, recorder: Arc<Recorder>) -> Self {
+        CommandPool { raw, id, recorder }
+    }
+}
+
+impl<B: hal::Backend> hal::pool::CommandPool<Backend<B>> for CommandPool<B> {
+    unsafe fn reset(&mut self, release_resources: bool) {
+        self.recorder.record(Action::ResetCommandPool {
+            pool: self.id,
+            release_resources,
+        });
+        self.raw.reset(release_resources)
+    }
+
+    unsafe fn allocate_one(&mut self, level: command::Level) -> CommandBuffer<B> {
+        let raw = self.raw.allocate_one(level);
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::AllocateCommandBuffer {
+            id,
+            pool: self.id,
+            level,
+        });
+        CommandBuffer::new(raw, id, Arc::clone(&self.recorder))
+    }
+
+    unsafe fn free<I>(&mut self, buffers: I)
+    where
+        I: IntoIterator<Item = CommandBuffer<B>>,
+    {
+        let buffers = buffers.into_iter().collect::<Vec<_>>();
+        self.recorder.record(Action::FreeCommandBuffers {
+            pool: self.id,
+            buffers: buffers.iter().map(|buffer| buffer.id).collect(),
+        });
+        self.raw.free(buffers.into_iter().map(|buffer| buffer.raw))
+    }
+}
+
+#[derive(Debug)]
+pub struct DescriptorPool<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorPool,
+    pub(crate) id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> DescriptorPool<B> {
+    pub(crate) fn new(raw: B::DescriptorPool, id: Id, recorder: Arc<Recorder>) -> Self {
+        DescriptorPool { raw, id, recorder }
+    }
+}
+
+impl<B: hal::Backend> pso::DescriptorPool<Backend<B>> for DescriptorPool<B> {
+    unsafe fn allocate_set(
+        &mut self,
+        layout: &DescriptorSetLayout<B>,
+    ) -> Result<DescriptorSet<B>, pso::AllocationError> {
+        let raw = self.raw.allocate_set(&layout.raw)?;
+        let id = self.recorder.new_id();
+        self.recorder.record(Action::AllocateDescriptorSet {
+            id,
+            pool: self.id,
+            layout: layout.id,
+        });
+        Ok(DescriptorSet { raw, id })
+    }
+
+    unsafe fn free<I>(&mut self, descriptor_sets: I)
+    where
+        I: IntoIterator<Item = DescriptorSet<B>>,
+    {
+        let sets = descriptor_sets.into_iter().collect::<Vec<_>>();
+        self.recorder.record(Action::FreeDescriptorSets {
+            pool: self.id,
+            sets: sets.iter().map(|set| set.id).collect(),
+        });
+        self.raw.free(sets.into_iter().map(|set| set.raw))
+    }
+
+    unsafe fn reset(&mut self) {
+        self.recorder.record(Action::ResetDescriptorPool(self.id));
+        self.raw.reset()
+    }
+}
@@ -0,0 +1,146 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+use hal::{device, pso, queue, window};
+
+use crate::{
+    action::{Action, Id},
+    command::CommandBuffer,
+    native::{collect, Fence, Semaphore},
+    recorder::Recorder,
+    Backend,
+    Surface,
+    Swapchain,
+    SwapchainImage,
+};
+
+#[derive(Debug)]
+pub struct CommandQueue<B: hal::Backend> {
+    raw: B::CommandQueue,
+    id: Id,
+    recorder: Arc<Recorder>,
+}
+
+impl<B: hal::Backend> CommandQueue<B> {
+    pub(crate) fn new(raw: B::CommandQueue, id: Id, recorder: Arc<Recorder>) -> Self {
+        CommandQueue { raw, id, recorder }
+    }
+
+    pub fn raw(&self) -> &B::CommandQueue {
+        &self.raw
+    }
+}
+
+impl<B: hal::Backend> queue::CommandQueue<Backend<B>> for CommandQueue<B> {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        submission: queue::Submission<Ic, Iw, Is>,
+        fence: Option<&Fence<B>>,
+    ) where
+        T: 'a + Borrow<CommandBuffer<B>>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<Semaphore<B>>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        let command_buffers = submission
+            .command_buffers
+            .into_iter()
+            .map(|cmd_buffer| cmd_buffer.borrow())
+            .collect::<Vec<_>>();
+        let wait_semaphores = submission
+            .wait_semaphores
+            .into_iter()
+            .map(|(semaphore, stage)| (semaphore.borrow(), stage))
+            .collect::<Vec<_>>();
+        let signal_semaphores = submission
+            .signal_semaphores
+            .into_iter()
+            .map(|semaphore| semaphore.borrow())
+            .collect::<Vec<_>>();
+
+        // Coherent memory may have been written without any flush.
+        self.recorder.capture_all();
+        self.recorder.record(Action::Submit {
+            queue: self.id,
+            command_buffers: command_buffers
+                .iter()
+                .map(|cmd_buffer| cmd_buffer.id)
+                .collect(),
+            wait_semaphores: wait_semaphores
+                .iter()
+                .map(|&(semaphore, stage)| (semaphore.id, stage))
+                .collect(),
+            signal_semaphores: signal_semaphores
+                .iter()
+                .map(|semaphore| semaphore.id)
+                .collect(),
+            fence: fence.map(|fence| fence.id),
+        });
+        self.recorder.flush();
+
+        let raw_submission = queue::Submission {
+            command_buffers: command_buffers.iter().map(|cmd_buffer| &cmd_buffer.raw),
+            wait_semaphores: wait_semaphores
+                .iter()
+                .map(|&(semaphore, stage)| (&semaphore.raw, stage)),
+            signal_semaphores: signal_semaphores.iter().map(|semaphore| &semaphore.raw),
+        };
+        self.raw
+            .submit(raw_submission, fence.map(|fence| &fence.raw))
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        swapchains: Is,
+        wait_semaphores: Iw,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError>
+    where
+        W: 'a + Borrow<Swapchain<B>>,
+        Is: IntoIterator<Item = (&'a W, window::SwapImageIndex)>,
+        S: 'a + Borrow<Semaphore<B>>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        let swapchains = collect(swapchains);
+        let wait_semaphores = wait_semaphores
+            .into_iter()
+            .map(|semaphore| semaphore.borrow())
+            .collect::<Vec<_>>();
+        self.recorder.record(Action::Present {
+            queue: self.id,
+            wait_semaphores: wait_semaphores
+                .iter()
+                .map(|semaphore| semaphore.id)
+                .collect(),
+        });
+        self.raw.present(
+            swapchains
+                .iter()
+                .map(|&(swapchain, index)| (&swapchain.borrow().raw, index)),
+            wait_semaphores.iter().map(|semaphore| &semaphore.raw),
+        )
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        surface: &mut Surface<B>,
+        image: SwapchainImage<B>,
+        wait_semaphore: Option<&Semaphore<B>>,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError> {
+        self.recorder.record(Action::PresentSurface {
+            queue: self.id,
+            view: image.view.id,
+            wait_semaphore: wait_semaphore.map(|semaphore| semaphore.id),
+        });
+        self.raw.present_surface(
+            &mut surface.raw,
+            image.into_raw(),
+            wait_semaphore.map(|semaphore| &semaphore.raw),
+        )
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        self.recorder.record(Action::QueueWaitIdle(self.id));
+        self.raw.wait_idle()
+    }
+}
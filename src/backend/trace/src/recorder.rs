@@ -0,0 +1,171 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::hash::{Hash, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::slice;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use parking_lot::Mutex;
+
+use crate::action::{Action, DataFile, Id};
+
+/// Name of the file listing the actions, inside of the trace directory.
+pub const TRACE_FILE: &str = "trace.ron";
+
+/// Host mapping of a memory object.
+#[derive(Debug)]
+struct Mapping {
+    /// Address of the mapped range.
+    ptr: usize,
+    range: Range<u64>,
+    /// Hash of the last contents written to the trace.
+    last_write: Option<u64>,
+}
+
+/// Writes the actions of a trace and the data they refer to.
+#[derive(Debug)]
+pub(crate) struct Recorder {
+    dir: PathBuf,
+    file: Mutex<BufWriter<File>>,
+    next_id: AtomicU64,
+    next_data: AtomicU64,
+    mappings: Mutex<HashMap<Id, Mapping>>,
+}
+
+impl Recorder {
+    pub(crate) fn create(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let file = File::create(dir.join(TRACE_FILE))?;
+        Ok(Recorder {
+            dir: dir.to_path_buf(),
+            file: Mutex::new(BufWriter::new(file)),
+            next_id: AtomicU64::new(0),
+            next_data: AtomicU64::new(0),
+            mappings: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub(crate) fn new_id(&self) -> Id {
+        Id(self.next_id.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// Appends an action to the trace, one per line.
+    pub(crate) fn record(&self, action: Action) {
+        let result = ron::ser::to_string(&action)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+            .and_then(|line| writeln!(self.file.lock(), "{}", line));
+        if let Err(e) = result {
+            error!("Failed to record {:?}: {}", action, e);
+        }
+    }
+
+    /// Writes the buffered actions to the trace file, so that they survive
+    /// a crash of the application.
+    pub(crate) fn flush(&self) {
+        if let Err(e) = self.file.lock().flush() {
+            error!("Failed to flush the trace: {}", e);
+        }
+    }
+
+    /// Stores `data` in a new file next to the trace.
+    pub(crate) fn write_data(&self, data: &[u8]) -> DataFile {
+        let name = format!("data{}.bin", self.next_data.fetch_add(1, Ordering::Relaxed));
+        if let Err(e) = fs::write(self.dir.join(&name), data) {
+            error!("Failed to write {}: {}", name, e);
+        }
+        name
+    }
+
+    pub(crate) fn map(&self, memory: Id, ptr: *mut u8, range: Range<u64>) {
+        self.mappings.lock().insert(
+            memory,
+            Mapping {
+                ptr: ptr as usize,
+                range,
+                last_write: None,
+            },
+        );
+    }
+
+    /// Records the contents of the mapped part of `range`.
+    pub(crate) unsafe fn capture(&self, memory: Id, range: Range<u64>) {
+        if let Some(mapping) = self.mappings.lock().get_mut(&memory) {
+            self.capture_mapping(memory, mapping, range);
+        }
+    }
+
+    /// Records the contents of the mapping of `memory` and forgets about it.
+    pub(crate) unsafe fn unmap(&self, memory: Id) {
+        if let Some(mut mapping) = self.mappings.lock().remove(&memory) {
+            let range = mapping.range.clone();
+            self.capture_mapping(memory, &mut mapping, range);
+        }
+    }
+
+    /// Forgets about the mapping of `memory`, which is getting freed.
+    pub(crate) fn forget(&self, memory: Id) {
+        self.mappings.lock().remove(&memory);
+    }
+
+    /// Records the contents of all mapped memory, which the host may have
+    /// written without flushing if the memory is coherent.
+    pub(crate) unsafe fn capture_all(&self) {
+        let mut mappings = self.mappings.lock();
+        let mut ids = mappings.keys().cloned().collect::<Vec<_>>();
+        ids.sort();
+        for memory in ids {
+            let mapping = mappings.get_mut(&memory).unwrap();
+            let range = mapping.range.clone();
+            self.capture_mapping(memory, mapping, range);
+        }
+    }
+
+    unsafe fn capture_mapping(&self, memory: Id, mapping: &mut Mapping, range: Range<u64>) {
+        let start = range.start.max(mapping.range.start);
+        let end = range.end.min(mapping.range.end);
+        if start >= end {
+            return;
+        }
+        let ptr = (mapping.ptr + (start - mapping.range.start) as usize) as *const u8;
+        let data = slice::from_raw_parts(ptr, (end - start) as usize);
+
+        // Skip the contents already in the trace, which is common for
+        // persistently mapped memory captured at every submission.
+        let mut hasher = DefaultHasher::new();
+        start.hash(&mut hasher);
+        data.hash(&mut hasher);
+        let hash = hasher.finish();
+        if mapping.last_write == Some(hash) {
+            return;
+        }
+        mapping.last_write = Some(hash);
+
+        let data = self.write_data(data);
+        self.record(Action::WriteMemory {
+            memory,
+            offset: start,
+            data,
+        });
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// Reads the actions of the trace stored in `dir`.
+pub fn read_trace(dir: &Path) -> io::Result<Vec<Action>> {
+    let file = File::open(dir.join(TRACE_FILE))?;
+    BufReader::new(file)
+        .lines()
+        .map(|line| {
+            ron::de::from_str(&line?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+        })
+        .collect()
+}
@@ -0,0 +1,1474 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::iter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::{fs, io, ptr};
+
+use hal::{
+    adapter,
+    command as com,
+    device::Device as _,
+    format,
+    image,
+    memory,
+    pass,
+    pool::CommandPool as _,
+    pso::{self, DescriptorPool as _},
+    query,
+    queue::{self, CommandQueue as _, QueueFamily as _},
+    window,
+    MemoryTypeId,
+};
+
+use crate::action::{self as a, Action, Command, Id};
+use crate::recorder::read_trace;
+
+type Objects<T> = HashMap<Id, T>;
+
+fn get<T>(objects: &Objects<T>, id: Id) -> &T {
+    objects
+        .get(&id)
+        .unwrap_or_else(|| panic!("Unknown object {:?}", id))
+}
+
+fn get_mut<T>(objects: &mut Objects<T>, id: Id) -> &mut T {
+    objects
+        .get_mut(&id)
+        .unwrap_or_else(|| panic!("Unknown object {:?}", id))
+}
+
+/// Offscreen image standing in for a swapchain or surface image.
+#[derive(Debug)]
+struct Offscreen<B: hal::Backend> {
+    image: B::Image,
+    memory: B::Memory,
+    /// View of the image handed out when acquiring a surface image.
+    view: B::ImageView,
+}
+
+/// Replays a trace on a device of the backend `B`.
+///
+/// Swapchains and presentation surfaces are replaced with offscreen images,
+/// and presentation only waits for the semaphores it was given. Errors of
+/// the backend are treated as fatal.
+#[derive(Debug)]
+pub struct Replayer<B: hal::Backend> {
+    dir: PathBuf,
+    device: B::Device,
+    memory_types: Vec<adapter::MemoryType>,
+    /// Queue families of the adapter, by the family they replace.
+    families: HashMap<queue::QueueFamilyId, queue::QueueFamilyId>,
+    queues: Vec<B::CommandQueue>,
+    /// Index into `queues` of each recorded queue, as the adapter may
+    /// provide less of them.
+    queue_indices: Objects<usize>,
+    memories: Objects<B::Memory>,
+    command_pools: Objects<B::CommandPool>,
+    /// Command buffers, along with the pool they got allocated from.
+    command_buffers: Objects<(Id, B::CommandBuffer)>,
+    render_passes: Objects<B::RenderPass>,
+    pipeline_layouts: Objects<B::PipelineLayout>,
+    pipeline_caches: Objects<B::PipelineCache>,
+    graphics_pipelines: Objects<B::GraphicsPipeline>,
+    compute_pipelines: Objects<B::ComputePipeline>,
+    framebuffers: Objects<B::Framebuffer>,
+    shader_modules: Objects<B::ShaderModule>,
+    buffers: Objects<B::Buffer>,
+    buffer_views: Objects<B::BufferView>,
+    images: Objects<B::Image>,
+    image_views: Objects<B::ImageView>,
+    samplers: Objects<B::Sampler>,
+    descriptor_pools: Objects<B::DescriptorPool>,
+    /// Descriptor sets, along with the pool they got allocated from.
+    descriptor_sets: Objects<(Id, B::DescriptorSet)>,
+    descriptor_set_layouts: Objects<B::DescriptorSetLayout>,
+    semaphores: Objects<B::Semaphore>,
+    fences: Objects<B::Fence>,
+    events: Objects<B::Event>,
+    query_pools: Objects<B::QueryPool>,
+    /// Images of each swapchain, which are stored in `images` with their
+    /// memory in `swapchain_memories`.
+    swapchains: Objects<Vec<Id>>,
+    swapchain_memories: Objects<B::Memory>,
+    surfaces: Objects<Offscreen<B>>,
+    /// Surface of each acquired surface image view.
+    surface_views: Objects<Id>,
+}
+
+impl<B: hal::Backend> Replayer<B> {
+    /// Opens a device on `adapter` with the queues requested by `open`,
+    /// for replaying the trace stored in `dir`.
+    ///
+    /// # Safety
+    ///
+    /// The trace must have been captured from a valid usage of the API.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `open` isn't an `Action::Open`, or if the adapter lacks a
+    /// compatible queue family for one of the recorded families.
+    pub unsafe fn new(adapter: &adapter::Adapter<B>, dir: impl AsRef<Path>, open: &Action) -> Self {
+        let (recorded_families, features) = match *open {
+            Action::Open {
+                ref families,
+                features,
+            } => (families, features),
+            ref other => panic!(
+                "Trace starting with {:?} instead of opening a device",
+                other
+            ),
+        };
+
+        // Pick an unused family for each of the recorded ones, preferring
+        // families of the same type.
+        let mut chosen = Vec::<&B::QueueFamily>::new();
+        for recorded in recorded_families {
+            let unused = |family: &&B::QueueFamily| chosen.iter().all(|c| c.id() != family.id());
+            let family = adapter
+                .queue_families
+                .iter()
+                .filter(unused)
+                .find(|family| family.queue_type() == recorded.ty)
+                .or_else(|| {
+                    adapter.queue_families.iter().filter(unused).find(|family| {
+                        let ty = family.queue_type();
+                        (ty.supports_graphics() || !recorded.ty.supports_graphics())
+                            && (ty.supports_compute() || !recorded.ty.supports_compute())
+                    })
+                })
+                .unwrap_or_else(|| panic!("No queue family compatible with {:?}", recorded.ty));
+            chosen.push(family);
+        }
+
+        let priorities = recorded_families
+            .iter()
+            .zip(&chosen)
+            .map(|(recorded, family)| {
+                let count = recorded.queues.len().min(family.max_queues());
+                recorded.queues[.. count]
+                    .iter()
+                    .map(|&(_, priority)| priority)
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        let requests = chosen
+            .iter()
+            .zip(&priorities)
+            .map(|(&family, priorities)| (family, priorities.as_slice()))
+            .collect::<Vec<_>>();
+        let gpu = adapter::PhysicalDevice::open(&adapter.physical_device, &requests, features)
+            .expect("Failed to open the device");
+
+        let mut families = HashMap::new();
+        let mut queues = Vec::new();
+        let mut queue_indices = HashMap::new();
+        let mut queue_groups = gpu.queue_groups;
+        for (recorded, family) in recorded_families.iter().zip(&chosen) {
+            families.insert(recorded.id, family.id());
+            let group = queue_groups
+                .iter_mut()
+                .find(|group| group.family == family.id())
+                .expect("Missing queue group");
+            let first = queues.len();
+            queues.append(&mut group.queues);
+            // Queues the adapter couldn't provide share its last one.
+            for (i, &(id, _)) in recorded.queues.iter().enumerate() {
+                queue_indices.insert(id, (first + i).min(queues.len() - 1));
+            }
+        }
+
+        Replayer {
+            dir: dir.as_ref().to_path_buf(),
+            device: gpu.device,
+            memory_types: adapter::PhysicalDevice::memory_properties(&adapter.physical_device)
+                .memory_types,
+            families,
+            queues,
+            queue_indices,
+            memories: HashMap::new(),
+            command_pools: HashMap::new(),
+            command_buffers: HashMap::new(),
+            render_passes: HashMap::new(),
+            pipeline_layouts: HashMap::new(),
+            pipeline_caches: HashMap::new(),
+            graphics_pipelines: HashMap::new(),
+            compute_pipelines: HashMap::new(),
+            framebuffers: HashMap::new(),
+            shader_modules: HashMap::new(),
+            buffers: HashMap::new(),
+            buffer_views: HashMap::new(),
+            images: HashMap::new(),
+            image_views: HashMap::new(),
+            samplers: HashMap::new(),
+            descriptor_pools: HashMap::new(),
+            descriptor_sets: HashMap::new(),
+            descriptor_set_layouts: HashMap::new(),
+            semaphores: HashMap::new(),
+            fences: HashMap::new(),
+            events: HashMap::new(),
+            query_pools: HashMap::new(),
+            swapchains: HashMap::new(),
+            swapchain_memories: HashMap::new(),
+            surfaces: HashMap::new(),
+            surface_views: HashMap::new(),
+        }
+    }
+
+    /// Replays the whole trace stored in `dir` on `adapter`.
+    ///
+    /// # Safety
+    ///
+    /// See [`Replayer::new`](#method.new).
+    pub unsafe fn from_trace(
+        adapter: &adapter::Adapter<B>,
+        dir: impl AsRef<Path>,
+    ) -> io::Result<Self> {
+        let mut actions = read_trace(dir.as_ref())?.into_iter();
+        let open = actions
+            .next()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "The trace is empty"))?;
+        let mut replayer = Replayer::new(adapter, dir, &open);
+        for action in actions {
+            replayer.replay(action);
+        }
+        Ok(replayer)
+    }
+
+    pub fn device(&self) -> &B::Device {
+        &self.device
+    }
+
+    /// Returns the replayed memory object recorded as `id`.
+    pub fn memory(&self, id: Id) -> Option<&B::Memory> {
+        self.memories.get(&id)
+    }
+
+    /// Returns the replayed buffer recorded as `id`.
+    pub fn buffer(&self, id: Id) -> Option<&B::Buffer> {
+        self.buffers.get(&id)
+    }
+
+    /// Returns the replayed image recorded as `id`, which may be one of the
+    /// offscreen images replacing a swapchain.
+    pub fn image(&self, id: Id) -> Option<&B::Image> {
+        self.images.get(&id)
+    }
+
+    fn read_data(&self, name: &str) -> Vec<u8> {
+        let path = self.dir.join(name);
+        fs::read(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+    }
+
+    /// Returns the memory type to use for an allocation recorded with the
+    /// given type and properties.
+    fn memory_type(&self, recorded: MemoryTypeId, properties: memory::Properties) -> MemoryTypeId {
+        match self.memory_types.get(recorded.0) {
+            Some(ty) if ty.properties.contains(properties) => recorded,
+            _ => self
+                .memory_types
+                .iter()
+                .position(|ty| ty.properties.contains(properties))
+                .map(MemoryTypeId)
+                .unwrap_or_else(|| panic!("No memory type with {:?}", properties)),
+        }
+    }
+
+    fn image_view(&self, id: Id) -> &B::ImageView {
+        match self.surface_views.get(&id) {
+            Some(surface) => &get(&self.surfaces, *surface).view,
+            None => get(&self.image_views, id),
+        }
+    }
+
+    unsafe fn create_offscreen(
+        &self,
+        format: format::Format,
+        extent: window::Extent2D,
+        usage: image::Usage,
+    ) -> (B::Image, B::Memory) {
+        let mut image = self
+            .device
+            .create_image(
+                image::Kind::D2(extent.width, extent.height, 1, 1),
+                1,
+                format,
+                image::Tiling::Optimal,
+                usage,
+                image::ViewCapabilities::empty(),
+            )
+            .expect("Failed to create an offscreen image");
+        let requirements = self.device.get_image_requirements(&image);
+        let memory_type = self
+            .memory_types
+            .iter()
+            .enumerate()
+            .position(|(id, ty)| {
+                requirements.type_mask & (1 << id) != 0
+                    && ty.properties.contains(memory::Properties::DEVICE_LOCAL)
+            })
+            .map(MemoryTypeId)
+            .expect("No device local memory for an offscreen image");
+        let memory = self
+            .device
+            .allocate_memory(memory_type, requirements.size)
+            .expect("Failed to allocate an offscreen image");
+        self.device
+            .bind_image_memory(&memory, 0, &mut image)
+            .expect("Failed to bind an offscreen image");
+        (image, memory)
+    }
+
+    unsafe fn destroy_surface_images(&mut self, surface: Id) {
+        self.surface_views.retain(|_, s| *s != surface);
+        if let Some(offscreen) = self.surfaces.remove(&surface) {
+            self.device.destroy_image_view(offscreen.view);
+            self.device.destroy_image(offscreen.image);
+            self.device.free_memory(offscreen.memory);
+        }
+    }
+
+    /// Submits no work to the queue at `index`, only to wait for and signal
+    /// semaphores and a fence.
+    unsafe fn submit_empty(
+        &mut self,
+        index: usize,
+        wait_semaphores: &[Id],
+        signal_semaphores: &[Id],
+        fence: Option<Id>,
+    ) {
+        let (semaphores, fences) = (&self.semaphores, &self.fences);
+        self.queues[index].submit(
+            queue::Submission {
+                command_buffers: iter::empty::<&B::CommandBuffer>(),
+                wait_semaphores: wait_semaphores
+                    .iter()
+                    .map(|&id| (get(semaphores, id), pso::PipelineStage::BOTTOM_OF_PIPE)),
+                signal_semaphores: signal_semaphores.iter().map(|&id| get(semaphores, id)),
+            },
+            fence.map(|id| get(fences, id)),
+        )
+    }
+
+    fn family_range(
+        &self,
+        families: &Option<Range<queue::QueueFamilyId>>,
+    ) -> Option<Range<queue::QueueFamilyId>> {
+        families
+            .as_ref()
+            .map(|range| self.families[&range.start] .. self.families[&range.end])
+    }
+
+    fn barrier(&self, barrier: &a::Barrier) -> memory::Barrier<'_, B> {
+        match *barrier {
+            a::Barrier::AllBuffers(ref access) => memory::Barrier::AllBuffers(access.clone()),
+            a::Barrier::AllImages(ref access) => memory::Barrier::AllImages(access.clone()),
+            a::Barrier::Buffer {
+                ref states,
+                target,
+                ref range,
+                ref families,
+            } => memory::Barrier::Buffer {
+                states: states.clone(),
+                target: get(&self.buffers, target),
+                range: range.clone(),
+                families: self.family_range(families),
+            },
+            a::Barrier::Image {
+                ref states,
+                target,
+                ref range,
+                ref families,
+            } => memory::Barrier::Image {
+                states: states.clone(),
+                target: get(&self.images, target),
+                range: range.clone(),
+                families: self.family_range(families),
+            },
+        }
+    }
+
+    fn descriptor(&self, descriptor: &a::Descriptor) -> pso::Descriptor<'_, B> {
+        match *descriptor {
+            a::Descriptor::Sampler(sampler) => {
+                pso::Descriptor::Sampler(get(&self.samplers, sampler))
+            }
+            a::Descriptor::Image(view, layout) => {
+                pso::Descriptor::Image(self.image_view(view), layout)
+            }
+            a::Descriptor::CombinedImageSampler(view, layout, sampler) => {
+                pso::Descriptor::CombinedImageSampler(
+                    self.image_view(view),
+                    layout,
+                    get(&self.samplers, sampler),
+                )
+            }
+            a::Descriptor::Buffer(buffer, ref range) => {
+                pso::Descriptor::Buffer(get(&self.buffers, buffer), range.clone())
+            }
+            a::Descriptor::TexelBuffer(view) => {
+                pso::Descriptor::TexelBuffer(get(&self.buffer_views, view))
+            }
+        }
+    }
+
+    fn entry<'a>(&'a self, entry: &'a a::EntryPoint) -> pso::EntryPoint<'a, B> {
+        pso::EntryPoint {
+            entry: &entry.entry,
+            module: get(&self.shader_modules, entry.module),
+            specialization: pso::Specialization {
+                constants: Cow::Borrowed(&entry.specialization.constants),
+                data: Cow::Borrowed(&entry.specialization.data),
+            },
+        }
+    }
+
+    fn subpass(&self, subpass: a::Subpass) -> pass::Subpass<'_, B> {
+        pass::Subpass {
+            index: subpass.index,
+            main_pass: get(&self.render_passes, subpass.main_pass),
+        }
+    }
+
+    fn query(&self, query: a::Query) -> query::Query<'_, B> {
+        query::Query {
+            pool: get(&self.query_pools, query.pool),
+            id: query.id,
+        }
+    }
+
+    /// Replays a single action of the trace.
+    ///
+    /// # Safety
+    ///
+    /// The action must follow the ones replayed before in a valid trace.
+    pub unsafe fn replay(&mut self, action: Action) {
+        match action {
+            Action::Open { .. } => panic!("Device opened twice"),
+            Action::AllocateMemory {
+                id,
+                memory_type,
+                properties,
+                size,
+            } => {
+                let memory_type = self.memory_type(memory_type, properties);
+                let memory = self
+                    .device
+                    .allocate_memory(memory_type, size)
+                    .expect("Failed to allocate memory");
+                self.memories.insert(id, memory);
+            }
+            Action::FreeMemory(id) => {
+                if let Some(memory) = self.memories.remove(&id) {
+                    self.device.free_memory(memory);
+                }
+            }
+            Action::WriteMemory {
+                memory,
+                offset,
+                data,
+            } => {
+                let data = self.read_data(&data);
+                let memory = get(&self.memories, memory);
+                let segment = memory::Segment {
+                    offset,
+                    size: Some(data.len() as u64),
+                };
+                let ptr = self
+                    .device
+                    .map_memory(memory, segment.clone())
+                    .expect("Failed to map memory");
+                ptr::copy_nonoverlapping(data.as_ptr(), ptr, data.len());
+                self.device
+                    .flush_mapped_memory_ranges(iter::once((memory, segment)))
+                    .expect("Failed to flush memory");
+                self.device.unmap_memory(memory);
+            }
+            Action::CreateCommandPool { id, family, flags } => {
+                let pool = self
+                    .device
+                    .create_command_pool(self.families[&family], flags)
+                    .expect("Failed to create a command pool");
+                self.command_pools.insert(id, pool);
+            }
+            Action::ResetCommandPool {
+                pool,
+                release_resources,
+            } => get_mut(&mut self.command_pools, pool).reset(release_resources),
+            Action::DestroyCommandPool(id) => {
+                self.command_buffers.retain(|_, &mut (pool, _)| pool != id);
+                if let Some(pool) = self.command_pools.remove(&id) {
+                    self.device.destroy_command_pool(pool);
+                }
+            }
+            Action::AllocateCommandBuffer { id, pool, level } => {
+                let buffer = get_mut(&mut self.command_pools, pool).allocate_one(level);
+                self.command_buffers.insert(id, (pool, buffer));
+            }
+            Action::FreeCommandBuffers { pool, buffers } => {
+                let command_buffers = &mut self.command_buffers;
+                let buffers = buffers
+                    .iter()
+                    .filter_map(|id| command_buffers.remove(id))
+                    .map(|(_, buffer)| buffer);
+                get_mut(&mut self.command_pools, pool).free(buffers);
+            }
+            Action::Command(id, command) => {
+                let (pool, mut buffer) = self
+                    .command_buffers
+                    .remove(&id)
+                    .unwrap_or_else(|| panic!("Unknown command buffer {:?}", id));
+                self.replay_command(&mut buffer, command);
+                self.command_buffers.insert(id, (pool, buffer));
+            }
+            Action::CreateRenderPass {
+                id,
+                attachments,
+                subpasses,
+                dependencies,
+            } => {
+                let subpasses = subpasses.iter().map(|subpass| pass::SubpassDesc {
+                    colors: &subpass.colors,
+                    depth_stencil: subpass.depth_stencil.as_ref(),
+                    inputs: &subpass.inputs,
+                    resolves: &subpass.resolves,
+                    preserves: &subpass.preserves,
+                });
+                let render_pass = self
+                    .device
+                    .create_render_pass(attachments, subpasses, dependencies)
+                    .expect("Failed to create a render pass");
+                self.render_passes.insert(id, render_pass);
+            }
+            Action::DestroyRenderPass(id) => {
+                if let Some(render_pass) = self.render_passes.remove(&id) {
+                    self.device.destroy_render_pass(render_pass);
+                }
+            }
+            Action::CreatePipelineLayout {
+                id,
+                set_layouts,
+                push_constants,
+            } => {
+                let layout = self
+                    .device
+                    .create_pipeline_layout(
+                        set_layouts
+                            .iter()
+                            .map(|&id| get(&self.descriptor_set_layouts, id)),
+                        push_constants,
+                    )
+                    .expect("Failed to create a pipeline layout");
+                self.pipeline_layouts.insert(id, layout);
+            }
+            Action::DestroyPipelineLayout(id) => {
+                if let Some(layout) = self.pipeline_layouts.remove(&id) {
+                    self.device.destroy_pipeline_layout(layout);
+                }
+            }
+            Action::CreatePipelineCache(id) => {
+                let cache = self
+                    .device
+                    .create_pipeline_cache(None)
+                    .expect("Failed to create a pipeline cache");
+                self.pipeline_caches.insert(id, cache);
+            }
+            Action::MergePipelineCaches { target, sources } => {
+                self.device
+                    .merge_pipeline_caches(
+                        get(&self.pipeline_caches, target),
+                        sources.iter().map(|&id| get(&self.pipeline_caches, id)),
+                    )
+                    .expect("Failed to merge pipeline caches");
+            }
+            Action::DestroyPipelineCache(id) => {
+                if let Some(cache) = self.pipeline_caches.remove(&id) {
+                    self.device.destroy_pipeline_cache(cache);
+                }
+            }
+            Action::CreateGraphicsPipeline { id, desc, cache } => {
+                let shaders = &desc.shaders;
+                let raw_desc = pso::GraphicsPipelineDesc {
+                    shaders: pso::GraphicsShaderSet {
+                        vertex: self.entry(&shaders.vertex),
+                        hull: shaders.hull.as_ref().map(|entry| self.entry(entry)),
+                        domain: shaders.domain.as_ref().map(|entry| self.entry(entry)),
+                        geometry: shaders.geometry.as_ref().map(|entry| self.entry(entry)),
+                        fragment: shaders.fragment.as_ref().map(|entry| self.entry(entry)),
+                    },
+                    rasterizer: desc.rasterizer,
+                    vertex_buffers: desc.vertex_buffers.clone(),
+                    attributes: desc.attributes.clone(),
+                    input_assembler: desc.input_assembler.clone(),
+                    blender: desc.blender.clone(),
+                    depth_stencil: desc.depth_stencil,
+                    multisampling: desc.multisampling.clone(),
+                    baked_states: desc.baked_states.clone(),
+                    layout: get(&self.pipeline_layouts, desc.layout),
+                    subpass: self.subpass(desc.subpass),
+                    flags: desc.flags,
+                    parent: match desc.parent {
+                        a::BasePipeline::Pipeline(id) => {
+                            pso::BasePipeline::Pipeline(get(&self.graphics_pipelines, id))
+                        }
+                        a::BasePipeline::Index(index) => pso::BasePipeline::Index(index),
+                        a::BasePipeline::None => pso::BasePipeline::None,
+                    },
+                };
+                let pipeline = self
+                    .device
+                    .create_graphics_pipeline(
+                        &raw_desc,
+                        cache.map(|id| get(&self.pipeline_caches, id)),
+                    )
+                    .expect("Failed to create a graphics pipeline");
+                self.graphics_pipelines.insert(id, pipeline);
+            }
+            Action::DestroyGraphicsPipeline(id) => {
+                if let Some(pipeline) = self.graphics_pipelines.remove(&id) {
+                    self.device.destroy_graphics_pipeline(pipeline);
+                }
+            }
+            Action::CreateComputePipeline { id, desc, cache } => {
+                let raw_desc = pso::ComputePipelineDesc {
+                    shader: self.entry(&desc.shader),
+                    layout: get(&self.pipeline_layouts, desc.layout),
+                    flags: desc.flags,
+                    parent: match desc.parent {
+                        a::BasePipeline::Pipeline(id) => {
+                            pso::BasePipeline::Pipeline(get(&self.compute_pipelines, id))
+                        }
+                        a::BasePipeline::Index(index) => pso::BasePipeline::Index(index),
+                        a::BasePipeline::None => pso::BasePipeline::None,
+                    },
+                };
+                let pipeline = self
+                    .device
+                    .create_compute_pipeline(
+                        &raw_desc,
+                        cache.map(|id| get(&self.pipeline_caches, id)),
+                    )
+                    .expect("Failed to create a compute pipeline");
+                self.compute_pipelines.insert(id, pipeline);
+            }
+            Action::DestroyComputePipeline(id) => {
+                if let Some(pipeline) = self.compute_pipelines.remove(&id) {
+                    self.device.destroy_compute_pipeline(pipeline);
+                }
+            }
+            Action::CreateFramebuffer {
+                id,
+                pass,
+                attachments,
+                extent,
+            } => {
+                let framebuffer = self
+                    .device
+                    .create_framebuffer(
+                        get(&self.render_passes, pass),
+                        attachments.iter().map(|&id| self.image_view(id)),
+                        extent,
+                    )
+                    .expect("Failed to create a framebuffer");
+                self.framebuffers.insert(id, framebuffer);
+            }
+            Action::DestroyFramebuffer(id) => {
+                if let Some(framebuffer) = self.framebuffers.remove(&id) {
+                    self.device.destroy_framebuffer(framebuffer);
+                }
+            }
+            Action::CreateShaderModule { id, data } => {
+                let spirv = self
+                    .read_data(&data)
+                    .chunks(4)
+                    .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+                    .collect::<Vec<_>>();
+                let module = self
+                    .device
+                    .create_shader_module(&spirv)
+                    .expect("Failed to create a shader module");
+                self.shader_modules.insert(id, module);
+            }
+            Action::DestroyShaderModule(id) => {
+                if let Some(module) = self.shader_modules.remove(&id) {
+                    self.device.destroy_shader_module(module);
+                }
+            }
+            Action::CreateBuffer { id, size, usage } => {
+                let buffer = self
+                    .device
+                    .create_buffer(size, usage)
+                    .expect("Failed to create a buffer");
+                self.buffers.insert(id, buffer);
+            }
+            Action::BindBufferMemory {
+                buffer,
+                memory,
+                offset,
+            } => {
+                self.device
+                    .bind_buffer_memory(
+                        get(&self.memories, memory),
+                        offset,
+                        get_mut(&mut self.buffers, buffer),
+                    )
+                    .expect("Failed to bind buffer memory");
+            }
+            Action::DestroyBuffer(id) => {
+                if let Some(buffer) = self.buffers.remove(&id) {
+                    self.device.destroy_buffer(buffer);
+                }
+            }
+            Action::CreateBufferView {
+                id,
+                buffer,
+                format,
+                range,
+            } => {
+                let view = self
+                    .device
+                    .create_buffer_view(get(&self.buffers, buffer), format, range)
+                    .expect("Failed to create a buffer view");
+                self.buffer_views.insert(id, view);
+            }
+            Action::DestroyBufferView(id) => {
+                if let Some(view) = self.buffer_views.remove(&id) {
+                    self.device.destroy_buffer_view(view);
+                }
+            }
+            Action::CreateImage {
+                id,
+                kind,
+                mip_levels,
+                format,
+                tiling,
+                usage,
+                view_caps,
+            } => {
+                let image = self
+                    .device
+                    .create_image(kind, mip_levels, format, tiling, usage, view_caps)
+                    .expect("Failed to create an image");
+                self.images.insert(id, image);
+            }
+            Action::BindImageMemory {
+                image,
+                memory,
+                offset,
+            } => {
+                self.device
+                    .bind_image_memory(
+                        get(&self.memories, memory),
+                        offset,
+                        get_mut(&mut self.images, image),
+                    )
+                    .expect("Failed to bind image memory");
+            }
+            Action::DestroyImage(id) => {
+                if let Some(image) = self.images.remove(&id) {
+                    self.device.destroy_image(image);
+                }
+            }
+            Action::CreateImageView {
+                id,
+                image,
+                kind,
+                format,
+                swizzle,
+                range,
+            } => {
+                let view = self
+                    .device
+                    .create_image_view(get(&self.images, image), kind, format, swizzle, range)
+                    .expect("Failed to create an image view");
+                self.image_views.insert(id, view);
+            }
+            Action::DestroyImageView(id) => {
+                if let Some(view) = self.image_views.remove(&id) {
+                    self.device.destroy_image_view(view);
+                }
+            }
+            Action::CreateSampler { id, desc } => {
+                let sampler = self
+                    .device
+                    .create_sampler(&desc)
+                    .expect("Failed to create a sampler");
+                self.samplers.insert(id, sampler);
+            }
+            Action::DestroySampler(id) => {
+                if let Some(sampler) = self.samplers.remove(&id) {
+                    self.device.destroy_sampler(sampler);
+                }
+            }
+            Action::CreateDescriptorPool {
+                id,
+                max_sets,
+                ranges,
+                flags,
+            } => {
+                let pool = self
+                    .device
+                    .create_descriptor_pool(max_sets, ranges, flags)
+                    .expect("Failed to create a descriptor pool");
+                self.descriptor_pools.insert(id, pool);
+            }
+            Action::DestroyDescriptorPool(id) => {
+                self.descriptor_sets.retain(|_, &mut (pool, _)| pool != id);
+                if let Some(pool) = self.descriptor_pools.remove(&id) {
+                    self.device.destroy_descriptor_pool(pool);
+                }
+            }
+            Action::AllocateDescriptorSet { id, pool, layout } => {
+                let set = get_mut(&mut self.descriptor_pools, pool)
+                    .allocate_set(get(&self.descriptor_set_layouts, layout))
+                    .expect("Failed to allocate a descriptor set");
+                self.descriptor_sets.insert(id, (pool, set));
+            }
+            Action::FreeDescriptorSets { pool, sets } => {
+                let descriptor_sets = &mut self.descriptor_sets;
+                let sets = sets
+                    .iter()
+                    .filter_map(|id| descriptor_sets.remove(id))
+                    .map(|(_, set)| set);
+                get_mut(&mut self.descriptor_pools, pool).free(sets);
+            }
+            Action::ResetDescriptorPool(id) => {
+                self.descriptor_sets.retain(|_, &mut (pool, _)| pool != id);
+                get_mut(&mut self.descriptor_pools, id).reset();
+            }
+            Action::CreateDescriptorSetLayout {
+                id,
+                bindings,
+                immutable_samplers,
+            } => {
+                let layout = self
+                    .device
+                    .create_descriptor_set_layout(
+                        bindings,
+                        immutable_samplers.iter().map(|&id| get(&self.samplers, id)),
+                    )
+                    .expect("Failed to create a descriptor set layout");
+                self.descriptor_set_layouts.insert(id, layout);
+            }
+            Action::DestroyDescriptorSetLayout(id) => {
+                if let Some(layout) = self.descriptor_set_layouts.remove(&id) {
+                    self.device.destroy_descriptor_set_layout(layout);
+                }
+            }
+            Action::WriteDescriptorSets(writes) => {
+                self.device
+                    .write_descriptor_sets(writes.iter().map(|write| {
+                        pso::DescriptorSetWrite {
+                            set: &get(&self.descriptor_sets, write.set).1,
+                            binding: write.binding,
+                            array_offset: write.array_offset,
+                            descriptors: write
+                                .descriptors
+                                .iter()
+                                .map(|descriptor| self.descriptor(descriptor))
+                                .collect::<Vec<_>>(),
+                        }
+                    }));
+            }
+            Action::CopyDescriptorSets(copies) => {
+                self.device.copy_descriptor_sets(copies.iter().map(|copy| {
+                    pso::DescriptorSetCopy {
+                        src_set: &get(&self.descriptor_sets, copy.src_set).1,
+                        src_binding: copy.src_binding,
+                        src_array_offset: copy.src_array_offset,
+                        dst_set: &get(&self.descriptor_sets, copy.dst_set).1,
+                        dst_binding: copy.dst_binding,
+                        dst_array_offset: copy.dst_array_offset,
+                        count: copy.count,
+                    }
+                }));
+            }
+            Action::CreateSemaphore(id) => {
+                let semaphore = self
+                    .device
+                    .create_semaphore()
+                    .expect("Failed to create a semaphore");
+                self.semaphores.insert(id, semaphore);
+            }
+            Action::DestroySemaphore(id) => {
+                if let Some(semaphore) = self.semaphores.remove(&id) {
+                    self.device.destroy_semaphore(semaphore);
+                }
+            }
+            Action::CreateFence { id, signaled } => {
+                let fence = self
+                    .device
+                    .create_fence(signaled)
+                    .expect("Failed to create a fence");
+                self.fences.insert(id, fence);
+            }
+            Action::ResetFences(fences) => {
+                self.device
+                    .reset_fences(fences.iter().map(|&id| get(&self.fences, id)))
+                    .expect("Failed to reset fences");
+            }
+            Action::WaitForFences { fences, wait } => {
+                self.device
+                    .wait_for_fences(fences.iter().map(|&id| get(&self.fences, id)), wait, !0)
+                    .expect("Failed to wait for fences");
+            }
+            Action::DestroyFence(id) => {
+                if let Some(fence) = self.fences.remove(&id) {
+                    self.device.destroy_fence(fence);
+                }
+            }
+            Action::CreateEvent(id) => {
+                let event = self
+                    .device
+                    .create_event()
+                    .expect("Failed to create an event");
+                self.events.insert(id, event);
+            }
+            Action::SetEvent(id) => {
+                self.device
+                    .set_event(get(&self.events, id))
+                    .expect("Failed to set an event");
+            }
+            Action::ResetEvent(id) => {
+                self.device
+                    .reset_event(get(&self.events, id))
+                    .expect("Failed to reset an event");
+            }
+            Action::DestroyEvent(id) => {
+                if let Some(event) = self.events.remove(&id) {
+                    self.device.destroy_event(event);
+                }
+            }
+            Action::CreateQueryPool { id, ty, count } => {
+                let pool = self
+                    .device
+                    .create_query_pool(ty, count)
+                    .expect("Failed to create a query pool");
+                self.query_pools.insert(id, pool);
+            }
+            Action::DestroyQueryPool(id) => {
+                if let Some(pool) = self.query_pools.remove(&id) {
+                    self.device.destroy_query_pool(pool);
+                }
+            }
+            Action::CreateSwapchain {
+                id,
+                format,
+                extent,
+                usage,
+                images,
+            } => {
+                for &image_id in &images {
+                    let (image, memory) = self.create_offscreen(format, extent, usage);
+                    self.images.insert(image_id, image);
+                    self.swapchain_memories.insert(image_id, memory);
+                }
+                self.swapchains.insert(id, images);
+            }
+            Action::DestroySwapchain(id) => {
+                for image_id in self.swapchains.remove(&id).unwrap_or_default() {
+                    if let Some(image) = self.images.remove(&image_id) {
+                        self.device.destroy_image(image);
+                    }
+                    if let Some(memory) = self.swapchain_memories.remove(&image_id) {
+                        self.device.free_memory(memory);
+                    }
+                }
+            }
+            Action::AcquireImage {
+                swapchain: _,
+                semaphore,
+                fence,
+            } => {
+                let semaphores = semaphore.into_iter().collect::<Vec<_>>();
+                self.submit_empty(0, &[], &semaphores, fence);
+            }
+            Action::ConfigureSurface {
+                surface,
+                format,
+                extent,
+                usage,
+            } => {
+                self.destroy_surface_images(surface);
+                let (image, memory) = self.create_offscreen(format, extent, usage);
+                let range = image::SubresourceRange {
+                    aspects: format.surface_desc().aspects,
+                    levels: 0 .. 1,
+                    layers: 0 .. 1,
+                };
+                let view = self
+                    .device
+                    .create_image_view(
+                        &image,
+                        image::ViewKind::D2,
+                        format,
+                        format::Swizzle::NO,
+                        range,
+                    )
+                    .expect("Failed to create an offscreen view");
+                self.surfaces.insert(
+                    surface,
+                    Offscreen {
+                        image,
+                        memory,
+                        view,
+                    },
+                );
+            }
+            Action::UnconfigureSurface(surface) => self.destroy_surface_images(surface),
+            Action::AcquireSurfaceImage { surface, view } => {
+                self.surface_views.insert(view, surface);
+            }
+            Action::SetName { object, name } => self.set_name(object, &name),
+            Action::Submit {
+                queue,
+                command_buffers,
+                wait_semaphores,
+                signal_semaphores,
+                fence,
+            } => {
+                let (semaphores, fences) = (&self.semaphores, &self.fences);
+                let buffers = &self.command_buffers;
+                self.queues[self.queue_indices[&queue]].submit(
+                    queue::Submission {
+                        command_buffers: command_buffers.iter().map(|&id| &get(buffers, id).1),
+                        wait_semaphores: wait_semaphores
+                            .iter()
+                            .map(|&(id, stage)| (get(semaphores, id), stage)),
+                        signal_semaphores: signal_semaphores.iter().map(|&id| get(semaphores, id)),
+                    },
+                    fence.map(|id| get(fences, id)),
+                );
+            }
+            Action::Present {
+                queue,
+                wait_semaphores,
+            } => {
+                let index = self.queue_indices[&queue];
+                self.submit_empty(index, &wait_semaphores, &[], None);
+            }
+            Action::PresentSurface {
+                queue,
+                view,
+                wait_semaphore,
+            } => {
+                self.surface_views.remove(&view);
+                let index = self.queue_indices[&queue];
+                let semaphores = wait_semaphore.into_iter().collect::<Vec<_>>();
+                self.submit_empty(index, &semaphores, &[], None);
+            }
+            Action::QueueWaitIdle(queue) => {
+                self.queues[self.queue_indices[&queue]]
+                    .wait_idle()
+                    .expect("Failed to wait for a queue");
+            }
+            Action::WaitIdle => self
+                .device
+                .wait_idle()
+                .expect("Failed to wait for the device"),
+        }
+    }
+
+    unsafe fn set_name(&mut self, object: Id, name: &str) {
+        let device = &self.device;
+        if let Some(image) = self.images.get_mut(&object) {
+            device.set_image_name(image, name);
+        } else if let Some(buffer) = self.buffers.get_mut(&object) {
+            device.set_buffer_name(buffer, name);
+        } else if let Some(&mut (_, ref mut buffer)) = self.command_buffers.get_mut(&object) {
+            device.set_command_buffer_name(buffer, name);
+        } else if let Some(semaphore) = self.semaphores.get_mut(&object) {
+            device.set_semaphore_name(semaphore, name);
+        } else if let Some(fence) = self.fences.get_mut(&object) {
+            device.set_fence_name(fence, name);
+        } else if let Some(framebuffer) = self.framebuffers.get_mut(&object) {
+            device.set_framebuffer_name(framebuffer, name);
+        } else if let Some(render_pass) = self.render_passes.get_mut(&object) {
+            device.set_render_pass_name(render_pass, name);
+        } else if let Some(&mut (_, ref mut set)) = self.descriptor_sets.get_mut(&object) {
+            device.set_descriptor_set_name(set, name);
+        } else if let Some(layout) = self.descriptor_set_layouts.get_mut(&object) {
+            device.set_descriptor_set_layout_name(layout, name);
+        } else {
+            warn!("Unknown object {:?} named {:?}", object, name);
+        }
+    }
+
+    unsafe fn replay_command(&self, cmd: &mut B::CommandBuffer, command: Command) {
+        use hal::command::CommandBuffer as _;
+
+        match command {
+            Command::Begin { flags, inheritance } => cmd.begin(
+                flags,
+                com::CommandBufferInheritanceInfo {
+                    subpass: inheritance.subpass.map(|subpass| self.subpass(subpass)),
+                    framebuffer: inheritance
+                        .framebuffer
+                        .map(|id| get(&self.framebuffers, id)),
+                    occlusion_query_enable: inheritance.occlusion_query_enable,
+                    occlusion_query_flags: inheritance.occlusion_query_flags,
+                    pipeline_statistics: inheritance.pipeline_statistics,
+                },
+            ),
+            Command::Finish => cmd.finish(),
+            Command::Reset { release_resources } => cmd.reset(release_resources),
+            Command::PipelineBarrier {
+                stages,
+                dependencies,
+                barriers,
+            } => cmd.pipeline_barrier(
+                stages,
+                dependencies,
+                barriers.iter().map(|barrier| self.barrier(barrier)),
+            ),
+            Command::FillBuffer {
+                buffer,
+                range,
+                data,
+            } => cmd.fill_buffer(get(&self.buffers, buffer), range, data),
+            Command::UpdateBuffer {
+                buffer,
+                offset,
+                data,
+            } => cmd.update_buffer(get(&self.buffers, buffer), offset, &self.read_data(&data)),
+            Command::ClearImage {
+                image,
+                layout,
+                value,
+                ranges,
+            } => cmd.clear_image(get(&self.images, image), layout, clear_value(value), ranges),
+            Command::ClearAttachments { clears, rects } => cmd.clear_attachments(
+                clears.iter().map(|clear| match *clear {
+                    a::AttachmentClear::Color { index, value } => com::AttachmentClear::Color {
+                        index,
+                        value: com::ClearColor { uint32: value },
+                    },
+                    a::AttachmentClear::DepthStencil { depth, stencil } => {
+                        com::AttachmentClear::DepthStencil { depth, stencil }
+                    }
+                }),
+                rects,
+            ),
+            Command::ResolveImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions,
+            } => cmd.resolve_image(
+                get(&self.images, src),
+                src_layout,
+                get(&self.images, dst),
+                dst_layout,
+                regions,
+            ),
+            Command::BlitImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                filter,
+                regions,
+            } => cmd.blit_image(
+                get(&self.images, src),
+                src_layout,
+                get(&self.images, dst),
+                dst_layout,
+                filter,
+                regions,
+            ),
+            Command::BindIndexBuffer {
+                buffer,
+                range,
+                index_type,
+            } => cmd.bind_index_buffer(hal::buffer::IndexBufferView {
+                buffer: get(&self.buffers, buffer),
+                range,
+                index_type,
+            }),
+            Command::BindVertexBuffers {
+                first_binding,
+                buffers,
+            } => cmd.bind_vertex_buffers(
+                first_binding,
+                buffers
+                    .into_iter()
+                    .map(|(id, range)| (get(&self.buffers, id), range)),
+            ),
+            Command::SetViewports {
+                first_viewport,
+                viewports,
+            } => cmd.set_viewports(first_viewport, viewports),
+            Command::SetScissors {
+                first_scissor,
+                rects,
+            } => cmd.set_scissors(first_scissor, rects),
+            Command::SetStencilReference { faces, value } => {
+                cmd.set_stencil_reference(faces, value)
+            }
+            Command::SetStencilReadMask { faces, value } => cmd.set_stencil_read_mask(faces, value),
+            Command::SetStencilWriteMask { faces, value } => {
+                cmd.set_stencil_write_mask(faces, value)
+            }
+            Command::SetBlendConstants(color) => cmd.set_blend_constants(color),
+            Command::SetDepthBounds(bounds) => cmd.set_depth_bounds(bounds),
+            Command::SetLineWidth(width) => cmd.set_line_width(width),
+            Command::SetDepthBias(depth_bias) => cmd.set_depth_bias(depth_bias),
+            Command::BeginRenderPass {
+                render_pass,
+                framebuffer,
+                render_area,
+                clear_values,
+                first_subpass,
+            } => cmd.begin_render_pass(
+                get(&self.render_passes, render_pass),
+                get(&self.framebuffers, framebuffer),
+                render_area,
+                clear_values.into_iter().map(clear_value),
+                first_subpass,
+            ),
+            Command::NextSubpass(contents) => cmd.next_subpass(contents),
+            Command::EndRenderPass => cmd.end_render_pass(),
+            Command::BindGraphicsPipeline(id) => {
+                cmd.bind_graphics_pipeline(get(&self.graphics_pipelines, id))
+            }
+            Command::BindGraphicsDescriptorSets {
+                layout,
+                first_set,
+                sets,
+                offsets,
+            } => cmd.bind_graphics_descriptor_sets(
+                get(&self.pipeline_layouts, layout),
+                first_set,
+                sets.iter().map(|&id| &get(&self.descriptor_sets, id).1),
+                offsets,
+            ),
+            Command::BindComputePipeline(id) => {
+                cmd.bind_compute_pipeline(get(&self.compute_pipelines, id))
+            }
+            Command::BindComputeDescriptorSets {
+                layout,
+                first_set,
+                sets,
+                offsets,
+            } => cmd.bind_compute_descriptor_sets(
+                get(&self.pipeline_layouts, layout),
+                first_set,
+                sets.iter().map(|&id| &get(&self.descriptor_sets, id).1),
+                offsets,
+            ),
+            Command::Dispatch(count) => cmd.dispatch(count),
+            Command::DispatchIndirect { buffer, offset } => {
+                cmd.dispatch_indirect(get(&self.buffers, buffer), offset)
+            }
+            Command::CopyBuffer { src, dst, regions } => {
+                cmd.copy_buffer(get(&self.buffers, src), get(&self.buffers, dst), regions)
+            }
+            Command::CopyImage {
+                src,
+                src_layout,
+                dst,
+                dst_layout,
+                regions,
+            } => cmd.copy_image(
+                get(&self.images, src),
+                src_layout,
+                get(&self.images, dst),
+                dst_layout,
+                regions,
+            ),
+            Command::CopyBufferToImage {
+                src,
+                dst,
+                dst_layout,
+                regions,
+            } => cmd.copy_buffer_to_image(
+                get(&self.buffers, src),
+                get(&self.images, dst),
+                dst_layout,
+                regions,
+            ),
+            Command::CopyImageToBuffer {
+                src,
+                src_layout,
+                dst,
+                regions,
+            } => cmd.copy_image_to_buffer(
+                get(&self.images, src),
+                src_layout,
+                get(&self.buffers, dst),
+                regions,
+            ),
+            Command::Draw {
+                vertices,
+                instances,
+            } => cmd.draw(vertices, instances),
+            Command::DrawIndexed {
+                indices,
+                base_vertex,
+                instances,
+            } => cmd.draw_indexed(indices, base_vertex, instances),
+            Command::DrawIndirect {
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => cmd.draw_indirect(get(&self.buffers, buffer), offset, draw_count, stride),
+            Command::DrawIndexedIndirect {
+                buffer,
+                offset,
+                draw_count,
+                stride,
+            } => cmd.draw_indexed_indirect(get(&self.buffers, buffer), offset, draw_count, stride),
+            Command::SetEvent { event, stages } => cmd.set_event(get(&self.events, event), stages),
+            Command::ResetEvent { event, stages } => {
+                cmd.reset_event(get(&self.events, event), stages)
+            }
+            Command::WaitEvents {
+                events,
+                stages,
+                barriers,
+            } => cmd.wait_events(
+                events.iter().map(|&id| get(&self.events, id)),
+                stages,
+                barriers.iter().map(|barrier| self.barrier(barrier)),
+            ),
+            Command::BeginQuery { query, flags } => cmd.begin_query(self.query(query), flags),
+            Command::EndQuery(query) => cmd.end_query(self.query(query)),
+            Command::ResetQueryPool { pool, queries } => {
+                cmd.reset_query_pool(get(&self.query_pools, pool), queries)
+            }
+            Command::CopyQueryPoolResults {
+                pool,
+                queries,
+                buffer,
+                offset,
+                stride,
+                flags,
+            } => cmd.copy_query_pool_results(
+                get(&self.query_pools, pool),
+                queries,
+                get(&self.buffers, buffer),
+                offset,
+                stride,
+                flags,
+            ),
+            Command::WriteTimestamp { stage, query } => {
+                cmd.write_timestamp(stage, self.query(query))
+            }
+            Command::PushGraphicsConstants {
+                layout,
+                stages,
+                offset,
+                constants,
+            } => cmd.push_graphics_constants(
+                get(&self.pipeline_layouts, layout),
+                stages,
+                offset,
+                &constants,
+            ),
+            Command::PushComputeConstants {
+                layout,
+                offset,
+                constants,
+            } => {
+                cmd.push_compute_constants(get(&self.pipeline_layouts, layout), offset, &constants)
+            }
+            Command::ExecuteCommands(buffers) => {
+                cmd.execute_commands(buffers.iter().map(|&id| &get(&self.command_buffers, id).1))
+            }
+            Command::InsertDebugMarker { name, color } => cmd.insert_debug_marker(&name, color),
+            Command::BeginDebugMarker { name, color } => cmd.begin_debug_marker(&name, color),
+            Command::EndDebugMarker => cmd.end_debug_marker(),
+        }
+    }
+}
+
+fn clear_value(value: a::ClearValue) -> com::ClearValue {
+    com::ClearValue {
+        color: com::ClearColor { uint32: value.0 },
+    }
+}
+
+impl<B: hal::Backend> Drop for Replayer<B> {
+    fn drop(&mut self) {
+        unsafe {
+            if let Err(e) = self.device.wait_idle() {
+                error!("Failed to wait for the device: {:?}", e);
+            }
+            let device = &self.device;
+            self.command_buffers.clear();
+            for (_, pool) in self.command_pools.drain() {
+                device.destroy_command_pool(pool);
+            }
+            self.descriptor_sets.clear();
+            for (_, pool) in self.descriptor_pools.drain() {
+                device.destroy_descriptor_pool(pool);
+            }
+            for (_, pipeline) in self.graphics_pipelines.drain() {
+                device.destroy_graphics_pipeline(pipeline);
+            }
+            for (_, pipeline) in self.compute_pipelines.drain() {
+                device.destroy_compute_pipeline(pipeline);
+            }
+            for (_, cache) in self.pipeline_caches.drain() {
+                device.destroy_pipeline_cache(cache);
+            }
+            for (_, layout) in self.pipeline_layouts.drain() {
+                device.destroy_pipeline_layout(layout);
+            }
+            for (_, layout) in self.descriptor_set_layouts.drain() {
+                device.destroy_descriptor_set_layout(layout);
+            }
+            for (_, framebuffer) in self.framebuffers.drain() {
+                device.destroy_framebuffer(framebuffer);
+            }
+            for (_, render_pass) in self.render_passes.drain() {
+                device.destroy_render_pass(render_pass);
+            }
+            for (_, module) in self.shader_modules.drain() {
+                device.destroy_shader_module(module);
+            }
+            for (_, sampler) in self.samplers.drain() {
+                device.destroy_sampler(sampler);
+            }
+            for (_, view) in self.image_views.drain() {
+                device.destroy_image_view(view);
+            }
+            for (_, view) in self.buffer_views.drain() {
+                device.destroy_buffer_view(view);
+            }
+            for (_, image) in self.images.drain() {
+                device.destroy_image(image);
+            }
+            for (_, buffer) in self.buffers.drain() {
+                device.destroy_buffer(buffer);
+            }
+            self.surface_views.clear();
+            for (_, offscreen) in self.surfaces.drain() {
+                device.destroy_image_view(offscreen.view);
+                device.destroy_image(offscreen.image);
+                device.free_memory(offscreen.memory);
+            }
+            self.swapchains.clear();
+            for (_, memory) in self.swapchain_memories.drain() {
+                device.free_memory(memory);
+            }
+            for (_, memory) in self.memories.drain() {
+                device.free_memory(memory);
+            }
+            for (_, semaphore) in self.semaphores.drain() {
+                device.destroy_semaphore(semaphore);
+            }
+            for (_, fence) in self.fences.drain() {
+                device.destroy_fence(fence);
+            }
+            for (_, event) in self.events.drain() {
+                device.destroy_event(event);
+            }
+            for (_, pool) in self.query_pools.drain() {
+                device.destroy_query_pool(pool);
+            }
+        }
+    }
+}
@@ -0,0 +1,925 @@
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hal::{
+    buffer,
+    command as com,
+    image,
+    memory,
+    pso,
+    query,
+    DrawCount,
+    IndexCount,
+    InstanceCount,
+    VertexCount,
+    VertexOffset,
+    WorkGroupCount,
+};
+
+use crate::{
+    error::{Error, Reporter, Resource},
+    native::{self as n, collect, Handle},
+    Backend,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum State {
+    Initial,
+    Recording,
+    Executable,
+}
+
+#[derive(Debug)]
+struct PassState {
+    subpass: usize,
+    subpasses: usize,
+}
+
+#[derive(Debug)]
+pub struct CommandBuffer<B: hal::Backend> {
+    pub(crate) raw: B::CommandBuffer,
+    level: com::Level,
+    reporter: Arc<Reporter>,
+    pool_epoch: Arc<AtomicU64>,
+    /// Epoch of the pool when recording began.
+    epoch: u64,
+    state: State,
+    pass: Option<PassState>,
+    graphics_pipeline: bool,
+    compute_pipeline: bool,
+    index_buffer: bool,
+    /// Objects referenced by the recorded commands.
+    used: Vec<Arc<Handle>>,
+}
+
+impl<B: hal::Backend> CommandBuffer<B> {
+    pub(crate) fn new(
+        raw: B::CommandBuffer,
+        level: com::Level,
+        pool_epoch: Arc<AtomicU64>,
+        reporter: Arc<Reporter>,
+    ) -> Self {
+        CommandBuffer {
+            raw,
+            level,
+            reporter,
+            pool_epoch,
+            epoch: 0,
+            state: State::Initial,
+            pass: None,
+            graphics_pipeline: false,
+            compute_pipeline: false,
+            index_buffer: false,
+            used: Vec::new(),
+        }
+    }
+
+    /// Checks whether the command buffer can be submitted or executed,
+    /// reporting the errors preventing it.
+    pub(crate) fn check_executable(&self, command: &'static str) -> bool {
+        if self.state != State::Executable || self.epoch != self.pool_epoch.load(Ordering::Acquire)
+        {
+            self.reporter.report(Error::NotExecutable);
+            return false;
+        }
+        match self.find_destroyed() {
+            Some(resource) => {
+                self.reporter.report(Error::Destroyed { resource, command });
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn find_destroyed(&self) -> Option<Resource> {
+        self.used
+            .iter()
+            .filter_map(|handle| handle.find_destroyed())
+            .next()
+    }
+
+    fn reset_state(&mut self) {
+        self.state = State::Initial;
+        self.pass = None;
+        self.graphics_pipeline = false;
+        self.compute_pipeline = false;
+        self.index_buffer = false;
+        self.used.clear();
+    }
+
+    fn recording(&self, command: &'static str) -> bool {
+        if self.state != State::Recording {
+            self.reporter.report(Error::NotRecording(command));
+            return false;
+        }
+        true
+    }
+
+    fn inside_pass(&self, command: &'static str) -> bool {
+        if !self.recording(command) {
+            return false;
+        }
+        if self.pass.is_none() {
+            self.reporter.report(Error::OutsideRenderPass(command));
+            return false;
+        }
+        true
+    }
+
+    fn outside_pass(&self, command: &'static str) -> bool {
+        if !self.recording(command) {
+            return false;
+        }
+        if self.pass.is_some() {
+            self.reporter.report(Error::InsideRenderPass(command));
+            return false;
+        }
+        true
+    }
+
+    fn use_handle(&mut self, handle: &Arc<Handle>, command: &'static str) -> bool {
+        if let Some(resource) = handle.find_destroyed() {
+            self.reporter.report(Error::Destroyed { resource, command });
+            return false;
+        }
+        self.used.push(Arc::clone(handle));
+        true
+    }
+
+    fn use_memory(&mut self, bound: bool, handle: &Arc<Handle>, command: &'static str) -> bool {
+        if !bound {
+            self.reporter.report(Error::Unbound {
+                resource: handle.resource,
+                command,
+            });
+            return false;
+        }
+        self.use_handle(handle, command)
+    }
+
+    fn use_buffer(&mut self, buffer: &n::Buffer<B>, command: &'static str) -> bool {
+        self.use_memory(buffer.bound, &buffer.handle, command)
+    }
+
+    fn use_image(&mut self, image: &n::Image<B>, command: &'static str) -> bool {
+        self.use_memory(image.bound, &image.handle, command)
+    }
+
+    fn use_barriers<'a>(
+        &mut self,
+        barriers: &[memory::Barrier<'a, Backend<B>>],
+        command: &'static str,
+    ) -> bool {
+        barriers.iter().all(|barrier| match *barrier {
+            memory::Barrier::Buffer { target, .. } => self.use_buffer(target, command),
+            memory::Barrier::Image { target, .. } => self.use_image(target, command),
+            _ => true,
+        })
+    }
+
+    fn draw_state(&self, indexed: bool, command: &'static str) -> bool {
+        if !self.inside_pass(command) {
+            return false;
+        }
+        if !self.graphics_pipeline {
+            self.reporter.report(Error::NoPipeline(command));
+            return false;
+        }
+        if indexed && !self.index_buffer {
+            self.reporter.report(Error::NoIndexBuffer(command));
+            return false;
+        }
+        true
+    }
+
+    fn dispatch_state(&self, command: &'static str) -> bool {
+        if !self.outside_pass(command) {
+            return false;
+        }
+        if !self.compute_pipeline {
+            self.reporter.report(Error::NoPipeline(command));
+            return false;
+        }
+        true
+    }
+
+    fn bind_descriptor_sets<I>(&mut self, sets: &[I], command: &'static str) -> bool
+    where
+        I: Borrow<n::DescriptorSet<B>>,
+    {
+        sets.iter().all(|set| {
+            set.borrow()
+                .handles()
+                .iter()
+                .all(|handle| self.use_handle(handle, command))
+        })
+    }
+}
+
+fn raw_query<'a, B: hal::Backend>(query: &query::Query<'a, Backend<B>>) -> query::Query<'a, B> {
+    query::Query {
+        pool: &query.pool.raw,
+        id: query.id,
+    }
+}
+
+/// Copies barriers out of their borrows, so they can be validated before recording.
+fn owned_barriers<'a, B, T>(barriers: T) -> Vec<memory::Barrier<'a, Backend<B>>>
+where
+    B: hal::Backend,
+    T: IntoIterator,
+    T::Item: Borrow<memory::Barrier<'a, Backend<B>>>,
+{
+    barriers
+        .into_iter()
+        .map(|barrier| match *barrier.borrow() {
+            memory::Barrier::AllBuffers(ref access) => memory::Barrier::AllBuffers(access.clone()),
+            memory::Barrier::AllImages(ref access) => memory::Barrier::AllImages(access.clone()),
+            memory::Barrier::Buffer {
+                ref states,
+                target,
+                ref range,
+                ref families,
+            } => memory::Barrier::Buffer {
+                states: states.clone(),
+                target,
+                range: range.clone(),
+                families: families.clone(),
+            },
+            memory::Barrier::Image {
+                ref states,
+                target,
+                ref range,
+                ref families,
+            } => memory::Barrier::Image {
+                states: states.clone(),
+                target,
+                range: range.clone(),
+                families: families.clone(),
+            },
+        })
+        .collect()
+}
+
+impl<B: hal::Backend> com::CommandBuffer<Backend<B>> for CommandBuffer<B> {
+    unsafe fn begin(
+        &mut self,
+        flags: com::CommandBufferFlags,
+        inheritance_info: com::CommandBufferInheritanceInfo<Backend<B>>,
+    ) {
+        if self.state == State::Recording {
+            self.reporter.report(Error::AlreadyRecording);
+            return;
+        }
+        self.reset_state();
+        self.state = State::Recording;
+        self.epoch = self.pool_epoch.load(Ordering::Acquire);
+
+        if self.level == com::Level::Secondary
+            && flags.contains(com::CommandBufferFlags::RENDER_PASS_CONTINUE)
+        {
+            if let Some(ref subpass) = inheritance_info.subpass {
+                self.pass = Some(PassState {
+                    subpass: subpass.index as usize,
+                    subpasses: subpass.main_pass.subpasses,
+                });
+            }
+        }
+
+        let raw_info = com::CommandBufferInheritanceInfo {
+            subpass: inheritance_info.subpass.as_ref().map(n::raw_subpass),
+            framebuffer: inheritance_info
+                .framebuffer
+                .map(|framebuffer| &framebuffer.raw),
+            occlusion_query_enable: inheritance_info.occlusion_query_enable,
+            occlusion_query_flags: inheritance_info.occlusion_query_flags,
+            pipeline_statistics: inheritance_info.pipeline_statistics,
+        };
+        self.raw.begin(flags, raw_info)
+    }
+
+    unsafe fn finish(&mut self) {
+        if !self.recording("finish") {
+            return;
+        }
+        if self.level == com::Level::Primary && self.pass.is_some() {
+            self.reporter.report(Error::InsideRenderPass("finish"));
+            return;
+        }
+        self.state = State::Executable;
+        self.raw.finish()
+    }
+
+    unsafe fn reset(&mut self, release_resources: bool) {
+        self.reset_state();
+        self.raw.reset(release_resources)
+    }
+
+    unsafe fn pipeline_barrier<'a, T>(
+        &mut self,
+        stages: Range<pso::PipelineStage>,
+        dependencies: memory::Dependencies,
+        barriers: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<memory::Barrier<'a, Backend<B>>>,
+    {
+        let barriers = owned_barriers(barriers);
+        if !self.recording("pipeline_barrier") || !self.use_barriers(&barriers, "pipeline_barrier")
+        {
+            return;
+        }
+        self.raw
+            .pipeline_barrier(stages, dependencies, barriers.iter().map(n::raw_barrier))
+    }
+
+    unsafe fn fill_buffer(&mut self, buffer: &n::Buffer<B>, range: buffer::SubRange, data: u32) {
+        if self.outside_pass("fill_buffer") && self.use_buffer(buffer, "fill_buffer") {
+            self.raw.fill_buffer(&buffer.raw, range, data)
+        }
+    }
+
+    unsafe fn update_buffer(&mut self, buffer: &n::Buffer<B>, offset: buffer::Offset, data: &[u8]) {
+        if self.outside_pass("update_buffer") && self.use_buffer(buffer, "update_buffer") {
+            self.raw.update_buffer(&buffer.raw, offset, data)
+        }
+    }
+
+    unsafe fn clear_image<T>(
+        &mut self,
+        image: &n::Image<B>,
+        layout: image::Layout,
+        value: com::ClearValue,
+        subresource_ranges: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<image::SubresourceRange>,
+    {
+        if self.outside_pass("clear_image") && self.use_image(image, "clear_image") {
+            self.raw
+                .clear_image(&image.raw, layout, value, subresource_ranges)
+        }
+    }
+
+    unsafe fn clear_attachments<T, U>(&mut self, clears: T, rects: U)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::AttachmentClear>,
+        U: IntoIterator,
+        U::Item: Borrow<pso::ClearRect>,
+    {
+        if self.inside_pass("clear_attachments") {
+            self.raw.clear_attachments(clears, rects)
+        }
+    }
+
+    unsafe fn resolve_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageResolve>,
+    {
+        if self.outside_pass("resolve_image")
+            && self.use_image(src, "resolve_image")
+            && self.use_image(dst, "resolve_image")
+        {
+            self.raw
+                .resolve_image(&src.raw, src_layout, &dst.raw, dst_layout, regions)
+        }
+    }
+
+    unsafe fn blit_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        filter: image::Filter,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageBlit>,
+    {
+        if self.outside_pass("blit_image")
+            && self.use_image(src, "blit_image")
+            && self.use_image(dst, "blit_image")
+        {
+            self.raw
+                .blit_image(&src.raw, src_layout, &dst.raw, dst_layout, filter, regions)
+        }
+    }
+
+    unsafe fn bind_index_buffer(&mut self, view: buffer::IndexBufferView<Backend<B>>) {
+        if !self.recording("bind_index_buffer")
+            || !self.use_buffer(view.buffer, "bind_index_buffer")
+        {
+            return;
+        }
+        self.index_buffer = true;
+        self.raw.bind_index_buffer(buffer::IndexBufferView {
+            buffer: &view.buffer.raw,
+            range: view.range,
+            index_type: view.index_type,
+        })
+    }
+
+    unsafe fn bind_vertex_buffers<I, T>(&mut self, first_binding: pso::BufferIndex, buffers: I)
+    where
+        I: IntoIterator<Item = (T, buffer::SubRange)>,
+        T: Borrow<n::Buffer<B>>,
+    {
+        let buffers = collect(buffers);
+        if !self.recording("bind_vertex_buffers")
+            || !buffers
+                .iter()
+                .all(|(buffer, _)| self.use_buffer(buffer.borrow(), "bind_vertex_buffers"))
+        {
+            return;
+        }
+        self.raw.bind_vertex_buffers(
+            first_binding,
+            buffers
+                .iter()
+                .map(|(buffer, range)| (&buffer.borrow().raw, range.clone())),
+        )
+    }
+
+    unsafe fn set_viewports<T>(&mut self, first_viewport: u32, viewports: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Viewport>,
+    {
+        if self.recording("set_viewports") {
+            self.raw.set_viewports(first_viewport, viewports)
+        }
+    }
+
+    unsafe fn set_scissors<T>(&mut self, first_scissor: u32, rects: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<pso::Rect>,
+    {
+        if self.recording("set_scissors") {
+            self.raw.set_scissors(first_scissor, rects)
+        }
+    }
+
+    unsafe fn set_stencil_reference(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        if self.recording("set_stencil_reference") {
+            self.raw.set_stencil_reference(faces, value)
+        }
+    }
+
+    unsafe fn set_stencil_read_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        if self.recording("set_stencil_read_mask") {
+            self.raw.set_stencil_read_mask(faces, value)
+        }
+    }
+
+    unsafe fn set_stencil_write_mask(&mut self, faces: pso::Face, value: pso::StencilValue) {
+        if self.recording("set_stencil_write_mask") {
+            self.raw.set_stencil_write_mask(faces, value)
+        }
+    }
+
+    unsafe fn set_blend_constants(&mut self, color: pso::ColorValue) {
+        if self.recording("set_blend_constants") {
+            self.raw.set_blend_constants(color)
+        }
+    }
+
+    unsafe fn set_depth_bounds(&mut self, bounds: Range<f32>) {
+        if self.recording("set_depth_bounds") {
+            self.raw.set_depth_bounds(bounds)
+        }
+    }
+
+    unsafe fn set_line_width(&mut self, width: f32) {
+        if self.recording("set_line_width") {
+            self.raw.set_line_width(width)
+        }
+    }
+
+    unsafe fn set_depth_bias(&mut self, depth_bias: pso::DepthBias) {
+        if self.recording("set_depth_bias") {
+            self.raw.set_depth_bias(depth_bias)
+        }
+    }
+
+    unsafe fn begin_render_pass<T>(
+        &mut self,
+        render_pass: &n::RenderPass<B>,
+        framebuffer: &n::Framebuffer<B>,
+        render_area: pso::Rect,
+        clear_values: T,
+        first_subpass: com::SubpassContents,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ClearValue>,
+    {
+        if !self.outside_pass("begin_render_pass")
+            || !self.use_handle(&render_pass.handle, "begin_render_pass")
+            || !self.use_handle(&framebuffer.handle, "begin_render_pass")
+        {
+            return;
+        }
+        self.pass = Some(PassState {
+            subpass: 0,
+            subpasses: render_pass.subpasses,
+        });
+        self.raw.begin_render_pass(
+            &render_pass.raw,
+            &framebuffer.raw,
+            render_area,
+            clear_values,
+            first_subpass,
+        )
+    }
+
+    unsafe fn next_subpass(&mut self, contents: com::SubpassContents) {
+        if !self.inside_pass("next_subpass") {
+            return;
+        }
+        let pass = self.pass.as_mut().unwrap();
+        if pass.subpass + 1 >= pass.subpasses {
+            self.reporter.report(Error::NoNextSubpass);
+            return;
+        }
+        pass.subpass += 1;
+        self.raw.next_subpass(contents)
+    }
+
+    unsafe fn end_render_pass(&mut self) {
+        if !self.inside_pass("end_render_pass") {
+            return;
+        }
+        let pass = self.pass.as_ref().unwrap();
+        let remaining = pass.subpasses - 1 - pass.subpass;
+        if remaining != 0 {
+            self.reporter.report(Error::SubpassesRemaining(remaining));
+            return;
+        }
+        self.pass = None;
+        self.raw.end_render_pass()
+    }
+
+    unsafe fn bind_graphics_pipeline(&mut self, pipeline: &n::GraphicsPipeline<B>) {
+        if self.recording("bind_graphics_pipeline")
+            && self.use_handle(&pipeline.handle, "bind_graphics_pipeline")
+        {
+            self.graphics_pipeline = true;
+            self.raw.bind_graphics_pipeline(&pipeline.raw)
+        }
+    }
+
+    unsafe fn bind_graphics_descriptor_sets<I, J>(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = collect(sets);
+        if self.recording("bind_graphics_descriptor_sets")
+            && self.bind_descriptor_sets(&sets, "bind_graphics_descriptor_sets")
+        {
+            self.raw.bind_graphics_descriptor_sets(
+                &layout.raw,
+                first_set,
+                sets.iter().map(|set| &set.borrow().raw),
+                offsets,
+            )
+        }
+    }
+
+    unsafe fn bind_compute_pipeline(&mut self, pipeline: &n::ComputePipeline<B>) {
+        if self.recording("bind_compute_pipeline")
+            && self.use_handle(&pipeline.handle, "bind_compute_pipeline")
+        {
+            self.compute_pipeline = true;
+            self.raw.bind_compute_pipeline(&pipeline.raw)
+        }
+    }
+
+    unsafe fn bind_compute_descriptor_sets<I, J>(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        first_set: usize,
+        sets: I,
+        offsets: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::DescriptorSet<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<com::DescriptorSetOffset>,
+    {
+        let sets = collect(sets);
+        if self.recording("bind_compute_descriptor_sets")
+            && self.bind_descriptor_sets(&sets, "bind_compute_descriptor_sets")
+        {
+            self.raw.bind_compute_descriptor_sets(
+                &layout.raw,
+                first_set,
+                sets.iter().map(|set| &set.borrow().raw),
+                offsets,
+            )
+        }
+    }
+
+    unsafe fn dispatch(&mut self, count: WorkGroupCount) {
+        if self.dispatch_state("dispatch") {
+            self.raw.dispatch(count)
+        }
+    }
+
+    unsafe fn dispatch_indirect(&mut self, buffer: &n::Buffer<B>, offset: buffer::Offset) {
+        if self.dispatch_state("dispatch_indirect") && self.use_buffer(buffer, "dispatch_indirect")
+        {
+            self.raw.dispatch_indirect(&buffer.raw, offset)
+        }
+    }
+
+    unsafe fn copy_buffer<T>(&mut self, src: &n::Buffer<B>, dst: &n::Buffer<B>, regions: T)
+    where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferCopy>,
+    {
+        if self.outside_pass("copy_buffer")
+            && self.use_buffer(src, "copy_buffer")
+            && self.use_buffer(dst, "copy_buffer")
+        {
+            self.raw.copy_buffer(&src.raw, &dst.raw, regions)
+        }
+    }
+
+    unsafe fn copy_image<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::ImageCopy>,
+    {
+        if self.outside_pass("copy_image")
+            && self.use_image(src, "copy_image")
+            && self.use_image(dst, "copy_image")
+        {
+            self.raw
+                .copy_image(&src.raw, src_layout, &dst.raw, dst_layout, regions)
+        }
+    }
+
+    unsafe fn copy_buffer_to_image<T>(
+        &mut self,
+        src: &n::Buffer<B>,
+        dst: &n::Image<B>,
+        dst_layout: image::Layout,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        if self.outside_pass("copy_buffer_to_image")
+            && self.use_buffer(src, "copy_buffer_to_image")
+            && self.use_image(dst, "copy_buffer_to_image")
+        {
+            self.raw
+                .copy_buffer_to_image(&src.raw, &dst.raw, dst_layout, regions)
+        }
+    }
+
+    unsafe fn copy_image_to_buffer<T>(
+        &mut self,
+        src: &n::Image<B>,
+        src_layout: image::Layout,
+        dst: &n::Buffer<B>,
+        regions: T,
+    ) where
+        T: IntoIterator,
+        T::Item: Borrow<com::BufferImageCopy>,
+    {
+        if self.outside_pass("copy_image_to_buffer")
+            && self.use_image(src, "copy_image_to_buffer")
+            && self.use_buffer(dst, "copy_image_to_buffer")
+        {
+            self.raw
+                .copy_image_to_buffer(&src.raw, src_layout, &dst.raw, regions)
+        }
+    }
+
+    unsafe fn draw(&mut self, vertices: Range<VertexCount>, instances: Range<InstanceCount>) {
+        if self.draw_state(false, "draw") {
+            self.raw.draw(vertices, instances)
+        }
+    }
+
+    unsafe fn draw_indexed(
+        &mut self,
+        indices: Range<IndexCount>,
+        base_vertex: VertexOffset,
+        instances: Range<InstanceCount>,
+    ) {
+        if self.draw_state(true, "draw_indexed") {
+            self.raw.draw_indexed(indices, base_vertex, instances)
+        }
+    }
+
+    unsafe fn draw_indirect(
+        &mut self,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        if self.draw_state(false, "draw_indirect") && self.use_buffer(buffer, "draw_indirect") {
+            self.raw
+                .draw_indirect(&buffer.raw, offset, draw_count, stride)
+        }
+    }
+
+    unsafe fn draw_indexed_indirect(
+        &mut self,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        draw_count: DrawCount,
+        stride: u32,
+    ) {
+        if self.draw_state(true, "draw_indexed_indirect")
+            && self.use_buffer(buffer, "draw_indexed_indirect")
+        {
+            self.raw
+                .draw_indexed_indirect(&buffer.raw, offset, draw_count, stride)
+        }
+    }
+
+    unsafe fn set_event(&mut self, event: &n::Event<B>, stages: pso::PipelineStage) {
+        if self.outside_pass("set_event") && self.use_handle(&event.handle, "set_event") {
+            self.raw.set_event(&event.raw, stages)
+        }
+    }
+
+    unsafe fn reset_event(&mut self, event: &n::Event<B>, stages: pso::PipelineStage) {
+        if self.outside_pass("reset_event") && self.use_handle(&event.handle, "reset_event") {
+            self.raw.reset_event(&event.raw, stages)
+        }
+    }
+
+    unsafe fn wait_events<'a, I, J>(
+        &mut self,
+        events: I,
+        stages: Range<pso::PipelineStage>,
+        barriers: J,
+    ) where
+        I: IntoIterator,
+        I::Item: Borrow<n::Event<B>>,
+        J: IntoIterator,
+        J::Item: Borrow<memory::Barrier<'a, Backend<B>>>,
+    {
+        let events = collect(events);
+        let barriers = owned_barriers(barriers);
+        if !self.recording("wait_events")
+            || !events
+                .iter()
+                .all(|event| self.use_handle(&event.borrow().handle, "wait_events"))
+            || !self.use_barriers(&barriers, "wait_events")
+        {
+            return;
+        }
+        self.raw.wait_events(
+            events.iter().map(|event| &event.borrow().raw),
+            stages,
+            barriers.iter().map(n::raw_barrier),
+        )
+    }
+
+    unsafe fn begin_query(&mut self, query: query::Query<Backend<B>>, flags: query::ControlFlags) {
+        if self.recording("begin_query") && self.use_handle(&query.pool.handle, "begin_query") {
+            self.raw.begin_query(raw_query(&query), flags)
+        }
+    }
+
+    unsafe fn end_query(&mut self, query: query::Query<Backend<B>>) {
+        if self.recording("end_query") && self.use_handle(&query.pool.handle, "end_query") {
+            self.raw.end_query(raw_query(&query))
+        }
+    }
+
+    unsafe fn reset_query_pool(&mut self, pool: &n::QueryPool<B>, queries: Range<query::Id>) {
+        if self.outside_pass("reset_query_pool")
+            && self.use_handle(&pool.handle, "reset_query_pool")
+        {
+            self.raw.reset_query_pool(&pool.raw, queries)
+        }
+    }
+
+    unsafe fn copy_query_pool_results(
+        &mut self,
+        pool: &n::QueryPool<B>,
+        queries: Range<query::Id>,
+        buffer: &n::Buffer<B>,
+        offset: buffer::Offset,
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) {
+        if self.outside_pass("copy_query_pool_results")
+            && self.use_handle(&pool.handle, "copy_query_pool_results")
+            && self.use_buffer(buffer, "copy_query_pool_results")
+        {
+            self.raw
+                .copy_query_pool_results(&pool.raw, queries, &buffer.raw, offset, stride, flags)
+        }
+    }
+
+    unsafe fn write_timestamp(
+        &mut self,
+        stage: pso::PipelineStage,
+        query: query::Query<Backend<B>>,
+    ) {
+        if self.recording("write_timestamp")
+            && self.use_handle(&query.pool.handle, "write_timestamp")
+        {
+            self.raw.write_timestamp(stage, raw_query(&query))
+        }
+    }
+
+    unsafe fn push_graphics_constants(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        stages: pso::ShaderStageFlags,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        if self.recording("push_graphics_constants") {
+            self.raw
+                .push_graphics_constants(&layout.raw, stages, offset, constants)
+        }
+    }
+
+    unsafe fn push_compute_constants(
+        &mut self,
+        layout: &n::PipelineLayout<B>,
+        offset: u32,
+        constants: &[u32],
+    ) {
+        if self.recording("push_compute_constants") {
+            self.raw
+                .push_compute_constants(&layout.raw, offset, constants)
+        }
+    }
+
+    unsafe fn execute_commands<'a, T, I>(&mut self, cmd_buffers: I)
+    where
+        T: 'a + Borrow<CommandBuffer<B>>,
+        I: IntoIterator<Item = &'a T>,
+    {
+        let cmd_buffers = collect(cmd_buffers);
+        if !self.recording("execute_commands")
+            || !cmd_buffers
+                .iter()
+                .all(|&cmd_buffer| cmd_buffer.borrow().check_executable("execute_commands"))
+        {
+            return;
+        }
+        for &cmd_buffer in &cmd_buffers {
+            self.used.extend(cmd_buffer.borrow().used.iter().cloned());
+        }
+        self.raw.execute_commands(
+            cmd_buffers
+                .into_iter()
+                .map(|cmd_buffer| &cmd_buffer.borrow().raw),
+        )
+    }
+
+    unsafe fn insert_debug_marker(&mut self, name: &str, color: u32) {
+        if self.recording("insert_debug_marker") {
+            self.raw.insert_debug_marker(name, color)
+        }
+    }
+
+    unsafe fn begin_debug_marker(&mut self, name: &str, color: u32) {
+        if self.recording("begin_debug_marker") {
+            self.raw.begin_debug_marker(name, color)
+        }
+    }
+
+    unsafe fn end_debug_marker(&mut self) {
+        if self.recording("end_debug_marker") {
+            self.raw.end_debug_marker()
+        }
+    }
+}
@@ -0,0 +1,1079 @@
+use std::borrow::Borrow;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use hal::{
+    adapter,
+    buffer,
+    device,
+    format,
+    image,
+    memory,
+    pass,
+    pool,
+    pso,
+    query,
+    queue,
+    window,
+};
+
+use crate::{
+    command::CommandBuffer,
+    error::{Error, Reporter, ResourceKind},
+    native::{self as n, collect, Handle},
+    pool::{CommandPool, DescriptorPool},
+    Backend,
+    Surface,
+    Swapchain,
+};
+
+#[derive(Debug)]
+pub struct Device<B: hal::Backend> {
+    raw: B::Device,
+    memory_properties: adapter::MemoryProperties,
+    reporter: Arc<Reporter>,
+}
+
+impl<B: hal::Backend> Device<B> {
+    pub(crate) fn new(
+        raw: B::Device,
+        memory_properties: adapter::MemoryProperties,
+        reporter: Arc<Reporter>,
+    ) -> Self {
+        Device {
+            raw,
+            memory_properties,
+            reporter,
+        }
+    }
+
+    pub fn raw(&self) -> &B::Device {
+        &self.raw
+    }
+
+    /// Returns the reporter collecting the errors of this device and its objects.
+    pub fn reporter(&self) -> &Arc<Reporter> {
+        &self.reporter
+    }
+
+    /// Reports the first destroyed object `handle` relies on, if any.
+    fn check_alive(&self, handle: &Handle, command: &'static str) -> bool {
+        match handle.find_destroyed() {
+            Some(resource) => {
+                self.reporter.report(Error::Destroyed { resource, command });
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn check_bound(&self, bound: bool, handle: &Handle, command: &'static str) -> bool {
+        if !bound {
+            self.reporter.report(Error::Unbound {
+                resource: handle.resource,
+                command,
+            });
+        }
+        bound && self.check_alive(handle, command)
+    }
+
+    fn check_bind(
+        &self,
+        memory: &n::Memory<B>,
+        offset: u64,
+        bound: bool,
+        handle: &Handle,
+        requirements: memory::Requirements,
+    ) -> Result<(), device::BindError> {
+        let resource = handle.resource;
+        if bound {
+            self.reporter.report(Error::AlreadyBound(resource));
+            return Err(device::BindError::WrongMemory);
+        }
+        if requirements.type_mask & (1 << memory.memory_type.0) == 0 {
+            self.reporter.report(Error::WrongMemoryType {
+                resource,
+                memory_type: memory.memory_type,
+            });
+            return Err(device::BindError::WrongMemory);
+        }
+        if offset & (requirements.alignment - 1) != 0 {
+            self.reporter.report(Error::MisalignedBind {
+                resource,
+                offset,
+                alignment: requirements.alignment,
+            });
+            return Err(device::BindError::OutOfBounds);
+        }
+        if offset + requirements.size > memory.size {
+            self.reporter.report(Error::BindOutOfBounds {
+                resource,
+                offset,
+                size: requirements.size,
+                memory_size: memory.size,
+            });
+            return Err(device::BindError::OutOfBounds);
+        }
+        handle.depend_on(&memory.handle);
+        Ok(())
+    }
+
+    fn check_segment(&self, memory: &n::Memory<B>, segment: &memory::Segment) -> bool {
+        let end = match segment.size {
+            Some(size) => segment.offset.checked_add(size),
+            None => Some(segment.offset),
+        };
+        match end {
+            Some(end) if end <= memory.size => true,
+            _ => {
+                self.reporter.report(Error::SegmentOutOfBounds {
+                    resource: memory.handle.resource,
+                    segment: segment.clone(),
+                    memory_size: memory.size,
+                });
+                false
+            }
+        }
+    }
+
+    /// Validates the mapped ranges to flush or invalidate, dropping the invalid ones.
+    fn mapped_ranges<'a, I>(
+        &self,
+        ranges: I,
+        command: &'static str,
+    ) -> Vec<(&'a B::Memory, memory::Segment)>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory<B>, memory::Segment)>,
+    {
+        ranges
+            .into_iter()
+            .filter_map(|range| {
+                let (memory, ref segment) = *range.borrow();
+                if !memory.mapped.load(Ordering::Acquire) {
+                    self.reporter.report(Error::NotMapped {
+                        resource: memory.handle.resource,
+                        command,
+                    });
+                    return None;
+                }
+                if !self.check_segment(memory, segment) {
+                    return None;
+                }
+                Some((&memory.raw, segment.clone()))
+            })
+            .collect()
+    }
+
+    /// Validates the descriptors of a write against the set layout, returning
+    /// the objects written to each array element.
+    fn check_descriptors<'a>(
+        &self,
+        set: &n::DescriptorSet<B>,
+        mut binding: pso::DescriptorBinding,
+        mut array_index: pso::DescriptorArrayIndex,
+        descriptors: &[pso::Descriptor<'a, Backend<B>>],
+    ) -> Option<Vec<(n::DescriptorSlot, Vec<Arc<Handle>>)>> {
+        let mut contents = Vec::with_capacity(descriptors.len());
+        for descriptor in descriptors {
+            // Writes past the end of a binding continue with the next one.
+            let layout = loop {
+                let layout = set.bindings.iter().find(|layout| layout.binding == binding);
+                match layout {
+                    Some(layout) if array_index >= layout.count => {
+                        binding += 1;
+                        array_index = 0;
+                    }
+                    Some(layout) => break layout,
+                    None => {
+                        self.reporter.report(Error::DescriptorOutOfBounds {
+                            binding,
+                            array_index,
+                        });
+                        return None;
+                    }
+                }
+            };
+
+            let (matches, found, handles) = match *descriptor {
+                pso::Descriptor::Sampler(sampler) => (
+                    layout.ty == pso::DescriptorType::Sampler,
+                    "Sampler",
+                    vec![Arc::clone(&sampler.handle)],
+                ),
+                pso::Descriptor::Image(view, _) => (
+                    match layout.ty {
+                        pso::DescriptorType::Image {
+                            ty: pso::ImageDescriptorType::Sampled { with_sampler },
+                        } => !with_sampler,
+                        pso::DescriptorType::Image {
+                            ty: pso::ImageDescriptorType::Storage { .. },
+                        }
+                        | pso::DescriptorType::InputAttachment => true,
+                        _ => false,
+                    },
+                    "Image",
+                    vec![Arc::clone(&view.handle)],
+                ),
+                pso::Descriptor::CombinedImageSampler(view, _, sampler) => (
+                    layout.ty
+                        == pso::DescriptorType::Image {
+                            ty: pso::ImageDescriptorType::Sampled { with_sampler: true },
+                        },
+                    "CombinedImageSampler",
+                    vec![Arc::clone(&view.handle), Arc::clone(&sampler.handle)],
+                ),
+                pso::Descriptor::Buffer(buffer, _) => {
+                    if !self.check_bound(buffer.bound, &buffer.handle, "write_descriptor_sets") {
+                        return None;
+                    }
+                    (
+                        match layout.ty {
+                            pso::DescriptorType::Buffer { format, .. } => {
+                                format != pso::BufferDescriptorFormat::Texel
+                            }
+                            _ => false,
+                        },
+                        "Buffer",
+                        vec![Arc::clone(&buffer.handle)],
+                    )
+                }
+                pso::Descriptor::TexelBuffer(view) => (
+                    match layout.ty {
+                        pso::DescriptorType::Buffer { format, .. } => {
+                            format == pso::BufferDescriptorFormat::Texel
+                        }
+                        _ => false,
+                    },
+                    "TexelBuffer",
+                    vec![Arc::clone(&view.handle)],
+                ),
+            };
+            if !matches {
+                self.reporter.report(Error::DescriptorTypeMismatch {
+                    binding,
+                    array_index,
+                    expected: layout.ty,
+                    found,
+                });
+                return None;
+            }
+            if !handles
+                .iter()
+                .all(|handle| self.check_alive(handle, "write_descriptor_sets"))
+            {
+                return None;
+            }
+            contents.push(((binding, array_index), handles));
+            array_index += 1;
+        }
+        Some(contents)
+    }
+}
+
+fn raw_descriptor<'a, B: hal::Backend>(
+    descriptor: &pso::Descriptor<'a, Backend<B>>,
+) -> pso::Descriptor<'a, B> {
+    match *descriptor {
+        pso::Descriptor::Sampler(sampler) => pso::Descriptor::Sampler(&sampler.raw),
+        pso::Descriptor::Image(view, layout) => pso::Descriptor::Image(view.raw(), layout),
+        pso::Descriptor::CombinedImageSampler(view, layout, sampler) => {
+            pso::Descriptor::CombinedImageSampler(view.raw(), layout, &sampler.raw)
+        }
+        pso::Descriptor::Buffer(buffer, ref range) => {
+            pso::Descriptor::Buffer(&buffer.raw, range.clone())
+        }
+        pso::Descriptor::TexelBuffer(view) => pso::Descriptor::TexelBuffer(&view.raw),
+    }
+}
+
+fn raw_base<'a, P, R>(
+    parent: &pso::BasePipeline<'a, P>,
+    raw: impl FnOnce(&'a P) -> &'a R,
+) -> pso::BasePipeline<'a, R> {
+    match *parent {
+        pso::BasePipeline::Pipeline(pipeline) => pso::BasePipeline::Pipeline(raw(pipeline)),
+        pso::BasePipeline::Index(index) => pso::BasePipeline::Index(index),
+        pso::BasePipeline::None => pso::BasePipeline::None,
+    }
+}
+
+impl<B: hal::Backend> device::Device<Backend<B>> for Device<B> {
+    unsafe fn allocate_memory(
+        &self,
+        memory_type: hal::MemoryTypeId,
+        size: u64,
+    ) -> Result<n::Memory<B>, device::AllocationError> {
+        let raw = self.raw.allocate_memory(memory_type, size)?;
+        let properties = self
+            .memory_properties
+            .memory_types
+            .get(memory_type.0)
+            .map(|ty| ty.properties)
+            .unwrap_or_else(memory::Properties::empty);
+        Ok(n::Memory {
+            raw,
+            handle: Handle::new(ResourceKind::Memory),
+            memory_type,
+            properties,
+            size,
+            mapped: AtomicBool::new(false),
+        })
+    }
+
+    unsafe fn free_memory(&self, memory: n::Memory<B>) {
+        memory.handle.destroy();
+        self.raw.free_memory(memory.raw)
+    }
+
+    unsafe fn create_command_pool(
+        &self,
+        family: queue::QueueFamilyId,
+        create_flags: pool::CommandPoolCreateFlags,
+    ) -> Result<CommandPool<B>, device::OutOfMemory> {
+        let raw = self.raw.create_command_pool(family, create_flags)?;
+        Ok(CommandPool::new(raw, Arc::clone(&self.reporter)))
+    }
+
+    unsafe fn destroy_command_pool(&self, pool: CommandPool<B>) {
+        self.raw.destroy_command_pool(pool.raw)
+    }
+
+    unsafe fn create_render_pass<'a, IA, IS, ID>(
+        &self,
+        attachments: IA,
+        subpasses: IS,
+        dependencies: ID,
+    ) -> Result<n::RenderPass<B>, device::OutOfMemory>
+    where
+        IA: IntoIterator,
+        IA::Item: Borrow<pass::Attachment>,
+        IS: IntoIterator,
+        IS::Item: Borrow<pass::SubpassDesc<'a>>,
+        ID: IntoIterator,
+        ID::Item: Borrow<pass::SubpassDependency>,
+    {
+        let subpasses = collect(subpasses);
+        let count = subpasses.len();
+        let raw = self
+            .raw
+            .create_render_pass(attachments, subpasses, dependencies)?;
+        Ok(n::RenderPass {
+            raw,
+            handle: Handle::new(ResourceKind::RenderPass),
+            subpasses: count,
+        })
+    }
+
+    unsafe fn destroy_render_pass(&self, rp: n::RenderPass<B>) {
+        rp.handle.destroy();
+        self.raw.destroy_render_pass(rp.raw)
+    }
+
+    unsafe fn create_pipeline_layout<IS, IR>(
+        &self,
+        set_layouts: IS,
+        push_constant: IR,
+    ) -> Result<n::PipelineLayout<B>, device::OutOfMemory>
+    where
+        IS: IntoIterator,
+        IS::Item: Borrow<n::DescriptorSetLayout<B>>,
+        IR: IntoIterator,
+        IR::Item: Borrow<(pso::ShaderStageFlags, Range<u32>)>,
+    {
+        let set_layouts = collect(set_layouts);
+        let raw = self.raw.create_pipeline_layout(
+            set_layouts.iter().map(|layout| &layout.borrow().raw),
+            push_constant,
+        )?;
+        Ok(n::PipelineLayout { raw })
+    }
+
+    unsafe fn destroy_pipeline_layout(&self, layout: n::PipelineLayout<B>) {
+        self.raw.destroy_pipeline_layout(layout.raw)
+    }
+
+    unsafe fn create_pipeline_cache(
+        &self,
+        data: Option<&[u8]>,
+    ) -> Result<n::PipelineCache<B>, device::OutOfMemory> {
+        let raw = self.raw.create_pipeline_cache(data)?;
+        Ok(n::PipelineCache { raw })
+    }
+
+    unsafe fn get_pipeline_cache_data(
+        &self,
+        cache: &n::PipelineCache<B>,
+    ) -> Result<Vec<u8>, device::OutOfMemory> {
+        self.raw.get_pipeline_cache_data(&cache.raw)
+    }
+
+    unsafe fn merge_pipeline_caches<I>(
+        &self,
+        target: &n::PipelineCache<B>,
+        sources: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::PipelineCache<B>>,
+    {
+        let sources = collect(sources);
+        self.raw
+            .merge_pipeline_caches(&target.raw, sources.iter().map(|cache| &cache.borrow().raw))
+    }
+
+    unsafe fn destroy_pipeline_cache(&self, cache: n::PipelineCache<B>) {
+        self.raw.destroy_pipeline_cache(cache.raw)
+    }
+
+    unsafe fn create_graphics_pipeline<'a>(
+        &self,
+        desc: &pso::GraphicsPipelineDesc<'a, Backend<B>>,
+        cache: Option<&n::PipelineCache<B>>,
+    ) -> Result<n::GraphicsPipeline<B>, pso::CreationError> {
+        let shaders = &desc.shaders;
+        let raw_desc = pso::GraphicsPipelineDesc {
+            shaders: pso::GraphicsShaderSet {
+                vertex: n::raw_entry(&shaders.vertex),
+                hull: shaders.hull.as_ref().map(n::raw_entry),
+                domain: shaders.domain.as_ref().map(n::raw_entry),
+                geometry: shaders.geometry.as_ref().map(n::raw_entry),
+                fragment: shaders.fragment.as_ref().map(n::raw_entry),
+            },
+            rasterizer: desc.rasterizer,
+            vertex_buffers: desc.vertex_buffers.clone(),
+            attributes: desc.attributes.clone(),
+            input_assembler: desc.input_assembler.clone(),
+            blender: desc.blender.clone(),
+            depth_stencil: desc.depth_stencil,
+            multisampling: desc.multisampling.clone(),
+            baked_states: desc.baked_states.clone(),
+            layout: &desc.layout.raw,
+            subpass: n::raw_subpass(&desc.subpass),
+            flags: desc.flags,
+            parent: raw_base(&desc.parent, |pipeline: &'a n::GraphicsPipeline<B>| {
+                &pipeline.raw
+            }),
+        };
+        let raw = self
+            .raw
+            .create_graphics_pipeline(&raw_desc, cache.map(|cache| &cache.raw))?;
+        Ok(n::GraphicsPipeline {
+            raw,
+            handle: Handle::new(ResourceKind::GraphicsPipeline),
+        })
+    }
+
+    unsafe fn destroy_graphics_pipeline(&self, pipeline: n::GraphicsPipeline<B>) {
+        pipeline.handle.destroy();
+        self.raw.destroy_graphics_pipeline(pipeline.raw)
+    }
+
+    unsafe fn create_compute_pipeline<'a>(
+        &self,
+        desc: &pso::ComputePipelineDesc<'a, Backend<B>>,
+        cache: Option<&n::PipelineCache<B>>,
+    ) -> Result<n::ComputePipeline<B>, pso::CreationError> {
+        let raw_desc = pso::ComputePipelineDesc {
+            shader: n::raw_entry(&desc.shader),
+            layout: &desc.layout.raw,
+            flags: desc.flags,
+            parent: raw_base(&desc.parent, |pipeline: &'a n::ComputePipeline<B>| {
+                &pipeline.raw
+            }),
+        };
+        let raw = self
+            .raw
+            .create_compute_pipeline(&raw_desc, cache.map(|cache| &cache.raw))?;
+        Ok(n::ComputePipeline {
+            raw,
+            handle: Handle::new(ResourceKind::ComputePipeline),
+        })
+    }
+
+    unsafe fn destroy_compute_pipeline(&self, pipeline: n::ComputePipeline<B>) {
+        pipeline.handle.destroy();
+        self.raw.destroy_compute_pipeline(pipeline.raw)
+    }
+
+    unsafe fn create_framebuffer<I>(
+        &self,
+        pass: &n::RenderPass<B>,
+        attachments: I,
+        extent: image::Extent,
+    ) -> Result<n::Framebuffer<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::ImageView<B>>,
+    {
+        let attachments = collect(attachments);
+        let handle = Handle::new(ResourceKind::Framebuffer);
+        for view in &attachments {
+            handle.depend_on(&view.borrow().handle);
+        }
+        let raw = self.raw.create_framebuffer(
+            &pass.raw,
+            attachments.iter().map(|view| view.borrow().raw()),
+            extent,
+        )?;
+        Ok(n::Framebuffer { raw, handle })
+    }
+
+    unsafe fn destroy_framebuffer(&self, fb: n::Framebuffer<B>) {
+        fb.handle.destroy();
+        self.raw.destroy_framebuffer(fb.raw)
+    }
+
+    unsafe fn create_shader_module(
+        &self,
+        spirv_data: &[u32],
+    ) -> Result<n::ShaderModule<B>, device::ShaderError> {
+        let raw = self.raw.create_shader_module(spirv_data)?;
+        Ok(n::ShaderModule { raw })
+    }
+
+    unsafe fn destroy_shader_module(&self, shader: n::ShaderModule<B>) {
+        self.raw.destroy_shader_module(shader.raw)
+    }
+
+    unsafe fn create_buffer(
+        &self,
+        size: u64,
+        usage: buffer::Usage,
+    ) -> Result<n::Buffer<B>, buffer::CreationError> {
+        let raw = self.raw.create_buffer(size, usage)?;
+        Ok(n::Buffer {
+            raw,
+            handle: Handle::new(ResourceKind::Buffer),
+            bound: false,
+        })
+    }
+
+    unsafe fn get_buffer_requirements(&self, buf: &n::Buffer<B>) -> memory::Requirements {
+        self.raw.get_buffer_requirements(&buf.raw)
+    }
+
+    unsafe fn bind_buffer_memory(
+        &self,
+        memory: &n::Memory<B>,
+        offset: u64,
+        buf: &mut n::Buffer<B>,
+    ) -> Result<(), device::BindError> {
+        let requirements = self.raw.get_buffer_requirements(&buf.raw);
+        self.check_bind(memory, offset, buf.bound, &buf.handle, requirements)?;
+        self.raw
+            .bind_buffer_memory(&memory.raw, offset, &mut buf.raw)?;
+        buf.bound = true;
+        Ok(())
+    }
+
+    unsafe fn destroy_buffer(&self, buffer: n::Buffer<B>) {
+        buffer.handle.destroy();
+        self.raw.destroy_buffer(buffer.raw)
+    }
+
+    unsafe fn create_buffer_view(
+        &self,
+        buf: &n::Buffer<B>,
+        fmt: Option<format::Format>,
+        range: buffer::SubRange,
+    ) -> Result<n::BufferView<B>, buffer::ViewCreationError> {
+        if !self.check_bound(buf.bound, &buf.handle, "create_buffer_view") {
+            return Err(buffer::ViewCreationError::UnsupportedFormat(fmt));
+        }
+        let raw = self.raw.create_buffer_view(&buf.raw, fmt, range)?;
+        let handle = Handle::new(ResourceKind::BufferView);
+        handle.depend_on(&buf.handle);
+        Ok(n::BufferView { raw, handle })
+    }
+
+    unsafe fn destroy_buffer_view(&self, view: n::BufferView<B>) {
+        view.handle.destroy();
+        self.raw.destroy_buffer_view(view.raw)
+    }
+
+    unsafe fn create_image(
+        &self,
+        kind: image::Kind,
+        mip_levels: image::Level,
+        format: format::Format,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Result<n::Image<B>, image::CreationError> {
+        let raw = self
+            .raw
+            .create_image(kind, mip_levels, format, tiling, usage, view_caps)?;
+        Ok(n::Image {
+            raw,
+            handle: Handle::new(ResourceKind::Image),
+            bound: false,
+        })
+    }
+
+    unsafe fn get_image_requirements(&self, image: &n::Image<B>) -> memory::Requirements {
+        self.raw.get_image_requirements(&image.raw)
+    }
+
+    unsafe fn get_image_subresource_footprint(
+        &self,
+        image: &n::Image<B>,
+        subresource: image::Subresource,
+    ) -> image::SubresourceFootprint {
+        self.raw
+            .get_image_subresource_footprint(&image.raw, subresource)
+    }
+
+    unsafe fn bind_image_memory(
+        &self,
+        memory: &n::Memory<B>,
+        offset: u64,
+        image: &mut n::Image<B>,
+    ) -> Result<(), device::BindError> {
+        let requirements = self.raw.get_image_requirements(&image.raw);
+        self.check_bind(memory, offset, image.bound, &image.handle, requirements)?;
+        self.raw
+            .bind_image_memory(&memory.raw, offset, &mut image.raw)?;
+        image.bound = true;
+        Ok(())
+    }
+
+    unsafe fn destroy_image(&self, image: n::Image<B>) {
+        image.handle.destroy();
+        self.raw.destroy_image(image.raw)
+    }
+
+    unsafe fn create_image_view(
+        &self,
+        image: &n::Image<B>,
+        view_kind: image::ViewKind,
+        format: format::Format,
+        swizzle: format::Swizzle,
+        range: image::SubresourceRange,
+    ) -> Result<n::ImageView<B>, image::ViewCreationError> {
+        if !self.check_bound(image.bound, &image.handle, "create_image_view") {
+            return Err(image::ViewCreationError::Unsupported);
+        }
+        let raw = self
+            .raw
+            .create_image_view(&image.raw, view_kind, format, swizzle, range)?;
+        let handle = Handle::new(ResourceKind::ImageView);
+        handle.depend_on(&image.handle);
+        Ok(n::ImageView {
+            raw: n::RawImageView::Owned(raw),
+            handle,
+        })
+    }
+
+    unsafe fn destroy_image_view(&self, view: n::ImageView<B>) {
+        view.handle.destroy();
+        match view.raw {
+            n::RawImageView::Owned(raw) => self.raw.destroy_image_view(raw),
+            // The view is owned by the image acquired from the surface.
+            n::RawImageView::Swapchain(_) => {}
+        }
+    }
+
+    unsafe fn create_sampler(
+        &self,
+        desc: &image::SamplerDesc,
+    ) -> Result<n::Sampler<B>, device::AllocationError> {
+        let raw = self.raw.create_sampler(desc)?;
+        Ok(n::Sampler {
+            raw,
+            handle: Handle::new(ResourceKind::Sampler),
+        })
+    }
+
+    unsafe fn destroy_sampler(&self, sampler: n::Sampler<B>) {
+        sampler.handle.destroy();
+        self.raw.destroy_sampler(sampler.raw)
+    }
+
+    unsafe fn create_descriptor_pool<I>(
+        &self,
+        max_sets: usize,
+        descriptor_ranges: I,
+        flags: pso::DescriptorPoolCreateFlags,
+    ) -> Result<DescriptorPool<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorRangeDesc>,
+    {
+        let raw = self
+            .raw
+            .create_descriptor_pool(max_sets, descriptor_ranges, flags)?;
+        Ok(DescriptorPool::new(raw))
+    }
+
+    unsafe fn destroy_descriptor_pool(&self, pool: DescriptorPool<B>) {
+        pool.destroy_sets();
+        self.raw.destroy_descriptor_pool(pool.raw)
+    }
+
+    unsafe fn create_descriptor_set_layout<I, J>(
+        &self,
+        bindings: I,
+        immutable_samplers: J,
+    ) -> Result<n::DescriptorSetLayout<B>, device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetLayoutBinding>,
+        J: IntoIterator,
+        J::Item: Borrow<n::Sampler<B>>,
+    {
+        let bindings = bindings
+            .into_iter()
+            .map(|binding| binding.borrow().clone())
+            .collect::<Vec<_>>();
+        let immutable_samplers = collect(immutable_samplers);
+        let raw = self.raw.create_descriptor_set_layout(
+            &bindings,
+            immutable_samplers
+                .iter()
+                .map(|sampler| &sampler.borrow().raw),
+        )?;
+        Ok(n::DescriptorSetLayout {
+            raw,
+            bindings: Arc::new(bindings),
+        })
+    }
+
+    unsafe fn destroy_descriptor_set_layout(&self, layout: n::DescriptorSetLayout<B>) {
+        self.raw.destroy_descriptor_set_layout(layout.raw)
+    }
+
+    unsafe fn write_descriptor_sets<'a, I, J>(&self, write_iter: I)
+    where
+        I: IntoIterator<Item = pso::DescriptorSetWrite<'a, Backend<B>, J>>,
+        J: IntoIterator,
+        J::Item: Borrow<pso::Descriptor<'a, Backend<B>>>,
+    {
+        let mut writes = Vec::new();
+        for write in write_iter {
+            let set = write.set;
+            let descriptors = write
+                .descriptors
+                .into_iter()
+                .map(|descriptor| match *descriptor.borrow() {
+                    pso::Descriptor::Sampler(sampler) => pso::Descriptor::Sampler(sampler),
+                    pso::Descriptor::Image(view, layout) => pso::Descriptor::Image(view, layout),
+                    pso::Descriptor::CombinedImageSampler(view, layout, sampler) => {
+                        pso::Descriptor::CombinedImageSampler(view, layout, sampler)
+                    }
+                    pso::Descriptor::Buffer(buffer, ref range) => {
+                        pso::Descriptor::Buffer(buffer, range.clone())
+                    }
+                    pso::Descriptor::TexelBuffer(view) => pso::Descriptor::TexelBuffer(view),
+                })
+                .collect::<Vec<_>>();
+            let contents = match self.check_descriptors(
+                set,
+                write.binding,
+                write.array_offset,
+                &descriptors,
+            ) {
+                Some(contents) => contents,
+                None => continue,
+            };
+            set.contents.lock().extend(contents);
+            writes.push(pso::DescriptorSetWrite {
+                set: &set.raw,
+                binding: write.binding,
+                array_offset: write.array_offset,
+                descriptors: descriptors.iter().map(raw_descriptor).collect::<Vec<_>>(),
+            });
+        }
+        self.raw.write_descriptor_sets(writes)
+    }
+
+    unsafe fn copy_descriptor_sets<'a, I>(&self, copy_iter: I)
+    where
+        I: IntoIterator,
+        I::Item: Borrow<pso::DescriptorSetCopy<'a, Backend<B>>>,
+    {
+        let copies = collect(copy_iter);
+        for copy in &copies {
+            let copy = copy.borrow();
+            let copied = {
+                let src = copy.src_set.contents.lock();
+                (0 .. copy.count as pso::DescriptorArrayIndex)
+                    .map(|i| {
+                        let key = (copy.src_binding, copy.src_array_offset + i);
+                        src.get(&key).cloned().unwrap_or_default()
+                    })
+                    .collect::<Vec<_>>()
+            };
+            let mut dst = copy.dst_set.contents.lock();
+            for (i, handles) in copied.into_iter().enumerate() {
+                let key = (
+                    copy.dst_binding,
+                    copy.dst_array_offset + i as pso::DescriptorArrayIndex,
+                );
+                dst.insert(key, handles);
+            }
+        }
+        self.raw.copy_descriptor_sets(copies.iter().map(|copy| {
+            let copy = copy.borrow();
+            pso::DescriptorSetCopy {
+                src_set: &copy.src_set.raw,
+                src_binding: copy.src_binding,
+                src_array_offset: copy.src_array_offset,
+                dst_set: &copy.dst_set.raw,
+                dst_binding: copy.dst_binding,
+                dst_array_offset: copy.dst_array_offset,
+                count: copy.count,
+            }
+        }))
+    }
+
+    unsafe fn map_memory(
+        &self,
+        memory: &n::Memory<B>,
+        segment: memory::Segment,
+    ) -> Result<*mut u8, device::MapError> {
+        if !memory.properties.contains(memory::Properties::CPU_VISIBLE) {
+            self.reporter
+                .report(Error::NotHostVisible(memory.handle.resource));
+            return Err(device::MapError::Access);
+        }
+        if !self.check_segment(memory, &segment) {
+            return Err(device::MapError::OutOfBounds);
+        }
+        if memory.mapped.swap(true, Ordering::AcqRel) {
+            self.reporter
+                .report(Error::AlreadyMapped(memory.handle.resource));
+            return Err(device::MapError::MappingFailed);
+        }
+        let result = self.raw.map_memory(&memory.raw, segment);
+        if result.is_err() {
+            memory.mapped.store(false, Ordering::Release);
+        }
+        result
+    }
+
+    unsafe fn flush_mapped_memory_ranges<'a, I>(&self, ranges: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory<B>, memory::Segment)>,
+    {
+        let ranges = self.mapped_ranges(ranges, "flush_mapped_memory_ranges");
+        self.raw.flush_mapped_memory_ranges(ranges)
+    }
+
+    unsafe fn invalidate_mapped_memory_ranges<'a, I>(
+        &self,
+        ranges: I,
+    ) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<(&'a n::Memory<B>, memory::Segment)>,
+    {
+        let ranges = self.mapped_ranges(ranges, "invalidate_mapped_memory_ranges");
+        self.raw.invalidate_mapped_memory_ranges(ranges)
+    }
+
+    unsafe fn unmap_memory(&self, memory: &n::Memory<B>) {
+        if !memory.mapped.swap(false, Ordering::AcqRel) {
+            self.reporter.report(Error::NotMapped {
+                resource: memory.handle.resource,
+                command: "unmap_memory",
+            });
+            return;
+        }
+        self.raw.unmap_memory(&memory.raw)
+    }
+
+    fn create_semaphore(&self) -> Result<n::Semaphore<B>, device::OutOfMemory> {
+        let raw = self.raw.create_semaphore()?;
+        Ok(n::Semaphore { raw })
+    }
+
+    unsafe fn destroy_semaphore(&self, semaphore: n::Semaphore<B>) {
+        self.raw.destroy_semaphore(semaphore.raw)
+    }
+
+    fn create_fence(&self, signaled: bool) -> Result<n::Fence<B>, device::OutOfMemory> {
+        let raw = self.raw.create_fence(signaled)?;
+        Ok(n::Fence { raw })
+    }
+
+    unsafe fn reset_fence(&self, fence: &n::Fence<B>) -> Result<(), device::OutOfMemory> {
+        self.raw.reset_fence(&fence.raw)
+    }
+
+    unsafe fn reset_fences<I>(&self, fences: I) -> Result<(), device::OutOfMemory>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::Fence<B>>,
+    {
+        let fences = collect(fences);
+        self.raw
+            .reset_fences(fences.iter().map(|fence| &fence.borrow().raw))
+    }
+
+    unsafe fn wait_for_fence(
+        &self,
+        fence: &n::Fence<B>,
+        timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        self.raw.wait_for_fence(&fence.raw, timeout_ns)
+    }
+
+    unsafe fn wait_for_fences<I>(
+        &self,
+        fences: I,
+        wait: device::WaitFor,
+        timeout_ns: u64,
+    ) -> Result<bool, device::OomOrDeviceLost>
+    where
+        I: IntoIterator,
+        I::Item: Borrow<n::Fence<B>>,
+    {
+        let fences = collect(fences);
+        self.raw.wait_for_fences(
+            fences.iter().map(|fence| &fence.borrow().raw),
+            wait,
+            timeout_ns,
+        )
+    }
+
+    unsafe fn get_fence_status(&self, fence: &n::Fence<B>) -> Result<bool, device::DeviceLost> {
+        self.raw.get_fence_status(&fence.raw)
+    }
+
+    unsafe fn destroy_fence(&self, fence: n::Fence<B>) {
+        self.raw.destroy_fence(fence.raw)
+    }
+
+    fn create_event(&self) -> Result<n::Event<B>, device::OutOfMemory> {
+        let raw = self.raw.create_event()?;
+        Ok(n::Event {
+            raw,
+            handle: Handle::new(ResourceKind::Event),
+        })
+    }
+
+    unsafe fn destroy_event(&self, event: n::Event<B>) {
+        event.handle.destroy();
+        self.raw.destroy_event(event.raw)
+    }
+
+    unsafe fn get_event_status(
+        &self,
+        event: &n::Event<B>,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        self.raw.get_event_status(&event.raw)
+    }
+
+    unsafe fn set_event(&self, event: &n::Event<B>) -> Result<(), device::OutOfMemory> {
+        self.raw.set_event(&event.raw)
+    }
+
+    unsafe fn reset_event(&self, event: &n::Event<B>) -> Result<(), device::OutOfMemory> {
+        self.raw.reset_event(&event.raw)
+    }
+
+    unsafe fn create_query_pool(
+        &self,
+        ty: query::Type,
+        count: query::Id,
+    ) -> Result<n::QueryPool<B>, query::CreationError> {
+        let raw = self.raw.create_query_pool(ty, count)?;
+        Ok(n::QueryPool {
+            raw,
+            handle: Handle::new(ResourceKind::QueryPool),
+        })
+    }
+
+    unsafe fn destroy_query_pool(&self, pool: n::QueryPool<B>) {
+        pool.handle.destroy();
+        self.raw.destroy_query_pool(pool.raw)
+    }
+
+    unsafe fn get_query_pool_results(
+        &self,
+        pool: &n::QueryPool<B>,
+        queries: Range<query::Id>,
+        data: &mut [u8],
+        stride: buffer::Offset,
+        flags: query::ResultFlags,
+    ) -> Result<bool, device::OomOrDeviceLost> {
+        self.raw
+            .get_query_pool_results(&pool.raw, queries, data, stride, flags)
+    }
+
+    unsafe fn create_swapchain(
+        &self,
+        surface: &mut Surface<B>,
+        config: window::SwapchainConfig,
+        old_swapchain: Option<Swapchain<B>>,
+    ) -> Result<(Swapchain<B>, Vec<n::Image<B>>), window::CreationError> {
+        let (raw, images) = self.raw.create_swapchain(
+            &mut surface.raw,
+            config,
+            old_swapchain.map(|swapchain| swapchain.raw),
+        )?;
+        let images = images
+            .into_iter()
+            .map(|raw| n::Image {
+                raw,
+                handle: Handle::new(ResourceKind::Image),
+                bound: true,
+            })
+            .collect();
+        Ok((Swapchain { raw }, images))
+    }
+
+    unsafe fn destroy_swapchain(&self, swapchain: Swapchain<B>) {
+        self.raw.destroy_swapchain(swapchain.raw)
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        self.raw.wait_idle()
+    }
+
+    unsafe fn set_image_name(&self, image: &mut n::Image<B>, name: &str) {
+        self.raw.set_image_name(&mut image.raw, name)
+    }
+
+    unsafe fn set_buffer_name(&self, buffer: &mut n::Buffer<B>, name: &str) {
+        self.raw.set_buffer_name(&mut buffer.raw, name)
+    }
+
+    unsafe fn set_command_buffer_name(&self, command_buffer: &mut CommandBuffer<B>, name: &str) {
+        self.raw
+            .set_command_buffer_name(&mut command_buffer.raw, name)
+    }
+
+    unsafe fn set_semaphore_name(&self, semaphore: &mut n::Semaphore<B>, name: &str) {
+        self.raw.set_semaphore_name(&mut semaphore.raw, name)
+    }
+
+    unsafe fn set_fence_name(&self, fence: &mut n::Fence<B>, name: &str) {
+        self.raw.set_fence_name(&mut fence.raw, name)
+    }
+
+    unsafe fn set_framebuffer_name(&self, framebuffer: &mut n::Framebuffer<B>, name: &str) {
+        self.raw.set_framebuffer_name(&mut framebuffer.raw, name)
+    }
+
+    unsafe fn set_render_pass_name(&self, render_pass: &mut n::RenderPass<B>, name: &str) {
+        self.raw.set_render_pass_name(&mut render_pass.raw, name)
+    }
+
+    unsafe fn set_descriptor_set_name(&self, descriptor_set: &mut n::DescriptorSet<B>, name: &str) {
+        self.raw
+            .set_descriptor_set_name(&mut descriptor_set.raw, name)
+    }
+
+    unsafe fn set_descriptor_set_layout_name(
+        &self,
+        descriptor_set_layout: &mut n::DescriptorSetLayout<B>,
+        name: &str,
+    ) {
+        self.raw
+            .set_descriptor_set_layout_name(&mut descriptor_set_layout.raw, name)
+    }
+}
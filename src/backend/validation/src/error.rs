@@ -0,0 +1,238 @@
+use std::fmt;
+
+use hal::{memory::Segment, pso, MemoryTypeId};
+use parking_lot::Mutex;
+
+/// Kind of object an error refers to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ResourceKind {
+    Memory,
+    Buffer,
+    BufferView,
+    Image,
+    ImageView,
+    Sampler,
+    RenderPass,
+    Framebuffer,
+    GraphicsPipeline,
+    ComputePipeline,
+    DescriptorSet,
+    Event,
+    QueryPool,
+}
+
+/// Identifies an object created through the validation backend.
+///
+/// Identifiers are unique for the lifetime of the process.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct Resource {
+    pub kind: ResourceKind,
+    pub id: u64,
+}
+
+impl fmt::Display for Resource {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(fmt, "{:?}#{}", self.kind, self.id)
+    }
+}
+
+/// A violated precondition of the `gfx-hal` API.
+///
+/// `command` fields name the `Device` or `CommandBuffer` method during which
+/// the violation was detected.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Error {
+    /// A resource was used after it, or an object it relies on, was destroyed.
+    Destroyed {
+        resource: Resource,
+        command: &'static str,
+    },
+    /// Memory was bound to a buffer or image which already has memory bound.
+    AlreadyBound(Resource),
+    /// A buffer or image was used before memory was bound to it.
+    Unbound {
+        resource: Resource,
+        command: &'static str,
+    },
+    /// The memory type is not allowed by the requirements of the resource.
+    WrongMemoryType {
+        resource: Resource,
+        memory_type: MemoryTypeId,
+    },
+    /// The bind offset does not satisfy the alignment of the resource.
+    MisalignedBind {
+        resource: Resource,
+        offset: u64,
+        alignment: u64,
+    },
+    /// The resource does not fit in the memory after the bind offset.
+    BindOutOfBounds {
+        resource: Resource,
+        offset: u64,
+        size: u64,
+        memory_size: u64,
+    },
+    /// Memory without `CPU_VISIBLE` properties was mapped.
+    NotHostVisible(Resource),
+    /// Memory was mapped while already being mapped.
+    AlreadyMapped(Resource),
+    /// Memory was accessed through a mapping while not being mapped.
+    NotMapped {
+        resource: Resource,
+        command: &'static str,
+    },
+    /// A segment extends past the end of the memory object.
+    SegmentOutOfBounds {
+        resource: Resource,
+        segment: Segment,
+        memory_size: u64,
+    },
+    /// A command was recorded into a command buffer which is not recording.
+    NotRecording(&'static str),
+    /// `begin` was called on a command buffer which is already recording.
+    AlreadyRecording,
+    /// A command buffer was submitted or executed without being fully recorded,
+    /// or after it was reset.
+    NotExecutable,
+    /// A command only allowed inside of a render pass was recorded outside of one.
+    OutsideRenderPass(&'static str),
+    /// A command only allowed outside of a render pass was recorded inside of one.
+    InsideRenderPass(&'static str),
+    /// `next_subpass` was called in the last subpass of a render pass.
+    NoNextSubpass,
+    /// `end_render_pass` was called before reaching the last subpass.
+    SubpassesRemaining(usize),
+    /// A draw or dispatch was recorded without a pipeline bound.
+    NoPipeline(&'static str),
+    /// An indexed draw was recorded without an index buffer bound.
+    NoIndexBuffer(&'static str),
+    /// A descriptor was written to a binding which doesn't exist in the set layout.
+    DescriptorOutOfBounds {
+        binding: pso::DescriptorBinding,
+        array_index: pso::DescriptorArrayIndex,
+    },
+    /// A descriptor doesn't match the type of the binding it was written to.
+    DescriptorTypeMismatch {
+        binding: pso::DescriptorBinding,
+        array_index: pso::DescriptorArrayIndex,
+        expected: pso::DescriptorType,
+        found: &'static str,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, fmt: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Error::Destroyed { resource, command } => {
+                write!(fmt, "{} uses destroyed resource {}", command, resource)
+            }
+            Error::AlreadyBound(resource) => write!(fmt, "{} already has memory bound", resource),
+            Error::Unbound { resource, command } => {
+                write!(fmt, "{} uses {} without memory bound", command, resource)
+            }
+            Error::WrongMemoryType {
+                resource,
+                memory_type,
+            } => write!(
+                fmt,
+                "{} can't be bound to memory of type {}",
+                resource, memory_type.0
+            ),
+            Error::MisalignedBind {
+                resource,
+                offset,
+                alignment,
+            } => write!(
+                fmt,
+                "{} bound at offset {} which is not aligned to {}",
+                resource, offset, alignment
+            ),
+            Error::BindOutOfBounds {
+                resource,
+                offset,
+                size,
+                memory_size,
+            } => write!(
+                fmt,
+                "{} of size {} bound at offset {} of memory with size {}",
+                resource, size, offset, memory_size
+            ),
+            Error::NotHostVisible(resource) => write!(fmt, "{} is not CPU visible", resource),
+            Error::AlreadyMapped(resource) => write!(fmt, "{} is already mapped", resource),
+            Error::NotMapped { resource, command } => {
+                write!(fmt, "{} requires {} to be mapped", command, resource)
+            }
+            Error::SegmentOutOfBounds {
+                resource,
+                ref segment,
+                memory_size,
+            } => write!(
+                fmt,
+                "{:?} is out of bounds of {} with size {}",
+                segment, resource, memory_size
+            ),
+            Error::NotRecording(command) => {
+                write!(fmt, "{} recorded while not recording", command)
+            }
+            Error::AlreadyRecording => write!(fmt, "Command buffer is already recording"),
+            Error::NotExecutable => write!(fmt, "Command buffer is not executable"),
+            Error::OutsideRenderPass(command) => {
+                write!(fmt, "{} recorded outside of a render pass", command)
+            }
+            Error::InsideRenderPass(command) => {
+                write!(fmt, "{} recorded inside of a render pass", command)
+            }
+            Error::NoNextSubpass => write!(fmt, "next_subpass called in the last subpass"),
+            Error::SubpassesRemaining(count) => write!(
+                fmt,
+                "end_render_pass called with {} subpasses remaining",
+                count
+            ),
+            Error::NoPipeline(command) => write!(fmt, "{} recorded without a pipeline", command),
+            Error::NoIndexBuffer(command) => {
+                write!(fmt, "{} recorded without an index buffer", command)
+            }
+            Error::DescriptorOutOfBounds {
+                binding,
+                array_index,
+            } => write!(
+                fmt,
+                "Descriptor written to binding {}[{}] which is out of bounds",
+                binding, array_index
+            ),
+            Error::DescriptorTypeMismatch {
+                binding,
+                array_index,
+                expected,
+                found,
+            } => write!(
+                fmt,
+                "{} descriptor written to binding {}[{}] of type {:?}",
+                found, binding, array_index, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Collects the errors found by the validation backend.
+///
+/// One reporter is shared by an instance and every object created from it.
+/// Errors are also logged as they are reported.
+#[derive(Debug, Default)]
+pub struct Reporter {
+    errors: Mutex<Vec<Error>>,
+}
+
+impl Reporter {
+    pub(crate) fn report(&self, error: Error) {
+        error!("{}", error);
+        self.errors.lock().push(error);
+    }
+
+    /// Removes and returns the errors reported so far.
+    pub fn take_errors(&self) -> Vec<Error> {
+        std::mem::take(&mut *self.errors.lock())
+    }
+}
@@ -0,0 +1,1151 @@
+//! Validation layer wrapping another backend.
+//!
+//! `Backend<B>` forwards every call to the backend `B` while tracking the
+//! state of the objects created through it, and reports API misuse as
+//! structured [`Error`](enum.Error.html)s to the [`Reporter`](struct.Reporter.html)
+//! shared by the instance and all of its objects. Calls violating a
+//! precondition are not forwarded; where the signature allows, the closest
+//! matching `gfx-hal` error is returned instead.
+
+#![allow(missing_docs, missing_copy_implementations)]
+
+#[macro_use]
+extern crate log;
+extern crate gfx_hal as hal;
+
+use std::borrow::Borrow;
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+use std::sync::Arc;
+
+use hal::{adapter, format, image, queue as q, window};
+
+pub use self::command::CommandBuffer;
+pub use self::device::Device;
+pub use self::error::{Error, Reporter, Resource, ResourceKind};
+pub use self::native::*;
+pub use self::pool::{CommandPool, DescriptorPool};
+pub use self::queue::CommandQueue;
+
+mod command;
+mod device;
+mod error;
+mod native;
+mod pool;
+mod queue;
+
+/// Validation backend wrapping the backend `B`.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct Backend<B>(PhantomData<B>);
+
+impl<B: hal::Backend> hal::Backend for Backend<B> {
+    type Instance = Instance<B>;
+    type PhysicalDevice = PhysicalDevice<B>;
+    type Device = Device<B>;
+
+    type Surface = Surface<B>;
+    type Swapchain = Swapchain<B>;
+
+    type QueueFamily = QueueFamily<B>;
+    type CommandQueue = CommandQueue<B>;
+    type CommandBuffer = CommandBuffer<B>;
+
+    type Memory = Memory<B>;
+    type CommandPool = CommandPool<B>;
+
+    type ShaderModule = ShaderModule<B>;
+    type RenderPass = RenderPass<B>;
+    type Framebuffer = Framebuffer<B>;
+
+    type Buffer = Buffer<B>;
+    type BufferView = BufferView<B>;
+    type Image = Image<B>;
+    type ImageView = ImageView<B>;
+    type Sampler = Sampler<B>;
+
+    type ComputePipeline = ComputePipeline<B>;
+    type GraphicsPipeline = GraphicsPipeline<B>;
+    type PipelineCache = PipelineCache<B>;
+    type PipelineLayout = PipelineLayout<B>;
+    type DescriptorSetLayout = DescriptorSetLayout<B>;
+    type DescriptorPool = DescriptorPool<B>;
+    type DescriptorSet = DescriptorSet<B>;
+
+    type Fence = Fence<B>;
+    type Semaphore = Semaphore<B>;
+    type Event = Event<B>;
+    type QueryPool = QueryPool<B>;
+}
+
+#[derive(Debug)]
+pub struct Instance<B: hal::Backend> {
+    raw: B::Instance,
+    reporter: Arc<Reporter>,
+}
+
+impl<B: hal::Backend> Instance<B> {
+    /// Wraps an instance of the inner backend.
+    pub fn new(raw: B::Instance) -> Self {
+        Instance {
+            raw,
+            reporter: Arc::new(Reporter::default()),
+        }
+    }
+
+    pub fn raw(&self) -> &B::Instance {
+        &self.raw
+    }
+
+    /// Returns the reporter collecting the errors of every object created from this instance.
+    pub fn reporter(&self) -> &Arc<Reporter> {
+        &self.reporter
+    }
+}
+
+impl<B: hal::Backend> hal::Instance<Backend<B>> for Instance<B> {
+    fn create(name: &str, version: u32) -> Result<Self, hal::UnsupportedBackend> {
+        B::Instance::create(name, version).map(Instance::new)
+    }
+
+    fn enumerate_adapters(&self) -> Vec<adapter::Adapter<Backend<B>>> {
+        self.raw
+            .enumerate_adapters()
+            .into_iter()
+            .map(|adapter| adapter::Adapter {
+                info: adapter.info,
+                physical_device: PhysicalDevice {
+                    raw: adapter.physical_device,
+                    reporter: Arc::clone(&self.reporter),
+                },
+                queue_families: adapter
+                    .queue_families
+                    .into_iter()
+                    .map(|raw| QueueFamily { raw })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    unsafe fn create_surface(
+        &self,
+        has_handle: &impl raw_window_handle::HasRawWindowHandle,
+    ) -> Result<Surface<B>, window::InitError> {
+        self.raw
+            .create_surface(has_handle)
+            .map(|raw| Surface { raw })
+    }
+
+    unsafe fn destroy_surface(&self, surface: Surface<B>) {
+        self.raw.destroy_surface(surface.raw)
+    }
+}
+
+#[derive(Debug)]
+pub struct PhysicalDevice<B: hal::Backend> {
+    raw: B::PhysicalDevice,
+    reporter: Arc<Reporter>,
+}
+
+impl<B: hal::Backend> adapter::PhysicalDevice<Backend<B>> for PhysicalDevice<B> {
+    unsafe fn open(
+        &self,
+        families: &[(&QueueFamily<B>, &[q::QueuePriority])],
+        requested_features: hal::Features,
+    ) -> Result<adapter::Gpu<Backend<B>>, hal::device::CreationError> {
+        let families = families
+            .iter()
+            .map(|&(family, priorities)| (&family.raw, priorities))
+            .collect::<Vec<_>>();
+        let gpu = self.raw.open(&families, requested_features)?;
+
+        let reporter = &self.reporter;
+        Ok(adapter::Gpu {
+            device: Device::new(
+                gpu.device,
+                self.raw.memory_properties(),
+                Arc::clone(reporter),
+            ),
+            queue_groups: gpu
+                .queue_groups
+                .into_iter()
+                .map(|group| q::QueueGroup {
+                    family: group.family,
+                    queues: group
+                        .queues
+                        .into_iter()
+                        .map(|raw| CommandQueue::new(raw, Arc::clone(reporter)))
+                        .collect(),
+                })
+                .collect(),
+        })
+    }
+
+    fn format_properties(&self, format: Option<format::Format>) -> format::Properties {
+        self.raw.format_properties(format)
+    }
+
+    fn image_format_properties(
+        &self,
+        format: format::Format,
+        dimensions: u8,
+        tiling: image::Tiling,
+        usage: image::Usage,
+        view_caps: image::ViewCapabilities,
+    ) -> Option<image::FormatProperties> {
+        self.raw
+            .image_format_properties(format, dimensions, tiling, usage, view_caps)
+    }
+
+    fn memory_properties(&self) -> adapter::MemoryProperties {
+        self.raw.memory_properties()
+    }
+
+    fn features(&self) -> hal::Features {
+        self.raw.features()
+    }
+
+    fn hints(&self) -> hal::Hints {
+        self.raw.hints()
+    }
+
+    fn limits(&self) -> hal::Limits {
+        self.raw.limits()
+    }
+
+    fn is_valid_cache(&self, cache: &[u8]) -> bool {
+        self.raw.is_valid_cache(cache)
+    }
+}
+
+#[derive(Debug)]
+pub struct QueueFamily<B: hal::Backend> {
+    raw: B::QueueFamily,
+}
+
+impl<B: hal::Backend> q::QueueFamily for QueueFamily<B> {
+    fn queue_type(&self) -> q::QueueType {
+        self.raw.queue_type()
+    }
+    fn max_queues(&self) -> usize {
+        self.raw.max_queues()
+    }
+    fn id(&self) -> q::QueueFamilyId {
+        self.raw.id()
+    }
+}
+
+#[derive(Debug)]
+pub struct Surface<B: hal::Backend> {
+    raw: B::Surface,
+}
+
+impl<B: hal::Backend> window::Surface<Backend<B>> for Surface<B> {
+    fn supports_queue_family(&self, family: &QueueFamily<B>) -> bool {
+        self.raw.supports_queue_family(&family.raw)
+    }
+
+    fn capabilities(&self, physical_device: &PhysicalDevice<B>) -> window::SurfaceCapabilities {
+        self.raw.capabilities(&physical_device.raw)
+    }
+
+    fn supported_formats(
+        &self,
+        physical_device: &PhysicalDevice<B>,
+    ) -> Option<Vec<format::Format>> {
+        self.raw.supported_formats(&physical_device.raw)
+    }
+}
+
+impl<B: hal::Backend> window::PresentationSurface<Backend<B>> for Surface<B> {
+    type SwapchainImage = SwapchainImage<B>;
+
+    unsafe fn configure_swapchain(
+        &mut self,
+        device: &Device<B>,
+        config: window::SwapchainConfig,
+    ) -> Result<(), window::CreationError> {
+        self.raw.configure_swapchain(device.raw(), config)
+    }
+
+    unsafe fn unconfigure_swapchain(&mut self, device: &Device<B>) {
+        self.raw.unconfigure_swapchain(device.raw())
+    }
+
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+    ) -> Result<(SwapchainImage<B>, Option<window::Suboptimal>), window::AcquireError> {
+        let (raw, suboptimal) = self.raw.acquire_image(timeout_ns)?;
+        Ok((SwapchainImage::new(raw), suboptimal))
+    }
+}
+
+type RawSwapchainImage<B> =
+    <<B as hal::Backend>::Surface as window::PresentationSurface<B>>::SwapchainImage;
+
+/// Image acquired from a surface, along with a view of it.
+#[derive(Debug)]
+pub struct SwapchainImage<B: hal::Backend> {
+    // Declared first so the view is dropped before the image it points into.
+    view: ImageView<B>,
+    raw: Box<RawSwapchainImage<B>>,
+}
+
+impl<B: hal::Backend> SwapchainImage<B> {
+    fn new(raw: RawSwapchainImage<B>) -> Self {
+        let raw = Box::new(raw);
+        let view = NonNull::from((*raw).borrow());
+        SwapchainImage {
+            view: ImageView {
+                raw: RawImageView::Swapchain(view),
+                handle: native::Handle::new(ResourceKind::ImageView),
+            },
+            raw,
+        }
+    }
+
+    fn into_raw(self) -> RawSwapchainImage<B> {
+        self.view.handle.destroy();
+        *self.raw
+    }
+}
+
+impl<B: hal::Backend> Borrow<ImageView<B>> for SwapchainImage<B> {
+    fn borrow(&self) -> &ImageView<B> {
+        &self.view
+    }
+}
+
+#[derive(Debug)]
+pub struct Swapchain<B: hal::Backend> {
+    raw: B::Swapchain,
+}
+
+impl<B: hal::Backend> window::Swapchain<Backend<B>> for Swapchain<B> {
+    unsafe fn acquire_image(
+        &mut self,
+        timeout_ns: u64,
+        semaphore: Option<&Semaphore<B>>,
+        fence: Option<&Fence<B>>,
+    ) -> Result<(window::SwapImageIndex, Option<window::Suboptimal>), window::AcquireError> {
+        self.raw.acquire_image(
+            timeout_ns,
+            semaphore.map(|semaphore| &semaphore.raw),
+            fence.map(|fence| &fence.raw),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gfx_backend_mock as mock;
+    use hal::adapter::PhysicalDevice as _;
+    use hal::command::{self as com, CommandBuffer as _};
+    use hal::device::{self, Device as _};
+    use hal::pool::CommandPool as _;
+    use hal::pso::{self, DescriptorPool as _};
+    use hal::queue::CommandQueue as _;
+    use hal::Instance as _;
+    use hal::{buffer, memory, pass, pool};
+    use std::ops::Range;
+
+    type Validation = Backend<mock::Backend>;
+
+    struct Context {
+        reporter: Arc<Reporter>,
+        device: Device<mock::Backend>,
+        queue: CommandQueue<mock::Backend>,
+    }
+
+    impl Context {
+        fn new() -> Self {
+            let instance = Instance::<mock::Backend>::create("test", 1).unwrap();
+            let reporter = Arc::clone(instance.reporter());
+            let adapter = instance.enumerate_adapters().remove(0);
+            let family = &adapter.queue_families[0];
+            let mut gpu = unsafe {
+                adapter
+                    .physical_device
+                    .open(&[(family, &[1.0])], hal::Features::empty())
+                    .unwrap()
+            };
+            mock::take_calls();
+            Context {
+                reporter,
+                device: gpu.device,
+                queue: gpu.queue_groups.remove(0).queues.remove(0),
+            }
+        }
+
+        /// Checks the errors reported and the calls forwarded to the mock
+        /// backend since the last check.
+        fn check(&self, errors: Vec<Error>, calls: &[&str]) {
+            assert_eq!(self.reporter.take_errors(), errors);
+            assert_eq!(mock::take_calls(), calls);
+        }
+
+        unsafe fn memory(
+            &self,
+            memory_type: hal::MemoryTypeId,
+            size: u64,
+        ) -> Memory<mock::Backend> {
+            let memory = self.device.allocate_memory(memory_type, size).unwrap();
+            mock::take_calls();
+            memory
+        }
+
+        unsafe fn buffer(&self, size: u64) -> Buffer<mock::Backend> {
+            let buffer = self
+                .device
+                .create_buffer(size, buffer::Usage::all())
+                .unwrap();
+            mock::take_calls();
+            buffer
+        }
+
+        /// Creates a buffer along with the memory bound to it.
+        unsafe fn bound_buffer(&self, size: u64) -> (Memory<mock::Backend>, Buffer<mock::Backend>) {
+            let memory = self.memory(mock::HOST_VISIBLE, size);
+            let mut buffer = self.buffer(size);
+            self.device
+                .bind_buffer_memory(&memory, 0, &mut buffer)
+                .unwrap();
+            mock::take_calls();
+            (memory, buffer)
+        }
+
+        unsafe fn image(&self) -> Image<mock::Backend> {
+            let image = self
+                .device
+                .create_image(
+                    hal::image::Kind::D2(4, 4, 1, 1),
+                    1,
+                    hal::format::Format::Rgba8Unorm,
+                    hal::image::Tiling::Optimal,
+                    hal::image::Usage::SAMPLED,
+                    hal::image::ViewCapabilities::empty(),
+                )
+                .unwrap();
+            mock::take_calls();
+            image
+        }
+
+        /// Creates a primary command buffer which began recording.
+        unsafe fn recording(&self) -> (CommandPool<mock::Backend>, CommandBuffer<mock::Backend>) {
+            let mut pool = self
+                .device
+                .create_command_pool(
+                    q::QueueFamilyId(0),
+                    pool::CommandPoolCreateFlags::RESET_INDIVIDUAL,
+                )
+                .unwrap();
+            let mut cmd = pool.allocate_one(com::Level::Primary);
+            cmd.begin_primary(com::CommandBufferFlags::empty());
+            mock::take_calls();
+            (pool, cmd)
+        }
+
+        /// Creates a render pass without attachments and a framebuffer for it.
+        unsafe fn render_pass(
+            &self,
+            subpasses: usize,
+        ) -> (RenderPass<mock::Backend>, Framebuffer<mock::Backend>) {
+            let subpass = pass::SubpassDesc {
+                colors: &[],
+                depth_stencil: None,
+                inputs: &[],
+                resolves: &[],
+                preserves: &[],
+            };
+            let render_pass = self
+                .device
+                .create_render_pass(
+                    Vec::<pass::Attachment>::new(),
+                    vec![subpass; subpasses],
+                    Vec::<pass::SubpassDependency>::new(),
+                )
+                .unwrap();
+            let framebuffer = self
+                .device
+                .create_framebuffer(
+                    &render_pass,
+                    Vec::<ImageView<mock::Backend>>::new(),
+                    hal::image::Extent {
+                        width: 4,
+                        height: 4,
+                        depth: 1,
+                    },
+                )
+                .unwrap();
+            mock::take_calls();
+            (render_pass, framebuffer)
+        }
+
+        unsafe fn pipeline_layout(&self) -> PipelineLayout<mock::Backend> {
+            self.device
+                .create_pipeline_layout(
+                    Vec::<DescriptorSetLayout<mock::Backend>>::new(),
+                    Vec::<(pso::ShaderStageFlags, Range<u32>)>::new(),
+                )
+                .unwrap()
+        }
+
+        unsafe fn graphics_pipeline(
+            &self,
+            render_pass: &RenderPass<mock::Backend>,
+        ) -> GraphicsPipeline<mock::Backend> {
+            let module = self.device.create_shader_module(&[]).unwrap();
+            let layout = self.pipeline_layout();
+            let shaders = pso::GraphicsShaderSet {
+                vertex: pso::EntryPoint {
+                    entry: "main",
+                    module: &module,
+                    specialization: pso::Specialization::default(),
+                },
+                hull: None,
+                domain: None,
+                geometry: None,
+                fragment: None,
+            };
+            let desc = pso::GraphicsPipelineDesc::new(
+                shaders,
+                pso::Primitive::TriangleList,
+                pso::Rasterizer::FILL,
+                &layout,
+                pass::Subpass {
+                    index: 0,
+                    main_pass: render_pass,
+                },
+            );
+            let pipeline = self.device.create_graphics_pipeline(&desc, None).unwrap();
+            mock::take_calls();
+            pipeline
+        }
+    }
+
+    unsafe fn begin_render_pass(
+        cmd: &mut CommandBuffer<mock::Backend>,
+        render_pass: &RenderPass<mock::Backend>,
+        framebuffer: &Framebuffer<mock::Backend>,
+    ) {
+        cmd.begin_render_pass(
+            render_pass,
+            framebuffer,
+            pso::Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            },
+            Vec::<com::ClearValue>::new(),
+            com::SubpassContents::Inline,
+        );
+    }
+
+    #[test]
+    fn destroyed() {
+        let ctx = Context::new();
+        unsafe {
+            let (memory, buffer) = ctx.bound_buffer(64);
+            let view = ctx
+                .device
+                .create_buffer_view(&buffer, None, buffer::SubRange::WHOLE)
+                .unwrap();
+            ctx.check(vec![], &["create_buffer_view"]);
+            ctx.device.destroy_buffer_view(view);
+            let resource = memory.handle.resource;
+            ctx.device.free_memory(memory);
+            ctx.check(vec![], &["destroy_buffer_view", "free_memory"]);
+
+            assert!(ctx
+                .device
+                .create_buffer_view(&buffer, None, buffer::SubRange::WHOLE)
+                .is_err());
+            ctx.check(
+                vec![Error::Destroyed {
+                    resource,
+                    command: "create_buffer_view",
+                }],
+                &[],
+            );
+        }
+    }
+
+    #[test]
+    fn destroyed_on_submit() {
+        let mut ctx = Context::new();
+        unsafe {
+            let (_src_memory, src) = ctx.bound_buffer(64);
+            let (dst_memory, dst) = ctx.bound_buffer(64);
+            let (_pool, mut cmd) = ctx.recording();
+            cmd.copy_buffer(
+                &src,
+                &dst,
+                Some(com::BufferCopy {
+                    src: 0,
+                    dst: 0,
+                    size: 64,
+                }),
+            );
+            cmd.finish();
+            ctx.queue.submit_without_semaphores(Some(&cmd), None);
+            ctx.check(vec![], &["copy_buffer", "finish", "submit"]);
+
+            let resource = dst.handle.resource;
+            ctx.device.destroy_buffer(dst);
+            ctx.device.free_memory(dst_memory);
+            mock::take_calls();
+            let fence = ctx.device.create_fence(false).unwrap();
+            ctx.queue
+                .submit_without_semaphores(Some(&cmd), Some(&fence));
+            // The rest of the submission still goes through to signal the fence.
+            assert!(ctx.device.get_fence_status(&fence).unwrap());
+            ctx.check(
+                vec![Error::Destroyed {
+                    resource,
+                    command: "submit",
+                }],
+                &["create_fence", "submit", "get_fence_status"],
+            );
+        }
+    }
+
+    #[test]
+    fn already_bound() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 128);
+            let mut buffer = ctx.buffer(64);
+            ctx.device
+                .bind_buffer_memory(&memory, 0, &mut buffer)
+                .unwrap();
+            ctx.check(vec![], &["get_buffer_requirements", "bind_buffer_memory"]);
+
+            assert_eq!(
+                ctx.device.bind_buffer_memory(&memory, 64, &mut buffer),
+                Err(device::BindError::WrongMemory)
+            );
+            ctx.check(
+                vec![Error::AlreadyBound(buffer.handle.resource)],
+                &["get_buffer_requirements"],
+            );
+        }
+    }
+
+    #[test]
+    fn unbound() {
+        let ctx = Context::new();
+        unsafe {
+            let buffer = ctx.buffer(64);
+            assert!(ctx
+                .device
+                .create_buffer_view(&buffer, None, buffer::SubRange::WHOLE)
+                .is_err());
+            ctx.check(
+                vec![Error::Unbound {
+                    resource: buffer.handle.resource,
+                    command: "create_buffer_view",
+                }],
+                &[],
+            );
+
+            let (_pool, mut cmd) = ctx.recording();
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            ctx.check(
+                vec![Error::Unbound {
+                    resource: buffer.handle.resource,
+                    command: "fill_buffer",
+                }],
+                &[],
+            );
+
+            let (_memory, bound) = ctx.bound_buffer(64);
+            cmd.fill_buffer(&bound, buffer::SubRange::WHOLE, 0);
+            ctx.check(vec![], &["fill_buffer"]);
+        }
+    }
+
+    #[test]
+    fn wrong_memory_type() {
+        let ctx = Context::new();
+        unsafe {
+            let host_memory = ctx.memory(mock::HOST_VISIBLE, 1024);
+            let mut image = ctx.image();
+            assert_eq!(
+                ctx.device.bind_image_memory(&host_memory, 0, &mut image),
+                Err(device::BindError::WrongMemory)
+            );
+            ctx.check(
+                vec![Error::WrongMemoryType {
+                    resource: image.handle.resource,
+                    memory_type: mock::HOST_VISIBLE,
+                }],
+                &["get_image_requirements"],
+            );
+
+            let device_memory = ctx.memory(mock::DEVICE_LOCAL, 1024);
+            ctx.device
+                .bind_image_memory(&device_memory, 0, &mut image)
+                .unwrap();
+            ctx.check(vec![], &["get_image_requirements", "bind_image_memory"]);
+        }
+    }
+
+    #[test]
+    fn misaligned_bind() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 128);
+            let mut buffer = ctx.buffer(64);
+            assert_eq!(
+                ctx.device.bind_buffer_memory(&memory, 2, &mut buffer),
+                Err(device::BindError::OutOfBounds)
+            );
+            ctx.check(
+                vec![Error::MisalignedBind {
+                    resource: buffer.handle.resource,
+                    offset: 2,
+                    alignment: 4,
+                }],
+                &["get_buffer_requirements"],
+            );
+
+            ctx.device
+                .bind_buffer_memory(&memory, 4, &mut buffer)
+                .unwrap();
+            ctx.check(vec![], &["get_buffer_requirements", "bind_buffer_memory"]);
+        }
+    }
+
+    #[test]
+    fn bind_out_of_bounds() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 128);
+            let mut buffer = ctx.buffer(64);
+            assert_eq!(
+                ctx.device.bind_buffer_memory(&memory, 96, &mut buffer),
+                Err(device::BindError::OutOfBounds)
+            );
+            ctx.check(
+                vec![Error::BindOutOfBounds {
+                    resource: buffer.handle.resource,
+                    offset: 96,
+                    size: 64,
+                    memory_size: 128,
+                }],
+                &["get_buffer_requirements"],
+            );
+
+            ctx.device
+                .bind_buffer_memory(&memory, 64, &mut buffer)
+                .unwrap();
+            ctx.check(vec![], &["get_buffer_requirements", "bind_buffer_memory"]);
+        }
+    }
+
+    #[test]
+    fn not_host_visible() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::DEVICE_LOCAL, 64);
+            assert_eq!(
+                ctx.device.map_memory(&memory, memory::Segment::ALL),
+                Err(device::MapError::Access)
+            );
+            ctx.check(vec![Error::NotHostVisible(memory.handle.resource)], &[]);
+
+            let memory = ctx.memory(mock::HOST_VISIBLE, 64);
+            ctx.device
+                .map_memory(&memory, memory::Segment::ALL)
+                .unwrap();
+            ctx.check(vec![], &["map_memory"]);
+        }
+    }
+
+    #[test]
+    fn already_mapped() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 64);
+            ctx.device
+                .map_memory(&memory, memory::Segment::ALL)
+                .unwrap();
+            ctx.check(vec![], &["map_memory"]);
+
+            assert_eq!(
+                ctx.device.map_memory(&memory, memory::Segment::ALL),
+                Err(device::MapError::MappingFailed)
+            );
+            ctx.check(vec![Error::AlreadyMapped(memory.handle.resource)], &[]);
+        }
+    }
+
+    #[test]
+    fn not_mapped() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 64);
+            ctx.device.unmap_memory(&memory);
+            ctx.device
+                .flush_mapped_memory_ranges(Some((&memory, memory::Segment::ALL)))
+                .unwrap();
+            let resource = memory.handle.resource;
+            ctx.check(
+                vec![
+                    Error::NotMapped {
+                        resource,
+                        command: "unmap_memory",
+                    },
+                    Error::NotMapped {
+                        resource,
+                        command: "flush_mapped_memory_ranges",
+                    },
+                ],
+                &[],
+            );
+
+            ctx.device
+                .map_memory(&memory, memory::Segment::ALL)
+                .unwrap();
+            ctx.device
+                .flush_mapped_memory_ranges(Some((&memory, memory::Segment::ALL)))
+                .unwrap();
+            ctx.device.unmap_memory(&memory);
+            ctx.check(
+                vec![],
+                &["map_memory", "flush_mapped_memory_ranges", "unmap_memory"],
+            );
+        }
+    }
+
+    #[test]
+    fn segment_out_of_bounds() {
+        let ctx = Context::new();
+        unsafe {
+            let memory = ctx.memory(mock::HOST_VISIBLE, 256);
+            let segment = memory::Segment {
+                offset: 200,
+                size: Some(100),
+            };
+            assert_eq!(
+                ctx.device.map_memory(&memory, segment.clone()),
+                Err(device::MapError::OutOfBounds)
+            );
+            ctx.check(
+                vec![Error::SegmentOutOfBounds {
+                    resource: memory.handle.resource,
+                    segment,
+                    memory_size: 256,
+                }],
+                &[],
+            );
+
+            let segment = memory::Segment {
+                offset: 200,
+                size: Some(56),
+            };
+            ctx.device.map_memory(&memory, segment).unwrap();
+            ctx.check(vec![], &["map_memory"]);
+        }
+    }
+
+    #[test]
+    fn not_recording() {
+        let ctx = Context::new();
+        unsafe {
+            let (_memory, buffer) = ctx.bound_buffer(64);
+            let mut pool = ctx
+                .device
+                .create_command_pool(q::QueueFamilyId(0), pool::CommandPoolCreateFlags::empty())
+                .unwrap();
+            let mut cmd = pool.allocate_one(com::Level::Primary);
+            mock::take_calls();
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            ctx.check(vec![Error::NotRecording("fill_buffer")], &[]);
+
+            cmd.begin_primary(com::CommandBufferFlags::empty());
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            cmd.finish();
+            ctx.check(vec![], &["begin", "fill_buffer", "finish"]);
+
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            ctx.check(vec![Error::NotRecording("fill_buffer")], &[]);
+        }
+    }
+
+    #[test]
+    fn already_recording() {
+        let ctx = Context::new();
+        unsafe {
+            let (_pool, mut cmd) = ctx.recording();
+            cmd.begin_primary(com::CommandBufferFlags::empty());
+            ctx.check(vec![Error::AlreadyRecording], &[]);
+
+            cmd.finish();
+            cmd.begin_primary(com::CommandBufferFlags::empty());
+            ctx.check(vec![], &["finish", "begin"]);
+        }
+    }
+
+    #[test]
+    fn not_executable() {
+        let mut ctx = Context::new();
+        unsafe {
+            let (mut pool, mut cmd) = ctx.recording();
+            ctx.queue.submit_without_semaphores(Some(&cmd), None);
+            // The submission goes through without the command buffer.
+            ctx.check(vec![Error::NotExecutable], &["submit"]);
+
+            cmd.finish();
+            pool.reset(false);
+            ctx.queue.submit_without_semaphores(Some(&cmd), None);
+            ctx.check(
+                vec![Error::NotExecutable],
+                &["finish", "reset_command_pool", "submit"],
+            );
+
+            cmd.begin_primary(com::CommandBufferFlags::empty());
+            cmd.finish();
+            ctx.queue.submit_without_semaphores(Some(&cmd), None);
+            ctx.check(vec![], &["begin", "finish", "submit"]);
+        }
+    }
+
+    #[test]
+    fn outside_render_pass() {
+        let ctx = Context::new();
+        unsafe {
+            let (render_pass, framebuffer) = ctx.render_pass(1);
+            let (_pool, mut cmd) = ctx.recording();
+            cmd.clear_attachments(
+                Vec::<com::AttachmentClear>::new(),
+                Vec::<pso::ClearRect>::new(),
+            );
+            ctx.check(vec![Error::OutsideRenderPass("clear_attachments")], &[]);
+
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            cmd.clear_attachments(
+                Vec::<com::AttachmentClear>::new(),
+                Vec::<pso::ClearRect>::new(),
+            );
+            ctx.check(vec![], &["begin_render_pass", "clear_attachments"]);
+        }
+    }
+
+    #[test]
+    fn inside_render_pass() {
+        let ctx = Context::new();
+        unsafe {
+            let (_memory, buffer) = ctx.bound_buffer(64);
+            let (render_pass, framebuffer) = ctx.render_pass(1);
+            let (_pool, mut cmd) = ctx.recording();
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            mock::take_calls();
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            cmd.finish();
+            ctx.check(
+                vec![
+                    Error::InsideRenderPass("fill_buffer"),
+                    Error::InsideRenderPass("finish"),
+                ],
+                &[],
+            );
+
+            cmd.end_render_pass();
+            cmd.fill_buffer(&buffer, buffer::SubRange::WHOLE, 0);
+            cmd.finish();
+            ctx.check(vec![], &["end_render_pass", "fill_buffer", "finish"]);
+        }
+    }
+
+    #[test]
+    fn no_next_subpass() {
+        let ctx = Context::new();
+        unsafe {
+            let (render_pass, framebuffer) = ctx.render_pass(2);
+            let (_pool, mut cmd) = ctx.recording();
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            mock::take_calls();
+            cmd.next_subpass(com::SubpassContents::Inline);
+            ctx.check(vec![], &["next_subpass"]);
+
+            cmd.next_subpass(com::SubpassContents::Inline);
+            ctx.check(vec![Error::NoNextSubpass], &[]);
+        }
+    }
+
+    #[test]
+    fn subpasses_remaining() {
+        let ctx = Context::new();
+        unsafe {
+            let (render_pass, framebuffer) = ctx.render_pass(2);
+            let (_pool, mut cmd) = ctx.recording();
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            mock::take_calls();
+            cmd.end_render_pass();
+            ctx.check(vec![Error::SubpassesRemaining(1)], &[]);
+
+            cmd.next_subpass(com::SubpassContents::Inline);
+            cmd.end_render_pass();
+            ctx.check(vec![], &["next_subpass", "end_render_pass"]);
+        }
+    }
+
+    #[test]
+    fn no_pipeline() {
+        let ctx = Context::new();
+        unsafe {
+            let (render_pass, framebuffer) = ctx.render_pass(1);
+            let (_pool, mut cmd) = ctx.recording();
+            cmd.dispatch([1, 1, 1]);
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            cmd.draw(0 .. 3, 0 .. 1);
+            ctx.check(
+                vec![Error::NoPipeline("dispatch"), Error::NoPipeline("draw")],
+                &["begin_render_pass"],
+            );
+
+            let pipeline = ctx.graphics_pipeline(&render_pass);
+            cmd.bind_graphics_pipeline(&pipeline);
+            cmd.draw(0 .. 3, 0 .. 1);
+            ctx.check(vec![], &["bind_graphics_pipeline", "draw"]);
+        }
+    }
+
+    #[test]
+    fn no_index_buffer() {
+        let ctx = Context::new();
+        unsafe {
+            let (_memory, indices) = ctx.bound_buffer(64);
+            let (render_pass, framebuffer) = ctx.render_pass(1);
+            let pipeline = ctx.graphics_pipeline(&render_pass);
+            let (_pool, mut cmd) = ctx.recording();
+            begin_render_pass(&mut cmd, &render_pass, &framebuffer);
+            cmd.bind_graphics_pipeline(&pipeline);
+            mock::take_calls();
+            cmd.draw_indexed(0 .. 3, 0, 0 .. 1);
+            ctx.check(vec![Error::NoIndexBuffer("draw_indexed")], &[]);
+
+            cmd.bind_index_buffer(buffer::IndexBufferView {
+                buffer: &indices,
+                range: buffer::SubRange::WHOLE,
+                index_type: hal::IndexType::U16,
+            });
+            cmd.draw_indexed(0 .. 3, 0, 0 .. 1);
+            ctx.check(vec![], &["bind_index_buffer", "draw_indexed"]);
+        }
+    }
+
+    fn storage_binding() -> pso::DescriptorSetLayoutBinding {
+        pso::DescriptorSetLayoutBinding {
+            binding: 0,
+            ty: pso::DescriptorType::Buffer {
+                ty: pso::BufferDescriptorType::Storage { read_only: false },
+                format: pso::BufferDescriptorFormat::Structured {
+                    dynamic_offset: false,
+                },
+            },
+            count: 1,
+            stage_flags: pso::ShaderStageFlags::FRAGMENT,
+            immutable_samplers: false,
+        }
+    }
+
+    unsafe fn descriptor_set(
+        ctx: &Context,
+        binding: pso::DescriptorSetLayoutBinding,
+    ) -> DescriptorSet<mock::Backend> {
+        let layout = ctx
+            .device
+            .create_descriptor_set_layout(Some(binding), None::<Sampler<mock::Backend>>)
+            .unwrap();
+        let mut pool = ctx
+            .device
+            .create_descriptor_pool(
+                1,
+                None::<pso::DescriptorRangeDesc>,
+                pso::DescriptorPoolCreateFlags::empty(),
+            )
+            .unwrap();
+        let set = pool.allocate_set(&layout).unwrap();
+        mock::take_calls();
+        set
+    }
+
+    #[test]
+    fn descriptor_out_of_bounds() {
+        let ctx = Context::new();
+        unsafe {
+            let (_memory, buffer) = ctx.bound_buffer(64);
+            let set = descriptor_set(&ctx, storage_binding());
+            let write = |binding, array_offset| pso::DescriptorSetWrite {
+                set: &set,
+                binding,
+                array_offset,
+                descriptors: Some(pso::Descriptor::<Validation>::Buffer(
+                    &buffer,
+                    buffer::SubRange::WHOLE,
+                )),
+            };
+            ctx.device.write_descriptor_sets(vec![write(0, 1)]);
+            ctx.check(
+                vec![Error::DescriptorOutOfBounds {
+                    binding: 1,
+                    array_index: 0,
+                }],
+                &[],
+            );
+
+            ctx.device.write_descriptor_sets(vec![write(0, 0)]);
+            ctx.check(vec![], &["write_descriptor_sets"]);
+        }
+    }
+
+    #[test]
+    fn descriptor_type_mismatch() {
+        let ctx = Context::new();
+        unsafe {
+            let (_memory, buffer) = ctx.bound_buffer(64);
+            let sampled = pso::DescriptorSetLayoutBinding {
+                ty: pso::DescriptorType::Image {
+                    ty: pso::ImageDescriptorType::Sampled {
+                        with_sampler: false,
+                    },
+                },
+                ..storage_binding()
+            };
+            let descriptor =
+                pso::Descriptor::<Validation>::Buffer(&buffer, buffer::SubRange::WHOLE);
+
+            let set = descriptor_set(&ctx, sampled.clone());
+            ctx.device
+                .write_descriptor_sets(vec![pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(&descriptor),
+                }]);
+            ctx.check(
+                vec![Error::DescriptorTypeMismatch {
+                    binding: 0,
+                    array_index: 0,
+                    expected: sampled.ty,
+                    found: "Buffer",
+                }],
+                &[],
+            );
+
+            let set = descriptor_set(&ctx, storage_binding());
+            ctx.device
+                .write_descriptor_sets(vec![pso::DescriptorSetWrite {
+                    set: &set,
+                    binding: 0,
+                    array_offset: 0,
+                    descriptors: Some(&descriptor),
+                }]);
+            ctx.check(vec![], &["write_descriptor_sets"]);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::collections::HashMap;
+use std::ptr::NonNull;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hal::{memory, pso};
+use parking_lot::Mutex;
+
+use crate::error::{Resource, ResourceKind};
+
+/// Liveness of an object, shared with everything referring to it.
+#[derive(Debug)]
+pub(crate) struct Handle {
+    pub(crate) resource: Resource,
+    alive: AtomicBool,
+    /// Objects which have to outlive this one, like the memory bound to a buffer.
+    dependencies: Mutex<Vec<Arc<Handle>>>,
+}
+
+impl Handle {
+    pub(crate) fn new(kind: ResourceKind) -> Arc<Self> {
+        static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+        Arc::new(Handle {
+            resource: Resource {
+                kind,
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            },
+            alive: AtomicBool::new(true),
+            dependencies: Mutex::new(Vec::new()),
+        })
+    }
+
+    pub(crate) fn depend_on(&self, other: &Arc<Handle>) {
+        self.dependencies.lock().push(Arc::clone(other));
+    }
+
+    pub(crate) fn destroy(&self) {
+        self.alive.store(false, Ordering::Release);
+    }
+
+    /// Returns this object or the first object it relies on which was destroyed.
+    pub(crate) fn find_destroyed(&self) -> Option<Resource> {
+        if !self.alive.load(Ordering::Acquire) {
+            return Some(self.resource);
+        }
+        self.dependencies
+            .lock()
+            .iter()
+            .filter_map(|handle| handle.find_destroyed())
+            .next()
+    }
+}
+
+#[derive(Debug)]
+pub struct Memory<B: hal::Backend> {
+    pub(crate) raw: B::Memory,
+    pub(crate) handle: Arc<Handle>,
+    pub(crate) memory_type: hal::MemoryTypeId,
+    pub(crate) properties: memory::Properties,
+    pub(crate) size: u64,
+    pub(crate) mapped: AtomicBool,
+}
+
+#[derive(Debug)]
+pub struct Buffer<B: hal::Backend> {
+    pub(crate) raw: B::Buffer,
+    pub(crate) handle: Arc<Handle>,
+    pub(crate) bound: bool,
+}
+
+#[derive(Debug)]
+pub struct BufferView<B: hal::Backend> {
+    pub(crate) raw: B::BufferView,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct Image<B: hal::Backend> {
+    pub(crate) raw: B::Image,
+    pub(crate) handle: Arc<Handle>,
+    pub(crate) bound: bool,
+}
+
+#[derive(Debug)]
+pub(crate) enum RawImageView<B: hal::Backend> {
+    Owned(B::ImageView),
+    /// View owned by a swapchain image of the inner backend, which is kept
+    /// at a stable address by the `SwapchainImage` wrapping both.
+    Swapchain(NonNull<B::ImageView>),
+}
+
+#[derive(Debug)]
+pub struct ImageView<B: hal::Backend> {
+    pub(crate) raw: RawImageView<B>,
+    pub(crate) handle: Arc<Handle>,
+}
+
+// The swapchain view pointer is only a borrow of a `Send + Sync` object.
+unsafe impl<B: hal::Backend> Send for ImageView<B> {}
+unsafe impl<B: hal::Backend> Sync for ImageView<B> {}
+
+impl<B: hal::Backend> ImageView<B> {
+    pub(crate) fn raw(&self) -> &B::ImageView {
+        match self.raw {
+            RawImageView::Owned(ref view) => view,
+            RawImageView::Swapchain(view) => unsafe { &*view.as_ptr() },
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Sampler<B: hal::Backend> {
+    pub(crate) raw: B::Sampler,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct ShaderModule<B: hal::Backend> {
+    pub(crate) raw: B::ShaderModule,
+}
+
+#[derive(Debug)]
+pub struct RenderPass<B: hal::Backend> {
+    pub(crate) raw: B::RenderPass,
+    pub(crate) handle: Arc<Handle>,
+    pub(crate) subpasses: usize,
+}
+
+#[derive(Debug)]
+pub struct Framebuffer<B: hal::Backend> {
+    pub(crate) raw: B::Framebuffer,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct PipelineCache<B: hal::Backend> {
+    pub(crate) raw: B::PipelineCache,
+}
+
+#[derive(Debug)]
+pub struct PipelineLayout<B: hal::Backend> {
+    pub(crate) raw: B::PipelineLayout,
+}
+
+#[derive(Debug)]
+pub struct GraphicsPipeline<B: hal::Backend> {
+    pub(crate) raw: B::GraphicsPipeline,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct ComputePipeline<B: hal::Backend> {
+    pub(crate) raw: B::ComputePipeline,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct DescriptorSetLayout<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorSetLayout,
+    pub(crate) bindings: Arc<Vec<pso::DescriptorSetLayoutBinding>>,
+}
+
+/// Binding and array index of a descriptor.
+pub(crate) type DescriptorSlot = (pso::DescriptorBinding, pso::DescriptorArrayIndex);
+
+#[derive(Debug)]
+pub struct DescriptorSet<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorSet,
+    pub(crate) handle: Arc<Handle>,
+    pub(crate) bindings: Arc<Vec<pso::DescriptorSetLayoutBinding>>,
+    /// Objects written to each array element of the bindings.
+    pub(crate) contents: Mutex<HashMap<DescriptorSlot, Vec<Arc<Handle>>>>,
+}
+
+impl<B: hal::Backend> DescriptorSet<B> {
+    /// Returns the handles of the set and everything written to it.
+    pub(crate) fn handles(&self) -> Vec<Arc<Handle>> {
+        let mut handles = vec![Arc::clone(&self.handle)];
+        handles.extend(self.contents.lock().values().flatten().cloned());
+        handles
+    }
+}
+
+#[derive(Debug)]
+pub struct Fence<B: hal::Backend> {
+    pub(crate) raw: B::Fence,
+}
+
+#[derive(Debug)]
+pub struct Semaphore<B: hal::Backend> {
+    pub(crate) raw: B::Semaphore,
+}
+
+#[derive(Debug)]
+pub struct Event<B: hal::Backend> {
+    pub(crate) raw: B::Event,
+    pub(crate) handle: Arc<Handle>,
+}
+
+#[derive(Debug)]
+pub struct QueryPool<B: hal::Backend> {
+    pub(crate) raw: B::QueryPool,
+    pub(crate) handle: Arc<Handle>,
+}
+
+/// Collects the items of an iterator of borrowed objects, so that references
+/// to their inner objects can be handed to the inner backend.
+pub(crate) fn collect<I>(iter: I) -> Vec<I::Item>
+where
+    I: IntoIterator,
+{
+    iter.into_iter().collect()
+}
+
+pub(crate) fn raw_entry<'a, B: hal::Backend>(
+    entry: &pso::EntryPoint<'a, crate::Backend<B>>,
+) -> pso::EntryPoint<'a, B> {
+    pso::EntryPoint {
+        entry: entry.entry,
+        module: &entry.module.raw,
+        specialization: entry.specialization.clone(),
+    }
+}
+
+pub(crate) fn raw_subpass<'a, B: hal::Backend>(
+    subpass: &hal::pass::Subpass<'a, crate::Backend<B>>,
+) -> hal::pass::Subpass<'a, B> {
+    hal::pass::Subpass {
+        index: subpass.index,
+        main_pass: &subpass.main_pass.raw,
+    }
+}
+
+pub(crate) fn raw_barrier<'a, B: hal::Backend>(
+    barrier: &memory::Barrier<'a, crate::Backend<B>>,
+) -> memory::Barrier<'a, B> {
+    match *barrier {
+        memory::Barrier::AllBuffers(ref access) => memory::Barrier::AllBuffers(access.clone()),
+        memory::Barrier::AllImages(ref access) => memory::Barrier::AllImages(access.clone()),
+        memory::Barrier::Buffer {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => memory::Barrier::Buffer {
+            states: states.clone(),
+            target: &target.raw,
+            range: range.clone(),
+            families: families.clone(),
+        },
+        memory::Barrier::Image {
+            ref states,
+            target,
+            ref range,
+            ref families,
+        } => memory::Barrier::Image {
+            states: states.clone(),
+            target: &target.raw,
+            range: range.clone(),
+            families: families.clone(),
+        },
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use hal::{command, pso};
+use parking_lot::Mutex;
+
+use crate::command::CommandBuffer;
+use crate::error::{Reporter, ResourceKind};
+use crate::native::{DescriptorSet, DescriptorSetLayout, Handle};
+use crate::Backend;
+
+#[derive(Debug)]
+pub struct CommandPool<B: hal::Backend> {
+    pub(crate) raw: B::CommandPool,
+    reporter: Arc<Reporter>,
+    /// Incremented on every reset, invalidating all command buffers recorded before.
+    epoch: Arc<AtomicU64>,
+}
+
+impl<B: hal::Backend> CommandPool<B> {
+    pub(crate) fn new(raw: B::CommandPool, reporter: Arc<Reporter>) -> Self {
+        CommandPool {
+            raw,
+            reporter,
+            epoch: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl<B: hal::Backend> hal::pool::CommandPool<Backend<B>> for CommandPool<B> {
+    unsafe fn reset(&mut self, release_resources: bool) {
+        self.epoch.fetch_add(1, Ordering::AcqRel);
+        self.raw.reset(release_resources)
+    }
+
+    unsafe fn allocate_one(&mut self, level: command::Level) -> CommandBuffer<B> {
+        let raw = self.raw.allocate_one(level);
+        CommandBuffer::new(
+            raw,
+            level,
+            Arc::clone(&self.epoch),
+            Arc::clone(&self.reporter),
+        )
+    }
+
+    unsafe fn free<I>(&mut self, buffers: I)
+    where
+        I: IntoIterator<Item = CommandBuffer<B>>,
+    {
+        self.raw.free(buffers.into_iter().map(|buffer| buffer.raw))
+    }
+}
+
+#[derive(Debug)]
+pub struct DescriptorPool<B: hal::Backend> {
+    pub(crate) raw: B::DescriptorPool,
+    /// Handles of the sets currently allocated from the pool.
+    sets: Vec<Arc<Handle>>,
+}
+
+impl<B: hal::Backend> DescriptorPool<B> {
+    pub(crate) fn new(raw: B::DescriptorPool) -> Self {
+        DescriptorPool {
+            raw,
+            sets: Vec::new(),
+        }
+    }
+
+    pub(crate) fn destroy_sets(&self) {
+        for set in &self.sets {
+            set.destroy();
+        }
+    }
+}
+
+impl<B: hal::Backend> pso::DescriptorPool<Backend<B>> for DescriptorPool<B> {
+    unsafe fn allocate_set(
+        &mut self,
+        layout: &DescriptorSetLayout<B>,
+    ) -> Result<DescriptorSet<B>, pso::AllocationError> {
+        let raw = self.raw.allocate_set(&layout.raw)?;
+        let handle = Handle::new(ResourceKind::DescriptorSet);
+        self.sets.push(Arc::clone(&handle));
+        Ok(DescriptorSet {
+            raw,
+            handle,
+            bindings: Arc::clone(&layout.bindings),
+            contents: Mutex::new(HashMap::new()),
+        })
+    }
+
+    unsafe fn free<I>(&mut self, descriptor_sets: I)
+    where
+        I: IntoIterator<Item = DescriptorSet<B>>,
+    {
+        let sets = &mut self.sets;
+        self.raw.free(descriptor_sets.into_iter().map(|set| {
+            set.handle.destroy();
+            sets.retain(|handle| !Arc::ptr_eq(handle, &set.handle));
+            set.raw
+        }))
+    }
+
+    unsafe fn reset(&mut self) {
+        self.destroy_sets();
+        self.sets.clear();
+        self.raw.reset()
+    }
+}
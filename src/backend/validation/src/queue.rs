@@ -0,0 +1,110 @@
+use std::borrow::Borrow;
+use std::sync::Arc;
+
+use hal::{device, pso, queue, window};
+
+use crate::{
+    command::CommandBuffer,
+    error::Reporter,
+    native::{collect, Fence, Semaphore},
+    Backend,
+    Surface,
+    Swapchain,
+    SwapchainImage,
+};
+
+#[derive(Debug)]
+pub struct CommandQueue<B: hal::Backend> {
+    raw: B::CommandQueue,
+    reporter: Arc<Reporter>,
+}
+
+impl<B: hal::Backend> CommandQueue<B> {
+    pub(crate) fn new(raw: B::CommandQueue, reporter: Arc<Reporter>) -> Self {
+        CommandQueue { raw, reporter }
+    }
+
+    pub fn raw(&self) -> &B::CommandQueue {
+        &self.raw
+    }
+
+    pub fn reporter(&self) -> &Arc<Reporter> {
+        &self.reporter
+    }
+}
+
+impl<B: hal::Backend> queue::CommandQueue<Backend<B>> for CommandQueue<B> {
+    unsafe fn submit<'a, T, Ic, S, Iw, Is>(
+        &mut self,
+        submission: queue::Submission<Ic, Iw, Is>,
+        fence: Option<&Fence<B>>,
+    ) where
+        T: 'a + Borrow<CommandBuffer<B>>,
+        Ic: IntoIterator<Item = &'a T>,
+        S: 'a + Borrow<Semaphore<B>>,
+        Iw: IntoIterator<Item = (&'a S, pso::PipelineStage)>,
+        Is: IntoIterator<Item = &'a S>,
+    {
+        // Invalid command buffers are left out, but the rest of the submission
+        // goes through so the semaphores and the fence still get signaled.
+        let command_buffers = submission
+            .command_buffers
+            .into_iter()
+            .map(|cmd_buffer| cmd_buffer.borrow())
+            .filter(|cmd_buffer| cmd_buffer.check_executable("submit"))
+            .map(|cmd_buffer| &cmd_buffer.raw)
+            .collect::<Vec<_>>();
+        let raw_submission = queue::Submission {
+            command_buffers,
+            wait_semaphores: submission
+                .wait_semaphores
+                .into_iter()
+                .map(|(semaphore, stage)| (&semaphore.borrow().raw, stage)),
+            signal_semaphores: submission
+                .signal_semaphores
+                .into_iter()
+                .map(|semaphore| &semaphore.borrow().raw),
+        };
+        self.raw
+            .submit(raw_submission, fence.map(|fence| &fence.raw))
+    }
+
+    unsafe fn present<'a, W, Is, S, Iw>(
+        &mut self,
+        swapchains: Is,
+        wait_semaphores: Iw,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError>
+    where
+        W: 'a + Borrow<Swapchain<B>>,
+        Is: IntoIterator<Item = (&'a W, window::SwapImageIndex)>,
+        S: 'a + Borrow<Semaphore<B>>,
+        Iw: IntoIterator<Item = &'a S>,
+    {
+        let swapchains = collect(swapchains);
+        self.raw.present(
+            swapchains
+                .iter()
+                .map(|&(swapchain, index)| (&swapchain.borrow().raw, index)),
+            wait_semaphores
+                .into_iter()
+                .map(|semaphore| &semaphore.borrow().raw),
+        )
+    }
+
+    unsafe fn present_surface(
+        &mut self,
+        surface: &mut Surface<B>,
+        image: SwapchainImage<B>,
+        wait_semaphore: Option<&Semaphore<B>>,
+    ) -> Result<Option<window::Suboptimal>, window::PresentError> {
+        self.raw.present_surface(
+            &mut surface.raw,
+            image.into_raw(),
+            wait_semaphore.map(|semaphore| &semaphore.raw),
+        )
+    }
+
+    fn wait_idle(&self) -> Result<(), device::OutOfMemory> {
+        self.raw.wait_idle()
+    }
+}
@@ -43,6 +43,7 @@ pub type DescriptorSetOffset = u32;
 bitflags! {
     /// Option flags for various command buffer settings.
     #[derive(Default)]
+    #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
     pub struct CommandBufferFlags: u32 {
         // TODO: Remove once 'const fn' is stabilized: https://github.com/rust-lang/rust/issues/24111
         /// No flags.
@@ -67,13 +68,15 @@ bitflags! {
 /// and `command::Secondary` do at compile-time.
 #[allow(missing_docs)]
 #[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum Level {
     Primary,
     Secondary,
 }
 
 /// Specifies how commands for the following renderpasses will be recorded.
-#[derive(Debug)]
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub enum SubpassContents {
     /// Contents of the subpass will be inline in the command buffer,
     /// NOT in secondary command buffers.
@@ -5,6 +5,7 @@
         feature = "dx11",
         feature = "metal",
         feature = "gl",
+        feature = "cpu",
     )),
     allow(dead_code)
 )]
@@ -19,6 +20,7 @@ use std::fs::File;
 use std::path::PathBuf;
 
 use ron::de;
+use warden::report::{self, Format, Options, Record, Status};
 
 #[derive(Debug, Deserialize)]
 enum Expectation {
@@ -34,6 +36,8 @@ struct Test {
 #[derive(Debug, Deserialize)]
 struct RawTestGroup {
     features: Vec<warden::Feature>,
+    #[serde(default)]
+    limits: Vec<warden::Limit>,
     tests: HashMap<String, Test>,
 }
 
@@ -44,31 +48,53 @@ struct TestGroup {
     scene: warden::raw::Scene,
     tests: HashMap<String, Test>,
     features: hal::Features,
+    limits: Vec<warden::Limit>,
 }
 
 #[derive(Default)]
 struct Disabilities {}
 
+/// Human-readable progress goes to stderr when stdout carries a report.
+macro_rules! progress {
+    ($format:expr, $($arg:tt)*) => {
+        if $format == Format::Text {
+            print!($($arg)*);
+        } else {
+            eprint!($($arg)*);
+        }
+    };
+}
+
 struct Harness {
     base_path: PathBuf,
     suite: Vec<TestGroup>,
+    options: Options,
+    records: Vec<Record>,
 }
 
 impl Harness {
-    fn new(suite_name: &str) -> Self {
+    fn new(options: Options) -> Self {
         let base_path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../work"));
-        println!("Parsing test suite '{}'...", suite_name);
+        progress!(options.format, "Parsing test suite '{}'...\n", options.suite);
 
         let suite_path = base_path
             .join("benches")
-            .join(suite_name)
+            .join(&options.suite)
             .with_extension("ron");
         let suite = File::open(&suite_path)
             .map_err(de::Error::from)
             .and_then(de::from_reader::<_, Suite>)
             .expect(&format!("failed to open/parse the suite: {:?}", suite_path))
             .into_iter()
-            .map(|(name, raw_group)| {
+            .filter_map(|(name, raw_group)| {
+                let tests = raw_group
+                    .tests
+                    .into_iter()
+                    .filter(|&(ref test_name, _)| options.is_selected(&name, test_name))
+                    .collect::<HashMap<_, _>>();
+                if tests.is_empty() {
+                    return None;
+                }
                 let path = base_path.join("scenes").join(&name).with_extension("ron");
                 let scene = File::open(path)
                     .map_err(de::Error::from)
@@ -78,36 +104,61 @@ impl Harness {
                     .features
                     .into_iter()
                     .fold(hal::Features::empty(), |u, f| u | f.into_hal());
-                TestGroup {
+                Some(TestGroup {
                     name,
                     scene,
-                    tests: raw_group.tests,
+                    tests,
                     features,
-                }
+                    limits: raw_group.limits,
+                })
             })
             .collect();
 
-        Harness { base_path, suite }
+        Harness {
+            base_path,
+            suite,
+            options,
+            records: Vec::new(),
+        }
     }
 
-    fn run<B: hal::Backend>(&self, name: &str, disabilities: Disabilities) {
-        println!("Benching {}:", name);
+    fn run<B: hal::Backend>(&mut self, name: &str, disabilities: Disabilities) {
+        progress!(self.options.format, "Benching {}:\n", name);
         let instance = B::Instance::create("warden", 1).unwrap();
-        self.run_instance(instance, disabilities)
+        let records = self.run_instance(name, instance, disabilities);
+        self.records.extend(records);
     }
 
     fn run_instance<B: hal::Backend, I: hal::Instance<B>>(
         &self,
+        name: &str,
         instance: I,
         _disabilities: Disabilities,
-    ) {
+    ) -> Vec<Record> {
+        let format = self.options.format;
+        let mut records = Vec::new();
         for tg in &self.suite {
             let mut adapters = instance.enumerate_adapters();
             let adapter = adapters.remove(0);
             let supported_features = adapter.physical_device.features();
             let limits = adapter.physical_device.limits();
-            //println!("\t{:?}", adapter.info);
-            println!("\tScene '{}':", tg.name);
+            let info = adapter.info.clone();
+            progress!(format, "\tScene '{}':\n", tg.name);
+
+            let record = |test_name: &str, status, time, message| Record {
+                backend: name.to_string(),
+                adapter: info.clone(),
+                group: tg.name.clone(),
+                test: test_name.to_string(),
+                status,
+                time,
+                message,
+            };
+            let skip_group = |records: &mut Vec<Record>, reason: String| {
+                for test_name in tg.tests.keys() {
+                    records.push(record(test_name, Status::Skip, 0.0, Some(reason.clone())));
+                }
+            };
 
             #[cfg(not(feature = "glsl-to-spirv"))]
             {
@@ -116,16 +167,28 @@ impl Harness {
                     _ => true,
                 });
                 if !all_spirv {
-                    println!("\t\tskipped {} tests (GLSL shaders)", tg.tests.len());
+                    progress!(format, "\t\tskipped {} tests (GLSL shaders)\n", tg.tests.len());
+                    skip_group(&mut records, "GLSL shaders".to_string());
                     continue;
                 }
             }
 
             if !supported_features.contains(tg.features) {
-                println!(
-                    "\tskipped (features missing: {:?})",
-                    tg.features - supported_features
-                );
+                let reason = format!("features missing: {:?}", tg.features - supported_features);
+                progress!(format, "\tskipped ({})\n", reason);
+                skip_group(&mut records, reason);
+                continue;
+            }
+
+            let missing_limits = tg
+                .limits
+                .iter()
+                .filter(|limit| !limit.is_supported_by(&limits))
+                .collect::<Vec<_>>();
+            if !missing_limits.is_empty() {
+                let reason = format!("limits missing: {:?}", missing_limits);
+                progress!(format, "\tskipped ({})\n", reason);
+                skip_group(&mut records, reason);
                 continue;
             }
 
@@ -138,7 +201,7 @@ impl Harness {
             .unwrap();
 
             for (test_name, test) in &tg.tests {
-                print!("\t\tTest '{}' ...", test_name);
+                progress!(format, "\t\tTest '{}' ...", test_name);
                 let mut max_compute_work_groups = [0; 3];
                 for job_name in &test.jobs {
                     if let warden::raw::Job::Compute { dispatch, .. } = tg.scene.jobs[job_name] {
@@ -152,33 +215,39 @@ impl Harness {
                     || max_compute_work_groups[1] > limits.max_compute_work_group_size[1]
                     || max_compute_work_groups[2] > limits.max_compute_work_group_size[2]
                 {
-                    println!("\tskipped (compute {:?})", max_compute_work_groups);
+                    let reason = format!("compute {:?}", max_compute_work_groups);
+                    progress!(format, "\tskipped ({})\n", reason);
+                    records.push(record(test_name, Status::Skip, 0.0, Some(reason)));
                     continue;
                 }
 
                 scene.run(test.jobs.iter());
                 let time = scene.measure_time();
-                println!(" {} mcs", time / 1000);
+                progress!(format, " {} mcs\n", time / 1000);
+                records.push(record(test_name, Status::Pass, time as f64 / 1e9, None));
             }
         }
+
+        records
     }
 }
 
 fn main() {
-    use std::env;
+    use std::{env, io};
 
     #[cfg(feature = "env_logger")]
     env_logger::init();
 
-    let suite_name = match env::args().nth(1) {
-        Some(name) => name,
-        None => {
-            println!("Call with the argument of the bench suite name");
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: bench <suite> [--format text|json|junit] [--filter <glob>]");
             return;
         }
     };
 
-    let harness = Harness::new(&suite_name);
+    let mut harness = Harness::new(options);
     #[cfg(feature = "vulkan")]
     {
         harness.run::<gfx_backend_vulkan::Backend>("Vulkan", Disabilities::default());
@@ -199,15 +268,28 @@ fn main() {
     {
         harness.run::<gfx_backend_gl::Backend>("GL", Disabilities::default());
     }
+    #[cfg(feature = "cpu")]
+    {
+        harness.run::<gfx_backend_cpu::Backend>("CPU", Disabilities::default());
+    }
     #[cfg(not(any(
         feature = "vulkan",
         feature = "dx12",
         feature = "dx11",
         feature = "metal",
         feature = "gl",
+        feature = "cpu",
     )))]
     {
         println!("No backend selected!");
-        let _ = harness;
     }
+
+    let stdout = io::stdout();
+    report::write(
+        stdout.lock(),
+        harness.options.format,
+        &harness.options.suite,
+        &harness.records,
+    )
+    .expect("failed to write the report");
 }
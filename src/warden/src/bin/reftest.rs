@@ -5,6 +5,7 @@
         feature = "dx11",
         feature = "metal",
         feature = "gl",
+        feature = "cpu",
     )),
     allow(dead_code)
 )]
@@ -15,16 +16,204 @@ extern crate serde;
 
 use hal::{adapter::PhysicalDevice as _, Instance as _};
 use std::collections::HashMap;
-use std::fs::File;
-use std::path::PathBuf;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
 
 use ron::de;
+use warden::report::{self, Format, Options, Record, Status};
 
+#[derive(Debug, Deserialize)]
+struct Region {
+    x: usize,
+    y: usize,
+    width: usize,
+    height: usize,
+}
 
 #[derive(Debug, Deserialize)]
 enum Expectation {
     Buffer(String, Vec<u8>),
     ImageRow(String, usize, Vec<u8>),
+    /// Compare an image (or a region of it) against a reference PNG,
+    /// relative to the `work` directory.
+    Image {
+        image: String,
+        reference: String,
+        #[serde(default)]
+        region: Option<Region>,
+        /// Maximum difference allowed per channel.
+        #[serde(default)]
+        tolerance: u8,
+        /// Number of pixels allowed to exceed the tolerance.
+        #[serde(default)]
+        max_differing_pixels: usize,
+    },
+    /// Check the 64-bit results of a range of queries.
+    Query {
+        pool: String,
+        queries: Range<hal::query::Id>,
+        check: QueryCheck,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+enum QueryCheck {
+    /// Results match the given values exactly.
+    Equal(Vec<u64>),
+    /// Every result is non-zero, e.g. occlusion samples have passed.
+    NonZero,
+    /// Results strictly increase, e.g. consecutive timestamps.
+    Increasing,
+}
+
+impl QueryCheck {
+    fn check(&self, results: &[u64]) -> Result<(), String> {
+        let pass = match *self {
+            QueryCheck::Equal(ref values) => values.as_slice() == results,
+            QueryCheck::NonZero => results.iter().all(|&r| r != 0),
+            QueryCheck::Increasing => results.windows(2).all(|w| w[0] < w[1]),
+        };
+        if pass {
+            Ok(())
+        } else {
+            Err(format!("{:?}", results))
+        }
+    }
+}
+
+/// Decoded 8-bit PNG image.
+struct Reference {
+    width: usize,
+    height: usize,
+    channels: usize,
+    data: Vec<u8>,
+}
+
+impl Reference {
+    fn load(path: &Path) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{:?}: {}", path, e))?;
+        let mut decoder = png::Decoder::new(file);
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let (info, mut reader) = decoder
+            .read_info()
+            .map_err(|e| format!("{:?}: {}", path, e))?;
+        if info.bit_depth != png::BitDepth::Eight {
+            return Err(format!("{:?}: only 8-bit references are supported", path));
+        }
+        let mut data = vec![0; info.buffer_size()];
+        reader
+            .next_frame(&mut data)
+            .map_err(|e| format!("{:?}: {}", path, e))?;
+        Ok(Reference {
+            width: info.width as usize,
+            height: info.height as usize,
+            channels: info.color_type.samples(),
+            data,
+        })
+    }
+}
+
+/// Writes an RGBA image highlighting differing pixels in red
+/// over a darkened grayscale copy of the reference.
+fn write_diff(path: &Path, reference: &Reference, mask: &[bool]) -> Result<(), String> {
+    let mut data = Vec::with_capacity(mask.len() * 4);
+    for (texel, &differs) in reference.data.chunks(reference.channels).zip(mask) {
+        if differs {
+            data.extend_from_slice(&[0xFF, 0, 0, 0xFF]);
+        } else {
+            let luma = texel.iter().map(|&c| c as usize).sum::<usize>() / texel.len();
+            let dark = (luma / 4) as u8;
+            data.extend_from_slice(&[dark, dark, dark, 0xFF]);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{:?}: {}", dir, e))?;
+    }
+    let file = File::create(path).map_err(|e| format!("{:?}: {}", path, e))?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        reference.width as u32,
+        reference.height as u32,
+    );
+    encoder.set_color(png::ColorType::RGBA);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(|e| format!("{:?}: {}", path, e))
+}
+
+fn compare_row(
+    guard: &warden::gpu::FetchGuard,
+    row: usize,
+    data: &[u8],
+) -> Result<(), String> {
+    if data == guard.row(row) {
+        Ok(())
+    } else {
+        Err(format!("{:?}", guard.row(row)))
+    }
+}
+
+fn compare_image(
+    guard: &warden::gpu::FetchGuard,
+    region: Option<&Region>,
+    reference: &Reference,
+    tolerance: u8,
+    max_differing_pixels: usize,
+    diff_path: &Path,
+) -> Result<(), String> {
+    let texel_size = guard.texel_size();
+    if texel_size != reference.channels {
+        return Err(format!(
+            "reference has {} channels, image texels are {} bytes",
+            reference.channels, texel_size
+        ));
+    }
+    let (x, y, width, height) = match region {
+        Some(r) => (r.x, r.y, r.width, r.height),
+        None => (0, 0, guard.row(0).len() / texel_size, guard.height()),
+    };
+    if x + width > guard.row(0).len() / texel_size || y + height > guard.height() {
+        return Err(format!("region {:?} is out of image bounds", region));
+    }
+    if (width, height) != (reference.width, reference.height) {
+        return Err(format!(
+            "reference is {}x{}, compared region is {}x{}",
+            reference.width, reference.height, width, height
+        ));
+    }
+
+    let row_size = width * texel_size;
+    let mut mask = Vec::with_capacity(width * height);
+    for (i, expected_row) in reference.data.chunks(row_size).enumerate() {
+        let start = x * texel_size;
+        let row = &guard.row(y + i)[start .. start + row_size];
+        for (actual, expected) in row.chunks(texel_size).zip(expected_row.chunks(texel_size)) {
+            let differs = actual
+                .iter()
+                .zip(expected)
+                .any(|(&a, &e)| (a as i16 - e as i16).abs() > tolerance as i16);
+            mask.push(differs);
+        }
+    }
+
+    let num_differing = mask.iter().filter(|&&differs| differs).count();
+    if num_differing <= max_differing_pixels {
+        return Ok(());
+    }
+    let diff_status = match write_diff(diff_path, reference, &mask) {
+        Ok(()) => format!("diff written to {:?}", diff_path),
+        Err(e) => format!("failed to write diff: {}", e),
+    };
+    Err(format!(
+        "{} pixels differ (max {}), {}",
+        num_differing, max_differing_pixels, diff_status
+    ))
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,6 +225,8 @@ struct Test {
 #[derive(Debug, Deserialize)]
 struct RawTestGroup {
     features: Vec<warden::Feature>,
+    #[serde(default)]
+    limits: Vec<warden::Limit>,
     tests: HashMap<String, Test>,
 }
 
@@ -46,6 +237,7 @@ struct TestGroup {
     scene: warden::raw::Scene,
     tests: HashMap<String, Test>,
     features: hal::Features,
+    limits: Vec<warden::Limit>,
 }
 
 #[derive(Debug)]
@@ -58,26 +250,47 @@ struct TestResults {
 #[derive(Default)]
 struct Disabilities {}
 
+/// Human-readable progress goes to stderr when stdout carries a report.
+macro_rules! progress {
+    ($format:expr, $($arg:tt)*) => {
+        if $format == Format::Text {
+            print!($($arg)*);
+        } else {
+            eprint!($($arg)*);
+        }
+    };
+}
+
 struct Harness {
     base_path: PathBuf,
     suite: Vec<TestGroup>,
+    options: Options,
+    records: Vec<Record>,
 }
 
 impl Harness {
-    fn new(suite_name: &str) -> Self {
+    fn new(options: Options) -> Self {
         let base_path = PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/../../work"));
-        println!("Parsing test suite '{}'...", suite_name);
+        progress!(options.format, "Parsing test suite '{}'...\n", options.suite);
 
         let suite_path = base_path
             .join("reftests")
-            .join(suite_name)
+            .join(&options.suite)
             .with_extension("ron");
         let suite = File::open(&suite_path)
             .map_err(de::Error::from)
             .and_then(de::from_reader::<_, Suite>)
             .expect(&format!("failed to open/parse the suite: {:?}", suite_path))
             .into_iter()
-            .map(|(name, raw_group)| {
+            .filter_map(|(name, raw_group)| {
+                let tests = raw_group
+                    .tests
+                    .into_iter()
+                    .filter(|&(ref test_name, _)| options.is_selected(&name, test_name))
+                    .collect::<HashMap<_, _>>();
+                if tests.is_empty() {
+                    return None;
+                }
                 let path = base_path.join("scenes").join(&name).with_extension("ron");
                 let scene = File::open(path)
                     .map_err(de::Error::from)
@@ -87,41 +300,68 @@ impl Harness {
                     .features
                     .into_iter()
                     .fold(hal::Features::empty(), |u, f| u | f.into_hal());
-                TestGroup {
+                Some(TestGroup {
                     name,
                     scene,
-                    tests: raw_group.tests,
+                    tests,
                     features,
-                }
+                    limits: raw_group.limits,
+                })
             })
             .collect();
 
-        Harness { base_path, suite }
+        Harness {
+            base_path,
+            suite,
+            options,
+            records: Vec::new(),
+        }
     }
 
-    fn run<B: hal::Backend>(&self, name: &str, disabilities: Disabilities) -> usize {
-        println!("Testing {}:", name);
+    fn run<B: hal::Backend>(&mut self, name: &str, disabilities: Disabilities) -> usize {
+        progress!(self.options.format, "Testing {}:\n", name);
         let instance = B::Instance::create("warden", 1).unwrap();
-        self.run_instance(instance, disabilities)
+        let records = self.run_instance(name, instance, disabilities);
+        let num_failures = records.iter().filter(|r| r.status == Status::Fail).count();
+        self.records.extend(records);
+        num_failures
     }
 
     fn run_instance<B: hal::Backend, I: hal::Instance<B>>(
         &self,
+        name: &str,
         instance: I,
         _disabilities: Disabilities,
-    ) -> usize {
+    ) -> Vec<Record> {
+        let format = self.options.format;
         let mut results = TestResults {
             pass: 0,
             skip: 0,
             fail: 0,
         };
+        let mut records = Vec::new();
         for tg in &self.suite {
             let mut adapters = instance.enumerate_adapters();
             let adapter = adapters.remove(0);
             let supported_features = adapter.physical_device.features();
             let limits = adapter.physical_device.limits();
-            //println!("\t{:?}", adapter.info);
-            println!("\tScene '{}':", tg.name);
+            let info = adapter.info.clone();
+            progress!(format, "\tScene '{}':\n", tg.name);
+
+            let record = |test_name: &str, status, time, message| Record {
+                backend: name.to_string(),
+                adapter: info.clone(),
+                group: tg.name.clone(),
+                test: test_name.to_string(),
+                status,
+                time,
+                message,
+            };
+            let skip_group = |records: &mut Vec<Record>, reason: String| {
+                for test_name in tg.tests.keys() {
+                    records.push(record(test_name, Status::Skip, 0.0, Some(reason.clone())));
+                }
+            };
 
             #[cfg(not(feature = "glsl-to-spirv"))]
             {
@@ -130,31 +370,61 @@ impl Harness {
                     _ => true,
                 });
                 if !all_spirv {
-                    println!("\t\tskipped {} tests (GLSL shaders)", tg.tests.len());
+                    progress!(format, "\t\tskipped {} tests (GLSL shaders)\n", tg.tests.len());
                     results.skip += tg.tests.len();
+                    skip_group(&mut records, "GLSL shaders".to_string());
                     continue;
                 }
             }
 
             if !supported_features.contains(tg.features) {
-                println!(
-                    "\tskipped (features missing: {:?})",
-                    tg.features - supported_features
-                );
+                let reason = format!("features missing: {:?}", tg.features - supported_features);
+                progress!(format, "\tskipped ({})\n", reason);
                 results.skip += tg.tests.len();
+                skip_group(&mut records, reason);
                 continue;
             }
 
-            let mut scene = warden::gpu::Scene::<B>::new(
+            let missing_limits = tg
+                .limits
+                .iter()
+                .filter(|limit| !limit.is_supported_by(&limits))
+                .collect::<Vec<_>>();
+            if !missing_limits.is_empty() {
+                let reason = format!("limits missing: {:?}", missing_limits);
+                progress!(format, "\tskipped ({})\n", reason);
+                results.skip += tg.tests.len();
+                skip_group(&mut records, reason);
+                continue;
+            }
+
+            let mut scene = match warden::gpu::Scene::<B>::new(
                 adapter,
                 tg.features,
                 &tg.scene,
                 self.base_path.join("data"),
-            )
-            .unwrap();
+            ) {
+                Ok(scene) => scene,
+                Err(err @ warden::gpu::SceneError::Unsupported(_)) => {
+                    let reason = err.to_string();
+                    progress!(format, "\tskipped ({})\n", reason);
+                    results.skip += tg.tests.len();
+                    skip_group(&mut records, reason);
+                    continue;
+                }
+                Err(err) => {
+                    let message = format!("scene creation failed: {}", err);
+                    progress!(format, "\tFAIL {}\n", message);
+                    results.fail += tg.tests.len();
+                    for test_name in tg.tests.keys() {
+                        records.push(record(test_name, Status::Fail, 0.0, Some(message.clone())));
+                    }
+                    continue;
+                }
+            };
 
             for (test_name, test) in &tg.tests {
-                print!("\t\tTest '{}' ...", test_name);
+                progress!(format, "\t\tTest '{}' ...", test_name);
                 let mut max_compute_work_groups = [0; 3];
                 for job_name in &test.jobs {
                     if let warden::raw::Job::Compute { dispatch, .. } = tg.scene.jobs[job_name] {
@@ -168,54 +438,95 @@ impl Harness {
                     || max_compute_work_groups[1] > limits.max_compute_work_group_size[1]
                     || max_compute_work_groups[2] > limits.max_compute_work_group_size[2]
                 {
-                    println!("\tskipped (compute {:?})", max_compute_work_groups);
+                    let reason = format!("compute {:?}", max_compute_work_groups);
+                    progress!(format, "\tskipped ({})\n", reason);
                     results.skip += 1;
+                    records.push(record(test_name, Status::Skip, 0.0, Some(reason)));
                     continue;
                 }
 
+                let start = Instant::now();
                 scene.run(test.jobs.iter());
 
-                print!("\tran: ");
-                let (guard, row, data) = match test.expect {
+                progress!(format, "\tran: ");
+                let outcome = match test.expect {
                     Expectation::Buffer(ref buffer, ref data) => {
-                        (scene.fetch_buffer(buffer), 0, data)
+                        compare_row(&scene.fetch_buffer(buffer), 0, data)
                     }
                     Expectation::ImageRow(ref image, row, ref data) => {
-                        (scene.fetch_image(image), row, data)
+                        compare_row(&scene.fetch_image(image), row, data)
                     }
+                    Expectation::Image {
+                        ref image,
+                        ref reference,
+                        ref region,
+                        tolerance,
+                        max_differing_pixels,
+                    } => Reference::load(&self.base_path.join(reference)).and_then(|reference| {
+                        let diff_path = self
+                            .base_path
+                            .join("diff")
+                            .join(name)
+                            .join(&tg.name)
+                            .join(test_name)
+                            .with_extension("png");
+                        compare_image(
+                            &scene.fetch_image(image),
+                            region.as_ref(),
+                            &reference,
+                            tolerance,
+                            max_differing_pixels,
+                            &diff_path,
+                        )
+                    }),
+                    Expectation::Query {
+                        ref pool,
+                        ref queries,
+                        ref check,
+                    } => check.check(&scene.fetch_query_results(pool, queries.clone())),
                 };
 
-                if data.as_slice() == guard.row(row) {
-                    println!("PASS");
-                    results.pass += 1;
-                } else {
-                    println!("FAIL {:?}", guard.row(row));
-                    results.fail += 1;
+                let elapsed = start.elapsed();
+                let micros = elapsed.as_secs() * 1_000_000 + elapsed.subsec_micros() as u64;
+                let time = micros as f64 / 1e6;
+
+                match outcome {
+                    Ok(()) => {
+                        progress!(format, "PASS\n");
+                        results.pass += 1;
+                        records.push(record(test_name, Status::Pass, time, None));
+                    }
+                    Err(message) => {
+                        progress!(format, "FAIL {}\n", message);
+                        results.fail += 1;
+                        records.push(record(test_name, Status::Fail, time, Some(message)));
+                    }
                 }
             }
         }
 
-        println!("\t{:?}", results);
-        results.fail
+        progress!(format, "\t{:?}\n", results);
+        records
     }
 }
 
 fn main() {
-    use std::{env, process};
+    use std::{env, io, process};
 
     #[cfg(feature = "env_logger")]
     env_logger::init();
     let mut num_failures = 0;
 
-    let suite_name = match env::args().nth(1) {
-        Some(name) => name,
-        None => {
-            println!("Call with the argument of the reftest suite name");
+    let options = match Options::parse(env::args().skip(1)) {
+        Ok(options) => options,
+        Err(e) => {
+            println!("{}", e);
+            println!("Usage: reftest <suite> [--format text|json|junit] [--filter <glob>]");
             return;
         }
     };
 
-    let harness = Harness::new(&suite_name);
+    let mut harness = Harness::new(options);
     #[cfg(feature = "vulkan")]
     {
         num_failures +=
@@ -237,7 +548,18 @@ fn main() {
     {
         num_failures += harness.run::<gfx_backend_gl::Backend>("GL", Disabilities::default());
     }
-    let _ = harness;
+    #[cfg(feature = "cpu")]
+    {
+        num_failures += harness.run::<gfx_backend_cpu::Backend>("CPU", Disabilities::default());
+    }
+    let stdout = io::stdout();
+    report::write(
+        stdout.lock(),
+        harness.options.format,
+        &harness.options.suite,
+        &harness.records,
+    )
+    .expect("failed to write the report");
     num_failures += 0; // mark as mutated
     process::exit(num_failures as _);
 }
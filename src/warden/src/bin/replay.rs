@@ -0,0 +1,79 @@
+#![cfg_attr(
+    not(any(
+        feature = "vulkan",
+        feature = "dx12",
+        feature = "dx11",
+        feature = "metal",
+        feature = "gl",
+        feature = "cpu",
+    )),
+    allow(dead_code)
+)]
+
+use hal::Instance as _;
+use std::path::Path;
+
+fn replay<B: hal::Backend>(name: &str, dir: &Path) {
+    println!("Replaying on {}...", name);
+    let instance = B::Instance::create("warden", 1).unwrap();
+    let mut adapters = instance.enumerate_adapters();
+    let adapter = adapters.remove(0);
+    println!("\t{:?}", adapter.info);
+    let replayer = unsafe { gfx_backend_trace::Replayer::from_trace(&adapter, dir) }
+        .expect("failed to read the trace");
+    drop(replayer);
+    println!("\tdone");
+}
+
+fn main() {
+    use std::env;
+
+    #[cfg(feature = "env_logger")]
+    env_logger::init();
+
+    let dir = match env::args().nth(1) {
+        Some(dir) => dir,
+        None => {
+            println!("Call with the argument of the trace directory");
+            return;
+        }
+    };
+    let dir = Path::new(&dir);
+
+    #[cfg(feature = "vulkan")]
+    {
+        replay::<gfx_backend_vulkan::Backend>("Vulkan", dir);
+    }
+    #[cfg(feature = "dx12")]
+    {
+        replay::<gfx_backend_dx12::Backend>("DX12", dir);
+    }
+    #[cfg(feature = "dx11")]
+    {
+        replay::<gfx_backend_dx11::Backend>("DX11", dir);
+    }
+    #[cfg(feature = "metal")]
+    {
+        replay::<gfx_backend_metal::Backend>("Metal", dir);
+    }
+    #[cfg(feature = "gl")]
+    {
+        replay::<gfx_backend_gl::Backend>("GL", dir);
+    }
+    #[cfg(feature = "cpu")]
+    {
+        replay::<gfx_backend_cpu::Backend>("CPU", dir);
+    }
+    #[cfg(not(any(
+        feature = "vulkan",
+        feature = "dx12",
+        feature = "dx11",
+        feature = "metal",
+        feature = "gl",
+        feature = "cpu",
+    )))]
+    {
+        println!("No backend selected!");
+        let _ = dir;
+    }
+}
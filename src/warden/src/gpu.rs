@@ -6,8 +6,9 @@ use std::fs::File;
 use std::io::Read;
 use std::ops::Range;
 use std::path::PathBuf;
-use std::{iter, mem, slice};
+use std::{fmt, iter, mem, slice};
 
+use gfx_staging::{ImageReadback, Readback};
 use hal::{
     self,
     adapter,
@@ -30,31 +31,26 @@ const COLOR_RANGE: i::SubresourceRange = i::SubresourceRange {
     layers: 0 .. 1,
 };
 
-pub struct FetchGuard<'a, B: hal::Backend> {
-    device: &'a mut B::Device,
-    buffer: Option<B::Buffer>,
-    memory: Option<B::Memory>,
-    mapping: *const u8,
-    row_pitch: usize,
-    width: usize,
+pub struct FetchGuard {
+    data: Vec<u8>,
+    row_size: usize,
+    height: usize,
+    texel_size: usize,
 }
 
-impl<'a, B: hal::Backend> FetchGuard<'a, B> {
+impl FetchGuard {
     pub fn row(&self, i: usize) -> &[u8] {
-        let offset = (i * self.row_pitch) as isize;
-        unsafe { slice::from_raw_parts(self.mapping.offset(offset), self.width) }
+        &self.data[i * self.row_size .. (i + 1) * self.row_size]
     }
-}
 
-impl<'a, B: hal::Backend> Drop for FetchGuard<'a, B> {
-    fn drop(&mut self) {
-        let buffer = self.buffer.take().unwrap();
-        let memory = self.memory.take().unwrap();
-        unsafe {
-            self.device.unmap_memory(&memory);
-            self.device.destroy_buffer(buffer);
-            self.device.free_memory(memory);
-        }
+    /// Number of rows available for reading.
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Size of a single texel in bytes, or 1 for buffers.
+    pub fn texel_size(&self) -> usize {
+        self.texel_size
     }
 }
 
@@ -169,6 +165,8 @@ pub struct Resources<B: hal::Backend> {
     pub pipeline_layouts: HashMap<String, B::PipelineLayout>,
     pub graphics_pipelines: HashMap<String, B::GraphicsPipeline>,
     pub compute_pipelines: HashMap<String, (String, B::ComputePipeline)>,
+    pub query_pools: HashMap<String, B::QueryPool>,
+    pub events: HashMap<String, B::Event>,
 }
 
 pub struct Job<B: hal::Backend> {
@@ -185,10 +183,28 @@ pub struct Scene<B: hal::Backend> {
     command_pool: Option<B::CommandPool>,
     query_pool: Option<B::QueryPool>,
     upload_buffers: HashMap<String, (B::Buffer, B::Memory)>,
-    download_types: Vec<hal::MemoryTypeId>,
+    memory_types: Vec<adapter::MemoryType>,
     limits: hal::Limits,
 }
 
+/// Possible cause of a scene creation failure.
+#[derive(Debug)]
+pub enum SceneError {
+    /// The scene needs something the device doesn't support.
+    Unsupported(String),
+    /// Creating a resource of the scene failed.
+    Failed(String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            SceneError::Unsupported(ref reason) => write!(f, "unsupported {}", reason),
+            SceneError::Failed(ref reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
 fn align(x: u64, y: u64) -> u64 {
     if x > 0 && y > 0 {
         ((x - 1) | (y - 1)) + 1
@@ -203,7 +219,7 @@ impl<B: hal::Backend> Scene<B> {
         featues: hal::Features,
         raw: &raw::Scene,
         data_path: PathBuf,
-    ) -> Result<Self, ()> {
+    ) -> Result<Self, SceneError> {
         info!("creating Scene from {:?}", data_path);
         let memory_types = adapter.physical_device.memory_properties().memory_types;
         let limits = adapter.physical_device.limits();
@@ -229,22 +245,7 @@ impl<B: hal::Backend> Scene<B> {
                 }
             })
             .collect();
-        let download_types: Vec<hal::MemoryTypeId> = memory_types
-            .iter()
-            .enumerate()
-            .filter_map(|(i, mt)| {
-                if mt
-                    .properties
-                    .contains(memory::Properties::CPU_VISIBLE | memory::Properties::CPU_CACHED)
-                {
-                    Some(i.into())
-                } else {
-                    None
-                }
-            })
-            .collect();
         info!("upload memory: {:?}", upload_types);
-        info!("download memory: {:?}", &download_types);
 
         let mut command_pool = unsafe {
             device
@@ -271,6 +272,8 @@ impl<B: hal::Backend> Scene<B> {
             pipeline_layouts: HashMap::new(),
             graphics_pipelines: HashMap::new(),
             compute_pipelines: HashMap::new(),
+            query_pools: HashMap::new(),
+            events: HashMap::new(),
         };
         let mut upload_buffers = HashMap::new();
         let (mut finish_cmd, mut init_cmd);
@@ -611,6 +614,28 @@ impl<B: hal::Backend> Scene<B> {
                     let sampler = unsafe { device.create_sampler(info).unwrap() };
                     resources.samplers.insert(name.clone(), sampler);
                 }
+                raw::Resource::QueryPool { ty, count } => {
+                    let pool = match unsafe { device.create_query_pool(ty, count) } {
+                        Ok(pool) => pool,
+                        Err(query::CreationError::Unsupported(ty)) => {
+                            return Err(SceneError::Unsupported(format!(
+                                "query pool '{}' of type {:?}",
+                                name, ty
+                            )));
+                        }
+                        Err(e) => {
+                            return Err(SceneError::Failed(format!(
+                                "query pool '{}' creation failure: {}",
+                                name, e
+                            )));
+                        }
+                    };
+                    resources.query_pools.insert(name.clone(), pool);
+                }
+                raw::Resource::Event => {
+                    let event = device.create_event().expect("Event creation failure");
+                    resources.events.insert(name.clone(), event);
+                }
                 raw::Resource::RenderPass {
                     ref attachments,
                     ref subpasses,
@@ -1210,6 +1235,88 @@ impl<B: hal::Backend> Scene<B> {
                                     data,
                                 );
                             },
+                            Tc::ResetQueryPool {
+                                ref pool,
+                                ref queries,
+                            } => unsafe {
+                                let pool = resources
+                                    .query_pools
+                                    .get(pool)
+                                    .expect(&format!("Missing query pool: {}", pool));
+                                command_buf.reset_query_pool(pool, queries.clone());
+                            },
+                            Tc::WriteTimestamp {
+                                stage,
+                                ref pool,
+                                query,
+                            } => unsafe {
+                                let pool = resources
+                                    .query_pools
+                                    .get(pool)
+                                    .expect(&format!("Missing query pool: {}", pool));
+                                command_buf
+                                    .write_timestamp(stage, query::Query { pool, id: query });
+                            },
+                            Tc::CopyQueryPoolResults {
+                                ref pool,
+                                ref queries,
+                                ref buffer,
+                                offset,
+                                stride,
+                                flags,
+                            } => unsafe {
+                                let pool = resources
+                                    .query_pools
+                                    .get(pool)
+                                    .expect(&format!("Missing query pool: {}", pool));
+                                let buf = resources
+                                    .buffers
+                                    .get(buffer)
+                                    .expect(&format!("Missing buffer: {}", buffer));
+                                command_buf.pipeline_barrier(
+                                    src_stage .. pso::PipelineStage::TRANSFER,
+                                    memory::Dependencies::empty(),
+                                    buf.barrier(buffers.entry(buffer), b::State::TRANSFER_WRITE),
+                                );
+                                command_buf.copy_query_pool_results(
+                                    pool,
+                                    queries.clone(),
+                                    &buf.handle,
+                                    offset,
+                                    stride,
+                                    flags,
+                                );
+                            },
+                            Tc::SetEvent { ref event, stages } => unsafe {
+                                let event = resources
+                                    .events
+                                    .get(event)
+                                    .expect(&format!("Missing event: {}", event));
+                                command_buf.set_event(event, stages);
+                            },
+                            Tc::ResetEvent { ref event, stages } => unsafe {
+                                let event = resources
+                                    .events
+                                    .get(event)
+                                    .expect(&format!("Missing event: {}", event));
+                                command_buf.reset_event(event, stages);
+                            },
+                            Tc::WaitEvents {
+                                ref events,
+                                ref stages,
+                            } => unsafe {
+                                let events = events.iter().map(|name| {
+                                    resources
+                                        .events
+                                        .get(name)
+                                        .expect(&format!("Missing event: {}", name))
+                                });
+                                command_buf.wait_events(
+                                    events,
+                                    stages.clone(),
+                                    iter::empty::<memory::Barrier<B>>(),
+                                );
+                            },
                         }
                     }
 
@@ -1302,11 +1409,21 @@ impl<B: hal::Backend> Scene<B> {
                         }),
                     );
 
-                    for subpass in &rp.subpasses {
-                        if Some(subpass) != rp.subpasses.first() {
+                    let mut current_subpass = 0;
+                    for (index, subpass) in rp.subpasses.iter().enumerate() {
+                        // Subpasses may already be entered by explicit `NextSubpass` commands.
+                        if current_subpass > index {
+                            continue;
+                        }
+                        while current_subpass < index {
                             command_buf.next_subpass(c::SubpassContents::Inline);
+                            current_subpass += 1;
                         }
-                        for command in &pass.1[subpass].commands {
+                        let draw_pass = match pass.1.get(subpass) {
+                            Some(draw_pass) => draw_pass,
+                            None => continue,
+                        };
+                        for command in &draw_pass.commands {
                             use crate::raw::DrawCommand as Dc;
                             match *command {
                                 Dc::BindIndexBuffer {
@@ -1384,15 +1501,109 @@ impl<B: hal::Backend> Scene<B> {
                                         instances.clone(),
                                     );
                                 }
+                                Dc::DrawIndirect {
+                                    ref buffer,
+                                    offset,
+                                    draw_count,
+                                    stride,
+                                } => {
+                                    let buffer = &resources
+                                        .buffers
+                                        .get(buffer)
+                                        .expect(&format!("Missing indirect buffer: {}", buffer))
+                                        .handle;
+                                    command_buf.draw_indirect(buffer, offset, draw_count, stride);
+                                }
+                                Dc::DrawIndexedIndirect {
+                                    ref buffer,
+                                    offset,
+                                    draw_count,
+                                    stride,
+                                } => {
+                                    let buffer = &resources
+                                        .buffers
+                                        .get(buffer)
+                                        .expect(&format!("Missing indirect buffer: {}", buffer))
+                                        .handle;
+                                    command_buf
+                                        .draw_indexed_indirect(buffer, offset, draw_count, stride);
+                                }
+                                Dc::PushConstants {
+                                    ref layout,
+                                    stages,
+                                    offset,
+                                    ref data,
+                                } => {
+                                    command_buf.push_graphics_constants(
+                                        resources.pipeline_layouts.get(layout).expect(&format!(
+                                            "Missing pipeline layout: {}",
+                                            layout
+                                        )),
+                                        stages,
+                                        offset,
+                                        data,
+                                    );
+                                }
                                 Dc::SetViewports(ref viewports) => {
                                     command_buf.set_viewports(0, viewports);
                                 }
                                 Dc::SetScissors(ref scissors) => {
                                     command_buf.set_scissors(0, scissors);
                                 }
+                                Dc::SetStencilReference { faces, value } => {
+                                    command_buf.set_stencil_reference(faces, value);
+                                }
+                                Dc::SetBlendConstants(color) => {
+                                    command_buf.set_blend_constants(color);
+                                }
+                                Dc::SetDepthBias(depth_bias) => {
+                                    command_buf.set_depth_bias(depth_bias);
+                                }
+                                Dc::SetLineWidth(width) => {
+                                    command_buf.set_line_width(width);
+                                }
+                                Dc::BeginQuery {
+                                    ref pool,
+                                    query,
+                                    flags,
+                                } => {
+                                    let pool = resources
+                                        .query_pools
+                                        .get(pool)
+                                        .expect(&format!("Missing query pool: {}", pool));
+                                    command_buf
+                                        .begin_query(query::Query { pool, id: query }, flags);
+                                }
+                                Dc::EndQuery { ref pool, query } => {
+                                    let pool = resources
+                                        .query_pools
+                                        .get(pool)
+                                        .expect(&format!("Missing query pool: {}", pool));
+                                    command_buf.end_query(query::Query { pool, id: query });
+                                }
+                                Dc::WriteTimestamp {
+                                    stage,
+                                    ref pool,
+                                    query,
+                                } => {
+                                    let pool = resources
+                                        .query_pools
+                                        .get(pool)
+                                        .expect(&format!("Missing query pool: {}", pool));
+                                    command_buf
+                                    .write_timestamp(stage, query::Query { pool, id: query });
+                                }
+                                Dc::NextSubpass => {
+                                    command_buf.next_subpass(c::SubpassContents::Inline);
+                                    current_subpass += 1;
+                                }
                             }
                         }
                     }
+                    while current_subpass + 1 < rp.subpasses.len() {
+                        command_buf.next_subpass(c::SubpassContents::Inline);
+                        current_subpass += 1;
+                    }
 
                     command_buf.end_render_pass();
                     command_buf.pipeline_barrier(
@@ -1463,7 +1674,7 @@ impl<B: hal::Backend> Scene<B> {
             command_pool: Some(command_pool),
             query_pool: query_pool.ok(),
             upload_buffers,
-            download_types,
+            memory_types,
             limits,
         })
     }
@@ -1491,35 +1702,12 @@ impl<B: hal::Backend> Scene<B> {
         }
     }
 
-    pub fn fetch_buffer(&mut self, name: &str) -> FetchGuard<B> {
+    pub fn fetch_buffer(&mut self, name: &str) -> FetchGuard {
         let buffer = self
             .resources
             .buffers
             .get(name)
             .expect(&format!("Unable to find buffer to fetch: {}", name));
-        let limits = &self.limits;
-
-        let down_size = align(
-            buffer.size as u64,
-            limits.optimal_buffer_copy_pitch_alignment,
-        );
-
-        let mut down_buffer =
-            unsafe { self.device.create_buffer(down_size, b::Usage::TRANSFER_DST) }.unwrap();
-        let down_req = unsafe { self.device.get_buffer_requirements(&down_buffer) };
-        let download_type = *self
-            .download_types
-            .iter()
-            .find(|i| down_req.type_mask & (1 << i.0) != 0)
-            .unwrap();
-        let down_memory =
-            unsafe { self.device.allocate_memory(download_type, down_req.size) }.unwrap();
-
-        unsafe {
-            self.device
-                .bind_buffer_memory(&down_memory, 0, &mut down_buffer)
-        }
-        .unwrap();
 
         let mut command_pool = unsafe {
             self.device.create_command_pool(
@@ -1529,6 +1717,7 @@ impl<B: hal::Backend> Scene<B> {
         }
         .expect("Can't create command pool");
         let mut cmd_buffer;
+        let readback;
         unsafe {
             cmd_buffer = command_pool.allocate_one(c::Level::Primary);
             cmd_buffer.begin_primary(c::CommandBufferFlags::ONE_TIME_SUBMIT);
@@ -1543,12 +1732,14 @@ impl<B: hal::Backend> Scene<B> {
                 &[pre_barrier],
             );
 
-            let copy = c::BufferCopy {
-                src: 0,
-                dst: 0,
-                size: buffer.size as _,
-            };
-            cmd_buffer.copy_buffer(&buffer.handle, &down_buffer, &[copy]);
+            readback = Readback::buffer(
+                &self.device,
+                &mut cmd_buffer,
+                &self.memory_types,
+                &buffer.handle,
+                0 .. buffer.size as u64,
+            )
+            .expect("Can't create readback buffer");
 
             let post_barrier = memory::Barrier::whole_buffer(
                 &buffer.handle,
@@ -1563,74 +1754,36 @@ impl<B: hal::Backend> Scene<B> {
             cmd_buffer.finish()
         }
 
-        let copy_fence = self
-            .device
-            .create_fence(false)
-            .expect("Can't create copy-fence");
-        unsafe {
-            self.queue_group.queues[0]
-                .submit_without_semaphores(iter::once(&cmd_buffer), Some(&copy_fence));
-            self.device.wait_for_fence(&copy_fence, !0).unwrap();
-            self.device.destroy_fence(copy_fence);
-            self.device.destroy_command_pool(command_pool);
-        }
-
-        let mapping =
-            unsafe { self.device.map_memory(&down_memory, memory::Segment::ALL) }.unwrap();
-
+        let row_size = buffer.size;
+        let data = self.read_back(readback, command_pool, &cmd_buffer);
         FetchGuard {
-            device: &mut self.device,
-            buffer: Some(down_buffer),
-            memory: Some(down_memory),
-            mapping,
-            row_pitch: down_size as _,
-            width: buffer.size,
+            data,
+            row_size,
+            height: 1,
+            texel_size: 1,
         }
     }
 
-    pub fn fetch_image(&mut self, name: &str) -> FetchGuard<B> {
+    pub fn fetch_image(&mut self, name: &str) -> FetchGuard {
         let image = self
             .resources
             .images
             .get(name)
             .expect(&format!("Unable to find image to fetch: {}", name));
-        let limits = &self.limits;
 
-        let i::Extent {
-            width,
-            height,
-            depth,
-        } = image.kind.extent();
+        let extent = image.kind.extent();
         assert_eq!(image.kind.num_samples(), 1);
 
-        // TODO:
-        let base_format = image.format.base_format();
-        let format_desc = base_format.0.desc();
-        let (block_width, block_height) = format_desc.dim;
-
-        // Width and height need to be multiple of the block dimensions.
-        let width = align(width as _, block_width as _);
-        let height = align(height as _, block_height as _);
-
-        let width_bytes = (format_desc.bits as u64 * width as u64) / (8 * block_width as u64);
-        let row_pitch = align(width_bytes, limits.optimal_buffer_copy_pitch_alignment);
-        let down_size = (row_pitch * height * depth as u64) / block_height as u64;
-
-        let mut down_buffer =
-            unsafe { self.device.create_buffer(down_size, b::Usage::TRANSFER_DST) }.unwrap();
-        let down_req = unsafe { self.device.get_buffer_requirements(&down_buffer) };
-        let download_type = *self
-            .download_types
-            .iter()
-            .find(|i| down_req.type_mask & (1 << i.0) != 0)
-            .unwrap();
-        let down_memory =
-            unsafe { self.device.allocate_memory(download_type, down_req.size) }.unwrap();
-        unsafe {
-            self.device
-                .bind_buffer_memory(&down_memory, 0, &mut down_buffer)
-        }
-        .unwrap();
+        let region = ImageReadback {
+            format: image.format,
+            layers: i::SubresourceLayers {
+                aspects: f::Aspects::COLOR,
+                level: 0,
+                layers: 0 .. 1,
+            },
+            offset: i::Offset::ZERO,
+            extent,
+        };
 
         let mut command_pool = unsafe {
             self.device.create_command_pool(
@@ -1640,6 +1793,7 @@ impl<B: hal::Backend> Scene<B> {
         }
         .expect("Can't create command pool");
         let mut cmd_buffer;
+        let readback;
         unsafe {
             cmd_buffer = command_pool.allocate_one(c::Level::Primary);
             cmd_buffer.begin_primary(c::CommandBufferFlags::ONE_TIME_SUBMIT);
@@ -1657,28 +1811,16 @@ impl<B: hal::Backend> Scene<B> {
                 &[pre_barrier],
             );
 
-            let copy = c::BufferImageCopy {
-                buffer_offset: 0,
-                buffer_width: (row_pitch as u32 * 8) / format_desc.bits as u32,
-                buffer_height: height as u32,
-                image_layers: i::SubresourceLayers {
-                    aspects: f::Aspects::COLOR,
-                    level: 0,
-                    layers: 0 .. 1,
-                },
-                image_offset: i::Offset { x: 0, y: 0, z: 0 },
-                image_extent: i::Extent {
-                    width: width as _,
-                    height: height as _,
-                    depth: depth as _,
-                },
-            };
-            cmd_buffer.copy_image_to_buffer(
+            readback = Readback::image(
+                &self.device,
+                &mut cmd_buffer,
+                &self.memory_types,
+                &self.limits,
                 &image.handle,
                 i::Layout::TransferSrcOptimal,
-                &down_buffer,
-                &[copy],
-            );
+                &region,
+            )
+            .expect("Can't create readback buffer");
 
             let post_barrier = memory::Barrier::Image {
                 states: (i::Access::TRANSFER_READ, i::Layout::TransferSrcOptimal)
@@ -1696,29 +1838,66 @@ impl<B: hal::Backend> Scene<B> {
             cmd_buffer.finish();
         }
 
+        let format_desc = image.format.surface_desc();
+        let (block_width, block_height) = format_desc.dim;
+        let width = (extent.width + block_width as u32 - 1) / block_width as u32;
+        let height = (extent.height + block_height as u32 - 1) / block_height as u32;
+        let data = self.read_back(readback, command_pool, &cmd_buffer);
+        FetchGuard {
+            data,
+            row_size: width as usize * format_desc.bits as usize / 8,
+            height: height as _,
+            texel_size: format_desc.bits as usize / 8,
+        }
+    }
+
+    /// Submit the commands recording a readback and wait for its data.
+    fn read_back(
+        &mut self,
+        mut readback: Readback<B>,
+        command_pool: B::CommandPool,
+        cmd_buffer: &B::CommandBuffer,
+    ) -> Vec<u8> {
         let copy_fence = self
             .device
             .create_fence(false)
             .expect("Can't create copy-fence");
         unsafe {
             self.queue_group.queues[0]
-                .submit_without_semaphores(iter::once(&cmd_buffer), Some(&copy_fence));
-            self.device.wait_for_fence(&copy_fence, !0).unwrap();
+                .submit_without_semaphores(iter::once(cmd_buffer), Some(&copy_fence));
+            readback.track(copy_fence);
+            readback.wait(&self.device).unwrap();
+            let data = readback.read(&self.device).unwrap();
+            let copy_fence = readback.dispose(&self.device).unwrap();
             self.device.destroy_fence(copy_fence);
             self.device.destroy_command_pool(command_pool);
+            data
         }
+    }
 
-        let mapping =
-            unsafe { self.device.map_memory(&down_memory, memory::Segment::ALL) }.unwrap();
-
-        FetchGuard {
-            device: &mut self.device,
-            buffer: Some(down_buffer),
-            memory: Some(down_memory),
-            mapping,
-            row_pitch: row_pitch as _,
-            width: width_bytes as _,
+    /// Wait for the submitted jobs and read back 64-bit results of a query pool.
+    pub fn fetch_query_results(&self, name: &str, queries: Range<query::Id>) -> Vec<u64> {
+        let pool = self
+            .resources
+            .query_pools
+            .get(name)
+            .expect(&format!("Unable to find query pool to fetch: {}", name));
+        let mut results = vec![0u64; (queries.end - queries.start) as usize];
+        unsafe {
+            self.device.wait_idle().unwrap();
+            let raw_data =
+                slice::from_raw_parts_mut(results.as_mut_ptr() as *mut u8, results.len() * 8);
+            self.device
+                .get_query_pool_results(
+                    pool,
+                    queries,
+                    raw_data,
+                    8,
+                    query::ResultFlags::BITS_64 | query::ResultFlags::WAIT,
+                )
+                .unwrap();
         }
+        results
     }
 
     pub fn measure_time(&self) -> u32 {
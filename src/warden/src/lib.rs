@@ -8,12 +8,206 @@ extern crate serde;
 
 pub mod gpu;
 pub mod raw;
+pub mod report;
 
-#[derive(Debug, serde::Deserialize)]
-pub enum Feature {}
+macro_rules! features {
+    ($($name:ident,)*) => {
+        /// A `hal::Features` flag that a test group may require.
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+        pub enum Feature {
+            $($name,)*
+        }
 
-impl Feature {
-    pub fn into_hal(self) -> hal::Features {
-        match self {}
+        impl Feature {
+            pub fn into_hal(self) -> hal::Features {
+                match self {
+                    $(Feature::$name => hal::Features::$name,)*
+                }
+            }
+        }
+    };
+}
+
+features! {
+    ROBUST_BUFFER_ACCESS,
+    FULL_DRAW_INDEX_U32,
+    IMAGE_CUBE_ARRAY,
+    INDEPENDENT_BLENDING,
+    GEOMETRY_SHADER,
+    TESSELLATION_SHADER,
+    SAMPLE_RATE_SHADING,
+    DUAL_SRC_BLENDING,
+    LOGIC_OP,
+    MULTI_DRAW_INDIRECT,
+    DRAW_INDIRECT_FIRST_INSTANCE,
+    DEPTH_CLAMP,
+    DEPTH_BIAS_CLAMP,
+    NON_FILL_POLYGON_MODE,
+    DEPTH_BOUNDS,
+    LINE_WIDTH,
+    POINT_SIZE,
+    ALPHA_TO_ONE,
+    MULTI_VIEWPORTS,
+    SAMPLER_ANISOTROPY,
+    FORMAT_ETC2,
+    FORMAT_ASTC_LDR,
+    FORMAT_BC,
+    PRECISE_OCCLUSION_QUERY,
+    PIPELINE_STATISTICS_QUERY,
+    VERTEX_STORES_AND_ATOMICS,
+    FRAGMENT_STORES_AND_ATOMICS,
+    SHADER_TESSELLATION_AND_GEOMETRY_POINT_SIZE,
+    SHADER_IMAGE_GATHER_EXTENDED,
+    SHADER_STORAGE_IMAGE_EXTENDED_FORMATS,
+    SHADER_STORAGE_IMAGE_MULTISAMPLE,
+    SHADER_STORAGE_IMAGE_READ_WITHOUT_FORMAT,
+    SHADER_STORAGE_IMAGE_WRITE_WITHOUT_FORMAT,
+    SHADER_UNIFORM_BUFFER_ARRAY_DYNAMIC_INDEXING,
+    SHADER_SAMPLED_IMAGE_ARRAY_DYNAMIC_INDEXING,
+    SHADER_STORAGE_BUFFER_ARRAY_DYNAMIC_INDEXING,
+    SHADER_STORAGE_IMAGE_ARRAY_DYNAMIC_INDEXING,
+    SHADER_CLIP_DISTANCE,
+    SHADER_CULL_DISTANCE,
+    SHADER_FLOAT64,
+    SHADER_INT64,
+    SHADER_INT16,
+    SHADER_RESOURCE_RESIDENCY,
+    SHADER_RESOURCE_MIN_LOD,
+    SPARSE_BINDING,
+    SPARSE_RESIDENCY_BUFFER,
+    SPARSE_RESIDENCY_IMAGE_2D,
+    SPARSE_RESIDENCY_IMAGE_3D,
+    SPARSE_RESIDENCY_2_SAMPLES,
+    SPARSE_RESIDENCY_4_SAMPLES,
+    SPARSE_RESIDENCY_8_SAMPLES,
+    SPARSE_RESIDENCY_16_SAMPLES,
+    SPARSE_RESIDENCY_ALIASED,
+    VARIABLE_MULTISAMPLE_RATE,
+    INHERITED_QUERIES,
+    SAMPLER_MIRROR_CLAMP_EDGE,
+    TRIANGLE_FAN,
+    SEPARATE_STENCIL_REF_VALUES,
+    INSTANCE_RATE,
+    SAMPLER_MIP_LOD_BIAS,
+    NDC_Y_UP,
+}
+
+/// Values of `hal::Limits` fields that can be compared against
+/// a minimum requirement.
+pub trait LimitValue: std::fmt::Debug {
+    /// Check if `self` is at least as large as `required`.
+    fn satisfies(&self, required: &Self) -> bool;
+}
+
+macro_rules! impl_limit_value {
+    ($($ty:ty),*) => {
+        $(
+            impl LimitValue for $ty {
+                fn satisfies(&self, required: &Self) -> bool {
+                    *self >= *required
+                }
+            }
+        )*
+    };
+}
+
+impl_limit_value!(u8, u16, u32, u64, usize, f32);
+
+impl<T: LimitValue> LimitValue for [T; 2] {
+    fn satisfies(&self, required: &Self) -> bool {
+        self.iter().zip(required).all(|(a, r)| a.satisfies(r))
+    }
+}
+
+impl<T: LimitValue> LimitValue for [T; 3] {
+    fn satisfies(&self, required: &Self) -> bool {
+        self.iter().zip(required).all(|(a, r)| a.satisfies(r))
+    }
+}
+
+impl LimitValue for hal::image::Extent {
+    fn satisfies(&self, required: &Self) -> bool {
+        self.width >= required.width
+            && self.height >= required.height
+            && self.depth >= required.depth
     }
 }
+
+macro_rules! limits {
+    ($($name:ident: $ty:ty,)*) => {
+        /// Minimum value of a `hal::Limits` field that a test group requires.
+        #[allow(non_camel_case_types)]
+        #[derive(Clone, Copy, Debug, PartialEq, serde::Deserialize)]
+        pub enum Limit {
+            $($name($ty),)*
+        }
+
+        impl Limit {
+            /// Check if the adapter `limits` meet this requirement.
+            pub fn is_supported_by(&self, limits: &hal::Limits) -> bool {
+                match *self {
+                    $(Limit::$name(ref value) => limits.$name.satisfies(value),)*
+                }
+            }
+        }
+    };
+}
+
+limits! {
+    max_image_1d_size: hal::image::Size,
+    max_image_2d_size: hal::image::Size,
+    max_image_3d_size: hal::image::Size,
+    max_image_cube_size: hal::image::Size,
+    max_image_array_layers: hal::image::Layer,
+    max_texel_elements: usize,
+    max_uniform_buffer_range: hal::buffer::Offset,
+    max_storage_buffer_range: hal::buffer::Offset,
+    max_push_constants_size: usize,
+    max_memory_allocation_count: usize,
+    max_sampler_allocation_count: usize,
+    max_bound_descriptor_sets: hal::pso::DescriptorSetIndex,
+    max_framebuffer_layers: usize,
+    max_per_stage_descriptor_samplers: usize,
+    max_per_stage_descriptor_uniform_buffers: usize,
+    max_per_stage_descriptor_storage_buffers: usize,
+    max_per_stage_descriptor_sampled_images: usize,
+    max_per_stage_descriptor_storage_images: usize,
+    max_per_stage_descriptor_input_attachments: usize,
+    max_per_stage_resources: usize,
+    max_descriptor_set_samplers: usize,
+    max_descriptor_set_uniform_buffers: usize,
+    max_descriptor_set_uniform_buffers_dynamic: usize,
+    max_descriptor_set_storage_buffers: usize,
+    max_descriptor_set_storage_buffers_dynamic: usize,
+    max_descriptor_set_sampled_images: usize,
+    max_descriptor_set_storage_images: usize,
+    max_descriptor_set_input_attachments: usize,
+    max_vertex_input_attributes: usize,
+    max_vertex_input_bindings: usize,
+    max_vertex_input_attribute_offset: usize,
+    max_vertex_input_binding_stride: usize,
+    max_vertex_output_components: usize,
+    max_patch_size: hal::pso::PatchSize,
+    max_geometry_shader_invocations: usize,
+    max_geometry_input_components: usize,
+    max_geometry_output_components: usize,
+    max_geometry_output_vertices: usize,
+    max_geometry_total_output_components: usize,
+    max_fragment_input_components: usize,
+    max_fragment_output_attachments: usize,
+    max_fragment_dual_source_attachments: usize,
+    max_fragment_combined_output_resources: usize,
+    max_compute_shared_memory_size: usize,
+    max_compute_work_group_count: hal::WorkGroupCount,
+    max_compute_work_group_invocations: usize,
+    max_compute_work_group_size: [u32; 3],
+    max_draw_indexed_index_value: hal::IndexCount,
+    max_draw_indirect_count: hal::InstanceCount,
+    max_sampler_lod_bias: f32,
+    max_sampler_anisotropy: f32,
+    max_viewports: usize,
+    max_viewport_dimensions: [hal::image::Size; 2],
+    max_framebuffer_extent: hal::image::Extent,
+    max_color_attachments: usize,
+}
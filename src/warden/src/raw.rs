@@ -152,6 +152,11 @@ pub enum Resource {
         views: HashMap<String, String>,
         extent: hal::image::Extent,
     },
+    QueryPool {
+        ty: hal::query::Type,
+        count: hal::query::Id,
+    },
+    Event,
 }
 
 #[derive(Debug, Deserialize)]
@@ -193,6 +198,35 @@ pub enum TransferCommand {
         size: Option<hal::buffer::Offset>,
         data: u32,
     },
+    ResetQueryPool {
+        pool: String,
+        queries: Range<hal::query::Id>,
+    },
+    WriteTimestamp {
+        stage: hal::pso::PipelineStage,
+        pool: String,
+        query: hal::query::Id,
+    },
+    CopyQueryPoolResults {
+        pool: String,
+        queries: Range<hal::query::Id>,
+        buffer: String,
+        offset: hal::buffer::Offset,
+        stride: hal::buffer::Offset,
+        flags: hal::query::ResultFlags,
+    },
+    SetEvent {
+        event: String,
+        stages: hal::pso::PipelineStage,
+    },
+    ResetEvent {
+        event: String,
+        stages: hal::pso::PipelineStage,
+    },
+    WaitEvents {
+        events: Vec<String>,
+        stages: Range<hal::pso::PipelineStage>,
+    },
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -230,8 +264,51 @@ pub enum DrawCommand {
         base_vertex: hal::VertexOffset,
         instances: Range<hal::InstanceCount>,
     },
+    DrawIndirect {
+        buffer: String,
+        offset: hal::buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    DrawIndexedIndirect {
+        buffer: String,
+        offset: hal::buffer::Offset,
+        draw_count: hal::DrawCount,
+        stride: u32,
+    },
+    PushConstants {
+        layout: String,
+        stages: hal::pso::ShaderStageFlags,
+        offset: u32,
+        data: Vec<u32>,
+    },
     SetViewports(Vec<hal::pso::Viewport>),
     SetScissors(Vec<hal::pso::Rect>),
+    SetStencilReference {
+        faces: hal::pso::Face,
+        value: hal::pso::StencilValue,
+    },
+    SetBlendConstants(hal::pso::ColorValue),
+    SetDepthBias(hal::pso::DepthBias),
+    SetLineWidth(f32),
+    BeginQuery {
+        pool: String,
+        query: hal::query::Id,
+        #[serde(default = "hal::query::ControlFlags::empty")]
+        flags: hal::query::ControlFlags,
+    },
+    EndQuery {
+        pool: String,
+        query: hal::query::Id,
+    },
+    WriteTimestamp {
+        stage: hal::pso::PipelineStage,
+        pool: String,
+        query: hal::query::Id,
+    },
+    /// Move on to the next subpass of the render pass, allowing a single
+    /// command list to span several subpasses.
+    NextSubpass,
 }
 
 #[derive(Debug, Deserialize)]
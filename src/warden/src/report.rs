@@ -0,0 +1,193 @@
+//! Command line options and machine-readable reports shared
+//! by the `reftest` and `bench` binaries.
+
+use std::io::{self, Write};
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Human-readable progress on stdout.
+    Text,
+    /// One JSON object per line for each test.
+    Json,
+    /// JUnit XML document with a test suite per backend and group.
+    Junit,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "junit" => Ok(Format::Junit),
+            _ => Err(format!(
+                "unknown format '{}', expected text, json or junit",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub suite: String,
+    pub format: Format,
+    /// Glob matched against `group/test` names.
+    pub filter: Option<glob::Pattern>,
+}
+
+impl Options {
+    /// Parse `<suite> [--format text|json|junit] [--filter <glob>]`,
+    /// not including the program name.
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut suite = None;
+        let mut format = Format::Text;
+        let mut filter = None;
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--format" => {
+                    let value = args.next().ok_or("--format requires a value")?;
+                    format = value.parse()?;
+                }
+                "--filter" => {
+                    let value = args.next().ok_or("--filter requires a value")?;
+                    let pattern = glob::Pattern::new(&value)
+                        .map_err(|e| format!("invalid filter '{}': {}", value, e))?;
+                    filter = Some(pattern);
+                }
+                _ if arg.starts_with("--") => return Err(format!("unknown option '{}'", arg)),
+                _ if suite.is_none() => suite = Some(arg),
+                _ => return Err(format!("unexpected argument '{}'", arg)),
+            }
+        }
+
+        Ok(Options {
+            suite: suite.ok_or("missing suite name")?,
+            format,
+            filter,
+        })
+    }
+
+    pub fn is_selected(&self, group: &str, test: &str) -> bool {
+        match self.filter {
+            Some(ref pattern) => pattern.matches(&format!("{}/{}", group, test)),
+            None => true,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Pass,
+    Skip,
+    Fail,
+}
+
+/// Outcome of a single test on a single backend.
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub backend: String,
+    pub adapter: hal::adapter::AdapterInfo,
+    pub group: String,
+    pub test: String,
+    pub status: Status,
+    /// Duration of the test in seconds.
+    pub time: f64,
+    /// Reason of a skip, or the mismatch of a failure.
+    pub message: Option<String>,
+}
+
+/// Write the records in the given format. Nothing is written for `Format::Text`,
+/// since the progress is already reported while running.
+pub fn write<W: Write>(out: W, format: Format, suite: &str, records: &[Record]) -> io::Result<()> {
+    match format {
+        Format::Text => Ok(()),
+        Format::Json => write_json(out, records),
+        Format::Junit => write_junit(out, suite, records),
+    }
+}
+
+fn write_json<W: Write>(mut out: W, records: &[Record]) -> io::Result<()> {
+    for record in records {
+        serde_json::to_writer(&mut out, record)?;
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn write_junit<W: Write>(mut out: W, suite: &str, records: &[Record]) -> io::Result<()> {
+    writeln!(out, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(out, r#"<testsuites name="{}">"#, escape_xml(suite))?;
+
+    // Records of a backend and group are produced together.
+    let mut start = 0;
+    while start < records.len() {
+        let first = &records[start];
+        let count = records[start ..]
+            .iter()
+            .take_while(|r| r.backend == first.backend && r.group == first.group)
+            .count();
+        let cases = &records[start .. start + count];
+        start += count;
+
+        let count_of = |status| cases.iter().filter(|r| r.status == status).count();
+        writeln!(
+            out,
+            r#"  <testsuite name="{}.{}" hostname="{}" tests="{}" failures="{}" skipped="{}" time="{}">"#,
+            escape_xml(&first.backend),
+            escape_xml(&first.group),
+            escape_xml(&first.adapter.name),
+            cases.len(),
+            count_of(Status::Fail),
+            count_of(Status::Skip),
+            cases.iter().map(|r| r.time).sum::<f64>(),
+        )?;
+        for case in cases {
+            write!(
+                out,
+                r#"    <testcase classname="{}.{}" name="{}" time="{}""#,
+                escape_xml(&case.backend),
+                escape_xml(&case.group),
+                escape_xml(&case.test),
+                case.time,
+            )?;
+            let message = escape_xml(case.message.as_ref().map_or("", String::as_str));
+            match case.status {
+                Status::Pass => writeln!(out, "/>")?,
+                Status::Skip => {
+                    writeln!(out, ">")?;
+                    writeln!(out, r#"      <skipped message="{}"/>"#, message)?;
+                    writeln!(out, "    </testcase>")?;
+                }
+                Status::Fail => {
+                    writeln!(out, ">")?;
+                    writeln!(out, r#"      <failure message="{}"/>"#, message)?;
+                    writeln!(out, "    </testcase>")?;
+                }
+            }
+        }
+        writeln!(out, "  </testsuite>")?;
+    }
+
+    writeln!(out, "</testsuites>")
+}